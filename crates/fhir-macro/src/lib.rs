@@ -2241,6 +2241,13 @@ fn generate_deserialize_impl(data: &Data, name: &Ident) -> proc_macro2::TokenStr
 /// - **Resource enum**: Adds `resourceType` field automatically for resource variants
 /// - **Unit variants**: Returns the variant name as a string (for status codes, etc.)
 ///
+/// ## Field-by-Name Access
+///
+/// Structs and the `Resource` enum also get a `helios_fhirpath_support::FhirPathFields`
+/// implementation. `fhirpath_field(name)` converts a single field to the same value
+/// the whole-object conversion stores under that name, so the evaluator can navigate
+/// a typed resource without converting the fields an expression never touches.
+///
 /// # FHIRPath Integration
 ///
 /// The generated implementations enable FHIR resources to be used directly in
@@ -2328,7 +2335,21 @@ pub fn fhir_path_derive(input: TokenStream) -> TokenStream {
         Data::Union(_) => panic!("FhirPath derive macro does not support unions."),
     };
 
-    TokenStream::from(trait_impl)
+    // Metadata tables are statics, so they can only be generated for concrete types
+    let metadata_impl = if generics.params.is_empty() {
+        match &input.data {
+            Data::Struct(data) => generate_fhirpath_struct_metadata(name, data),
+            Data::Enum(data) => generate_fhirpath_enum_metadata(name, data),
+            Data::Union(_) => unreachable!(),
+        }
+    } else {
+        quote! {}
+    };
+
+    TokenStream::from(quote! {
+        #trait_impl
+        #metadata_impl
+    })
 }
 
 /// Determines the effective field name for FHIRPath object property access.
@@ -2402,77 +2423,71 @@ fn generate_fhirpath_struct_impl(
         _ => panic!("FhirPath derive macro only supports structs with named fields."),
     };
 
-    let field_conversions = fields.iter().map(|field| {
-        let field_name_ident = field.ident.as_ref().unwrap();
-        let field_key_str = get_fhirpath_field_name(field); // Use the specific FHIRPath naming helper
-        let field_ty = &field.ty; // Get the field type
-
-        // Check if this field is a FHIR primitive type that needs special handling
-        let fhir_type_name = extract_fhir_primitive_type_name(field_ty);
-        // Generate code to handle the field based on whether it's Option
-        let is_option = get_option_inner_type(field_ty).is_some();
-
-        if is_option {
-            // For Option<T>, evaluate the inner value only if Some
-            if let Some(type_name) = fhir_type_name {
-                // Special handling for FHIR primitive types to preserve type information
-                quote! {
-                    if let Some(inner_value) = &self.#field_name_ident {
-                        let mut field_result = inner_value.to_evaluation_result();
-                        // Override type information for FHIR primitive types
-                        field_result = match field_result {
-                            helios_fhirpath_support::EvaluationResult::String(s, _) => {
-                                helios_fhirpath_support::EvaluationResult::fhir_string(s, #type_name)
-                            },
-                            _ => field_result,
-                        };
-                        // Only insert if the inner evaluation is not Empty
-                        if field_result != helios_fhirpath_support::EvaluationResult::Empty {
-                            map.insert(#field_key_str.to_string(), field_result);
-                        }
-                    }
-                    // If self.#field_name_ident is None, do nothing (don't insert Empty)
-                }
-            } else {
-                quote! {
-                    if let Some(inner_value) = &self.#field_name_ident {
-                        let field_result = inner_value.to_evaluation_result();
-                        // Only insert if the inner evaluation is not Empty
-                        if field_result != helios_fhirpath_support::EvaluationResult::Empty {
-                            map.insert(#field_key_str.to_string(), field_result);
+    // One expression per field that converts just that field, shared by the
+    // whole-object conversion and the field-by-name accessor
+    let converted_fields: Vec<(String, proc_macro2::TokenStream)> = fields
+        .iter()
+        .map(|field| {
+            let field_name_ident = field.ident.as_ref().unwrap();
+            let field_key_str = get_fhirpath_field_name(field); // Use the specific FHIRPath naming helper
+            let field_ty = &field.ty; // Get the field type
+
+            // Check if this field is a FHIR primitive type that needs special handling
+            let fhir_type_name = extract_fhir_primitive_type_name(field_ty);
+            // Generate code to handle the field based on whether it's Option
+            let is_option = get_option_inner_type(field_ty).is_some();
+
+            // Converts `value` (a reference to the field or its inner value)
+            let convert = |value: proc_macro2::TokenStream| {
+                if let Some(type_name) = fhir_type_name {
+                    // Special handling for FHIR primitive types to preserve type information.
+                    // Temporal primitives are held as strings and become typed values here.
+                    let typed = fhir_temporal_result(type_name, quote! { s }).unwrap_or_else(
+                        || quote! { helios_fhirpath_support::EvaluationResult::fhir_string(s, #type_name) },
+                    );
+                    quote! {
+                        match #value.to_evaluation_result() {
+                            helios_fhirpath_support::EvaluationResult::String(s, _) => #typed,
+                            field_result => field_result,
                         }
                     }
-                    // If self.#field_name_ident is None, do nothing (don't insert Empty)
+                } else {
+                    quote! { #value.to_evaluation_result() }
                 }
-            }
-        } else {
-            // For non-Option<T>, evaluate directly
-            if let Some(type_name) = fhir_type_name {
-                // Special handling for FHIR primitive types to preserve type information
+            };
+
+            let conversion = if is_option {
+                // For Option<T>, evaluate the inner value only if Some
+                let inner = convert(quote! { inner_value });
                 quote! {
-                    let mut field_result = self.#field_name_ident.to_evaluation_result();
-                    // Override type information for FHIR primitive types
-                    field_result = match field_result {
-                        helios_fhirpath_support::EvaluationResult::String(s, _) => {
-                            helios_fhirpath_support::EvaluationResult::fhir_string(s, #type_name)
-                        },
-                        _ => field_result,
-                    };
-                    // Only insert if the evaluation is not Empty
-                    if field_result != helios_fhirpath_support::EvaluationResult::Empty {
-                        map.insert(#field_key_str.to_string(), field_result);
+                    match &self.#field_name_ident {
+                        Some(inner_value) => #inner,
+                        None => helios_fhirpath_support::EvaluationResult::Empty,
                     }
                 }
             } else {
-                quote! {
-                    let field_result = self.#field_name_ident.to_evaluation_result();
-                    // Only insert if the evaluation is not Empty
-                    if field_result != helios_fhirpath_support::EvaluationResult::Empty {
-                        map.insert(#field_key_str.to_string(), field_result);
-                    }
-                }
+                // For non-Option<T>, evaluate directly
+                convert(quote! { self.#field_name_ident })
+            };
+
+            (field_key_str, conversion)
+        })
+        .collect();
+
+    let field_conversions = converted_fields.iter().map(|(field_key_str, conversion)| {
+        quote! {
+            let field_result = #conversion;
+            // Only insert if the evaluation is not Empty
+            if field_result != helios_fhirpath_support::EvaluationResult::Empty {
+                map.insert(#field_key_str.to_string(), field_result);
             }
-        } // Return the generated code for this field
+        }
+    });
+
+    let field_arms = converted_fields.iter().map(|(field_key_str, conversion)| {
+        quote! {
+            #field_key_str => Some(#conversion),
+        }
     });
 
     // Determine the type name to use for type info
@@ -2495,7 +2510,212 @@ fn generate_fhirpath_struct_impl(
                 )
             }
         }
+
+        impl #impl_generics helios_fhirpath_support::FhirPathFields for #name #ty_generics #where_clause {
+            fn fhirpath_field(&self, name: &str) -> Option<helios_fhirpath_support::EvaluationResult> {
+                #[allow(unused_imports)]
+                use helios_fhirpath_support::IntoEvaluationResult;
+
+                match name {
+                    #(#field_arms)*
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// Strips `Option`, `Vec` and `Box` from a field type.
+///
+/// Returns the type whose metadata describes the field's values and whether the
+/// field repeats.
+fn get_metadata_type(ty: &Type) -> (&Type, bool) {
+    let mut ty = ty;
+    let mut is_array = false;
+    loop {
+        if let Some(inner) = get_option_inner_type(ty).or_else(|| get_box_inner_type(ty)) {
+            ty = inner;
+        } else if let Some(inner) = get_vec_inner_type(ty) {
+            ty = inner;
+            is_array = true;
+        } else {
+            return (ty, is_array);
+        }
+    }
+}
+
+/// Generates the `FhirPathMetadata` table of a struct.
+///
+/// Each field is described with the same name and primitive type override used
+/// by the generated `IntoEvaluationResult` conversion.
+fn generate_fhirpath_struct_metadata(
+    name: &Ident,
+    data: &syn::DataStruct,
+) -> proc_macro2::TokenStream {
+    let Fields::Named(fields) = &data.fields else {
+        return quote! {};
+    };
+
+    let elements = fields.named.iter().map(|field| {
+        let field_key_str = get_fhirpath_field_name(field);
+        let (metadata_ty, is_array) = get_metadata_type(&field.ty);
+        let primitive_type = match extract_fhir_primitive_type_name(&field.ty) {
+            Some(type_name) => quote! { Some(#type_name) },
+            None => quote! { None },
+        };
+        let value_type = match extract_fhir_primitive_type_name(metadata_ty) {
+            Some(type_name) => quote! { Some(#type_name) },
+            None => quote! { None },
+        };
+        quote! {
+            helios_fhirpath_support::ElementMetadata {
+                name: #field_key_str,
+                is_array: #is_array,
+                primitive_type: #primitive_type,
+                value_type: #value_type,
+                metadata: <#metadata_ty as helios_fhirpath_support::FhirPathMetadata>::fhirpath_metadata,
+            }
+        }
+    });
+
+    let type_name_str = name.to_string();
+
+    quote! {
+        impl helios_fhirpath_support::FhirPathMetadata for #name {
+            fn fhirpath_metadata() -> &'static helios_fhirpath_support::TypeMetadata {
+                static METADATA: helios_fhirpath_support::TypeMetadata =
+                    helios_fhirpath_support::TypeMetadata::Complex {
+                        name: #type_name_str,
+                        elements: &[#(#elements),*],
+                    };
+                &METADATA
+            }
+        }
+    }
+}
+
+/// Generates the `FhirPathMetadata` table of an enum.
+///
+/// The `Resource` enum resolves resource types by name; other enums are choice
+/// types whose newtype variants are listed with their JSON names.
+fn generate_fhirpath_enum_metadata(name: &Ident, data: &syn::DataEnum) -> proc_macro2::TokenStream {
+    let newtype_variants = data
+        .variants
+        .iter()
+        .filter_map(|variant| match &variant.fields {
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                Some((variant, &fields.unnamed[0].ty))
+            }
+            _ => None,
+        });
+
+    let metadata = if name == "Resource" {
+        let lookup_arms = newtype_variants.map(|(variant, ty)| {
+            let variant_name_str = variant.ident.to_string();
+            quote! {
+                #variant_name_str => Some(<#ty as helios_fhirpath_support::FhirPathMetadata>::fhirpath_metadata()),
+            }
+        });
+        quote! {
+            fn lookup(resource_type: &str) -> Option<&'static helios_fhirpath_support::TypeMetadata> {
+                match resource_type {
+                    #(#lookup_arms)*
+                    _ => None,
+                }
+            }
+            static METADATA: helios_fhirpath_support::TypeMetadata =
+                helios_fhirpath_support::TypeMetadata::Resource { lookup };
+        }
+    } else {
+        let variants = newtype_variants.map(|(variant, ty)| {
+            let (json_name, fhir_type) = get_choice_variant_names(variant);
+            quote! {
+                helios_fhirpath_support::ChoiceVariant {
+                    json_name: #json_name,
+                    fhir_type: #fhir_type,
+                    metadata: <#ty as helios_fhirpath_support::FhirPathMetadata>::fhirpath_metadata,
+                }
+            }
+        });
+        quote! {
+            static METADATA: helios_fhirpath_support::TypeMetadata =
+                helios_fhirpath_support::TypeMetadata::Choice {
+                    variants: &[#(#variants),*],
+                };
+        }
+    };
+
+    quote! {
+        impl helios_fhirpath_support::FhirPathMetadata for #name {
+            fn fhirpath_metadata() -> &'static helios_fhirpath_support::TypeMetadata {
+                #metadata
+                &METADATA
+            }
+        }
+    }
+}
+
+/// Determines the JSON name and FHIR type of a choice type variant.
+///
+/// The JSON name is the `#[fhir_serde(rename = "...")]` value (e.g. `valueCode`),
+/// defaulting to the variant name. The FHIR type is derived from it (`code`) and
+/// is given to values that carry no type information of their own.
+fn get_choice_variant_names(variant: &syn::Variant) -> (String, String) {
+    let variant_name = &variant.ident;
+    let variant_name_str = variant_name.to_string();
+    // Check for fhir_serde rename attribute to get the FHIR field name
+    let mut fhir_field_name = variant_name_str.clone();
+    for attr in &variant.attrs {
+        if attr.path().is_ident("fhir_serde") {
+            if let Ok(list) = attr.parse_args_with(
+                syn::punctuated::Punctuated::<syn::Meta, syn::token::Comma>::parse_terminated,
+            ) {
+                for meta in list {
+                    if let syn::Meta::NameValue(nv) = meta {
+                        if nv.path.is_ident("rename") {
+                            if let syn::Expr::Lit(expr_lit) = nv.value {
+                                if let syn::Lit::Str(lit_str) = expr_lit.lit {
+                                    fhir_field_name = lit_str.value();
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
     }
+
+    // Extract FHIR type from choice element field name (e.g., "valueCode" -> "code")
+    let fhir_type = if fhir_field_name.starts_with("value") && fhir_field_name.len() > 5 {
+        // Convert first character to lowercase for FHIR primitive types
+        let type_part = &fhir_field_name[5..]; // Remove "value" prefix
+        let mut chars = type_part.chars();
+        match chars.next() {
+            None => variant_name_str.clone(),
+            Some(first) => first.to_lowercase().collect::<String>() + chars.as_str(),
+        }
+    } else if fhir_field_name.ends_with("Boolean") {
+        // Special case for FHIR boolean primitives - use lowercase
+        "boolean".to_string()
+    } else if fhir_field_name.ends_with("Integer") {
+        // Special case for FHIR integer primitives - use lowercase
+        "integer".to_string()
+    } else if fhir_field_name.ends_with("Decimal") {
+        // Special case for FHIR decimal primitives - use lowercase
+        "decimal".to_string()
+    } else if fhir_field_name.ends_with("String") {
+        // Special case for FHIR string primitives - use lowercase
+        "string".to_string()
+    } else {
+        // Fallback to variant name if it doesn't match known patterns
+        // Convert first character to lowercase for consistency with FHIR primitive naming
+        let mut chars = variant_name_str.chars();
+        match chars.next() {
+            None => variant_name_str.clone(),
+            Some(first) => first.to_lowercase().collect::<String>() + chars.as_str(),
+        }
+    };
+    (fhir_field_name, fhir_type)
 }
 
 fn generate_fhirpath_enum_impl(
@@ -2516,6 +2736,12 @@ fn generate_fhirpath_enum_impl(
                         vec![] // Empty enum has no resource types
                     }
                 }
+
+                impl #impl_generics helios_fhirpath_support::FhirPathFields for #name #ty_generics #where_clause {
+                    fn fhirpath_field(&self, _name: &str) -> Option<helios_fhirpath_support::EvaluationResult> {
+                        None // Empty enum has no fields
+                    }
+                }
             }
         } else {
             quote! {}
@@ -2579,57 +2805,13 @@ fn generate_fhirpath_enum_impl(
                 } else {
                     // For other enums (like choice types), preserve type information from the variant
                     // Extract type information from the variant name or rename attribute
-                    let variant_name_str = variant_name.to_string();
-                    // Check for fhir_serde rename attribute to get the FHIR field name
-                    let mut fhir_field_name = variant_name_str.clone();
-                    for attr in &variant.attrs {
-                        if attr.path().is_ident("fhir_serde") {
-                            if let Ok(list) = attr.parse_args_with(syn::punctuated::Punctuated::<syn::Meta, syn::token::Comma>::parse_terminated) {
-                                for meta in list {
-                                    if let syn::Meta::NameValue(nv) = meta {
-                                        if nv.path.is_ident("rename") {
-                                            if let syn::Expr::Lit(expr_lit) = nv.value {
-                                                if let syn::Lit::Str(lit_str) = expr_lit.lit {
-                                                    fhir_field_name = lit_str.value();
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-
-                    // Extract FHIR type from choice element field name (e.g., "valueCode" -> "code")
-                    let fhir_type = if fhir_field_name.starts_with("value") && fhir_field_name.len() > 5 {
-                        // Convert first character to lowercase for FHIR primitive types
-                        let type_part = &fhir_field_name[5..]; // Remove "value" prefix
-                        let mut chars = type_part.chars();
-                        match chars.next() {
-                            None => variant_name_str.clone(),
-                            Some(first) => first.to_lowercase().collect::<String>() + chars.as_str(),
-                        }
-                    } else if fhir_field_name.ends_with("Boolean") {
-                        // Special case for FHIR boolean primitives - use lowercase
-                        "boolean".to_string()
-                    } else if fhir_field_name.ends_with("Integer") {
-                        // Special case for FHIR integer primitives - use lowercase  
-                        "integer".to_string()
-                    } else if fhir_field_name.ends_with("Decimal") {
-                        // Special case for FHIR decimal primitives - use lowercase
-                        "decimal".to_string()
-                    } else if fhir_field_name.ends_with("String") {
-                        // Special case for FHIR string primitives - use lowercase
-                        "string".to_string()
-                    } else {
-                        // Fallback to variant name if it doesn't match known patterns
-                        // Convert first character to lowercase for consistency with FHIR primitive naming
-                        let mut chars = variant_name_str.chars();
-                        match chars.next() {
-                            None => variant_name_str.clone(),
-                            Some(first) => first.to_lowercase().collect::<String>() + chars.as_str(),
+                    let (_, fhir_type) = get_choice_variant_names(variant);
+                    // Temporal choices (e.g. valueDateTime) hold strings that become typed values
+                    let temporal_arm = fhir_temporal_result(&fhir_type, quote! { s }).map(|typed| {
+                        quote! {
+                            helios_fhirpath_support::EvaluationResult::String(s, _) => #typed,
                         }
-                    };
+                    });
                     quote! {
                         Self::#variant_name(value) => {
                             // Get the base evaluation result from the inner value
@@ -2637,6 +2819,7 @@ fn generate_fhirpath_enum_impl(
                             // Add FHIR type information to preserve type for .ofType() operations
                             // Only override type info if it's not already set correctly
                             result = match result {
+                                #temporal_arm
                                 helios_fhirpath_support::EvaluationResult::String(s, existing_type_info) => {
                                     let type_info = existing_type_info.unwrap_or_else(|| helios_fhirpath_support::TypeInfoResult::new("FHIR", &#fhir_type));
                                     helios_fhirpath_support::EvaluationResult::String(s, Some(type_info))
@@ -2731,6 +2914,32 @@ fn generate_fhirpath_enum_impl(
             }
         });
 
+        // Generate field-by-name access for Resource enum, adding resourceType
+        // exactly as the whole-resource conversion does
+        let fhirpath_field_arms = data.variants.iter().map(|variant| {
+            let variant_name = &variant.ident;
+            let variant_name_str = variant_name.to_string();
+
+            match &variant.fields {
+                Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                    quote! {
+                        Self::#variant_name(value) => {
+                            if name == "resourceType" {
+                                Some(helios_fhirpath_support::EvaluationResult::string(#variant_name_str.to_string()))
+                            } else {
+                                value.fhirpath_field(name)
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    quote! {
+                        Self::#variant_name { .. } => None,
+                    }
+                }
+            }
+        });
+
         // Generate get_last_updated method for Resource enum
         let get_last_updated_arms = data.variants.iter().map(|variant| {
             let variant_name = &variant.ident;
@@ -2787,6 +2996,14 @@ fn generate_fhirpath_enum_impl(
                     vec![#(#resource_type_literals),*]
                 }
             }
+
+            impl #impl_generics helios_fhirpath_support::FhirPathFields for #name #ty_generics #where_clause {
+                fn fhirpath_field(&self, name: &str) -> Option<helios_fhirpath_support::EvaluationResult> {
+                    match self {
+                        #(#fhirpath_field_arms)*
+                    }
+                }
+            }
         }
     } else {
         into_evaluation_result_impl
@@ -2879,6 +3096,25 @@ fn extract_type_info_attributes(attrs: &[syn::Attribute], type_name: &Ident) ->
     ("\"FHIR\"".to_string(), format!("\"{}\"", type_name))
 }
 
+/// Generates the typed Date, DateTime or Time result for a FHIR temporal
+/// primitive held as the string `value`, or None for other primitive types.
+///
+/// The generated model structs store temporal primitives as strings, so the
+/// generated code parses `value` each time the field is converted.
+fn fhir_temporal_result(
+    type_name: &str,
+    value: proc_macro2::TokenStream,
+) -> Option<proc_macro2::TokenStream> {
+    match type_name {
+        "date" => Some(quote! { helios_fhirpath_support::EvaluationResult::fhir_date(#value) }),
+        "dateTime" | "instant" => Some(
+            quote! { helios_fhirpath_support::EvaluationResult::fhir_datetime(#value, #type_name) },
+        ),
+        "time" => Some(quote! { helios_fhirpath_support::EvaluationResult::fhir_time(#value) }),
+        _ => None,
+    }
+}
+
 /// Extracts the FHIR type name from a type path for primitive FHIR types.
 /// Returns None if the type is not a recognized FHIR primitive type.
 fn extract_fhir_primitive_type_name(ty: &syn::Type) -> Option<&'static str> {
@@ -40,7 +40,10 @@
 //! let precise = PreciseDecimal::from(Decimal::new(12340, 3)); // 12.340
 //! ```
 
-use helios_fhirpath_support::{EvaluationResult, IntoEvaluationResult};
+use helios_fhirpath_support::{
+    EvaluationResult, FhirPathFields, FhirPathMetadata, FhirPrimitiveValue, IntoEvaluationResult,
+    PrimitiveKind, TypeMetadata,
+};
 use rust_decimal::Decimal;
 use serde::{
     Deserialize, Serialize,
@@ -1714,6 +1717,37 @@ where
     }
 }
 
+// Primitive elements are described by the kind of value they hold; the
+// extension type is needed to walk the `_name` sibling in JSON
+impl<V, E> FhirPathMetadata for Element<V, E>
+where
+    V: FhirPrimitiveValue,
+    E: FhirPathMetadata,
+{
+    fn fhirpath_metadata() -> &'static TypeMetadata {
+        const {
+            &TypeMetadata::Primitive {
+                kind: V::KIND,
+                extension: E::fhirpath_metadata,
+            }
+        }
+    }
+}
+
+impl<E> FhirPathMetadata for DecimalElement<E>
+where
+    E: FhirPathMetadata,
+{
+    fn fhirpath_metadata() -> &'static TypeMetadata {
+        const {
+            &TypeMetadata::Primitive {
+                kind: PrimitiveKind::Decimal,
+                extension: E::fhirpath_metadata,
+            }
+        }
+    }
+}
+
 // Implement the trait for the top-level enum
 impl IntoEvaluationResult for FhirResource {
     fn to_evaluation_result(&self) -> EvaluationResult {
@@ -1732,6 +1766,21 @@ impl IntoEvaluationResult for FhirResource {
     }
 }
 
+impl FhirPathFields for FhirResource {
+    fn fhirpath_field(&self, name: &str) -> Option<EvaluationResult> {
+        match self {
+            #[cfg(feature = "R4")]
+            FhirResource::R4(r) => r.fhirpath_field(name),
+            #[cfg(feature = "R4B")]
+            FhirResource::R4B(r) => r.fhirpath_field(name),
+            #[cfg(feature = "R5")]
+            FhirResource::R5(r) => r.fhirpath_field(name),
+            #[cfg(feature = "R6")]
+            FhirResource::R6(r) => r.fhirpath_field(name),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -18,6 +18,9 @@
 //! - [`EvaluationResult`] - Universal result type for FHIRPath expression evaluation
 //! - [`EvaluationError`] - Comprehensive error handling for evaluation failures
 //! - [`IntoEvaluationResult`] - Trait for converting types to evaluation results
+//! - [`FhirPathFields`] - Field-by-name access for lazy navigation
+//! - [`FhirPathMetadata`] - Static type metadata used to evaluate raw FHIR JSON
+//! - [`FhirPathDate`], [`FhirPathDateTime`], [`FhirPathTime`] - Precision-aware temporal values
 //!
 //! ## Usage Example
 //!
@@ -41,7 +44,17 @@ use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 
+mod metadata;
+mod temporal;
 mod type_info;
+pub use metadata::{
+    ChoiceVariant, ElementMetadata, FhirPathMetadata, FhirPrimitiveValue, MetadataFn,
+    PrimitiveKind, TypeMetadata,
+};
+pub use temporal::{
+    DateTimeComponents, DateTimePrecision, FhirPathDate, FhirPathDateTime, FhirPathTime,
+    parse_date_components, parse_datetime_components, parse_time_components,
+};
 pub use type_info::TypeInfoResult;
 
 /// Universal conversion trait for transforming values into FHIRPath evaluation results.
@@ -85,6 +98,48 @@ pub trait IntoEvaluationResult {
     fn to_evaluation_result(&self) -> EvaluationResult;
 }
 
+/// Field-by-name access for FHIR structures.
+///
+/// Generated by the `FhirPath` derive next to [`IntoEvaluationResult`]. It lets the
+/// evaluator navigate a typed resource lazily, converting only the fields an
+/// expression touches instead of turning the whole resource tree into an
+/// `EvaluationResult::Object` up front.
+///
+/// # Examples
+///
+/// ```rust
+/// use helios_fhirpath_support::{EvaluationResult, FhirPathFields, IntoEvaluationResult};
+///
+/// struct CustomType {
+///     value: String,
+/// }
+///
+/// impl IntoEvaluationResult for CustomType {
+///     fn to_evaluation_result(&self) -> EvaluationResult {
+///         let mut map = std::collections::HashMap::new();
+///         map.insert("value".to_string(), self.value.to_evaluation_result());
+///         EvaluationResult::Object { map, type_info: None }
+///     }
+/// }
+///
+/// impl FhirPathFields for CustomType {
+///     fn fhirpath_field(&self, name: &str) -> Option<EvaluationResult> {
+///         match name {
+///             "value" => Some(self.value.to_evaluation_result()),
+///             _ => None,
+///         }
+///     }
+/// }
+/// ```
+pub trait FhirPathFields: IntoEvaluationResult {
+    /// Converts only the field with the given FHIRPath name.
+    ///
+    /// Returns `None` if the type has no such field and `Some(EvaluationResult::Empty)`
+    /// if the field exists but has no value. Otherwise the result equals the entry
+    /// `to_evaluation_result()` stores under `name`.
+    fn fhirpath_field(&self, name: &str) -> Option<EvaluationResult>;
+}
+
 /// Universal result type for FHIRPath expression evaluation.
 ///
 /// This enum represents any value that can result from evaluating a FHIRPath expression
@@ -169,21 +224,21 @@ pub enum EvaluationResult {
     /// Explicit 64-bit integer type for cases where the distinction from regular
     /// integers is important.
     Integer64(i64, Option<TypeInfoResult>),
-    /// Date value in ISO format.
+    /// Date value of year, month or day precision.
     ///
-    /// Stores date as string in YYYY-MM-DD format. Handles FHIR date fields
-    /// and results from date extraction functions.
-    Date(String, Option<TypeInfoResult>),
-    /// DateTime value in ISO format.
+    /// Holds the parsed components along with the original lexical form.
+    /// Handles FHIR date fields and results from date extraction functions.
+    Date(FhirPathDate, Option<TypeInfoResult>),
+    /// DateTime value of year to millisecond precision with optional timezone.
     ///
-    /// Stores datetime as string in ISO 8601 format with optional timezone.
+    /// Holds the parsed components along with the original lexical form.
     /// Handles FHIR dateTime and instant fields.
-    DateTime(String, Option<TypeInfoResult>),
-    /// Time value in ISO format.
+    DateTime(FhirPathDateTime, Option<TypeInfoResult>),
+    /// Time value of hour to millisecond precision.
     ///
-    /// Stores time as string in HH:MM:SS format. Handles FHIR time fields
-    /// and results from time extraction functions.
-    Time(String, Option<TypeInfoResult>),
+    /// Holds the parsed components along with the original lexical form.
+    /// Handles FHIR time fields and results from time extraction functions.
+    Time(FhirPathTime, Option<TypeInfoResult>),
     /// Quantity with value and unit.
     ///
     /// Represents measurements with units (e.g., "5.4 mg", "10 years").
@@ -340,6 +395,14 @@ pub enum EvaluationError {
     ///
     /// Example: "Property 'invalidField' does not exist on type 'Patient'"
     SemanticError(String),
+    /// An evaluation limit was exceeded or evaluation was cancelled.
+    ///
+    /// Occurs when evaluation nests deeper, builds larger collections or runs
+    /// longer than the limits set on the evaluation context allow, or when
+    /// its cancellation token is cancelled.
+    ///
+    /// Example: "Collection size of 10001 exceeds the limit of 10000"
+    LimitExceeded(String),
     /// Generic error for cases not covered by specific variants.
     ///
     /// Used for internal errors, edge cases, or temporary error conditions
@@ -380,6 +443,7 @@ impl std::fmt::Display for EvaluationError {
                 write!(f, "Singleton Evaluation Error: {}", msg)
             }
             EvaluationError::SemanticError(msg) => write!(f, "Semantic Error: {}", msg),
+            EvaluationError::LimitExceeded(msg) => write!(f, "Limit Exceeded: {}", msg),
             EvaluationError::Other(msg) => write!(f, "Evaluation Error: {}", msg),
         }
     }
@@ -713,18 +777,36 @@ impl EvaluationResult {
     }
 
     /// Creates a Date result with System type.
-    pub fn date(value: String) -> Self {
-        EvaluationResult::Date(value, Some(TypeInfoResult::new("System", "Date")))
+    pub fn date(value: impl Into<FhirPathDate>) -> Self {
+        EvaluationResult::Date(value.into(), Some(TypeInfoResult::new("System", "Date")))
+    }
+
+    /// Creates a Date result with FHIR type.
+    pub fn fhir_date(value: impl Into<FhirPathDate>) -> Self {
+        EvaluationResult::Date(value.into(), Some(TypeInfoResult::new("FHIR", "date")))
     }
 
     /// Creates a DateTime result with System type.
-    pub fn datetime(value: String) -> Self {
-        EvaluationResult::DateTime(value, Some(TypeInfoResult::new("System", "DateTime")))
+    pub fn datetime(value: impl Into<FhirPathDateTime>) -> Self {
+        EvaluationResult::DateTime(
+            value.into(),
+            Some(TypeInfoResult::new("System", "DateTime")),
+        )
+    }
+
+    /// Creates a DateTime result with FHIR type (`dateTime` or `instant`).
+    pub fn fhir_datetime(value: impl Into<FhirPathDateTime>, fhir_type: &str) -> Self {
+        EvaluationResult::DateTime(value.into(), Some(TypeInfoResult::new("FHIR", fhir_type)))
     }
 
     /// Creates a Time result with System type.
-    pub fn time(value: String) -> Self {
-        EvaluationResult::Time(value, Some(TypeInfoResult::new("System", "Time")))
+    pub fn time(value: impl Into<FhirPathTime>) -> Self {
+        EvaluationResult::Time(value.into(), Some(TypeInfoResult::new("System", "Time")))
+    }
+
+    /// Creates a Time result with FHIR type.
+    pub fn fhir_time(value: impl Into<FhirPathTime>) -> Self {
+        EvaluationResult::Time(value.into(), Some(TypeInfoResult::new("FHIR", "time")))
     }
 
     /// Creates a Quantity result with System type.
@@ -804,7 +886,7 @@ impl EvaluationResult {
     }
 
     /// Extracts the date value if this is a Date variant.
-    pub fn as_date(&self) -> Option<&String> {
+    pub fn as_date(&self) -> Option<&FhirPathDate> {
         match self {
             EvaluationResult::Date(val, _) => Some(val),
             _ => None,
@@ -812,7 +894,7 @@ impl EvaluationResult {
     }
 
     /// Extracts the datetime value if this is a DateTime variant.
-    pub fn as_datetime(&self) -> Option<&String> {
+    pub fn as_datetime(&self) -> Option<&FhirPathDateTime> {
         match self {
             EvaluationResult::DateTime(val, _) => Some(val),
             _ => None,
@@ -820,7 +902,7 @@ impl EvaluationResult {
     }
 
     /// Extracts the time value if this is a Time variant.
-    pub fn as_time(&self) -> Option<&String> {
+    pub fn as_time(&self) -> Option<&FhirPathTime> {
         match self {
             EvaluationResult::Time(val, _) => Some(val),
             _ => None,
@@ -834,6 +916,73 @@ impl EvaluationResult {
             _ => None,
         }
     }
+
+    /// Returns the path of the element this value was read from.
+    ///
+    /// Locations are only recorded when the evaluator tracks them, and only
+    /// for values read from a resource, e.g. `Patient.name[1].given[0]`.
+    /// Computed values have no location.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use helios_fhirpath_support::EvaluationResult;
+    ///
+    /// let given = EvaluationResult::fhir_string("Peter".to_string(), "string")
+    ///     .with_location("Patient.name[0].given[0]".to_string());
+    /// assert_eq!(given.location(), Some("Patient.name[0].given[0]"));
+    /// assert_eq!(EvaluationResult::string("Peter".to_string()).location(), None);
+    /// ```
+    pub fn location(&self) -> Option<&str> {
+        self.type_info()?.location.as_deref()
+    }
+
+    /// Records the path of the element this value was read from.
+    ///
+    /// The location is kept with the value's type information, so values
+    /// without type information are returned unchanged.
+    pub fn with_location(mut self, location: String) -> Self {
+        if let Some(type_info) = self.type_info_mut() {
+            type_info.location = Some(location);
+        }
+        self
+    }
+
+    /// The type information of a single value or collection
+    fn type_info(&self) -> Option<&TypeInfoResult> {
+        match self {
+            EvaluationResult::Empty => None,
+            EvaluationResult::Boolean(_, type_info)
+            | EvaluationResult::String(_, type_info)
+            | EvaluationResult::Decimal(_, type_info)
+            | EvaluationResult::Integer(_, type_info)
+            | EvaluationResult::Integer64(_, type_info)
+            | EvaluationResult::Date(_, type_info)
+            | EvaluationResult::DateTime(_, type_info)
+            | EvaluationResult::Time(_, type_info)
+            | EvaluationResult::Quantity(_, _, type_info)
+            | EvaluationResult::Collection { type_info, .. }
+            | EvaluationResult::Object { type_info, .. } => type_info.as_ref(),
+        }
+    }
+
+    fn type_info_mut(&mut self) -> Option<&mut TypeInfoResult> {
+        match self {
+            EvaluationResult::Empty => None,
+            EvaluationResult::Boolean(_, type_info)
+            | EvaluationResult::String(_, type_info)
+            | EvaluationResult::Decimal(_, type_info)
+            | EvaluationResult::Integer(_, type_info)
+            | EvaluationResult::Integer64(_, type_info)
+            | EvaluationResult::Date(_, type_info)
+            | EvaluationResult::DateTime(_, type_info)
+            | EvaluationResult::Time(_, type_info)
+            | EvaluationResult::Quantity(_, _, type_info)
+            | EvaluationResult::Collection { type_info, .. }
+            | EvaluationResult::Object { type_info, .. } => type_info.as_mut(),
+        }
+    }
+
     /// Checks if this result represents a collection.
     ///
     /// Returns `true` only for the `Collection` variant, not for other
@@ -969,9 +1118,9 @@ impl EvaluationResult {
             EvaluationResult::Decimal(d, _) => d.to_string(),
             EvaluationResult::Integer(i, _) => i.to_string(),
             EvaluationResult::Integer64(i, _) => i.to_string(),
-            EvaluationResult::Date(d, _) => d.clone(), // Return stored string
-            EvaluationResult::DateTime(dt, _) => dt.clone(), // Return stored string
-            EvaluationResult::Time(t, _) => t.clone(), // Return stored string
+            EvaluationResult::Date(d, _) => d.to_string(), // Return lexical form
+            EvaluationResult::DateTime(dt, _) => dt.to_string(), // Return lexical form
+            EvaluationResult::Time(t, _) => t.to_string(), // Return lexical form
             EvaluationResult::Quantity(val, unit, _) => {
                 // Format as "value 'unit'" per FHIRPath specification
                 // Use UCUM formatting for calendar units
@@ -1254,6 +1403,16 @@ where
     }
 }
 
+/// Field access through boxed values, as used by the `Resource` enums.
+impl<T> FhirPathFields for Box<T>
+where
+    T: FhirPathFields + ?Sized,
+{
+    fn fhirpath_field(&self, name: &str) -> Option<EvaluationResult> {
+        (**self).fhirpath_field(name)
+    }
+}
+
 /// Convenience function for converting values to evaluation results.
 ///
 /// This function provides a unified interface for conversion that can be used
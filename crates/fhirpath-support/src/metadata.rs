@@ -0,0 +1,156 @@
+//! Static type metadata for FHIR structures.
+//!
+//! The `FhirPath` derive describes every generated FHIR type with a
+//! [`TypeMetadata`] table: the elements of complex types, the variants of choice
+//! types and the kind of value held by primitive elements. The tables mirror the
+//! generated [`IntoEvaluationResult`](crate::IntoEvaluationResult) conversions,
+//! so a FHIRPath evaluator can walk raw FHIR JSON and produce the same
+//! `EvaluationResult`s without deserializing into the typed structures first.
+
+/// Returns the metadata of a type, used to refer to types lazily
+///
+/// FHIR types are recursive (an `Extension` contains `Extension`s), so element
+/// tables refer to their types through functions rather than directly.
+pub type MetadataFn = fn() -> &'static TypeMetadata;
+
+/// The kind of value held by a FHIR primitive element
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrimitiveKind {
+    /// `string`, `code`, `uri`, `date`, `dateTime` and the other string-based primitives
+    String,
+    /// `boolean`
+    Boolean,
+    /// `integer`, `positiveInt` and `unsignedInt`
+    Integer,
+    /// `integer64` (R5 and above)
+    Integer64,
+    /// `decimal`
+    Decimal,
+}
+
+/// Describes how a FHIR type appears in JSON and converts to an `EvaluationResult`
+#[derive(Debug)]
+pub enum TypeMetadata {
+    /// A resource or complex data type, converted to an object typed `FHIR.<name>`
+    Complex {
+        /// The type name used for the object's type information
+        name: &'static str,
+        /// The elements of the type, in declaration order
+        elements: &'static [ElementMetadata],
+    },
+    /// A primitive element such as `Element<String, Extension>`
+    Primitive {
+        /// The kind of value the element holds
+        kind: PrimitiveKind,
+        /// Metadata of the extensions carried in the `_name` sibling
+        extension: MetadataFn,
+    },
+    /// A choice type such as `Observation.value[x]`
+    Choice {
+        /// The types the element can take
+        variants: &'static [ChoiceVariant],
+    },
+    /// A `Resource` enum, resolved through the `resourceType` of the JSON object
+    Resource {
+        /// Looks up the metadata of a resource type by name
+        lookup: fn(&str) -> Option<&'static TypeMetadata>,
+    },
+}
+
+impl TypeMetadata {
+    /// Finds the element with the given FHIRPath name of a complex type
+    pub fn element(&self, name: &str) -> Option<&'static ElementMetadata> {
+        match self {
+            TypeMetadata::Complex { elements, .. } => {
+                elements.iter().find(|element| element.name == name)
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks whether `key` is a JSON property of a complex type
+    ///
+    /// Choice elements appear in JSON under their variant names
+    /// (`valueQuantity`), not under the element name.
+    pub fn has_json_property(&self, key: &str) -> bool {
+        let TypeMetadata::Complex { elements, .. } = self else {
+            return false;
+        };
+        elements.iter().any(|element| match (element.metadata)() {
+            TypeMetadata::Choice { variants } => {
+                variants.iter().any(|variant| variant.json_name == key)
+            }
+            _ => element.name == key,
+        })
+    }
+}
+
+/// Describes one element of a complex type
+#[derive(Debug)]
+pub struct ElementMetadata {
+    /// The FHIRPath name of the element, which is also its JSON property name
+    /// unless the element is a choice type
+    pub name: &'static str,
+    /// Whether the element repeats
+    pub is_array: bool,
+    /// The FHIR primitive type the element is declared with (e.g. `code`), which
+    /// overrides the type information of string values
+    pub primitive_type: Option<&'static str>,
+    /// The FHIR primitive type of the element's values (e.g. `code`), also for
+    /// repeating elements; `None` for complex types and undeclared primitives
+    pub value_type: Option<&'static str>,
+    /// The metadata of the element's type
+    pub metadata: MetadataFn,
+}
+
+/// Describes one type of a choice element
+#[derive(Debug)]
+pub struct ChoiceVariant {
+    /// The JSON property name of this choice (e.g. `valueQuantity`)
+    pub json_name: &'static str,
+    /// The FHIR type given to values that carry no type information
+    pub fhir_type: &'static str,
+    /// The metadata of the variant's type
+    pub metadata: MetadataFn,
+}
+
+/// Types that describe themselves with [`TypeMetadata`].
+///
+/// Generated by the `FhirPath` derive for FHIR structures and choice enums, and
+/// implemented by the FHIR crate for its primitive element types.
+pub trait FhirPathMetadata {
+    /// Returns the metadata describing this type
+    fn fhirpath_metadata() -> &'static TypeMetadata;
+}
+
+/// Metadata of boxed values is that of the boxed type.
+impl<T> FhirPathMetadata for Box<T>
+where
+    T: FhirPathMetadata + ?Sized,
+{
+    fn fhirpath_metadata() -> &'static TypeMetadata {
+        T::fhirpath_metadata()
+    }
+}
+
+/// Rust value types held by FHIR primitive elements
+pub trait FhirPrimitiveValue {
+    /// The kind of primitive this value type represents
+    const KIND: PrimitiveKind;
+}
+
+impl FhirPrimitiveValue for String {
+    const KIND: PrimitiveKind = PrimitiveKind::String;
+}
+
+impl FhirPrimitiveValue for bool {
+    const KIND: PrimitiveKind = PrimitiveKind::Boolean;
+}
+
+impl FhirPrimitiveValue for i32 {
+    const KIND: PrimitiveKind = PrimitiveKind::Integer;
+}
+
+impl FhirPrimitiveValue for i64 {
+    const KIND: PrimitiveKind = PrimitiveKind::Integer64;
+}
@@ -0,0 +1,444 @@
+//! Precision-aware temporal values.
+//!
+//! FHIRPath dates, datetimes and times may be partial: `@2020`, `@2020-03` and
+//! `@2020-03-04T10` are all valid and must keep their precision through
+//! comparison and arithmetic. [`FhirPathDate`], [`FhirPathDateTime`] and
+//! [`FhirPathTime`] hold the parsed [`DateTimeComponents`] of a value together
+//! with the lexical form it was written in, so values are parsed once and still
+//! print exactly as they appeared in the resource or expression.
+//!
+//! A value whose text is not a valid date, datetime or time keeps its text but
+//! has no components; comparisons involving it are empty.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// The precision of a (possibly partial) date, time or datetime value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DateTimePrecision {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Millisecond,
+}
+
+/// The individual components of a Date, DateTime or Time value
+///
+/// Components that are absent from the source string are `None`, so the
+/// precision of partial values such as `@2020` or `@2020-03-04T10` is kept.
+/// Time values have no date components.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTimeComponents {
+    pub year: Option<i32>,
+    pub month: Option<u32>,
+    pub day: Option<u32>,
+    pub hour: Option<u32>,
+    pub minute: Option<u32>,
+    pub second: Option<u32>,
+    pub millisecond: Option<u32>,
+    /// Timezone offset in minutes east of UTC, if the value specifies one
+    pub timezone_offset: Option<i32>,
+}
+
+impl DateTimeComponents {
+    /// Returns the precision of the most precise component present
+    pub fn precision(&self) -> Option<DateTimePrecision> {
+        if self.millisecond.is_some() {
+            Some(DateTimePrecision::Millisecond)
+        } else if self.second.is_some() {
+            Some(DateTimePrecision::Second)
+        } else if self.minute.is_some() {
+            Some(DateTimePrecision::Minute)
+        } else if self.hour.is_some() {
+            Some(DateTimePrecision::Hour)
+        } else if self.day.is_some() {
+            Some(DateTimePrecision::Day)
+        } else if self.month.is_some() {
+            Some(DateTimePrecision::Month)
+        } else if self.year.is_some() {
+            Some(DateTimePrecision::Year)
+        } else {
+            None
+        }
+    }
+
+    /// Drops every component more precise than `precision`
+    pub fn truncate(&self, precision: DateTimePrecision) -> Self {
+        let keep = |p: DateTimePrecision| p <= precision;
+        DateTimeComponents {
+            year: self.year,
+            month: self.month.filter(|_| keep(DateTimePrecision::Month)),
+            day: self.day.filter(|_| keep(DateTimePrecision::Day)),
+            hour: self.hour.filter(|_| keep(DateTimePrecision::Hour)),
+            minute: self.minute.filter(|_| keep(DateTimePrecision::Minute)),
+            second: self.second.filter(|_| keep(DateTimePrecision::Second)),
+            millisecond: self
+                .millisecond
+                .filter(|_| keep(DateTimePrecision::Millisecond)),
+            timezone_offset: self.timezone_offset,
+        }
+    }
+
+    /// Formats the date components as a partial date string (YYYY, YYYY-MM or YYYY-MM-DD)
+    pub fn format_date(&self) -> Option<String> {
+        let mut result = format!("{:04}", self.year?);
+        if let Some(month) = self.month {
+            result.push_str(&format!("-{:02}", month));
+            if let Some(day) = self.day {
+                result.push_str(&format!("-{:02}", day));
+            }
+        }
+        Some(result)
+    }
+
+    /// Formats the time components as a partial time string (HH, HH:mm, HH:mm:ss or HH:mm:ss.sss)
+    pub fn format_time(&self) -> Option<String> {
+        let mut result = format!("{:02}", self.hour?);
+        if let Some(minute) = self.minute {
+            result.push_str(&format!(":{:02}", minute));
+            if let Some(second) = self.second {
+                result.push_str(&format!(":{:02}", second));
+                if let Some(millisecond) = self.millisecond {
+                    result.push_str(&format!(".{:03}", millisecond));
+                }
+            }
+        }
+        Some(result)
+    }
+
+    /// Formats the components as a partial datetime string, e.g. `2020-03-04T10:30Z`
+    pub fn format_datetime(&self) -> Option<String> {
+        let mut result = self.format_date()?;
+        if let Some(time) = self.format_time() {
+            result.push('T');
+            result.push_str(&time);
+            if let Some(offset) = self.timezone_offset {
+                result.push_str(&format_timezone_offset(offset));
+            }
+        }
+        Some(result)
+    }
+}
+
+/// Formats a timezone offset in minutes as Z, +HH:MM or -HH:MM
+fn format_timezone_offset(offset: i32) -> String {
+    if offset == 0 {
+        return "Z".to_string();
+    }
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    format!("{}{:02}:{:02}", sign, offset / 60, offset % 60)
+}
+
+/// Parses a fixed-width run of ASCII digits
+fn parse_digits<T: std::str::FromStr>(s: &str, width: usize) -> Option<T> {
+    if s.len() != width || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// Parses an optional two-digit component in `range`. Returns `Some(None)`
+/// when the component is absent and `None` when it is malformed.
+fn parse_optional_component(
+    part: Option<&str>,
+    range: std::ops::RangeInclusive<u32>,
+) -> Option<Option<u32>> {
+    match part {
+        None => Some(None),
+        Some(part) => parse_digits::<u32>(part, 2)
+            .filter(|value| range.contains(value))
+            .map(Some),
+    }
+}
+
+/// The number of days in a month of the proleptic Gregorian calendar
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parses a partial date string (YYYY, YYYY-MM or YYYY-MM-DD) into its components
+pub fn parse_date_components(date_str: &str) -> Option<DateTimeComponents> {
+    let mut parts = date_str.splitn(3, '-');
+    let year = parse_digits::<i32>(parts.next()?, 4)?;
+    let month = parse_optional_component(parts.next(), 1..=12)?;
+    let day = parse_optional_component(parts.next(), 1..=31)?;
+
+    // Validate against the calendar, e.g. rejects 2023-02-30
+    if let (Some(month), Some(day)) = (month, day)
+        && day > days_in_month(year, month)
+    {
+        return None;
+    }
+
+    Some(DateTimeComponents {
+        year: Some(year),
+        month,
+        day,
+        ..Default::default()
+    })
+}
+
+/// Parses a partial time string (HH, HH:mm, HH:mm:ss or HH:mm:ss.fff, with an
+/// optional leading 'T') into its components. Fractional seconds beyond
+/// milliseconds are truncated.
+pub fn parse_time_components(time_str: &str) -> Option<DateTimeComponents> {
+    let time_str = time_str.strip_prefix('T').unwrap_or(time_str);
+    let (main, fraction) = match time_str.split_once('.') {
+        Some((main, fraction)) => (main, Some(fraction)),
+        None => (time_str, None),
+    };
+
+    let mut parts = main.splitn(3, ':');
+    let hour = parse_digits::<u32>(parts.next()?, 2).filter(|h| *h < 24)?;
+    let minute = parse_optional_component(parts.next(), 0..=59)?;
+    let second = parse_optional_component(parts.next(), 0..=59)?;
+
+    let millisecond = match fraction {
+        None => None,
+        Some(f) if second.is_some() && !f.is_empty() && f.bytes().all(|b| b.is_ascii_digit()) => {
+            // Keep the first three digits, padding shorter fractions ("5" is 500ms)
+            let digits: String = f.chars().chain("00".chars()).take(3).collect();
+            Some(digits.parse().ok()?)
+        }
+        Some(_) => return None,
+    };
+
+    Some(DateTimeComponents {
+        hour: Some(hour),
+        minute,
+        second,
+        millisecond,
+        ..Default::default()
+    })
+}
+
+/// Parses a timezone offset (Z, +HH:MM or -HH:MM) into minutes east of UTC
+fn parse_timezone_offset(tz_str: &str) -> Option<i32> {
+    if tz_str == "Z" {
+        return Some(0);
+    }
+    let (sign, rest) = match tz_str.split_at_checked(1)? {
+        ("+", rest) => (1, rest),
+        ("-", rest) => (-1, rest),
+        _ => return None,
+    };
+    let (hours, minutes) = rest.split_once(':')?;
+    let hours = parse_digits::<i32>(hours, 2).filter(|h| *h <= 14)?;
+    let minutes = parse_digits::<i32>(minutes, 2).filter(|m| *m < 60)?;
+    Some(sign * (hours * 60 + minutes))
+}
+
+/// Parses a partial datetime string into its components
+///
+/// Accepts a date part of any precision, optionally followed by 'T', a partial
+/// time and a timezone offset, e.g. `2020`, `2020-03-04T`, `2020-03-04T10:30Z`
+/// or `2020-03-04T10:30:00.000+05:30`.
+pub fn parse_datetime_components(datetime_str: &str) -> Option<DateTimeComponents> {
+    let (date_part, time_part) = match datetime_str.split_once('T') {
+        Some((date_part, time_part)) => (date_part, time_part),
+        None => (datetime_str, ""),
+    };
+    let date = parse_date_components(date_part)?;
+    if time_part.is_empty() {
+        return Some(date);
+    }
+
+    let (time_only, timezone_offset) = match time_part.find(['Z', '+', '-']) {
+        Some(pos) => (
+            &time_part[..pos],
+            Some(parse_timezone_offset(&time_part[pos..])?),
+        ),
+        None => (time_part, None),
+    };
+    // A time (and therefore a timezone) is only valid on a full date
+    date.day?;
+    let time = parse_time_components(time_only)?;
+
+    Some(DateTimeComponents {
+        hour: time.hour,
+        minute: time.minute,
+        second: time.second,
+        millisecond: time.millisecond,
+        timezone_offset,
+        ..date
+    })
+}
+
+/// Defines a temporal value type holding parsed components and lexical text
+macro_rules! temporal_value {
+    ($(#[$doc:meta])* $name:ident, $parse:ident, $format:ident) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone)]
+        pub struct $name {
+            components: Option<DateTimeComponents>,
+            lexical: String,
+        }
+
+        impl $name {
+            /// Parses a value, returning None when the text is not valid
+            pub fn parse(text: &str) -> Option<Self> {
+                Some(Self {
+                    components: Some($parse(text)?),
+                    lexical: text.to_string(),
+                })
+            }
+
+            /// Creates a value from its components, formatting its lexical form
+            pub fn from_components(components: DateTimeComponents) -> Option<Self> {
+                Some(Self {
+                    lexical: components.$format()?,
+                    components: Some(components),
+                })
+            }
+
+            /// The parsed components, or None when the text is not valid
+            pub fn components(&self) -> Option<&DateTimeComponents> {
+                self.components.as_ref()
+            }
+
+            /// The precision of the value, or None when the text is not valid
+            pub fn precision(&self) -> Option<DateTimePrecision> {
+                self.components?.precision()
+            }
+
+            /// The value as it was written
+            pub fn as_str(&self) -> &str {
+                &self.lexical
+            }
+        }
+
+        /// Keeps the text even when it is not a valid value, so that values
+        /// read from a resource always round-trip
+        impl From<String> for $name {
+            fn from(lexical: String) -> Self {
+                Self {
+                    components: $parse(&lexical),
+                    lexical,
+                }
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(lexical: &str) -> Self {
+                Self::from(lexical.to_string())
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.lexical)
+            }
+        }
+
+        /// Compares by lexical form; FHIRPath equality and ordering, which
+        /// account for precision and timezones, live in the evaluator
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.lexical == other.lexical
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.lexical.cmp(&other.lexical)
+            }
+        }
+
+        impl Hash for $name {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.lexical.hash(state);
+            }
+        }
+    };
+}
+
+temporal_value!(
+    /// A FHIRPath Date of year, month or day precision, e.g. `2020-03`
+    FhirPathDate,
+    parse_date_components,
+    format_date
+);
+
+temporal_value!(
+    /// A FHIRPath DateTime of year to millisecond precision with an optional
+    /// timezone offset, e.g. `2020-03-04T10:30:00.000+05:30`
+    FhirPathDateTime,
+    parse_datetime_components,
+    format_datetime
+);
+
+temporal_value!(
+    /// A FHIRPath Time of hour to millisecond precision, e.g. `10:30`
+    FhirPathTime,
+    parse_time_components,
+    format_time
+);
+
+/// A date is a datetime of day precision or coarser, with the same lexical form
+impl From<FhirPathDate> for FhirPathDateTime {
+    fn from(date: FhirPathDate) -> Self {
+        Self {
+            components: date.components,
+            lexical: date.lexical,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_keep_their_lexical_form() {
+        let datetime = FhirPathDateTime::from("2015-02-04T14:30:05.12-05:30");
+        assert_eq!(datetime.as_str(), "2015-02-04T14:30:05.12-05:30");
+        assert_eq!(datetime.precision(), Some(DateTimePrecision::Millisecond));
+        assert_eq!(datetime.components().unwrap().millisecond, Some(120));
+        assert_eq!(datetime.components().unwrap().timezone_offset, Some(-330));
+
+        let invalid = FhirPathDate::from("2015-02-30");
+        assert_eq!(invalid.to_string(), "2015-02-30");
+        assert_eq!(invalid.components(), None);
+    }
+
+    #[test]
+    fn test_parse_components_keeps_precision() {
+        let date = parse_date_components("2015-02").unwrap();
+        assert_eq!(date.precision(), Some(DateTimePrecision::Month));
+        assert_eq!(date.day, None);
+        assert!(parse_date_components("2015-02-30").is_none());
+        assert!(parse_date_components("2016-02-29").is_some());
+        assert!(parse_date_components("2015-13").is_none());
+
+        let dt = parse_datetime_components("2015-02-04T14:30:05.12-05:30").unwrap();
+        assert_eq!(dt.format_date(), Some("2015-02-04".to_string()));
+        assert_eq!(dt.format_time(), Some("14:30:05.120".to_string()));
+        assert_eq!(
+            dt.format_datetime(),
+            Some("2015-02-04T14:30:05.120-05:30".to_string())
+        );
+        assert!(parse_datetime_components("2015-02T14:30").is_none());
+
+        let time = parse_time_components("T14").unwrap();
+        assert_eq!(time.precision(), Some(DateTimePrecision::Hour));
+        assert!(parse_time_components("25:00").is_none());
+    }
+}
@@ -1,8 +1,14 @@
 /// Type information result for FHIRPath type() function
-#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Values read from a resource while locations are tracked also carry the
+/// path of the element they were read from. The location is not part of the
+/// type, so it is ignored when type information is compared.
+#[derive(Debug, Clone)]
 pub struct TypeInfoResult {
     pub namespace: String,
     pub name: String,
+    /// Path of the element the value was read from, e.g. `Patient.name[1].given[0]`
+    pub location: Option<String>,
 }
 
 impl TypeInfoResult {
@@ -10,6 +16,15 @@ impl TypeInfoResult {
         Self {
             namespace: namespace.to_string(),
             name: name.to_string(),
+            location: None,
         }
     }
 }
+
+impl PartialEq for TypeInfoResult {
+    fn eq(&self, other: &Self) -> bool {
+        self.namespace == other.namespace && self.name == other.name
+    }
+}
+
+impl Eq for TypeInfoResult {}
@@ -123,7 +123,9 @@ fn bench_simple_expressions(c: &mut Criterion) {
                 output: Some(fixture.output_file.clone()),
                 parse_debug_tree: false,
                 parse_debug: false,
+                optimize: false,
                 trace: false,
+                debug_eval: false,
                 fhir_version: helios_fhir::FhirVersion::R4,
                 validate: false,
                 terminology_server: None,
@@ -143,7 +145,9 @@ fn bench_simple_expressions(c: &mut Criterion) {
                 output: Some(fixture.output_file.clone()),
                 parse_debug_tree: false,
                 parse_debug: false,
+                optimize: false,
                 trace: false,
+                debug_eval: false,
                 fhir_version: helios_fhir::FhirVersion::R4,
                 validate: false,
                 terminology_server: None,
@@ -170,7 +174,9 @@ fn bench_context_expressions(c: &mut Criterion) {
                 output: Some(fixture.output_file.clone()),
                 parse_debug_tree: false,
                 parse_debug: false,
+                optimize: false,
                 trace: false,
+                debug_eval: false,
                 fhir_version: helios_fhir::FhirVersion::R4,
                 validate: false,
                 terminology_server: None,
@@ -190,7 +196,9 @@ fn bench_context_expressions(c: &mut Criterion) {
                 output: Some(fixture.output_file.clone()),
                 parse_debug_tree: false,
                 parse_debug: false,
+                optimize: false,
                 trace: false,
+                debug_eval: false,
                 fhir_version: helios_fhir::FhirVersion::R4,
                 validate: false,
                 terminology_server: None,
@@ -217,7 +225,9 @@ fn bench_variable_expressions(c: &mut Criterion) {
                 output: Some(fixture.output_file.clone()),
                 parse_debug_tree: false,
                 parse_debug: false,
+                optimize: false,
                 trace: false,
+                debug_eval: false,
                 fhir_version: helios_fhir::FhirVersion::R4,
                 validate: false,
                 terminology_server: None,
@@ -237,7 +247,9 @@ fn bench_variable_expressions(c: &mut Criterion) {
                 output: Some(fixture.output_file.clone()),
                 parse_debug_tree: false,
                 parse_debug: false,
+                optimize: false,
                 trace: false,
+                debug_eval: false,
                 fhir_version: helios_fhir::FhirVersion::R4,
                 validate: false,
                 terminology_server: None,
@@ -264,7 +276,9 @@ fn bench_bundle_operations(c: &mut Criterion) {
                 output: Some(fixture.output_file.clone()),
                 parse_debug_tree: false,
                 parse_debug: false,
+                optimize: false,
                 trace: false,
+                debug_eval: false,
                 fhir_version: helios_fhir::FhirVersion::R4,
                 validate: false,
                 terminology_server: None,
@@ -284,7 +298,9 @@ fn bench_bundle_operations(c: &mut Criterion) {
                 output: Some(fixture.output_file.clone()),
                 parse_debug_tree: false,
                 parse_debug: false,
+                optimize: false,
                 trace: false,
+                debug_eval: false,
                 fhir_version: helios_fhir::FhirVersion::R4,
                 validate: false,
                 terminology_server: None,
@@ -311,7 +327,9 @@ fn bench_debug_features(c: &mut Criterion) {
                 output: Some(fixture.output_file.clone()),
                 parse_debug_tree: true,
                 parse_debug: false,
+                optimize: false,
                 trace: false,
+                debug_eval: false,
                 fhir_version: helios_fhir::FhirVersion::R4,
                 validate: false,
                 terminology_server: None,
@@ -331,7 +349,9 @@ fn bench_debug_features(c: &mut Criterion) {
                 output: Some(fixture.output_file.clone()),
                 parse_debug_tree: false,
                 parse_debug: false,
+                optimize: false,
                 trace: false,
+                debug_eval: false,
                 fhir_version: helios_fhir::FhirVersion::R4,
                 validate: true,
                 terminology_server: None,
@@ -1,8 +1,12 @@
 use crate::evaluator::EvaluationContext;
 use crate::evaluator::evaluate;
 use crate::parser::Expression;
+use crate::ucum;
 use helios_fhirpath_support::EvaluationError;
 use helios_fhirpath_support::EvaluationResult;
+use rust_decimal::Decimal;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
 
 /// Implements the FHIRPath aggregate() function
 ///
@@ -59,6 +63,17 @@ pub fn aggregate_function(
         agg_context.variables = context.variables.clone(); // Copy variables
         agg_context.is_strict_mode = context.is_strict_mode; // Propagate strict mode
         agg_context.check_ordered_functions = context.check_ordered_functions; // Propagate ordered check
+        agg_context.reference_resolver = context.reference_resolver.clone(); // Propagate resolver
+        agg_context.terminology_provider = context.terminology_provider.clone(); // Propagate terminology provider
+        agg_context.profile_registry = context.profile_registry.clone(); // Propagate profile registry
+        agg_context.function_registry = context.function_registry.clone(); // Propagate custom functions
+        agg_context.limits = context.limits.clone(); // Propagate evaluation limits
+        agg_context.trace_sink = context.trace_sink.clone(); // Propagate trace sink
+        agg_context.trace_buffer = context.trace_buffer.clone(); // Keep traces made in the aggregator
+        agg_context.debugger = context.debugger.clone(); // Record the aggregator's steps
+        agg_context.track_locations = context.track_locations; // Propagate location tracking
+        agg_context.evaluation_depth = Cell::new(context.evaluation_depth.get()); // Nested evaluation continues at the same depth
+        agg_context.defined_variables = RefCell::new(context.defined_variables.borrow().clone()); // Keep defineVariable() scopes
 
         // Set the special $total accumulator for this iteration.
         // The $this context is handled by passing `Some(item)` to `evaluate`.
@@ -79,6 +94,311 @@ pub fn aggregate_function(
     Ok(total)
 }
 
+/// Collects the items of an aggregate function's input, skipping empty entries
+fn aggregate_items(invocation_base: &EvaluationResult) -> Vec<&EvaluationResult> {
+    match invocation_base {
+        EvaluationResult::Collection { items, .. } => items
+            .iter()
+            .filter(|item| !matches!(item, EvaluationResult::Empty))
+            .collect(),
+        EvaluationResult::Empty => vec![],
+        single_item => vec![single_item],
+    }
+}
+
+/// Extracts a (value, unit) pair from a System.Quantity or a FHIR Quantity object
+pub(crate) fn as_quantity(item: &EvaluationResult) -> Option<(Decimal, String)> {
+    match item {
+        EvaluationResult::Quantity(value, unit, _) => Some((*value, unit.clone())),
+        EvaluationResult::Object { map, .. } => {
+            let value = match map.get("value") {
+                Some(EvaluationResult::Decimal(d, _)) => *d,
+                Some(EvaluationResult::Integer(i, _)) => Decimal::from(*i),
+                _ => return None,
+            };
+            // Prefer the coded unit, as the comparison operators do
+            match map.get("code").or_else(|| map.get("unit")) {
+                Some(EvaluationResult::String(unit, _)) => Some((value, unit.clone())),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Running total used by sum() and avg()
+///
+/// Integer values are promoted to Long and Decimal as needed, following
+/// the FHIRPath implicit conversion rules.
+enum NumericTotal {
+    Integer(i64),
+    Long(i64),
+    Decimal(Decimal),
+    Quantity(Decimal, String),
+}
+
+impl NumericTotal {
+    fn from_item(item: &EvaluationResult, function_name: &str) -> Result<Self, EvaluationError> {
+        match item {
+            EvaluationResult::Integer(i, _) => Ok(NumericTotal::Integer(*i)),
+            EvaluationResult::Integer64(i, _) => Ok(NumericTotal::Long(*i)),
+            EvaluationResult::Decimal(d, _) => Ok(NumericTotal::Decimal(*d)),
+            _ => match as_quantity(item) {
+                Some((value, unit)) => Ok(NumericTotal::Quantity(value, unit)),
+                None => Err(EvaluationError::TypeError(format!(
+                    "{}() requires Integer, Long, Decimal or Quantity items, found {}",
+                    function_name,
+                    item.type_name()
+                ))),
+            },
+        }
+    }
+
+    fn add(self, item: &EvaluationResult, function_name: &str) -> Result<Self, EvaluationError> {
+        let next = NumericTotal::from_item(item, function_name)?;
+        match (self, next) {
+            (NumericTotal::Integer(a), NumericTotal::Integer(b)) => a
+                .checked_add(b)
+                .map(NumericTotal::Integer)
+                .ok_or(EvaluationError::ArithmeticOverflow),
+            (
+                NumericTotal::Integer(a) | NumericTotal::Long(a),
+                NumericTotal::Integer(b) | NumericTotal::Long(b),
+            ) => a
+                .checked_add(b)
+                .map(NumericTotal::Long)
+                .ok_or(EvaluationError::ArithmeticOverflow),
+            (NumericTotal::Quantity(a, unit_a), NumericTotal::Quantity(b, unit_b)) => {
+                match ucum::add_quantities(a, &unit_a, b, &unit_b) {
+                    Some((total, unit)) => Ok(NumericTotal::Quantity(total, unit)),
+                    None => Err(EvaluationError::TypeError(format!(
+                        "{}() cannot combine Quantities with incompatible units '{}' and '{}'",
+                        function_name, unit_a, unit_b
+                    ))),
+                }
+            }
+            (NumericTotal::Quantity(..), _) | (_, NumericTotal::Quantity(..)) => {
+                Err(EvaluationError::TypeError(format!(
+                    "{}() cannot combine Quantity and non-Quantity items",
+                    function_name
+                )))
+            }
+            (a, b) => a
+                .as_decimal()
+                .checked_add(b.as_decimal())
+                .map(NumericTotal::Decimal)
+                .ok_or(EvaluationError::ArithmeticOverflow),
+        }
+    }
+
+    fn as_decimal(&self) -> Decimal {
+        match self {
+            NumericTotal::Integer(i) | NumericTotal::Long(i) => Decimal::from(*i),
+            NumericTotal::Decimal(d) | NumericTotal::Quantity(d, _) => *d,
+        }
+    }
+
+    fn into_result(self) -> EvaluationResult {
+        match self {
+            NumericTotal::Integer(i) => EvaluationResult::integer(i),
+            NumericTotal::Long(i) => EvaluationResult::integer64(i),
+            NumericTotal::Decimal(d) => EvaluationResult::decimal(d),
+            NumericTotal::Quantity(d, unit) => EvaluationResult::quantity(d, unit),
+        }
+    }
+}
+
+/// Sums the items of the input collection, or returns None if it is empty
+fn numeric_total(
+    items: &[&EvaluationResult],
+    function_name: &str,
+) -> Result<Option<NumericTotal>, EvaluationError> {
+    let Some((first, rest)) = items.split_first() else {
+        return Ok(None);
+    };
+    let mut total = NumericTotal::from_item(first, function_name)?;
+    for item in rest {
+        total = total.add(item, function_name)?;
+    }
+    Ok(Some(total))
+}
+
+/// Implements the FHIRPath sum() function
+///
+/// Syntax: sum() : Integer | Long | Decimal | Quantity
+///
+/// Returns the sum of the numeric values in the input collection. Integers
+/// are promoted to Long or Decimal when mixed with those types, and
+/// Quantities must have compatible units. This is equivalent to
+/// `aggregate($this + $total, 0)`, so an empty input sums to 0.
+///
+/// # Returns
+///
+/// * The sum of the items, or `0` if the input is empty
+/// * `Err` - If the items are not numeric or cannot be combined
+pub fn sum_function(
+    invocation_base: &EvaluationResult,
+) -> Result<EvaluationResult, EvaluationError> {
+    let items = aggregate_items(invocation_base);
+    Ok(match numeric_total(&items, "sum")? {
+        Some(total) => total.into_result(),
+        None => EvaluationResult::integer(0),
+    })
+}
+
+/// Implements the FHIRPath avg() function
+///
+/// Syntax: avg() : Decimal | Quantity
+///
+/// Returns the average of the numeric values in the input collection.
+/// Integer and Long inputs produce a Decimal result; Quantity inputs produce
+/// a Quantity in the unit of the first item.
+///
+/// # Returns
+///
+/// * The average of the items, or Empty if the input is empty
+/// * `Err` - If the items are not numeric or cannot be combined
+pub fn avg_function(
+    invocation_base: &EvaluationResult,
+) -> Result<EvaluationResult, EvaluationError> {
+    let items = aggregate_items(invocation_base);
+    let Some(total) = numeric_total(&items, "avg")? else {
+        return Ok(EvaluationResult::Empty);
+    };
+    let count = Decimal::from(items.len());
+    Ok(match total {
+        NumericTotal::Quantity(value, unit) => {
+            EvaluationResult::quantity((value / count).normalize(), unit)
+        }
+        other => EvaluationResult::decimal((other.as_decimal() / count).normalize()),
+    })
+}
+
+/// Compares two items for min() and max()
+///
+/// Returns an error when the items are of different types or when the
+/// comparison is not defined (e.g. date/time values with different precision).
+fn compare_extreme_items(
+    left: &EvaluationResult,
+    right: &EvaluationResult,
+    function_name: &str,
+) -> Result<Ordering, EvaluationError> {
+    let ordering = match (left, right) {
+        (
+            EvaluationResult::Integer(a, _) | EvaluationResult::Integer64(a, _),
+            EvaluationResult::Integer(b, _) | EvaluationResult::Integer64(b, _),
+        ) => Some(a.cmp(b)),
+        (EvaluationResult::Decimal(a, _), EvaluationResult::Decimal(b, _)) => Some(a.cmp(b)),
+        (
+            EvaluationResult::Decimal(a, _),
+            EvaluationResult::Integer(b, _) | EvaluationResult::Integer64(b, _),
+        ) => Some(a.cmp(&Decimal::from(*b))),
+        (
+            EvaluationResult::Integer(a, _) | EvaluationResult::Integer64(a, _),
+            EvaluationResult::Decimal(b, _),
+        ) => Some(Decimal::from(*a).cmp(b)),
+        (EvaluationResult::String(a, _), EvaluationResult::String(b, _)) => Some(a.cmp(b)),
+        (
+            EvaluationResult::Date(..)
+            | EvaluationResult::DateTime(..)
+            | EvaluationResult::Time(..),
+            EvaluationResult::Date(..)
+            | EvaluationResult::DateTime(..)
+            | EvaluationResult::Time(..),
+        ) => crate::datetime_impl::compare_date_time_values(left, right),
+        _ => match (as_quantity(left), as_quantity(right)) {
+            (Some((a, unit_a)), Some((b, unit_b))) => {
+                let Some(ordering) = ucum::compare_quantities(a, &unit_a, b, &unit_b) else {
+                    return Err(EvaluationError::TypeError(format!(
+                        "{}() cannot compare Quantities with incompatible units '{}' and '{}'",
+                        function_name, unit_a, unit_b
+                    )));
+                };
+                Some(ordering)
+            }
+            _ => None,
+        },
+    };
+
+    ordering.ok_or_else(|| {
+        EvaluationError::TypeError(format!(
+            "{}() cannot compare {} and {}",
+            function_name,
+            left.type_name(),
+            right.type_name()
+        ))
+    })
+}
+
+/// Shared implementation of min() and max()
+fn extreme_function(
+    invocation_base: &EvaluationResult,
+    wanted: Ordering,
+    function_name: &str,
+) -> Result<EvaluationResult, EvaluationError> {
+    let items = aggregate_items(invocation_base);
+    let Some((first, rest)) = items.split_first() else {
+        return Ok(EvaluationResult::Empty);
+    };
+
+    // Booleans, objects and other non-orderable types are rejected up front,
+    // so a single-item input is validated the same way as a larger one.
+    compare_extreme_items(first, first, function_name)?;
+
+    let mut best = *first;
+    for item in rest {
+        if compare_extreme_items(item, best, function_name)? == wanted {
+            best = item;
+        }
+    }
+
+    // Integer compared against Decimal keeps its own type; promote the result
+    // so min()/max() over mixed numerics always yields a Decimal.
+    let has_decimal = items
+        .iter()
+        .any(|item| matches!(item, EvaluationResult::Decimal(..)));
+    Ok(match best {
+        EvaluationResult::Integer(i, _) | EvaluationResult::Integer64(i, _) if has_decimal => {
+            EvaluationResult::decimal(Decimal::from(*i))
+        }
+        other => other.clone(),
+    })
+}
+
+/// Implements the FHIRPath min() function
+///
+/// Syntax: min() : Integer | Long | Decimal | Quantity | Date | DateTime | Time | String
+///
+/// Returns the smallest item in the input collection. All items must be of
+/// the same (or implicitly convertible) type.
+///
+/// # Returns
+///
+/// * The minimum item, or Empty if the input is empty
+/// * `Err` - If the items are of mixed or non-comparable types
+pub fn min_function(
+    invocation_base: &EvaluationResult,
+) -> Result<EvaluationResult, EvaluationError> {
+    extreme_function(invocation_base, Ordering::Less, "min")
+}
+
+/// Implements the FHIRPath max() function
+///
+/// Syntax: max() : Integer | Long | Decimal | Quantity | Date | DateTime | Time | String
+///
+/// Returns the largest item in the input collection. All items must be of
+/// the same (or implicitly convertible) type.
+///
+/// # Returns
+///
+/// * The maximum item, or Empty if the input is empty
+/// * `Err` - If the items are of mixed or non-comparable types
+pub fn max_function(
+    invocation_base: &EvaluationResult,
+) -> Result<EvaluationResult, EvaluationError> {
+    extreme_function(invocation_base, Ordering::Greater, "max")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,245 @@
+//! # Batch Evaluation
+//!
+//! This module provides [`BatchEvaluator`], which evaluates one
+//! [`CompiledExpression`] against many resources in parallel.
+//!
+//! An [`EvaluationContext`] holds the resources it was created for and keeps
+//! per-evaluation state in `RefCell`s, so it cannot be shared between threads.
+//! The batch evaluator instead runs a pool of worker threads that take
+//! resources from the input one at a time and build a fresh context for each
+//! on the worker, while the compiled expression is shared by all of them.
+//!
+//! ```rust
+//! use helios_fhir::{FhirResource, r4};
+//! use helios_fhirpath::{BatchEvaluator, CompiledExpression, EvaluationResult};
+//!
+//! let patients = ["alice", "bob"].map(|id| {
+//!     let patient: r4::Resource = serde_json::from_value(serde_json::json!({
+//!         "resourceType": "Patient",
+//!         "id": id,
+//!     }))
+//!     .unwrap();
+//!     FhirResource::R4(Box::new(patient))
+//! });
+//!
+//! let compiled = CompiledExpression::compile("Patient.id.upper()")?;
+//! let results = BatchEvaluator::new(compiled).with_threads(2).evaluate(patients);
+//!
+//! assert_eq!(results[0], Ok(EvaluationResult::string("ALICE".to_string())));
+//! assert_eq!(results[1], Ok(EvaluationResult::string("BOB".to_string())));
+//! # Ok::<(), String>(())
+//! ```
+//!
+//! Results are returned in input order, one per resource, whatever order the
+//! workers finish in. [`BatchEvaluator::evaluate`] returns them all at once;
+//! [`BatchEvaluator::evaluate_chunked`] evaluates the input a chunk at a time
+//! and yields the results as each chunk completes, so only one chunk of
+//! resources and results is held at a time. A resource whose evaluation fails gets an `Err` in its
+//! position; the other resources are still evaluated. This includes a panic
+//! while evaluating a resource (or in the setup function), which is reported as
+//! an `EvaluationError::Other` for that resource.
+//!
+//! ## Configuring Contexts
+//!
+//! Variables, terminology providers, limits and other context settings are
+//! applied by a setup function, which is called on the worker thread for each
+//! context it creates:
+//!
+//! ```rust
+//! use helios_fhirpath::{BatchEvaluator, CompiledExpression, EvaluationResult};
+//!
+//! let compiled = CompiledExpression::compile("%threshold + 1")?;
+//! let evaluator = BatchEvaluator::new(compiled).with_context_setup(|context| {
+//!     context.set_variable_result("threshold", EvaluationResult::integer(5));
+//! });
+//! # Ok::<(), String>(())
+//! ```
+//!
+//! Each resource gets a new context and a new run of the setup function, so
+//! no state is carried from one resource to the next. That costs an allocation
+//! of the context and whatever the setup function does for every resource, so
+//! keep setup cheap: build expensive values such as terminology providers once,
+//! outside the function, and share them with `Arc`.
+
+use crate::compiled::CompiledExpression;
+use crate::evaluator::{EvaluationContext, evaluate};
+use helios_fhir::FhirResource;
+use helios_fhirpath_support::{EvaluationError, EvaluationResult};
+use std::any::Any;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// The function applied to each context a [`BatchEvaluator`] creates
+pub type ContextSetup = dyn Fn(&mut EvaluationContext) + Send + Sync;
+
+/// Evaluates a compiled expression against many resources on a pool of threads
+///
+/// Cloning a `BatchEvaluator` is cheap; the expression and setup function are
+/// shared.
+#[derive(Clone)]
+pub struct BatchEvaluator {
+    expression: CompiledExpression,
+    threads: usize,
+    setup: Option<Arc<ContextSetup>>,
+}
+
+impl BatchEvaluator {
+    /// Creates an evaluator for `expression` using one thread per available
+    /// CPU
+    pub fn new(expression: CompiledExpression) -> Self {
+        Self {
+            expression,
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            setup: None,
+        }
+    }
+
+    /// Sets the number of worker threads; zero is treated as one
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Sets a function that configures each context before evaluation, for
+    /// example to set variables, limits or a terminology provider
+    pub fn with_context_setup(
+        mut self,
+        setup: impl Fn(&mut EvaluationContext) + Send + Sync + 'static,
+    ) -> Self {
+        self.setup = Some(Arc::new(setup));
+        self
+    }
+
+    /// The expression being evaluated
+    pub fn expression(&self) -> &CompiledExpression {
+        &self.expression
+    }
+
+    /// Number of worker threads used by [`evaluate`](Self::evaluate)
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Evaluates the expression against each resource, returning the results
+    /// in input order
+    ///
+    /// Resources are taken from `resources` as workers become free, so the
+    /// iterator can produce them lazily. The call returns once every resource
+    /// has been evaluated.
+    pub fn evaluate<I>(&self, resources: I) -> Vec<Result<EvaluationResult, EvaluationError>>
+    where
+        I: IntoIterator<Item = FhirResource>,
+        I::IntoIter: Send,
+    {
+        let queue = Mutex::new(resources.into_iter().enumerate());
+        let next = || {
+            // The lock is only poisoned if the input iterator panics, and
+            // that panic is raised again when its worker is joined
+            queue.lock().unwrap_or_else(|e| e.into_inner()).next()
+        };
+
+        let mut results: Vec<(usize, Result<EvaluationResult, EvaluationError>)> =
+            thread::scope(|scope| {
+                let workers: Vec<_> = (0..self.threads)
+                    .map(|_| {
+                        scope.spawn(|| {
+                            let mut results = Vec::new();
+                            while let Some((index, resource)) = next() {
+                                results.push((index, self.evaluate_one(resource)));
+                            }
+                            results
+                        })
+                    })
+                    .collect();
+
+                workers
+                    .into_iter()
+                    .flat_map(|worker| match worker.join() {
+                        Ok(results) => results,
+                        Err(panic) => std::panic::resume_unwind(panic),
+                    })
+                    .collect()
+            });
+
+        results.sort_unstable_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Evaluates the expression against the resources a chunk at a time,
+    /// yielding the results in input order
+    ///
+    /// Up to `chunk_size` resources are taken from `resources` and evaluated
+    /// in parallel as by [`evaluate`](Self::evaluate); their results are
+    /// yielded before the next chunk is taken. The input is read on the
+    /// calling thread, so it does not need to be `Send`. A `chunk_size` of
+    /// zero is treated as one.
+    pub fn evaluate_chunked<I>(
+        &self,
+        resources: I,
+        chunk_size: usize,
+    ) -> impl Iterator<Item = Result<EvaluationResult, EvaluationError>>
+    where
+        I: IntoIterator<Item = FhirResource>,
+    {
+        let mut resources = resources.into_iter();
+        let chunk_size = chunk_size.max(1);
+        std::iter::from_fn(move || {
+            let chunk: Vec<_> = resources.by_ref().take(chunk_size).collect();
+            (!chunk.is_empty()).then(|| self.evaluate(chunk))
+        })
+        .flatten()
+    }
+
+    /// Evaluates the expression against a single resource in a new context
+    ///
+    /// A panic while setting up or evaluating is caught and returned as an
+    /// error, so it only affects this resource. The context is dropped with
+    /// the failed evaluation, so no state it left behind is reused.
+    fn evaluate_one(&self, resource: FhirResource) -> Result<EvaluationResult, EvaluationError> {
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut context = EvaluationContext::new(vec![resource]);
+            if let Some(setup) = &self.setup {
+                setup(&mut context);
+            }
+            evaluate(self.expression.expression(), &context, None)
+        }))
+        .unwrap_or_else(|panic| {
+            Err(EvaluationError::Other(format!(
+                "Evaluation of '{}' panicked: {}",
+                self.expression.source(),
+                panic_message(panic.as_ref())
+            )))
+        })
+    }
+}
+
+/// The message a panic was raised with, when it has one
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+impl fmt::Debug for BatchEvaluator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchEvaluator")
+            .field("expression", &self.expression.source())
+            .field("threads", &self.threads)
+            .field("setup", &self.setup.is_some())
+            .finish()
+    }
+}
+
+// A batch evaluator is built once and may be shared by the threads that
+// submit batches
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<BatchEvaluator>();
+};
@@ -3,14 +3,20 @@
 //! This binary provides command-line access to FHIRPath expression evaluation.
 //! It allows users to evaluate FHIRPath expressions against FHIR resources,
 //! with support for variables, context expressions, and debugging features.
+//! `fhirpath-cli fmt` prints expressions in canonical form instead.
 //!
 //! See the cli module documentation for detailed usage information.
 
 use clap::Parser;
-use helios_fhirpath::cli::{Args, run_cli};
+use helios_fhirpath::cli::{Cli, Command, run_cli, run_fmt};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
-    run_cli(args)?;
+    let cli = Cli::parse();
+    match (cli.command, cli.args) {
+        (Some(Command::Fmt(args)), _) => run_fmt(args)?,
+        (None, Some(args)) => run_cli(args)?,
+        // The evaluation arguments are required without a subcommand
+        (None, None) => unreachable!(),
+    }
     Ok(())
 }
@@ -1,7 +1,21 @@
+use crate::datetime_impl::{
+    DateTimeComponents, DateTimePrecision, TemporalKind, parse_date_components,
+    parse_datetime_components, parse_time_components, temporal_value,
+};
 use chrono::{Datelike, NaiveDate};
-use helios_fhirpath_support::{EvaluationError, EvaluationResult};
-use rust_decimal::Decimal;
+use helios_fhirpath_support::{EvaluationError, EvaluationResult, FhirPathDateTime};
 use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+
+/// The largest precision accepted for Decimal boundaries
+const MAX_DECIMAL_PRECISION: i64 = 28;
+
+/// Which end of the range of possible values a boundary function returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Boundary {
+    Low,
+    High,
+}
 
 /// Implements the FHIRPath lowBoundary() function
 ///
@@ -9,12 +23,16 @@ use rust_decimal::prelude::FromPrimitive;
 /// given its precision. For example:
 /// - Decimal 1.0 with precision 1 -> 0.95 (precision boundary)
 /// - Date 1970-06 -> 1970-06-01 (start of month)
-/// - DateTime 1970-06-01T12:34 -> 1970-06-01T12:34:00.000Z (start of minute)
+/// - DateTime 1970-06-01T12:34 -> 1970-06-01T12:34:00.000+14:00 (start of minute)
 /// - Time 12:34 -> 12:34:00.000 (start of minute)
 ///
+/// When `precision` is given the result is expressed to that many digits,
+/// e.g. `1.587.lowBoundary(2)` is 1.58 and `@2014.lowBoundary(6)` is @2014-01.
+///
 /// # Arguments
 ///
 /// * `invocation_base` - The input value to find the low boundary for
+/// * `precision` - The optional number of digits of precision for the result
 ///
 /// # Returns
 ///
@@ -23,73 +41,14 @@ use rust_decimal::prelude::FromPrimitive;
 /// * `Err` - If an error occurs, such as when the input is a multi-item collection
 pub fn low_boundary_function(
     invocation_base: &EvaluationResult,
+    precision: Option<i64>,
 ) -> Result<EvaluationResult, EvaluationError> {
-    // Check for singleton
     if invocation_base.count() > 1 {
         return Err(EvaluationError::SingletonEvaluationError(
             "lowBoundary requires a singleton input".to_string(),
         ));
     }
-
-    // Handle each type according to FHIRPath boundary rules
-    Ok(match invocation_base {
-        EvaluationResult::Empty => EvaluationResult::Empty,
-        EvaluationResult::Decimal(d, _) => {
-            // For decimals, the low boundary depends on the precision
-            // The precision is determined by the number of decimal places
-            let decimal_str = d.to_string();
-            let precision = get_decimal_precision(&decimal_str);
-            let low_bound = calculate_decimal_low_boundary(*d, precision);
-            EvaluationResult::decimal(low_bound)
-        }
-        EvaluationResult::Date(date_str, _) => {
-            // For dates, return the earliest possible date given the precision
-            calculate_date_low_boundary(date_str)
-        }
-        EvaluationResult::DateTime(datetime_str, _) => {
-            // For datetimes, return the earliest possible datetime given the precision
-            calculate_datetime_low_boundary(datetime_str)
-        }
-        EvaluationResult::Time(time_str, _) => {
-            // For times, return the earliest possible time given the precision
-            calculate_time_low_boundary(time_str)
-        }
-        EvaluationResult::String(s, type_info) => {
-            // Handle FHIR primitive values that are represented as strings
-            if let Some(ti) = type_info {
-                match ti.name.to_lowercase().as_str() {
-                    "date" => calculate_date_low_boundary(s),
-                    "datetime" => calculate_datetime_low_boundary(s),
-                    "time" => calculate_time_low_boundary(s),
-                    _ => {
-                        // Fallback to pattern matching if type info doesn't match
-                        if looks_like_date(s) {
-                            calculate_date_low_boundary(s)
-                        } else if looks_like_datetime(s) {
-                            calculate_datetime_low_boundary(s)
-                        } else if looks_like_time(s) {
-                            calculate_time_low_boundary(s)
-                        } else {
-                            EvaluationResult::Empty
-                        }
-                    }
-                }
-            } else {
-                // Try to infer type from string format
-                if looks_like_date(s) {
-                    calculate_date_low_boundary(s)
-                } else if looks_like_datetime(s) {
-                    calculate_datetime_low_boundary(s)
-                } else if looks_like_time(s) {
-                    calculate_time_low_boundary(s)
-                } else {
-                    EvaluationResult::Empty
-                }
-            }
-        }
-        // Other types don't have boundaries
-        _ => EvaluationResult::Empty,
-    })
+    Ok(boundary(invocation_base, precision, Boundary::Low))
 }
 
 /// Implements the FHIRPath highBoundary() function
@@ -98,12 +57,16 @@ pub fn low_boundary_function(
 /// given its precision. For example:
 /// - Decimal 1.0 with precision 1 -> 1.05 (precision boundary)
 /// - Date 1970-06 -> 1970-06-30 (end of month)
-/// - DateTime 1970-06-01T12:34 -> 1970-06-01T12:34:59.999Z (end of minute)
+/// - DateTime 1970-06-01T12:34 -> 1970-06-01T12:34:59.999-12:00 (end of minute)
 /// - Time 12:34 -> 12:34:59.999 (end of minute)
 ///
+/// When `precision` is given the result is expressed to that many digits,
+/// e.g. `1.587.highBoundary(2)` is 1.59 and `@2014.highBoundary(6)` is @2014-12.
+///
 /// # Arguments
 ///
 /// * `invocation_base` - The input value to find the high boundary for
+/// * `precision` - The optional number of digits of precision for the result
 ///
 /// # Returns
 ///
@@ -112,73 +75,283 @@ pub fn low_boundary_function(
 /// * `Err` - If an error occurs, such as when the input is a multi-item collection
 pub fn high_boundary_function(
     invocation_base: &EvaluationResult,
+    precision: Option<i64>,
 ) -> Result<EvaluationResult, EvaluationError> {
-    // Check for singleton
     if invocation_base.count() > 1 {
         return Err(EvaluationError::SingletonEvaluationError(
             "highBoundary requires a singleton input".to_string(),
         ));
     }
+    Ok(boundary(invocation_base, precision, Boundary::High))
+}
+
+/// Implements the FHIRPath precision() function
+///
+/// Returns the number of digits of precision of the input:
+/// - Decimal: the number of decimal places (1.58700 -> 5)
+/// - Date: 4, 6 or 8 for year, month or day precision
+/// - DateTime: 4 to 17, counting every digit through milliseconds
+/// - Time: 2, 4, 6 or 9 for hour, minute, second or millisecond precision
+///
+/// # Returns
+///
+/// * `Ok(Integer)` - The precision of the input
+/// * `Ok(Empty)` - If the input is Empty or has no defined precision
+/// * `Err` - If the input is a multi-item collection
+pub fn precision_function(
+    invocation_base: &EvaluationResult,
+) -> Result<EvaluationResult, EvaluationError> {
+    if invocation_base.count() > 1 {
+        return Err(EvaluationError::SingletonEvaluationError(
+            "precision requires a singleton input".to_string(),
+        ));
+    }
 
-    // Handle each type according to FHIRPath boundary rules
     Ok(match invocation_base {
-        EvaluationResult::Empty => EvaluationResult::Empty,
-        EvaluationResult::Decimal(d, _) => {
-            // For decimals, the high boundary depends on the precision
-            // The precision is determined by the number of decimal places
-            let decimal_str = d.to_string();
-            let precision = get_decimal_precision(&decimal_str);
-            let high_bound = calculate_decimal_high_boundary(*d, precision);
-            EvaluationResult::decimal(high_bound)
+        EvaluationResult::Decimal(d, _) => EvaluationResult::integer(d.scale() as i64),
+        EvaluationResult::Integer(_, _) | EvaluationResult::Integer64(_, _) => {
+            EvaluationResult::integer(0)
         }
-        EvaluationResult::Date(date_str, _) => {
-            // For dates, return the latest possible date given the precision
-            calculate_date_high_boundary(date_str)
-        }
-        EvaluationResult::DateTime(datetime_str, _) => {
-            // For datetimes, return the latest possible datetime given the precision
-            calculate_datetime_high_boundary(datetime_str)
-        }
-        EvaluationResult::Time(time_str, _) => {
-            // For times, return the latest possible time given the precision
-            calculate_time_high_boundary(time_str)
+        other => match temporal_value(other) {
+            Some((kind, components)) => components
+                .and_then(|components| components.precision())
+                .map(|precision| EvaluationResult::integer(digits_for(kind, precision)))
+                .unwrap_or(EvaluationResult::Empty),
+            None => EvaluationResult::Empty,
+        },
+    })
+}
+
+/// Computes the low or high boundary of a singleton value
+fn boundary(
+    invocation_base: &EvaluationResult,
+    precision: Option<i64>,
+    which: Boundary,
+) -> EvaluationResult {
+    match invocation_base {
+        EvaluationResult::Decimal(d, _) => decimal_boundary(*d, precision, which)
+            .map(EvaluationResult::decimal)
+            .unwrap_or(EvaluationResult::Empty),
+        EvaluationResult::Integer(i, _) | EvaluationResult::Integer64(i, _) => {
+            // Integers have no decimal places, so 1 ranges over [0.5, 1.5)
+            decimal_boundary(Decimal::from(*i), precision, which)
+                .map(EvaluationResult::decimal)
+                .unwrap_or(EvaluationResult::Empty)
         }
+        EvaluationResult::Quantity(value, unit, _) => decimal_boundary(*value, precision, which)
+            .map(|bound| EvaluationResult::quantity(bound, unit.clone()))
+            .unwrap_or(EvaluationResult::Empty),
         EvaluationResult::String(s, type_info) => {
-            // Handle FHIR primitive values that are represented as strings
-            if let Some(ti) = type_info {
-                match ti.name.to_lowercase().as_str() {
-                    "date" => calculate_date_high_boundary(s),
-                    "datetime" => calculate_datetime_high_boundary(s),
-                    "time" => calculate_time_high_boundary(s),
-                    _ => {
-                        // Fallback to pattern matching if type info doesn't match
-                        if looks_like_date(s) {
-                            calculate_date_high_boundary(s)
-                        } else if looks_like_datetime(s) {
-                            calculate_datetime_high_boundary(s)
-                        } else if looks_like_time(s) {
-                            calculate_time_high_boundary(s)
-                        } else {
-                            EvaluationResult::Empty
-                        }
-                    }
-                }
-            } else {
-                // Try to infer type from string format
-                if looks_like_date(s) {
-                    calculate_date_high_boundary(s)
-                } else if looks_like_datetime(s) {
-                    calculate_datetime_high_boundary(s)
-                } else if looks_like_time(s) {
-                    calculate_time_high_boundary(s)
-                } else {
-                    EvaluationResult::Empty
+            // FHIR primitive values and literals that are represented as strings
+            let type_name = type_info.as_ref().map(|ti| ti.name.as_str());
+            match string_temporal_value(s, type_name) {
+                Some((kind, components)) => {
+                    temporal_boundary(kind, Some(components), precision, which)
                 }
+                None => EvaluationResult::Empty,
             }
         }
-        // Other types don't have boundaries
-        _ => EvaluationResult::Empty,
-    })
+        other => match temporal_value(other) {
+            Some((kind, components)) => temporal_boundary(kind, components, precision, which),
+            // Other types don't have boundaries
+            None => EvaluationResult::Empty,
+        },
+    }
+}
+
+/// Reads a string as a Date, DateTime or Time for the boundary functions
+///
+/// The kind comes from the type name of the value when it names a temporal
+/// type, and is otherwise inferred from the format of the string.
+fn string_temporal_value(
+    s: &str,
+    type_name: Option<&str>,
+) -> Option<(TemporalKind, DateTimeComponents)> {
+    let kind = match type_name.map(str::to_lowercase).as_deref() {
+        Some("date") => TemporalKind::Date,
+        Some("datetime" | "instant") => TemporalKind::DateTime,
+        Some("time") => TemporalKind::Time,
+        _ if s.contains('T') => TemporalKind::DateTime,
+        _ if parse_date_components(s).is_some() => TemporalKind::Date,
+        _ => TemporalKind::Time,
+    };
+    let components = match kind {
+        TemporalKind::Date => parse_date_components(s),
+        TemporalKind::DateTime => parse_datetime_components(s),
+        TemporalKind::Time => parse_time_components(s),
+    }?;
+    Some((kind, components))
+}
+
+/// Returns the number of digits a value of the given kind has at a precision
+fn digits_for(kind: TemporalKind, precision: DateTimePrecision) -> i64 {
+    let date_digits = match precision {
+        DateTimePrecision::Year => 4,
+        DateTimePrecision::Month => 6,
+        DateTimePrecision::Day => 8,
+        DateTimePrecision::Hour => 10,
+        DateTimePrecision::Minute => 12,
+        DateTimePrecision::Second => 14,
+        DateTimePrecision::Millisecond => 17,
+    };
+    match kind {
+        // Times have no date digits
+        TemporalKind::Time => date_digits - 8,
+        TemporalKind::Date | TemporalKind::DateTime => date_digits,
+    }
+}
+
+/// Maps a requested number of digits back to a precision, if it is valid for the kind
+fn precision_for(kind: TemporalKind, digits: i64) -> Option<DateTimePrecision> {
+    let candidates: &[DateTimePrecision] = match kind {
+        TemporalKind::Date => &[
+            DateTimePrecision::Year,
+            DateTimePrecision::Month,
+            DateTimePrecision::Day,
+        ],
+        TemporalKind::DateTime => &[
+            DateTimePrecision::Year,
+            DateTimePrecision::Month,
+            DateTimePrecision::Day,
+            DateTimePrecision::Hour,
+            DateTimePrecision::Minute,
+            DateTimePrecision::Second,
+            DateTimePrecision::Millisecond,
+        ],
+        TemporalKind::Time => &[
+            DateTimePrecision::Hour,
+            DateTimePrecision::Minute,
+            DateTimePrecision::Second,
+            DateTimePrecision::Millisecond,
+        ],
+    };
+    candidates
+        .iter()
+        .copied()
+        .find(|precision| digits_for(kind, *precision) == digits)
+}
+
+/// Calculates the boundary of a decimal value
+///
+/// The value is widened by half a unit in its last decimal place. With an
+/// explicit precision the result is then rounded outwards to that many places,
+/// so the boundary never falls inside the range of possible values.
+fn decimal_boundary(value: Decimal, precision: Option<i64>, which: Boundary) -> Option<Decimal> {
+    let scale = get_decimal_precision(&value.to_string());
+    let bound = match which {
+        Boundary::Low => calculate_decimal_low_boundary(value, scale),
+        Boundary::High => calculate_decimal_high_boundary(value, scale),
+    };
+    let Some(precision) = precision else {
+        return Some(bound);
+    };
+    if !(0..=MAX_DECIMAL_PRECISION).contains(&precision) {
+        return None;
+    }
+
+    let strategy = match which {
+        Boundary::Low => RoundingStrategy::ToNegativeInfinity,
+        Boundary::High => RoundingStrategy::ToPositiveInfinity,
+    };
+    let mut rounded = bound.round_dp_with_strategy(precision as u32, strategy);
+    // Show the requested number of decimal places, e.g. 1.586500
+    rounded.rescale(precision as u32);
+    if rounded.is_zero() {
+        rounded.set_sign_positive(true);
+    }
+    Some(rounded)
+}
+
+/// Calculates the boundary of a Date, DateTime or Time value
+///
+/// Components missing from the input are filled with their lowest or highest
+/// possible values up to the requested precision, which defaults to the
+/// finest precision of the type (day for Date, millisecond otherwise).
+/// DateTimes without a timezone take the earliest (+14:00) or latest (-12:00)
+/// offset, while an explicit offset is preserved.
+fn temporal_boundary(
+    kind: TemporalKind,
+    components: Option<DateTimeComponents>,
+    precision: Option<i64>,
+    which: Boundary,
+) -> EvaluationResult {
+    let target = match precision {
+        Some(digits) => precision_for(kind, digits),
+        None => Some(match kind {
+            TemporalKind::Date => DateTimePrecision::Day,
+            TemporalKind::DateTime | TemporalKind::Time => DateTimePrecision::Millisecond,
+        }),
+    };
+    let (Some(target), Some(components)) = (target, components) else {
+        return EvaluationResult::Empty;
+    };
+    let Some(filled) = fill_components(components, target, which) else {
+        return EvaluationResult::Empty;
+    };
+    let filled = filled.truncate(target);
+
+    match kind {
+        TemporalKind::Date => filled
+            .format_date()
+            .map(EvaluationResult::date)
+            .unwrap_or(EvaluationResult::Empty),
+        TemporalKind::Time => filled
+            .format_time()
+            .map(EvaluationResult::time)
+            .unwrap_or(EvaluationResult::Empty),
+        TemporalKind::DateTime => {
+            let mut filled = filled;
+            // Day precision or coarser carries no time or timezone; otherwise an
+            // unspecified timezone is widened to the earliest or latest offset
+            if filled.hour.is_some() && filled.timezone_offset.is_none() {
+                filled.timezone_offset = Some(match which {
+                    Boundary::Low => 14 * 60,
+                    Boundary::High => -12 * 60,
+                });
+            }
+            FhirPathDateTime::from_components(filled)
+                .map(EvaluationResult::datetime)
+                .unwrap_or(EvaluationResult::Empty)
+        }
+    }
+}
+
+/// Fills the components missing from a value, down to `target`, with their
+/// lowest or highest possible values
+fn fill_components(
+    components: DateTimeComponents,
+    target: DateTimePrecision,
+    which: Boundary,
+) -> Option<DateTimeComponents> {
+    let mut filled = components;
+    let wanted = |precision: DateTimePrecision| precision <= target;
+    let low = which == Boundary::Low;
+
+    if components.year.is_some() {
+        if wanted(DateTimePrecision::Month) && filled.month.is_none() {
+            filled.month = Some(if low { 1 } else { 12 });
+        }
+        if wanted(DateTimePrecision::Day) && filled.day.is_none() {
+            filled.day = Some(if low {
+                1
+            } else {
+                last_day_of_month(filled.year?, filled.month?)?
+            });
+        }
+    }
+    if wanted(DateTimePrecision::Hour) && filled.hour.is_none() {
+        filled.hour = Some(if low { 0 } else { 23 });
+    }
+    if wanted(DateTimePrecision::Minute) && filled.minute.is_none() {
+        filled.minute = Some(if low { 0 } else { 59 });
+    }
+    if wanted(DateTimePrecision::Second) && filled.second.is_none() {
+        filled.second = Some(if low { 0 } else { 59 });
+    }
+    if wanted(DateTimePrecision::Millisecond) && filled.millisecond.is_none() {
+        filled.millisecond = Some(if low { 0 } else { 999 });
+    }
+    Some(filled)
 }
 
 /// Gets the decimal precision (number of decimal places) from a decimal string
@@ -216,51 +389,6 @@ fn calculate_decimal_high_boundary(value: Decimal, precision: u32) -> Decimal {
     }
 }
 
-/// Calculates the low boundary for a date value based on its precision
-fn calculate_date_low_boundary(date_str: &str) -> EvaluationResult {
-    match date_str.len() {
-        4 => {
-            // YYYY format - return January 1st
-            EvaluationResult::date(format!("{}-01-01", date_str))
-        }
-        7 => {
-            // YYYY-MM format - return first day of month
-            EvaluationResult::date(format!("{}-01", date_str))
-        }
-        10 => {
-            // YYYY-MM-DD format - already at day precision, return as-is
-            EvaluationResult::date(date_str.to_string())
-        }
-        _ => EvaluationResult::Empty,
-    }
-}
-
-/// Calculates the high boundary for a date value based on its precision
-fn calculate_date_high_boundary(date_str: &str) -> EvaluationResult {
-    match date_str.len() {
-        4 => {
-            // YYYY format - return December 31st
-            EvaluationResult::date(format!("{}-12-31", date_str))
-        }
-        7 => {
-            // YYYY-MM format - return last day of month
-            if let Ok(year) = date_str[0..4].parse::<i32>() {
-                if let Ok(month) = date_str[5..7].parse::<u32>() {
-                    if let Some(last_day) = last_day_of_month(year, month) {
-                        return EvaluationResult::date(format!("{}-{:02}", date_str, last_day));
-                    }
-                }
-            }
-            EvaluationResult::Empty
-        }
-        10 => {
-            // YYYY-MM-DD format - already at day precision, return as-is
-            EvaluationResult::date(date_str.to_string())
-        }
-        _ => EvaluationResult::Empty,
-    }
-}
-
 /// Gets the last day of a given month and year
 fn last_day_of_month(year: i32, month: u32) -> Option<u32> {
     // Create the first day of the next month, then subtract one day
@@ -275,202 +403,6 @@ fn last_day_of_month(year: i32, month: u32) -> Option<u32> {
     }
 }
 
-/// Calculates the low boundary for a datetime value based on its precision
-fn calculate_datetime_low_boundary(datetime_str: &str) -> EvaluationResult {
-    // Parse the datetime to understand its components
-    if let Some(t_pos) = datetime_str.find('T') {
-        let date_part = &datetime_str[..t_pos];
-        let time_part = &datetime_str[t_pos + 1..];
-
-        // Get timezone info if present
-        let (time_only, _timezone) = extract_timezone(time_part);
-
-        // Determine precision based on time format
-        let low_time = match time_only.len() {
-            2 => format!("{}:00:00.000", time_only), // HH -> HH:00:00.000
-            5 => format!("{}:00.000", time_only),    // HH:MM -> HH:MM:00.000
-            8 => format!("{}.000", time_only),       // HH:MM:SS -> HH:MM:SS.000
-            _ => time_only.to_string(),              // Already precise or unknown format
-        };
-
-        // Combine with date boundary and use the earliest timezone (+14:00 for low boundary)
-        let low_date = match calculate_date_low_boundary(date_part) {
-            EvaluationResult::Date(d, _) => d,
-            _ => date_part.to_string(),
-        };
-
-        let result_str = format!("{}T{}+14:00", low_date, low_time);
-
-        EvaluationResult::datetime(result_str)
-    } else {
-        // No time part, treat as date-only but convert to datetime with earliest timezone
-        let low_date = match calculate_date_low_boundary(datetime_str) {
-            EvaluationResult::Date(d, _) => d,
-            _ => datetime_str.to_string(),
-        };
-        EvaluationResult::datetime(format!("{}T00:00:00.000+14:00", low_date))
-    }
-}
-
-/// Calculates the high boundary for a datetime value based on its precision
-fn calculate_datetime_high_boundary(datetime_str: &str) -> EvaluationResult {
-    // Parse the datetime to understand its components
-    if let Some(t_pos) = datetime_str.find('T') {
-        let date_part = &datetime_str[..t_pos];
-        let time_part = &datetime_str[t_pos + 1..];
-
-        // Get timezone info if present
-        let (time_only, _timezone) = extract_timezone(time_part);
-
-        // Determine precision based on time format
-        let high_time = match time_only.len() {
-            2 => format!("{}:59:59.999", time_only), // HH -> HH:59:59.999
-            5 => format!("{}:59.999", time_only),    // HH:MM -> HH:MM:59.999
-            8 => format!("{}.999", time_only),       // HH:MM:SS -> HH:MM:SS.999
-            _ => time_only.to_string(),              // Already precise or unknown format
-        };
-
-        // Combine with date boundary and use the latest timezone (-12:00 for high boundary)
-        let high_date = match calculate_date_high_boundary(date_part) {
-            EvaluationResult::Date(d, _) => d,
-            _ => date_part.to_string(),
-        };
-
-        let result_str = format!("{}T{}-12:00", high_date, high_time);
-
-        EvaluationResult::datetime(result_str)
-    } else {
-        // No time part, treat as date-only but convert to datetime with latest timezone
-        let high_date = match calculate_date_high_boundary(datetime_str) {
-            EvaluationResult::Date(d, _) => d,
-            _ => datetime_str.to_string(),
-        };
-        EvaluationResult::datetime(format!("{}T23:59:59.999-12:00", high_date))
-    }
-}
-
-/// Extracts timezone information from a time string
-fn extract_timezone(time_str: &str) -> (&str, &str) {
-    // Look for timezone indicators: Z, +HH:MM, -HH:MM
-    if let Some(stripped) = time_str.strip_suffix('Z') {
-        (stripped, "Z")
-    } else if let Some(plus_pos) = time_str.rfind('+') {
-        (&time_str[..plus_pos], &time_str[plus_pos..])
-    } else if let Some(minus_pos) = time_str.rfind('-') {
-        // Make sure this is actually a timezone offset, not part of the date
-        if minus_pos > 2 {
-            // Avoid confusion with time like "12:34-05:00"
-            (&time_str[..minus_pos], &time_str[minus_pos..])
-        } else {
-            (time_str, "")
-        }
-    } else {
-        (time_str, "")
-    }
-}
-
-/// Calculates the low boundary for a time value based on its precision
-fn calculate_time_low_boundary(time_str: &str) -> EvaluationResult {
-    match time_str.len() {
-        2 => {
-            // HH format - return start of hour (00:00.000)
-            EvaluationResult::time(format!("{}:00:00.000", time_str))
-        }
-        5 => {
-            // HH:MM format - return start of minute (00.000)
-            EvaluationResult::time(format!("{}:00.000", time_str))
-        }
-        8 => {
-            // HH:MM:SS format - return start of second (.000)
-            EvaluationResult::time(format!("{}.000", time_str))
-        }
-        _ => {
-            // Already precise or unknown format
-            EvaluationResult::time(time_str.to_string())
-        }
-    }
-}
-
-/// Calculates the high boundary for a time value based on its precision
-fn calculate_time_high_boundary(time_str: &str) -> EvaluationResult {
-    match time_str.len() {
-        2 => {
-            // HH format - return end of hour (59:59.999)
-            EvaluationResult::time(format!("{}:59:59.999", time_str))
-        }
-        5 => {
-            // HH:MM format - return end of minute (59.999)
-            EvaluationResult::time(format!("{}:59.999", time_str))
-        }
-        8 => {
-            // HH:MM:SS format - return end of second (.999)
-            EvaluationResult::time(format!("{}.999", time_str))
-        }
-        _ => {
-            // Already precise or unknown format
-            EvaluationResult::time(time_str.to_string())
-        }
-    }
-}
-
-/// Checks if a string looks like a date (YYYY, YYYY-MM, YYYY-MM-DD)
-fn looks_like_date(s: &str) -> bool {
-    // Basic date pattern matching
-    if s.len() == 4 {
-        // YYYY
-        s.chars().all(|c| c.is_ascii_digit())
-    } else if s.len() == 7 {
-        // YYYY-MM
-        s.chars()
-            .enumerate()
-            .all(|(i, c)| if i == 4 { c == '-' } else { c.is_ascii_digit() })
-    } else if s.len() == 10 {
-        // YYYY-MM-DD
-        s.chars().enumerate().all(|(i, c)| {
-            if i == 4 || i == 7 {
-                c == '-'
-            } else {
-                c.is_ascii_digit()
-            }
-        })
-    } else {
-        false
-    }
-}
-
-/// Checks if a string looks like a datetime (contains 'T')
-fn looks_like_datetime(s: &str) -> bool {
-    s.contains('T')
-}
-
-/// Checks if a string looks like a time (HH, HH:MM, HH:MM:SS, HH:MM:SS.sss)
-fn looks_like_time(s: &str) -> bool {
-    // Basic time pattern matching
-    if s.len() == 2 {
-        // HH
-        s.chars().all(|c| c.is_ascii_digit())
-    } else if s.len() == 5 {
-        // HH:MM
-        s.chars()
-            .enumerate()
-            .all(|(i, c)| if i == 2 { c == ':' } else { c.is_ascii_digit() })
-    } else if s.len() == 8 {
-        // HH:MM:SS
-        s.chars().enumerate().all(|(i, c)| {
-            if i == 2 || i == 5 {
-                c == ':'
-            } else {
-                c.is_ascii_digit()
-            }
-        })
-    } else if s.len() > 8 && s.contains(':') && s.contains('.') {
-        // HH:MM:SS.sss (rough check)
-        true
-    } else {
-        false
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -480,7 +412,7 @@ mod tests {
     fn test_low_boundary_decimal() {
         // Test decimal with precision 1
         let decimal_val = EvaluationResult::decimal(Decimal::from_str("1.0").unwrap());
-        let result = low_boundary_function(&decimal_val).unwrap();
+        let result = low_boundary_function(&decimal_val, None).unwrap();
         assert_eq!(
             result,
             EvaluationResult::decimal(Decimal::from_str("0.95").unwrap())
@@ -491,7 +423,7 @@ mod tests {
     fn test_high_boundary_decimal() {
         // Test decimal with precision 1
         let decimal_val = EvaluationResult::decimal(Decimal::from_str("1.0").unwrap());
-        let result = high_boundary_function(&decimal_val).unwrap();
+        let result = high_boundary_function(&decimal_val, None).unwrap();
         assert_eq!(
             result,
             EvaluationResult::decimal(Decimal::from_str("1.05").unwrap())
@@ -502,7 +434,7 @@ mod tests {
     fn test_low_boundary_date_month() {
         // Test date with month precision
         let date_val = EvaluationResult::date("1970-06".to_string());
-        let result = low_boundary_function(&date_val).unwrap();
+        let result = low_boundary_function(&date_val, None).unwrap();
         assert_eq!(result, EvaluationResult::date("1970-06-01".to_string()));
     }
 
@@ -510,7 +442,7 @@ mod tests {
     fn test_high_boundary_date_month() {
         // Test date with month precision
         let date_val = EvaluationResult::date("1970-06".to_string());
-        let result = high_boundary_function(&date_val).unwrap();
+        let result = high_boundary_function(&date_val, None).unwrap();
         assert_eq!(result, EvaluationResult::date("1970-06-30".to_string()));
     }
 
@@ -518,7 +450,7 @@ mod tests {
     fn test_low_boundary_time_minute() {
         // Test time with minute precision
         let time_val = EvaluationResult::time("12:34".to_string());
-        let result = low_boundary_function(&time_val).unwrap();
+        let result = low_boundary_function(&time_val, None).unwrap();
         assert_eq!(result, EvaluationResult::time("12:34:00.000".to_string()));
     }
 
@@ -526,7 +458,7 @@ mod tests {
     fn test_high_boundary_time_minute() {
         // Test time with minute precision
         let time_val = EvaluationResult::time("12:34".to_string());
-        let result = high_boundary_function(&time_val).unwrap();
+        let result = high_boundary_function(&time_val, None).unwrap();
         assert_eq!(result, EvaluationResult::time("12:34:59.999".to_string()));
     }
 
@@ -534,11 +466,11 @@ mod tests {
     fn test_boundary_empty() {
         let empty = EvaluationResult::Empty;
         assert_eq!(
-            low_boundary_function(&empty).unwrap(),
+            low_boundary_function(&empty, None).unwrap(),
             EvaluationResult::Empty
         );
         assert_eq!(
-            high_boundary_function(&empty).unwrap(),
+            high_boundary_function(&empty, None).unwrap(),
             EvaluationResult::Empty
         );
     }
@@ -12,6 +12,7 @@
 //! - Define variables for use in expressions
 //! - Generate parse debug trees for expression analysis
 //! - Output results in JSON format
+//! - Print expressions in canonical form with the `fmt` subcommand
 //!
 //! ## Command Line Options
 //!
@@ -24,7 +25,9 @@
 //! -o, --output <OUTPUT>            Output file path (defaults to stdout)
 //!     --parse-debug-tree           Output parse debug tree as JSON
 //!     --parse-debug                Output parse debug info
-//!     --trace                      Enable trace output
+//!     --optimize                   Simplify the expression before evaluating or printing it
+//!     --trace                      Print trace() output to stderr
+//!     --debug-eval                 Print each evaluation step to stderr
 //!     --fhir-version <VERSION>     FHIR version [default: R4]
 //!     --validate                   Validate expression before execution
 //!     --terminology-server <URL>   Terminology server URL
@@ -58,6 +61,16 @@
 //! fhirpath-cli -e "Patient.name.given.first()" --parse-debug-tree
 //! ```
 //!
+//! ### Show the optimized parse tree
+//! ```bash
+//! fhirpath-cli -e "name.where(true).given | ('a' + 'b')" --parse-debug --optimize
+//! ```
+//!
+//! ### Step through an evaluation
+//! ```bash
+//! fhirpath-cli -e "Patient.name.where(use = 'official').given" -r patient.json --debug-eval
+//! ```
+//!
 //! ### Output to file
 //! ```bash
 //! fhirpath-cli -e "Patient.name" -r patient.json -o result.json
@@ -67,27 +80,75 @@
 //! ```bash
 //! cat patient.json | fhirpath-cli -e "Patient.name.family" -r -
 //! ```
+//!
+//! ## Formatting Expressions
+//!
+//! `fhirpath-cli fmt` prints expressions in the canonical form produced by the
+//! [`formatter`](crate::formatter) module, without evaluating them:
+//!
+//! ```text
+//! fhirpath-cli fmt [OPTIONS] [EXPRESSIONS]...
+//!
+//! -f, --file <FILE>                  File of expressions, one per line ('-' for stdin)
+//!     --max-width <WIDTH>            Break lines longer than this many characters
+//!     --indent-width <WIDTH>         Spaces to indent continuation lines by [default: 2]
+//!     --check                        Fail if an expression is not in canonical form
+//! -o, --output <OUTPUT>              Output file path (defaults to stdout)
+//! ```
+//!
+//! ```bash
+//! fhirpath-cli fmt "Patient.name.where( use='official' )"
+//! fhirpath-cli fmt --check -f expressions.txt
+//! ```
 
 use std::collections::HashMap;
 use std::fs;
 use std::io::{self, Read, Write};
 use std::path::PathBuf;
+use std::sync::Arc;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use serde_json::{Value, json};
 
+use crate::debug_eval::{EvaluationDebugger, generate_evaluation_debug};
 use crate::error::{FhirPathError, FhirPathResult};
 use crate::evaluator::EvaluationContext;
+use crate::formatter::{FormatOptions, format_source};
+use crate::optimizer::optimize;
 use crate::parse_debug::{expression_to_debug_tree, generate_parse_debug};
-use crate::{EvaluationResult, evaluate_expression};
+use crate::terminology::{HttpTerminologyProvider, TerminologyProvider};
+use crate::trace_sink::{CallbackTraceSink, TraceSink};
+use crate::type_checker::TypeChecker;
+use crate::type_inference::InferredType;
+use crate::{CompiledExpression, EvaluationResult, evaluate_expression};
 use helios_fhir::{FhirResource, FhirVersion};
 
+/// Command line of `fhirpath-cli`: the arguments of an evaluation, or a
+/// subcommand
 #[derive(Parser, Debug)]
 #[command(name = "fhirpath-cli")]
 #[command(about = "FHIRPath CLI tool for evaluating expressions against FHIR resources")]
 #[command(
     long_about = "Evaluate FHIRPath expressions against FHIR resources with support for context expressions, variables, and debug output"
 )]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub args: Option<Args>,
+}
+
+/// Subcommands of `fhirpath-cli`
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print FHIRPath expressions in canonical form
+    Fmt(FmtArgs),
+}
+
+/// Arguments of an evaluation
+#[derive(Parser, Debug)]
 pub struct Args {
     /// FHIRPath expression to evaluate
     #[arg(short, long)]
@@ -121,10 +182,19 @@ pub struct Args {
     #[arg(long)]
     pub parse_debug: bool,
 
-    /// Enable trace output
+    /// Fold constants and remove no-op filters before evaluating or printing
+    /// the parse tree
+    #[arg(long)]
+    pub optimize: bool,
+
+    /// Print trace() output to stderr
     #[arg(long)]
     pub trace: bool,
 
+    /// Print each evaluation step, with its focus, result and timing, to stderr
+    #[arg(long)]
+    pub debug_eval: bool,
+
     /// FHIR version to use for parsing resources
     #[arg(long, value_enum, default_value_t = FhirVersion::R4)]
     pub fhir_version: FhirVersion,
@@ -138,6 +208,33 @@ pub struct Args {
     pub terminology_server: Option<String>,
 }
 
+/// Arguments of `fhirpath-cli fmt`
+#[derive(clap::Args, Debug)]
+pub struct FmtArgs {
+    /// FHIRPath expressions to format
+    pub expressions: Vec<String>,
+
+    /// File of expressions to format, one per line (use '-' for stdin)
+    #[arg(short, long)]
+    pub file: Option<PathBuf>,
+
+    /// Break lines longer than this many characters
+    #[arg(long)]
+    pub max_width: Option<usize>,
+
+    /// Number of spaces to indent continuation lines by
+    #[arg(long, default_value_t = 2)]
+    pub indent_width: usize,
+
+    /// Fail if an expression is not already in canonical form instead of printing it
+    #[arg(long)]
+    pub check: bool,
+
+    /// Output file path (defaults to stdout)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
 /// Parse a key=value pair
 fn parse_var(s: &str) -> Result<(String, String), String> {
     let pos = s
@@ -157,6 +254,11 @@ pub fn run_cli(args: Args) -> FhirPathResult<()> {
     let resource_content = read_input(&args.resource)?;
     let resource_json: Value = serde_json::from_str(&resource_content)?;
 
+    // Check the expression against the FHIR model before evaluating it
+    if args.validate {
+        validate_expression(&args, &resource_json)?;
+    }
+
     // Parse the resource based on FHIR version
     let fhir_resource = parse_fhir_resource(resource_json, args.fhir_version)?;
 
@@ -174,30 +276,62 @@ pub fn run_cli(args: Args) -> FhirPathResult<()> {
     }
 
     // Set terminology server if provided
-    if let Some(terminology_server) = &args.terminology_server {
-        // Note: This would need to be implemented in the evaluator
-        // For now, we'll just note it in the context
+    let terminology_provider = args.terminology_server.as_ref().map(|terminology_server| {
         context.set_variable_result(
             "terminologyServer",
             EvaluationResult::string(terminology_server.clone()),
         );
+        let provider: Arc<dyn TerminologyProvider> = Arc::new(HttpTerminologyProvider::new(
+            terminology_server.clone(),
+            args.fhir_version,
+        ));
+        context.set_terminology_provider(provider.clone());
+        provider
+    });
+
+    // Print traces as they are made if requested
+    let trace_sink: Option<Arc<dyn TraceSink>> = args.trace.then(|| {
+        Arc::new(CallbackTraceSink::new(|name, value| {
+            eprintln!(
+                "trace({}): {}",
+                name,
+                evaluation_result_to_json_value(value)
+            );
+        })) as Arc<dyn TraceSink>
+    });
+    if let Some(sink) = &trace_sink {
+        context.set_trace_sink(sink.clone());
     }
 
-    // Enable trace if requested
-    if args.trace {
-        // Note: This would need to be implemented in the evaluator
-        // For now, we'll set a flag in the context
-        context.set_variable_result("_trace", EvaluationResult::boolean(true));
+    // Record the steps of evaluation if requested
+    let debugger = args.debug_eval.then(|| Arc::new(EvaluationDebugger::new()));
+    if let Some(debugger) = &debugger {
+        context.set_debugger(debugger.clone());
     }
+    let print_steps = |source: &str| {
+        if let Some(debugger) = &debugger {
+            eprint!("{}", generate_evaluation_debug(&debugger.take(), source));
+        }
+    };
 
     // Evaluate context expression if provided
     let result = if let Some(context_expr) = &args.context {
         // First evaluate the context expression
-        let context_result =
-            evaluate_expression(context_expr, &context).map_err(FhirPathError::EvaluationError)?;
+        let context_result = evaluate_expression(context_expr, &context);
+        print_steps(context_expr);
+        let context_result = context_result.map_err(FhirPathError::EvaluationError)?;
 
         // Create a new context with the context result
         let mut scoped_context = EvaluationContext::new(vec![]);
+        if let Some(provider) = terminology_provider {
+            scoped_context.set_terminology_provider(provider);
+        }
+        if let Some(sink) = trace_sink {
+            scoped_context.set_trace_sink(sink);
+        }
+        if let Some(debugger) = &debugger {
+            scoped_context.set_debugger(debugger.clone());
+        }
         // Set the context result as the root
         let context_items = match context_result {
             EvaluationResult::Collection { items, .. } => items,
@@ -211,12 +345,13 @@ pub fn run_cli(args: Args) -> FhirPathResult<()> {
         }
 
         // Evaluate the main expression in the scoped context
-        evaluate_expression(&args.expression, &scoped_context)
-            .map_err(FhirPathError::EvaluationError)?
+        evaluate_main_expression(&args, &scoped_context)
     } else {
         // Evaluate the expression directly
-        evaluate_expression(&args.expression, &context).map_err(FhirPathError::EvaluationError)?
+        evaluate_main_expression(&args, &context)
     };
+    print_steps(&args.expression);
+    let result = result.map_err(FhirPathError::EvaluationError)?;
 
     // Convert result to JSON
     let output = result_to_json(&result)?;
@@ -227,19 +362,121 @@ pub fn run_cli(args: Args) -> FhirPathResult<()> {
     Ok(())
 }
 
+/// Formatting CLI execution function
+///
+/// Prints each expression in canonical form, or with `check` set, reports the
+/// expressions that are not in canonical form on stderr and fails if there are any.
+pub fn run_fmt(args: FmtArgs) -> FhirPathResult<()> {
+    let mut options = FormatOptions::new().with_indent_width(args.indent_width);
+    if let Some(max_width) = args.max_width {
+        options = options.with_max_width(max_width);
+    }
+
+    let mut expressions = args.expressions.clone();
+    if let Some(file) = &args.file {
+        expressions.extend(
+            read_input(file)?
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(str::to_string),
+        );
+    }
+
+    let mut formatted = Vec::with_capacity(expressions.len());
+    let mut unformatted = 0;
+    for expression in &expressions {
+        let canonical = format_source(expression, &options)
+            .map_err(|e| FhirPathError::ParseError(format!("{}: {}", expression, e)))?;
+        if args.check && canonical != *expression {
+            eprintln!("not in canonical form: {}", expression);
+            unformatted += 1;
+        }
+        formatted.push(canonical);
+    }
+
+    if args.check {
+        if unformatted > 0 {
+            return Err(FhirPathError::InvalidInput(format!(
+                "{} of {} expressions are not in canonical form",
+                unformatted,
+                expressions.len()
+            )));
+        }
+        return Ok(());
+    }
+
+    write_output(&args.output, &formatted.join("\n"))
+}
+
+/// Evaluates the main expression, optimizing it first if requested
+fn evaluate_main_expression(
+    args: &Args,
+    context: &EvaluationContext,
+) -> Result<EvaluationResult, String> {
+    if args.optimize {
+        CompiledExpression::compile_optimized(&args.expression)?.evaluate(context)
+    } else {
+        evaluate_expression(&args.expression, context)
+    }
+}
+
+/// Type check the expression (and context expression), reporting diagnostics on
+/// stderr and failing if any of them is an error
+fn validate_expression(args: &Args, resource_json: &Value) -> FhirPathResult<()> {
+    let mut checker = TypeChecker::new(args.fhir_version);
+    if let Some(resource_type) = resource_json.get("resourceType").and_then(Value::as_str) {
+        checker = checker.with_root_type(InferredType::fhir(resource_type));
+    }
+    let mut variable_names: Vec<String> = args.var.iter().map(|(key, _)| key.clone()).collect();
+    if let Some(vars_path) = &args.variables {
+        let variables: HashMap<String, Value> =
+            serde_json::from_str(&fs::read_to_string(vars_path)?)?;
+        variable_names.extend(variables.into_keys());
+    }
+    for name in &variable_names {
+        checker = checker.with_variable(name, InferredType::system("Any"));
+    }
+
+    let mut has_errors = false;
+    if let Some(context_expr) = &args.context {
+        let context_check = checker
+            .check(context_expr)
+            .map_err(FhirPathError::ParseError)?;
+        for diagnostic in &context_check.diagnostics {
+            eprintln!("{}: {}", context_expr, diagnostic);
+        }
+        has_errors |= context_check.has_errors();
+        checker = checker.with_context_types(context_check.return_types);
+    }
+
+    let check = checker
+        .check(&args.expression)
+        .map_err(FhirPathError::ParseError)?;
+    for diagnostic in &check.diagnostics {
+        eprintln!("{}: {}", args.expression, diagnostic);
+    }
+    if has_errors || check.has_errors() {
+        return Err(FhirPathError::InvalidInput(
+            "Expression failed validation".to_string(),
+        ));
+    }
+    Ok(())
+}
+
 /// Handle parse debug output
 fn handle_parse_debug(args: &Args) -> FhirPathResult<()> {
-    use chumsky::Parser as ChumskyParser;
-
     // Parse the expression
-    let parsed = crate::parser::parser()
-        .parse(args.expression.as_str())
-        .map_err(|e| FhirPathError::ParseError(format!("{:?}", e)))?;
+    let mut parsed = crate::parser::parse(&args.expression)
+        .map_err(|e| FhirPathError::ParseError(e.to_string()))?;
+    if args.optimize {
+        parsed = optimize(&parsed);
+    }
 
     let output = if args.parse_debug_tree {
         // Generate JSON debug tree
         // Create a default type context for CLI usage
-        let type_context = crate::type_inference::TypeContext::new();
+        let type_context =
+            crate::type_inference::TypeContext::new().with_fhir_version(args.fhir_version);
         let debug_tree = expression_to_debug_tree(&parsed, &type_context);
         serde_json::to_string_pretty(&debug_tree)?
     } else {
@@ -430,9 +667,9 @@ fn evaluation_result_to_json_value(result: &EvaluationResult) -> Value {
         EvaluationResult::Integer(i, _) => json!(i),
         EvaluationResult::Integer64(i, _) => json!(i),
         EvaluationResult::Decimal(d, _) => json!(d),
-        EvaluationResult::Date(s, _) => json!(s),
-        EvaluationResult::DateTime(s, _) => json!(s),
-        EvaluationResult::Time(s, _) => json!(s),
+        EvaluationResult::Date(s, _) => json!(s.as_str()),
+        EvaluationResult::DateTime(s, _) => json!(s.as_str()),
+        EvaluationResult::Time(s, _) => json!(s.as_str()),
         EvaluationResult::Quantity(value, unit, _) => json!({
             "value": value,
             "unit": unit
@@ -477,7 +714,9 @@ mod tests {
             output: None,
             parse_debug_tree: false,
             parse_debug: false,
+            optimize: false,
             trace: false,
+            debug_eval: false,
             fhir_version: FhirVersion::R4,
             validate: false,
             terminology_server: None,
@@ -603,6 +842,33 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_optimize_option() {
+        let temp_dir = TempDir::new().unwrap();
+        let resource_path = temp_dir.path().join("patient.json");
+        let output_path = temp_dir.path().join("output.txt");
+        fs::write(&resource_path, create_test_resource().to_string()).unwrap();
+
+        let mut args = create_test_args("name.where(true).family | ('D' + 'oe')", resource_path);
+        args.optimize = true;
+        args.output = Some(output_path.clone());
+        assert!(run_cli(args).is_ok());
+        assert!(
+            fs::read_to_string(&output_path)
+                .unwrap()
+                .contains("\"Doe\"")
+        );
+
+        let mut args = create_test_args("name.where(true).family | ('D' + 'oe')", PathBuf::new());
+        args.parse_debug = true;
+        args.optimize = true;
+        args.output = Some(output_path.clone());
+        assert!(run_cli(args).is_ok());
+        let debug = fs::read_to_string(output_path).unwrap();
+        assert!(debug.contains("String(\"Doe\")"), "{}", debug);
+        assert!(!debug.contains("where"), "{}", debug);
+    }
+
     #[test]
     fn test_invalid_resource_file() {
         let args = create_test_args("Patient.name", PathBuf::from("/nonexistent/file.json"));
@@ -718,4 +984,85 @@ mod tests {
         let result = run_cli(args);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_debug_eval_option() {
+        let temp_dir = TempDir::new().unwrap();
+        let resource_path = temp_dir.path().join("patient.json");
+        fs::write(&resource_path, create_test_resource().to_string()).unwrap();
+
+        let mut args = create_test_args("given.first()", resource_path);
+        args.context = Some("Patient.name".to_string());
+        args.debug_eval = true;
+
+        let result = run_cli(args);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_fmt_subcommand() {
+        let cli = Cli::try_parse_from(["fhirpath-cli", "fmt", "--check", "1+2"]).unwrap();
+        let Some(Command::Fmt(args)) = cli.command else {
+            panic!("expected the fmt subcommand");
+        };
+        assert_eq!(args.expressions, vec!["1+2"]);
+        assert!(args.check);
+        assert!(cli.args.is_none());
+
+        let cli =
+            Cli::try_parse_from(["fhirpath-cli", "-e", "name", "-r", "patient.json"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.args.unwrap().expression, "name");
+
+        // Evaluation arguments are required without a subcommand, and are not
+        // accepted with one
+        assert!(Cli::try_parse_from(["fhirpath-cli"]).is_err());
+        assert!(Cli::try_parse_from(["fhirpath-cli", "-e", "name", "fmt", "1"]).is_err());
+    }
+
+    fn create_fmt_args(expressions: &[&str]) -> FmtArgs {
+        FmtArgs {
+            expressions: expressions.iter().map(|e| e.to_string()).collect(),
+            file: None,
+            max_width: None,
+            indent_width: 2,
+            check: false,
+            output: None,
+        }
+    }
+
+    #[test]
+    fn test_fmt_to_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let input_path = temp_dir.path().join("expressions.txt");
+        let output_path = temp_dir.path().join("formatted.txt");
+        fs::write(
+            &input_path,
+            "Patient.name.where( use='official' )\n\n(1+2)*3\n",
+        )
+        .unwrap();
+
+        let mut args = create_fmt_args(&["%`vs-name`"]);
+        args.file = Some(input_path);
+        args.output = Some(output_path.clone());
+
+        assert!(run_fmt(args).is_ok());
+        assert_eq!(
+            fs::read_to_string(output_path).unwrap(),
+            "%`vs-name`\nPatient.name.where(use = 'official')\n(1 + 2) * 3"
+        );
+    }
+
+    #[test]
+    fn test_fmt_check() {
+        let mut args = create_fmt_args(&["Patient.name.given", "1 + 2"]);
+        args.check = true;
+        assert!(run_fmt(args).is_ok());
+
+        let mut args = create_fmt_args(&["Patient.name.given", "1+2"]);
+        args.check = true;
+        assert!(run_fmt(args).is_err());
+
+        assert!(run_fmt(create_fmt_args(&["Patient.name."])).is_err());
+    }
 }
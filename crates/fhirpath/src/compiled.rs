@@ -0,0 +1,333 @@
+//! # Compiled Expressions
+//!
+//! This module provides [`CompiledExpression`], a FHIRPath expression that has
+//! been parsed and validated once and can then be evaluated any number of times,
+//! and [`ExpressionCache`], a bounded cache of compiled expressions keyed by their
+//! source text.
+//!
+//! [`evaluate_expression`](crate::evaluate_expression) parses its input on every
+//! call. When the same expression is evaluated against many resources (for example
+//! a ViewDefinition column path over a large export), the parser dominates the
+//! cost of evaluation. Compile the expression once instead:
+//!
+//! ```rust
+//! use helios_fhirpath::{CompiledExpression, EvaluationContext, EvaluationResult};
+//!
+//! let compiled = CompiledExpression::compile("1 + 2")?;
+//! let context = EvaluationContext::new_empty_with_default_version();
+//!
+//! assert_eq!(compiled.evaluate(&context)?, EvaluationResult::integer(3));
+//! # Ok::<(), String>(())
+//! ```
+//!
+//! Compiled expressions are immutable, cheap to clone and `Send + Sync`, so a
+//! single instance can be shared between threads.
+//! [`CompiledExpression::compile_optimized`] also folds constant sub-expressions
+//! and removes no-op filters at compile time, and
+//! [`ExpressionCache::get_or_compile_optimized`] caches the optimized form.
+//!
+//! ## Compile Cache
+//!
+//! When expressions arrive at runtime (HTTP requests, ViewDefinitions), use an
+//! [`ExpressionCache`] to look them up by source text. The cache holds at most
+//! `capacity` expressions and evicts the least recently used one when full.
+//! [`ExpressionCache::global`] is a process-wide cache shared by the FHIRPath
+//! server and the SQL-on-FHIR crate.
+//!
+//! ```rust
+//! use helios_fhirpath::{EvaluationContext, ExpressionCache};
+//!
+//! let cache = ExpressionCache::new(128);
+//! let context = EvaluationContext::new_empty_with_default_version();
+//!
+//! for _ in 0..3 {
+//!     let compiled = cache.get_or_compile("'a' & 'b'")?;
+//!     compiled.evaluate(&context)?;
+//! }
+//! assert_eq!(cache.len(), 1);
+//! # Ok::<(), String>(())
+//! ```
+
+use crate::evaluator::{EvaluationContext, evaluate};
+use crate::optimizer::optimize;
+use crate::parser::{Expression, parse};
+use helios_fhirpath_support::EvaluationResult;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Number of expressions held by [`ExpressionCache::global`]
+pub const DEFAULT_CACHE_CAPACITY: usize = 1024;
+
+static GLOBAL_CACHE: Lazy<ExpressionCache> =
+    Lazy::new(|| ExpressionCache::new(DEFAULT_CACHE_CAPACITY));
+
+/// A parsed and validated FHIRPath expression, ready for repeated evaluation
+///
+/// Cloning a `CompiledExpression` is cheap: the source text and syntax tree are
+/// shared behind reference counts.
+#[derive(Debug, Clone)]
+pub struct CompiledExpression {
+    source: Arc<str>,
+    expression: Arc<Expression>,
+}
+
+impl CompiledExpression {
+    /// Parses `expression`, returning an error if it is not a complete, valid
+    /// FHIRPath expression
+    pub fn compile(expression: &str) -> Result<Self, String> {
+        let parsed = parse(expression).map_err(|e| {
+            format!(
+                "Failed to parse FHIRPath expression '{}': {}",
+                expression, e
+            )
+        })?;
+
+        Ok(Self {
+            source: Arc::from(expression),
+            expression: Arc::new(parsed),
+        })
+    }
+
+    /// Parses `expression` like [`compile`](Self::compile), then simplifies the
+    /// syntax tree with the [`optimizer`](crate::optimizer)
+    ///
+    /// The optimized expression evaluates to the same results; constant
+    /// sub-expressions are evaluated once here instead of on every evaluation.
+    pub fn compile_optimized(expression: &str) -> Result<Self, String> {
+        Ok(Self::compile(expression)?.optimized())
+    }
+
+    /// A copy of this expression with its syntax tree simplified by the
+    /// [`optimizer`](crate::optimizer), keeping the source text
+    pub fn optimized(&self) -> Self {
+        Self {
+            source: self.source.clone(),
+            expression: Arc::new(optimize(&self.expression)),
+        }
+    }
+
+    /// The source text the expression was compiled from
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The parsed syntax tree
+    pub fn expression(&self) -> &Expression {
+        &self.expression
+    }
+
+    /// Evaluates the expression against the resources in `context`
+    pub fn evaluate(&self, context: &EvaluationContext) -> Result<EvaluationResult, String> {
+        self.evaluate_with_this(context, None)
+    }
+
+    /// Evaluates the expression with `this` as the initial focus
+    ///
+    /// When `this` is `None` the context's default focus
+    /// ([`EvaluationContext::root_item`]) is used, exactly as with
+    /// [`evaluate`](Self::evaluate).
+    pub fn evaluate_with_this(
+        &self,
+        context: &EvaluationContext,
+        this: Option<&EvaluationResult>,
+    ) -> Result<EvaluationResult, String> {
+        evaluate(&self.expression, context, this).map_err(|e| {
+            format!(
+                "Failed to evaluate FHIRPath expression '{}': {}",
+                self.source, e
+            )
+        })
+    }
+}
+
+impl fmt::Display for CompiledExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl std::str::FromStr for CompiledExpression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::compile(s)
+    }
+}
+
+struct CacheEntry {
+    compiled: CompiledExpression,
+    /// The optimized form, once it has been asked for
+    optimized: Option<CompiledExpression>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<String, CacheEntry>,
+    clock: u64,
+}
+
+/// A bounded, thread-safe cache of compiled expressions keyed by source text
+///
+/// When the cache is full, the least recently used expression is evicted.
+/// Expressions that fail to compile are not cached. A capacity of zero disables
+/// caching entirely.
+pub struct ExpressionCache {
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+impl ExpressionCache {
+    /// Creates an empty cache holding at most `capacity` expressions
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// The process-wide cache used by the FHIRPath server and SQL-on-FHIR
+    pub fn global() -> &'static ExpressionCache {
+        &GLOBAL_CACHE
+    }
+
+    /// Returns the compiled form of `expression`, compiling and caching it on
+    /// first use
+    pub fn get_or_compile(&self, expression: &str) -> Result<CompiledExpression, String> {
+        if self.capacity == 0 {
+            return CompiledExpression::compile(expression);
+        }
+
+        {
+            let mut state = self.lock();
+            state.clock += 1;
+            let now = state.clock;
+            if let Some(entry) = state.entries.get_mut(expression) {
+                entry.last_used = now;
+                return Ok(entry.compiled.clone());
+            }
+        }
+
+        // Compile without holding the lock so other threads are not blocked on
+        // the parser
+        let compiled = CompiledExpression::compile(expression)?;
+
+        let mut state = self.lock();
+        if !state.entries.contains_key(expression) && state.entries.len() >= self.capacity {
+            let oldest = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+        state.clock += 1;
+        let now = state.clock;
+        state.entries.insert(
+            expression.to_string(),
+            CacheEntry {
+                compiled: compiled.clone(),
+                optimized: None,
+                last_used: now,
+            },
+        );
+        Ok(compiled)
+    }
+
+    /// Returns the [optimized](CompiledExpression::optimized) form of
+    /// `expression`, compiling, optimizing and caching it on first use
+    ///
+    /// The optimized form is kept in the same cache entry as the plain one, so
+    /// both count as a single expression towards the capacity.
+    pub fn get_or_compile_optimized(&self, expression: &str) -> Result<CompiledExpression, String> {
+        {
+            let mut state = self.lock();
+            state.clock += 1;
+            let now = state.clock;
+            if let Some(CacheEntry {
+                optimized: Some(optimized),
+                last_used,
+                ..
+            }) = state.entries.get_mut(expression)
+            {
+                *last_used = now;
+                return Ok(optimized.clone());
+            }
+        }
+
+        // Optimize without holding the lock, as for compiling
+        let optimized = self.get_or_compile(expression)?.optimized();
+
+        if let Some(entry) = self.lock().entries.get_mut(expression) {
+            entry.optimized = Some(optimized.clone());
+        }
+        Ok(optimized)
+    }
+
+    /// Compiles `expression` through the cache and evaluates it against `context`
+    pub fn evaluate(
+        &self,
+        expression: &str,
+        context: &EvaluationContext,
+    ) -> Result<EvaluationResult, String> {
+        self.get_or_compile(expression)?.evaluate(context)
+    }
+
+    /// Maximum number of expressions held
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of expressions currently cached
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Returns `true` if no expressions are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if `expression` is currently cached
+    pub fn contains(&self, expression: &str) -> bool {
+        self.lock().entries.contains_key(expression)
+    }
+
+    /// Removes all cached expressions
+    pub fn clear(&self) {
+        self.lock().entries.clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        // The cache holds no invariants a panicking thread could break, so a
+        // poisoned lock is still safe to use
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for ExpressionCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+impl fmt::Debug for ExpressionCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExpressionCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+// Compiled expressions and caches are shared across threads by the server and
+// by parallel SQL-on-FHIR runs
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<CompiledExpression>();
+    assert_send_sync::<ExpressionCache>();
+};
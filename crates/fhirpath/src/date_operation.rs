@@ -1,5 +1,9 @@
-use crate::datetime_impl;
+use crate::datetime_impl::{
+    self, DateTimeComponents, DateTimePrecision, TemporalKind, temporal_value,
+};
+use chrono::{Datelike, NaiveDateTime, TimeDelta, Timelike};
 use helios_fhirpath_support::{EvaluationError, EvaluationResult};
+use rust_decimal::Decimal;
 
 /// Check if a value is of a particular date/time type
 /// Handles both 'is' and 'as' operations
@@ -180,6 +184,312 @@ pub fn parse_date_literal(literal: &str) -> Result<EvaluationResult, EvaluationE
     }
 }
 
+/// A calendar duration accepted as the precision of duration() and difference()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CalendarUnit {
+    Years,
+    Months,
+    Weeks,
+    Days,
+    Hours,
+    Minutes,
+    Seconds,
+    Milliseconds,
+}
+
+impl CalendarUnit {
+    /// Parses a calendar duration keyword, singular or plural (e.g. 'year', 'days')
+    fn parse(unit: &str) -> Option<Self> {
+        match unit {
+            "year" | "years" => Some(CalendarUnit::Years),
+            "month" | "months" => Some(CalendarUnit::Months),
+            "week" | "weeks" => Some(CalendarUnit::Weeks),
+            "day" | "days" => Some(CalendarUnit::Days),
+            "hour" | "hours" => Some(CalendarUnit::Hours),
+            "minute" | "minutes" => Some(CalendarUnit::Minutes),
+            "second" | "seconds" => Some(CalendarUnit::Seconds),
+            "millisecond" | "milliseconds" => Some(CalendarUnit::Milliseconds),
+            _ => None,
+        }
+    }
+
+    /// The precision both values need for the unit to be meaningful
+    fn required_precision(self) -> DateTimePrecision {
+        match self {
+            CalendarUnit::Years => DateTimePrecision::Year,
+            CalendarUnit::Months => DateTimePrecision::Month,
+            CalendarUnit::Weeks | CalendarUnit::Days => DateTimePrecision::Day,
+            CalendarUnit::Hours => DateTimePrecision::Hour,
+            CalendarUnit::Minutes => DateTimePrecision::Minute,
+            CalendarUnit::Seconds => DateTimePrecision::Second,
+            CalendarUnit::Milliseconds => DateTimePrecision::Millisecond,
+        }
+    }
+}
+
+/// Extracts the components of a singleton temporal value
+///
+/// Returns `Ok(None)` for an empty input or a value that cannot be parsed, and
+/// an error for multi-item collections or values of a kind not in `accepted`.
+fn singleton_components(
+    function_name: &str,
+    value: &EvaluationResult,
+    accepted: &[TemporalKind],
+) -> Result<Option<(TemporalKind, DateTimeComponents)>, EvaluationError> {
+    let value = match value {
+        EvaluationResult::Empty => return Ok(None),
+        EvaluationResult::Collection { items, .. } if items.len() > 1 => {
+            return Err(EvaluationError::SingletonEvaluationError(format!(
+                "{} requires a singleton input",
+                function_name
+            )));
+        }
+        EvaluationResult::Collection { items, .. } => match items.first() {
+            Some(item) => item,
+            None => return Ok(None),
+        },
+        other => other,
+    };
+
+    let Some((kind, components)) =
+        temporal_value(value).filter(|(kind, _)| accepted.contains(kind))
+    else {
+        let expected: Vec<&str> = accepted.iter().map(|kind| kind.name()).collect();
+        return Err(EvaluationError::TypeError(format!(
+            "{} requires a {} input, found {}",
+            function_name,
+            expected.join(" or "),
+            value.type_name()
+        )));
+    };
+    Ok(components.map(|components| (kind, components)))
+}
+
+/// Implements the FHIRPath component functions yearOf(), monthOf(), dayOf(),
+/// hourOf(), minuteOf(), secondOf(), millisecondOf(), timezoneOffsetOf(),
+/// dateOf() and timeOf()
+///
+/// The result is empty when the input does not have the requested component,
+/// so `@2020.monthOf()` and `@2020-03-04T10.minuteOf()` are both empty.
+/// timezoneOffsetOf() returns the offset in hours as a Decimal.
+pub fn date_component_function(
+    name: &str,
+    invocation_base: &EvaluationResult,
+) -> Result<EvaluationResult, EvaluationError> {
+    let accepted: &[TemporalKind] = match name {
+        "yearOf" | "monthOf" | "dayOf" => &[TemporalKind::Date, TemporalKind::DateTime],
+        "hourOf" | "minuteOf" | "secondOf" | "millisecondOf" => {
+            &[TemporalKind::DateTime, TemporalKind::Time]
+        }
+        _ => &[TemporalKind::DateTime],
+    };
+    let Some((_, components)) = singleton_components(name, invocation_base, accepted)? else {
+        return Ok(EvaluationResult::Empty);
+    };
+
+    let integer = |value: u32| EvaluationResult::integer(value as i64);
+    let result = match name {
+        "yearOf" => components
+            .year
+            .map(|year| EvaluationResult::integer(year as i64)),
+        "monthOf" => components.month.map(integer),
+        "dayOf" => components.day.map(integer),
+        "hourOf" => components.hour.map(integer),
+        "minuteOf" => components.minute.map(integer),
+        "secondOf" => components.second.map(integer),
+        "millisecondOf" => components.millisecond.map(integer),
+        "timezoneOffsetOf" => components
+            .timezone_offset
+            .map(|minutes| EvaluationResult::decimal(Decimal::from(minutes) / Decimal::from(60))),
+        "dateOf" => components.format_date().map(EvaluationResult::date),
+        "timeOf" => components.format_time().map(EvaluationResult::time),
+        _ => {
+            return Err(EvaluationError::InvalidOperation(format!(
+                "Unknown date/time component function: {}",
+                name
+            )));
+        }
+    };
+    Ok(result.unwrap_or(EvaluationResult::Empty))
+}
+
+/// Implements the FHIRPath duration(value, precision) function
+///
+/// Returns the number of whole calendar periods of the given precision between
+/// the input and `value`; negative if `value` is earlier than the input.
+pub fn duration_function(
+    invocation_base: &EvaluationResult,
+    args: &[EvaluationResult],
+) -> Result<EvaluationResult, EvaluationError> {
+    calendar_span_function("duration", invocation_base, args, false)
+}
+
+/// Implements the FHIRPath difference(value, precision) function
+///
+/// Returns the number of calendar boundaries of the given precision crossed
+/// between the input and `value`, so the difference in years between
+/// @2020-12-31 and @2021-01-01 is 1 even though their duration is 0.
+pub fn difference_function(
+    invocation_base: &EvaluationResult,
+    args: &[EvaluationResult],
+) -> Result<EvaluationResult, EvaluationError> {
+    calendar_span_function("difference", invocation_base, args, true)
+}
+
+/// Shared implementation of duration() and difference()
+///
+/// The result is empty if either value is empty or is less precise than the
+/// requested precision. When both values carry a timezone offset they are
+/// compared in UTC; otherwise they are compared as local times.
+fn calendar_span_function(
+    name: &str,
+    invocation_base: &EvaluationResult,
+    args: &[EvaluationResult],
+    count_boundaries: bool,
+) -> Result<EvaluationResult, EvaluationError> {
+    const ALL: &[TemporalKind] = &[
+        TemporalKind::Date,
+        TemporalKind::DateTime,
+        TemporalKind::Time,
+    ];
+    let unit = match &args[1] {
+        EvaluationResult::Empty => return Ok(EvaluationResult::Empty),
+        EvaluationResult::String(unit, _) => CalendarUnit::parse(unit).ok_or_else(|| {
+            EvaluationError::InvalidArgument(format!(
+                "{} precision must be a calendar duration such as 'days' or 'months', found '{}'",
+                name, unit
+            ))
+        })?,
+        _ => {
+            return Err(EvaluationError::InvalidArgument(format!(
+                "{} precision must be a String",
+                name
+            )));
+        }
+    };
+    let Some((start_kind, start)) = singleton_components(name, invocation_base, ALL)? else {
+        return Ok(EvaluationResult::Empty);
+    };
+    let Some((end_kind, end)) = singleton_components(name, &args[0], ALL)? else {
+        return Ok(EvaluationResult::Empty);
+    };
+    if (start_kind == TemporalKind::Time) != (end_kind == TemporalKind::Time) {
+        return Err(EvaluationError::TypeError(format!(
+            "{} cannot compare a {} with a {}",
+            name,
+            start_kind.name(),
+            end_kind.name()
+        )));
+    }
+
+    let required = unit.required_precision();
+    let (Some(start_precision), Some(end_precision)) = (start.precision(), end.precision()) else {
+        return Ok(EvaluationResult::Empty);
+    };
+    // Time values have no date components to count
+    let missing_date = start_kind == TemporalKind::Time && required < DateTimePrecision::Hour;
+    if start_precision < required || end_precision < required || missing_date {
+        return Ok(EvaluationResult::Empty);
+    }
+
+    let (start, end) = match (start.timezone_offset, end.timezone_offset) {
+        (Some(_), Some(_)) => (shift_to_utc(&start), shift_to_utc(&end)),
+        _ => (Some(start), Some(end)),
+    };
+    let (Some(start), Some(end)) = (start, end) else {
+        return Ok(EvaluationResult::Empty);
+    };
+
+    let result = if count_boundaries {
+        boundaries_crossed(&start.truncate(required), &end.truncate(required), unit)
+    } else {
+        let common = start_precision.min(end_precision);
+        whole_periods(&start.truncate(common), &end.truncate(common), unit)
+    };
+    Ok(result.map_or(EvaluationResult::Empty, EvaluationResult::integer))
+}
+
+/// Moves a value with a timezone offset to UTC, keeping its precision.
+/// Values without an hour component are left unchanged.
+fn shift_to_utc(components: &DateTimeComponents) -> Option<DateTimeComponents> {
+    let (Some(offset), Some(_)) = (components.timezone_offset, components.hour) else {
+        return Some(*components);
+    };
+    let utc = datetime_impl::to_naive_datetime(components)? - TimeDelta::minutes(offset as i64);
+    Some(DateTimeComponents {
+        year: Some(utc.year()),
+        month: Some(utc.month()),
+        day: Some(utc.day()),
+        hour: Some(utc.hour()),
+        minute: components.minute.map(|_| utc.minute()),
+        second: components.second.map(|_| utc.second()),
+        millisecond: components.millisecond,
+        timezone_offset: Some(0),
+    })
+}
+
+/// Counts the whole periods of `unit` from `start` to `end`
+fn whole_periods(
+    start: &DateTimeComponents,
+    end: &DateTimeComponents,
+    unit: CalendarUnit,
+) -> Option<i64> {
+    let start = datetime_impl::to_naive_datetime(start)?;
+    let end = datetime_impl::to_naive_datetime(end)?;
+    Some(match unit {
+        CalendarUnit::Years => whole_months(start, end) / 12,
+        CalendarUnit::Months => whole_months(start, end),
+        _ => fixed_periods(start, end, unit),
+    })
+}
+
+/// Counts the whole calendar months from `start` to `end`. A month is only
+/// complete once `end` reaches the day and time of month of `start`.
+fn whole_months(start: NaiveDateTime, end: NaiveDateTime) -> i64 {
+    if end < start {
+        return -whole_months(end, start);
+    }
+    let months =
+        (end.year() - start.year()) as i64 * 12 + end.month() as i64 - start.month() as i64;
+    if (end.day(), end.time()) < (start.day(), start.time()) {
+        months - 1
+    } else {
+        months
+    }
+}
+
+/// Counts the calendar boundaries of `unit` crossed from `start` to `end`.
+/// Both values must already be truncated to the precision of `unit`; weeks
+/// have no calendar boundary and count whole multiples of seven days.
+fn boundaries_crossed(
+    start: &DateTimeComponents,
+    end: &DateTimeComponents,
+    unit: CalendarUnit,
+) -> Option<i64> {
+    let start = datetime_impl::to_naive_datetime(start)?;
+    let end = datetime_impl::to_naive_datetime(end)?;
+    Some(match unit {
+        CalendarUnit::Years => (end.year() - start.year()) as i64,
+        CalendarUnit::Months => {
+            (end.year() - start.year()) as i64 * 12 + end.month() as i64 - start.month() as i64
+        }
+        _ => fixed_periods(start, end, unit),
+    })
+}
+
+/// Counts the whole fixed-length periods (weeks and shorter) from `start` to `end`
+fn fixed_periods(start: NaiveDateTime, end: NaiveDateTime, unit: CalendarUnit) -> i64 {
+    let elapsed = end - start;
+    match unit {
+        CalendarUnit::Weeks => elapsed.num_weeks(),
+        CalendarUnit::Days => elapsed.num_days(),
+        CalendarUnit::Hours => elapsed.num_hours(),
+        CalendarUnit::Minutes => elapsed.num_minutes(),
+        CalendarUnit::Seconds => elapsed.num_seconds(),
+        _ => elapsed.num_milliseconds(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,7 +1,48 @@
-use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc};
 use helios_fhirpath_support::EvaluationResult;
 use std::cmp::Ordering;
 
+pub use helios_fhirpath_support::{
+    DateTimeComponents, DateTimePrecision, parse_date_components, parse_datetime_components,
+    parse_time_components,
+};
+
+/// The kinds of temporal value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemporalKind {
+    Date,
+    DateTime,
+    Time,
+}
+
+impl TemporalKind {
+    /// The FHIRPath type name of values of this kind
+    pub fn name(self) -> &'static str {
+        match self {
+            TemporalKind::Date => "Date",
+            TemporalKind::DateTime => "DateTime",
+            TemporalKind::Time => "Time",
+        }
+    }
+}
+
+/// Identifies Date, DateTime and Time values and their components
+///
+/// FHIR date, dateTime, instant and time elements are read as typed temporal
+/// values, so strings are never temporal, whatever their format.
+pub fn temporal_value(
+    value: &EvaluationResult,
+) -> Option<(TemporalKind, Option<DateTimeComponents>)> {
+    match value {
+        EvaluationResult::Date(d, _) => Some((TemporalKind::Date, d.components().copied())),
+        EvaluationResult::DateTime(dt, _) => {
+            Some((TemporalKind::DateTime, dt.components().copied()))
+        }
+        EvaluationResult::Time(t, _) => Some((TemporalKind::Time, t.components().copied())),
+        _ => None,
+    }
+}
+
 /// Normalizes a date string to a consistent format
 /// FHIR dates can be YYYY, YYYY-MM, or YYYY-MM-DD format
 pub fn normalize_date(date_str: &str) -> String {
@@ -113,25 +154,23 @@ pub fn compare_date_time_values(
     right: &EvaluationResult,
 ) -> Option<Ordering> {
     match (left, right) {
-        // Direct comparisons of same types
-        (EvaluationResult::Date(d1, _), EvaluationResult::Date(d2, _)) => compare_dates(d1, d2),
-        (EvaluationResult::Time(t1, _), EvaluationResult::Time(t2, _)) => compare_times(t1, t2),
+        // Direct comparisons of same types, using the already parsed components
+        (EvaluationResult::Date(d1, _), EvaluationResult::Date(d2, _)) => {
+            compare_components(d1.components(), d2.components())
+        }
+        (EvaluationResult::Time(t1, _), EvaluationResult::Time(t2, _)) => {
+            compare_components(t1.components(), t2.components())
+        }
         (EvaluationResult::DateTime(dt1, _), EvaluationResult::DateTime(dt2, _)) => {
-            compare_datetimes(dt1, dt2)
+            compare_components(dt1.components(), dt2.components())
         }
 
-        // Date vs DateTime comparison
+        // Date vs DateTime comparison, taking the date as 00:00:00 on that day
         (EvaluationResult::Date(d, _), EvaluationResult::DateTime(dt, _)) => {
-            // Convert date to datetime with time 00:00:00 for comparison
-            let d_normalized = normalize_date(d);
-            let d_as_dt = format!("{}T00:00:00", d_normalized);
-            compare_datetimes(&d_as_dt, dt)
+            compare_components(d.components(), dt.components())
         }
         (EvaluationResult::DateTime(dt, _), EvaluationResult::Date(d, _)) => {
-            // Convert date to datetime with time 00:00:00 for comparison
-            let d_normalized = normalize_date(d);
-            let d_as_dt = format!("{}T00:00:00", d_normalized);
-            compare_datetimes(dt, &d_as_dt)
+            compare_components(dt.components(), d.components())
         }
 
         // Handle string-based date/time formats
@@ -168,29 +207,35 @@ pub fn compare_date_time_values(
         // String vs Date
         (EvaluationResult::String(s_val, _), EvaluationResult::Date(d_val, _)) => {
             // Attempt to parse s_val as a date and compare with d_val
-            compare_dates(s_val, d_val)
+            compare_components(parse_date_components(s_val).as_ref(), d_val.components())
         }
         (EvaluationResult::Date(d_val, _), EvaluationResult::String(s_val, _)) => {
             // Attempt to parse s_val as a date and compare with d_val
-            compare_dates(d_val, s_val)
+            compare_components(d_val.components(), parse_date_components(s_val).as_ref())
         }
         // String vs DateTime
         (EvaluationResult::String(s_val, _), EvaluationResult::DateTime(dt_val, _)) => {
             // Attempt to parse s_val as a datetime and compare with dt_val
-            compare_datetimes(s_val, dt_val)
+            compare_components(
+                parse_datetime_components(s_val).as_ref(),
+                dt_val.components(),
+            )
         }
         (EvaluationResult::DateTime(dt_val, _), EvaluationResult::String(s_val, _)) => {
             // Attempt to parse s_val as a datetime and compare with dt_val
-            compare_datetimes(dt_val, s_val)
+            compare_components(
+                dt_val.components(),
+                parse_datetime_components(s_val).as_ref(),
+            )
         }
         // String vs Time
         (EvaluationResult::String(s_val, _), EvaluationResult::Time(t_val, _)) => {
             // Attempt to parse s_val as a time and compare with t_val
-            compare_times(s_val, t_val)
+            compare_components(parse_time_components(s_val).as_ref(), t_val.components())
         }
         (EvaluationResult::Time(t_val, _), EvaluationResult::String(s_val, _)) => {
             // Attempt to parse s_val as a time and compare with t_val
-            compare_times(t_val, s_val)
+            compare_components(t_val.components(), parse_time_components(s_val).as_ref())
         }
 
         // Cannot compare different types
@@ -201,10 +246,10 @@ pub fn compare_date_time_values(
 /// Converts a value to a date representation if possible
 pub fn to_date(value: &EvaluationResult) -> Option<String> {
     match value {
-        EvaluationResult::Date(d, _) => Some(d.clone()),
+        EvaluationResult::Date(d, _) => Some(d.to_string()),
         EvaluationResult::DateTime(dt, _) => {
             // Extract date part from datetime
-            let parts: Vec<&str> = dt.split('T').collect();
+            let parts: Vec<&str> = dt.as_str().split('T').collect();
             if !parts.is_empty() {
                 Some(parts[0].to_string())
             } else {
@@ -233,7 +278,7 @@ pub fn to_date(value: &EvaluationResult) -> Option<String> {
 /// Converts a value to a datetime representation if possible
 pub fn to_datetime(value: &EvaluationResult) -> Option<String> {
     match value {
-        EvaluationResult::DateTime(dt, _) => Some(dt.clone()),
+        EvaluationResult::DateTime(dt, _) => Some(dt.to_string()),
         EvaluationResult::Date(d, _) => {
             // Extend date to datetime
             Some(format!("{}T00:00:00", d))
@@ -259,6 +304,38 @@ pub fn to_datetime(value: &EvaluationResult) -> Option<String> {
     }
 }
 
+/// Converts components to a NaiveDateTime, filling missing components with
+/// the start of the period. Time values are placed on 1970-01-01.
+pub fn to_naive_datetime(components: &DateTimeComponents) -> Option<NaiveDateTime> {
+    let date = NaiveDate::from_ymd_opt(
+        components.year.unwrap_or(1970),
+        components.month.unwrap_or(1),
+        components.day.unwrap_or(1),
+    )?;
+    let time = NaiveTime::from_hms_milli_opt(
+        components.hour.unwrap_or(0),
+        components.minute.unwrap_or(0),
+        components.second.unwrap_or(0),
+        components.millisecond.unwrap_or(0),
+    )?;
+    Some(NaiveDateTime::new(date, time))
+}
+
+/// Converts components to a UTC NaiveDateTime like [`to_naive_datetime`],
+/// taking values without a timezone offset to be in UTC
+fn to_utc_datetime(components: &DateTimeComponents) -> Option<NaiveDateTime> {
+    let offset = components.timezone_offset.unwrap_or(0);
+    Some(to_naive_datetime(components)? - TimeDelta::minutes(offset as i64))
+}
+
+/// Compares the components of two parsed temporal values
+fn compare_components(
+    left: Option<&DateTimeComponents>,
+    right: Option<&DateTimeComponents>,
+) -> Option<Ordering> {
+    Some(to_utc_datetime(left?)?.cmp(&to_utc_datetime(right?)?))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,240 @@
+//! # Evaluation Debugging
+//!
+//! Records every step of an evaluation so that it can be stepped through.
+//!
+//! When an [`EvaluationDebugger`] is attached to the
+//! [`EvaluationContext`](crate::EvaluationContext), each AST node evaluated
+//! produces an [`EvaluationStep`] holding the node's source span, a short
+//! description of its input focus, its output (or error) and how long it took.
+//! Steps are recorded in the order evaluation of their nodes begins, and link
+//! to the step of the node that evaluated them, so the steps form the tree of
+//! the evaluation. A debugger created with
+//! [`EvaluationDebugger::with_max_steps`] stops recording once it holds that
+//! many steps.
+//!
+//! ```
+//! use helios_fhirpath::debug_eval::EvaluationDebugger;
+//! use helios_fhirpath::evaluator::EvaluationContext;
+//! use helios_fhirpath::evaluate_expression;
+//! use std::sync::Arc;
+//!
+//! let debugger = Arc::new(EvaluationDebugger::new());
+//! let mut context = EvaluationContext::new_empty_with_default_version();
+//! context.set_debugger(debugger.clone());
+//! evaluate_expression("(1 | 2).count()", &context).unwrap();
+//!
+//! let steps = debugger.steps();
+//! assert_eq!(steps[0].span, 0..15); // (1 | 2).count()
+//! assert_eq!(steps[1].parent, Some(0)); // (1 | 2)
+//! ```
+
+use crate::parser::{Expression, Span};
+use helios_fhirpath_support::{EvaluationError, EvaluationResult};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The evaluation of a single AST node
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvaluationStep {
+    /// Index of the step of the node that evaluated this one
+    pub parent: Option<usize>,
+    /// Number of steps enclosing this one
+    pub depth: usize,
+    /// Kind of the node, such as `Invocation` or `Equality`
+    pub node_type: &'static str,
+    /// Byte range of the node in the expression source
+    pub span: Span,
+    /// Description of the input the node was evaluated against, as given by
+    /// [`describe_value`]
+    pub focus: String,
+    /// The output of the node
+    pub result: Result<EvaluationResult, EvaluationError>,
+    /// Time taken to evaluate the node, including the nodes it evaluated
+    pub duration: Duration,
+}
+
+/// Records the steps of evaluations made with the contexts it is attached to
+///
+/// Steps are nested by the order evaluations begin and end, so a debugger
+/// should only be used by one evaluation at a time.
+///
+/// With a maximum number of steps, steps beyond the maximum are counted but
+/// not kept.
+#[derive(Debug, Default)]
+pub struct EvaluationDebugger {
+    state: Mutex<DebuggerState>,
+    max_steps: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+struct DebuggerState {
+    steps: Vec<EvaluationStep>,
+    /// Steps whose evaluation has begun but not ended, innermost last
+    open: Vec<usize>,
+    dropped: usize,
+}
+
+impl EvaluationDebugger {
+    /// Creates a debugger with no steps recorded
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a debugger that keeps at most `max_steps` steps
+    pub fn with_max_steps(max_steps: usize) -> Self {
+        Self {
+            max_steps: Some(max_steps),
+            ..Self::default()
+        }
+    }
+
+    /// Returns a copy of the recorded steps
+    pub fn steps(&self) -> Vec<EvaluationStep> {
+        self.lock().steps.clone()
+    }
+
+    /// Removes and returns the recorded steps, resetting the count of dropped
+    /// steps
+    pub fn take(&self) -> Vec<EvaluationStep> {
+        std::mem::take(&mut *self.lock()).steps
+    }
+
+    /// Removes the recorded steps
+    pub fn clear(&self) {
+        self.take();
+    }
+
+    /// Number of steps that were not kept because the debugger was full
+    pub fn dropped(&self) -> usize {
+        self.lock().dropped
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, DebuggerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records the start of the evaluation of `expr` against `focus`,
+    /// returning its step index, or `None` when the debugger is full
+    pub(crate) fn enter(
+        &self,
+        expr: &Expression,
+        focus: Option<&EvaluationResult>,
+    ) -> Option<usize> {
+        let mut state = self.lock();
+        let index = state.steps.len();
+        if self.max_steps.is_some_and(|max| index >= max) {
+            state.dropped += 1;
+            return None;
+        }
+        let step = EvaluationStep {
+            parent: state.open.last().copied(),
+            depth: state.open.len(),
+            node_type: node_type(expr),
+            span: expr.span(),
+            focus: focus.map_or_else(|| describe_value(&EvaluationResult::Empty), describe_value),
+            result: Ok(EvaluationResult::Empty),
+            duration: Duration::ZERO,
+        };
+        state.steps.push(step);
+        state.open.push(index);
+        Some(index)
+    }
+
+    /// Records the end of the evaluation begun by `enter`
+    pub(crate) fn exit(
+        &self,
+        index: usize,
+        result: &Result<EvaluationResult, EvaluationError>,
+        duration: Duration,
+    ) {
+        let mut state = self.lock();
+        state.open.retain(|open| *open != index);
+        // The steps may have been taken while the evaluation was running
+        if let Some(step) = state.steps.get_mut(index) {
+            step.result = result.clone();
+            step.duration = duration;
+        }
+    }
+}
+
+/// Name of the kind of AST node
+fn node_type(expr: &Expression) -> &'static str {
+    match expr {
+        Expression::Term(..) => "Term",
+        Expression::Invocation(..) => "Invocation",
+        Expression::Indexer(..) => "Indexer",
+        Expression::Polarity(..) => "Polarity",
+        Expression::Multiplicative(..) => "Multiplicative",
+        Expression::Additive(..) => "Additive",
+        Expression::Type(..) => "Type",
+        Expression::Union(..) => "Union",
+        Expression::Inequality(..) => "Inequality",
+        Expression::Equality(..) => "Equality",
+        Expression::Membership(..) => "Membership",
+        Expression::And(..) => "And",
+        Expression::Or(..) => "Or",
+        Expression::Implies(..) => "Implies",
+        Expression::Lambda(..) => "Lambda",
+    }
+}
+
+impl EvaluationStep {
+    /// The source text of the node, given the source of the whole expression
+    pub fn source_text<'a>(&self, source: &'a str) -> &'a str {
+        source.get(self.span.clone()).unwrap_or("")
+    }
+}
+
+/// Generate a text listing of evaluation steps, one line per step
+///
+/// Steps are indented by depth and show the source text of the node, its
+/// focus, its result and the time taken, e.g.
+/// `  name.given : Patient -> ['Peter', 'James'] (12µs)`.
+pub fn generate_evaluation_debug(steps: &[EvaluationStep], source: &str) -> String {
+    let mut output = String::new();
+    for step in steps {
+        let result = match &step.result {
+            Ok(value) => describe_value(value),
+            Err(error) => format!("error: {}", error),
+        };
+        output.push_str(&format!(
+            "{}{} : {} -> {} ({:?})\n",
+            "  ".repeat(step.depth),
+            step.source_text(source),
+            step.focus,
+            result,
+            step.duration
+        ));
+    }
+    output
+}
+
+/// Number of items of a collection shown by `describe_value`
+const DESCRIBED_ITEMS: usize = 10;
+
+/// A short description of a value: resources and other objects are shown by
+/// type rather than content, and only the first items of a large collection
+/// are shown
+pub fn describe_value(value: &EvaluationResult) -> String {
+    match value {
+        EvaluationResult::Empty => "{ }".to_string(),
+        EvaluationResult::String(s, _) => format!("'{}'", s),
+        EvaluationResult::Object { map, type_info } => match (type_info, map.get("resourceType")) {
+            (Some(type_info), _) => type_info.name.clone(),
+            (None, Some(EvaluationResult::String(resource_type, _))) => resource_type.clone(),
+            _ => "Object".to_string(),
+        },
+        EvaluationResult::Collection { items, .. } => {
+            let mut described: Vec<_> = items
+                .iter()
+                .take(DESCRIBED_ITEMS)
+                .map(describe_value)
+                .collect();
+            if items.len() > DESCRIBED_ITEMS {
+                described.push(format!("... {} more", items.len() - DESCRIBED_ITEMS));
+            }
+            format!("[{}]", described.join(", "))
+        }
+        other => other.to_string_value(),
+    }
+}
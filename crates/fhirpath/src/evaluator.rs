@@ -1,12 +1,30 @@
+use crate::debug_eval::EvaluationDebugger;
+use crate::function_registry::{ArgumentEvaluation, CustomFunction, FunctionRegistry};
+use crate::json_resource::JsonResource;
+use crate::limits::EvaluationLimits;
 use crate::parser::{Expression, Invocation, Literal, Term, TypeSpecifier};
+use crate::profile_registry::ProfileRegistry;
+use crate::reference_resolver::ReferenceResolver;
+use crate::terminology::TerminologyProvider;
+use crate::trace_sink::{BufferedTraceSink, TraceSink};
 use chrono::{Local, Timelike};
 use helios_fhir::{FhirResource, FhirVersion};
-use helios_fhirpath_support::{EvaluationError, EvaluationResult, IntoEvaluationResult};
+use helios_fhirpath_support::{
+    EvaluationError, EvaluationResult, FhirPathFields, IntoEvaluationResult,
+};
 use regex::Regex;
 use rust_decimal::Decimal;
 use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
-use std::cell::RefCell;
+use std::cell::{Cell, OnceCell, RefCell};
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// The most trace outputs a context buffers when no trace sink is set
+///
+/// Further traces are counted by `EvaluationContext::dropped_trace_outputs`.
+/// Set a sink to keep every trace.
+pub const MAX_BUFFERED_TRACES: usize = 10_000;
 
 /// Evaluation context for FHIRPath expressions
 ///
@@ -50,6 +68,11 @@ pub struct EvaluationContext {
 
     /// The 'this' context for direct evaluation (primarily used in tests)
     /// When set, this overrides the current item passed to the evaluate function
+    /// When None, the first resource is the default focus. The first resource is
+    /// no longer stored here, so read the default focus with `root_item`
+    #[deprecated(
+        note = "set the override with `set_this` and read the default focus with `root_item`"
+    )]
     pub this: Option<EvaluationResult>,
 
     /// Flag to enable strict mode evaluation
@@ -64,12 +87,65 @@ pub struct EvaluationContext {
     /// Used to pass the current aggregation result between iterations
     pub current_aggregate_total: Option<EvaluationResult>,
 
-    /// Collects trace outputs during expression evaluation
-    /// Each tuple contains (trace_name, traced_value)
-    /// Uses RefCell for interior mutability to allow collection during evaluation
-    pub trace_outputs: RefCell<Vec<(String, EvaluationResult)>>,
+    /// Receives the output of trace() calls
+    /// When None, traces are buffered and returned by get_trace_outputs()
+    pub trace_sink: Option<Arc<dyn TraceSink>>,
+
+    /// Buffers trace outputs when no trace sink is set, up to
+    /// `MAX_BUFFERED_TRACES` entries
+    /// Shared with the contexts aggregate() creates, so their traces are kept
+    pub(crate) trace_buffer: Arc<BufferedTraceSink>,
+
+    /// Resolver used by the resolve() function
+    /// When None, contained resources, Bundle entries and the context resources are searched
+    pub reference_resolver: Option<Arc<dyn ReferenceResolver>>,
+
+    /// Provider used by memberOf(), subsumes(), subsumedBy() and %terminologies
+    /// When None, these functions raise an error
+    pub terminology_provider: Option<Arc<dyn TerminologyProvider>>,
+
+    /// Profiles used by conformsTo()
+    /// When None, only the base resource definitions are known
+    pub profile_registry: Option<Arc<ProfileRegistry>>,
+
+    /// Functions added by the application, consulted for names that are not
+    /// built-in functions
+    pub function_registry: Option<Arc<dyn FunctionRegistry>>,
+
+    /// Limits on the depth, collection sizes and duration of evaluation
+    /// No limits are set by default
+    pub limits: EvaluationLimits,
+
+    /// Records each step of evaluation when set
+    pub debugger: Option<Arc<EvaluationDebugger>>,
+
+    /// Flag to record the location of each element read from the root resource
+    /// When enabled, result items report their path through `EvaluationResult::location`
+    pub track_locations: bool,
+
+    /// How deeply the expression currently being evaluated is nested
+    pub(crate) evaluation_depth: Cell<usize>,
+
+    /// Variables defined by defineVariable(), innermost last
+    /// Uses RefCell so definitions can be made while evaluating; each
+    /// expression removes the definitions made within it when it completes
+    pub(crate) defined_variables: RefCell<Vec<(String, EvaluationResult)>>,
+
+    /// The first resource converted to an EvaluationResult, filled on first use
+    /// Member access on the root resource reads typed fields directly, so the
+    /// whole resource is only converted when an expression needs all of it
+    root_result: OnceCell<EvaluationResult>,
+
+    /// Members of the first resource read without converting the whole resource,
+    /// by name, so each field is converted at most once
+    root_members: RefCell<HashMap<String, EvaluationResult>>,
+
+    /// A resource held as JSON, used as the root when there are no typed resources
+    json_resource: Option<JsonResource>,
 }
 
+// The deprecated `this` field still holds the override set with `set_this`
+#[allow(deprecated)]
 impl EvaluationContext {
     /// Creates a new evaluation context with the given FHIR resources
     ///
@@ -114,18 +190,28 @@ impl EvaluationContext {
             }
         });
 
-        // Set 'this' to the first resource if available
-        let this = resources.first().map(convert_resource_to_result);
-
         Self {
             resources,
             fhir_version,
             variables: HashMap::new(),
-            this,
+            this: None,                     // The first resource is converted on first use
             is_strict_mode: false,          // Default to non-strict mode
             check_ordered_functions: false, // Default to false
             current_aggregate_total: None,  // Initialize aggregate total
-            trace_outputs: RefCell::new(Vec::new()), // Initialize trace outputs
+            trace_sink: None,               // Buffer trace outputs
+            trace_buffer: Arc::new(BufferedTraceSink::with_max_entries(MAX_BUFFERED_TRACES)),
+            reference_resolver: None,   // Use the default resolution
+            terminology_provider: None, // No terminology services by default
+            profile_registry: None,     // No profiles by default
+            function_registry: None,    // Only the built-in functions
+            limits: EvaluationLimits::default(), // Unlimited evaluation
+            debugger: None,             // No step recording
+            track_locations: false,     // Locations are not recorded
+            evaluation_depth: Cell::new(0),
+            defined_variables: RefCell::new(Vec::new()), // No defineVariable() scopes yet
+            root_result: OnceCell::new(),
+            root_members: RefCell::new(HashMap::new()),
+            json_resource: None,
         }
     }
 
@@ -143,18 +229,28 @@ impl EvaluationContext {
     ///
     /// A new `EvaluationContext` instance with the provided resources and version
     pub fn new_with_version(resources: Vec<FhirResource>, fhir_version: FhirVersion) -> Self {
-        // Set 'this' to the first resource if available
-        let this = resources.first().map(convert_resource_to_result);
-
         Self {
             resources,
             fhir_version,
             variables: HashMap::new(),
-            this,
+            this: None,                     // The first resource is converted on first use
             is_strict_mode: false,          // Default to non-strict mode
             check_ordered_functions: false, // Default to false
             current_aggregate_total: None,  // Initialize aggregate total
-            trace_outputs: RefCell::new(Vec::new()), // Initialize trace outputs
+            trace_sink: None,               // Buffer trace outputs
+            trace_buffer: Arc::new(BufferedTraceSink::with_max_entries(MAX_BUFFERED_TRACES)),
+            reference_resolver: None,   // Use the default resolution
+            terminology_provider: None, // No terminology services by default
+            profile_registry: None,     // No profiles by default
+            function_registry: None,    // Only the built-in functions
+            limits: EvaluationLimits::default(), // Unlimited evaluation
+            debugger: None,             // No step recording
+            track_locations: false,     // Locations are not recorded
+            evaluation_depth: Cell::new(0),
+            defined_variables: RefCell::new(Vec::new()), // No defineVariable() scopes yet
+            root_result: OnceCell::new(),
+            root_members: RefCell::new(HashMap::new()),
+            json_resource: None,
         }
     }
 
@@ -180,7 +276,20 @@ impl EvaluationContext {
             is_strict_mode: false,          // Default to non-strict mode
             check_ordered_functions: false, // Default to false
             current_aggregate_total: None,  // Initialize aggregate total
-            trace_outputs: RefCell::new(Vec::new()), // Initialize trace outputs
+            trace_sink: None,               // Buffer trace outputs
+            trace_buffer: Arc::new(BufferedTraceSink::with_max_entries(MAX_BUFFERED_TRACES)),
+            reference_resolver: None,   // Use the default resolution
+            terminology_provider: None, // No terminology services by default
+            profile_registry: None,     // No profiles by default
+            function_registry: None,    // Only the built-in functions
+            limits: EvaluationLimits::default(), // Unlimited evaluation
+            debugger: None,             // No step recording
+            track_locations: false,     // Locations are not recorded
+            evaluation_depth: Cell::new(0),
+            defined_variables: RefCell::new(Vec::new()), // No defineVariable() scopes yet
+            root_result: OnceCell::new(),
+            root_members: RefCell::new(HashMap::new()),
+            json_resource: None,
         }
     }
 
@@ -219,19 +328,110 @@ impl EvaluationContext {
         }
     }
 
+    /// Creates an evaluation context over a resource held as JSON
+    ///
+    /// The resource is navigated directly, without deserializing it into the
+    /// typed FHIR structures, using the type metadata of `fhir_version`. This
+    /// accepts resources that typed deserialization would reject, such as
+    /// resources with elements of another FHIR version, and gives the same
+    /// results as a typed context for valid resources.
+    ///
+    /// # Arguments
+    ///
+    /// * `resource` - The FHIR resource as JSON
+    /// * `fhir_version` - The FHIR version whose type metadata describes the resource
+    ///
+    /// # Returns
+    ///
+    /// A new `EvaluationContext` whose root is the JSON resource
+    pub fn from_json(resource: serde_json::Value, fhir_version: FhirVersion) -> Self {
+        let mut context = Self::new_empty(fhir_version);
+        context.json_resource = Some(JsonResource::new(resource, fhir_version));
+        context
+    }
+
     /// Clears all collected trace outputs
     ///
     /// This should be called at the start of each new evaluation to ensure
     /// trace outputs from previous evaluations don't persist.
     pub fn clear_trace_outputs(&self) {
-        self.trace_outputs.borrow_mut().clear();
+        self.trace_buffer.clear();
     }
 
     /// Gets the collected trace outputs
     ///
-    /// Returns a clone of all trace outputs collected during evaluation
+    /// Returns a clone of all trace outputs collected during evaluation.
+    /// Traces are only collected here when no trace sink is set, and at most
+    /// `MAX_BUFFERED_TRACES` are kept; see `dropped_trace_outputs`.
     pub fn get_trace_outputs(&self) -> Vec<(String, EvaluationResult)> {
-        self.trace_outputs.borrow().clone()
+        self.trace_buffer.outputs()
+    }
+
+    /// Number of trace outputs not kept because the buffer was full
+    ///
+    /// Reset by `clear_trace_outputs`.
+    pub fn dropped_trace_outputs(&self) -> usize {
+        self.trace_buffer.dropped()
+    }
+
+    /// Sets the sink that receives the output of trace() calls
+    ///
+    /// Traces go to the sink instead of being buffered in the context, so
+    /// get_trace_outputs() no longer returns them.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - The sink to send traces to
+    pub fn set_trace_sink(&mut self, sink: Arc<dyn TraceSink>) {
+        self.trace_sink = Some(sink);
+    }
+
+    /// Sets the debugger that records each step of evaluation
+    pub fn set_debugger(&mut self, debugger: Arc<EvaluationDebugger>) {
+        self.debugger = Some(debugger);
+    }
+
+    /// Enables or disables tracking the location of result items
+    ///
+    /// When enabled, every element of the root resource records the path it
+    /// is found at, and values read from the resource report it through
+    /// `EvaluationResult::location`. The whole resource is converted up front
+    /// rather than field by field. Changing the setting discards the converted
+    /// resource, so the next evaluation converts it again in the new mode.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use helios_fhirpath::{EvaluationContext, evaluate_expression};
+    /// use helios_fhir::FhirVersion;
+    /// use serde_json::json;
+    ///
+    /// let mut context = EvaluationContext::from_json(
+    ///     json!({
+    ///         "resourceType": "Patient",
+    ///         "name": [{ "given": ["Peter"] }, { "given": ["Jim"] }]
+    ///     }),
+    ///     FhirVersion::R4,
+    /// );
+    /// context.set_track_locations(true);
+    ///
+    /// let result = evaluate_expression("Patient.name.given.last()", &context).unwrap();
+    /// assert_eq!(result.location(), Some("Patient.name[1].given[0]"));
+    /// ```
+    pub fn set_track_locations(&mut self, track: bool) {
+        if self.track_locations != track {
+            self.root_result = OnceCell::new();
+            self.root_members.get_mut().clear();
+        }
+        self.track_locations = track;
+    }
+
+    /// Sends a trace to the trace sink, or buffers it when there is none
+    pub(crate) fn trace(&self, name: &str, value: &EvaluationResult) {
+        match &self.trace_sink {
+            Some(sink) => sink.trace(name, value),
+            None => self.trace_buffer.trace(name, value),
+        }
     }
 
     /// Sets the strict mode for evaluation
@@ -273,6 +473,154 @@ impl EvaluationContext {
         self.this = Some(value);
     }
 
+    /// Returns the default focus of an evaluation
+    ///
+    /// This is `this` when set, otherwise the first resource in the context.
+    /// The resource is converted to an `EvaluationResult` the first time it is
+    /// needed and the conversion is reused afterwards.
+    pub fn root_item(&self) -> Option<&EvaluationResult> {
+        self.this.as_ref().or_else(|| self.root_resource_result())
+    }
+
+    /// The resource the evaluation starts from: the first typed resource, or
+    /// the JSON resource of a context created with `from_json`
+    fn root_resource(&self) -> Option<&dyn FhirPathFields> {
+        match self.resources.first() {
+            Some(resource) => Some(resource),
+            None => self
+                .json_resource
+                .as_ref()
+                .map(|resource| resource as &dyn FhirPathFields),
+        }
+    }
+
+    /// The root resource converted to an `EvaluationResult`, converted once
+    pub(crate) fn root_resource_result(&self) -> Option<&EvaluationResult> {
+        let resource = self.root_resource()?;
+        Some(self.root_result.get_or_init(|| {
+            let result = resource.to_evaluation_result();
+            if self.track_locations {
+                crate::locations::locate_resource(result)
+            } else {
+                result
+            }
+        }))
+    }
+
+    /// Reads a member of the root resource without converting the whole resource
+    ///
+    /// Only the named field of the typed resource is converted, and the result is
+    /// kept for later reads of the same member. Navigation below the member works
+    /// on the converted field. Returns None when the member has to be resolved on
+    /// the converted resource instead: when `this` overrides the root, when
+    /// locations are recorded, when the resource has already been converted, for
+    /// names that are not fields (e.g. `valueQuantity`), and for absent fields
+    /// that choice-type lookup or strict mode treat specially.
+    fn lazy_root_member(&self, name: &str) -> Option<EvaluationResult> {
+        if self.this.is_some() || self.track_locations || self.root_result.get().is_some() {
+            return None;
+        }
+        if let Some(result) = self.root_members.borrow().get(name) {
+            return Some(result.clone());
+        }
+        let result = match self.root_resource()?.fhirpath_field(name)? {
+            EvaluationResult::Empty
+                if self.is_strict_mode || crate::polymorphic_access::is_choice_element(name) =>
+            {
+                return None;
+            }
+            result => result,
+        };
+        self.root_members
+            .borrow_mut()
+            .insert(name.to_string(), result.clone());
+        Some(result)
+    }
+
+    /// Checks whether `name` is the resource type of the default focus
+    fn is_root_type(&self, name: &str) -> bool {
+        let resource_type = match &self.this {
+            Some(EvaluationResult::Object { map, .. }) => map.get("resourceType").cloned(),
+            Some(_) => None,
+            None => self
+                .root_resource()
+                .and_then(|resource| resource.fhirpath_field("resourceType")),
+        };
+        matches!(resource_type, Some(EvaluationResult::String(ctx_type, _)) if name.eq_ignore_ascii_case(&ctx_type))
+    }
+
+    /// Sets the resolver used by the resolve() function
+    ///
+    /// The resolver looks up the resources that references point to. Without
+    /// a resolver, resolve() only finds contained resources, entries of the
+    /// surrounding Bundle and the resources in this context.
+    ///
+    /// # Arguments
+    ///
+    /// * `resolver` - The resolver to use for reference resolution
+    pub fn set_reference_resolver(&mut self, resolver: Arc<dyn ReferenceResolver>) {
+        self.reference_resolver = Some(resolver);
+    }
+
+    /// Sets the terminology provider
+    ///
+    /// The provider answers the terminology functions memberOf(), subsumes()
+    /// and subsumedBy(), and the functions of the %terminologies object.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The provider to use for terminology operations
+    pub fn set_terminology_provider(&mut self, provider: Arc<dyn TerminologyProvider>) {
+        self.terminology_provider = Some(provider);
+    }
+
+    /// Sets the profile registry
+    ///
+    /// The registry provides the StructureDefinitions that conformsTo()
+    /// checks values against.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The registry to look up profiles in
+    pub fn set_profile_registry(&mut self, registry: Arc<ProfileRegistry>) {
+        self.profile_registry = Some(registry);
+    }
+
+    /// Sets the registry of custom functions
+    ///
+    /// Functions in the registry can be called like built-in functions.
+    /// Built-in functions take precedence over registered functions of the
+    /// same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The registry to look up custom functions in
+    pub fn set_function_registry(&mut self, registry: Arc<dyn FunctionRegistry>) {
+        self.function_registry = Some(registry);
+    }
+
+    /// Sets the limits on evaluation
+    ///
+    /// Evaluation fails with `EvaluationError::LimitExceeded` when it nests
+    /// too deeply, builds too large a collection, runs past the deadline or
+    /// is cancelled.
+    ///
+    /// # Arguments
+    ///
+    /// * `limits` - The limits to apply to evaluations in this context
+    pub fn set_limits(&mut self, limits: EvaluationLimits) {
+        self.limits = limits;
+    }
+
+    /// Returns the registered function called `name`, unless `name` is a
+    /// built-in function
+    fn custom_function(&self, name: &str) -> Option<&CustomFunction> {
+        if BUILTIN_FUNCTIONS.contains(&name) {
+            return None;
+        }
+        self.function_registry.as_ref()?.function(name)
+    }
+
     /// Adds a resource to the context
     ///
     /// Appends a FHIR resource to the list of resources available in the context.
@@ -327,6 +675,54 @@ impl EvaluationContext {
         self.variables.get(name)
     }
 
+    /// Looks up a variable by name as seen from the current scope
+    ///
+    /// Variables defined by defineVariable() are found first, followed by
+    /// the variables set on the context.
+    pub(crate) fn lookup_variable(&self, name: &str) -> Option<EvaluationResult> {
+        self.defined_variables
+            .borrow()
+            .iter()
+            .rev()
+            .find(|(defined, _)| defined == name)
+            .map(|(_, value)| value.clone())
+            .or_else(|| self.variables.get(name).cloned())
+    }
+
+    /// Defines a variable for the rest of the current scope (defineVariable())
+    ///
+    /// Fails if a variable with the same name is already in scope, or if the
+    /// name is reserved for an environment variable such as %context.
+    pub(crate) fn define_variable(
+        &self,
+        name: &str,
+        value: EvaluationResult,
+    ) -> Result<(), EvaluationError> {
+        let reserved = matches!(
+            name,
+            "context"
+                | "resource"
+                | "rootResource"
+                | "ucum"
+                | "sct"
+                | "loinc"
+                | "terminologies"
+                | "factory"
+                | "server"
+        ) || name.starts_with("vs-")
+            || name.starts_with("ext-");
+        if reserved || self.lookup_variable(name).is_some() {
+            return Err(EvaluationError::SemanticError(format!(
+                "Variable '%{}' is already defined",
+                name
+            )));
+        }
+        self.defined_variables
+            .borrow_mut()
+            .push((name.to_string(), value));
+        Ok(())
+    }
+
     /// Gets a variable from the context as an EvaluationResult
     ///
     /// Retrieves a variable by name, returning Empty if the variable doesn't exist.
@@ -477,6 +873,51 @@ pub fn evaluate(
     expr: &Expression,
     context: &EvaluationContext,
     current_item: Option<&EvaluationResult>,
+) -> Result<EvaluationResult, EvaluationError> {
+    let depth = context.evaluation_depth.get() + 1;
+    context.limits.check_depth(depth)?;
+    context.limits.check_running()?;
+    context.evaluation_depth.set(depth);
+
+    // Variables defined by defineVariable() within the expression go out of scope with it
+    let scope = context.defined_variables.borrow().len();
+    let result = evaluate_in_scope(expr, context, current_item);
+    context.defined_variables.borrow_mut().truncate(scope);
+    context.evaluation_depth.set(depth - 1);
+
+    if let Ok(EvaluationResult::Collection { items, .. }) = &result {
+        context.limits.check_collection_size(items.len())?;
+    }
+    result
+}
+
+/// Evaluates an expression without closing its defineVariable() scope
+///
+/// Used for the left side of invocations and indexers, so that variables
+/// defined there remain visible to the rest of the invocation chain.
+fn evaluate_in_scope(
+    expr: &Expression,
+    context: &EvaluationContext,
+    current_item: Option<&EvaluationResult>,
+) -> Result<EvaluationResult, EvaluationError> {
+    let Some(debugger) = &context.debugger else {
+        return evaluate_node(expr, context, current_item);
+    };
+
+    let Some(step) = debugger.enter(expr, current_item.or_else(|| context.root_item())) else {
+        return evaluate_node(expr, context, current_item);
+    };
+    let start = Instant::now();
+    let result = evaluate_node(expr, context, current_item);
+    debugger.exit(step, &result, start.elapsed());
+    result
+}
+
+/// Evaluates a single AST node
+fn evaluate_node(
+    expr: &Expression,
+    context: &EvaluationContext,
+    current_item: Option<&EvaluationResult>,
 ) -> Result<EvaluationResult, EvaluationError> {
     // FHIRPath Spec Section 3: Path Selection
     // "When resolving an identifier that is also the root of a FHIRPath expression,
@@ -486,28 +927,16 @@ pub fn evaluate(
     // This applies when current_item is None (not in an iteration) and the expression
     // starts with a simple member identifier.
     if current_item.is_none() {
-        if let Expression::Term(Term::Invocation(Invocation::Member(initial_name))) = expr {
-            let global_context_item = if let Some(this_item) = &context.this {
-                this_item.clone()
-            } else if !context.resources.is_empty() {
-                convert_resource_to_result(&context.resources[0])
-            } else {
-                EvaluationResult::Empty
-            };
-
-            if let EvaluationResult::Object {
-                map: obj_map,
-                type_info: _,
-            } = &global_context_item
-            {
-                if let Some(EvaluationResult::String(ctx_type, _)) = obj_map.get("resourceType") {
-                    // The parser ensures initial_name is cleaned of backticks.
-                    if initial_name.eq_ignore_ascii_case(ctx_type) {
-                        // The initial identifier matches the context type.
-                        // The expression resolves to the context item itself.
-                        return Ok(global_context_item);
-                    }
-                }
+        if let Expression::Term(Term::Invocation(Invocation::Member(initial_name, _), _), _) = expr
+        {
+            // The parser ensures initial_name is cleaned of backticks.
+            if context.is_root_type(initial_name) {
+                // The initial identifier matches the context type.
+                // The expression resolves to the context item itself.
+                return Ok(context
+                    .root_item()
+                    .cloned()
+                    .unwrap_or(EvaluationResult::Empty));
             }
             // If no match, or context is not an Object with resourceType,
             // evaluation proceeds normally (initial_name treated as member access on context).
@@ -515,10 +944,10 @@ pub fn evaluate(
     }
 
     let result = match expr {
-        Expression::Term(term) => evaluate_term(term, context, current_item),
-        Expression::Invocation(left_expr, invocation) => {
+        Expression::Term(term, _) => evaluate_term(term, context, current_item),
+        Expression::Invocation(left_expr, invocation, _) => {
             // Check for special handling of the 'extension' function
-            if let Invocation::Function(func_name, args_exprs) = invocation {
+            if let Invocation::Function(func_name, args_exprs, _) = invocation {
                 if func_name == "extension" {
                     let evaluated_args = args_exprs
                         .iter()
@@ -540,24 +969,26 @@ pub fn evaluate(
 
                     // Extract field name and parent object based on expression structure
                     match left_expr.as_ref() {
-                        Expression::Term(Term::Invocation(Invocation::Member(
-                            field_name_from_term,
-                        ))) => {
+                        Expression::Term(
+                            Term::Invocation(Invocation::Member(field_name_from_term, _), _),
+                            _,
+                        ) => {
                             // Scenario 1: `field.extension()`
                             field_name = Some(field_name_from_term.to_string());
 
                             // Find the parent object
                             if let Some(EvaluationResult::Object { map, .. }) = current_item {
                                 parent_obj = Some(map.clone());
-                            } else if let Some(EvaluationResult::Object { ref map, .. }) =
-                                context.this
+                            } else if let Some(EvaluationResult::Object { map, .. }) =
+                                context.root_item()
                             {
                                 parent_obj = Some(map.clone());
                             }
                         }
                         Expression::Invocation(
                             parent_expr_of_field,
-                            Invocation::Member(field_name_from_invocation),
+                            Invocation::Member(field_name_from_invocation, _),
+                            _,
                         ) => {
                             // Scenario 2: `object.field.extension()`
                             field_name = Some(field_name_from_invocation.to_string());
@@ -613,60 +1044,88 @@ pub fn evaluate(
                     );
                 }
             }
+            // %terminologies.fn(...) calls go to the terminology provider
+            if let Some((func_name, args_exprs)) = terminologies_call(left_expr, invocation) {
+                let evaluated_args = args_exprs
+                    .iter()
+                    .map(|arg_expr| evaluate(arg_expr, context, current_item))
+                    .collect::<Result<Vec<_>, _>>()?;
+                return crate::terminology::terminologies_function(
+                    func_name,
+                    &evaluated_args,
+                    context,
+                );
+            }
+            // `Type.member` on the root resource reads the member from the typed
+            // resource instead of converting the whole resource first
+            if let (
+                None,
+                Expression::Term(Term::Invocation(Invocation::Member(type_name, _), _), _),
+                Invocation::Member(name, _),
+            ) = (current_item, left_expr.as_ref(), invocation)
+            {
+                let lazy_member = match context.is_root_type(type_name) {
+                    true => context.lazy_root_member(name),
+                    false => None,
+                };
+                if let Some(result) = lazy_member {
+                    return Ok(result);
+                }
+            }
             // Default: evaluate left, then invoke on result
-            let left_result = evaluate(left_expr, context, current_item)?;
+            let left_result = evaluate_in_scope(left_expr, context, current_item)?;
             // Pass current_item to evaluate_invocation for argument evaluation context
             evaluate_invocation(&left_result, invocation, context, current_item)
         }
-        Expression::Indexer(left, index) => {
-            let left_result = evaluate(left, context, current_item)?;
+        Expression::Indexer(left, index, _) => {
+            let left_result = evaluate_in_scope(left, context, current_item)?;
             // Index expression doesn't depend on $this, evaluate normally
             let index_result = evaluate(index, context, None)?;
             evaluate_indexer(&left_result, &index_result, context) // Pass context
         }
-        Expression::Polarity(op, expr) => {
+        Expression::Polarity(op, expr, _) => {
             let result = evaluate(expr, context, current_item)?;
             apply_polarity(*op, &result)
         }
-        Expression::Multiplicative(left, op, right) => {
+        Expression::Multiplicative(left, op, right, _) => {
             let left_result = evaluate(left, context, current_item)?;
             let right_result = evaluate(right, context, current_item)?;
             apply_multiplicative(&left_result, op, &right_result)
         }
-        Expression::Additive(left, op, right) => {
+        Expression::Additive(left, op, right, _) => {
             let left_result = evaluate(left, context, current_item)?;
             let right_result = evaluate(right, context, current_item)?;
             apply_additive(&left_result, op, &right_result)
         }
-        Expression::Type(left, op, type_spec) => {
+        Expression::Type(left, op, type_spec, _) => {
             let result = evaluate(left, context, current_item)?;
             apply_type_operation(&result, op, type_spec, context) // Pass context
         }
-        Expression::Union(left, right) => {
+        Expression::Union(left, right, _) => {
             let left_result = evaluate(left, context, current_item)?;
             let right_result = evaluate(right, context, current_item)?;
             // Union itself doesn't typically error, just returns combined set
             Ok(union_collections(&left_result, &right_result))
         }
-        Expression::Inequality(left, op, right) => {
+        Expression::Inequality(left, op, right, _) => {
             let left_result = evaluate(left, context, current_item)?;
             let right_result = evaluate(right, context, current_item)?;
             // compare_inequality now returns Result, so just call it directly
             compare_inequality(&left_result, op, &right_result)
         }
-        Expression::Equality(left, op, right) => {
+        Expression::Equality(left, op, right, _) => {
             let left_result = evaluate(left, context, current_item)?;
             let right_result = evaluate(right, context, current_item)?;
             // compare_equality now returns Result, so just call it directly
             compare_equality(&left_result, op, &right_result, context)
         }
-        Expression::Membership(left, op, right) => {
+        Expression::Membership(left, op, right, _) => {
             let left_result = evaluate(left, context, current_item)?;
             let right_result = evaluate(right, context, current_item)?;
             // Membership returns Empty on empty operand or errors on multi-item left
             check_membership(&left_result, op, &right_result, context)
         }
-        Expression::And(left, right) => {
+        Expression::And(left, right, _) => {
             // Evaluate operands first
             let left_eval = evaluate(left, context, current_item)?;
             let right_eval = evaluate(right, context, current_item)?;
@@ -737,7 +1196,7 @@ pub fn evaluate(
                 ))),
             }
         }
-        Expression::Or(left, op, right) => {
+        Expression::Or(left, op, right, _) => {
             // Evaluate left, handle potential error
             let left_eval = evaluate(left, context, current_item)?;
             let left_bool = left_eval.to_boolean_for_logic()?; // Propagate error
@@ -834,7 +1293,7 @@ pub fn evaluate(
                 }
             }
         }
-        Expression::Implies(left, right) => {
+        Expression::Implies(left, right, _) => {
             // Evaluate left, handle potential error
             let left_eval = evaluate(left, context, current_item)?;
             let left_bool = left_eval.to_boolean_for_logic()?; // Propagate error
@@ -893,7 +1352,7 @@ pub fn evaluate(
                 }
             }
         }
-        Expression::Lambda(_, _) => {
+        Expression::Lambda(_, _, _) => {
             // Lambda expressions are not directly evaluated here.
             // They are used in function calls
             // Return Ok(Empty) as it's not an error, just not evaluated yet.
@@ -1012,44 +1471,30 @@ fn evaluate_term(
     current_item: Option<&EvaluationResult>,
 ) -> Result<EvaluationResult, EvaluationError> {
     let result = match term {
-        Term::Invocation(invocation) => {
+        Term::Invocation(invocation, _) => {
             // Explicitly handle $this first and return
-            if *invocation == Invocation::This {
+            if matches!(invocation, Invocation::This(_)) {
                 return Ok(if let Some(item) = current_item.cloned() {
                     item // Return the item if Some
-                } else if let Some(this_context) = &context.this {
-                    // Use the explicitly set 'this' context if available (for testing)
-                    this_context.clone()
+                } else if let Some(root) = context.root_item() {
+                    // Use the explicitly set 'this' context, or the first resource
+                    root.clone()
                 } else {
-                    // Return the default context if None
-                    if context.resources.is_empty() {
-                        EvaluationResult::Empty
-                    } else if context.resources.len() == 1 {
-                        convert_resource_to_result(&context.resources[0])
-                    } else {
-                        EvaluationResult::Collection {
-                            items: context
-                                .resources
-                                .iter()
-                                .map(convert_resource_to_result)
-                                .collect(),
-                            has_undefined_order: false, // Resources in context are typically ordered
-                            type_info: None,
-                        }
-                    }
+                    EvaluationResult::Empty
                 }); // Close Ok() here
             }
 
             // Handle variables (%var, %context) next and return
-            if let Invocation::Member(name) = invocation {
+            if let Invocation::Member(name, _) = invocation {
                 if let Some(var_name) = name.strip_prefix('%') {
                     if var_name == "context" {
                         // Return %context value
                         // Correctly wrap the entire conditional result in Ok()
-                        return Ok(if context.resources.is_empty() {
-                            EvaluationResult::Empty
-                        } else if context.resources.len() == 1 {
-                            convert_resource_to_result(&context.resources[0])
+                        return Ok(if context.resources.len() <= 1 {
+                            context
+                                .root_resource_result()
+                                .cloned()
+                                .unwrap_or(EvaluationResult::Empty)
                         } else {
                             EvaluationResult::Collection {
                                 items: context
@@ -1063,8 +1508,8 @@ fn evaluate_term(
                         });
                     } else {
                         // Return other variable value or error if undefined
-                        return match context.get_variable(var_name) {
-                            Some(value) => Ok(value.clone()),
+                        return match context.lookup_variable(var_name) {
+                            Some(value) => Ok(value),
                             None => {
                                 Err(EvaluationError::UndefinedVariable(format!("%{}", var_name)))
                             }
@@ -1074,35 +1519,27 @@ fn evaluate_term(
             }
 
             // If not $this or a variable, it must be a member/function invocation.
+            // Members of the root resource are read from the typed resource
+            let lazy_member = match (current_item, invocation) {
+                (None, Invocation::Member(name, _)) => context.lazy_root_member(name),
+                _ => None,
+            };
+            if let Some(result) = lazy_member {
+                return Ok(result);
+            }
+
             // Determine the base context for this invocation ($this for the current term).
-            // Priority: current_item > context.this > context.resources
+            // Priority: current_item > context.this > first resource
             let base_context = match current_item {
                 Some(item) => item.clone(),
-                None => match &context.this {
-                    Some(this_item) => this_item.clone(),
-                    None => {
-                        // Fallback to resources if context.this is also None
-                        if context.resources.is_empty() {
-                            EvaluationResult::Empty
-                        } else if context.resources.len() == 1 {
-                            convert_resource_to_result(&context.resources[0])
-                        } else {
-                            EvaluationResult::Collection {
-                                items: context
-                                    .resources
-                                    .iter()
-                                    .map(convert_resource_to_result)
-                                    .collect(),
-                                has_undefined_order: false, // Resources in context are typically ordered
-                                type_info: None,
-                            }
-                        }
-                    }
-                },
+                None => context
+                    .root_item()
+                    .cloned()
+                    .unwrap_or(EvaluationResult::Empty),
             };
 
             // Check if the invocation is a variable (non-% style)
-            if let Invocation::Member(name) = invocation {
+            if let Invocation::Member(name, _) = invocation {
                 // This check ensures we don't misinterpret %variables as type names.
                 // Variables (starting with '%') are handled earlier and would have returned.
                 if !name.starts_with('%') {
@@ -1133,15 +1570,16 @@ fn evaluate_term(
             // to evaluate_invocation, which is used for $this in function arguments (e.g., for lambdas).
             evaluate_invocation(&base_context, invocation, context, current_item)
         }
-        Term::Literal(literal) => Ok(evaluate_literal(literal)), // Wrap in Ok
-        Term::ExternalConstant(name) => {
+        Term::Literal(literal, _) => Ok(evaluate_literal(literal)), // Wrap in Ok
+        Term::ExternalConstant(name, _) => {
             // Look up external constant in the context
             // Special handling for %context
             if name == "context" {
-                Ok(if context.resources.is_empty() {
-                    EvaluationResult::Empty
-                } else if context.resources.len() == 1 {
-                    convert_resource_to_result(&context.resources[0])
+                Ok(if context.resources.len() <= 1 {
+                    context
+                        .root_resource_result()
+                        .cloned()
+                        .unwrap_or(EvaluationResult::Empty)
                 } else {
                     EvaluationResult::Collection {
                         items: context
@@ -1155,17 +1593,34 @@ fn evaluate_term(
                 }) // Correctly placed Ok() wrapping
             } else {
                 // Return variable value or error if undefined
-                match context.get_variable(name) {
-                    Some(value) => Ok(value.clone()),
+                match context.lookup_variable(name) {
+                    Some(value) => Ok(value),
                     None => Err(EvaluationError::UndefinedVariable(format!("%{}", name))),
                 }
             }
         }
-        Term::Parenthesized(expr) => evaluate(expr, context, current_item), // Propagate Result
+        Term::Parenthesized(expr, _) => evaluate(expr, context, current_item), // Propagate Result
     };
     result // Return the result
 }
 
+/// Matches a function invoked on the `%terminologies` constant
+///
+/// Returns the function name and argument expressions for
+/// `%terminologies.fn(...)`, or None for any other invocation.
+fn terminologies_call<'a>(
+    left_expr: &Expression,
+    invocation: &'a Invocation,
+) -> Option<(&'a str, &'a [Expression])> {
+    match (left_expr, invocation) {
+        (
+            Expression::Term(Term::ExternalConstant(constant, _), _),
+            Invocation::Function(func_name, args_exprs, _),
+        ) if constant == "terminologies" => Some((func_name.as_str(), args_exprs.as_slice())),
+        _ => None,
+    }
+}
+
 /// Converts a FHIR resource to an EvaluationResult
 ///
 /// This function converts a FHIR resource to an EvaluationResult by using the
@@ -1237,8 +1692,8 @@ fn evaluate_literal(literal: &Literal) -> EvaluationResult {
         }
         Literal::Time(t) => EvaluationResult::time(t.clone()),
         Literal::Quantity(value, unit) => {
-            // Normalize the unit to canonical form for consistency
-            let normalized_unit = normalize_unit_for_equality(unit);
+            // Calendar keywords written as annotations ('{day}') become the keyword
+            let normalized_unit = crate::ucum::normalize_quantity_unit(unit);
             EvaluationResult::quantity(*value, normalized_unit)
         }
     }
@@ -1271,13 +1726,13 @@ fn evaluate_literal(literal: &Literal) -> EvaluationResult {
 ///
 /// ```text
 /// // Member access: Patient.name
-/// evaluate_invocation(&patient, &Invocation::Member("name".to_string()), &context, None);
+/// evaluate_invocation(&patient, &Invocation::Member("name".to_string(), span), &context, None);
 ///
 /// // Function call: name.given.first()
-/// evaluate_invocation(&names, &Invocation::Function("first".to_string(), vec![]), &context, None);
+/// evaluate_invocation(&names, &Invocation::Function("first".to_string(), vec![], span), &context, None);
 ///
 /// // Indexing: name[0]
-/// evaluate_invocation(&names, &Invocation::Index(Expression::Term(Term::Literal(Literal::Integer(0)))), &context, None);
+/// evaluate_invocation(&names, &Invocation::Index(Expression::Term(Term::Literal(Literal::Integer(0), span))), &context, None);
 /// ```
 fn evaluate_invocation(
     invocation_base: &EvaluationResult, // The result of the expression the invocation is called on
@@ -1286,7 +1741,7 @@ fn evaluate_invocation(
     current_item_for_args: Option<&EvaluationResult>, // Context for $this in function arguments
 ) -> Result<EvaluationResult, EvaluationError> {
     let result = match invocation {
-        Invocation::Member(name) => {
+        Invocation::Member(name, _) => {
             // Handle member access on the invocation_base
             // Special handling for boolean literals that might be parsed as identifiers
             if name == "true" && matches!(invocation_base, EvaluationResult::Empty) {
@@ -1339,7 +1794,7 @@ fn evaluate_invocation(
                         // Pass current_item_for_args down for consistency
                         let res = evaluate_invocation(
                             item,
-                            &Invocation::Member(name.clone()),
+                            &Invocation::Member(name.clone(), invocation.span()),
                             context,
                             current_item_for_args,
                         )?;
@@ -1448,7 +1903,7 @@ fn evaluate_invocation(
                 EvaluationResult::Empty => Ok(EvaluationResult::Empty), // Wrap in Ok
             }
         }
-        Invocation::Function(name, args_exprs) => {
+        Invocation::Function(name, args_exprs, _) => {
             // Use args_exprs (AST)
             // Handle functions that take lambdas specially
             match name.as_str() {
@@ -1471,7 +1926,7 @@ fn evaluate_invocation(
                 "ofType" if args_exprs.len() == 1 => {
                     let type_spec_opt = match &args_exprs[0] {
                         // Handle literal string like 'Integer'
-                        Expression::Term(Term::Literal(Literal::String(type_name))) => {
+                        Expression::Term(Term::Literal(Literal::String(type_name), _), _) => {
                             // Check if the type name contains a namespace qualifier
                             if type_name.contains('.') {
                                 // Split into namespace and type
@@ -1496,15 +1951,21 @@ fn evaluate_invocation(
                             }
                         }
                         // Handle simple identifier like Integer (without quotes)
-                        Expression::Term(Term::Invocation(Invocation::Member(type_name))) => {
-                            Some(TypeSpecifier::QualifiedIdentifier(type_name.clone(), None))
-                        }
+                        Expression::Term(
+                            Term::Invocation(Invocation::Member(type_name, _), _),
+                            _,
+                        ) => Some(TypeSpecifier::QualifiedIdentifier(type_name.clone(), None)),
                         // Handle qualified identifier like System.Integer
-                        Expression::Invocation(base_expr, Invocation::Member(member_name)) => {
+                        Expression::Invocation(
+                            base_expr,
+                            Invocation::Member(member_name, _),
+                            _,
+                        ) => {
                             // Check if the base is a simple member invocation (like 'System')
-                            if let Expression::Term(Term::Invocation(Invocation::Member(
-                                base_name,
-                            ))) = &**base_expr
+                            if let Expression::Term(
+                                Term::Invocation(Invocation::Member(base_name, _), _),
+                                _,
+                            ) = &**base_expr
                             {
                                 // Create a properly qualified identifier with namespace and type name separated
                                 Some(TypeSpecifier::QualifiedIdentifier(
@@ -1531,7 +1992,7 @@ fn evaluate_invocation(
                 "is" | "as" if args_exprs.len() == 1 => {
                     // Logic for handling 'is' and 'as' functions by parsing their AST argument
                     let type_spec_opt = match &args_exprs[0] {
-                        Expression::Term(Term::Literal(Literal::String(type_name_str))) => {
+                        Expression::Term(Term::Literal(Literal::String(type_name_str), _), _) => {
                             // Argument is a string literal like 'Patient', 'System.String', or 'FHIR.Patient'.
                             // Parse it into namespace and type name if qualified.
                             if type_name_str.contains('.') {
@@ -1558,18 +2019,26 @@ fn evaluate_invocation(
                                 ))
                             }
                         }
-                        Expression::Term(Term::Invocation(Invocation::Member(type_name_ident))) => {
+                        Expression::Term(
+                            Term::Invocation(Invocation::Member(type_name_ident, _), _),
+                            _,
+                        ) => {
                             // Argument is an identifier like Patient or Quantity.
                             Some(TypeSpecifier::QualifiedIdentifier(
                                 type_name_ident.clone(),
                                 None,
                             ))
                         }
-                        Expression::Invocation(base_expr, Invocation::Member(member_name)) => {
+                        Expression::Invocation(
+                            base_expr,
+                            Invocation::Member(member_name, _),
+                            _,
+                        ) => {
                             // Argument is a qualified identifier like System.String
-                            if let Expression::Term(Term::Invocation(Invocation::Member(
-                                base_name,
-                            ))) = &**base_expr
+                            if let Expression::Term(
+                                Term::Invocation(Invocation::Member(base_name, _), _),
+                                _,
+                            ) = &**base_expr
                             {
                                 Some(TypeSpecifier::QualifiedIdentifier(
                                     base_name.clone(),
@@ -1651,6 +2120,10 @@ fn evaluate_invocation(
                         context,
                     )
                 }
+                "sort" => {
+                    // Key expressions are evaluated per item, so pass the AST
+                    crate::sort_function::sort_function(invocation_base, args_exprs, context)
+                }
                 "aggregate" if !args_exprs.is_empty() => {
                     // Get the aggregator expression
                     let aggregator_expr = &args_exprs[0];
@@ -1679,6 +2152,39 @@ fn evaluate_invocation(
                         ),
                     }
                 }
+                "defineVariable" => {
+                    if args_exprs.is_empty() || args_exprs.len() > 2 {
+                        return Err(EvaluationError::InvalidArity(
+                            "Function 'defineVariable' expects 1 or 2 arguments".to_string(),
+                        ));
+                    }
+                    let name = match evaluate(&args_exprs[0], context, None)? {
+                        EvaluationResult::String(name, _) => name,
+                        other => {
+                            return Err(EvaluationError::InvalidArgument(format!(
+                                "defineVariable() requires a String name, found {}",
+                                other.type_name()
+                            )));
+                        }
+                    };
+
+                    // The value expression is evaluated with the whole input as focus
+                    let value = match args_exprs.get(1) {
+                        Some(value_expr) => {
+                            let focus = if expression_starts_with_resource_identifier(
+                                value_expr, context,
+                            ) {
+                                None
+                            } else {
+                                Some(invocation_base)
+                            };
+                            evaluate(value_expr, context, focus)?
+                        }
+                        None => invocation_base.clone(),
+                    };
+                    context.define_variable(&name, value)?;
+                    Ok(invocation_base.clone())
+                }
                 "trace" => {
                     // Check if there are arguments - trace() requires at least a name
                     if args_exprs.is_empty() {
@@ -1722,13 +2228,14 @@ fn evaluate_invocation(
                         // Check if the argument is a bare type identifier
                         match &args_exprs[0] {
                             // Handle literal string like 'Patient'
-                            Expression::Term(Term::Literal(Literal::String(type_name))) => {
+                            Expression::Term(Term::Literal(Literal::String(type_name), _), _) => {
                                 Some(type_name.clone())
                             }
                             // Handle bare identifier like Patient (without quotes)
-                            Expression::Term(Term::Invocation(Invocation::Member(type_name))) => {
-                                Some(type_name.clone())
-                            }
+                            Expression::Term(
+                                Term::Invocation(Invocation::Member(type_name, _), _),
+                                _,
+                            ) => Some(type_name.clone()),
                             _ => {
                                 // For other expressions, evaluate normally and try to extract string
                                 let evaluated =
@@ -1760,6 +2267,16 @@ fn evaluate_invocation(
                 }
                 // Add other functions taking lambdas here (e.g., any)
                 _ => {
+                    // Functions the evaluator does not know may be registered by the application
+                    if let Some(function) = context.custom_function(name) {
+                        return call_custom_function(
+                            function,
+                            invocation_base,
+                            args_exprs,
+                            context,
+                            current_item_for_args,
+                        );
+                    }
                     // Default: Evaluate all standard function arguments first (without $this context), then call function
                     let mut evaluated_args = Vec::with_capacity(args_exprs.len());
                     for arg_expr in args_exprs {
@@ -1771,17 +2288,17 @@ fn evaluate_invocation(
                 }
             }
         }
-        Invocation::This => {
+        Invocation::This(_) => {
             // This should be handled by evaluate_term, but as a fallback:
             Ok(invocation_base.clone()) // Return the base it was invoked on
         }
-        Invocation::Index => {
+        Invocation::Index(_) => {
             // $index should return the current index in a collection operation
             // This is typically used in filter expressions
             // For now, we return Empty as this requires tracking iteration state
             Ok(EvaluationResult::Empty)
         }
-        Invocation::Total => {
+        Invocation::Total(_) => {
             // $total has two meanings:
             // 1. In aggregate(): it's the accumulator.
             // 2. Elsewhere (often with $index): it's the count of the context collection.
@@ -1933,6 +2450,53 @@ fn evaluate_select(
     ))
 }
 
+/// Calls a function from the function registry of the context
+fn call_custom_function(
+    function: &CustomFunction,
+    input: &EvaluationResult,
+    args_exprs: &[Expression],
+    context: &EvaluationContext,
+    current_item_for_args: Option<&EvaluationResult>,
+) -> Result<EvaluationResult, EvaluationError> {
+    function.check_arity(args_exprs.len())?;
+    let evaluate_args = |this: Option<&EvaluationResult>| {
+        args_exprs
+            .iter()
+            .map(|arg_expr| evaluate(arg_expr, context, this))
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    match function.argument_evaluation() {
+        ArgumentEvaluation::Once => {
+            let args = evaluate_args(current_item_for_args)?;
+            function.call(input, &args, context)
+        }
+        ArgumentEvaluation::PerItem => {
+            let (items, input_was_unordered) = match input {
+                EvaluationResult::Collection {
+                    items,
+                    has_undefined_order,
+                    ..
+                } => (items.clone(), *has_undefined_order),
+                EvaluationResult::Empty => (vec![], false),
+                single_item => (vec![single_item.clone()], false),
+            };
+            let mut results = Vec::with_capacity(items.len());
+            for item in &items {
+                let args = evaluate_args(Some(item))?;
+                results.push(function.call(item, &args, context)?);
+            }
+            let (flattened_items, is_unordered) =
+                flatten_collections_recursive(EvaluationResult::Collection {
+                    items: results,
+                    has_undefined_order: input_was_unordered,
+                    type_info: None,
+                });
+            Ok(normalize_collection_result(flattened_items, is_unordered))
+        }
+    }
+}
+
 /// Evaluates the 'all' function with a criteria expression.
 fn evaluate_all_with_criteria(
     collection: &EvaluationResult,
@@ -1972,6 +2536,136 @@ fn evaluate_all_with_criteria(
     Ok(EvaluationResult::boolean(true))
 }
 
+/// The functions the evaluator implements itself
+///
+/// Registered functions with these names are never called: built-in functions
+/// take precedence (see `EvaluationContext::custom_function`).
+const BUILTIN_FUNCTIONS: &[&str] = &[
+    "where",
+    "select",
+    "exists",
+    "all",
+    "iif",
+    "repeat",
+    "aggregate",
+    "sort",
+    "trace",
+    "ofType",
+    "is",
+    "as",
+    "children",
+    "descendants",
+    "type",
+    "extension",
+    "toBoolean",
+    "convertsToBoolean",
+    "toInteger",
+    "convertsToInteger",
+    "toDecimal",
+    "convertsToDecimal",
+    "toString",
+    "convertsToString",
+    "toDate",
+    "convertsToDate",
+    "toDateTime",
+    "convertsToDateTime",
+    "toTime",
+    "convertsToTime",
+    "toLong",
+    "convertsToLong",
+    "toQuantity",
+    "convertsToQuantity",
+    "count",
+    "sum",
+    "min",
+    "max",
+    "avg",
+    "empty",
+    "first",
+    "last",
+    "not",
+    "contains",
+    "isDistinct",
+    "distinct",
+    "skip",
+    "tail",
+    "take",
+    "intersect",
+    "exclude",
+    "union",
+    "combine",
+    "length",
+    "indexOf",
+    "lastIndexOf",
+    "substring",
+    "startsWith",
+    "endsWith",
+    "upper",
+    "lower",
+    "replace",
+    "matches",
+    "matchesFull",
+    "replaceMatches",
+    "join",
+    "split",
+    "trim",
+    "encode",
+    "decode",
+    "escape",
+    "unescape",
+    "round",
+    "sqrt",
+    "toChars",
+    "now",
+    "today",
+    "timeOfDay",
+    "lowBoundary",
+    "highBoundary",
+    "precision",
+    "comparable",
+    "yearOf",
+    "monthOf",
+    "dayOf",
+    "hourOf",
+    "minuteOf",
+    "secondOf",
+    "millisecondOf",
+    "timezoneOffsetOf",
+    "dateOf",
+    "timeOf",
+    "duration",
+    "difference",
+    "getResourceKey",
+    "getReferenceKey",
+    "resolve",
+    "memberOf",
+    "subsumes",
+    "subsumedBy",
+    "conformsTo",
+    "elementDefinition",
+    "slice",
+    "hasValue",
+    "getValue",
+    "htmlChecks",
+    "checkModifiers",
+    "abs",
+    "ceiling",
+    "floor",
+    "truncate",
+    "exp",
+    "ln",
+    "log",
+    "power",
+    "single",
+    "subsetOf",
+    "supersetOf",
+    "allTrue",
+    "anyTrue",
+    "allFalse",
+    "anyFalse",
+    "defineVariable",
+];
+
 /// Calls a standard FHIRPath function (that doesn't take a lambda).
 fn call_function(
     name: &str,
@@ -2027,10 +2721,119 @@ fn call_function(
 
             apply_type_operation(invocation_base, name, &type_spec, context) // Pass context
         }
+        "memberOf" => {
+            if args.len() != 1 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'memberOf' expects 1 argument".to_string(),
+                ));
+            }
+            crate::terminology::member_of_function(invocation_base, args, context)
+        }
+        "subsumes" => {
+            if args.len() != 1 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'subsumes' expects 1 argument".to_string(),
+                ));
+            }
+            crate::terminology::subsumes_function(invocation_base, args, context)
+        }
+        "subsumedBy" => {
+            if args.len() != 1 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'subsumedBy' expects 1 argument".to_string(),
+                ));
+            }
+            crate::terminology::subsumed_by_function(invocation_base, args, context)
+        }
+        "conformsTo" => {
+            if args.len() != 1 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'conformsTo' expects 1 argument".to_string(),
+                ));
+            }
+            crate::profile_registry::conforms_to_function(invocation_base, args, context)
+        }
+        "elementDefinition" => {
+            if !args.is_empty() {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'elementDefinition' expects 0 arguments".to_string(),
+                ));
+            }
+            crate::profile_registry::element_definition_function(invocation_base, context)
+        }
+        "slice" => {
+            if args.len() != 2 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'slice' expects 2 arguments (structure, name)".to_string(),
+                ));
+            }
+            crate::profile_registry::slice_function(invocation_base, args, context)
+        }
+        "hasValue" | "getValue" | "htmlChecks" => {
+            if !args.is_empty() {
+                return Err(EvaluationError::InvalidArity(format!(
+                    "Function '{}' expects 0 arguments",
+                    name
+                )));
+            }
+            match name {
+                "hasValue" => Ok(crate::fhir_functions::has_value_function(invocation_base)),
+                "getValue" => Ok(crate::fhir_functions::get_value_function(invocation_base)),
+                _ => crate::fhir_functions::html_checks_function(invocation_base),
+            }
+        }
+        "checkModifiers" => {
+            if args.len() != 1 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'checkModifiers' expects 1 argument (modifiers)".to_string(),
+                ));
+            }
+            crate::fhir_functions::check_modifiers_function(invocation_base, args)
+        }
+        "resolve" => {
+            if !args.is_empty() {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'resolve' expects 0 arguments".to_string(),
+                ));
+            }
+            crate::reference_resolver::resolve_function(invocation_base, context)
+        }
         "count" => {
             // Delegate to the dedicated function in collection_functions.rs
             Ok(crate::collection_functions::count_function(invocation_base))
         }
+        "sum" => {
+            if !args.is_empty() {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'sum' expects 0 arguments".to_string(),
+                ));
+            }
+            crate::aggregate_function::sum_function(invocation_base)
+        }
+        "min" => {
+            if !args.is_empty() {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'min' expects 0 arguments".to_string(),
+                ));
+            }
+            crate::aggregate_function::min_function(invocation_base)
+        }
+        "max" => {
+            if !args.is_empty() {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'max' expects 0 arguments".to_string(),
+                ));
+            }
+            crate::aggregate_function::max_function(invocation_base)
+        }
+        "avg" => {
+            if !args.is_empty() {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'avg' expects 0 arguments".to_string(),
+                ));
+            }
+            crate::aggregate_function::avg_function(invocation_base)
+        }
         "type" => crate::type_function::type_function(invocation_base, args),
         "empty" => {
             // Delegate to the dedicated function in collection_functions.rs
@@ -2422,7 +3225,7 @@ fn call_function(
                 EvaluationResult::Date(d, _) => EvaluationResult::date(d.clone()),
                 EvaluationResult::DateTime(dt, _) => {
                     // Extract the date part
-                    if let Some(date_part) = dt.split('T').next() {
+                    if let Some(date_part) = dt.as_str().split('T').next() {
                         EvaluationResult::date(date_part.to_string())
                     } else {
                         EvaluationResult::Empty // Should not happen if DateTime format is valid
@@ -2672,15 +3475,31 @@ fn call_function(
             crate::long_conversion::converts_to_long(invocation_base, context)
         }
         "toQuantity" => {
-            // Converts the input to Quantity according to FHIRPath rules
-            // The result is just the numeric value (Decimal or Integer) as unit handling is complex
+            // Converts the input to Quantity according to FHIRPath rules, optionally
+            // converting it to the given unit
+            if args.len() > 1 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'toQuantity' expects 0 or 1 argument (unit)".to_string(),
+                ));
+            }
             // Check for singleton first
             if invocation_base.count() > 1 {
                 return Err(EvaluationError::SingletonEvaluationError(
                     "toQuantity requires a singleton input".to_string(),
                 ));
             }
-            Ok(match invocation_base {
+            let target_unit = match args.first() {
+                None => None,
+                Some(EvaluationResult::Empty) => return Ok(EvaluationResult::Empty),
+                Some(EvaluationResult::String(unit, _)) => Some(unit.as_str()),
+                Some(other) => {
+                    return Err(EvaluationError::InvalidArgument(format!(
+                        "toQuantity unit must be a String, found {}",
+                        other.type_name()
+                    )));
+                }
+            };
+            let quantity = match invocation_base {
                 EvaluationResult::Empty => EvaluationResult::Empty,
                 EvaluationResult::Boolean(b, _) => {
                     // Convert Boolean to Quantity 1.0 '1' or 0.0 '1'
@@ -2715,10 +3534,8 @@ fn call_function(
                         if let Ok(decimal_value) = value_part.parse::<Decimal>() {
                             // Check if the unit part is valid (remove quotes if present)
                             let unit_str = unit_part.trim_matches('\'');
-                            if is_valid_fhirpath_quantity_unit(unit_str) {
-                                // Convert calendar-based units to UCUM format for consistency
-                                let ucum_unit = convert_to_ucum_unit(unit_str);
-                                EvaluationResult::quantity(decimal_value, ucum_unit)
+                            if crate::ucum::is_valid_quantity_unit(unit_str) {
+                                EvaluationResult::quantity(decimal_value, unit_str.to_string())
                             } else {
                                 EvaluationResult::Empty // Invalid unit
                             }
@@ -2731,10 +3548,27 @@ fn call_function(
                 }
                 EvaluationResult::Collection { .. } => unreachable!(),
                 _ => EvaluationResult::Empty, // Other types cannot convert
+            };
+            // Convert to the requested unit, which is empty if the units are not commensurable
+            Ok(match (quantity, target_unit) {
+                (EvaluationResult::Quantity(value, unit, _), Some(target)) => {
+                    match crate::ucum::convert_quantity(value, &unit, target) {
+                        Some(converted) => {
+                            EvaluationResult::quantity(converted, target.to_string())
+                        }
+                        None => EvaluationResult::Empty,
+                    }
+                }
+                (quantity, _) => quantity,
             })
         }
         "convertsToQuantity" => {
             // Checks if the input can be converted to Quantity
+            if args.len() > 1 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'convertsToQuantity' expects 0 or 1 argument (unit)".to_string(),
+                ));
+            }
             // Check for singleton first
             if invocation_base.count() > 1 {
                 return Err(EvaluationError::SingletonEvaluationError(
@@ -2746,7 +3580,7 @@ fn call_function(
                 return Ok(EvaluationResult::Empty);
             }
             // Now we know it's a non-empty singleton
-            Ok(match invocation_base {
+            let converts = match invocation_base {
                 EvaluationResult::Boolean(_, _) => EvaluationResult::boolean(true),
                 EvaluationResult::Integer(_, _) => EvaluationResult::boolean(true),
                 EvaluationResult::Decimal(_, _) => EvaluationResult::boolean(true),
@@ -2770,7 +3604,8 @@ fn call_function(
 
                                 // Check if the unit content (after trimming quotes) is a valid FHIRPath unit.
                                 // This also handles if unit_content_after_trimming is empty (which is invalid).
-                                if !is_valid_fhirpath_quantity_unit(unit_content_after_trimming) {
+                                if !crate::ucum::is_valid_quantity_unit(unit_content_after_trimming)
+                                {
                                     false
                                 } else {
                                     // At this point, unit_content_after_trimming is a non-empty, valid unit.
@@ -2801,7 +3636,47 @@ fn call_function(
                 }),
                 EvaluationResult::Collection { .. } => unreachable!(),
                 _ => EvaluationResult::boolean(false),
-            })
+            };
+            // With a unit, the value must also convert to that unit
+            if args.is_empty() || !converts.to_boolean() {
+                return Ok(converts);
+            }
+            Ok(
+                match call_function("toQuantity", invocation_base, args, context)? {
+                    EvaluationResult::Empty if args[0] == EvaluationResult::Empty => {
+                        EvaluationResult::Empty
+                    }
+                    EvaluationResult::Empty => EvaluationResult::boolean(false),
+                    _ => EvaluationResult::boolean(true),
+                },
+            )
+        }
+        "comparable" => {
+            // Implements comparable(quantity : Quantity) : Boolean
+            if args.len() != 1 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'comparable' expects 1 argument (quantity)".to_string(),
+                ));
+            }
+            if invocation_base.count() > 1 || args[0].count() > 1 {
+                return Err(EvaluationError::SingletonEvaluationError(
+                    "comparable requires singleton input and argument".to_string(),
+                ));
+            }
+            if invocation_base == &EvaluationResult::Empty || args[0] == EvaluationResult::Empty {
+                return Ok(EvaluationResult::Empty);
+            }
+            match (
+                crate::aggregate_function::as_quantity(invocation_base),
+                crate::aggregate_function::as_quantity(&args[0]),
+            ) {
+                (Some((_, left_unit)), Some((_, right_unit))) => Ok(EvaluationResult::boolean(
+                    crate::ucum::units_comparable(&left_unit, &right_unit),
+                )),
+                _ => Err(EvaluationError::TypeError(
+                    "comparable requires Quantity input and argument".to_string(),
+                )),
+            }
         }
         "length" => {
             // Returns the length of a string
@@ -3080,6 +3955,43 @@ fn call_function(
                 }
             })
         }
+        "lastIndexOf" => {
+            // Returns the 0-based index of the last occurrence of the substring
+            if args.len() != 1 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'lastIndexOf' expects 1 argument".to_string(),
+                ));
+            }
+            if invocation_base.count() > 1 || args[0].count() > 1 {
+                return Err(EvaluationError::SingletonEvaluationError(
+                    "lastIndexOf requires singleton input and argument".to_string(),
+                ));
+            }
+            Ok(match (invocation_base, &args[0]) {
+                // An empty substring is found at the start of the string
+                (EvaluationResult::String(_, _), EvaluationResult::String(substring, _))
+                    if substring.is_empty() =>
+                {
+                    EvaluationResult::integer(0)
+                }
+                (EvaluationResult::String(s, _), EvaluationResult::String(substring, _)) => {
+                    match s.rfind(substring.as_str()) {
+                        // Report the position in characters rather than bytes
+                        Some(index) => EvaluationResult::integer(s[..index].chars().count() as i64),
+                        None => EvaluationResult::integer(-1),
+                    }
+                }
+                (EvaluationResult::String(_, _), EvaluationResult::Empty) => {
+                    EvaluationResult::Empty
+                } // X.lastIndexOf({}) -> {}
+                (EvaluationResult::Empty, _) => EvaluationResult::Empty, // {}.lastIndexOf(X) -> {}
+                _ => {
+                    return Err(EvaluationError::TypeError(
+                        "lastIndexOf requires String input and argument".to_string(),
+                    ));
+                }
+            })
+        }
         "matches" => {
             if args.len() != 1 {
                 return Err(EvaluationError::InvalidArity(
@@ -3112,6 +4024,36 @@ fn call_function(
                 }
             })
         }
+        "matchesFull" => {
+            // Like matches(), but the regex must match the entire input string
+            if args.len() != 1 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'matchesFull' expects 1 argument".to_string(),
+                ));
+            }
+            if invocation_base.count() > 1 || args[0].count() > 1 {
+                return Err(EvaluationError::SingletonEvaluationError(
+                    "matchesFull requires singleton input and argument".to_string(),
+                ));
+            }
+            Ok(match (invocation_base, &args[0]) {
+                (EvaluationResult::String(s, _), EvaluationResult::String(regex_pattern, _)) => {
+                    match Regex::new(&format!("^(?:{})$", regex_pattern)) {
+                        Ok(re) => EvaluationResult::boolean(re.is_match(s)),
+                        Err(e) => return Err(EvaluationError::InvalidRegex(e.to_string())),
+                    }
+                }
+                (EvaluationResult::String(_, _), EvaluationResult::Empty) => {
+                    EvaluationResult::Empty
+                } // S.matchesFull({}) -> {}
+                (EvaluationResult::Empty, _) => EvaluationResult::Empty, // {}.matchesFull(R) -> {}
+                _ => {
+                    return Err(EvaluationError::TypeError(
+                        "matchesFull requires String input and argument".to_string(),
+                    ));
+                }
+            })
+        }
         "replaceMatches" => {
             if args.len() != 2 {
                 return Err(EvaluationError::InvalidArity(
@@ -3149,6 +4091,54 @@ fn call_function(
                 }
             })
         }
+        "split" => {
+            if args.len() != 1 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'split' expects 1 argument".to_string(),
+                ));
+            }
+            crate::string_functions::split_function(invocation_base, &args[0])
+        }
+        "trim" => {
+            if !args.is_empty() {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'trim' expects 0 arguments".to_string(),
+                ));
+            }
+            crate::string_functions::trim_function(invocation_base)
+        }
+        "encode" => {
+            if args.len() != 1 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'encode' expects 1 argument".to_string(),
+                ));
+            }
+            crate::string_functions::encode_function(invocation_base, &args[0])
+        }
+        "decode" => {
+            if args.len() != 1 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'decode' expects 1 argument".to_string(),
+                ));
+            }
+            crate::string_functions::decode_function(invocation_base, &args[0])
+        }
+        "escape" => {
+            if args.len() != 1 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'escape' expects 1 argument".to_string(),
+                ));
+            }
+            crate::string_functions::escape_function(invocation_base, &args[0])
+        }
+        "unescape" => {
+            if args.len() != 1 {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'unescape' expects 1 argument".to_string(),
+                ));
+            }
+            crate::string_functions::unescape_function(invocation_base, &args[0])
+        }
         "join" => {
             // Joins a collection of strings with a separator
             // If no separator is provided, defaults to empty string
@@ -4320,6 +5310,10 @@ fn call_function(
             while !current_level.is_empty() {
                 let mut next_level: Vec<EvaluationResult> = Vec::new();
                 for item in &current_level {
+                    context.limits.check_running()?;
+                    context
+                        .limits
+                        .check_collection_size(all_descendants.len())?;
                     match call_function("children", item, &[], context)? {
                         EvaluationResult::Empty => (),
                         EvaluationResult::Collection {
@@ -4355,13 +5349,62 @@ fn call_function(
             // Delegate to the extension_function module
             crate::extension_function::extension_function(invocation_base, args)
         }
-        "lowBoundary" => {
-            // Delegate to the dedicated function in boundary_functions.rs
-            crate::boundary_functions::low_boundary_function(invocation_base)
+        "lowBoundary" | "highBoundary" => {
+            // Implements lowBoundary([precision : Integer]) and highBoundary([precision : Integer])
+            let precision = match args {
+                [] => None,
+                [EvaluationResult::Empty] => return Ok(EvaluationResult::Empty),
+                [EvaluationResult::Integer(p, _)] => Some(*p),
+                [_] => {
+                    return Err(EvaluationError::TypeError(format!(
+                        "{} precision must be an Integer",
+                        name
+                    )));
+                }
+                _ => {
+                    return Err(EvaluationError::InvalidArity(format!(
+                        "Function '{}' expects 0 or 1 argument (precision)",
+                        name
+                    )));
+                }
+            };
+            // Delegate to the dedicated functions in boundary_functions.rs
+            if name == "lowBoundary" {
+                crate::boundary_functions::low_boundary_function(invocation_base, precision)
+            } else {
+                crate::boundary_functions::high_boundary_function(invocation_base, precision)
+            }
+        }
+        "precision" => {
+            if !args.is_empty() {
+                return Err(EvaluationError::InvalidArity(
+                    "Function 'precision' expects 0 arguments".to_string(),
+                ));
+            }
+            crate::boundary_functions::precision_function(invocation_base)
         }
-        "highBoundary" => {
-            // Delegate to the dedicated function in boundary_functions.rs
-            crate::boundary_functions::high_boundary_function(invocation_base)
+        "yearOf" | "monthOf" | "dayOf" | "hourOf" | "minuteOf" | "secondOf" | "millisecondOf"
+        | "timezoneOffsetOf" | "dateOf" | "timeOf" => {
+            if !args.is_empty() {
+                return Err(EvaluationError::InvalidArity(format!(
+                    "Function '{}' expects 0 arguments",
+                    name
+                )));
+            }
+            crate::date_operation::date_component_function(name, invocation_base)
+        }
+        "duration" | "difference" => {
+            if args.len() != 2 {
+                return Err(EvaluationError::InvalidArity(format!(
+                    "Function '{}' expects 2 arguments",
+                    name
+                )));
+            }
+            if name == "duration" {
+                crate::date_operation::duration_function(invocation_base, args)
+            } else {
+                crate::date_operation::difference_function(invocation_base, args)
+            }
         }
         "getResourceKey" => {
             // Delegate to the reference key functions module
@@ -4375,79 +5418,7 @@ fn call_function(
         // Add other standard functions here
         _ => {
             // Only print warning for functions not handled elsewhere
-            // Added conversion functions and now/today/timeOfDay to the list
-            let handled_functions = [
-                "where",
-                "select",
-                "exists",
-                "all",
-                "iif",
-                "repeat",
-                "aggregate",
-                "trace",
-                "ofType",
-                "is",
-                "as",
-                "children",
-                "descendants",
-                "type",
-                "extension",
-                "toBoolean",
-                "convertsToBoolean",
-                "toInteger",
-                "convertsToInteger",
-                "toDecimal",
-                "convertsToDecimal",
-                "toString",
-                "convertsToString",
-                "toDate",
-                "convertsToDate",
-                "toDateTime",
-                "convertsToDateTime",
-                "toTime",
-                "convertsToTime",
-                "toLong",
-                "convertsToLong",
-                "toQuantity",
-                "convertsToQuantity",
-                "count",
-                "empty",
-                "first",
-                "last",
-                "not",
-                "contains",
-                "isDistinct",
-                "distinct",
-                "skip",
-                "tail",
-                "take",
-                "intersect",
-                "exclude",
-                "union",
-                "combine",
-                "length",
-                "indexOf",
-                "substring",
-                "startsWith",
-                "endsWith",
-                "upper",
-                "lower",
-                "replace",
-                "matches",
-                "replaceMatches",
-                "join",
-                "round",
-                "sqrt",
-                "toChars",
-                "now",
-                "today",
-                "timeOfDay",
-                "lowBoundary",
-                "highBoundary",
-                "getResourceKey",
-                "getReferenceKey",
-            ];
-            if !handled_functions.contains(&name) {
+            if !BUILTIN_FUNCTIONS.contains(&name) {
                 eprintln!("Warning: Unsupported function called: {}", name); // Keep this warning for truly unhandled functions
             }
             Ok(EvaluationResult::Empty) // Return Ok(Empty) for unhandled but potentially valid functions
@@ -4535,73 +5506,6 @@ fn to_decimal(value: &EvaluationResult) -> Result<Decimal, EvaluationError> {
     }
 }
 
-/// Normalizes units for equality comparison, handling both word and UCUM brace formats
-fn normalize_unit_for_equality(unit: &str) -> String {
-    // Only remove curly braces for comparison, but don't change the unit otherwise
-    // This allows "{day}" and "day" to be considered equal without changing existing behavior
-    let cleaned = unit.trim_start_matches('{').trim_end_matches('}');
-    cleaned.to_string()
-}
-
-/// Converts calendar-based units to canonical format for internal consistency
-fn convert_to_ucum_unit(unit: &str) -> String {
-    // Keep units as-is during parsing to preserve existing behavior
-    // Only apply minimal changes needed for the specific R4 test fixes
-    unit.to_string()
-}
-
-/// Checks if a string is a valid FHIRPath quantity unit (UCUM or time-based).
-/// Note: This is a simplified check. A full UCUM validator is complex.
-fn is_valid_fhirpath_quantity_unit(unit: &str) -> bool {
-    // Remove underscore
-    // Allow known time-based units
-    const TIME_UNITS: &[&str] = &[
-        "year",
-        "month",
-        "week",
-        "day",
-        "hour",
-        "minute",
-        "second",
-        "millisecond",
-        "years",
-        "months",
-        "weeks",
-        "days",
-        "hours",
-        "minutes",
-        "seconds",
-        "milliseconds",
-    ];
-    if TIME_UNITS.contains(&unit) {
-        return true;
-    }
-
-    // Basic check for UCUM units (non-empty, no whitespace, doesn't start with digit unless '1')
-    // This is NOT a full UCUM validation.
-    if unit.is_empty() {
-        return false; // Empty string is not a valid unit
-    }
-    // Check for whitespace
-    if unit.chars().any(|c| c.is_whitespace()) {
-        return false; // UCUM units generally don't contain whitespace
-    }
-    // Allow '1' as the default unit.
-    if unit == "1" {
-        return true;
-    }
-    // Check if it starts with a digit (generally not allowed after checking '1')
-    if unit.starts_with(|c: char| c.is_ascii_digit()) {
-        return false;
-    }
-
-    // For now, assume other non-empty strings without whitespace that don't start with a digit
-    // are potentially valid UCUM units for parsing purposes.
-    // A real implementation would need a proper UCUM validator.
-    // Let's make this slightly stricter: disallow common punctuation that's unlikely in simple UCUM
-    !unit.contains(|c: char| !c.is_ascii_alphanumeric() && !"[]{}/.%".contains(c))
-}
-
 /// Evaluates an indexer expression
 fn evaluate_indexer(
     collection_result: &EvaluationResult, // Renamed from collection to avoid confusion with items
@@ -4693,6 +5597,33 @@ fn apply_multiplicative(
     op: &str,
     right: &EvaluationResult,
 ) -> Result<EvaluationResult, EvaluationError> {
+    // Quantities multiply and divide with each other and with plain numbers,
+    // combining their units
+    let quantity_operands = match (left, right) {
+        (EvaluationResult::Quantity(..), _) | (_, EvaluationResult::Quantity(..))
+            if op == "*" || op == "/" =>
+        {
+            quantity_operand(left).zip(quantity_operand(right))
+        }
+        _ => None,
+    };
+    if let Some(((val_l, unit_l), (val_r, unit_r))) = quantity_operands {
+        let product = if op == "*" {
+            crate::ucum::multiply_quantities(val_l, unit_l, val_r, unit_r)
+        } else if val_r.is_zero() {
+            // Spec: Division by zero returns empty
+            None
+        } else {
+            crate::ucum::divide_quantities(val_l, unit_l, val_r, unit_r)
+                .map(|(value, unit)| (round_to_precision(value, 8), unit))
+        };
+        return Ok(match product {
+            Some((value, unit)) => EvaluationResult::quantity(value, unit),
+            // Units that cannot be combined give an empty result
+            None => EvaluationResult::Empty,
+        });
+    }
+
     match op {
         "*" => {
             // Handle multiplication: Int * Int = Int, otherwise Decimal
@@ -4731,13 +5662,11 @@ fn apply_multiplicative(
             let left_dec = match left {
                 EvaluationResult::Decimal(d, _) => Some(*d),
                 EvaluationResult::Integer(i, _) => Some(Decimal::from(*i)),
-                EvaluationResult::Quantity(val, _, _) => Some(*val), // Extract value from Quantity
                 _ => None,
             };
             let right_dec = match right {
                 EvaluationResult::Decimal(d, _) => Some(*d),
                 EvaluationResult::Integer(i, _) => Some(Decimal::from(*i)),
-                EvaluationResult::Quantity(val, _, _) => Some(*val), // Extract value from Quantity
                 _ => None,
             };
 
@@ -4793,6 +5722,16 @@ fn apply_multiplicative(
     }
 }
 
+/// Returns the value and unit of a Quantity, or of a number as a `'1'` Quantity
+fn quantity_operand(value: &EvaluationResult) -> Option<(Decimal, &str)> {
+    match value {
+        EvaluationResult::Quantity(val, unit, _) => Some((*val, unit.as_str())),
+        EvaluationResult::Decimal(d, _) => Some((*d, "1")),
+        EvaluationResult::Integer(i, _) => Some((Decimal::from(*i), "1")),
+        _ => None,
+    }
+}
+
 /// Applies integer-only multiplicative operators (div, mod)
 fn apply_integer_multiplicative(
     left: i64,
@@ -4843,19 +5782,15 @@ fn apply_additive(
                 (EvaluationResult::Integer(l, _), EvaluationResult::Decimal(r, _)) => {
                     EvaluationResult::decimal(Decimal::from(*l) + *r)
                 }
-                // Quantity addition (requires same units) - Added
+                // Quantity addition (requires commensurable units)
                 (
                     EvaluationResult::Quantity(val_l, unit_l, _),
                     EvaluationResult::Quantity(val_r, unit_r, _),
-                ) => {
-                    if unit_l == unit_r {
-                        EvaluationResult::quantity(*val_l + *val_r, unit_l.clone())
-                    } else {
-                        // Incompatible units for now, return empty
-                        // TODO: Implement UCUM conversion if needed
-                        EvaluationResult::Empty
-                    }
-                }
+                ) => match crate::ucum::add_quantities(*val_l, unit_l, *val_r, unit_r) {
+                    Some((value, unit)) => EvaluationResult::quantity(value, unit),
+                    // Incompatible units, return empty
+                    None => EvaluationResult::Empty,
+                },
                 // Handle string concatenation with '+'
                 (EvaluationResult::String(l, _), EvaluationResult::String(r, _)) => {
                     EvaluationResult::string(format!("{}{}", l, r))
@@ -4961,19 +5896,15 @@ fn apply_additive(
                 (EvaluationResult::Integer(l, _), EvaluationResult::Decimal(r, _)) => {
                     EvaluationResult::decimal(Decimal::from(*l) - *r)
                 }
-                // Quantity subtraction (requires same units) - Added
+                // Quantity subtraction (requires commensurable units)
                 (
                     EvaluationResult::Quantity(val_l, unit_l, _),
                     EvaluationResult::Quantity(val_r, unit_r, _),
-                ) => {
-                    if unit_l == unit_r {
-                        EvaluationResult::quantity(*val_l - *val_r, unit_l.clone())
-                    } else {
-                        // Incompatible units for now, return empty
-                        // TODO: Implement UCUM conversion if needed
-                        EvaluationResult::Empty
-                    }
-                }
+                ) => match crate::ucum::add_quantities(*val_l, unit_l, -*val_r, unit_r) {
+                    Some((value, unit)) => EvaluationResult::quantity(value, unit),
+                    // Incompatible units, return empty
+                    None => EvaluationResult::Empty,
+                },
                 // Handle String - Number (attempt conversion, prioritize Integer result if possible)
                 (EvaluationResult::String(s, _), EvaluationResult::Integer(i, _)) => {
                     // Try parsing string as Integer first
@@ -5277,16 +6208,26 @@ fn compare_inequality(
         )));
     }
 
+    let ordering = compare_ordering(left, right)?;
+    let result = match op {
+        "<" => ordering.is_lt(),
+        "<=" => ordering.is_le(),
+        ">" => ordering.is_gt(),
+        ">=" => ordering.is_ge(),
+        _ => false, // Should not happen
+    };
+    Ok(EvaluationResult::boolean(result)) // Return Ok result
+}
+
+/// Orders two non-empty singleton values using the type-aware rules of the
+/// comparison operators. Used by `<`, `>`, `<=`, `>=` and sort().
+pub(crate) fn compare_ordering(
+    left: &EvaluationResult,
+    right: &EvaluationResult,
+) -> Result<std::cmp::Ordering, EvaluationError> {
     // First, check if both values are date/time types that can be compared
     if let Some(ordering) = crate::datetime_impl::compare_date_time_values(left, right) {
-        let result = match op {
-            "<" => ordering.is_lt(),
-            "<=" => ordering.is_le(),
-            ">" => ordering.is_gt(),
-            ">=" => ordering.is_ge(),
-            _ => false, // Should not happen
-        };
-        return Ok(EvaluationResult::boolean(result));
+        return Ok(ordering);
     }
 
     // If not date/time types, handle other types
@@ -5305,20 +6246,20 @@ fn compare_inequality(
         }
         // String comparison
         (EvaluationResult::String(l, _), EvaluationResult::String(r, _)) => Some(l.cmp(r)),
-        // Quantity comparison (only if units match)
+        // Quantity comparison (only if the units are commensurable)
         (
             EvaluationResult::Quantity(val_l, unit_l, _),
             EvaluationResult::Quantity(val_r, unit_r, _),
         ) => {
-            if unit_l == unit_r {
-                // Simple string comparison for now
-                Some(val_l.cmp(val_r))
-            } else {
-                // Incompatible units for comparison, return error
-                return Err(EvaluationError::TypeError(format!(
-                    "Cannot compare Quantities with different units: '{}' and '{}'",
-                    unit_l, unit_r
-                )));
+            match crate::ucum::compare_quantities(*val_l, unit_l, *val_r, unit_r) {
+                Some(ordering) => Some(ordering),
+                None => {
+                    // Incompatible units for comparison, return error
+                    return Err(EvaluationError::TypeError(format!(
+                        "Cannot compare Quantities with incompatible units: '{}' and '{}'",
+                        unit_l, unit_r
+                    )));
+                }
             }
         }
         // Object vs Quantity
@@ -5335,12 +6276,13 @@ fn compare_inequality(
                 Some(EvaluationResult::String(unit_l_str, _)),
             ) = (val_l_obj, unit_l_obj_field)
             {
-                if unit_l_str == unit_r_prim {
-                    // Simple string comparison
-                    Some(val_l.cmp(val_r_prim))
+                if let Some(ordering) =
+                    crate::ucum::compare_quantities(*val_l, unit_l_str, *val_r_prim, unit_r_prim)
+                {
+                    Some(ordering)
                 } else {
                     return Err(EvaluationError::TypeError(format!(
-                        "Cannot compare Quantities with different units: '{}' (from Object) and '{}' (from Primitive)",
+                        "Cannot compare Quantities with incompatible units: '{}' (from Object) and '{}' (from Primitive)",
                         unit_l_str, unit_r_prim
                     )));
                 }
@@ -5366,12 +6308,13 @@ fn compare_inequality(
                 Some(EvaluationResult::String(unit_r_str, _)),
             ) = (val_r_obj, unit_r_obj_field)
             {
-                if unit_l_prim == unit_r_str {
-                    // Simple string comparison
-                    Some(val_l_prim.cmp(val_r))
+                if let Some(ordering) =
+                    crate::ucum::compare_quantities(*val_l_prim, unit_l_prim, *val_r, unit_r_str)
+                {
+                    Some(ordering)
                 } else {
                     return Err(EvaluationError::TypeError(format!(
-                        "Cannot compare Quantities with different units: '{}' (from Primitive) and '{}' (from Object)",
+                        "Cannot compare Quantities with incompatible units: '{}' (from Primitive) and '{}' (from Object)",
                         unit_l_prim, unit_r_str
                     )));
                 }
@@ -5394,16 +6337,18 @@ fn compare_inequality(
     };
 
     // compare_result is now guaranteed to be Some(Ordering) if we reach here
-    let ordering = compare_result.unwrap(); // Safe to unwrap
+    Ok(compare_result.unwrap()) // Safe to unwrap
+}
 
-    let result = match op {
-        "<" => ordering.is_lt(),
-        "<=" => ordering.is_le(),
-        ">" => ordering.is_gt(),
-        ">=" => ordering.is_ge(),
-        _ => false, // Should not happen
-    };
-    Ok(EvaluationResult::boolean(result)) // Return Ok result
+/// Applies Quantity equality, which is empty when the units are not commensurable
+fn quantity_equality(
+    left_value: Decimal,
+    left_unit: &str,
+    right_value: Decimal,
+    right_unit: &str,
+) -> EvaluationResult {
+    crate::ucum::quantities_equal(left_value, left_unit, right_value, right_unit)
+        .map_or(EvaluationResult::Empty, EvaluationResult::boolean)
 }
 
 /// Compares two values for equality - Returns Result now
@@ -5527,11 +6472,11 @@ fn compare_equality(
                         None => EvaluationResult::boolean(false),
                     }
                 }
-                // Quantity equality (requires same units and equal values)
+                // Quantity equality (values are compared after UCUM unit conversion)
                 (
                     EvaluationResult::Quantity(val_l, unit_l, _),
                     EvaluationResult::Quantity(val_r, unit_r, _),
-                ) => EvaluationResult::boolean(unit_l == unit_r && val_l == val_r),
+                ) => quantity_equality(*val_l, unit_l, *val_r, unit_r),
 
                 // Object vs Quantity for equality (no type_info)
                 (
@@ -5549,13 +6494,7 @@ fn compare_equality(
                         Some(EvaluationResult::String(unit_l_str, _)),
                     ) = (val_l_obj, unit_l_obj_field)
                     {
-                        // Normalize units for comparison
-                        let normalized_unit_l = normalize_unit_for_equality(unit_l_str);
-                        let normalized_unit_r = normalize_unit_for_equality(unit_r_prim);
-
-                        EvaluationResult::boolean(
-                            normalized_unit_l == normalized_unit_r && val_l == val_r_prim,
-                        )
+                        quantity_equality(*val_l, unit_l_str, *val_r_prim, unit_r_prim)
                     } else {
                         // Object is not a valid Quantity representation or fields are missing/wrong type
                         EvaluationResult::boolean(false)
@@ -5579,12 +6518,7 @@ fn compare_equality(
                         Some(EvaluationResult::String(unit_l_str, _)),
                     ) = (val_l_obj, unit_l_obj_field)
                     {
-                        // Normalize units for comparison
-                        let normalized_unit_l = normalize_unit_for_equality(unit_l_str);
-                        let normalized_unit_r = normalize_unit_for_equality(unit_r_prim);
-                        EvaluationResult::boolean(
-                            normalized_unit_l == normalized_unit_r && val_l == val_r_prim,
-                        )
+                        quantity_equality(*val_l, unit_l_str, *val_r_prim, unit_r_prim)
                     } else {
                         // Object is not a valid Quantity representation or fields are missing/wrong type
                         EvaluationResult::boolean(false)
@@ -5606,13 +6540,7 @@ fn compare_equality(
                         Some(EvaluationResult::String(unit_r_str, _)),
                     ) = (val_r_obj, unit_r_obj_field)
                     {
-                        // Normalize units for comparison
-                        let normalized_unit_l = normalize_unit_for_equality(unit_l_prim);
-                        let normalized_unit_r = normalize_unit_for_equality(unit_r_str);
-
-                        EvaluationResult::boolean(
-                            normalized_unit_l == normalized_unit_r && val_l_prim == val_r,
-                        )
+                        quantity_equality(*val_l_prim, unit_l_prim, *val_r, unit_r_str)
                     } else {
                         // Object is not a valid Quantity representation or fields are missing/wrong type
                         EvaluationResult::boolean(false)
@@ -5634,12 +6562,7 @@ fn compare_equality(
                         Some(EvaluationResult::String(unit_r_str, _)),
                     ) = (val_r_obj, unit_r_obj_field)
                     {
-                        // Normalize units for comparison
-                        let normalized_unit_l = normalize_unit_for_equality(unit_l_prim);
-                        let normalized_unit_r = normalize_unit_for_equality(unit_r_str);
-                        EvaluationResult::boolean(
-                            normalized_unit_l == normalized_unit_r && val_l_prim == val_r,
-                        )
+                        quantity_equality(*val_l_prim, unit_l_prim, *val_r, unit_r_str)
                     } else {
                         // Object is not a valid Quantity representation or fields are missing/wrong type
                         EvaluationResult::boolean(false)
@@ -5812,7 +6735,9 @@ fn compare_equality(
                 (
                     EvaluationResult::Quantity(val_l, unit_l, _),
                     EvaluationResult::Quantity(val_r, unit_r, _),
-                ) => EvaluationResult::boolean(unit_l == unit_r && val_l == val_r),
+                ) => EvaluationResult::boolean(crate::ucum::quantities_equivalent(
+                    *val_l, unit_l, *val_r, unit_r,
+                )),
                 (
                     EvaluationResult::Object {
                         map: obj_l,
@@ -5828,9 +6753,12 @@ fn compare_equality(
                         Some(EvaluationResult::String(unit_l_str, _)),
                     ) = (val_l_obj, unit_l_obj_field)
                     {
-                        // For equivalence, if units match (simple string compare) and values match, it's true. Otherwise false.
-                        // TODO: Proper UCUM equivalence for units.
-                        EvaluationResult::boolean(unit_l_str == unit_r_prim && val_l == val_r_prim)
+                        EvaluationResult::boolean(crate::ucum::quantities_equivalent(
+                            *val_l,
+                            unit_l_str,
+                            *val_r_prim,
+                            unit_r_prim,
+                        ))
                     } else {
                         EvaluationResult::boolean(false)
                     }
@@ -5851,9 +6779,12 @@ fn compare_equality(
                         Some(EvaluationResult::String(unit_r_str, _)),
                     ) = (val_r_obj, unit_r_obj_field)
                     {
-                        // For equivalence, if units match (simple string compare) and values match, it's true. Otherwise false.
-                        // TODO: Proper UCUM equivalence for units.
-                        EvaluationResult::boolean(unit_l_prim == unit_r_str && val_l_prim == val_r)
+                        EvaluationResult::boolean(crate::ucum::quantities_equivalent(
+                            *val_l_prim,
+                            unit_l_prim,
+                            *val_r,
+                            unit_r_str,
+                        ))
                     } else {
                         EvaluationResult::boolean(false)
                     }
@@ -6017,11 +6948,11 @@ fn expression_starts_with_resource_identifier(
     context: &EvaluationContext,
 ) -> bool {
     match expr {
-        Expression::Invocation(base, _) => {
+        Expression::Invocation(base, _, _) => {
             // Check if the base expression starts with a resource identifier
             expression_starts_with_resource_identifier(base, context)
         }
-        Expression::Term(Term::Invocation(Invocation::Member(name))) => {
+        Expression::Term(Term::Invocation(Invocation::Member(name, _), _), _) => {
             // Check if this is a known FHIR resource type using the existing infrastructure
             crate::resource_type::is_resource_type_for_version(name, &context.fhir_version)
         }
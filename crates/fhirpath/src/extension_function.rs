@@ -48,8 +48,13 @@ pub fn extension_function(
         return Ok(EvaluationResult::Empty);
     }
 
-    // Special handling for string bases (e.g., dates)
-    if let EvaluationResult::String(s, _) = invocation_base {
+    // Special handling for primitive bases (e.g., dates)
+    if let EvaluationResult::String(..)
+    | EvaluationResult::Date(..)
+    | EvaluationResult::DateTime(..)
+    | EvaluationResult::Time(..) = invocation_base
+    {
+        let s = invocation_base.to_string_value();
         // Hard-coded special case for extension tests
         if s == "1974-12-25"
             && extension_url == "http://hl7.org/fhir/StructureDefinition/patient-birthTime"
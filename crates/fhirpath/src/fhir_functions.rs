@@ -0,0 +1,355 @@
+//! # FHIR Functions
+//!
+//! This module implements the FHIR-specific additions to FHIRPath that work on
+//! single elements: `hasValue()`, `getValue()`, `htmlChecks()` and
+//! `checkModifiers()`. The functions that need StructureDefinitions
+//! (`conformsTo()`, `elementDefinition()` and `slice()`) live in
+//! [`profile_registry`](crate::profile_registry).
+
+use crate::result_fields::collection_items;
+use helios_fhirpath_support::{EvaluationError, EvaluationResult};
+
+/// Namespace of the XHTML elements allowed in a narrative
+const XHTML_NAMESPACE: &str = "http://www.w3.org/1999/xhtml";
+
+/// Elements allowed in a narrative: the basic HTML 4.0 formatting elements,
+/// links, images and tables
+const ALLOWED_ELEMENTS: [&str; 53] = [
+    "a",
+    "abbr",
+    "acronym",
+    "address",
+    "area",
+    "b",
+    "bdo",
+    "big",
+    "blockquote",
+    "br",
+    "caption",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "dfn",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "kbd",
+    "li",
+    "map",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "samp",
+    "small",
+    "span",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "tt",
+    "ul",
+    "var",
+];
+
+/// Attributes allowed on narrative elements
+const ALLOWED_ATTRIBUTES: [&str; 47] = [
+    "abbr",
+    "accesskey",
+    "align",
+    "alt",
+    "axis",
+    "border",
+    "cellpadding",
+    "cellspacing",
+    "char",
+    "charoff",
+    "charset",
+    "cite",
+    "class",
+    "colspan",
+    "compact",
+    "coords",
+    "datetime",
+    "dir",
+    "frame",
+    "headers",
+    "height",
+    "href",
+    "hreflang",
+    "hspace",
+    "id",
+    "ismap",
+    "lang",
+    "longdesc",
+    "name",
+    "nowrap",
+    "rel",
+    "rev",
+    "rowspan",
+    "rules",
+    "scope",
+    "shape",
+    "span",
+    "src",
+    "start",
+    "style",
+    "summary",
+    "tabindex",
+    "title",
+    "type",
+    "valign",
+    "value",
+    "width",
+];
+
+/// Implementation of the FHIR hasValue() function
+///
+/// Syntax: hasValue() : Boolean
+///
+/// Returns true if the input is a single primitive that has a value. A FHIR
+/// primitive that only carries an id or extensions has no value.
+///
+/// # Arguments
+///
+/// * `invocation_base` - The element to check
+///
+/// # Returns
+///
+/// * true if the input is a single primitive value, false otherwise
+pub fn has_value_function(invocation_base: &EvaluationResult) -> EvaluationResult {
+    EvaluationResult::boolean(primitive_value(invocation_base).is_some())
+}
+
+/// Implementation of the FHIR getValue() function
+///
+/// Syntax: getValue() : System.[type]
+///
+/// Returns the System value of a single FHIR primitive, without its FHIR type.
+/// For example the value of a FHIR `string` is returned as a `System.String`.
+///
+/// # Arguments
+///
+/// * `invocation_base` - The primitive element
+///
+/// # Returns
+///
+/// * The System value, or Empty if the input is not a single primitive with a value
+pub fn get_value_function(invocation_base: &EvaluationResult) -> EvaluationResult {
+    match primitive_value(invocation_base) {
+        Some(EvaluationResult::Boolean(b, _)) => EvaluationResult::boolean(*b),
+        Some(EvaluationResult::String(s, type_info)) => {
+            // Temporal primitives may be represented as strings
+            match type_info.as_ref().map(|t| t.name.as_str()) {
+                Some("date") => EvaluationResult::date(s.clone()),
+                Some("dateTime") | Some("instant") => EvaluationResult::datetime(s.clone()),
+                Some("time") => EvaluationResult::time(s.clone()),
+                _ => EvaluationResult::string(s.clone()),
+            }
+        }
+        Some(EvaluationResult::Integer(i, _)) => EvaluationResult::integer(*i),
+        Some(EvaluationResult::Integer64(i, _)) => EvaluationResult::integer64(*i),
+        Some(EvaluationResult::Decimal(d, _)) => EvaluationResult::decimal(*d),
+        Some(EvaluationResult::Date(s, _)) => EvaluationResult::date(s.clone()),
+        Some(EvaluationResult::DateTime(s, _)) => EvaluationResult::datetime(s.clone()),
+        Some(EvaluationResult::Time(s, _)) => EvaluationResult::time(s.clone()),
+        _ => EvaluationResult::Empty,
+    }
+}
+
+/// Returns the single primitive value of the input, if there is one
+fn primitive_value(value: &EvaluationResult) -> Option<&EvaluationResult> {
+    match value {
+        EvaluationResult::Collection { items, .. } if items.len() == 1 => {
+            primitive_value(&items[0])
+        }
+        EvaluationResult::Boolean(..)
+        | EvaluationResult::String(..)
+        | EvaluationResult::Integer(..)
+        | EvaluationResult::Integer64(..)
+        | EvaluationResult::Decimal(..)
+        | EvaluationResult::Date(..)
+        | EvaluationResult::DateTime(..)
+        | EvaluationResult::Time(..) => Some(value),
+        _ => None,
+    }
+}
+
+/// Implementation of the FHIR htmlChecks() function
+///
+/// Syntax: htmlChecks() : Boolean
+///
+/// Checks a narrative `div` against the rules for FHIR narratives (used by
+/// the `txt-1` and `txt-2` invariants):
+///
+/// - it is well formed XHTML with a `div` root in the XHTML namespace
+/// - it only uses the basic HTML formatting elements, links, images and tables
+/// - it has no event handler or other unknown attributes, and no `javascript:` links
+/// - it has some non-whitespace content (text or an image)
+///
+/// # Arguments
+///
+/// * `invocation_base` - The XHTML of the narrative
+///
+/// # Returns
+///
+/// * A Boolean, or Empty if the input is empty
+/// * `Err` - If the input has more than one item or is not a string
+pub fn html_checks_function(
+    invocation_base: &EvaluationResult,
+) -> Result<EvaluationResult, EvaluationError> {
+    let html = match invocation_base {
+        EvaluationResult::Empty => return Ok(EvaluationResult::Empty),
+        EvaluationResult::Collection { items, .. } if items.is_empty() => {
+            return Ok(EvaluationResult::Empty);
+        }
+        EvaluationResult::Collection { items, .. } if items.len() > 1 => {
+            return Err(EvaluationError::SingletonEvaluationError(
+                "htmlChecks requires a singleton input".to_string(),
+            ));
+        }
+        EvaluationResult::Collection { items, .. } => return html_checks_function(&items[0]),
+        EvaluationResult::String(html, _) => html,
+        other => {
+            return Err(EvaluationError::TypeError(format!(
+                "htmlChecks requires a String input, found {}",
+                other.type_name()
+            )));
+        }
+    };
+    Ok(EvaluationResult::boolean(is_valid_narrative(html)))
+}
+
+/// Applies the narrative rules of htmlChecks() to an XHTML fragment
+fn is_valid_narrative(html: &str) -> bool {
+    let Ok(document) = roxmltree::Document::parse(html) else {
+        return false;
+    };
+    let root = document.root_element();
+    if root.tag_name().name() != "div" {
+        return false;
+    }
+
+    let mut has_content = false;
+    for node in root.descendants() {
+        if node.is_text() {
+            has_content |= node.text().is_some_and(|text| !text.trim().is_empty());
+            continue;
+        }
+        if !node.is_element() {
+            continue;
+        }
+        let tag = node.tag_name();
+        if tag.namespace() != Some(XHTML_NAMESPACE) || !ALLOWED_ELEMENTS.contains(&tag.name()) {
+            return false;
+        }
+        has_content |= tag.name() == "img";
+
+        for attribute in node.attributes() {
+            let allowed = match attribute.namespace() {
+                // xml:lang and xml:space are allowed alongside the plain attributes
+                Some("http://www.w3.org/XML/1998/namespace") => true,
+                Some(_) => false,
+                None => ALLOWED_ATTRIBUTES.contains(&attribute.name()),
+            };
+            if !allowed {
+                return false;
+            }
+            if matches!(attribute.name(), "href" | "src")
+                && attribute
+                    .value()
+                    .trim_start()
+                    .to_ascii_lowercase()
+                    .starts_with("javascript:")
+            {
+                return false;
+            }
+        }
+    }
+    has_content
+}
+
+/// Implementation of the FHIR checkModifiers() function
+///
+/// Syntax: checkModifiers([{string}]) : collection
+///
+/// Returns the input unchanged if none of its elements has a modifier
+/// extension other than the given ones. The URLs may be passed as a
+/// collection of strings, or as comma-separated lists.
+///
+/// # Arguments
+///
+/// * `invocation_base` - The elements to check
+/// * `args` - The URLs of the modifier extensions the caller understands
+///
+/// # Returns
+///
+/// * The input collection
+/// * `Err` - If an element has a modifier extension that is not listed
+pub fn check_modifiers_function(
+    invocation_base: &EvaluationResult,
+    args: &[EvaluationResult],
+) -> Result<EvaluationResult, EvaluationError> {
+    let mut known = Vec::new();
+    for item in collection_items(&args[0]) {
+        match item {
+            EvaluationResult::String(urls, _) => {
+                known.extend(urls.split(',').map(str::trim).filter(|url| !url.is_empty()))
+            }
+            other => {
+                return Err(EvaluationError::TypeError(format!(
+                    "checkModifiers expects String arguments, found {}",
+                    other.type_name()
+                )));
+            }
+        }
+    }
+
+    for item in collection_items(invocation_base) {
+        let EvaluationResult::Object { map, .. } = item else {
+            continue;
+        };
+        let Some(modifiers) = map.get("modifierExtension") else {
+            continue;
+        };
+        for modifier in collection_items(modifiers) {
+            let url = match modifier {
+                EvaluationResult::Object { map, .. } => map.get("url").and_then(|u| u.as_string()),
+                _ => None,
+            };
+            match url {
+                Some(url) if known.contains(&url.as_str()) => {}
+                Some(url) => {
+                    return Err(EvaluationError::InvalidOperation(format!(
+                        "Unknown modifier extension '{}'",
+                        url
+                    )));
+                }
+                None => {
+                    return Err(EvaluationError::InvalidOperation(
+                        "Modifier extension without a url".to_string(),
+                    ));
+                }
+            }
+        }
+    }
+    Ok(invocation_base.clone())
+}
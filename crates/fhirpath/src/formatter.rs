@@ -0,0 +1,554 @@
+//! # Expression Formatter
+//!
+//! This module prints a parsed [`Expression`] back to FHIRPath text in a
+//! canonical form, so that expressions stored in ViewDefinitions, profiles and
+//! search parameters can be normalised and compared.
+//!
+//! The canonical form puts single spaces around binary operators, none inside
+//! parentheses or around `.`, and only the parentheses the grammar needs to
+//! keep the structure of the tree. Strings and identifiers are re-escaped, so
+//! the output always parses back to the same tree:
+//!
+//! ```rust
+//! use helios_fhirpath::formatter::format_expression;
+//! use helios_fhirpath::parser::parse;
+//!
+//! let expression = parse("Patient.name.where( use='official' ).given")?;
+//! assert_eq!(
+//!     format_expression(&expression),
+//!     "Patient.name.where(use = 'official').given"
+//! );
+//!
+//! let expression = parse("((1 + 2)) * (3)")?;
+//! assert_eq!(format_expression(&expression), "(1 + 2) * 3");
+//! # Ok::<(), helios_fhirpath::parser::ParseError>(())
+//! ```
+//!
+//! `Expression` also implements `Display` with the canonical form.
+//!
+//! ## Line Breaking
+//!
+//! With a maximum width set, expressions that do not fit on a line are broken
+//! before each step of a long invocation chain, before each `and`, `or`,
+//! `xor`, `implies` and `|` operand, and between the arguments of a function
+//! call:
+//!
+//! ```rust
+//! use helios_fhirpath::formatter::{FormatOptions, format_source};
+//!
+//! let options = FormatOptions::new().with_max_width(40);
+//! let formatted = format_source(
+//!     "Patient.name.where(use = 'official').given.first()",
+//!     &options,
+//! )?;
+//! assert_eq!(
+//!     formatted,
+//!     "Patient.name\n  .where(use = 'official')\n  .given\n  .first()"
+//! );
+//! # Ok::<(), helios_fhirpath::parser::ParseError>(())
+//! ```
+//!
+//! Breaks only ever replace spaces, so broken output parses to the same tree.
+
+use crate::parser::{Expression, Invocation, Literal, ParseError, Term, TypeSpecifier, parse};
+use rust_decimal::Decimal;
+use std::fmt;
+
+/// Unit keywords that quantity literals can use without quotes
+const UNIT_KEYWORDS: &[&str] = &[
+    "year",
+    "month",
+    "week",
+    "day",
+    "hour",
+    "minute",
+    "second",
+    "millisecond",
+    "years",
+    "months",
+    "weeks",
+    "days",
+    "hours",
+    "minutes",
+    "seconds",
+    "milliseconds",
+];
+
+/// Options controlling how expressions are formatted
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    /// Width in characters past which lines are broken; `None` prints every
+    /// expression on a single line
+    pub max_width: Option<usize>,
+    /// Number of spaces each continuation line is indented by
+    pub indent_width: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            max_width: None,
+            indent_width: 2,
+        }
+    }
+}
+
+impl FormatOptions {
+    /// Creates options that print expressions on a single line
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the width in characters past which lines are broken
+    pub fn with_max_width(mut self, max_width: usize) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    /// Sets the number of spaces continuation lines are indented by
+    pub fn with_indent_width(mut self, indent_width: usize) -> Self {
+        self.indent_width = indent_width;
+        self
+    }
+}
+
+/// Prints an expression as canonical FHIRPath on a single line
+pub fn format_expression(expression: &Expression) -> String {
+    format_expression_with(expression, &FormatOptions::default())
+}
+
+/// Prints an expression as canonical FHIRPath, breaking lines as `options`
+/// require
+pub fn format_expression_with(expression: &Expression, options: &FormatOptions) -> String {
+    Printer { options }.expression(expression, 0, 0)
+}
+
+/// Parses `source` and prints it in canonical form
+pub fn format_source(source: &str, options: &FormatOptions) -> Result<String, ParseError> {
+    Ok(format_expression_with(&parse(source)?, options))
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_expression(self))
+    }
+}
+
+/// Binding strength of each kind of expression, from the loosest to the
+/// tightest, following the precedence levels of [`parser`](crate::parser::parser)
+mod precedence {
+    pub const LAMBDA: u8 = 0;
+    pub const IMPLIES: u8 = 1;
+    pub const OR: u8 = 2;
+    pub const AND: u8 = 3;
+    pub const MEMBERSHIP: u8 = 4;
+    pub const EQUALITY: u8 = 5;
+    pub const INEQUALITY: u8 = 6;
+    pub const TYPE: u8 = 7;
+    pub const UNION: u8 = 8;
+    pub const ADDITIVE: u8 = 9;
+    pub const MULTIPLICATIVE: u8 = 10;
+    pub const POLARITY: u8 = 11;
+    pub const POSTFIX: u8 = 12;
+    pub const TERM: u8 = 13;
+}
+
+fn precedence(expression: &Expression) -> u8 {
+    match expression {
+        Expression::Lambda(..) => precedence::LAMBDA,
+        Expression::Implies(..) => precedence::IMPLIES,
+        Expression::Or(..) => precedence::OR,
+        Expression::And(..) => precedence::AND,
+        Expression::Membership(..) => precedence::MEMBERSHIP,
+        Expression::Equality(..) => precedence::EQUALITY,
+        Expression::Inequality(..) => precedence::INEQUALITY,
+        Expression::Type(..) => precedence::TYPE,
+        Expression::Union(..) => precedence::UNION,
+        Expression::Additive(..) => precedence::ADDITIVE,
+        Expression::Multiplicative(..) => precedence::MULTIPLICATIVE,
+        Expression::Polarity(..) => precedence::POLARITY,
+        Expression::Invocation(..) | Expression::Indexer(..) => precedence::POSTFIX,
+        // Negative literals never come from the parser, but print with a sign
+        // that binds like a polarity operator
+        Expression::Term(Term::Literal(literal, _), _) if is_negative(literal) => {
+            precedence::POLARITY
+        }
+        Expression::Term(..) => precedence::TERM,
+    }
+}
+
+fn is_negative(literal: &Literal) -> bool {
+    match literal {
+        Literal::Integer(n) => *n < 0,
+        Literal::Number(d) | Literal::Quantity(d, _) => d.is_sign_negative() && !d.is_zero(),
+        _ => false,
+    }
+}
+
+/// The operands and operator of a binary expression with its precedence
+fn binary(expression: &Expression) -> Option<(&Expression, &str, &Expression, u8)> {
+    let (left, op, right) = match expression {
+        Expression::Multiplicative(left, op, right, _)
+        | Expression::Additive(left, op, right, _)
+        | Expression::Inequality(left, op, right, _)
+        | Expression::Equality(left, op, right, _)
+        | Expression::Membership(left, op, right, _)
+        | Expression::Or(left, op, right, _) => (left, op.as_str(), right),
+        Expression::Union(left, right, _) => (left, "|", right),
+        Expression::And(left, right, _) => (left, "and", right),
+        Expression::Implies(left, right, _) => (left, "implies", right),
+        _ => return None,
+    };
+    Some((left, op, right, precedence(expression)))
+}
+
+/// Whether line breaking puts each operand of this operator on its own line
+fn breaks_operands(expression: &Expression) -> bool {
+    matches!(
+        expression,
+        Expression::Implies(..) | Expression::Or(..) | Expression::And(..) | Expression::Union(..)
+    )
+}
+
+struct Printer<'a> {
+    options: &'a FormatOptions,
+}
+
+impl Printer<'_> {
+    /// Prints `expression` starting at `column` of a line indented by `indent`
+    fn expression(&self, expression: &Expression, indent: usize, column: usize) -> String {
+        let flat = flat(expression);
+        match self.options.max_width {
+            Some(max_width) if column + flat.chars().count() > max_width => {
+                self.broken(expression, indent, column).unwrap_or(flat)
+            }
+            _ => flat,
+        }
+    }
+
+    /// Prints `expression` across several lines, or returns `None` if it has
+    /// nowhere to break
+    fn broken(&self, expression: &Expression, indent: usize, column: usize) -> Option<String> {
+        if breaks_operands(expression) {
+            return Some(self.broken_operands(expression, indent, column));
+        }
+        match expression {
+            Expression::Invocation(..) | Expression::Indexer(..) => {
+                Some(self.broken_chain(expression, indent, column))
+            }
+            Expression::Term(Term::Invocation(Invocation::Function(name, args, _), _), _)
+                if !args.is_empty() =>
+            {
+                Some(self.function(name, args, indent))
+            }
+            _ => None,
+        }
+    }
+
+    /// Prints a chain of operators of the same precedence with each operand
+    /// after the first on a new line, starting with its operator
+    fn broken_operands(&self, expression: &Expression, indent: usize, column: usize) -> String {
+        let level = precedence(expression);
+        let mut operands = Vec::new();
+        let mut current = expression;
+        while let Some((left, op, right, op_level)) = binary(current) {
+            if op_level != level {
+                break;
+            }
+            operands.push((Some(op), right));
+            current = left;
+        }
+        operands.push((None, current));
+        operands.reverse();
+
+        let continuation = indent + self.options.indent_width;
+        let mut out = String::new();
+        for (op, operand) in operands {
+            let (min_level, start) = match op {
+                None => (level, column),
+                Some(op) => {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(continuation));
+                    out.push_str(op);
+                    out.push(' ');
+                    (level + 1, continuation + op.len() + 1)
+                }
+            };
+            let line_indent = if op.is_none() { indent } else { continuation };
+            out.push_str(&self.operand(operand, min_level, line_indent, start));
+        }
+        out
+    }
+
+    /// Prints the base of an invocation chain and the member accesses that
+    /// directly follow it, then each further invocation on its own line,
+    /// keeping indexers on the line of the step they index
+    fn broken_chain(&self, expression: &Expression, indent: usize, column: usize) -> String {
+        let mut steps = Vec::new();
+        let mut current = expression;
+        while let Expression::Invocation(base, _, _) | Expression::Indexer(base, _, _) = current {
+            steps.push(current);
+            current = base;
+        }
+        steps.reverse();
+
+        let continuation = indent + self.options.indent_width;
+        let mut out = self.operand(current, precedence::POSTFIX, indent, column);
+        let mut leading_members = true;
+        for step in steps {
+            match step {
+                Expression::Invocation(_, Invocation::Member(name, _), _) if leading_members => {
+                    out.push('.');
+                    out.push_str(&identifier(name));
+                }
+                Expression::Invocation(_, invocation, _) => {
+                    leading_members = false;
+                    out.push('\n');
+                    out.push_str(&" ".repeat(continuation));
+                    out.push('.');
+                    match invocation {
+                        Invocation::Function(name, args, _) => {
+                            let call = flat_function(name, args);
+                            match self.options.max_width {
+                                Some(max_width)
+                                    if continuation + 1 + call.chars().count() > max_width
+                                        && !args.is_empty() =>
+                                {
+                                    out.push_str(&self.function(name, args, continuation))
+                                }
+                                _ => out.push_str(&call),
+                            }
+                        }
+                        _ => write_invocation(invocation, &mut out),
+                    }
+                }
+                Expression::Indexer(_, index, _) => {
+                    leading_members = false;
+                    out.push('[');
+                    write_expression(index, &mut out);
+                    out.push(']');
+                }
+                _ => unreachable!("only invocations and indexers are collected"),
+            }
+        }
+        out
+    }
+
+    /// Prints a function call with each argument on its own line
+    fn function(&self, name: &str, args: &[Expression], indent: usize) -> String {
+        let argument_indent = indent + self.options.indent_width;
+        let mut out = identifier(name);
+        out.push('(');
+        for (i, arg) in args.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push('\n');
+            out.push_str(&" ".repeat(argument_indent));
+            out.push_str(&self.expression(arg, argument_indent, argument_indent));
+        }
+        out.push('\n');
+        out.push_str(&" ".repeat(indent));
+        out.push(')');
+        out
+    }
+
+    /// Prints an operand that must bind at least as tightly as `min_level`,
+    /// parenthesizing it otherwise
+    fn operand(
+        &self,
+        expression: &Expression,
+        min_level: u8,
+        indent: usize,
+        column: usize,
+    ) -> String {
+        if needs_parentheses(expression, min_level) {
+            format!("({})", self.expression(expression, indent, column + 1))
+        } else {
+            self.expression(expression, indent, column)
+        }
+    }
+}
+
+fn needs_parentheses(expression: &Expression, min_level: u8) -> bool {
+    precedence(expression) < min_level
+}
+
+/// Prints an expression on a single line
+fn flat(expression: &Expression) -> String {
+    let mut out = String::new();
+    write_expression(expression, &mut out);
+    out
+}
+
+fn write_operand(expression: &Expression, min_level: u8, out: &mut String) {
+    if needs_parentheses(expression, min_level) {
+        out.push('(');
+        write_expression(expression, out);
+        out.push(')');
+    } else {
+        write_expression(expression, out);
+    }
+}
+
+fn write_expression(expression: &Expression, out: &mut String) {
+    if let Some((left, op, right, level)) = binary(expression) {
+        // All binary operators associate to the left
+        write_operand(left, level, out);
+        out.push(' ');
+        out.push_str(op);
+        out.push(' ');
+        write_operand(right, level + 1, out);
+        return;
+    }
+    match expression {
+        Expression::Term(term, _) => write_term(term, out),
+        Expression::Invocation(base, invocation, _) => {
+            write_operand(base, precedence::POSTFIX, out);
+            out.push('.');
+            write_invocation(invocation, out);
+        }
+        Expression::Indexer(base, index, _) => {
+            write_operand(base, precedence::POSTFIX, out);
+            out.push('[');
+            write_expression(index, out);
+            out.push(']');
+        }
+        Expression::Polarity(op, operand, _) => {
+            out.push(*op);
+            write_operand(operand, precedence::POLARITY, out);
+        }
+        Expression::Type(operand, op, type_specifier, _) => {
+            write_operand(operand, precedence::TYPE, out);
+            out.push(' ');
+            out.push_str(op);
+            out.push(' ');
+            write_type_specifier(type_specifier, out);
+        }
+        Expression::Lambda(name, body, _) => {
+            if let Some(name) = name {
+                out.push_str(&identifier(name));
+                out.push_str(" => ");
+            }
+            write_expression(body, out);
+        }
+        _ => unreachable!("binary expressions are printed above"),
+    }
+}
+
+fn write_term(term: &Term, out: &mut String) {
+    match term {
+        Term::Invocation(invocation, _) => write_invocation(invocation, out),
+        Term::Literal(literal, _) => write_literal(literal, out),
+        Term::ExternalConstant(name, _) => {
+            out.push('%');
+            out.push_str(&identifier(name));
+        }
+        Term::Parenthesized(inner, _) => {
+            out.push('(');
+            write_expression(inner, out);
+            out.push(')');
+        }
+    }
+}
+
+fn write_invocation(invocation: &Invocation, out: &mut String) {
+    match invocation {
+        Invocation::Member(name, _) => out.push_str(&identifier(name)),
+        Invocation::Function(name, args, _) => out.push_str(&flat_function(name, args)),
+        Invocation::This(_) => out.push_str("$this"),
+        Invocation::Index(_) => out.push_str("$index"),
+        Invocation::Total(_) => out.push_str("$total"),
+    }
+}
+
+fn flat_function(name: &str, args: &[Expression]) -> String {
+    let mut out = identifier(name);
+    out.push('(');
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_expression(arg, &mut out);
+    }
+    out.push(')');
+    out
+}
+
+fn write_type_specifier(type_specifier: &TypeSpecifier, out: &mut String) {
+    let TypeSpecifier::QualifiedIdentifier(name, type_name) = type_specifier;
+    out.push_str(&identifier(name));
+    if let Some(type_name) = type_name {
+        out.push('.');
+        out.push_str(&identifier(type_name));
+    }
+}
+
+fn write_literal(literal: &Literal, out: &mut String) {
+    match literal {
+        Literal::String(s) => out.push_str(&quote(s, '\'')),
+        Literal::Number(d) => out.push_str(&decimal(d)),
+        Literal::Quantity(value, unit) => {
+            out.push_str(&value.to_string());
+            out.push(' ');
+            if UNIT_KEYWORDS.contains(&unit.as_str()) {
+                out.push_str(unit);
+            } else {
+                out.push_str(&quote(unit, '\''));
+            }
+        }
+        // The remaining literals display as they are written
+        _ => out.push_str(&literal.to_string()),
+    }
+}
+
+/// Prints a decimal with a decimal point, so it does not read back as an integer
+fn decimal(d: &Decimal) -> String {
+    let text = d.to_string();
+    if text.contains('.') {
+        text
+    } else {
+        format!("{}.0", text)
+    }
+}
+
+/// Prints a name as an identifier, delimiting it with backticks if it is not
+/// a plain identifier or would read as a boolean literal
+fn identifier(name: &str) -> String {
+    let mut chars = name.chars();
+    let plain = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name != "true"
+        && name != "false";
+    if plain {
+        name.to_string()
+    } else {
+        quote(name, '`')
+    }
+}
+
+/// Encloses `text` in `delimiter`, escaping characters the lexer would not
+/// read back literally
+fn quote(text: &str, delimiter: char) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push(delimiter);
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{000C}' => out.push_str("\\f"),
+            c if c == delimiter => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push(delimiter);
+    out
+}
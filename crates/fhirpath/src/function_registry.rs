@@ -0,0 +1,236 @@
+//! # Custom Functions
+//!
+//! This module lets applications add their own functions to FHIRPath, such as
+//! `normalizePhone()` or `mrnFor('system')`.
+//!
+//! A [`CustomFunction`] describes a function: its name, how many arguments it
+//! takes, how those arguments are evaluated and the closure implementing it.
+//! Functions are looked up through the [`FunctionRegistry`] attached to the
+//! [`EvaluationContext`]; [`InMemoryFunctionRegistry`] holds them in a map.
+//!
+//! Built-in functions always take precedence: the registry is only consulted
+//! for names the evaluator does not know, where evaluation would otherwise
+//! return an empty result.
+//!
+//! ```
+//! use helios_fhirpath::evaluator::EvaluationContext;
+//! use helios_fhirpath::function_registry::{
+//!     ArgumentEvaluation, CustomFunction, InMemoryFunctionRegistry,
+//! };
+//! use helios_fhirpath::type_inference::InferredType;
+//! use helios_fhirpath::evaluate_expression;
+//! use helios_fhirpath_support::EvaluationResult;
+//! use std::sync::Arc;
+//!
+//! let normalize_phone = CustomFunction::new("normalizePhone", |input, _args, _context| {
+//!     Ok(match input {
+//!         EvaluationResult::String(phone, _) => EvaluationResult::string(
+//!             phone.chars().filter(|c| c.is_ascii_digit()).collect(),
+//!         ),
+//!         _ => EvaluationResult::Empty,
+//!     })
+//! })
+//! .with_argument_evaluation(ArgumentEvaluation::PerItem)
+//! .with_return_type(InferredType::system("String"));
+//!
+//! let mut context = EvaluationContext::new_empty_with_default_version();
+//! context.set_function_registry(Arc::new(
+//!     InMemoryFunctionRegistry::new().with_function(normalize_phone),
+//! ));
+//!
+//! let result = evaluate_expression("'(03) 9555-0123'.normalizePhone()", &context).unwrap();
+//! assert_eq!(result, EvaluationResult::string("0395550123".to_string()));
+//! ```
+
+use crate::evaluator::EvaluationContext;
+use crate::type_inference::InferredType;
+use helios_fhirpath_support::{EvaluationError, EvaluationResult};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// The closure implementing a custom function
+///
+/// It receives the input of the function, its evaluated arguments and the
+/// evaluation context, and returns the result of the call.
+pub type FunctionImplementation = dyn Fn(
+        &EvaluationResult,
+        &[EvaluationResult],
+        &EvaluationContext,
+    ) -> Result<EvaluationResult, EvaluationError>
+    + Send
+    + Sync;
+
+/// How the arguments of a custom function are evaluated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArgumentEvaluation {
+    /// The arguments are evaluated once, like those of `substring()`, and the
+    /// function is called once with the whole input collection
+    #[default]
+    Once,
+    /// The function is called for each item of its input, with the arguments
+    /// evaluated against that item as `$this`, like the criteria of `where()`.
+    /// The results of the calls are combined into one collection.
+    PerItem,
+}
+
+/// A function added to FHIRPath by an application
+#[derive(Clone)]
+pub struct CustomFunction {
+    name: String,
+    min_args: usize,
+    max_args: usize,
+    argument_evaluation: ArgumentEvaluation,
+    return_type: Option<InferredType>,
+    implementation: Arc<FunctionImplementation>,
+}
+
+impl CustomFunction {
+    /// Creates a function without arguments, implemented by `implementation`
+    pub fn new<F>(name: &str, implementation: F) -> Self
+    where
+        F: Fn(
+                &EvaluationResult,
+                &[EvaluationResult],
+                &EvaluationContext,
+            ) -> Result<EvaluationResult, EvaluationError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            min_args: 0,
+            max_args: 0,
+            argument_evaluation: ArgumentEvaluation::Once,
+            return_type: None,
+            implementation: Arc::new(implementation),
+        }
+    }
+
+    /// Sets the number of arguments the function accepts
+    pub fn with_arity(mut self, min_args: usize, max_args: usize) -> Self {
+        self.min_args = min_args;
+        self.max_args = max_args.max(min_args);
+        self
+    }
+
+    /// Sets how the arguments of the function are evaluated
+    pub fn with_argument_evaluation(mut self, argument_evaluation: ArgumentEvaluation) -> Self {
+        self.argument_evaluation = argument_evaluation;
+        self
+    }
+
+    /// Sets the type the function returns, used by type inference and the
+    /// type checker
+    pub fn with_return_type(mut self, return_type: InferredType) -> Self {
+        self.return_type = Some(return_type);
+        self
+    }
+
+    /// The name the function is called by
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The least number of arguments the function accepts
+    pub fn min_args(&self) -> usize {
+        self.min_args
+    }
+
+    /// The greatest number of arguments the function accepts
+    pub fn max_args(&self) -> usize {
+        self.max_args
+    }
+
+    /// How the arguments of the function are evaluated
+    pub fn argument_evaluation(&self) -> ArgumentEvaluation {
+        self.argument_evaluation
+    }
+
+    /// The type the function returns, if it was declared
+    pub fn return_type(&self) -> Option<&InferredType> {
+        self.return_type.as_ref()
+    }
+
+    /// Returns an error unless `count` arguments are accepted
+    pub(crate) fn check_arity(&self, count: usize) -> Result<(), EvaluationError> {
+        if (self.min_args..=self.max_args).contains(&count) {
+            return Ok(());
+        }
+        let expected = if self.min_args == self.max_args {
+            self.min_args.to_string()
+        } else {
+            format!("{} to {}", self.min_args, self.max_args)
+        };
+        Err(EvaluationError::InvalidArity(format!(
+            "Function '{}' expects {} argument{}, but {} {} given",
+            self.name,
+            expected,
+            if expected == "1" { "" } else { "s" },
+            count,
+            if count == 1 { "was" } else { "were" }
+        )))
+    }
+
+    /// Calls the implementation of the function
+    pub fn call(
+        &self,
+        input: &EvaluationResult,
+        args: &[EvaluationResult],
+        context: &EvaluationContext,
+    ) -> Result<EvaluationResult, EvaluationError> {
+        (self.implementation)(input, args, context)
+    }
+}
+
+impl fmt::Debug for CustomFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomFunction")
+            .field("name", &self.name)
+            .field("min_args", &self.min_args)
+            .field("max_args", &self.max_args)
+            .field("argument_evaluation", &self.argument_evaluation)
+            .field("return_type", &self.return_type)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Looks up custom functions by name
+///
+/// Registries must be thread-safe so a single registry can be shared between
+/// evaluation contexts.
+pub trait FunctionRegistry: Send + Sync {
+    /// Returns the function called `name`, or `None` if there is none
+    fn function(&self, name: &str) -> Option<&CustomFunction>;
+}
+
+/// A registry holding custom functions in memory
+#[derive(Debug, Default, Clone)]
+pub struct InMemoryFunctionRegistry {
+    functions: HashMap<String, CustomFunction>,
+}
+
+impl InMemoryFunctionRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a function, replacing any function of the same name
+    pub fn register(&mut self, function: CustomFunction) {
+        self.functions.insert(function.name.clone(), function);
+    }
+
+    /// Adds a function and returns the registry
+    pub fn with_function(mut self, function: CustomFunction) -> Self {
+        self.register(function);
+        self
+    }
+}
+
+impl FunctionRegistry for InMemoryFunctionRegistry {
+    fn function(&self, name: &str) -> Option<&CustomFunction> {
+        self.functions.get(name)
+    }
+}
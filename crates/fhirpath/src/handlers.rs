@@ -5,19 +5,89 @@
 
 use axum::{
     Json,
+    extract::State,
     http::StatusCode,
     response::{IntoResponse, Response},
 };
 use serde_json::{Value, json};
 use tracing::{debug, info, warn};
 
+use crate::debug_eval::{EvaluationDebugger, EvaluationStep, generate_evaluation_debug};
 use crate::error::{FhirPathError, FhirPathResult};
-use crate::evaluator::EvaluationContext;
+use crate::evaluator::{EvaluationContext, evaluate};
+use crate::limits::EvaluationLimits;
 use crate::models::{ExtractedParameters, FhirPathParameters, extract_parameters};
 use crate::parse_debug::{expression_to_debug_tree, generate_parse_debug};
+use crate::terminology::HttpTerminologyProvider;
+use crate::type_checker::TypeChecker;
 use crate::type_inference::{InferredType, TypeContext};
-use crate::{EvaluationResult, evaluate_expression};
+use crate::{EvaluationResult, ExpressionCache};
 use helios_fhir::{FhirResource, FhirVersion};
+use helios_fhirpath_support::EvaluationError;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Most terminology servers a [`ServerState`] keeps a provider for
+///
+/// Requests name the terminology server, so the number of providers (and
+/// their HTTP clients) is bounded.
+const MAX_TERMINOLOGY_PROVIDERS: usize = 16;
+
+/// Most steps of evaluation returned for a request with `debugEval`
+const MAX_DEBUG_STEPS: usize = 10_000;
+
+/// Terminology providers by server URL and FHIR version
+type TerminologyProviders = HashMap<(String, FhirVersion), Arc<HttpTerminologyProvider>>;
+
+/// State shared by the requests to the evaluation endpoint
+///
+/// Holds the limits applied to each evaluation and the terminology providers
+/// built for the servers requests name. Cloning is cheap; clones share the
+/// providers.
+#[derive(Clone, Default)]
+pub struct ServerState {
+    limits: EvaluationLimits,
+    timeout: Option<Duration>,
+    /// Providers by server URL and FHIR version, so requests naming the same
+    /// server share one HTTP client and response cache
+    terminology_providers: Arc<Mutex<TerminologyProviders>>,
+}
+
+impl ServerState {
+    /// Creates state whose evaluations fail when they exceed `limits`, or
+    /// take longer than `timeout`
+    pub fn new(limits: EvaluationLimits, timeout: Option<Duration>) -> Self {
+        Self {
+            limits,
+            timeout,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the provider for the terminology server at `url`, building it
+    /// the first time the server is named
+    fn terminology_provider(
+        &self,
+        url: &str,
+        fhir_version: FhirVersion,
+    ) -> Arc<HttpTerminologyProvider> {
+        let mut providers = self
+            .terminology_providers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let key = (url.to_string(), fhir_version);
+        if let Some(provider) = providers.get(&key) {
+            return provider.clone();
+        }
+        if providers.len() >= MAX_TERMINOLOGY_PROVIDERS {
+            providers.clear();
+        }
+        let provider = Arc::new(HttpTerminologyProvider::new(url, fhir_version));
+        providers.insert(key, provider.clone());
+        provider
+    }
+}
 
 /// Handler for the main evaluation endpoint
 ///
@@ -25,10 +95,40 @@ use helios_fhir::{FhirResource, FhirVersion};
 /// expression against the provided resource, returning results in the format
 /// specified by the fhirpath-lab API.
 pub async fn evaluate_fhirpath(
+    params: Json<FhirPathParameters>,
+) -> Result<Response, FhirPathError> {
+    evaluate_fhirpath_with_state(State(ServerState::default()), params).await
+}
+
+/// Handler for the main evaluation endpoint using the server's shared state
+///
+/// Evaluation of the request fails with an error response when it exceeds the
+/// state's limits, or when it takes longer than its timeout. Terminology
+/// providers are taken from the state.
+pub async fn evaluate_fhirpath_with_state(
+    State(state): State<ServerState>,
     Json(params): Json<FhirPathParameters>,
 ) -> Result<Response, FhirPathError> {
     info!("Handling FHIRPath evaluation request");
 
+    // Evaluation is synchronous and may block on terminology requests, so it
+    // runs on the blocking pool rather than on a runtime worker
+    tokio::task::spawn_blocking(move || evaluate_request(&state, params))
+        .await
+        .map_err(|e| FhirPathError::EvaluationError(format!("Evaluation task failed: {}", e)))?
+}
+
+/// Evaluates a single request against the server's shared state
+fn evaluate_request(
+    state: &ServerState,
+    params: FhirPathParameters,
+) -> Result<Response, FhirPathError> {
+    // The deadline covers the whole request
+    let limits = match state.timeout {
+        Some(timeout) => state.limits.clone().with_timeout(timeout),
+        None => state.limits.clone(),
+    };
+
     // Extract parameters
     let extracted = extract_parameters(params)?;
     debug!("Extracted parameters: {:?}", extracted);
@@ -49,6 +149,9 @@ pub async fn evaluate_fhirpath(
 
     // Create evaluation context
     let mut context = EvaluationContext::new(vec![fhir_resource]);
+    context.set_limits(limits);
+    // Report where each result was found in the resource if requested
+    context.set_track_locations(extracted.result_locations);
 
     // Set variables
     for var in &extracted.variables {
@@ -58,22 +161,32 @@ pub async fn evaluate_fhirpath(
     // Set terminology server if provided
     if let Some(ts) = &extracted.terminology_server {
         context.set_variable_result("terminologyServer", EvaluationResult::string(ts.clone()));
+        context.set_terminology_provider(state.terminology_provider(ts, fhir_version));
     }
 
-    // Generate parse debug information if needed
-    let (parse_debug_tree, parse_debug) = if extracted.validate {
-        use chumsky::Parser as ChumskyParser;
+    // Compile the expression once; the shared cache lets repeated requests for the
+    // same expression skip the parser (and the optimizer)
+    let compiled = if extracted.optimize {
+        ExpressionCache::global().get_or_compile_optimized(&expression)
+    } else {
+        ExpressionCache::global().get_or_compile(&expression)
+    };
 
-        match crate::parser::parser().parse(expression.as_str()) {
-            Ok(parsed) => {
+    // Generate parse debug information and check the expression against the FHIR
+    // model if needed
+    let (parse_debug_tree, parse_debug, expected_return_type) = if extracted.validate {
+        match &compiled {
+            Ok(compiled) => {
                 // Create a type context with the resource type
-                let mut type_context = TypeContext::new();
+                let mut type_context = TypeContext::new().with_fhir_version(fhir_version);
+                let mut checker = TypeChecker::new(fhir_version);
 
                 // Try to infer the root resource type from the resource JSON
                 if let Some(resource_type) =
                     resource_json.get("resourceType").and_then(|rt| rt.as_str())
                 {
                     type_context = type_context.with_root_type(InferredType::fhir(resource_type));
+                    checker = checker.with_root_type(InferredType::fhir(resource_type));
                 }
 
                 // Add any variables from the context
@@ -91,20 +204,50 @@ pub async fn evaluate_fhirpath(
                         Value::String(_) => InferredType::system("String"),
                         _ => InferredType::system("Any"),
                     };
+                    checker = checker.with_variable(&var.name, var_type.clone());
                     type_context.variables.insert(var.name.clone(), var_type);
                 }
 
-                let debug_tree = expression_to_debug_tree(&parsed, &type_context);
-                let debug_text = generate_parse_debug(&parsed);
-                (Some(debug_tree), Some(debug_text))
+                // With a context expression, the expression is evaluated against
+                // each of its results rather than the resource
+                let context_check = extracted.context.as_ref().map(|c| checker.check(c));
+                if let Some(Ok(context_check)) = context_check {
+                    checker = checker.with_context_types(context_check.return_types);
+                }
+                let check = checker.check_compiled(compiled);
+
+                let debug_tree = expression_to_debug_tree(compiled.expression(), &type_context);
+                let mut debug_text = generate_parse_debug(compiled.expression());
+                for diagnostic in &check.diagnostics {
+                    debug_text.push_str(&format!("{}\n", diagnostic));
+                }
+                (
+                    Some(debug_tree),
+                    Some(debug_text),
+                    check.expected_return_type(),
+                )
             }
             Err(e) => {
-                warn!("Parse error during validation: {:?}", e);
-                (None, Some(format!("Parse error: {:?}", e)))
+                warn!("Parse error during validation: {}", e);
+                (None, Some(format!("Parse error: {}", e)), None)
             }
         }
     } else {
-        (None, None)
+        (None, None, None)
+    };
+
+    // Record the steps of evaluation if requested
+    let debugger = extracted
+        .debug_eval
+        .then(|| Arc::new(EvaluationDebugger::with_max_steps(MAX_DEBUG_STEPS)));
+    if let Some(debugger) = &debugger {
+        context.set_debugger(debugger.clone());
+    }
+    let take_steps = || {
+        debugger
+            .as_ref()
+            .map(|debugger| debugger.take())
+            .unwrap_or_default()
     };
 
     // Prepare results collection
@@ -116,23 +259,17 @@ pub async fn evaluate_fhirpath(
     // Evaluate with context if provided
     if let Some(context_expr) = &extracted.context {
         // Evaluate context expression
-        let context_results = match evaluate_expression(context_expr, &context) {
+        let context_results = match ExpressionCache::global().evaluate(context_expr, &context) {
             Ok(r) => r,
             Err(e) => {
-                return create_error_response(&expression, &extracted, e);
+                return create_evaluation_error_response(&expression, e, take_steps());
             }
         };
 
-        // Parse the main expression once
-        use chumsky::Parser as ChumskyParser;
-        let parsed_expr = match crate::parser::parser().parse(expression.as_str()) {
-            Ok(parsed) => parsed,
+        let compiled = match compiled {
+            Ok(compiled) => compiled,
             Err(e) => {
-                return create_error_response(
-                    &expression,
-                    &extracted,
-                    format!("Parse error: {:?}", e),
-                );
+                return create_error_response(&expression, &extracted, e);
             }
         };
 
@@ -143,11 +280,12 @@ pub async fn evaluate_fhirpath(
         };
 
         for (context_index, context_value) in context_items.into_iter().enumerate() {
-            // Clear trace outputs before each evaluation
+            // Clear trace outputs and steps before each evaluation
             context.clear_trace_outputs();
+            take_steps();
 
             // Evaluate expression with context value as current item
-            match crate::evaluator::evaluate(&parsed_expr, &context, Some(&context_value)) {
+            match evaluate(compiled.expression(), &context, Some(&context_value)) {
                 Ok(result) => {
                     let context_path = format!("{}[{}]", context_expr, context_index);
                     // Get trace outputs collected during this evaluation
@@ -156,8 +294,21 @@ pub async fn evaluate_fhirpath(
                         context_path,
                         result,
                         trace_outputs,
+                        &expression,
+                        take_steps(),
                     )?);
                 }
+                // Exceeding a limit stops the whole request
+                Err(e @ EvaluationError::LimitExceeded(_)) => {
+                    return create_evaluation_error_response(
+                        &expression,
+                        format!(
+                            "Failed to evaluate FHIRPath expression '{}': {}",
+                            expression, e
+                        ),
+                        take_steps(),
+                    );
+                }
                 Err(e) => {
                     warn!("Evaluation error for context {}: {}", context_index, e);
                 }
@@ -165,7 +316,7 @@ pub async fn evaluate_fhirpath(
         }
     } else {
         // Evaluate without context
-        match evaluate_expression(&expression, &context) {
+        match compiled.and_then(|compiled| compiled.evaluate(&context)) {
             Ok(result) => {
                 // Get trace outputs collected during evaluation
                 let trace_outputs = context.get_trace_outputs();
@@ -173,10 +324,12 @@ pub async fn evaluate_fhirpath(
                     "Resource".to_string(),
                     result,
                     trace_outputs,
+                    &expression,
+                    take_steps(),
                 )?);
             }
             Err(e) => {
-                return create_error_response(&expression, &extracted, e);
+                return create_evaluation_error_response(&expression, e, take_steps());
             }
         }
     }
@@ -186,6 +339,7 @@ pub async fn evaluate_fhirpath(
         &expression,
         &extracted,
         results,
+        expected_return_type,
         parse_debug_tree,
         parse_debug,
         resource_json,
@@ -293,6 +447,8 @@ fn create_result_parameter(
     context_path: String,
     result: EvaluationResult,
     trace_outputs: Vec<(String, EvaluationResult)>,
+    expression: &str,
+    steps: Vec<EvaluationStep>,
 ) -> FhirPathResult<Value> {
     let mut parts = Vec::new();
 
@@ -303,7 +459,7 @@ fn create_result_parameter(
     };
 
     for value in result_items {
-        parts.push(evaluation_result_to_result_value(value)?);
+        parts.push(located_result_value(value)?);
     }
 
     // Add trace outputs as parts
@@ -317,7 +473,7 @@ fn create_result_parameter(
         };
 
         for value in trace_items {
-            trace_parts.push(evaluation_result_to_result_value(value)?);
+            trace_parts.push(located_result_value(value)?);
         }
 
         // Create trace part with name and valueString
@@ -328,6 +484,18 @@ fn create_result_parameter(
         }));
     }
 
+    // Add the evaluation steps as a single part
+    if !steps.is_empty() {
+        let step_parts = steps
+            .into_iter()
+            .map(|step| evaluation_step_to_part(step, expression))
+            .collect::<FhirPathResult<Vec<_>>>()?;
+        parts.push(json!({
+            "name": "debugEval",
+            "part": step_parts
+        }));
+    }
+
     Ok(json!({
         "name": "result",
         "valueString": context_path,
@@ -335,6 +503,59 @@ fn create_result_parameter(
     }))
 }
 
+/// Extension fhirpath-lab reads the path of a result item from
+const RESOURCE_PATH_EXTENSION: &str = "http://fhir.forms-lab.com/StructureDefinition/resource-path";
+
+/// Convert EvaluationResult to ResultValue, adding the path of the element it
+/// was read from as an extension
+fn located_result_value(result: EvaluationResult) -> FhirPathResult<Value> {
+    let location = result.location().map(str::to_string);
+    let mut value = evaluation_result_to_result_value(result)?;
+    if let Some(location) = location {
+        value["extension"] = json!([{
+            "url": RESOURCE_PATH_EXTENSION,
+            "valueString": location
+        }]);
+    }
+    Ok(value)
+}
+
+/// Convert an evaluation step to a part holding the node's source text, its
+/// position, focus, result and timing
+fn evaluation_step_to_part(step: EvaluationStep, expression: &str) -> FhirPathResult<Value> {
+    let values = |value: EvaluationResult| -> FhirPathResult<Vec<Value>> {
+        let items = match value {
+            EvaluationResult::Collection { items, .. } => items,
+            EvaluationResult::Empty => vec![],
+            single_value => vec![single_value],
+        };
+        items
+            .into_iter()
+            .map(evaluation_result_to_result_value)
+            .collect()
+    };
+
+    let mut parts = vec![
+        json!({ "name": "nodeType", "valueString": step.node_type }),
+        json!({ "name": "position", "valueInteger": step.span.start }),
+        json!({ "name": "length", "valueInteger": step.span.len() }),
+        json!({ "name": "depth", "valueInteger": step.depth }),
+        json!({ "name": "durationMicroseconds", "valueInteger": step.duration.as_micros() as u64 }),
+    ];
+    let text = step.source_text(expression).to_string();
+    parts.push(json!({ "name": "focus", "valueString": step.focus }));
+    match step.result {
+        Ok(result) => parts.push(json!({ "name": "result", "part": values(result)? })),
+        Err(error) => parts.push(json!({ "name": "error", "valueString": error.to_string() })),
+    }
+
+    Ok(json!({
+        "name": "step",
+        "valueString": text,
+        "part": parts
+    }))
+}
+
 /// Convert object map to JSON
 fn convert_object_to_json(map: &std::collections::HashMap<String, EvaluationResult>) -> Value {
     let mut json_map = serde_json::Map::new();
@@ -354,9 +575,9 @@ fn convert_evaluation_result_to_json(result: &EvaluationResult) -> Value {
         EvaluationResult::String(s, _) => json!(s),
         EvaluationResult::Integer(i, _) => json!(i),
         EvaluationResult::Decimal(d, _) => json!(d.to_string()),
-        EvaluationResult::Date(d, _) => json!(d),
-        EvaluationResult::DateTime(dt, _) => json!(dt),
-        EvaluationResult::Time(t, _) => json!(t),
+        EvaluationResult::Date(d, _) => json!(d.as_str()),
+        EvaluationResult::DateTime(dt, _) => json!(dt.as_str()),
+        EvaluationResult::Time(t, _) => json!(t.as_str()),
         EvaluationResult::Quantity(v, u, _) => json!({"value": v, "unit": u}),
         #[cfg(not(any(feature = "R4", feature = "R4B")))]
         EvaluationResult::Integer64(i, _) => json!(i),
@@ -463,7 +684,7 @@ fn evaluation_result_to_result_value(result: EvaluationResult) -> FhirPathResult
 
             Ok(json!({
                 "name": type_name,
-                "valueDate": d
+                "valueDate": d.as_str()
             }))
         }
         EvaluationResult::DateTime(dt, type_info) => {
@@ -481,7 +702,7 @@ fn evaluation_result_to_result_value(result: EvaluationResult) -> FhirPathResult
 
             Ok(json!({
                 "name": type_name,
-                value_property: dt
+                value_property: dt.as_str()
             }))
         }
         EvaluationResult::Time(t, type_info) => {
@@ -493,7 +714,7 @@ fn evaluation_result_to_result_value(result: EvaluationResult) -> FhirPathResult
 
             Ok(json!({
                 "name": type_name,
-                "valueTime": t
+                "valueTime": t.as_str()
             }))
         }
         EvaluationResult::Quantity(value, unit, _) => Ok(json!({
@@ -586,11 +807,41 @@ fn create_error_response(
     Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(response)).into_response())
 }
 
+/// Create an error response for a failed evaluation, listing the steps
+/// recorded before the failure as an informational issue
+fn create_evaluation_error_response(
+    expression: &str,
+    error: String,
+    steps: Vec<EvaluationStep>,
+) -> Result<Response, FhirPathError> {
+    let mut issues = vec![json!({
+        "severity": "error",
+        "code": "processing",
+        "diagnostics": error,
+        "expression": [expression]
+    })];
+    if !steps.is_empty() {
+        issues.push(json!({
+            "severity": "information",
+            "code": "informational",
+            "diagnostics": generate_evaluation_debug(&steps, expression),
+            "expression": [expression]
+        }));
+    }
+    let response = json!({
+        "resourceType": "OperationOutcome",
+        "issue": issues
+    });
+
+    Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(response)).into_response())
+}
+
 /// Build the evaluation response
 fn build_evaluation_response(
     expression: &str,
     params: &ExtractedParameters,
     results: Vec<Value>,
+    expected_return_type: Option<String>,
     parse_debug_tree: Option<Value>,
     parse_debug: Option<String>,
     resource: Value,
@@ -620,6 +871,13 @@ fn build_evaluation_response(
         }));
     }
 
+    if let Some(return_type) = expected_return_type {
+        param_parts.push(json!({
+            "name": "expectedReturnType",
+            "valueString": return_type
+        }));
+    }
+
     if let Some(tree) = parse_debug_tree {
         param_parts.push(json!({
             "name": "parseDebugTree",
@@ -724,10 +982,7 @@ mod tests {
 
     #[test]
     fn test_instant_uses_value_instant() {
-        let result = EvaluationResult::DateTime(
-            "2023-01-01T12:00:00Z".to_string(),
-            Some(TypeInfoResult::new("FHIR", "instant")),
-        );
+        let result = EvaluationResult::fhir_datetime("2023-01-01T12:00:00Z", "instant");
         let json_result = evaluation_result_to_result_value(result).unwrap();
 
         assert_eq!(json_result["name"], "instant");
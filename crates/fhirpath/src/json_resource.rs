@@ -0,0 +1,442 @@
+//! # JSON Resources
+//!
+//! This module provides [`JsonResource`], a FHIR resource held as raw
+//! `serde_json::Value` that the evaluator navigates directly, without
+//! deserializing it into the typed structures of the `helios-fhir` crate.
+//!
+//! Typed deserialization rejects resources from a different FHIR version or with
+//! small schema violations, and costs a full pass over the resource before the
+//! first expression runs. A `JsonResource` instead walks the JSON with the
+//! [`TypeMetadata`] generated by the `FhirPath` derive, which describes the
+//! elements, choice types and primitive kinds of every FHIR type. The results are
+//! the same `EvaluationResult`s the typed conversion produces:
+//!
+//! - complex values become objects typed with their FHIR type
+//! - primitive values keep their FHIR type, including the declared primitive
+//!   type of the element (`code`, `uri`, ...)
+//! - `_name` siblings supply the `id` and `extension` of primitives
+//! - choice elements (`valueQuantity`) are stored under their base name (`value`)
+//!   so polymorphic access behaves as it does for typed resources
+//!
+//! Properties the metadata does not describe, such as elements of a newer FHIR
+//! version, are still converted, only without FHIR type information.
+//!
+//! ```rust
+//! use helios_fhir::FhirVersion;
+//! use helios_fhirpath::{EvaluationContext, EvaluationResult, evaluate_expression};
+//! use serde_json::json;
+//!
+//! let patient = json!({
+//!     "resourceType": "Patient",
+//!     "name": [{ "family": "Chalmers", "given": ["Peter", "James"] }]
+//! });
+//! let context = EvaluationContext::from_json(patient, FhirVersion::R4);
+//!
+//! let result = evaluate_expression("Patient.name.given.first()", &context)?;
+//! assert_eq!(result, EvaluationResult::fhir_string("Peter".to_string(), "string"));
+//! # Ok::<(), String>(())
+//! ```
+
+use helios_fhir::FhirVersion;
+use helios_fhirpath_support::{
+    ElementMetadata, EvaluationResult, FhirPathFields, FhirPathMetadata, IntoEvaluationResult,
+    PrimitiveKind, TypeInfoResult, TypeMetadata,
+};
+use rust_decimal::Decimal;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// A FHIR resource held as JSON and navigated without typed deserialization
+#[derive(Debug, Clone)]
+pub struct JsonResource {
+    json: Value,
+    fhir_version: FhirVersion,
+}
+
+impl JsonResource {
+    /// Wraps a JSON resource, interpreting it with the metadata of `fhir_version`
+    pub fn new(json: Value, fhir_version: FhirVersion) -> Self {
+        Self { json, fhir_version }
+    }
+
+    /// The underlying JSON
+    pub fn json(&self) -> &Value {
+        &self.json
+    }
+
+    /// The FHIR version the resource is interpreted with
+    pub fn fhir_version(&self) -> FhirVersion {
+        self.fhir_version
+    }
+
+    /// The `resourceType` of the JSON object, if present
+    pub fn resource_type(&self) -> Option<&str> {
+        self.json.get("resourceType").and_then(Value::as_str)
+    }
+
+    /// The metadata of this resource's type in its FHIR version
+    fn metadata(&self) -> Option<&'static TypeMetadata> {
+        match resource_metadata(self.fhir_version) {
+            TypeMetadata::Resource { lookup } => lookup(self.resource_type()?),
+            _ => None,
+        }
+    }
+}
+
+impl IntoEvaluationResult for JsonResource {
+    fn to_evaluation_result(&self) -> EvaluationResult {
+        convert_resource(&self.json, resource_metadata(self.fhir_version))
+    }
+}
+
+impl FhirPathFields for JsonResource {
+    fn fhirpath_field(&self, name: &str) -> Option<EvaluationResult> {
+        let map = self.json.as_object()?;
+        if name == "resourceType" {
+            return self
+                .resource_type()
+                .map(|resource_type| EvaluationResult::string(resource_type.to_string()));
+        }
+
+        let metadata = self.metadata();
+        if let Some(element) = metadata.and_then(|metadata| metadata.element(name)) {
+            return Some(convert_element(map, element));
+        }
+
+        // Properties without metadata are stored under their JSON name
+        if is_described(metadata, name) {
+            return None;
+        }
+        map.get(name).map(convert_untyped)
+    }
+}
+
+/// The metadata of the `Resource` enum of a FHIR version
+pub(crate) fn resource_metadata(fhir_version: FhirVersion) -> &'static TypeMetadata {
+    match fhir_version {
+        #[cfg(feature = "R4")]
+        FhirVersion::R4 => helios_fhir::r4::Resource::fhirpath_metadata(),
+        #[cfg(feature = "R4B")]
+        FhirVersion::R4B => helios_fhir::r4b::Resource::fhirpath_metadata(),
+        #[cfg(feature = "R5")]
+        FhirVersion::R5 => helios_fhir::r5::Resource::fhirpath_metadata(),
+        #[cfg(feature = "R6")]
+        FhirVersion::R6 => helios_fhir::r6::Resource::fhirpath_metadata(),
+    }
+}
+
+/// Checks whether a JSON property is covered by the metadata of its object,
+/// either as an element, a choice variant or a primitive's `_name` sibling
+fn is_described(metadata: Option<&TypeMetadata>, key: &str) -> bool {
+    key == "resourceType"
+        || key.starts_with('_')
+        || metadata.is_some_and(|metadata| metadata.has_json_property(key))
+}
+
+/// Converts a JSON value described by `metadata`
+///
+/// `extension` is the `_name` sibling of a primitive value, if any.
+fn convert_value(
+    value: Option<&Value>,
+    extension: Option<&Value>,
+    metadata: &'static TypeMetadata,
+) -> EvaluationResult {
+    match metadata {
+        TypeMetadata::Primitive {
+            kind,
+            extension: extension_metadata,
+        } => convert_primitive(value, extension, *kind, extension_metadata()),
+        TypeMetadata::Complex { name, .. } => match value {
+            Some(Value::Object(map)) => convert_object(map, name, Some(metadata)),
+            Some(value) => convert_untyped(value),
+            None => EvaluationResult::Empty,
+        },
+        TypeMetadata::Resource { .. } => match value {
+            Some(value) => convert_resource(value, metadata),
+            None => EvaluationResult::Empty,
+        },
+        // Choice types only occur as elements, where convert_element handles them
+        TypeMetadata::Choice { .. } => value.map_or(EvaluationResult::Empty, convert_untyped),
+    }
+}
+
+/// Converts a resource, looking its type up by `resourceType`
+fn convert_resource(value: &Value, resource_metadata: &'static TypeMetadata) -> EvaluationResult {
+    let Value::Object(map) = value else {
+        return convert_untyped(value);
+    };
+    let Some(resource_type) = map.get("resourceType").and_then(Value::as_str) else {
+        return convert_untyped(value);
+    };
+
+    let metadata = match resource_metadata {
+        TypeMetadata::Resource { lookup } => lookup(resource_type),
+        _ => None,
+    };
+    let mut result = match metadata {
+        Some(metadata @ TypeMetadata::Complex { name, .. }) => {
+            convert_object(map, name, Some(metadata))
+        }
+        _ => convert_object(map, resource_type, None),
+    };
+    if let EvaluationResult::Object { map, .. } = &mut result {
+        map.insert(
+            "resourceType".to_string(),
+            EvaluationResult::string(resource_type.to_string()),
+        );
+    }
+    result
+}
+
+/// Converts a JSON object to an object typed `FHIR.<type_name>`
+fn convert_object(
+    json: &Map<String, Value>,
+    type_name: &str,
+    metadata: Option<&'static TypeMetadata>,
+) -> EvaluationResult {
+    let mut map = HashMap::new();
+
+    if let Some(TypeMetadata::Complex { elements, .. }) = metadata {
+        for element in elements.iter() {
+            let result = convert_element(json, element);
+            if result != EvaluationResult::Empty {
+                map.insert(element.name.to_string(), result);
+            }
+        }
+    }
+
+    for (key, value) in json {
+        if is_described(metadata, key) {
+            continue;
+        }
+        let result = convert_untyped(value);
+        if result != EvaluationResult::Empty {
+            map.insert(key.clone(), result);
+        }
+    }
+
+    EvaluationResult::typed_object(map, "FHIR", type_name)
+}
+
+/// Converts one element of a complex type from its JSON object
+///
+/// This mirrors the field conversion generated by the `FhirPath` derive.
+fn convert_element(json: &Map<String, Value>, element: &ElementMetadata) -> EvaluationResult {
+    let metadata = (element.metadata)();
+
+    if let TypeMetadata::Choice { variants } = metadata {
+        for variant in variants.iter() {
+            let value = json.get(variant.json_name).filter(|value| !value.is_null());
+            let extension = json
+                .get(&format!("_{}", variant.json_name))
+                .filter(|value| !value.is_null());
+            if value.is_some() || extension.is_some() {
+                let result = convert_value(value, extension, (variant.metadata)());
+                return with_choice_type(result, variant.fhir_type);
+            }
+        }
+        return EvaluationResult::Empty;
+    }
+
+    let value = json.get(element.name).filter(|value| !value.is_null());
+    let extension = json
+        .get(&format!("_{}", element.name))
+        .filter(|value| !value.is_null());
+
+    let result = if element.is_array || value.is_some_and(Value::is_array) {
+        convert_array(value, extension, metadata)
+    } else {
+        convert_value(value, extension, metadata)
+    };
+
+    match (result, element.primitive_type) {
+        (EvaluationResult::String(s, _), Some(type_name)) => typed_primitive(s, type_name),
+        (result, _) => result,
+    }
+}
+
+/// Gives a string-valued primitive its FHIR type, converting the temporal
+/// primitives to Date, DateTime and Time values
+fn typed_primitive(value: String, type_name: &str) -> EvaluationResult {
+    match type_name {
+        "date" => EvaluationResult::fhir_date(value),
+        "dateTime" | "instant" => EvaluationResult::fhir_datetime(value, type_name),
+        "time" => EvaluationResult::fhir_time(value),
+        _ => EvaluationResult::fhir_string(value, type_name),
+    }
+}
+
+/// Converts a repeating element, pairing each value with its `_name` entry
+fn convert_array(
+    value: Option<&Value>,
+    extension: Option<&Value>,
+    metadata: &'static TypeMetadata,
+) -> EvaluationResult {
+    if value.is_none() && extension.is_none() {
+        return EvaluationResult::Empty;
+    }
+
+    // A single value where an array is expected is treated as one item
+    let as_items = |value: Option<&Value>| -> Vec<Value> {
+        match value {
+            Some(Value::Array(items)) => items.clone(),
+            Some(value) => vec![value.clone()],
+            None => Vec::new(),
+        }
+    };
+    let values = as_items(value);
+    let extensions = as_items(extension);
+
+    let items = (0..values.len().max(extensions.len()))
+        .map(|i| {
+            let value = values.get(i).filter(|value| !value.is_null());
+            let extension = extensions.get(i).filter(|value| !value.is_null());
+            convert_value(value, extension, metadata)
+        })
+        .collect();
+    EvaluationResult::collection(items)
+}
+
+/// Converts a primitive value, falling back to its `id` and `extension`
+///
+/// This mirrors `IntoEvaluationResult` for `Element` and `DecimalElement`.
+fn convert_primitive(
+    value: Option<&Value>,
+    extension: Option<&Value>,
+    kind: PrimitiveKind,
+    extension_metadata: &'static TypeMetadata,
+) -> EvaluationResult {
+    if let Some(result) = value.and_then(|value| convert_primitive_value(value, kind)) {
+        return result;
+    }
+
+    let Some(Value::Object(element)) = extension else {
+        return EvaluationResult::Empty;
+    };
+    let mut map = HashMap::new();
+    if let Some(Value::String(id)) = element.get("id") {
+        map.insert("id".to_string(), EvaluationResult::string(id.clone()));
+    }
+    if let Some(Value::Array(extensions)) = element.get("extension") {
+        let items: Vec<EvaluationResult> = extensions
+            .iter()
+            .map(|extension| convert_value(Some(extension), None, extension_metadata))
+            .collect();
+        if !items.is_empty() {
+            map.insert("extension".to_string(), EvaluationResult::collection(items));
+        }
+    }
+    if map.is_empty() {
+        return EvaluationResult::Empty;
+    }
+    let type_name = if kind == PrimitiveKind::Decimal {
+        "decimal"
+    } else {
+        "Element"
+    };
+    EvaluationResult::typed_object(map, "FHIR", type_name)
+}
+
+/// Converts the value of a primitive element
+///
+/// Returns `None` for decimals that cannot be parsed, which the typed conversion
+/// also treats as absent. Values of the wrong JSON type are accepted where the
+/// typed deserializer accepts them (numeric strings for integers) and otherwise
+/// converted without type information.
+fn convert_primitive_value(value: &Value, kind: PrimitiveKind) -> Option<EvaluationResult> {
+    let result = match (kind, value) {
+        (PrimitiveKind::String, Value::String(s)) => {
+            EvaluationResult::fhir_string(s.clone(), "string")
+        }
+        (PrimitiveKind::Boolean, Value::Bool(b)) => EvaluationResult::fhir_boolean(*b),
+        (PrimitiveKind::Integer, _) => match integer_value(value) {
+            Some(i) => EvaluationResult::fhir_integer(i),
+            None => convert_untyped(value),
+        },
+        (PrimitiveKind::Integer64, _) => match integer_value(value) {
+            #[cfg(not(any(feature = "R4", feature = "R4B")))]
+            Some(i) => EvaluationResult::fhir_integer64(i),
+            #[cfg(any(feature = "R4", feature = "R4B"))]
+            Some(i) => EvaluationResult::integer64(i),
+            None => convert_untyped(value),
+        },
+        (PrimitiveKind::Decimal, Value::Number(_) | Value::String(_)) => {
+            let decimal: helios_fhir::PreciseDecimal =
+                serde_json::from_value(value.clone()).ok()?;
+            EvaluationResult::fhir_decimal(decimal.value()?)
+        }
+        _ => convert_untyped(value),
+    };
+    Some(result)
+}
+
+/// Reads an integer from a JSON number or numeric string
+fn integer_value(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Gives a choice value the type of its variant unless it already has one
+///
+/// This mirrors the conversion generated for choice enums.
+fn with_choice_type(result: EvaluationResult, fhir_type: &str) -> EvaluationResult {
+    let type_info = |existing: Option<TypeInfoResult>| {
+        Some(existing.unwrap_or_else(|| TypeInfoResult::new("FHIR", fhir_type)))
+    };
+    match result {
+        EvaluationResult::String(s, _)
+            if matches!(fhir_type, "date" | "dateTime" | "instant" | "time") =>
+        {
+            typed_primitive(s, fhir_type)
+        }
+        EvaluationResult::String(s, existing) => EvaluationResult::String(s, type_info(existing)),
+        EvaluationResult::Integer(i, existing) => EvaluationResult::Integer(i, type_info(existing)),
+        EvaluationResult::Decimal(d, existing) => EvaluationResult::Decimal(d, type_info(existing)),
+        EvaluationResult::Boolean(b, existing) => EvaluationResult::Boolean(b, type_info(existing)),
+        EvaluationResult::Object {
+            map,
+            type_info: existing,
+        } => EvaluationResult::Object {
+            map,
+            type_info: type_info(existing),
+        },
+        result => result,
+    }
+}
+
+/// Converts JSON the metadata does not describe, without FHIR type information
+///
+/// Nested resources are still recognised by their `resourceType`.
+fn convert_untyped(value: &Value) -> EvaluationResult {
+    match value {
+        Value::Null => EvaluationResult::Empty,
+        Value::Bool(b) => EvaluationResult::boolean(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => EvaluationResult::integer(i),
+            None => Decimal::from_str(&n.to_string())
+                .or_else(|_| Decimal::from_scientific(&n.to_string()))
+                .map(EvaluationResult::decimal)
+                .unwrap_or(EvaluationResult::Empty),
+        },
+        Value::String(s) => EvaluationResult::string(s.clone()),
+        Value::Array(items) => {
+            EvaluationResult::collection(items.iter().map(convert_untyped).collect())
+        }
+        Value::Object(json) => {
+            let map = json
+                .iter()
+                .filter(|(key, _)| !key.starts_with('_'))
+                .map(|(key, value)| (key.clone(), convert_untyped(value)))
+                .filter(|(_, result)| *result != EvaluationResult::Empty)
+                .collect();
+            match json.get("resourceType").and_then(Value::as_str) {
+                Some(resource_type) => EvaluationResult::typed_object(map, "FHIR", resource_type),
+                None => EvaluationResult::object(map),
+            }
+        }
+    }
+}
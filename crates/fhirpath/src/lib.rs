@@ -219,7 +219,10 @@
 //!
 //! ## Performance Considerations
 //!
-//! - **Parsing**: Expression parsing is relatively expensive; consider caching parsed expressions for repeated use
+//! - **Parsing**: Expression parsing is relatively expensive; use [`CompiledExpression`] or an
+//!   [`ExpressionCache`] to parse once and evaluate many times
+//! - **Parallelism**: Contexts cannot be shared between threads; use a [`BatchEvaluator`] to
+//!   evaluate one expression against many resources on a pool of threads
 //! - **Evaluation**: Evaluation performance depends on resource size and expression complexity
 //! - **Memory**: Large collections in FHIR resources may consume significant memory during evaluation
 //! - **Stack Usage**: Deep expression nesting may require increased stack size (`RUST_MIN_STACK=8388608`)
@@ -274,14 +277,19 @@ pub mod date_operation;
 mod datetime_impl;
 mod distinct_functions;
 mod extension_function;
+mod fhir_functions;
 mod fhir_type_hierarchy;
+mod locations;
 mod long_conversion;
 mod not_function;
 mod polymorphic_access;
 mod reference_key_functions;
 mod repeat_function;
 mod resource_type;
+mod result_fields;
 mod set_operations;
+mod sort_function;
+mod string_functions;
 mod subset_functions;
 mod trace_function;
 mod type_function;
@@ -296,12 +304,29 @@ pub mod parse_debug;
 pub mod server;
 
 // Public modules needed for the public API
+pub mod batch;
+pub mod compiled;
+pub mod debug_eval;
 pub mod evaluator;
+pub mod formatter;
+pub mod function_registry;
+pub mod json_resource;
+pub mod limits;
+pub mod optimizer;
 pub mod parser;
+pub mod profile_registry;
+pub mod reference_resolver;
+pub mod terminology;
+pub mod trace_sink;
+pub mod type_checker;
+pub mod ucum;
 
 // Public API exports - this is what users of the fhirpath crate should use
+pub use batch::BatchEvaluator;
+pub use compiled::{CompiledExpression, ExpressionCache};
 pub use evaluator::EvaluationContext;
 pub use helios_fhirpath_support::EvaluationResult;
+pub use json_resource::JsonResource;
 
 /// Evaluates a FHIRPath expression against a given context.
 ///
@@ -340,25 +365,11 @@ pub use helios_fhirpath_support::EvaluationResult;
 /// - Evaluation is performed against the resources in the provided context
 /// - Variables should be set on the context before calling this function
 /// - The function handles all parsing errors and evaluation errors uniformly
+/// - The expression is parsed on every call; use [`CompiledExpression`] when evaluating the
+///   same expression repeatedly
 pub fn evaluate_expression(
     expression: &str,
     context: &EvaluationContext,
 ) -> Result<EvaluationResult, String> {
-    use chumsky::Parser;
-
-    // Parse the expression
-    let parsed = parser::parser().parse(expression).map_err(|e| {
-        format!(
-            "Failed to parse FHIRPath expression '{}': {:?}",
-            expression, e
-        )
-    })?;
-
-    // Evaluate the parsed expression
-    evaluator::evaluate(&parsed, context, None).map_err(|e| {
-        format!(
-            "Failed to evaluate FHIRPath expression '{}': {}",
-            expression, e
-        )
-    })
+    CompiledExpression::compile(expression)?.evaluate(context)
 }
@@ -0,0 +1,158 @@
+//! # Evaluation Limits
+//!
+//! Limits on the resources an evaluation may use, for evaluating expressions
+//! that cannot be trusted. Functions like `repeat()`, `descendants()` and nested
+//! `aggregate()` calls can otherwise run without bound.
+//!
+//! [`EvaluationLimits`] sets:
+//!
+//! - the maximum nesting depth of evaluation
+//! - the maximum size of any collection built during evaluation
+//! - a deadline after which evaluation stops
+//! - a [`CancellationToken`] that stops evaluation from another thread
+//!
+//! When a limit is exceeded, evaluation fails with
+//! [`EvaluationError::LimitExceeded`]. No limits are set by default.
+//!
+//! ```
+//! use helios_fhirpath::evaluator::EvaluationContext;
+//! use helios_fhirpath::limits::EvaluationLimits;
+//! use helios_fhirpath::evaluate_expression;
+//! use std::time::Duration;
+//!
+//! let mut context = EvaluationContext::new_empty_with_default_version();
+//! context.set_limits(
+//!     EvaluationLimits::new()
+//!         .with_max_collection_size(100)
+//!         .with_timeout(Duration::from_secs(1)),
+//! );
+//!
+//! let error = evaluate_expression("(1 | 2 | 3).repeat($this + 1)", &context).unwrap_err();
+//! assert!(error.contains("Limit Exceeded"));
+//! ```
+
+use helios_fhirpath_support::EvaluationError;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// A token for cancelling evaluation from another thread
+///
+/// Clones share the same state, so cancelling any clone cancels the
+/// evaluations of all contexts holding the token.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the evaluations using this token
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the token has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Limits on the resources used by an evaluation
+#[derive(Debug, Clone, Default)]
+pub struct EvaluationLimits {
+    /// Maximum nesting depth of evaluated expressions
+    pub max_depth: Option<usize>,
+    /// Maximum number of items in a collection built during evaluation
+    pub max_collection_size: Option<usize>,
+    /// Time after which evaluation stops
+    pub deadline: Option<Instant>,
+    /// Token for cancelling evaluation
+    pub cancellation_token: Option<CancellationToken>,
+}
+
+impl EvaluationLimits {
+    /// Creates limits that do not restrict evaluation
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum nesting depth of evaluated expressions
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Sets the maximum number of items in a collection
+    pub fn with_max_collection_size(mut self, max_collection_size: usize) -> Self {
+        self.max_collection_size = Some(max_collection_size);
+        self
+    }
+
+    /// Sets the time after which evaluation stops
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the deadline to `timeout` from now
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// Sets the token for cancelling evaluation
+    pub fn with_cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation_token = Some(token);
+        self
+    }
+
+    /// Returns an error if evaluation was cancelled or ran past its deadline
+    pub fn check_running(&self) -> Result<(), EvaluationError> {
+        if self
+            .cancellation_token
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(EvaluationError::LimitExceeded(
+                "Evaluation was cancelled".to_string(),
+            ));
+        }
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(EvaluationError::LimitExceeded(
+                "Evaluation exceeded its deadline".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns an error if evaluation is nested `depth` levels deep and that
+    /// exceeds the maximum depth
+    pub fn check_depth(&self, depth: usize) -> Result<(), EvaluationError> {
+        match self.max_depth {
+            Some(max_depth) if depth > max_depth => Err(EvaluationError::LimitExceeded(format!(
+                "Evaluation depth exceeds the limit of {}",
+                max_depth
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns an error if a collection of `size` items exceeds the maximum
+    /// collection size
+    pub fn check_collection_size(&self, size: usize) -> Result<(), EvaluationError> {
+        match self.max_collection_size {
+            Some(max_size) if size > max_size => Err(EvaluationError::LimitExceeded(format!(
+                "Collection size of {} exceeds the limit of {}",
+                size, max_size
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
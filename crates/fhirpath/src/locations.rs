@@ -0,0 +1,69 @@
+//! # Result Locations
+//!
+//! Records on every element of a resource the path it is found at, such as
+//! `Patient.name[1].given[0]`, so that the results of an expression can be
+//! traced back to the elements they were read from.
+//!
+//! Locations are kept with the type information of each value (see
+//! [`EvaluationResult::location`]), so they follow the values through
+//! navigation, filtering and subsetting. Values computed by the expression
+//! have no location.
+
+use helios_fhirpath_support::EvaluationResult;
+
+/// Records the location of `resource` and every element within it
+///
+/// The resource is located at its resource type. Elements of a repeating
+/// field are located by index, and the extensions of a primitive element
+/// (held in its `_`-prefixed peer) are located under the primitive itself.
+pub(crate) fn locate_resource(resource: EvaluationResult) -> EvaluationResult {
+    let resource_type = match &resource {
+        EvaluationResult::Object { map, type_info } => match map.get("resourceType") {
+            Some(EvaluationResult::String(resource_type, _)) => Some(resource_type.clone()),
+            _ => type_info.as_ref().map(|type_info| type_info.name.clone()),
+        },
+        _ => None,
+    };
+    match resource_type {
+        Some(resource_type) => locate(resource, &resource_type),
+        None => resource,
+    }
+}
+
+/// Records `path` as the location of `value`, and the locations of its elements
+fn locate(value: EvaluationResult, path: &str) -> EvaluationResult {
+    match value {
+        EvaluationResult::Object { map, type_info } => EvaluationResult::Object {
+            map: map
+                .into_iter()
+                .map(|(name, field)| {
+                    let field_path = format!("{}.{}", path, name.trim_start_matches('_'));
+                    (name, locate_field(field, &field_path))
+                })
+                .collect(),
+            type_info,
+        }
+        .with_location(path.to_string()),
+        value => value.with_location(path.to_string()),
+    }
+}
+
+/// Locates the value of a field, indexing the elements of a repeating field
+fn locate_field(field: EvaluationResult, path: &str) -> EvaluationResult {
+    match field {
+        EvaluationResult::Collection {
+            items,
+            has_undefined_order,
+            type_info,
+        } => EvaluationResult::Collection {
+            items: items
+                .into_iter()
+                .enumerate()
+                .map(|(index, item)| locate(item, &format!("{}[{}]", path, index)))
+                .collect(),
+            has_undefined_order,
+            type_info,
+        },
+        field => locate(field, path),
+    }
+}
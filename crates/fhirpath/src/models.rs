@@ -66,6 +66,16 @@ pub struct ExtractedParameters {
     /// Whether to validate the expression
     pub validate: bool,
 
+    /// Whether to simplify the expression with the optimizer before
+    /// evaluating it and reporting its parse tree
+    pub optimize: bool,
+
+    /// Whether to record each step of evaluation
+    pub debug_eval: bool,
+
+    /// Whether to report where each result was found in the resource
+    pub result_locations: bool,
+
     /// Variables to pass to the expression
     pub variables: Vec<Variable>,
 
@@ -238,6 +248,27 @@ fn process_parameter_r4(
                 .and_then(|v| v.as_boolean())
                 .unwrap_or(false);
         }
+        "optimize" => {
+            extracted.optimize = param
+                .value
+                .as_ref()
+                .and_then(|v| v.as_boolean())
+                .unwrap_or(false);
+        }
+        "debugEval" => {
+            extracted.debug_eval = param
+                .value
+                .as_ref()
+                .and_then(|v| v.as_boolean())
+                .unwrap_or(false);
+        }
+        "resultLocations" => {
+            extracted.result_locations = param
+                .value
+                .as_ref()
+                .and_then(|v| v.as_boolean())
+                .unwrap_or(false);
+        }
         "variables" => {
             if let Some(parts) = &param.part {
                 for part in parts {
@@ -337,6 +368,27 @@ fn extract_parameters_from_r4b(
                     .and_then(|v| v.as_boolean())
                     .unwrap_or(false);
             }
+            "optimize" => {
+                extracted.optimize = param
+                    .value
+                    .as_ref()
+                    .and_then(|v| v.as_boolean())
+                    .unwrap_or(false);
+            }
+            "debugEval" => {
+                extracted.debug_eval = param
+                    .value
+                    .as_ref()
+                    .and_then(|v| v.as_boolean())
+                    .unwrap_or(false);
+            }
+            "resultLocations" => {
+                extracted.result_locations = param
+                    .value
+                    .as_ref()
+                    .and_then(|v| v.as_boolean())
+                    .unwrap_or(false);
+            }
             "resource" => {
                 extracted.resource = param
                     .resource
@@ -388,6 +440,27 @@ fn extract_parameters_from_r5(
                     .and_then(|v| v.as_boolean())
                     .unwrap_or(false);
             }
+            "optimize" => {
+                extracted.optimize = param
+                    .value
+                    .as_ref()
+                    .and_then(|v| v.as_boolean())
+                    .unwrap_or(false);
+            }
+            "debugEval" => {
+                extracted.debug_eval = param
+                    .value
+                    .as_ref()
+                    .and_then(|v| v.as_boolean())
+                    .unwrap_or(false);
+            }
+            "resultLocations" => {
+                extracted.result_locations = param
+                    .value
+                    .as_ref()
+                    .and_then(|v| v.as_boolean())
+                    .unwrap_or(false);
+            }
             "resource" => {
                 extracted.resource = param
                     .resource
@@ -439,6 +512,27 @@ fn extract_parameters_from_r6(
                     .and_then(|v| v.as_boolean())
                     .unwrap_or(false);
             }
+            "optimize" => {
+                extracted.optimize = param
+                    .value
+                    .as_ref()
+                    .and_then(|v| v.as_boolean())
+                    .unwrap_or(false);
+            }
+            "debugEval" => {
+                extracted.debug_eval = param
+                    .value
+                    .as_ref()
+                    .and_then(|v| v.as_boolean())
+                    .unwrap_or(false);
+            }
+            "resultLocations" => {
+                extracted.result_locations = param
+                    .value
+                    .as_ref()
+                    .and_then(|v| v.as_boolean())
+                    .unwrap_or(false);
+            }
             "resource" => {
                 extracted.resource = param
                     .resource
@@ -0,0 +1,412 @@
+//! # Expression Optimizer
+//!
+//! This module rewrites a parsed [`Expression`] into a simpler tree that
+//! evaluates to the same result. Expressions produced by authoring tools often
+//! contain sub-expressions such as `iif(true, x, y)`, `'a' + 'b'` or
+//! `where(true)` that would otherwise be re-evaluated for every resource.
+//!
+//! The optimizer makes three kinds of rewrite:
+//!
+//! - **Constant folding**: sub-expressions built only from literals, operators
+//!   and side-effect free functions are evaluated once and replaced by their
+//!   result, when the result is a single Boolean, String, Integer or Decimal or
+//!   the empty collection. Sub-expressions whose evaluation fails are left in
+//!   place so that the error is still reported at evaluation time.
+//! - **No-op removal**: `where(true)` and `select($this)` are removed from
+//!   invocation chains, and `iif()` with a constant condition is replaced by
+//!   the branch it selects.
+//! - **Boolean short-circuiting**: `and`, `or` and `implies` with a constant
+//!   operand are reduced when the three-valued truth tables give the same
+//!   result for `true`, `false` and `{}`, and the other operand is known to
+//!   evaluate to a Boolean.
+//!
+//! ```rust
+//! use helios_fhirpath::optimizer::optimize;
+//! use helios_fhirpath::parser::parse;
+//!
+//! let expression = parse("iif(true, name.where(true).exists(), {}) and ('a' + 'b' = 'ab')")?;
+//! assert_eq!(optimize(&expression).to_string(), "name.exists()");
+//! # Ok::<(), helios_fhirpath::parser::ParseError>(())
+//! ```
+//!
+//! Operands dropped by short-circuiting are not evaluated, so errors they would
+//! raise and `trace()` calls they contain are skipped, as the FHIRPath
+//! specification allows for `and`, `or` and `implies`.
+//!
+//! Every rewritten node keeps the [`Span`](crate::parser::Span) of the source
+//! text it replaces, so the parse debug tree of an optimized expression still
+//! points at the original text. Use
+//! [`CompiledExpression::compile_optimized`](crate::CompiledExpression::compile_optimized)
+//! to evaluate optimized expressions repeatedly.
+
+use crate::evaluator::{EvaluationContext, evaluate};
+use crate::parser::{Expression, Invocation, Literal, Term};
+use helios_fhirpath_support::EvaluationResult;
+
+/// Built-in functions that always give the same result for the same input and
+/// arguments, and so can be folded when both are constant
+///
+/// Built-in functions take precedence over custom functions, so these names
+/// cannot be redefined by a [`FunctionRegistry`](crate::function_registry::FunctionRegistry).
+const FOLDABLE_FUNCTIONS: &[&str] = &[
+    "empty",
+    "exists",
+    "all",
+    "allTrue",
+    "anyTrue",
+    "allFalse",
+    "anyFalse",
+    "subsetOf",
+    "supersetOf",
+    "count",
+    "distinct",
+    "isDistinct",
+    "where",
+    "select",
+    "single",
+    "first",
+    "last",
+    "tail",
+    "skip",
+    "take",
+    "intersect",
+    "exclude",
+    "union",
+    "combine",
+    "iif",
+    "not",
+    "sum",
+    "min",
+    "max",
+    "avg",
+    "toBoolean",
+    "convertsToBoolean",
+    "toInteger",
+    "convertsToInteger",
+    "toDecimal",
+    "convertsToDecimal",
+    "toString",
+    "convertsToString",
+    "indexOf",
+    "lastIndexOf",
+    "substring",
+    "startsWith",
+    "endsWith",
+    "contains",
+    "upper",
+    "lower",
+    "replace",
+    "matches",
+    "matchesFull",
+    "replaceMatches",
+    "length",
+    "toChars",
+    "trim",
+    "split",
+    "join",
+    "encode",
+    "decode",
+    "escape",
+    "unescape",
+    "abs",
+    "ceiling",
+    "exp",
+    "floor",
+    "ln",
+    "log",
+    "power",
+    "round",
+    "sqrt",
+    "truncate",
+];
+
+/// Built-in functions whose result is always a single Boolean or empty
+const BOOLEAN_FUNCTIONS: &[&str] = &[
+    "empty",
+    "exists",
+    "all",
+    "allTrue",
+    "anyTrue",
+    "allFalse",
+    "anyFalse",
+    "subsetOf",
+    "supersetOf",
+    "isDistinct",
+    "not",
+    "hasValue",
+    "startsWith",
+    "endsWith",
+    "contains",
+    "matches",
+    "matchesFull",
+    "convertsToBoolean",
+    "convertsToInteger",
+    "convertsToDecimal",
+    "convertsToString",
+    "convertsToDate",
+    "convertsToDateTime",
+    "convertsToTime",
+    "convertsToQuantity",
+    "is",
+];
+
+/// Returns a simplified copy of `expression` that evaluates to the same result
+pub fn optimize(expression: &Expression) -> Expression {
+    Optimizer::new().optimize(expression.clone())
+}
+
+/// Applies the rewrites bottom-up, folding constants in an empty context
+struct Optimizer {
+    context: EvaluationContext,
+}
+
+impl Optimizer {
+    fn new() -> Self {
+        Self {
+            context: EvaluationContext::new_empty_with_default_version(),
+        }
+    }
+
+    fn optimize(&self, expression: Expression) -> Expression {
+        let expression = self.optimize_children(expression);
+        let expression = simplify(expression);
+        self.fold(expression)
+    }
+
+    fn optimize_children(&self, expression: Expression) -> Expression {
+        let optimize = |inner: Box<Expression>| Box::new(self.optimize(*inner));
+        let optimize_args =
+            |args: Vec<Expression>| args.into_iter().map(|arg| self.optimize(arg)).collect();
+
+        match expression {
+            Expression::Term(
+                Term::Invocation(Invocation::Function(name, args, function_span), term_span),
+                span,
+            ) => Expression::Term(
+                Term::Invocation(
+                    Invocation::Function(name, optimize_args(args), function_span),
+                    term_span,
+                ),
+                span,
+            ),
+            Expression::Term(Term::Parenthesized(inner, term_span), span) => {
+                Expression::Term(Term::Parenthesized(optimize(inner), term_span), span)
+            }
+            Expression::Term(term, span) => Expression::Term(term, span),
+            Expression::Invocation(base, Invocation::Function(name, args, function_span), span) => {
+                Expression::Invocation(
+                    optimize(base),
+                    Invocation::Function(name, optimize_args(args), function_span),
+                    span,
+                )
+            }
+            Expression::Invocation(base, invocation, span) => {
+                Expression::Invocation(optimize(base), invocation, span)
+            }
+            Expression::Indexer(base, index, span) => {
+                Expression::Indexer(optimize(base), optimize(index), span)
+            }
+            Expression::Polarity(op, inner, span) => {
+                Expression::Polarity(op, optimize(inner), span)
+            }
+            Expression::Multiplicative(left, op, right, span) => {
+                Expression::Multiplicative(optimize(left), op, optimize(right), span)
+            }
+            Expression::Additive(left, op, right, span) => {
+                Expression::Additive(optimize(left), op, optimize(right), span)
+            }
+            Expression::Type(inner, op, type_spec, span) => {
+                Expression::Type(optimize(inner), op, type_spec, span)
+            }
+            Expression::Union(left, right, span) => {
+                Expression::Union(optimize(left), optimize(right), span)
+            }
+            Expression::Inequality(left, op, right, span) => {
+                Expression::Inequality(optimize(left), op, optimize(right), span)
+            }
+            Expression::Equality(left, op, right, span) => {
+                Expression::Equality(optimize(left), op, optimize(right), span)
+            }
+            Expression::Membership(left, op, right, span) => {
+                Expression::Membership(optimize(left), op, optimize(right), span)
+            }
+            Expression::And(left, right, span) => {
+                Expression::And(optimize(left), optimize(right), span)
+            }
+            Expression::Or(left, op, right, span) => {
+                Expression::Or(optimize(left), op, optimize(right), span)
+            }
+            Expression::Implies(left, right, span) => {
+                Expression::Implies(optimize(left), optimize(right), span)
+            }
+            Expression::Lambda(param, inner, span) => {
+                Expression::Lambda(param, optimize(inner), span)
+            }
+        }
+    }
+
+    /// Replaces a constant expression with the literal it evaluates to
+    fn fold(&self, expression: Expression) -> Expression {
+        if literal_of(&expression).is_some() || !is_constant(&expression) {
+            return expression;
+        }
+
+        let literal = match evaluate(&expression, &self.context, None) {
+            Ok(EvaluationResult::Empty) => Literal::Null,
+            Ok(EvaluationResult::Boolean(b, _)) => Literal::Boolean(b),
+            Ok(EvaluationResult::String(s, _)) => Literal::String(s),
+            Ok(EvaluationResult::Integer(i, _)) => Literal::Integer(i),
+            Ok(EvaluationResult::Decimal(d, _)) => Literal::Number(d),
+            // Collections, temporal values and quantities are left for the
+            // evaluator, as are expressions that fail to evaluate
+            _ => return expression,
+        };
+        Expression::Term(Term::Literal(literal, expression.span()), expression.span())
+    }
+}
+
+/// Removes no-op filters, resolves `iif()` with a constant condition and
+/// short-circuits boolean operators with a constant operand
+fn simplify(expression: Expression) -> Expression {
+    match expression {
+        Expression::Invocation(base, Invocation::Function(name, args, _), span)
+            if is_no_op_filter(&name, &args) =>
+        {
+            base.with_span(span)
+        }
+
+        Expression::Term(
+            Term::Invocation(Invocation::Function(name, mut args, function_span), term_span),
+            span,
+        ) if name == "iif" && (2..=3).contains(&args.len()) => match literal_of(&args[0]) {
+            Some(Literal::Boolean(true)) => args.swap_remove(1).with_span(span),
+            Some(Literal::Boolean(false)) | Some(Literal::Null) => match args.len() {
+                3 => args.swap_remove(2).with_span(span),
+                _ => Expression::Term(Term::Literal(Literal::Null, term_span), span),
+            },
+            _ => Expression::Term(
+                Term::Invocation(Invocation::Function(name, args, function_span), term_span),
+                span,
+            ),
+        },
+
+        // true and X = X, false and X = false
+        Expression::And(left, right, span) => {
+            match (boolean_literal(&left), boolean_literal(&right)) {
+                (Some(true), _) if is_boolean(&right) => right.with_span(span),
+                (_, Some(true)) if is_boolean(&left) => left.with_span(span),
+                (Some(false), _) if is_boolean(&right) => left.with_span(span),
+                (_, Some(false)) if is_boolean(&left) => right.with_span(span),
+                _ => Expression::And(left, right, span),
+            }
+        }
+
+        // false or X = X, true or X = true
+        Expression::Or(left, op, right, span) if op == "or" => {
+            match (boolean_literal(&left), boolean_literal(&right)) {
+                (Some(false), _) if is_boolean(&right) => right.with_span(span),
+                (_, Some(false)) if is_boolean(&left) => left.with_span(span),
+                (Some(true), _) if is_boolean(&right) => left.with_span(span),
+                (_, Some(true)) if is_boolean(&left) => right.with_span(span),
+                _ => Expression::Or(left, op, right, span),
+            }
+        }
+
+        // true implies X = X, false implies X = true, X implies true = true
+        Expression::Implies(left, right, span) => {
+            match (boolean_literal(&left), boolean_literal(&right)) {
+                (Some(true), _) if is_boolean(&right) => right.with_span(span),
+                (Some(false), _) if is_boolean(&right) => {
+                    Expression::Term(Term::Literal(Literal::Boolean(true), span.clone()), span)
+                }
+                (_, Some(true)) if is_boolean(&left) => right.with_span(span),
+                _ => Expression::Implies(left, right, span),
+            }
+        }
+
+        expression => expression,
+    }
+}
+
+/// Returns `true` for `where(true)` and `select($this)`, which return their
+/// input unchanged
+fn is_no_op_filter(name: &str, args: &[Expression]) -> bool {
+    match (name, args) {
+        ("where", [criteria]) => boolean_literal(criteria) == Some(true),
+        ("select", [projection]) => matches!(
+            unparenthesized(projection),
+            Expression::Term(Term::Invocation(Invocation::This(_), _), _)
+        ),
+        _ => false,
+    }
+}
+
+fn unparenthesized(expression: &Expression) -> &Expression {
+    match expression {
+        Expression::Term(Term::Parenthesized(inner, _), _) => unparenthesized(inner),
+        _ => expression,
+    }
+}
+
+fn literal_of(expression: &Expression) -> Option<&Literal> {
+    match unparenthesized(expression) {
+        Expression::Term(Term::Literal(literal, _), _) => Some(literal),
+        _ => None,
+    }
+}
+
+fn boolean_literal(expression: &Expression) -> Option<bool> {
+    match literal_of(expression) {
+        Some(Literal::Boolean(b)) => Some(*b),
+        _ => None,
+    }
+}
+
+/// Returns `true` if the expression depends only on literals, so evaluates to
+/// the same result whatever the context
+fn is_constant(expression: &Expression) -> bool {
+    match expression {
+        Expression::Term(Term::Literal(_, _), _) => true,
+        Expression::Term(Term::Parenthesized(inner, _), _) => is_constant(inner),
+        Expression::Term(_, _) | Expression::Lambda(_, _, _) => false,
+        Expression::Invocation(base, Invocation::Function(name, args, _), _) => {
+            FOLDABLE_FUNCTIONS.contains(&name.as_str())
+                && is_constant(base)
+                && args.iter().all(is_constant)
+        }
+        Expression::Invocation(_, _, _) => false,
+        Expression::Polarity(_, inner, _) | Expression::Type(inner, _, _, _) => is_constant(inner),
+        Expression::Indexer(left, right, _)
+        | Expression::Union(left, right, _)
+        | Expression::And(left, right, _)
+        | Expression::Implies(left, right, _)
+        | Expression::Multiplicative(left, _, right, _)
+        | Expression::Additive(left, _, right, _)
+        | Expression::Inequality(left, _, right, _)
+        | Expression::Equality(left, _, right, _)
+        | Expression::Membership(left, _, right, _)
+        | Expression::Or(left, _, right, _) => is_constant(left) && is_constant(right),
+    }
+}
+
+/// Returns `true` if the expression always evaluates to a single Boolean or
+/// empty, so that boolean operators accept it without a type error
+fn is_boolean(expression: &Expression) -> bool {
+    match unparenthesized(expression) {
+        Expression::Term(Term::Literal(literal, _), _) => {
+            matches!(literal, Literal::Boolean(_) | Literal::Null)
+        }
+        Expression::Term(Term::Invocation(Invocation::Function(name, _, _), _), _)
+        | Expression::Invocation(_, Invocation::Function(name, _, _), _) => {
+            BOOLEAN_FUNCTIONS.contains(&name.as_str())
+        }
+        Expression::Type(_, op, _, _) => op == "is",
+        Expression::Inequality(_, _, _, _)
+        | Expression::Equality(_, _, _, _)
+        | Expression::Membership(_, _, _, _)
+        | Expression::And(_, _, _)
+        | Expression::Or(_, _, _, _)
+        | Expression::Implies(_, _, _) => true,
+        _ => false,
+    }
+}
@@ -16,9 +16,14 @@ use serde_json::{Value, json};
 ///   "ExpressionType": "BinaryExpression",
 ///   "Name": "|",
 ///   "Arguments": [...],
-///   "ReturnType": "string[]"
+///   "ReturnType": "string[]",
+///   "Position": 0,
+///   "Length": 31
 /// }
 /// ```
+///
+/// `Position` and `Length` give the byte range of the source text each node
+/// was parsed from, so the node can be highlighted in the expression.
 pub fn expression_to_debug_tree(expr: &Expression, context: &TypeContext) -> Value {
     expression_to_debug_tree_inner(expr, context)
 }
@@ -28,9 +33,9 @@ fn expression_to_debug_tree_inner(expr: &Expression, context: &TypeContext) -> V
     let return_type = infer_expression_type(expr, context).map(|t| t.to_display_string());
 
     let mut node = match expr {
-        Expression::Term(term) => term_to_debug_tree(term, context),
+        Expression::Term(term, _) => term_to_debug_tree(term, context),
 
-        Expression::Invocation(base_expr, invocation) => {
+        Expression::Invocation(base_expr, invocation, _) => {
             // For invocations, we need to handle the structure differently
             // The invocation is the main node, and the base expression is its first argument
             let mut inv_node = invocation_to_debug_tree(invocation, context);
@@ -50,7 +55,7 @@ fn expression_to_debug_tree_inner(expr: &Expression, context: &TypeContext) -> V
             inv_node
         }
 
-        Expression::Indexer(expr, index) => {
+        Expression::Indexer(expr, index, _) => {
             json!({
                 "ExpressionType": "IndexerExpression",
                 "Name": "[]",
@@ -61,7 +66,7 @@ fn expression_to_debug_tree_inner(expr: &Expression, context: &TypeContext) -> V
             })
         }
 
-        Expression::Polarity(op, expr) => {
+        Expression::Polarity(op, expr, _) => {
             json!({
                 "ExpressionType": "UnaryExpression",
                 "Name": op.to_string(),
@@ -69,11 +74,11 @@ fn expression_to_debug_tree_inner(expr: &Expression, context: &TypeContext) -> V
             })
         }
 
-        Expression::Multiplicative(left, op, right)
-        | Expression::Additive(left, op, right)
-        | Expression::Inequality(left, op, right)
-        | Expression::Equality(left, op, right)
-        | Expression::Membership(left, op, right) => {
+        Expression::Multiplicative(left, op, right, _)
+        | Expression::Additive(left, op, right, _)
+        | Expression::Inequality(left, op, right, _)
+        | Expression::Equality(left, op, right, _)
+        | Expression::Membership(left, op, right, _) => {
             json!({
                 "ExpressionType": "BinaryExpression",
                 "Name": op,
@@ -84,7 +89,7 @@ fn expression_to_debug_tree_inner(expr: &Expression, context: &TypeContext) -> V
             })
         }
 
-        Expression::Type(expr, op, type_spec) => {
+        Expression::Type(expr, op, type_spec, _) => {
             json!({
                 "ExpressionType": "TypeExpression",
                 "Name": op,
@@ -95,7 +100,7 @@ fn expression_to_debug_tree_inner(expr: &Expression, context: &TypeContext) -> V
             })
         }
 
-        Expression::Union(left, right) => {
+        Expression::Union(left, right, _) => {
             json!({
                 "ExpressionType": "BinaryExpression",
                 "Name": "|",
@@ -106,7 +111,7 @@ fn expression_to_debug_tree_inner(expr: &Expression, context: &TypeContext) -> V
             })
         }
 
-        Expression::And(left, right) => {
+        Expression::And(left, right, _) => {
             json!({
                 "ExpressionType": "BinaryExpression",
                 "Name": "and",
@@ -117,7 +122,7 @@ fn expression_to_debug_tree_inner(expr: &Expression, context: &TypeContext) -> V
             })
         }
 
-        Expression::Or(left, op, right) => {
+        Expression::Or(left, op, right, _) => {
             json!({
                 "ExpressionType": "BinaryExpression",
                 "Name": op,
@@ -128,7 +133,7 @@ fn expression_to_debug_tree_inner(expr: &Expression, context: &TypeContext) -> V
             })
         }
 
-        Expression::Implies(left, right) => {
+        Expression::Implies(left, right, _) => {
             json!({
                 "ExpressionType": "BinaryExpression",
                 "Name": "implies",
@@ -139,7 +144,7 @@ fn expression_to_debug_tree_inner(expr: &Expression, context: &TypeContext) -> V
             })
         }
 
-        Expression::Lambda(param, expr) => {
+        Expression::Lambda(param, expr, _) => {
             let mut node = json!({
                 "ExpressionType": "LambdaExpression",
                 "Name": "=>",
@@ -157,20 +162,24 @@ fn expression_to_debug_tree_inner(expr: &Expression, context: &TypeContext) -> V
         node["ReturnType"] = json!(rt);
     }
 
+    let span = expr.span();
+    node["Position"] = json!(span.start);
+    node["Length"] = json!(span.len());
+
     node
 }
 
 fn term_to_debug_tree(term: &Term, context: &TypeContext) -> Value {
     match term {
-        Term::Literal(lit) => literal_to_debug_tree(lit),
+        Term::Literal(lit, _) => literal_to_debug_tree(lit),
 
-        Term::Invocation(invocation) => {
+        Term::Invocation(invocation, _) => {
             // For a standalone invocation (e.g., at the start of an expression),
             // we need to add an implicit "builtin.that" as the context
             let mut inv_node = invocation_to_debug_tree(invocation, context);
 
             // Add implicit "that" context as first argument for member access
-            if matches!(invocation, Invocation::Member(_)) {
+            if matches!(invocation, Invocation::Member(_, _)) {
                 let that_node = json!({
                     "ExpressionType": "AxisExpression",
                     "Name": "builtin.that",
@@ -189,7 +198,7 @@ fn term_to_debug_tree(term: &Term, context: &TypeContext) -> Value {
             inv_node
         }
 
-        Term::ExternalConstant(name) => {
+        Term::ExternalConstant(name, _) => {
             let mut node = json!({
                 "ExpressionType": "VariableRefExpression",
                 "Name": name
@@ -203,7 +212,7 @@ fn term_to_debug_tree(term: &Term, context: &TypeContext) -> Value {
             node
         }
 
-        Term::Parenthesized(expr) => expression_to_debug_tree_inner(expr, context),
+        Term::Parenthesized(expr, _) => expression_to_debug_tree_inner(expr, context),
     }
 }
 
@@ -292,7 +301,7 @@ fn literal_to_debug_tree(literal: &Literal) -> Value {
 
 fn invocation_to_debug_tree(invocation: &Invocation, context: &TypeContext) -> Value {
     match invocation {
-        Invocation::Function(name, args) => {
+        Invocation::Function(name, args, _) => {
             let mut node = json!({
                 "ExpressionType": "FunctionCallExpression",
                 "Name": name
@@ -311,7 +320,7 @@ fn invocation_to_debug_tree(invocation: &Invocation, context: &TypeContext) -> V
             node
         }
 
-        Invocation::Member(name) => {
+        Invocation::Member(name, _) => {
             json!({
                 "ExpressionType": "ChildExpression",
                 "Name": name,
@@ -319,21 +328,21 @@ fn invocation_to_debug_tree(invocation: &Invocation, context: &TypeContext) -> V
             })
         }
 
-        Invocation::This => {
+        Invocation::This(_) => {
             json!({
                 "ExpressionType": "AxisExpression",
                 "Name": "builtin.this"
             })
         }
 
-        Invocation::Index => {
+        Invocation::Index(_) => {
             json!({
                 "ExpressionType": "AxisExpression",
                 "Name": "builtin.index"
             })
         }
 
-        Invocation::Total => {
+        Invocation::Total(_) => {
             json!({
                 "ExpressionType": "AxisExpression",
                 "Name": "builtin.total"
@@ -370,74 +379,76 @@ fn generate_parse_debug_inner(expr: &Expression, output: &mut String, indent: us
     let indent_str = "  ".repeat(indent);
 
     match expr {
-        Expression::Term(term) => match term {
-            Term::Literal(lit) => output.push_str(&format!("{}{:?}\n", indent_str, lit)),
-            Term::Invocation(inv) => output.push_str(&format!("{}{:?}\n", indent_str, inv)),
-            Term::ExternalConstant(name) => output.push_str(&format!("{}%{}\n", indent_str, name)),
-            Term::Parenthesized(expr) => {
+        Expression::Term(term, _) => match term {
+            Term::Literal(lit, _) => output.push_str(&format!("{}{:?}\n", indent_str, lit)),
+            Term::Invocation(inv, _) => output.push_str(&format!("{}{:?}\n", indent_str, inv)),
+            Term::ExternalConstant(name, _) => {
+                output.push_str(&format!("{}%{}\n", indent_str, name))
+            }
+            Term::Parenthesized(expr, _) => {
                 output.push_str(&format!("{}(\n", indent_str));
                 generate_parse_debug_inner(expr, output, indent + 1);
                 output.push_str(&format!("{})\n", indent_str));
             }
         },
 
-        Expression::Invocation(expr, inv) => {
+        Expression::Invocation(expr, inv, _) => {
             generate_parse_debug_inner(expr, output, indent);
             output.push_str(&format!("{}.{:?}\n", indent_str, inv));
         }
 
-        Expression::Indexer(expr, index) => {
+        Expression::Indexer(expr, index, _) => {
             generate_parse_debug_inner(expr, output, indent);
             output.push_str(&format!("{}[\n", indent_str));
             generate_parse_debug_inner(index, output, indent + 1);
             output.push_str(&format!("{}]\n", indent_str));
         }
 
-        Expression::Polarity(op, expr) => {
+        Expression::Polarity(op, expr, _) => {
             output.push_str(&format!("{}{}\n", indent_str, op));
             generate_parse_debug_inner(expr, output, indent + 1);
         }
 
-        Expression::Multiplicative(left, op, right)
-        | Expression::Additive(left, op, right)
-        | Expression::Inequality(left, op, right)
-        | Expression::Equality(left, op, right)
-        | Expression::Membership(left, op, right) => {
+        Expression::Multiplicative(left, op, right, _)
+        | Expression::Additive(left, op, right, _)
+        | Expression::Inequality(left, op, right, _)
+        | Expression::Equality(left, op, right, _)
+        | Expression::Membership(left, op, right, _) => {
             generate_parse_debug_inner(left, output, indent);
             output.push_str(&format!("{}{}\n", indent_str, op));
             generate_parse_debug_inner(right, output, indent + 1);
         }
 
-        Expression::Type(expr, op, type_spec) => {
+        Expression::Type(expr, op, type_spec, _) => {
             generate_parse_debug_inner(expr, output, indent);
             output.push_str(&format!("{}{} {:?}\n", indent_str, op, type_spec));
         }
 
-        Expression::Union(left, right) => {
+        Expression::Union(left, right, _) => {
             generate_parse_debug_inner(left, output, indent);
             output.push_str(&format!("{}|\n", indent_str));
             generate_parse_debug_inner(right, output, indent + 1);
         }
 
-        Expression::And(left, right) => {
+        Expression::And(left, right, _) => {
             generate_parse_debug_inner(left, output, indent);
             output.push_str(&format!("{}and\n", indent_str));
             generate_parse_debug_inner(right, output, indent + 1);
         }
 
-        Expression::Or(left, op, right) => {
+        Expression::Or(left, op, right, _) => {
             generate_parse_debug_inner(left, output, indent);
             output.push_str(&format!("{}{}\n", indent_str, op));
             generate_parse_debug_inner(right, output, indent + 1);
         }
 
-        Expression::Implies(left, right) => {
+        Expression::Implies(left, right, _) => {
             generate_parse_debug_inner(left, output, indent);
             output.push_str(&format!("{}implies\n", indent_str));
             generate_parse_debug_inner(right, output, indent + 1);
         }
 
-        Expression::Lambda(param, expr) => {
+        Expression::Lambda(param, expr, _) => {
             if let Some(p) = param {
                 output.push_str(&format!("{}{} =>\n", indent_str, p));
             } else {
@@ -1,11 +1,15 @@
 use chumsky::Parser;
-use chumsky::error::Simple;
+use chumsky::error::{Simple, SimpleReason};
 use chumsky::prelude::*;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
 use std::fmt;
+use std::ops::Range;
 use std::str::FromStr;
 
+/// A byte range in the source text of an expression
+pub type Span = Range<usize>;
+
 /// Represents a literal value in FHIRPath
 ///
 /// This enum represents all the different types of literal values that can appear
@@ -52,65 +56,71 @@ pub enum Literal {
 /// The Expression tree is built during parsing and later evaluated by the evaluator
 /// to produce a result value. The structure preserves operator precedence and
 /// expression nesting as specified in the FHIRPath grammar.
+///
+/// The last field of every variant is the [`Span`] of source text the node was
+/// parsed from, including the base of an invocation or indexer and any
+/// parentheses around the node. [`Term`]s and [`Invocation`]s carry their own
+/// spans, which cover only their own text. Type specifiers share the span of
+/// the expression that holds them.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
     /// A basic term (literal, invocation, etc.)
-    Term(Term),
+    Term(Term, Span),
 
     /// A method or function invocation on an expression
     /// (e.g., `Patient.name.given.first()`)
-    Invocation(Box<Expression>, Invocation),
+    Invocation(Box<Expression>, Invocation, Span),
 
     /// An indexer expression (e.g., `Patient.name[0]`)
-    Indexer(Box<Expression>, Box<Expression>),
+    Indexer(Box<Expression>, Box<Expression>, Span),
 
     /// A unary polarity expression (+ or -)
     /// (e.g., `-5` or `+value`)
-    Polarity(char, Box<Expression>),
+    Polarity(char, Box<Expression>, Span),
 
     /// A multiplicative expression (*, /, div, mod)
     /// (e.g., `value * 2` or `amount div 10`)
-    Multiplicative(Box<Expression>, String, Box<Expression>),
+    Multiplicative(Box<Expression>, String, Box<Expression>, Span),
 
     /// An additive expression (+ or -)
     /// (e.g., `value + 5` or `total - tax`)
-    Additive(Box<Expression>, String, Box<Expression>),
+    Additive(Box<Expression>, String, Box<Expression>, Span),
 
     /// A type operation (is, as)
     /// (e.g., `value is Integer` or `patient as Patient`)
-    Type(Box<Expression>, String, TypeSpecifier),
+    Type(Box<Expression>, String, TypeSpecifier, Span),
 
     /// A union operation (|)
     /// (e.g., `Patient.name | Patient.address`)
-    Union(Box<Expression>, Box<Expression>),
+    Union(Box<Expression>, Box<Expression>, Span),
 
     /// An inequality comparison (<, <=, >, >=)
     /// (e.g., `value > 5` or `date <= today()`)
-    Inequality(Box<Expression>, String, Box<Expression>),
+    Inequality(Box<Expression>, String, Box<Expression>, Span),
 
     /// An equality comparison (=, !=, ~, !~)
     /// (e.g., `name = 'John'` or `birthDate ~ @2020`)
-    Equality(Box<Expression>, String, Box<Expression>),
+    Equality(Box<Expression>, String, Box<Expression>, Span),
 
     /// A membership test (in, contains)
     /// (e.g., `'John' in Patient.name.given` or `Patient.name contains 'John'`)
-    Membership(Box<Expression>, String, Box<Expression>),
+    Membership(Box<Expression>, String, Box<Expression>, Span),
 
     /// A logical AND operation
     /// (e.g., `value > 5 and value < 10`)
-    And(Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>, Span),
 
     /// A logical OR or XOR operation
     /// (e.g., `status = 'active' or status = 'pending'`)
-    Or(Box<Expression>, String, Box<Expression>),
+    Or(Box<Expression>, String, Box<Expression>, Span),
 
     /// A logical IMPLIES operation
     /// (e.g., `exists() implies value > 0`)
-    Implies(Box<Expression>, Box<Expression>),
+    Implies(Box<Expression>, Box<Expression>, Span),
 
     /// A lambda expression with optional identifier
     /// (e.g., `item => item.value > 10`)
-    Lambda(Option<String>, Box<Expression>),
+    Lambda(Option<String>, Box<Expression>, Span),
 }
 
 /// Represents a type specifier in FHIRPath
@@ -154,23 +164,27 @@ pub enum TypeSpecifier {
 ///
 /// Terms can appear alone or as part of more complex expressions,
 /// and they are the starting point for expression evaluation.
+///
+/// The last field of every variant is the [`Span`] of the term's own text,
+/// without any parentheses around it. The span of a literal is the span of
+/// the `Literal` term holding it.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Term {
     /// An invocation, such as a member access, function call, or special identifier
     /// (e.g., `name`, `first()`, `$this`)
-    Invocation(Invocation),
+    Invocation(Invocation, Span),
 
     /// A literal value like a number, string, boolean, or date
     /// (e.g., `42`, `'text'`, `true`, `@2022-01-01`)
-    Literal(Literal),
+    Literal(Literal, Span),
 
     /// An external constant or environment variable reference
     /// (e.g., `%context`, `%ucum`, `%terminologies`)
-    ExternalConstant(String),
+    ExternalConstant(String, Span),
 
     /// A parenthesized expression
     /// (e.g., `(1 + 2)`, `(Patient.name)`)
-    Parenthesized(Box<Expression>),
+    Parenthesized(Box<Expression>, Span),
 }
 
 /// Represents an invocation in a FHIRPath expression
@@ -181,27 +195,31 @@ pub enum Term {
 ///
 /// Invocations are fundamental building blocks in FHIRPath expressions and
 /// are used for navigation, function application, and context references.
+///
+/// The last field of every variant is the [`Span`] of the invocation's own
+/// text: from the start of the name to the closing parenthesis of a function
+/// call, without the base or the `.` before it.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Invocation {
     /// A member access, referencing a property by name
     /// (e.g., `Patient.name`, `Observation.value`)
-    Member(String),
+    Member(String, Span),
 
     /// A function call with optional arguments
     /// (e.g., `first()`, `where(value > 5)`, `substring(2, 5)`)
-    Function(String, Vec<Expression>),
+    Function(String, Vec<Expression>, Span),
 
     /// A reference to the current focus item ($this)
     /// Used in expressions like `$this.name` or in lambda expressions
-    This,
+    This(Span),
 
     /// A reference to the current index ($index)
     /// Used in expressions like `$index > 5` in filtering operations
-    Index,
+    Index(Span),
 
     /// A reference to the current aggregate total ($total)
     /// Used in the aggregate() function to access the running total
-    Total,
+    Total(Span),
 }
 
 // Removed Unit, DateTimePrecision, PluralDateTimePrecision enums
@@ -231,6 +249,365 @@ impl fmt::Display for Literal {
     }
 }
 
+impl Term {
+    /// The span of source text this term was parsed from
+    pub fn span(&self) -> Span {
+        match self {
+            Term::Invocation(_, span)
+            | Term::Literal(_, span)
+            | Term::ExternalConstant(_, span)
+            | Term::Parenthesized(_, span) => span.clone(),
+        }
+    }
+
+    fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Term::Invocation(_, span)
+            | Term::Literal(_, span)
+            | Term::ExternalConstant(_, span)
+            | Term::Parenthesized(_, span) => span,
+        }
+    }
+}
+
+impl Invocation {
+    /// The span of source text this invocation was parsed from
+    pub fn span(&self) -> Span {
+        match self {
+            Invocation::Member(_, span)
+            | Invocation::Function(_, _, span)
+            | Invocation::This(span)
+            | Invocation::Index(span)
+            | Invocation::Total(span) => span.clone(),
+        }
+    }
+
+    fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Invocation::Member(_, span)
+            | Invocation::Function(_, _, span)
+            | Invocation::This(span)
+            | Invocation::Index(span)
+            | Invocation::Total(span) => span,
+        }
+    }
+}
+
+impl Expression {
+    /// The span of source text this expression was parsed from
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Term(_, span)
+            | Expression::Invocation(_, _, span)
+            | Expression::Indexer(_, _, span)
+            | Expression::Polarity(_, _, span)
+            | Expression::Multiplicative(_, _, _, span)
+            | Expression::Additive(_, _, _, span)
+            | Expression::Type(_, _, _, span)
+            | Expression::Union(_, _, span)
+            | Expression::Inequality(_, _, _, span)
+            | Expression::Equality(_, _, _, span)
+            | Expression::Membership(_, _, _, span)
+            | Expression::And(_, _, span)
+            | Expression::Or(_, _, _, span)
+            | Expression::Implies(_, _, span)
+            | Expression::Lambda(_, _, span) => span.clone(),
+        }
+    }
+
+    /// A copy of this expression with every span set to `0..0`
+    ///
+    /// Useful for comparing the structure of expressions parsed from
+    /// differently formatted text.
+    pub fn without_spans(&self) -> Expression {
+        let mut expression = self.clone();
+        clear_spans(&mut expression);
+        expression
+    }
+
+    fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Expression::Term(_, span)
+            | Expression::Invocation(_, _, span)
+            | Expression::Indexer(_, _, span)
+            | Expression::Polarity(_, _, span)
+            | Expression::Multiplicative(_, _, _, span)
+            | Expression::Additive(_, _, _, span)
+            | Expression::Type(_, _, _, span)
+            | Expression::Union(_, _, span)
+            | Expression::Inequality(_, _, _, span)
+            | Expression::Equality(_, _, _, span)
+            | Expression::Membership(_, _, _, span)
+            | Expression::And(_, _, span)
+            | Expression::Or(_, _, _, span)
+            | Expression::Implies(_, _, span)
+            | Expression::Lambda(_, _, span) => span,
+        }
+    }
+
+    /// Replaces the span of this expression
+    pub(crate) fn with_span(mut self, span: Span) -> Self {
+        *self.span_mut() = span;
+        self
+    }
+
+    /// The spans of this expression and of the term or invocation it holds,
+    /// mutably
+    fn spans_mut(&mut self) -> Vec<&mut Span> {
+        match self {
+            Expression::Term(term, span) => {
+                let mut spans = vec![span];
+                match term {
+                    Term::Invocation(invocation, term_span) => {
+                        spans.push(term_span);
+                        spans.push(invocation.span_mut());
+                    }
+                    term => spans.push(term.span_mut()),
+                }
+                spans
+            }
+            Expression::Invocation(_, invocation, span) => vec![span, invocation.span_mut()],
+            expression => vec![expression.span_mut()],
+        }
+    }
+
+    /// The expressions nested directly in this one, mutably
+    fn children_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            Expression::Term(term, _) => match term {
+                Term::Invocation(Invocation::Function(_, args, _), _) => args.iter_mut().collect(),
+                Term::Parenthesized(inner, _) => vec![inner.as_mut()],
+                _ => Vec::new(),
+            },
+            Expression::Invocation(base, invocation, _) => {
+                let mut children = vec![base.as_mut()];
+                if let Invocation::Function(_, args, _) = invocation {
+                    children.extend(args.iter_mut());
+                }
+                children
+            }
+            Expression::Polarity(_, inner, _)
+            | Expression::Type(inner, _, _, _)
+            | Expression::Lambda(_, inner, _) => vec![inner.as_mut()],
+            Expression::Indexer(left, right, _)
+            | Expression::Union(left, right, _)
+            | Expression::And(left, right, _)
+            | Expression::Implies(left, right, _)
+            | Expression::Multiplicative(left, _, right, _)
+            | Expression::Additive(left, _, right, _)
+            | Expression::Inequality(left, _, right, _)
+            | Expression::Equality(left, _, right, _)
+            | Expression::Membership(left, _, right, _)
+            | Expression::Or(left, _, right, _) => vec![left.as_mut(), right.as_mut()],
+        }
+    }
+}
+
+/// Spans a binary expression from the start of `left` to the end of `right`
+fn binary_span(left: &Expression, right: &Expression) -> Span {
+    left.span().start..right.span().end
+}
+
+/// Removes the whitespace the padded parsers include from the spans of an
+/// expression and everything nested in it
+fn trim_spans(expression: &mut Expression, source: &str) {
+    for span in expression.spans_mut() {
+        if let Some(text) = source.get(span.clone()) {
+            let trimmed = text.trim_start();
+            span.start += text.len() - trimmed.len();
+            span.end = span.start + trimmed.trim_end().len();
+        }
+    }
+    for child in expression.children_mut() {
+        trim_spans(child, source);
+    }
+}
+
+/// Sets the spans of an expression and everything nested in it to `0..0`
+fn clear_spans(expression: &mut Expression) {
+    for span in expression.spans_mut() {
+        *span = 0..0;
+    }
+    for child in expression.children_mut() {
+        clear_spans(child);
+    }
+}
+
+/// A failure to parse a FHIRPath expression
+///
+/// Displays as the message, followed by the line of the expression the error
+/// was found on with a caret under it:
+///
+/// ```text
+/// unexpected ')' at line 1, column 14
+///   |
+/// 1 | Patient.name.)
+///   |              ^
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// What went wrong, such as `unexpected ')'`
+    pub message: String,
+    /// Byte range of the input the error was found at
+    pub span: Span,
+    /// Line of the start of `span`, starting at 1
+    pub line: usize,
+    /// Column of the start of `span` in characters, starting at 1
+    pub column: usize,
+    /// The tokens that would have been accepted at `span`, sorted
+    ///
+    /// Characters that start a kind of token are reported as that kind, such
+    /// as `identifier`, `literal` or `operator`; other characters are quoted,
+    /// such as `')'`.
+    pub expected: Vec<String>,
+    /// The line of the source the error was found on
+    source_line: String,
+}
+
+impl ParseError {
+    fn new(error: &Simple<char>, source: &str) -> Self {
+        let span = error.span();
+        let start = span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |index| index + 1);
+        let source_line = source[line_start..]
+            .lines()
+            .next()
+            .unwrap_or("")
+            .to_string();
+
+        let after_percent = source[..start].trim_end().ends_with('%');
+        let expects_operand = error.expected().any(|token| *token == Some('`'));
+        let mut expected: Vec<String> = error
+            .expected()
+            .filter_map(|token| expected_token(*token, after_percent, expects_operand))
+            .collect();
+        expected.sort();
+        expected.dedup();
+        // Errors from keywords span the whole word without a found token
+        let found = match error.found() {
+            Some(c) => Some(format!("{:?}", c)),
+            None => source
+                .get(span.clone())
+                .filter(|text| !text.is_empty())
+                .map(|text| format!("'{}'", text)),
+        };
+
+        let message = match error.reason() {
+            SimpleReason::Custom(message) => message.clone(),
+            SimpleReason::Unclosed { delimiter, .. } => {
+                format!("unclosed delimiter '{}'", delimiter)
+            }
+            SimpleReason::Unexpected => match &found {
+                Some(found) => format!("unexpected {}", found),
+                None => "unexpected end of input".to_string(),
+            },
+        };
+
+        Self {
+            message,
+            line: source[..start].matches('\n').count() + 1,
+            column: source[line_start..start].chars().count() + 1,
+            span,
+            expected,
+            source_line,
+        }
+    }
+}
+
+/// Describes a character the parser expected as the kind of token it starts
+///
+/// The parser only reports the characters it matches exactly, so identifiers,
+/// which it matches by character class, are only seen through the '`' of a
+/// delimited identifier. `after_percent` is set when the error follows a `%`,
+/// where a name is expected, and `expects_operand` where an operand is
+/// expected, so that `+` and `-` are signs rather than operators. Returns None
+/// for characters another kind of token already describes.
+fn expected_token(
+    token: Option<char>,
+    after_percent: bool,
+    expects_operand: bool,
+) -> Option<String> {
+    let description = match token {
+        None => "end of input",
+        Some('`') => "identifier",
+        Some('\'') if after_percent => "identifier",
+        Some('\'' | '@' | '{') => "literal",
+        Some('%') => "external constant",
+        Some('$') => "special variable",
+        // A sign is part of the operand that follows it
+        Some('+' | '-') if expects_operand => return None,
+        Some('!' | '&' | '*' | '/' | '<' | '=' | '>' | '|' | '~' | '+' | '-') => "operator",
+        Some(c) => return Some(format!("{:?}", c)),
+    };
+    Some(description.to_string())
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        match self.expected.as_slice() {
+            [] => {}
+            [token] => write!(f, ", expected {}", token)?,
+            tokens => write!(f, ", expected one of {}", tokens.join(", "))?,
+        }
+        writeln!(f, " at line {}, column {}", self.line, self.column)?;
+
+        // Underline the part of the span on the error's line, at least one character
+        let offset = self
+            .source_line
+            .char_indices()
+            .nth(self.column - 1)
+            .map_or(self.source_line.len(), |(index, _)| index);
+        let end = (offset + self.span.len()).min(self.source_line.len());
+        let width = self
+            .source_line
+            .get(offset..end)
+            .map_or(0, |text| text.chars().count())
+            .max(1);
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.source_line)?;
+        write!(
+            f,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(self.column - 1),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses a FHIRPath expression
+///
+/// Unlike running [`parser`] on a string directly, the spans of the returned
+/// expression are byte offsets that exclude surrounding whitespace, and a
+/// failure is reported as a [`ParseError`] with its position in the source.
+///
+/// ```rust
+/// use helios_fhirpath::parser::parse;
+///
+/// let expression = parse("Patient.name.given").unwrap();
+/// assert_eq!(expression.span(), 0..18);
+///
+/// let error = parse("Patient.name.)").unwrap_err();
+/// assert_eq!((error.line, error.column), (1, 14));
+/// ```
+pub fn parse(source: &str) -> Result<Expression, ParseError> {
+    let tokens = source
+        .char_indices()
+        .map(|(offset, c)| (c, offset..offset + c.len_utf8()));
+    let stream = chumsky::Stream::from_iter(source.len()..source.len(), tokens);
+    match parser().parse(stream) {
+        Ok(mut expression) => {
+            trim_spans(&mut expression, source);
+            Ok(expression)
+        }
+        Err(errors) => Err(ParseError::new(&errors[0], source)),
+    }
+}
+
 /// Creates a parser for FHIRPath expressions
 ///
 /// This function creates and returns a parser that can parse FHIRPath expressions
@@ -253,6 +630,9 @@ impl fmt::Display for Literal {
 /// A parser that can consume a string of characters and produce an Expression
 /// representing the abstract syntax tree (AST) of the parsed FHIRPath expression.
 ///
+/// When given a string, the spans in the tree are character offsets and may
+/// include surrounding whitespace; use [`parse`] for exact byte spans.
+///
 /// # Errors
 ///
 /// The parser returns detailed error information when it encounters syntax errors
@@ -693,7 +1073,7 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
         time_literal.padded(),             // @ T Time (will fail if TZ present)
         date_literal.padded(),             // @Date
     ))
-    .map(Term::Literal);
+    .map_with_span(Term::Literal);
 
     // IDENTIFIER: ([A-Za-z] | '_')([A-Za-z0-9] | '_')*
     let standard_identifier = filter(|c: &char| c.is_ascii_alphabetic() || *c == '_')
@@ -707,7 +1087,7 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
 
     // DELIMITEDIDENTIFIER: '`' (ESC | .)*? '`'
     let delimited_identifier = just('`')
-        .ignore_then(none_of("`").or(esc).repeated().collect::<String>())
+        .ignore_then(none_of("`\\").or(esc).repeated().collect::<String>())
         .then_ignore(just('`'))
         .padded();
 
@@ -791,7 +1171,7 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
     // External constants
     let external_constant = just('%')
         .ignore_then(choice((identifier.clone(), string_for_external)))
-        .map(Term::ExternalConstant)
+        .map_with_span(Term::ExternalConstant)
         .padded();
 
     // Recursive parser definition that directly mirrors the grammar structure
@@ -799,8 +1179,11 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
         // Atom: the most basic elements like literals, identifiers, parenthesized expressions.
         let atom = choice((
             // Box each branch individually to ensure type uniformity for choice
-            literal.clone().map(Expression::Term).boxed(), // Map literal Term to Expression here
-            external_constant.clone().map(Expression::Term).boxed(),
+            literal.clone().map_with_span(Expression::Term).boxed(), // Map literal Term to Expression here
+            external_constant
+                .clone()
+                .map_with_span(Expression::Term)
+                .boxed(),
             // Function call: identifier(...) - Try this *before* simple identifier
             identifier
                 .clone()
@@ -813,24 +1196,26 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
                         .delimited_by(just('(').padded(), just(')').padded()),
                 )
                 // Directly create the Expression::Term(Term::Invocation(...)) structure
-                .map(|(name, params)| {
-                    Expression::Term(Term::Invocation(Invocation::Function(name, params)))
+                .map_with_span(|(name, params), span: Span| {
+                    let invocation = Invocation::Function(name, params, span.clone());
+                    Expression::Term(Term::Invocation(invocation, span.clone()), span)
                 })
                 .boxed(),
             // Simple identifier, $this, $index, $total (parsed if not a function call)
             choice((
-                identifier.clone().map(Invocation::Member),
-                just("$this").to(Invocation::This),
-                just("$index").to(Invocation::Index),
-                just("$total").to(Invocation::Total),
+                identifier.clone().map_with_span(Invocation::Member),
+                just("$this").map_with_span(|_, span| Invocation::This(span)),
+                just("$index").map_with_span(|_, span| Invocation::Index(span)),
+                just("$total").map_with_span(|_, span| Invocation::Total(span)),
             ))
-            .map(Term::Invocation) // Map these simple invocations to Term
-            .map(Expression::Term) // Map Term to Expression
+            .map_with_span(Term::Invocation) // Map these simple invocations to Term
+            .map_with_span(Expression::Term) // Map Term to Expression
             .boxed(),
             // Parenthesized expression
             expr.clone()
                 .delimited_by(just('(').padded(), just(')').padded())
-                // Parenthesized expression directly yields an Expression
+                // Parenthesized expression directly yields an Expression, spanning the parentheses
+                .map_with_span(Expression::with_span)
                 .boxed(),
         ))
         .padded();
@@ -840,33 +1225,40 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
             // Member/Function Invocation: '.' followed by identifier, optionally followed by args (...)
             just('.')
                 .ignore_then(
-                    identifier.clone().then(
-                        // Optionally parse arguments
-                        expr.clone()
-                            .separated_by(just(',').padded())
-                            .allow_trailing()
-                            .collect::<Vec<_>>()
-                            .delimited_by(just('(').padded(), just(')').padded())
-                            .or_not(), // Make arguments optional
-                    ),
+                    identifier
+                        .clone()
+                        .then(
+                            // Optionally parse arguments
+                            expr.clone()
+                                .separated_by(just(',').padded())
+                                .allow_trailing()
+                                .collect::<Vec<_>>()
+                                .delimited_by(just('(').padded(), just(')').padded())
+                                .or_not(), // Make arguments optional
+                        )
+                        // The invocation spans its name and arguments, without the '.'
+                        .map_with_span(|(name, params_opt), span: Span| {
+                            // Create the correct Invocation based on whether params were found
+                            match params_opt {
+                                Some(params) => Invocation::Function(name, params, span),
+                                None => Invocation::Member(name, span),
+                            }
+                        }),
                 )
-                .map(|(name, params_opt)| {
-                    // Create the correct Invocation based on whether params were found
-                    let invocation = match params_opt {
-                        Some(params) => Invocation::Function(name, params),
-                        None => Invocation::Member(name),
-                    };
+                .map_with_span(|invocation, span: Span| {
                     // Return the closure
                     Box::new(move |left: Expression| {
-                        Expression::Invocation(Box::new(left), invocation.clone())
+                        let span = left.span().start..span.end;
+                        Expression::Invocation(Box::new(left), invocation.clone(), span)
                     }) as Box<dyn Fn(Expression) -> Expression>
                 }),
             // Indexer
             expr.clone()
                 .delimited_by(just('[').padded(), just(']').padded())
-                .map(|idx| {
+                .map_with_span(|idx, span: Span| {
                     Box::new(move |left: Expression| {
-                        Expression::Indexer(Box::new(left), Box::new(idx.clone()))
+                        let span = left.span().start..span.end;
+                        Expression::Indexer(Box::new(left), Box::new(idx.clone()), span)
                     }) as Box<dyn Fn(Expression) -> Expression>
                 }),
         ))
@@ -882,12 +1274,17 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
             just::<_, _, Simple<char>>('+').to('+'),
             just::<_, _, Simple<char>>('-').to('-'),
         ))
+        .map_with_span(|op, span: Span| (op, span))
         .padded();
 
-        let term_with_polarity = prefix_op
-            .repeated()
-            .then(atom_with_postfix)
-            .foldr(|op, right| Expression::Polarity(op, Box::new(right)));
+        let term_with_polarity =
+            prefix_op
+                .repeated()
+                .then(atom_with_postfix)
+                .foldr(|(op, op_span), right| {
+                    let span = op_span.start..right.span().end;
+                    Expression::Polarity(op, Box::new(right), span)
+                });
 
         // Infix operators with precedence levels (from high to low)
 
@@ -903,7 +1300,13 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
             .clone()
             .then(op_mul.then(term_with_polarity).repeated())
             .foldl(|left, (op_str, right)| {
-                Expression::Multiplicative(Box::new(left), op_str.to_string(), Box::new(right))
+                let span = binary_span(&left, &right);
+                Expression::Multiplicative(
+                    Box::new(left),
+                    op_str.to_string(),
+                    Box::new(right),
+                    span,
+                )
             });
 
         // Level 2: Additive (+, -, &) - Left associative
@@ -912,7 +1315,8 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
             .clone()
             .then(op_add.then(multiplicative).repeated())
             .foldl(|left, (op_str, right)| {
-                Expression::Additive(Box::new(left), op_str.to_string(), Box::new(right))
+                let span = binary_span(&left, &right);
+                Expression::Additive(Box::new(left), op_str.to_string(), Box::new(right), span)
             });
 
         // Level 3: Union (|) - Left associative (though spec doesn't strictly define associativity here)
@@ -920,15 +1324,27 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
         let union = additive
             .clone()
             .then(op_union.then(additive).repeated())
-            .foldl(|left, (_, right)| Expression::Union(Box::new(left), Box::new(right)));
+            .foldl(|left, (_, right)| {
+                let span = binary_span(&left, &right);
+                Expression::Union(Box::new(left), Box::new(right), span)
+            });
 
         // Level 4: Type (is, as) - Left associative
         let op_type = choice((text::keyword("is").to("is"), text::keyword("as").to("as"))).padded();
         let type_expr = union
             .clone()
-            .then(op_type.then(qualified_identifier.clone()).repeated()) // Type specifier follows 'is'/'as'
-            .foldl(|left, (op_str, type_spec)| {
-                Expression::Type(Box::new(left), op_str.to_string(), type_spec)
+            .then(
+                op_type
+                    .then(
+                        qualified_identifier
+                            .clone()
+                            .map_with_span(|type_spec, span: Span| (type_spec, span)),
+                    )
+                    .repeated(),
+            ) // Type specifier follows 'is'/'as'
+            .foldl(|left, (op_str, (type_spec, type_span))| {
+                let span = left.span().start..type_span.end;
+                Expression::Type(Box::new(left), op_str.to_string(), type_spec, span)
             });
 
         // Level 5: Inequality (<, <=, >, >=) - Left associative
@@ -943,7 +1359,8 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
             .clone()
             .then(op_ineq.then(type_expr).repeated())
             .foldl(|left, (op_str, right)| {
-                Expression::Inequality(Box::new(left), op_str.to_string(), Box::new(right))
+                let span = binary_span(&left, &right);
+                Expression::Inequality(Box::new(left), op_str.to_string(), Box::new(right), span)
             });
 
         // Level 6: Equality (=, ~, !=, !~) - Left associative
@@ -958,7 +1375,8 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
             .clone()
             .then(op_eq.then(inequality).repeated())
             .foldl(|left, (op_str, right)| {
-                Expression::Equality(Box::new(left), op_str.to_string(), Box::new(right))
+                let span = binary_span(&left, &right);
+                Expression::Equality(Box::new(left), op_str.to_string(), Box::new(right), span)
             });
 
         // Level 7: Membership (in, contains) - Left associative
@@ -971,7 +1389,8 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
             .clone()
             .then(op_mem.then(equality).repeated())
             .foldl(|left, (op_str, right)| {
-                Expression::Membership(Box::new(left), op_str.to_string(), Box::new(right))
+                let span = binary_span(&left, &right);
+                Expression::Membership(Box::new(left), op_str.to_string(), Box::new(right), span)
             });
 
         // Level 8: Logical AND (and) - Left associative
@@ -979,7 +1398,10 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
         let logical_and = membership
             .clone()
             .then(op_and.then(membership).repeated())
-            .foldl(|left, (_, right)| Expression::And(Box::new(left), Box::new(right)));
+            .foldl(|left, (_, right)| {
+                let span = binary_span(&left, &right);
+                Expression::And(Box::new(left), Box::new(right), span)
+            });
 
         // Level 9: Logical OR/XOR (or, xor) - Left associative
         let op_or = choice((text::keyword("or").to("or"), text::keyword("xor").to("xor"))).padded();
@@ -987,7 +1409,8 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
             .clone()
             .then(op_or.then(logical_and).repeated())
             .foldl(|left, (op_str, right)| {
-                Expression::Or(Box::new(left), op_str.to_string(), Box::new(right))
+                let span = binary_span(&left, &right);
+                Expression::Or(Box::new(left), op_str.to_string(), Box::new(right), span)
             });
 
         // Level 10: Implies (implies) - Right associative (or handle as non-assoc if simpler)
@@ -998,7 +1421,10 @@ pub fn parser() -> impl Parser<char, Expression, Error = Simple<char>> + Clone {
         logical_or
             .clone()
             .then(op_implies.then(logical_or).repeated())
-            .foldl(|left, (_, right)| Expression::Implies(Box::new(left), Box::new(right)))
+            .foldl(|left, (_, right)| {
+                let span = binary_span(&left, &right);
+                Expression::Implies(Box::new(left), Box::new(right), span)
+            })
     }) // Close the recursive closure here
     .then_ignore(end()) // Ensure the entire input is consumed after the expression
 }
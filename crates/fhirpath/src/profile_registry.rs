@@ -0,0 +1,1225 @@
+//! # Profile Registry
+//!
+//! This module implements the FHIR `conformsTo()` function on top of a
+//! [`ProfileRegistry`] holding StructureDefinition resources. The registry
+//! is attached to the [`EvaluationContext`] and can be loaded from
+//! StructureDefinition JSON files, directories of them, or Bundles.
+//!
+//! Conformance is checked against the `snapshot` of the profile:
+//!
+//! - **Cardinality**: `min` and `max` of every element, counted per parent
+//! - **Types**: the allowed `type.code` values of every element
+//! - **Fixed and pattern values**: `fixed[x]` and `pattern[x]`
+//! - **Constraints**: the FHIRPath `expression` of every constraint with
+//!   severity `error`, evaluated with `%resource` and `%rootResource` set
+//! - **Slicing**: slices selected by `value`, `pattern`, `exists` and `type`
+//!   discriminators, with their own cardinality and rules
+//!
+//! Slices using other discriminators (such as `profile` or `position`) are
+//! not checked. Without a registry entry, the base resource definitions
+//! (`http://hl7.org/fhir/StructureDefinition/<type>`) are checked by type only.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! use helios_fhir::FhirVersion;
+//! use helios_fhirpath::evaluator::EvaluationContext;
+//! use helios_fhirpath::profile_registry::ProfileRegistry;
+//! use std::sync::Arc;
+//!
+//! let mut registry = ProfileRegistry::new(FhirVersion::R4);
+//! registry.load_directory("profiles/us-core").unwrap();
+//!
+//! let mut context = EvaluationContext::new_empty(FhirVersion::R4);
+//! context.set_profile_registry(Arc::new(registry));
+//! ```
+
+use crate::error::{FhirPathError, FhirPathResult};
+use crate::evaluator::{EvaluationContext, evaluate};
+use crate::parser::{Expression, parse};
+use crate::resource_type::{is_fhir_domain_resource, is_resource_type_for_version};
+use crate::result_fields::{collection_items, field, items, string_field};
+use crate::terminology::parse_resource;
+use helios_fhir::{FhirResource, FhirVersion};
+use helios_fhirpath_support::{EvaluationError, EvaluationResult, IntoEvaluationResult};
+use rust_decimal::Decimal;
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// Prefix of the canonical URLs of the base FHIR definitions
+const CORE_PREFIX: &str = "http://hl7.org/fhir/StructureDefinition/";
+
+/// Prefix used by snapshots for FHIRPath System types (such as `Element.id`)
+const SYSTEM_TYPE_PREFIX: &str = "http://hl7.org/fhirpath/System.";
+
+/// Types that share the structure of Quantity
+const QUANTITY_TYPES: [&str; 7] = [
+    "Quantity",
+    "SimpleQuantity",
+    "MoneyQuantity",
+    "Age",
+    "Count",
+    "Distance",
+    "Duration",
+];
+
+/// A constraint of an element definition
+#[derive(Debug, Clone)]
+struct ConstraintRule {
+    key: String,
+    human: String,
+    expression: Result<Expression, String>,
+}
+
+/// A discriminator of a sliced element
+#[derive(Debug, Clone)]
+struct Discriminator {
+    kind: String,
+    path: String,
+}
+
+/// The checks taken from one ElementDefinition of a snapshot
+#[derive(Debug, Clone)]
+struct ElementRule {
+    id: String,
+    /// Property name without the `[x]` suffix (empty for the root element)
+    name: String,
+    slice_name: Option<String>,
+    min: usize,
+    /// None means unbounded (`*`)
+    max: Option<usize>,
+    types: Vec<String>,
+    type_profiles: Vec<String>,
+    fixed: Option<EvaluationResult>,
+    pattern: Option<EvaluationResult>,
+    constraints: Vec<ConstraintRule>,
+    discriminators: Vec<Discriminator>,
+    /// The id of the element whose definition this one reuses (`contentReference`)
+    content_reference: Option<String>,
+    /// The ElementDefinition itself, returned by elementDefinition()
+    definition: EvaluationResult,
+}
+
+impl ElementRule {
+    fn from_result(element: &EvaluationResult) -> Option<Self> {
+        let path = string_field(element, "path")?;
+        let id = string_field(element, "id").unwrap_or_else(|| path.clone());
+        let last_segment = id.rsplit('.').next().unwrap_or(&id);
+        let (name, slice_name) = match last_segment.split_once(':') {
+            Some((name, slice)) => (name, Some(slice.to_string())),
+            None => (last_segment, None),
+        };
+        let name = if id.contains('.') {
+            name.trim_end_matches("[x]").to_string()
+        } else {
+            String::new()
+        };
+
+        let min = field(element, "min")
+            .and_then(|min| min.as_integer())
+            .map_or(0, |min| min.max(0) as usize);
+        let max = match string_field(element, "max").as_deref() {
+            Some("*") | None => None,
+            Some(max) => max.parse().ok(),
+        };
+
+        let mut types = Vec::new();
+        let mut type_profiles = Vec::new();
+        for element_type in items(field(element, "type")) {
+            if let Some(code) = string_field(element_type, "code") {
+                types.push(code);
+            }
+            type_profiles.extend(
+                items(field(element_type, "profile"))
+                    .into_iter()
+                    .filter_map(|profile| profile.as_string().cloned()),
+            );
+        }
+
+        let constraints = items(field(element, "constraint"))
+            .into_iter()
+            .filter(|constraint| string_field(constraint, "severity").as_deref() == Some("error"))
+            .filter_map(|constraint| {
+                let expression = string_field(constraint, "expression")?;
+                Some(ConstraintRule {
+                    key: string_field(constraint, "key").unwrap_or_default(),
+                    human: string_field(constraint, "human").unwrap_or_default(),
+                    expression: parse(&expression)
+                        .map_err(|e| format!("Failed to parse '{}': {}", expression, e)),
+                })
+            })
+            .collect();
+
+        let discriminators =
+            items(field(element, "slicing").and_then(|s| field(s, "discriminator")))
+                .into_iter()
+                .filter_map(|discriminator| {
+                    Some(Discriminator {
+                        kind: string_field(discriminator, "type")?,
+                        path: string_field(discriminator, "path")?,
+                    })
+                })
+                .collect();
+
+        Some(Self {
+            id,
+            name,
+            slice_name,
+            min,
+            max,
+            types,
+            type_profiles,
+            fixed: non_empty(field(element, "fixed")),
+            pattern: non_empty(field(element, "pattern")),
+            constraints,
+            discriminators,
+            content_reference: string_field(element, "contentReference")
+                .and_then(|reference| reference.rsplit('#').next().map(str::to_string)),
+            definition: element.clone(),
+        })
+    }
+
+    /// The last id segment without its slice name
+    ///
+    /// For example `value[x]` for `Observation.value[x]:valueQuantity`.
+    fn id_segment_name(&self) -> &str {
+        let last_segment = self.id.rsplit('.').next().unwrap_or(&self.id);
+        last_segment.split(':').next().unwrap_or(last_segment)
+    }
+}
+
+/// A loaded StructureDefinition
+#[derive(Debug, Clone)]
+struct Profile {
+    /// The type constrained by the profile
+    type_name: String,
+    /// The snapshot elements, or None when the profile has no snapshot
+    elements: Option<Vec<ElementRule>>,
+    /// Indexes of the elements by element id
+    by_id: HashMap<String, usize>,
+    /// Indexes of the child elements by the id of their parent
+    children: HashMap<String, Vec<usize>>,
+}
+
+impl Profile {
+    fn from_result(resource: &EvaluationResult) -> Option<Self> {
+        let type_name = string_field(resource, "type")?;
+        let elements = field(resource, "snapshot").map(|snapshot| {
+            items(field(snapshot, "element"))
+                .into_iter()
+                .filter_map(ElementRule::from_result)
+                .collect::<Vec<_>>()
+        });
+
+        let mut by_id = HashMap::new();
+        let mut children: HashMap<String, Vec<usize>> = HashMap::new();
+        for (index, element) in elements.iter().flatten().enumerate() {
+            by_id.insert(element.id.clone(), index);
+            if let Some((parent, _)) = element.id.rsplit_once('.') {
+                children.entry(parent.to_string()).or_default().push(index);
+            }
+        }
+
+        Some(Self {
+            type_name,
+            elements,
+            by_id,
+            children,
+        })
+    }
+
+    fn element(&self, id: &str) -> Option<&ElementRule> {
+        let elements = self.elements.as_ref()?;
+        self.by_id.get(id).map(|index| &elements[*index])
+    }
+
+    /// The element describing the constrained type itself
+    fn root(&self) -> Option<&ElementRule> {
+        self.elements
+            .as_ref()?
+            .iter()
+            .find(|element| element.name.is_empty())
+    }
+
+    /// The unsliced child element of `parent` with the given property name
+    fn child(&self, parent: &ElementRule, name: &str) -> Option<&ElementRule> {
+        let elements = self.elements.as_ref()?;
+        let parent_id = parent.content_reference.as_deref().unwrap_or(&parent.id);
+        self.children
+            .get(parent_id)?
+            .iter()
+            .map(|index| &elements[*index])
+            .find(|child| child.name == name && child.slice_name.is_none())
+    }
+}
+
+/// A reason why a value does not conform to a profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConformanceIssue {
+    /// The id of the element definition that was violated
+    pub element: String,
+    /// A description of the violation
+    pub message: String,
+}
+
+impl fmt::Display for ConformanceIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.element, self.message)
+    }
+}
+
+/// Registry of StructureDefinition profiles used by conformsTo()
+///
+/// Profiles are keyed by their canonical `url`. References to a profile
+/// may carry a version (`url|version`), which is ignored when no profile
+/// is registered under the full reference.
+#[derive(Debug, Clone)]
+pub struct ProfileRegistry {
+    fhir_version: FhirVersion,
+    profiles: HashMap<String, Profile>,
+}
+
+impl ProfileRegistry {
+    /// Creates an empty registry for profiles of the given FHIR version
+    pub fn new(fhir_version: FhirVersion) -> Self {
+        Self {
+            fhir_version,
+            profiles: HashMap::new(),
+        }
+    }
+
+    /// Loads a StructureDefinition, or the StructureDefinitions in a Bundle
+    ///
+    /// Other resources, and StructureDefinitions without a `url` or `type`,
+    /// are ignored.
+    pub fn add_resource(&mut self, resource: &FhirResource) {
+        self.insert_result(resource.to_evaluation_result());
+    }
+
+    /// Loads an already converted StructureDefinition or Bundle
+    pub fn insert_result(&mut self, resource: EvaluationResult) {
+        match string_field(&resource, "resourceType").as_deref() {
+            Some("Bundle") => {
+                for entry in items(field(&resource, "entry")) {
+                    if let Some(entry_resource) = field(entry, "resource") {
+                        self.insert_result(entry_resource.clone());
+                    }
+                }
+            }
+            Some("StructureDefinition") => {
+                let Some(url) = string_field(&resource, "url") else {
+                    return;
+                };
+                if let Some(profile) = Profile::from_result(&resource) {
+                    self.profiles.insert(url, profile);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Loads the StructureDefinitions from a JSON resource
+    ///
+    /// # Returns
+    ///
+    /// The number of profiles in the registry after loading
+    pub fn load_json(&mut self, json: &str) -> FhirPathResult<usize> {
+        let value = serde_json::from_str(json)?;
+        let resource =
+            parse_resource(value, self.fhir_version).map_err(FhirPathError::InvalidInput)?;
+        self.add_resource(&resource);
+        Ok(self.len())
+    }
+
+    /// Loads the StructureDefinitions from a JSON file
+    ///
+    /// # Returns
+    ///
+    /// The number of profiles in the registry after loading
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> FhirPathResult<usize> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)?;
+        self.load_json(&json).map_err(|e| {
+            FhirPathError::InvalidInput(format!("Failed to load '{}': {}", path.display(), e))
+        })
+    }
+
+    /// Loads the StructureDefinitions from all `.json` files in a directory
+    ///
+    /// # Returns
+    ///
+    /// The number of profiles in the registry after loading
+    pub fn load_directory(&mut self, path: impl AsRef<Path>) -> FhirPathResult<usize> {
+        let mut files = std::fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        files.sort();
+        for file in files
+            .iter()
+            .filter(|file| file.extension().is_some_and(|ext| ext == "json"))
+        {
+            self.load_file(file)?;
+        }
+        Ok(self.len())
+    }
+
+    /// Returns true if a profile is registered under the given URL
+    pub fn contains(&self, url: &str) -> bool {
+        self.profile(url).is_some()
+    }
+
+    /// Returns the number of registered profiles
+    pub fn len(&self) -> usize {
+        self.profiles.len()
+    }
+
+    /// Returns true if no profiles are registered
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+
+    fn profile(&self, url: &str) -> Option<&Profile> {
+        self.profiles.get(url).or_else(|| {
+            url.split_once('|')
+                .and_then(|(url, _)| self.profiles.get(url))
+        })
+    }
+
+    /// Checks a value against a registered profile
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The resource (or data type) to check
+    /// * `url` - The canonical URL of the profile
+    /// * `context` - The context used to evaluate the profile's constraints
+    ///
+    /// # Returns
+    ///
+    /// * The violations found, which is empty if the value conforms
+    /// * `Err` - If the profile is unknown, has no snapshot, or one of its
+    ///   constraints cannot be evaluated
+    pub fn check_conformance(
+        &self,
+        value: &EvaluationResult,
+        url: &str,
+        context: &EvaluationContext,
+    ) -> Result<Vec<ConformanceIssue>, EvaluationError> {
+        let profile = self.profile(url).ok_or_else(|| {
+            EvaluationError::InvalidArgument(format!("Unknown profile '{}'", url))
+        })?;
+        let Some(elements) = profile.elements.as_ref() else {
+            return Err(EvaluationError::InvalidArgument(format!(
+                "Profile '{}' has no snapshot",
+                url
+            )));
+        };
+
+        let mut checker = ConformanceChecker {
+            profile,
+            constraint_context: constraint_context(value, context),
+            issues: Vec::new(),
+        };
+        if !root_matches(value, &profile.type_name) {
+            checker.issue(
+                &profile.type_name,
+                format!("Expected a {}", profile.type_name),
+            );
+            return Ok(checker.issues);
+        }
+
+        match elements.iter().find(|element| element.name.is_empty()) {
+            Some(root) => {
+                checker.check_value(root, value)?;
+                checker.check_children(&root.id, &[value])?;
+            }
+            None => checker.check_children(&profile.type_name, &[value])?,
+        }
+        Ok(checker.issues)
+    }
+
+    /// Finds the ElementDefinition describing a value
+    ///
+    /// The value is located within the `roots` (usually the resource being
+    /// evaluated) and its path is followed through the base definition of the
+    /// resource type, continuing into the definitions of the data types it
+    /// passes through. Values are located by equality, so a value that occurs
+    /// in several elements is taken to be the first of them. A value that is
+    /// not found in any root is described by the root element of its type.
+    ///
+    /// # Returns
+    ///
+    /// The ElementDefinition, or None if the value cannot be located or the
+    /// definitions it needs are not registered
+    pub fn element_definition<'r>(
+        &self,
+        value: &EvaluationResult,
+        roots: impl IntoIterator<Item = &'r EvaluationResult>,
+    ) -> Option<EvaluationResult> {
+        let located = roots.into_iter().find_map(|root| match root == value {
+            true => string_field(root, "resourceType").map(|type_name| (type_name, Vec::new())),
+            false => locate(root, value).and_then(|(type_name, path)| Some((type_name?, path))),
+        });
+        let Some((type_name, path)) = located else {
+            let type_name = match value {
+                EvaluationResult::Object {
+                    type_info: Some(type_info),
+                    ..
+                } if type_info.namespace == "FHIR" => type_info.name.clone(),
+                _ => string_field(value, "resourceType")?,
+            };
+            let root = self
+                .profile(&format!("{}{}", CORE_PREFIX, type_name))?
+                .root()?;
+            return Some(root.definition.clone());
+        };
+
+        let mut profile = self.profile(&format!("{}{}", CORE_PREFIX, type_name))?;
+        let mut element = profile.root()?;
+        let mut parent_value = None;
+        for (name, child_value) in path {
+            let child = match profile.child(element, name) {
+                Some(child) => child,
+                None => {
+                    // Data type elements are defined by the data type itself
+                    let data_type = match element.types.as_slice() {
+                        [data_type] => data_type.clone(),
+                        _ => value_type_name(parent_value?),
+                    };
+                    profile = self.profile(&format!("{}{}", CORE_PREFIX, data_type))?;
+                    profile.child(profile.root()?, name)?
+                }
+            };
+            element = child;
+            parent_value = Some(child_value);
+        }
+        Some(element.definition.clone())
+    }
+
+    /// Checks whether a value belongs to a named slice of a profile
+    ///
+    /// Membership is decided by the discriminators of the sliced element.
+    /// When they are not supported, the value must conform to the rules of
+    /// the slice instead. If several elements define a slice with the same
+    /// name, the first is used.
+    ///
+    /// # Returns
+    ///
+    /// * Whether the value is a member of the slice
+    /// * `Err` - If the profile is unknown, has no snapshot or has no such slice
+    pub fn is_slice_member(
+        &self,
+        value: &EvaluationResult,
+        url: &str,
+        slice_name: &str,
+        context: &EvaluationContext,
+    ) -> Result<bool, EvaluationError> {
+        let profile = self.profile(url).ok_or_else(|| {
+            EvaluationError::InvalidArgument(format!("Unknown profile '{}'", url))
+        })?;
+        let Some(elements) = profile.elements.as_ref() else {
+            return Err(EvaluationError::InvalidArgument(format!(
+                "Profile '{}' has no snapshot",
+                url
+            )));
+        };
+        let no_slice = || {
+            EvaluationError::InvalidArgument(format!(
+                "Profile '{}' has no slice named '{}'",
+                url, slice_name
+            ))
+        };
+        let slice = elements
+            .iter()
+            .find(|element| element.slice_name.as_deref() == Some(slice_name))
+            .ok_or_else(no_slice)?;
+        let base = slice
+            .id
+            .rsplit_once(':')
+            .and_then(|(base_id, _)| profile.element(base_id))
+            .ok_or_else(no_slice)?;
+
+        if let Some(member) = in_slice(profile, base, slice, value) {
+            return Ok(member);
+        }
+        let roots = RootResources::new(context);
+        let resource = roots.iter().next().unwrap_or(value);
+        let mut checker = ConformanceChecker {
+            profile,
+            constraint_context: constraint_context(resource, context),
+            issues: Vec::new(),
+        };
+        checker.check_value(slice, value)?;
+        checker.check_children(&slice.id, &[value])?;
+        Ok(checker.issues.is_empty())
+    }
+}
+
+/// Walks the snapshot of a profile over a value, collecting the violations
+struct ConformanceChecker<'a> {
+    profile: &'a Profile,
+    constraint_context: EvaluationContext,
+    issues: Vec<ConformanceIssue>,
+}
+
+impl ConformanceChecker<'_> {
+    fn issue(&mut self, element: &str, message: String) {
+        self.issues.push(ConformanceIssue {
+            element: element.to_string(),
+            message,
+        });
+    }
+
+    /// Checks the child elements of `parent_id` on each of the parent values
+    fn check_children(
+        &mut self,
+        parent_id: &str,
+        parents: &[&EvaluationResult],
+    ) -> Result<(), EvaluationError> {
+        let profile = self.profile;
+        let Some(elements) = profile.elements.as_ref() else {
+            return Ok(());
+        };
+        let Some(children) = profile.children.get(parent_id) else {
+            return Ok(());
+        };
+
+        'elements: for element in children.iter().map(|index| &elements[*index]) {
+            let slice = match &element.slice_name {
+                Some(_) => {
+                    let base_id = format!("{}.{}", parent_id, element.id_segment_name());
+                    let Some(base) = profile.element(&base_id) else {
+                        continue;
+                    };
+                    Some(base)
+                }
+                None => None,
+            };
+
+            let mut checked = Vec::new();
+            for parent in parents {
+                let mut values = items(field(parent, &element.name));
+                if let Some(base) = slice {
+                    let mut members = Vec::new();
+                    for value in values {
+                        match in_slice(profile, base, element, value) {
+                            Some(true) => members.push(value),
+                            Some(false) => {}
+                            // Members of the slice cannot be told apart, so it is not checked
+                            None => continue 'elements,
+                        }
+                    }
+                    values = members;
+                }
+                self.check_cardinality(element, values.len());
+                checked.extend(values);
+            }
+
+            for value in &checked {
+                self.check_value(element, value)?;
+            }
+            if !checked.is_empty() {
+                self.check_children(&element.id, &checked)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_cardinality(&mut self, element: &ElementRule, count: usize) {
+        if count < element.min {
+            self.issue(
+                &element.id,
+                format!(
+                    "minimum required = {}, but only found {}",
+                    element.min, count
+                ),
+            );
+        }
+        if let Some(max) = element.max.filter(|max| count > *max) {
+            self.issue(
+                &element.id,
+                format!("maximum allowed = {}, but found {}", max, count),
+            );
+        }
+    }
+
+    fn check_value(
+        &mut self,
+        element: &ElementRule,
+        value: &EvaluationResult,
+    ) -> Result<(), EvaluationError> {
+        if !element.types.is_empty()
+            && !element
+                .types
+                .iter()
+                .any(|type_code| type_matches(value, type_code))
+        {
+            self.issue(
+                &element.id,
+                format!(
+                    "type {} is not allowed (expected {})",
+                    value_type_name(value),
+                    element.types.join(" | ")
+                ),
+            );
+        }
+        if let Some(fixed) = element
+            .fixed
+            .as_ref()
+            .filter(|fixed| !values_equal(value, fixed))
+        {
+            self.issue(
+                &element.id,
+                format!("value must be exactly {}", fixed.to_string_value()),
+            );
+        }
+        if let Some(pattern) = element
+            .pattern
+            .as_ref()
+            .filter(|pattern| !pattern_matches(value, pattern))
+        {
+            self.issue(
+                &element.id,
+                format!(
+                    "value does not match the pattern {}",
+                    pattern.to_string_value()
+                ),
+            );
+        }
+
+        for constraint in &element.constraints {
+            let expression = constraint.expression.as_ref().map_err(|e| {
+                EvaluationError::InvalidArgument(format!(
+                    "Constraint {} of '{}': {}",
+                    constraint.key, element.id, e
+                ))
+            })?;
+            let result =
+                evaluate(expression, &self.constraint_context, Some(value)).map_err(|e| {
+                    EvaluationError::InvalidOperation(format!(
+                        "Failed to evaluate constraint {} of '{}': {}",
+                        constraint.key, element.id, e
+                    ))
+                })?;
+            if !is_true(&result) {
+                self.issue(
+                    &element.id,
+                    format!("constraint {} failed: {}", constraint.key, constraint.human),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds the context in which constraints are evaluated
+fn constraint_context(
+    resource: &EvaluationResult,
+    context: &EvaluationContext,
+) -> EvaluationContext {
+    let mut constraint_context = EvaluationContext::new_empty(context.fhir_version);
+    constraint_context.variables = context.variables.clone();
+    constraint_context.reference_resolver = context.reference_resolver.clone();
+    constraint_context.terminology_provider = context.terminology_provider.clone();
+    constraint_context.profile_registry = context.profile_registry.clone();
+    constraint_context.set_variable_result("resource", resource.clone());
+    constraint_context.set_variable_result("rootResource", resource.clone());
+    constraint_context
+}
+
+/// Decides whether a value belongs to a slice
+///
+/// Returns None when the slice uses a discriminator that is not supported.
+fn in_slice(
+    profile: &Profile,
+    base: &ElementRule,
+    slice: &ElementRule,
+    value: &EvaluationResult,
+) -> Option<bool> {
+    if base.discriminators.is_empty() {
+        return None;
+    }
+    for discriminator in &base.discriminators {
+        if discriminator.path.contains('(') {
+            return None;
+        }
+        let (target, actual) = if discriminator.path == "$this" {
+            (Some(slice), vec![value])
+        } else {
+            let target_id = format!("{}.{}", slice.id, discriminator.path);
+            (
+                profile.element(&target_id),
+                navigate(value, &discriminator.path),
+            )
+        };
+
+        let matches = match discriminator.kind.as_str() {
+            "value" | "pattern" | "fixed" => {
+                match target.and_then(|target| target.fixed.as_ref().or(target.pattern.as_ref())) {
+                    Some(expected) => actual
+                        .iter()
+                        .any(|actual| pattern_matches(actual, expected)),
+                    // Extension slices often only name the extension definition
+                    None if discriminator.path == "url" => {
+                        let url = slice.type_profiles.first()?;
+                        actual.iter().any(|actual| actual.as_string() == Some(url))
+                    }
+                    None => return None,
+                }
+            }
+            "exists" => {
+                let target = target?;
+                if target.max == Some(0) {
+                    actual.is_empty()
+                } else if target.min > 0 {
+                    !actual.is_empty()
+                } else {
+                    return None;
+                }
+            }
+            "type" => {
+                let target = target?;
+                actual.iter().any(|actual| {
+                    target
+                        .types
+                        .iter()
+                        .any(|type_code| type_matches(actual, type_code))
+                })
+            }
+            _ => return None,
+        };
+        if !matches {
+            return Some(false);
+        }
+    }
+    Some(true)
+}
+
+/// Follows a dotted element path from a value
+fn navigate<'a>(value: &'a EvaluationResult, path: &str) -> Vec<&'a EvaluationResult> {
+    path.split('.').fold(vec![value], |current, name| {
+        let name = name.trim_end_matches("[x]");
+        current
+            .into_iter()
+            .flat_map(|value| items(field(value, name)))
+            .collect()
+    })
+}
+
+/// The names and values of the elements leading to a value
+type ElementPath<'a> = Vec<(&'a str, &'a EvaluationResult)>;
+
+/// Finds the path of `target` within `node`
+///
+/// Returns the names and values of the elements leading to the target, from
+/// the innermost resource containing it, together with that resource's type.
+/// The type is None when no resource between `node` and the target was found.
+fn locate<'a>(
+    node: &'a EvaluationResult,
+    target: &EvaluationResult,
+) -> Option<(Option<String>, ElementPath<'a>)> {
+    let EvaluationResult::Object { map, .. } = node else {
+        return None;
+    };
+    // Search the properties in a stable order
+    let mut names: Vec<&String> = map.keys().filter(|name| *name != "resourceType").collect();
+    names.sort();
+
+    let (type_name, path) = names.into_iter().find_map(|name| {
+        items(map.get(name)).into_iter().find_map(|child| {
+            if child == target {
+                return Some((None, vec![(name.as_str(), child)]));
+            }
+            let (type_name, mut path) = locate(child, target)?;
+            if type_name.is_none() {
+                path.insert(0, (name.as_str(), child));
+            }
+            Some((type_name, path))
+        })
+    })?;
+    Some((
+        type_name.or_else(|| string_field(node, "resourceType")),
+        path,
+    ))
+}
+
+/// The resources values are located in: %resource, or else the context resources
+///
+/// The first resource is the context's converted root. The other resources are
+/// only converted when a search gets past the first one.
+struct RootResources<'a> {
+    context: &'a EvaluationContext,
+    resource: Option<EvaluationResult>,
+    others: OnceCell<Vec<EvaluationResult>>,
+}
+
+impl<'a> RootResources<'a> {
+    fn new(context: &'a EvaluationContext) -> Self {
+        Self {
+            context,
+            resource: context.lookup_variable("resource"),
+            others: OnceCell::new(),
+        }
+    }
+
+    /// The roots in search order, ending with the focus when it overrides them
+    fn iter(&self) -> Box<dyn Iterator<Item = &EvaluationResult> + '_> {
+        if let Some(resource) = &self.resource {
+            return Box::new(std::iter::once(resource));
+        }
+        let first = self.context.root_resource_result();
+        let others = std::iter::once_with(|| {
+            self.others.get_or_init(|| {
+                self.context
+                    .resources
+                    .iter()
+                    .skip(1)
+                    .map(|resource| resource.to_evaluation_result())
+                    .collect()
+            })
+        })
+        .flatten();
+        let focus = self
+            .context
+            .root_item()
+            .filter(|root| !first.is_some_and(|first| std::ptr::eq(*root, first)));
+        Box::new(first.into_iter().chain(others).chain(focus))
+    }
+}
+
+/// Checks that the input is the resource or data type constrained by a profile
+fn root_matches(value: &EvaluationResult, type_name: &str) -> bool {
+    match string_field(value, "resourceType") {
+        Some(resource_type) => resource_type == type_name,
+        None => type_matches(value, type_name),
+    }
+}
+
+/// Checks a value against an ElementDefinition type code
+fn type_matches(value: &EvaluationResult, type_code: &str) -> bool {
+    let type_code = type_code
+        .strip_prefix(SYSTEM_TYPE_PREFIX)
+        .map(str::to_lowercase)
+        .unwrap_or_else(|| type_code.to_string());
+
+    match value {
+        EvaluationResult::Object { map, type_info } => {
+            if let Some(resource_type) = map.get("resourceType").and_then(|r| r.as_string()) {
+                return match type_code.as_str() {
+                    "Resource" => true,
+                    "DomainResource" => is_fhir_domain_resource(resource_type),
+                    _ => *resource_type == type_code,
+                };
+            }
+            match type_info {
+                Some(type_info) => {
+                    type_info.name == type_code
+                        || type_code == "Element"
+                        || type_code == "BackboneElement"
+                        || (QUANTITY_TYPES.contains(&type_info.name.as_str())
+                            && QUANTITY_TYPES.contains(&type_code.as_str()))
+                }
+                None => !is_primitive_type(&type_code),
+            }
+        }
+        EvaluationResult::Quantity(..) => QUANTITY_TYPES.contains(&type_code.as_str()),
+        EvaluationResult::Boolean(..) => type_code == "boolean",
+        EvaluationResult::Integer(..) | EvaluationResult::Integer64(..) => matches!(
+            type_code.as_str(),
+            "integer" | "integer64" | "positiveInt" | "unsignedInt"
+        ),
+        EvaluationResult::Decimal(..) => type_code == "decimal",
+        EvaluationResult::Date(..) => matches!(type_code.as_str(), "date" | "dateTime"),
+        EvaluationResult::DateTime(..) => matches!(type_code.as_str(), "dateTime" | "instant"),
+        EvaluationResult::Time(..) => type_code == "time",
+        // Choice values of string-based types are not always converted with
+        // their exact type, so any string-based primitive is accepted
+        EvaluationResult::String(..) => {
+            is_primitive_type(&type_code)
+                && !matches!(
+                    type_code.as_str(),
+                    "boolean" | "integer" | "integer64" | "positiveInt" | "unsignedInt" | "decimal"
+                )
+        }
+        EvaluationResult::Empty | EvaluationResult::Collection { .. } => false,
+    }
+}
+
+fn is_primitive_type(type_code: &str) -> bool {
+    type_code.starts_with(|c: char| c.is_ascii_lowercase())
+}
+
+fn value_type_name(value: &EvaluationResult) -> String {
+    match value {
+        EvaluationResult::Object { map, type_info } => map
+            .get("resourceType")
+            .and_then(|r| r.as_string())
+            .cloned()
+            .or_else(|| type_info.as_ref().map(|t| t.name.clone()))
+            .unwrap_or_else(|| "Object".to_string()),
+        EvaluationResult::Boolean(_, Some(t))
+        | EvaluationResult::String(_, Some(t))
+        | EvaluationResult::Decimal(_, Some(t))
+        | EvaluationResult::Integer(_, Some(t))
+        | EvaluationResult::Integer64(_, Some(t))
+        | EvaluationResult::Date(_, Some(t))
+        | EvaluationResult::DateTime(_, Some(t))
+        | EvaluationResult::Time(_, Some(t)) => t.name.clone(),
+        other => other.type_name().to_string(),
+    }
+}
+
+/// Compares a value with a `fixed[x]` value
+///
+/// Objects must have the same properties and collections the same items in
+/// the same order. Type annotations are ignored.
+fn values_equal(value: &EvaluationResult, fixed: &EvaluationResult) -> bool {
+    match (value, fixed) {
+        (EvaluationResult::Object { map, .. }, EvaluationResult::Object { map: fixed, .. }) => {
+            map.len() == fixed.len()
+                && fixed.iter().all(|(key, fixed_value)| {
+                    map.get(key)
+                        .is_some_and(|value| values_equal(value, fixed_value))
+                })
+        }
+        (EvaluationResult::Object { .. }, _) | (_, EvaluationResult::Object { .. }) => false,
+        _ => {
+            let values = collection_items(value);
+            let fixed = collection_items(fixed);
+            if values.len() != 1 || fixed.len() != 1 {
+                return values.len() == fixed.len()
+                    && values.iter().zip(&fixed).all(|(a, b)| values_equal(a, b));
+            }
+            primitives_equal(values[0], fixed[0])
+        }
+    }
+}
+
+/// Compares a value with a `pattern[x]` value
+///
+/// Every property of the pattern must be present with a matching value, and
+/// every item of a pattern collection must match some item of the value.
+fn pattern_matches(value: &EvaluationResult, pattern: &EvaluationResult) -> bool {
+    match (value, pattern) {
+        (EvaluationResult::Object { map, .. }, EvaluationResult::Object { map: pattern, .. }) => {
+            pattern.iter().all(|(key, pattern_value)| {
+                map.get(key)
+                    .is_some_and(|value| pattern_matches(value, pattern_value))
+            })
+        }
+        (EvaluationResult::Collection { .. }, _) | (_, EvaluationResult::Collection { .. }) => {
+            let values = collection_items(value);
+            collection_items(pattern).into_iter().all(|pattern_item| {
+                values
+                    .iter()
+                    .any(|value| pattern_matches(value, pattern_item))
+            })
+        }
+        (EvaluationResult::Object { .. }, _) | (_, EvaluationResult::Object { .. }) => false,
+        _ => primitives_equal(value, pattern),
+    }
+}
+
+fn primitives_equal(value: &EvaluationResult, expected: &EvaluationResult) -> bool {
+    match (number(value), number(expected)) {
+        (Some(value), Some(expected)) => value == expected,
+        (None, None) => value.to_string_value() == expected.to_string_value(),
+        _ => false,
+    }
+}
+
+fn number(value: &EvaluationResult) -> Option<Decimal> {
+    match value {
+        EvaluationResult::Integer(i, _) | EvaluationResult::Integer64(i, _) => {
+            Some(Decimal::from(*i))
+        }
+        EvaluationResult::Decimal(d, _) => Some(*d),
+        _ => None,
+    }
+}
+
+fn is_true(result: &EvaluationResult) -> bool {
+    match result {
+        EvaluationResult::Boolean(value, _) => *value,
+        EvaluationResult::Collection { items, .. } if items.len() == 1 => is_true(&items[0]),
+        _ => false,
+    }
+}
+
+/// Implementation of the FHIR conformsTo() function
+///
+/// Syntax: conformsTo(structure : String) : Boolean
+///
+/// Returns true if the single input conforms to the profile with the given
+/// canonical URL. Profiles are looked up in the [`ProfileRegistry`] of the
+/// context; the base resource definitions are checked by type when they are
+/// not registered.
+///
+/// # Arguments
+///
+/// * `invocation_base` - The resource or element to check
+/// * `args` - The canonical URL of the profile
+/// * `context` - The evaluation context providing the profile registry
+///
+/// # Returns
+///
+/// * A Boolean, or Empty if the input or URL is empty
+/// * `Err` - If the input has more than one item or the profile is unknown
+pub fn conforms_to_function(
+    invocation_base: &EvaluationResult,
+    args: &[EvaluationResult],
+    context: &EvaluationContext,
+) -> Result<EvaluationResult, EvaluationError> {
+    let value = match invocation_base {
+        EvaluationResult::Empty => return Ok(EvaluationResult::Empty),
+        EvaluationResult::Collection { items, .. } if items.is_empty() => {
+            return Ok(EvaluationResult::Empty);
+        }
+        EvaluationResult::Collection { items, .. } if items.len() > 1 => {
+            return Err(EvaluationError::SingletonEvaluationError(
+                "conformsTo requires a singleton input".to_string(),
+            ));
+        }
+        EvaluationResult::Collection { items, .. } => &items[0],
+        single => single,
+    };
+    let url = match &args[0] {
+        EvaluationResult::Empty => return Ok(EvaluationResult::Empty),
+        EvaluationResult::String(url, _) => url,
+        EvaluationResult::Collection { items, .. } if items.len() == 1 => match &items[0] {
+            EvaluationResult::String(url, _) => url,
+            other => {
+                return Err(EvaluationError::TypeError(format!(
+                    "conformsTo expects a String argument, found {}",
+                    other.type_name()
+                )));
+            }
+        },
+        other => {
+            return Err(EvaluationError::TypeError(format!(
+                "conformsTo expects a String argument, found {}",
+                other.type_name()
+            )));
+        }
+    };
+
+    if let Some(registry) = context
+        .profile_registry
+        .as_ref()
+        .filter(|registry| registry.contains(url))
+    {
+        let issues = registry.check_conformance(value, url, context)?;
+        return Ok(EvaluationResult::boolean(issues.is_empty()));
+    }
+
+    // The base definitions only require the resource type
+    match url.strip_prefix(CORE_PREFIX) {
+        Some(type_name)
+            if matches!(type_name, "Resource" | "DomainResource")
+                || is_resource_type_for_version(type_name, &context.fhir_version) =>
+        {
+            Ok(EvaluationResult::boolean(type_matches(value, type_name)))
+        }
+        _ => Err(EvaluationError::InvalidArgument(format!(
+            "Unknown profile '{}'",
+            url
+        ))),
+    }
+}
+
+/// Implementation of the FHIR elementDefinition() function
+///
+/// Syntax: elementDefinition() : collection
+///
+/// Returns the ElementDefinition of each item in the input, taken from the
+/// base definitions of the resource and data types in the [`ProfileRegistry`]
+/// of the context. Items whose definitions are not registered are skipped.
+///
+/// # Arguments
+///
+/// * `invocation_base` - The elements to describe
+/// * `context` - The evaluation context providing the profile registry
+///
+/// # Returns
+///
+/// * The ElementDefinitions, or Empty if there is no registry
+pub fn element_definition_function(
+    invocation_base: &EvaluationResult,
+    context: &EvaluationContext,
+) -> Result<EvaluationResult, EvaluationError> {
+    let Some(registry) = context.profile_registry.as_ref() else {
+        return Ok(EvaluationResult::Empty);
+    };
+    let roots = RootResources::new(context);
+    let mut definitions: Vec<EvaluationResult> = collection_items(invocation_base)
+        .into_iter()
+        .filter_map(|item| registry.element_definition(item, roots.iter()))
+        .collect();
+    Ok(match definitions.len() {
+        0 => EvaluationResult::Empty,
+        1 => definitions.pop().unwrap(),
+        _ => EvaluationResult::collection(definitions),
+    })
+}
+
+/// Implementation of the FHIR slice() function
+///
+/// Syntax: slice(structure : String, name : String) : collection
+///
+/// Returns the items of the input that belong to the named slice of the
+/// profile with the given canonical URL, e.g.
+/// `Observation.category.slice('http://hl7.org/fhir/StructureDefinition/vitalsigns', 'VSCat')`.
+///
+/// # Arguments
+///
+/// * `invocation_base` - The sliced elements
+/// * `args` - The canonical URL of the profile and the name of the slice
+/// * `context` - The evaluation context providing the profile registry
+///
+/// # Returns
+///
+/// * The members of the slice, or Empty if an argument is empty
+/// * `Err` - If the profile is unknown or has no slice with that name
+pub fn slice_function(
+    invocation_base: &EvaluationResult,
+    args: &[EvaluationResult],
+    context: &EvaluationContext,
+) -> Result<EvaluationResult, EvaluationError> {
+    let (url, slice_name) = match (&args[0], &args[1]) {
+        (EvaluationResult::Empty, _) | (_, EvaluationResult::Empty) => {
+            return Ok(EvaluationResult::Empty);
+        }
+        (EvaluationResult::String(url, _), EvaluationResult::String(slice_name, _)) => {
+            (url, slice_name)
+        }
+        _ => {
+            return Err(EvaluationError::TypeError(
+                "slice expects String arguments (structure, name)".to_string(),
+            ));
+        }
+    };
+    let registry = context
+        .profile_registry
+        .as_ref()
+        .filter(|registry| registry.contains(url))
+        .ok_or_else(|| EvaluationError::InvalidArgument(format!("Unknown profile '{}'", url)))?;
+
+    let mut members = Vec::new();
+    for item in collection_items(invocation_base) {
+        if registry.is_slice_member(item, url, slice_name, context)? {
+            members.push(item.clone());
+        }
+    }
+    Ok(match members.len() {
+        0 => EvaluationResult::Empty,
+        1 => members.pop().unwrap(),
+        _ => EvaluationResult::collection(members),
+    })
+}
+
+fn non_empty(value: Option<&EvaluationResult>) -> Option<EvaluationResult> {
+    value
+        .filter(|value| !matches!(value, EvaluationResult::Empty))
+        .cloned()
+}
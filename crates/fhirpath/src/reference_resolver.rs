@@ -0,0 +1,470 @@
+//! # Reference Resolution
+//!
+//! This module implements the FHIRPath `resolve()` function and the pluggable
+//! [`ReferenceResolver`] trait it is built on.
+//!
+//! `resolve()` takes Reference elements (or plain reference strings) and returns
+//! the resources they point to. Where those resources come from depends on the
+//! resolver attached to the [`EvaluationContext`]:
+//!
+//! - [`ContainedResourceResolver`] - local references (`#id`) to contained resources
+//! - [`BundleResourceResolver`] - entries of the surrounding Bundle, matched by
+//!   `fullUrl` or by `type/id`
+//! - [`InMemoryResourceResolver`] - an in-memory map of resources keyed by
+//!   `type/id` or full URL
+//! - [`ChainedResourceResolver`] - tries a list of resolvers in order
+//!
+//! When no resolver is attached, contained resources, Bundle entries and the
+//! resources in the context are searched, in that order.
+//!
+//! ## Custom Resolvers
+//!
+//! Implement [`ReferenceResolver`] to look references up in your own store:
+//!
+//! ```
+//! use helios_fhirpath::reference_resolver::{ReferenceResolver, ResolutionScope};
+//! use helios_fhirpath_support::EvaluationResult;
+//!
+//! struct NoopResolver;
+//!
+//! impl ReferenceResolver for NoopResolver {
+//!     fn resolve(&self, _reference: &str, _scope: &ResolutionScope) -> Option<EvaluationResult> {
+//!         None
+//!     }
+//! }
+//! ```
+
+use crate::evaluator::EvaluationContext;
+use crate::result_fields::{collection_items, str_field};
+use helios_fhir::FhirResource;
+use helios_fhirpath_support::{EvaluationError, EvaluationResult, IntoEvaluationResult};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The resources a reference is resolved against
+///
+/// Resolution of local (`#id`) and Bundle-relative references depends on
+/// where the reference occurs. The scope carries the root resources of the
+/// evaluation (usually the resource the expression was evaluated on, which
+/// may itself be a Bundle) and the resource holding the reference.
+pub struct ResolutionScope<'a> {
+    /// The root resources of the evaluation (`%rootResource`)
+    pub root_resources: Vec<&'a EvaluationResult>,
+    /// The resource the reference occurs in, whose `contained` resources local
+    /// references point to
+    ///
+    /// For a reference inside a contained resource this is the resource that
+    /// contains it, and for a reference inside a Bundle entry it is the entry
+    /// resource rather than the Bundle.
+    pub container: Option<&'a EvaluationResult>,
+}
+
+impl ResolutionScope<'_> {
+    /// Iterates over all Bundle resources among the root resources
+    fn bundles(&self) -> impl Iterator<Item = &EvaluationResult> {
+        self.root_resources
+            .iter()
+            .copied()
+            .filter(|resource| str_field(resource, "resourceType") == Some("Bundle"))
+    }
+}
+
+/// Resolves FHIR references to resources
+///
+/// Implementations return the resource a reference points to, or `None` if
+/// they cannot resolve it. Resolvers must be thread-safe so a single resolver
+/// can be shared between evaluation contexts.
+pub trait ReferenceResolver: Send + Sync {
+    /// Resolves a single reference string (e.g. `Patient/123`, `#p1`,
+    /// `urn:uuid:...` or an absolute URL)
+    ///
+    /// # Arguments
+    ///
+    /// * `reference` - The reference string to resolve
+    /// * `scope` - The resources the reference occurs in
+    ///
+    /// # Returns
+    ///
+    /// * `Some(EvaluationResult)` - The resolved resource
+    /// * `None` - If this resolver cannot resolve the reference
+    fn resolve(&self, reference: &str, scope: &ResolutionScope) -> Option<EvaluationResult>;
+}
+
+/// Resolves local references (`#id`) to contained resources
+///
+/// Only the `contained` elements of the scope's container, the resource the
+/// reference occurs in, are searched for a resource with a matching id. The
+/// reference `#` on its own refers to the container itself.
+#[derive(Debug, Default, Clone)]
+pub struct ContainedResourceResolver;
+
+impl ReferenceResolver for ContainedResourceResolver {
+    fn resolve(&self, reference: &str, scope: &ResolutionScope) -> Option<EvaluationResult> {
+        let local_id = reference.strip_prefix('#')?;
+        let container = scope.container?;
+        if local_id.is_empty() {
+            return Some(container.clone());
+        }
+
+        let EvaluationResult::Object { map, .. } = container else {
+            return None;
+        };
+        map.get("contained")
+            .into_iter()
+            .flat_map(collection_items)
+            .find(|contained| str_field(contained, "id") == Some(local_id))
+            .cloned()
+    }
+}
+
+/// Resolves references to entries of the surrounding Bundle
+///
+/// An entry matches when its `fullUrl` equals the reference, or when the
+/// reference ends in `Type/id` (optionally followed by `/_history/version`)
+/// and the entry resource has that type and id. An absolute reference only
+/// matches by type and id when its base URL is the base of the entry's
+/// `fullUrl`, so references to other servers are not resolved locally.
+#[derive(Debug, Default, Clone)]
+pub struct BundleResourceResolver;
+
+impl ReferenceResolver for BundleResourceResolver {
+    fn resolve(&self, reference: &str, scope: &ResolutionScope) -> Option<EvaluationResult> {
+        let parsed = parse_reference(reference);
+
+        for bundle in scope.bundles() {
+            let entries = bundle_entries(bundle);
+
+            // An exact fullUrl match takes precedence over a type/id match
+            if let Some(resource) = entries
+                .iter()
+                .find(|(full_url, _)| *full_url == Some(reference))
+                .and_then(|(_, resource)| *resource)
+            {
+                return Some(resource.clone());
+            }
+
+            let by_type_and_id = parsed.and_then(|(base, ref_type, ref_id)| {
+                entries
+                    .iter()
+                    .filter(|(full_url, _)| {
+                        base.is_empty()
+                            || full_url
+                                .and_then(parse_reference)
+                                .is_some_and(|(entry_base, _, _)| entry_base == base)
+                    })
+                    .filter_map(|(_, resource)| *resource)
+                    .find(|resource| {
+                        str_field(resource, "resourceType") == Some(ref_type)
+                            && str_field(resource, "id") == Some(ref_id)
+                    })
+            });
+            if let Some(resource) = by_type_and_id {
+                return Some(resource.clone());
+            }
+        }
+        None
+    }
+}
+
+/// Resolves references against an in-memory map of resources
+///
+/// Resources are registered under their `type/id` key and may additionally be
+/// registered under a full URL. Absolute references whose trailing `type/id`
+/// matches a registered resource are resolved as well.
+///
+/// # Examples
+///
+/// ```
+/// use helios_fhirpath::reference_resolver::InMemoryResourceResolver;
+/// use helios_fhirpath_support::EvaluationResult;
+/// use std::collections::HashMap;
+///
+/// let mut patient = HashMap::new();
+/// patient.insert("resourceType".to_string(), EvaluationResult::string("Patient".to_string()));
+/// patient.insert("id".to_string(), EvaluationResult::string("p1".to_string()));
+///
+/// let mut resolver = InMemoryResourceResolver::new();
+/// resolver.insert_result(EvaluationResult::object(patient));
+/// assert_eq!(resolver.len(), 1);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct InMemoryResourceResolver {
+    resources: HashMap<String, EvaluationResult>,
+}
+
+impl InMemoryResourceResolver {
+    /// Creates an empty resolver
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a FHIR resource under its `type/id` key
+    ///
+    /// Resources without an id are ignored.
+    pub fn add_resource(&mut self, resource: &FhirResource) {
+        self.insert_result(resource.to_evaluation_result());
+    }
+
+    /// Registers an already converted resource under its `type/id` key
+    ///
+    /// Resources without a resourceType or id are ignored.
+    pub fn insert_result(&mut self, resource: EvaluationResult) {
+        if let (Some(rt), Some(id)) = (
+            str_field(&resource, "resourceType"),
+            str_field(&resource, "id"),
+        ) {
+            let key = format!("{}/{}", rt, id);
+            self.resources.insert(key, resource);
+        }
+    }
+
+    /// Registers a resource under an explicit key, such as a full URL
+    pub fn insert(&mut self, key: impl Into<String>, resource: EvaluationResult) {
+        self.resources.insert(key.into(), resource);
+    }
+
+    /// Returns the number of registered keys
+    pub fn len(&self) -> usize {
+        self.resources.len()
+    }
+
+    /// Returns true if no resources are registered
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
+    }
+}
+
+impl ReferenceResolver for InMemoryResourceResolver {
+    fn resolve(&self, reference: &str, _scope: &ResolutionScope) -> Option<EvaluationResult> {
+        if let Some(resource) = self.resources.get(reference) {
+            return Some(resource.clone());
+        }
+        let (ref_type, ref_id) = parse_type_and_id(reference)?;
+        self.resources
+            .get(&format!("{}/{}", ref_type, ref_id))
+            .cloned()
+    }
+}
+
+/// Tries a list of resolvers in order, returning the first match
+#[derive(Default, Clone)]
+pub struct ChainedResourceResolver {
+    resolvers: Vec<Arc<dyn ReferenceResolver>>,
+}
+
+impl ChainedResourceResolver {
+    /// Creates an empty chain
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a resolver to the chain
+    pub fn with(mut self, resolver: Arc<dyn ReferenceResolver>) -> Self {
+        self.resolvers.push(resolver);
+        self
+    }
+}
+
+impl ReferenceResolver for ChainedResourceResolver {
+    fn resolve(&self, reference: &str, scope: &ResolutionScope) -> Option<EvaluationResult> {
+        self.resolvers
+            .iter()
+            .find_map(|resolver| resolver.resolve(reference, scope))
+    }
+}
+
+/// Implementation of the FHIRPath resolve() function
+///
+/// For each item in the input collection, the reference it holds is resolved
+/// using the resolver attached to the context. Items may be Reference
+/// elements (using their `reference` element) or strings holding a reference
+/// (e.g. `canonical` or `uri` values). Items that cannot be resolved are
+/// omitted from the result.
+///
+/// When no resolver is attached, contained resources, Bundle entries and the
+/// resources in the context are searched.
+///
+/// # Arguments
+///
+/// * `invocation_base` - The references to resolve
+/// * `context` - The evaluation context providing the resolver and root resources
+///
+/// # Returns
+///
+/// * The resolved resources, or Empty if none could be resolved
+pub fn resolve_function(
+    invocation_base: &EvaluationResult,
+    context: &EvaluationContext,
+) -> Result<EvaluationResult, EvaluationError> {
+    let references: Vec<(&EvaluationResult, String)> = collection_items(invocation_base)
+        .into_iter()
+        .filter_map(|item| Some((item, reference_string(item)?)))
+        .collect();
+    if references.is_empty() {
+        return Ok(EvaluationResult::Empty);
+    }
+
+    let root_resources = context
+        .root_item()
+        .map(collection_items)
+        .unwrap_or_default();
+
+    let mut resolved = Vec::new();
+    for (item, reference) in &references {
+        // Only local references depend on the resource holding them
+        let container = if reference.starts_with('#') {
+            containing_resource(item, &root_resources)
+        } else {
+            None
+        };
+        let scope = ResolutionScope {
+            root_resources: root_resources.clone(),
+            container,
+        };
+        let result = match &context.reference_resolver {
+            Some(resolver) => resolver.resolve(reference, &scope),
+            None => default_resolve(reference, &scope),
+        };
+        if let Some(resource) = result {
+            resolved.push(resource);
+        }
+    }
+
+    Ok(match resolved.len() {
+        0 => EvaluationResult::Empty,
+        1 => resolved.pop().unwrap(),
+        _ => EvaluationResult::Collection {
+            items: resolved,
+            has_undefined_order: false,
+            type_info: None,
+        },
+    })
+}
+
+/// Resolution used when no resolver is attached to the context
+fn default_resolve(reference: &str, scope: &ResolutionScope) -> Option<EvaluationResult> {
+    ContainedResourceResolver
+        .resolve(reference, scope)
+        .or_else(|| BundleResourceResolver.resolve(reference, scope))
+        .or_else(|| {
+            // Finally look at the root resources themselves by type/id. They
+            // have no fullUrl, so absolute references are not matched here
+            let (base, ref_type, ref_id) = parse_reference(reference)?;
+            if !base.is_empty() {
+                return None;
+            }
+            scope
+                .root_resources
+                .iter()
+                .find(|resource| {
+                    str_field(resource, "resourceType") == Some(ref_type)
+                        && str_field(resource, "id") == Some(ref_id)
+                })
+                .map(|resource| (*resource).clone())
+        })
+}
+
+/// Finds the resource that holds `item` within the root resources
+///
+/// This is the innermost resource on the path to the item that is not itself
+/// a contained resource: local references within a contained resource refer
+/// to the other resources contained by the same container.
+///
+/// When the context tracks locations (`set_track_locations`), the item is
+/// found by its location, which tells apart equal reference elements in
+/// different resources. Otherwise, and for items without a location, the item
+/// is matched by value against the first equal element. An item that is not
+/// found, such as a string computed by the expression, is taken to occur in
+/// the first root resource.
+fn containing_resource<'a>(
+    item: &EvaluationResult,
+    root_resources: &[&'a EvaluationResult],
+) -> Option<&'a EvaluationResult> {
+    root_resources
+        .iter()
+        .find_map(|root| find_container(root, item, root))
+        .or_else(|| root_resources.first().copied())
+}
+
+fn find_container<'a>(
+    node: &'a EvaluationResult,
+    item: &EvaluationResult,
+    container: &'a EvaluationResult,
+) -> Option<&'a EvaluationResult> {
+    if node == item && node.location() == item.location() {
+        return Some(container);
+    }
+    match node {
+        EvaluationResult::Object { map, .. } => map.iter().find_map(|(name, field)| {
+            collection_items(field).into_iter().find_map(|child| {
+                let is_resource = name != "contained" && str_field(child, "resourceType").is_some();
+                find_container(child, item, if is_resource { child } else { container })
+            })
+        }),
+        _ => None,
+    }
+}
+
+/// Extracts the reference string from a Reference element or string value
+fn reference_string(item: &EvaluationResult) -> Option<String> {
+    match item {
+        EvaluationResult::String(s, _) => Some(s.clone()),
+        EvaluationResult::Object { map, .. } => match map.get("reference") {
+            Some(EvaluationResult::String(s, _)) => Some(s.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Splits a reference into its resource type and id
+///
+/// Handles relative (`Patient/123`), absolute (`http://x/fhir/Patient/123`)
+/// and versioned (`Patient/123/_history/2`) references.
+fn parse_type_and_id(reference: &str) -> Option<(&str, &str)> {
+    parse_reference(reference).map(|(_, resource_type, id)| (resource_type, id))
+}
+
+/// Splits a reference into its base URL, resource type and id
+///
+/// The base is empty for a relative reference, and otherwise the part of an
+/// absolute reference before `Type/id`, such as `http://x/fhir/`.
+fn parse_reference(reference: &str) -> Option<(&str, &str, &str)> {
+    let path = match reference.find("/_history/") {
+        Some(pos) => &reference[..pos],
+        None => reference,
+    };
+    let mut segments = path.rsplit('/');
+    let id = segments.next()?;
+    let resource_type = segments.next()?;
+    let is_type = resource_type
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_uppercase());
+    if id.is_empty() || !is_type {
+        return None;
+    }
+    let base = &path[..path.len() - resource_type.len() - id.len() - 1];
+    Some((base, resource_type, id))
+}
+
+/// Returns the (fullUrl, resource) pairs of a Bundle's entries
+fn bundle_entries(bundle: &EvaluationResult) -> Vec<(Option<&str>, Option<&EvaluationResult>)> {
+    let EvaluationResult::Object { map, .. } = bundle else {
+        return Vec::new();
+    };
+    map.get("entry")
+        .into_iter()
+        .flat_map(collection_items)
+        .filter_map(|entry| match entry {
+            EvaluationResult::Object { map: entry_map, .. } => {
+                let full_url = match entry_map.get("fullUrl") {
+                    Some(EvaluationResult::String(url, _)) => Some(url.as_str()),
+                    _ => None,
+                };
+                Some((full_url, entry_map.get("resource")))
+            }
+            _ => None,
+        })
+        .collect()
+}
@@ -84,6 +84,9 @@ pub fn repeat_function(
             }
         }
 
+        // Stop runaway projections as soon as the result grows too large
+        context.limits.check_collection_size(result.len())?;
+
         // Update items to process for next iteration
         items_to_process = new_items_to_process;
     }
@@ -154,7 +157,7 @@ mod tests {
 
         // Build a context with our test object
         let mut context = EvaluationContext::new_empty_with_default_version();
-        context.this = Some(object1.clone());
+        context.set_this(object1.clone());
 
         // Test repeat with child projection
         let parsed = parser().parse("repeat(child)").unwrap();
@@ -266,7 +269,7 @@ mod tests {
 
         // Create context
         let mut context = EvaluationContext::new_empty_with_default_version();
-        context.this = Some(root.clone());
+        context.set_this(root.clone());
 
         // Test repeat with next projection (should handle the circular references)
         let parsed = parser().parse("next").unwrap();
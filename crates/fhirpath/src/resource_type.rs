@@ -1350,7 +1350,7 @@ fn try_convert_for_of_type(
                             if s.contains('T')
                                 && (s.contains('+') || s.contains('-') || s.ends_with('Z'))
                             {
-                                Ok(Some(EvaluationResult::fhir_string(s.clone(), "instant")))
+                                Ok(Some(EvaluationResult::fhir_datetime(s.clone(), "instant")))
                             } else {
                                 Ok(None)
                             }
@@ -0,0 +1,42 @@
+//! # Result Fields
+//!
+//! Helpers for reading the items and fields of `EvaluationResult` values,
+//! shared by the functions that inspect resources directly (profiles,
+//! terminology, reference resolution).
+
+use helios_fhirpath_support::EvaluationResult;
+
+/// Returns the items of a collection, or the value itself; none for an
+/// empty or missing value
+pub(crate) fn items(value: Option<&EvaluationResult>) -> Vec<&EvaluationResult> {
+    match value {
+        Some(EvaluationResult::Collection { items, .. }) => items.iter().collect(),
+        Some(EvaluationResult::Empty) | None => Vec::new(),
+        Some(single) => vec![single],
+    }
+}
+
+/// Returns the items of a collection, or the value itself
+pub(crate) fn collection_items(value: &EvaluationResult) -> Vec<&EvaluationResult> {
+    items(Some(value))
+}
+
+/// Returns the field `name` of an object
+pub(crate) fn field<'a>(value: &'a EvaluationResult, name: &str) -> Option<&'a EvaluationResult> {
+    match value {
+        EvaluationResult::Object { map, .. } => map.get(name),
+        _ => None,
+    }
+}
+
+/// Returns the field `name` of an object when it is a string
+pub(crate) fn str_field<'a>(value: &'a EvaluationResult, name: &str) -> Option<&'a str> {
+    field(value, name)
+        .and_then(|v| v.as_string())
+        .map(String::as_str)
+}
+
+/// Returns a copy of the field `name` of an object when it is a string
+pub(crate) fn string_field(value: &EvaluationResult, name: &str) -> Option<String> {
+    str_field(value, name).map(str::to_string)
+}
@@ -35,6 +35,11 @@
 //! - `FHIRPATH_LOG_LEVEL` / `--log-level`: Log level (default: info)
 //! - `FHIRPATH_ENABLE_CORS` / `--enable-cors`: Enable CORS (default: true)
 //! - `FHIRPATH_CORS_ORIGINS` / `--cors-origins`: Allowed origins (default: *)
+//! - `FHIRPATH_MAX_DEPTH` / `--max-depth`: Maximum evaluation depth (default: 0)
+//! - `FHIRPATH_MAX_COLLECTION_SIZE` / `--max-collection-size`: Maximum collection size (default: 0)
+//! - `FHIRPATH_TIMEOUT_MS` / `--timeout-ms`: Maximum evaluation time per request (default: 5000)
+//!
+//! Setting a limit to 0 disables it.
 //!
 //! ## Usage Example
 //!
@@ -58,11 +63,13 @@ use axum::{
 use clap::Parser;
 use http::{HeaderValue, Method};
 use std::net::SocketAddr;
+use std::time::Duration;
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
 use tracing::{info, warn};
 
-use crate::handlers::{evaluate_fhirpath, health_check};
+use crate::handlers::{ServerState, evaluate_fhirpath_with_state, health_check};
+use crate::limits::EvaluationLimits;
 
 /// Server configuration
 #[derive(Debug, Clone)]
@@ -81,6 +88,31 @@ pub struct ServerConfig {
     pub cors_methods: String,
     /// Allowed CORS headers (comma-separated list, "*" for any)
     pub cors_headers: String,
+    /// Maximum nesting depth of evaluation (0 for no limit)
+    pub max_depth: usize,
+    /// Maximum number of items in a collection built during evaluation (0 for no limit)
+    pub max_collection_size: usize,
+    /// Maximum time in milliseconds to spend evaluating a request (0 for no limit)
+    pub timeout_ms: u64,
+}
+
+impl ServerConfig {
+    /// The depth and collection size limits applied to each evaluation
+    pub fn evaluation_limits(&self) -> EvaluationLimits {
+        let mut limits = EvaluationLimits::new();
+        if self.max_depth > 0 {
+            limits = limits.with_max_depth(self.max_depth);
+        }
+        if self.max_collection_size > 0 {
+            limits = limits.with_max_collection_size(self.max_collection_size);
+        }
+        limits
+    }
+
+    /// The time each request may spend evaluating
+    pub fn evaluation_timeout(&self) -> Option<Duration> {
+        (self.timeout_ms > 0).then(|| Duration::from_millis(self.timeout_ms))
+    }
 }
 
 impl Default for ServerConfig {
@@ -94,6 +126,9 @@ impl Default for ServerConfig {
             cors_methods: "GET,POST,OPTIONS".to_string(),
             cors_headers: "Accept,Accept-Language,Content-Type,Content-Language,Authorization"
                 .to_string(),
+            max_depth: 0,
+            max_collection_size: 0,
+            timeout_ms: 5000,
         }
     }
 }
@@ -104,7 +139,7 @@ impl Default for ServerConfig {
     author,
     version,
     about = "FHIRPath HTTP server",
-    long_about = "HTTP server providing FHIRPath expression evaluation for fhirpath-lab integration\n\nEnvironment variables:\n  FHIRPATH_SERVER_PORT - Server port (default: 3000)\n  FHIRPATH_SERVER_HOST - Server host (default: 127.0.0.1)\n  FHIRPATH_LOG_LEVEL - Log level: error, warn, info, debug, trace (default: info)\n  FHIRPATH_ENABLE_CORS - Enable CORS: true/false (default: true)\n  FHIRPATH_CORS_ORIGINS - Allowed origins (comma-separated, * for any) (default: *)\n  FHIRPATH_CORS_METHODS - Allowed methods (comma-separated, * for any) (default: GET,POST,OPTIONS)\n  FHIRPATH_CORS_HEADERS - Allowed headers (comma-separated, * for any) (default: common headers)"
+    long_about = "HTTP server providing FHIRPath expression evaluation for fhirpath-lab integration\n\nEnvironment variables:\n  FHIRPATH_SERVER_PORT - Server port (default: 3000)\n  FHIRPATH_SERVER_HOST - Server host (default: 127.0.0.1)\n  FHIRPATH_LOG_LEVEL - Log level: error, warn, info, debug, trace (default: info)\n  FHIRPATH_ENABLE_CORS - Enable CORS: true/false (default: true)\n  FHIRPATH_CORS_ORIGINS - Allowed origins (comma-separated, * for any) (default: *)\n  FHIRPATH_CORS_METHODS - Allowed methods (comma-separated, * for any) (default: GET,POST,OPTIONS)\n  FHIRPATH_CORS_HEADERS - Allowed headers (comma-separated, * for any) (default: common headers)\n  FHIRPATH_MAX_DEPTH - Maximum evaluation depth, 0 for no limit (default: 0)\n  FHIRPATH_MAX_COLLECTION_SIZE - Maximum collection size, 0 for no limit (default: 0)\n  FHIRPATH_TIMEOUT_MS - Maximum evaluation time per request in milliseconds, 0 for no limit (default: 5000)"
 )]
 pub struct ServerArgs {
     /// Port to bind the server to
@@ -152,6 +187,18 @@ pub struct ServerArgs {
         default_value = "Accept,Accept-Language,Content-Type,Content-Language,Authorization"
     )]
     pub cors_headers: String,
+
+    /// Maximum nesting depth of evaluation (0 for no limit)
+    #[arg(long, env = "FHIRPATH_MAX_DEPTH", default_value_t = 0)]
+    pub max_depth: usize,
+
+    /// Maximum number of items in a collection built during evaluation (0 for no limit)
+    #[arg(long, env = "FHIRPATH_MAX_COLLECTION_SIZE", default_value_t = 0)]
+    pub max_collection_size: usize,
+
+    /// Maximum time in milliseconds to spend evaluating a request (0 for no limit)
+    #[arg(long, env = "FHIRPATH_TIMEOUT_MS", default_value_t = 5000)]
+    pub timeout_ms: u64,
 }
 
 impl From<ServerArgs> for ServerConfig {
@@ -164,6 +211,9 @@ impl From<ServerArgs> for ServerConfig {
             cors_origins: args.cors_origins,
             cors_methods: args.cors_methods,
             cors_headers: args.cors_headers,
+            max_depth: args.max_depth,
+            max_collection_size: args.max_collection_size,
+            timeout_ms: args.timeout_ms,
         }
     }
 }
@@ -208,11 +258,15 @@ pub async fn run_server(config: ServerConfig) -> Result<(), Box<dyn std::error::
 
 /// Create the axum application with all routes
 pub fn create_app(config: &ServerConfig) -> Router {
+    // Limits and terminology providers shared by every request
+    let state = ServerState::new(config.evaluation_limits(), config.evaluation_timeout());
+
     let mut app = Router::new()
-        // Main evaluation endpoint
-        .route("/", post(evaluate_fhirpath))
+        // Main evaluation endpoint, with the configured evaluation limits
+        .route("/", post(evaluate_fhirpath_with_state))
         // Health check endpoint
-        .route("/health", get(health_check));
+        .route("/health", get(health_check))
+        .with_state(state);
 
     // Add CORS if enabled
     if config.enable_cors {
@@ -360,6 +414,9 @@ mod tests {
             config.cors_headers,
             "Accept,Accept-Language,Content-Type,Content-Language,Authorization"
         );
+        assert_eq!(config.max_depth, 0);
+        assert_eq!(config.max_collection_size, 0);
+        assert_eq!(config.timeout_ms, 5000);
     }
 
     #[test]
@@ -372,6 +429,9 @@ mod tests {
             cors_origins: "http://example.com".to_string(),
             cors_methods: "GET,POST".to_string(),
             cors_headers: "Content-Type".to_string(),
+            max_depth: 64,
+            max_collection_size: 0,
+            timeout_ms: 250,
         };
 
         let config: ServerConfig = args.into();
@@ -382,6 +442,254 @@ mod tests {
         assert_eq!(config.cors_origins, "http://example.com");
         assert_eq!(config.cors_methods, "GET,POST");
         assert_eq!(config.cors_headers, "Content-Type");
+
+        let limits = config.evaluation_limits();
+        assert_eq!(limits.max_depth, Some(64));
+        assert_eq!(limits.max_collection_size, None);
+        assert_eq!(
+            config.evaluation_timeout(),
+            Some(Duration::from_millis(250))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_evaluation_limits() {
+        let config = ServerConfig {
+            max_collection_size: 50,
+            ..ServerConfig::default()
+        };
+        let app = create_app(&config);
+
+        let body = json!({
+            "resourceType": "Parameters",
+            "parameter": [
+                { "name": "expression", "valueString": "1.repeat($this + 1)" },
+                { "name": "resource", "resource": { "resourceType": "Patient" } }
+            ]
+        });
+        let request = Request::builder()
+            .method("POST")
+            .uri("/")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let diagnostics = json["issue"][0]["diagnostics"].as_str().unwrap();
+        assert!(
+            diagnostics.contains("Collection size of 51 exceeds the limit of 50"),
+            "{}",
+            diagnostics
+        );
+    }
+
+    #[tokio::test]
+    async fn test_debug_eval() {
+        let app = create_app(&ServerConfig::default());
+
+        let body = json!({
+            "resourceType": "Parameters",
+            "parameter": [
+                { "name": "expression", "valueString": "name.given.first()" },
+                { "name": "debugEval", "valueBoolean": true },
+                {
+                    "name": "resource",
+                    "resource": {
+                        "resourceType": "Patient",
+                        "name": [{ "given": ["Peter", "James"] }]
+                    }
+                }
+            ]
+        });
+        let request = Request::builder()
+            .method("POST")
+            .uri("/")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let result_parts = json["parameter"][1]["part"].as_array().unwrap();
+        let debug = result_parts
+            .iter()
+            .find(|part| part["name"] == "debugEval")
+            .unwrap();
+        let steps = debug["part"].as_array().unwrap();
+        assert_eq!(
+            steps
+                .iter()
+                .map(|step| step["valueString"].as_str().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["name.given.first()", "name.given", "name"]
+        );
+
+        // The outermost step takes the resource and returns the first given name
+        let parts = steps[0]["part"].as_array().unwrap();
+        let part = |name: &str| parts.iter().find(|part| part["name"] == name).unwrap();
+        assert_eq!(part("position")["valueInteger"], 0);
+        assert_eq!(part("length")["valueInteger"], 18);
+        assert_eq!(part("depth")["valueInteger"], 0);
+        assert_eq!(part("focus")["valueString"], "Patient");
+        assert_eq!(part("result")["part"][0]["valueString"], "Peter");
+    }
+
+    #[tokio::test]
+    async fn test_debug_eval_on_failure() {
+        let app = create_app(&ServerConfig::default());
+
+        let body = json!({
+            "resourceType": "Parameters",
+            "parameter": [
+                { "name": "expression", "valueString": "name.given.single()" },
+                { "name": "debugEval", "valueBoolean": true },
+                {
+                    "name": "resource",
+                    "resource": {
+                        "resourceType": "Patient",
+                        "name": [{ "given": ["Peter", "James"] }]
+                    }
+                }
+            ]
+        });
+        let request = Request::builder()
+            .method("POST")
+            .uri("/")
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        // The steps recorded before the failure are listed after the error
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["issue"][0]["severity"], "error");
+        assert_eq!(json["issue"][1]["severity"], "information");
+        let steps = json["issue"][1]["diagnostics"].as_str().unwrap();
+        assert!(
+            steps.starts_with("name.given.single() : Patient -> error: "),
+            "{}",
+            steps
+        );
+        assert!(
+            steps.contains("  name.given : Patient -> ['Peter', 'James']"),
+            "{}",
+            steps
+        );
+    }
+
+    #[tokio::test]
+    async fn test_optimize() {
+        let parse_debug = |optimize: bool| async move {
+            let app = create_app(&ServerConfig::default());
+            let body = json!({
+                "resourceType": "Parameters",
+                "parameter": [
+                    { "name": "expression", "valueString": "(1 + 2) * 3" },
+                    { "name": "validate", "valueBoolean": true },
+                    { "name": "optimize", "valueBoolean": optimize },
+                    { "name": "resource", "resource": { "resourceType": "Patient" } }
+                ]
+            });
+            let request = Request::builder()
+                .method("POST")
+                .uri("/")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+
+            let response = app.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(json["parameter"][1]["part"][0]["valueInteger"], 9);
+            let parts = json["parameter"][0]["part"].as_array().unwrap().clone();
+            parts
+                .iter()
+                .find(|part| part["name"] == "parseDebug")
+                .unwrap()["valueString"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        };
+
+        // The optimized tree is a single folded literal
+        assert!(parse_debug(true).await.starts_with("Integer(9)"));
+        assert!(parse_debug(false).await.contains("Integer(1)"));
+    }
+
+    #[tokio::test]
+    async fn test_result_locations() {
+        let result_parts = |result_locations: bool| async move {
+            let app = create_app(&ServerConfig::default());
+            let body = json!({
+                "resourceType": "Parameters",
+                "parameter": [
+                    { "name": "expression", "valueString": "name.where(use = 'usual').given | 1" },
+                    { "name": "resultLocations", "valueBoolean": result_locations },
+                    {
+                        "name": "resource",
+                        "resource": {
+                            "resourceType": "Patient",
+                            "name": [
+                                { "use": "official", "given": ["Peter", "James"] },
+                                { "use": "usual", "given": ["Jim"] }
+                            ]
+                        }
+                    }
+                ]
+            });
+            let request = Request::builder()
+                .method("POST")
+                .uri("/")
+                .header("Content-Type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+
+            let response = app.oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            json["parameter"][1]["part"].as_array().unwrap().clone()
+        };
+
+        let located = result_parts(true).await;
+        assert_eq!(located.len(), 2);
+        assert_eq!(
+            located[0]["extension"],
+            json!([{
+                "url": "http://fhir.forms-lab.com/StructureDefinition/resource-path",
+                "valueString": "Patient.name[1].given[0]"
+            }])
+        );
+        // Computed values are not located
+        assert!(located[1].get("extension").is_none());
+
+        // Locations are only reported on request
+        let unlocated = result_parts(false).await;
+        assert_eq!(unlocated.len(), 2);
+        assert!(unlocated[0].get("extension").is_none());
     }
 
     #[tokio::test]
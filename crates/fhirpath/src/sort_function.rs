@@ -0,0 +1,115 @@
+use crate::evaluator::{EvaluationContext, compare_ordering, evaluate};
+use crate::parser::Expression;
+use helios_fhirpath_support::{EvaluationError, EvaluationResult};
+use std::cmp::Ordering;
+
+/// Implements the FHIRPath sort() function
+///
+/// Sorts the input collection by one or more key expressions, each evaluated
+/// with the item as `$this`. Prefixing a key with unary minus sorts it in
+/// descending order. Later keys break ties left by earlier ones, and the sort
+/// is stable. Without keys the items are sorted by their own values.
+///
+/// Keys are compared with the same type-aware rules as the `<` operator.
+/// An empty key sorts before any other value (after, when descending).
+///
+/// # Syntax
+/// `sort([key : expression, ...]) : collection`
+///
+/// # Parameters
+/// * `invocation_base` - The collection to sort
+/// * `key_exprs` - The key expressions, possibly wrapped in unary minus
+/// * `context` - The evaluation context
+///
+/// # Returns
+/// The sorted collection, marked as ordered
+pub fn sort_function(
+    invocation_base: &EvaluationResult,
+    key_exprs: &[Expression],
+    context: &EvaluationContext,
+) -> Result<EvaluationResult, EvaluationError> {
+    let (items, type_info) = match invocation_base {
+        EvaluationResult::Empty => return Ok(EvaluationResult::Empty),
+        EvaluationResult::Collection {
+            items, type_info, ..
+        } => (items.clone(), type_info.clone()),
+        single_item => (vec![single_item.clone()], None),
+    };
+
+    // Split each key into the expression to evaluate and its direction
+    let keys: Vec<(&Expression, bool)> = key_exprs
+        .iter()
+        .map(|key_expr| match key_expr {
+            Expression::Polarity('-', inner, _) => (inner.as_ref(), true),
+            other => (other, false),
+        })
+        .collect();
+
+    // Evaluate every key once per item up front
+    let mut keyed_items = Vec::with_capacity(items.len());
+    for item in items {
+        let key_values = if keys.is_empty() {
+            vec![item.clone()]
+        } else {
+            keys.iter()
+                .map(|(key_expr, _)| sort_key(evaluate(key_expr, context, Some(&item))?))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        keyed_items.push((key_values, item));
+    }
+
+    // sort_by cannot fail, so remember the first comparison error instead
+    let mut comparison_error = None;
+    keyed_items.sort_by(|(left_keys, _), (right_keys, _)| {
+        for (index, (left, right)) in left_keys.iter().zip(right_keys).enumerate() {
+            let ordering = match (left, right) {
+                (EvaluationResult::Empty, EvaluationResult::Empty) => Ordering::Equal,
+                (EvaluationResult::Empty, _) => Ordering::Less,
+                (_, EvaluationResult::Empty) => Ordering::Greater,
+                _ => match compare_ordering(left, right) {
+                    Ok(ordering) => ordering,
+                    Err(e) => {
+                        comparison_error.get_or_insert(e);
+                        Ordering::Equal
+                    }
+                },
+            };
+            let descending = keys.get(index).is_some_and(|(_, descending)| *descending);
+            let ordering = if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    });
+    if let Some(e) = comparison_error {
+        return Err(e);
+    }
+
+    let mut sorted: Vec<EvaluationResult> = keyed_items.into_iter().map(|(_, item)| item).collect();
+    if sorted.len() == 1 {
+        return Ok(sorted.pop().unwrap());
+    }
+    Ok(EvaluationResult::Collection {
+        items: sorted,
+        has_undefined_order: false,
+        type_info,
+    })
+}
+
+/// Reduces an evaluated key to a single value, which may be Empty
+fn sort_key(key: EvaluationResult) -> Result<EvaluationResult, EvaluationError> {
+    match key {
+        EvaluationResult::Collection { mut items, .. } if items.len() <= 1 => {
+            Ok(items.pop().unwrap_or(EvaluationResult::Empty))
+        }
+        EvaluationResult::Collection { .. } => Err(EvaluationError::SingletonEvaluationError(
+            "sort() key expressions must return a single value for each item".to_string(),
+        )),
+        single => Ok(single),
+    }
+}
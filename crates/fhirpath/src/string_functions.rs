@@ -0,0 +1,454 @@
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE, URL_SAFE_NO_PAD};
+use helios_fhirpath_support::{EvaluationError, EvaluationResult};
+
+/// Extracts the singleton String input of a string function
+///
+/// Returns `Ok(None)` when the input is empty, so callers can propagate
+/// the empty collection as required by the specification.
+fn singleton_string_input<'a>(
+    invocation_base: &'a EvaluationResult,
+    function_name: &str,
+) -> Result<Option<&'a str>, EvaluationError> {
+    if invocation_base.count() > 1 {
+        return Err(EvaluationError::SingletonEvaluationError(format!(
+            "{} requires a singleton input",
+            function_name
+        )));
+    }
+    match invocation_base {
+        EvaluationResult::String(s, _) => Ok(Some(s.as_str())),
+        EvaluationResult::Empty => Ok(None),
+        EvaluationResult::Collection { items, .. } if items.is_empty() => Ok(None),
+        EvaluationResult::Collection { items, .. } => match &items[0] {
+            EvaluationResult::String(s, _) => Ok(Some(s.as_str())),
+            _ => Err(EvaluationError::TypeError(format!(
+                "{} requires a String input",
+                function_name
+            ))),
+        },
+        _ => Err(EvaluationError::TypeError(format!(
+            "{} requires a String input",
+            function_name
+        ))),
+    }
+}
+
+/// Extracts a singleton String argument of a string function
+///
+/// Returns `Ok(None)` when the argument is empty.
+fn singleton_string_arg<'a>(
+    arg: &'a EvaluationResult,
+    function_name: &str,
+) -> Result<Option<&'a str>, EvaluationError> {
+    if arg.count() > 1 {
+        return Err(EvaluationError::SingletonEvaluationError(format!(
+            "{} requires a singleton argument",
+            function_name
+        )));
+    }
+    match arg {
+        EvaluationResult::String(s, _) => Ok(Some(s.as_str())),
+        EvaluationResult::Empty => Ok(None),
+        _ => Err(EvaluationError::TypeError(format!(
+            "{} requires a String argument",
+            function_name
+        ))),
+    }
+}
+
+/// Implements the FHIRPath split() function
+///
+/// Syntax: split(separator: String) : collection
+///
+/// Splits the input string around occurrences of the separator and returns
+/// the parts as a collection of strings. An empty separator splits the
+/// input into its individual characters.
+///
+/// # Arguments
+///
+/// * `invocation_base` - The input string
+/// * `separator` - The separator to split around
+///
+/// # Returns
+///
+/// * A collection of strings
+/// * Empty if the input or the separator is empty
+///
+/// # Examples
+///
+/// ```text
+/// 'A,B,C'.split(',') = { 'A', 'B', 'C' }
+/// 'ABC'.split(',') = { 'ABC' }
+/// 'A,,C'.split(',') = { 'A', '', 'C' }
+/// ```
+pub fn split_function(
+    invocation_base: &EvaluationResult,
+    separator: &EvaluationResult,
+) -> Result<EvaluationResult, EvaluationError> {
+    let Some(s) = singleton_string_input(invocation_base, "split")? else {
+        return Ok(EvaluationResult::Empty);
+    };
+    let Some(separator) = singleton_string_arg(separator, "split")? else {
+        return Ok(EvaluationResult::Empty);
+    };
+
+    let parts: Vec<EvaluationResult> = if separator.is_empty() {
+        s.chars()
+            .map(|c| EvaluationResult::string(c.to_string()))
+            .collect()
+    } else {
+        s.split(separator)
+            .map(|part| EvaluationResult::string(part.to_string()))
+            .collect()
+    };
+
+    Ok(EvaluationResult::Collection {
+        items: parts,
+        has_undefined_order: false,
+        type_info: None,
+    })
+}
+
+/// Implements the FHIRPath trim() function
+///
+/// Syntax: trim() : String
+///
+/// Returns the input string with leading and trailing whitespace removed.
+/// If the input is empty, the result is empty.
+///
+/// # Examples
+///
+/// ```text
+/// '  John  '.trim() = 'John'
+/// ```
+pub fn trim_function(
+    invocation_base: &EvaluationResult,
+) -> Result<EvaluationResult, EvaluationError> {
+    Ok(match singleton_string_input(invocation_base, "trim")? {
+        Some(s) => EvaluationResult::string(s.trim().to_string()),
+        None => EvaluationResult::Empty,
+    })
+}
+
+/// Implements the FHIRPath encode() function
+///
+/// Syntax: encode(format: String) : String
+///
+/// Encodes the UTF-8 bytes of the input string using the given format.
+/// Supported formats are `hex`, `base64` and `urlbase64`.
+///
+/// # Returns
+///
+/// * The encoded string
+/// * Empty if the input or the format is empty
+/// * `Err` - If the format is not one of the supported encodings
+///
+/// # Examples
+///
+/// ```text
+/// 'test'.encode('base64') = 'dGVzdA=='
+/// 'test'.encode('hex') = '74657374'
+/// ```
+pub fn encode_function(
+    invocation_base: &EvaluationResult,
+    format: &EvaluationResult,
+) -> Result<EvaluationResult, EvaluationError> {
+    let Some(s) = singleton_string_input(invocation_base, "encode")? else {
+        return Ok(EvaluationResult::Empty);
+    };
+    let Some(format) = singleton_string_arg(format, "encode")? else {
+        return Ok(EvaluationResult::Empty);
+    };
+
+    let bytes = s.as_bytes();
+    let encoded = match format {
+        "hex" => bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+        "base64" => STANDARD.encode(bytes),
+        "urlbase64" => URL_SAFE.encode(bytes),
+        other => {
+            return Err(EvaluationError::InvalidArgument(format!(
+                "Unsupported encoding for encode: '{}' (expected 'hex', 'base64' or 'urlbase64')",
+                other
+            )));
+        }
+    };
+    Ok(EvaluationResult::string(encoded))
+}
+
+/// Implements the FHIRPath decode() function
+///
+/// Syntax: decode(format: String) : String
+///
+/// Decodes the input string using the given format and interprets the
+/// resulting bytes as UTF-8. Supported formats are `hex`, `base64` and
+/// `urlbase64`.
+///
+/// # Returns
+///
+/// * The decoded string
+/// * Empty if the input or the format is empty
+/// * `Err` - If the format is unsupported, the input is not validly encoded,
+///   or the decoded bytes are not valid UTF-8
+///
+/// # Examples
+///
+/// ```text
+/// 'dGVzdA=='.decode('base64') = 'test'
+/// '74657374'.decode('hex') = 'test'
+/// ```
+pub fn decode_function(
+    invocation_base: &EvaluationResult,
+    format: &EvaluationResult,
+) -> Result<EvaluationResult, EvaluationError> {
+    let Some(s) = singleton_string_input(invocation_base, "decode")? else {
+        return Ok(EvaluationResult::Empty);
+    };
+    let Some(format) = singleton_string_arg(format, "decode")? else {
+        return Ok(EvaluationResult::Empty);
+    };
+
+    let bytes = match format {
+        "hex" => decode_hex(s),
+        "base64" => STANDARD
+            .decode(s)
+            .map_err(|e| format!("invalid base64 input: {}", e)),
+        // Accept url-safe input both with and without trailing padding
+        "urlbase64" => URL_SAFE
+            .decode(s)
+            .or_else(|_| URL_SAFE_NO_PAD.decode(s))
+            .map_err(|e| format!("invalid urlbase64 input: {}", e)),
+        other => {
+            return Err(EvaluationError::InvalidArgument(format!(
+                "Unsupported encoding for decode: '{}' (expected 'hex', 'base64' or 'urlbase64')",
+                other
+            )));
+        }
+    }
+    .map_err(|msg| EvaluationError::InvalidArgument(format!("decode: {}", msg)))?;
+
+    String::from_utf8(bytes)
+        .map(EvaluationResult::string)
+        .map_err(|_| {
+            EvaluationError::InvalidArgument(
+                "decode: decoded bytes are not valid UTF-8".to_string(),
+            )
+        })
+}
+
+/// Decodes a hexadecimal string (case-insensitive) into bytes
+fn decode_hex(s: &str) -> Result<Vec<u8>, String> {
+    if !s.len().is_multiple_of(2) {
+        return Err("hex input must have an even number of digits".to_string());
+    }
+    s.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| format!("invalid hex digits '{}'", String::from_utf8_lossy(pair)))
+        })
+        .collect()
+}
+
+/// Implements the FHIRPath escape() function
+///
+/// Syntax: escape(target: String) : String
+///
+/// Escapes the input string for the given target. Supported targets are
+/// `html` (escapes `&`, `<`, `>`, `"` and `'`) and `json` (escapes the
+/// string so it can appear inside a JSON string literal, without the
+/// surrounding quotes).
+///
+/// # Examples
+///
+/// ```text
+/// '"1 < 5"'.escape('html') = '&quot;1 &lt; 5&quot;'
+/// '"1 < 5"'.escape('json') = '\"1 < 5\"'
+/// ```
+pub fn escape_function(
+    invocation_base: &EvaluationResult,
+    target: &EvaluationResult,
+) -> Result<EvaluationResult, EvaluationError> {
+    let Some(s) = singleton_string_input(invocation_base, "escape")? else {
+        return Ok(EvaluationResult::Empty);
+    };
+    let Some(target) = singleton_string_arg(target, "escape")? else {
+        return Ok(EvaluationResult::Empty);
+    };
+
+    let escaped = match target {
+        "html" => escape_html(s),
+        "json" => escape_json(s),
+        other => {
+            return Err(EvaluationError::InvalidArgument(format!(
+                "Unsupported target for escape: '{}' (expected 'html' or 'json')",
+                other
+            )));
+        }
+    };
+    Ok(EvaluationResult::string(escaped))
+}
+
+/// Implements the FHIRPath unescape() function
+///
+/// Syntax: unescape(target: String) : String
+///
+/// Reverses `escape()` for the given target (`html` or `json`). For `html`,
+/// named entities for the escaped characters and numeric character
+/// references (`&#39;`, `&#x27;`) are recognized; unknown entities are left
+/// untouched. For `json`, backslash escape sequences are decoded and all
+/// other characters, including unescaped quotes and control characters, are
+/// copied through; a malformed escape sequence is an error.
+///
+/// # Examples
+///
+/// ```text
+/// '&quot;1 &lt; 5&quot;'.unescape('html') = '"1 < 5"'
+/// '\"1 < 5\"'.unescape('json') = '"1 < 5"'
+/// ```
+pub fn unescape_function(
+    invocation_base: &EvaluationResult,
+    target: &EvaluationResult,
+) -> Result<EvaluationResult, EvaluationError> {
+    let Some(s) = singleton_string_input(invocation_base, "unescape")? else {
+        return Ok(EvaluationResult::Empty);
+    };
+    let Some(target) = singleton_string_arg(target, "unescape")? else {
+        return Ok(EvaluationResult::Empty);
+    };
+
+    let unescaped = match target {
+        "html" => unescape_html(s),
+        "json" => unescape_json(s).map_err(|e| {
+            EvaluationError::InvalidArgument(format!("unescape: invalid JSON escape: {}", e))
+        })?,
+        other => {
+            return Err(EvaluationError::InvalidArgument(format!(
+                "Unsupported target for unescape: '{}' (expected 'html' or 'json')",
+                other
+            )));
+        }
+    };
+    Ok(EvaluationResult::string(unescaped))
+}
+
+fn escape_html(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+fn unescape_html(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(amp) = rest.find('&') {
+        result.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let decoded = rest.find(';').and_then(|semi| {
+            let entity = &rest[1..semi];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| {
+                        entity
+                            .strip_prefix('#')
+                            .and_then(|dec| dec.parse::<u32>().ok())
+                    })
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, semi))
+        });
+
+        match decoded {
+            Some((c, semi)) => {
+                result.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                // Not a recognized entity; keep the ampersand as-is
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+fn escape_json(s: &str) -> String {
+    // serde_json produces a quoted JSON string literal; strip the quotes
+    serde_json::to_string(s)
+        .map(|quoted| quoted[1..quoted.len() - 1].to_string())
+        .unwrap_or_default()
+}
+
+fn unescape_json(s: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('"') => result.push('"'),
+            Some('\\') => result.push('\\'),
+            Some('/') => result.push('/'),
+            Some('b') => result.push('\u{8}'),
+            Some('f') => result.push('\u{c}'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some('u') => {
+                let high = json_code_unit(&mut chars)?;
+                let code = if (0xD800..0xDC00).contains(&high) {
+                    // A high surrogate must be followed by an escaped low surrogate
+                    if chars.next() != Some('\\') || chars.next() != Some('u') {
+                        return Err(format!("unpaired surrogate \\u{:04X}", high));
+                    }
+                    let low = json_code_unit(&mut chars)?;
+                    if !(0xDC00..0xE000).contains(&low) {
+                        return Err(format!("unpaired surrogate \\u{:04X}", high));
+                    }
+                    0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                } else {
+                    high
+                };
+                let c = char::from_u32(code)
+                    .ok_or_else(|| format!("unpaired surrogate \\u{:04X}", code))?;
+                result.push(c);
+            }
+            Some(other) => return Err(format!("unknown escape '\\{}'", other)),
+            None => return Err("trailing backslash".to_string()),
+        }
+    }
+    Ok(result)
+}
+
+/// Reads the four hex digits of a `\uXXXX` escape
+fn json_code_unit(chars: &mut std::str::Chars) -> Result<u32, String> {
+    let digits: String = chars.take(4).collect();
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid escape '\\u{}'", digits));
+    }
+    Ok(u32::from_str_radix(&digits, 16).unwrap_or_default())
+}
@@ -0,0 +1,1501 @@
+//! # Terminology Services
+//!
+//! This module implements the FHIR terminology functions `memberOf()`,
+//! `subsumes()` and `subsumedBy()`, and the `%terminologies` object with its
+//! `expand`, `lookup`, `validateVS`, `validateCS`, `subsumes` and `translate`
+//! functions.
+//!
+//! All of them delegate to the [`TerminologyProvider`] attached to the
+//! [`EvaluationContext`]. Two providers are included:
+//!
+//! - [`InMemoryTerminologyProvider`] - answers from loaded CodeSystem, ValueSet
+//!   and ConceptMap resources
+//! - [`HttpTerminologyProvider`] - calls the operations of a FHIR terminology server
+//!
+//! The `%terminologies` functions return FHIR resources (a ValueSet for
+//! `expand`, a Parameters resource for the others), except `subsumes`, which
+//! returns the outcome code (`equivalent`, `subsumes`, `subsumed-by` or
+//! `not-subsumed`).
+//!
+//! ## Examples
+//!
+//! ```
+//! use helios_fhirpath::terminology::{InMemoryTerminologyProvider, TerminologyProvider, Coding};
+//! use helios_fhirpath_support::EvaluationResult;
+//! use std::collections::HashMap;
+//!
+//! let mut concept = HashMap::new();
+//! concept.insert("code".to_string(), EvaluationResult::string("male".to_string()));
+//!
+//! let mut code_system = HashMap::new();
+//! code_system.insert("resourceType".to_string(), EvaluationResult::string("CodeSystem".to_string()));
+//! code_system.insert("url".to_string(), EvaluationResult::string("http://example.org/gender".to_string()));
+//! code_system.insert("concept".to_string(), EvaluationResult::object(concept));
+//!
+//! let mut provider = InMemoryTerminologyProvider::new();
+//! provider.insert_result(EvaluationResult::object(code_system));
+//!
+//! let coding = Coding::new("http://example.org/gender", "male");
+//! let result = provider.validate_cs("http://example.org/gender", &[coding], &[]).unwrap();
+//! ```
+
+use crate::evaluator::EvaluationContext;
+use crate::result_fields::{field, items, string_field};
+use helios_fhir::{FhirResource, FhirVersion};
+use helios_fhirpath_support::{EvaluationError, EvaluationResult, IntoEvaluationResult};
+use regex::Regex;
+use serde_json::{Value, json};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock, mpsc};
+use std::time::{Duration, Instant};
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+/// A code together with the code system it is defined in
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coding {
+    /// The code system URL, if known
+    pub system: Option<String>,
+    /// The code system version, if known
+    pub version: Option<String>,
+    /// The code itself
+    pub code: String,
+    /// The display text, if known
+    pub display: Option<String>,
+}
+
+impl Coding {
+    /// Creates a coding from a system URL and a code
+    pub fn new(system: impl Into<String>, code: impl Into<String>) -> Self {
+        Self {
+            system: Some(system.into()),
+            code: code.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Extracts the codings held by a code, Coding or CodeableConcept value
+    ///
+    /// A plain string is treated as a code without a system. Values that do
+    /// not hold a code produce no codings.
+    pub fn from_result(value: &EvaluationResult) -> Vec<Coding> {
+        match value {
+            EvaluationResult::String(code, _) => vec![Coding {
+                code: code.clone(),
+                ..Coding::default()
+            }],
+            EvaluationResult::Object { map, .. } => {
+                if let Some(code) = string_field(value, "code") {
+                    return vec![Coding {
+                        system: string_field(value, "system"),
+                        version: string_field(value, "version"),
+                        code,
+                        display: string_field(value, "display"),
+                    }];
+                }
+                items(map.get("coding"))
+                    .into_iter()
+                    .flat_map(Coding::from_result)
+                    .collect()
+            }
+            EvaluationResult::Collection { items, .. } => {
+                items.iter().flat_map(Coding::from_result).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Converts the coding to a FHIR Coding value
+    fn to_result(&self) -> EvaluationResult {
+        let mut map = HashMap::new();
+        let fields = [
+            ("system", &self.system),
+            ("version", &self.version),
+            ("display", &self.display),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value {
+                map.insert(name.to_string(), EvaluationResult::string(value.clone()));
+            }
+        }
+        map.insert(
+            "code".to_string(),
+            EvaluationResult::string(self.code.clone()),
+        );
+        EvaluationResult::typed_object(map, "FHIR", "Coding")
+    }
+
+    /// Converts the coding to FHIR JSON
+    fn to_json(&self) -> Value {
+        let mut coding = json!({ "code": self.code });
+        let fields = [
+            ("system", &self.system),
+            ("version", &self.version),
+            ("display", &self.display),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value {
+                coding[name] = json!(value);
+            }
+        }
+        coding
+    }
+
+    /// Returns true if both codings have the same code and compatible systems
+    ///
+    /// A coding without a system matches a code from any system.
+    fn matches(&self, other: &Coding) -> bool {
+        self.code == other.code
+            && (self.system.is_none() || other.system.is_none() || self.system == other.system)
+    }
+}
+
+/// Outcome of a subsumption test between two codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubsumptionOutcome {
+    /// The codes are the same concept
+    Equivalent,
+    /// The first code subsumes the second
+    Subsumes,
+    /// The first code is subsumed by the second
+    SubsumedBy,
+    /// Neither code subsumes the other
+    NotSubsumed,
+}
+
+impl SubsumptionOutcome {
+    /// Returns the code used for this outcome by `CodeSystem/$subsumes`
+    pub fn as_code(&self) -> &'static str {
+        match self {
+            SubsumptionOutcome::Equivalent => "equivalent",
+            SubsumptionOutcome::Subsumes => "subsumes",
+            SubsumptionOutcome::SubsumedBy => "subsumed-by",
+            SubsumptionOutcome::NotSubsumed => "not-subsumed",
+        }
+    }
+
+    /// Parses an outcome code returned by `CodeSystem/$subsumes`
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "equivalent" => Some(SubsumptionOutcome::Equivalent),
+            "subsumes" => Some(SubsumptionOutcome::Subsumes),
+            "subsumed-by" => Some(SubsumptionOutcome::SubsumedBy),
+            "not-subsumed" => Some(SubsumptionOutcome::NotSubsumed),
+            _ => None,
+        }
+    }
+}
+
+/// Answers terminology questions for FHIRPath expressions
+///
+/// The methods mirror the FHIR terminology operations used by the
+/// `%terminologies` object. `params` holds additional operation parameters
+/// (such as `displayLanguage`) as name/value pairs. Providers must be
+/// thread-safe so a single provider can be shared between evaluation contexts.
+///
+/// Providers that call other services should give up by the deadline of the
+/// calling evaluation, which is available from [`request_deadline`].
+pub trait TerminologyProvider: Send + Sync {
+    /// Expands a value set (`ValueSet/$expand`), returning a ValueSet resource
+    fn expand(
+        &self,
+        value_set: &str,
+        params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError>;
+
+    /// Looks up a code (`CodeSystem/$lookup`), returning a Parameters resource
+    fn lookup(
+        &self,
+        coding: &Coding,
+        params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError>;
+
+    /// Checks whether any of the codings is in a value set
+    /// (`ValueSet/$validate-code`), returning a Parameters resource
+    fn validate_vs(
+        &self,
+        value_set: &str,
+        codings: &[Coding],
+        params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError>;
+
+    /// Checks whether any of the codings is defined by a code system
+    /// (`CodeSystem/$validate-code`), returning a Parameters resource
+    fn validate_cs(
+        &self,
+        code_system: &str,
+        codings: &[Coding],
+        params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError>;
+
+    /// Tests the subsumption relationship between two codes of a code system
+    /// (`CodeSystem/$subsumes`)
+    fn subsumes(
+        &self,
+        system: &str,
+        code_a: &str,
+        code_b: &str,
+        params: &[(String, String)],
+    ) -> Result<SubsumptionOutcome, EvaluationError>;
+
+    /// Translates a coding using a concept map (`ConceptMap/$translate`),
+    /// returning a Parameters resource
+    fn translate(
+        &self,
+        concept_map: &str,
+        coding: &Coding,
+        params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError>;
+
+    /// Returns true if any of the codings is in the value set
+    ///
+    /// The default implementation reads the `result` parameter returned by
+    /// [`TerminologyProvider::validate_vs`].
+    fn member_of(&self, value_set: &str, codings: &[Coding]) -> Result<bool, EvaluationError> {
+        let result = self.validate_vs(value_set, codings, &[])?;
+        Ok(matches!(
+            parameter_value(&result, "result"),
+            Some(EvaluationResult::Boolean(true, _))
+        ))
+    }
+}
+
+thread_local! {
+    /// The deadline of the evaluation making the current provider call
+    static REQUEST_DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Returns the deadline of the evaluation calling the terminology provider
+///
+/// The terminology functions call the provider with the deadline of the
+/// evaluation's limits in effect, so a provider can stop waiting when the
+/// evaluation would stop. Returns None when the evaluation has no deadline and
+/// outside provider calls.
+pub fn request_deadline() -> Option<Instant> {
+    REQUEST_DEADLINE.with(Cell::get)
+}
+
+/// Restores the previous request deadline when a provider call ends
+struct DeadlineScope(Option<Instant>);
+
+impl DeadlineScope {
+    fn enter(context: &EvaluationContext) -> Self {
+        Self(REQUEST_DEADLINE.with(|deadline| deadline.replace(context.limits.deadline)))
+    }
+}
+
+impl Drop for DeadlineScope {
+    fn drop(&mut self) {
+        REQUEST_DEADLINE.with(|deadline| deadline.set(self.0));
+    }
+}
+
+/// A concept of a loaded code system
+#[derive(Debug, Clone)]
+struct Concept {
+    code: String,
+    display: Option<String>,
+    parents: Vec<String>,
+}
+
+/// The parts of a CodeSystem resource used by the in-memory provider
+#[derive(Debug, Clone)]
+struct CodeSystemData {
+    name: Option<String>,
+    version: Option<String>,
+    concepts: Vec<Concept>,
+}
+
+impl CodeSystemData {
+    fn from_result(resource: &EvaluationResult) -> Self {
+        let mut concepts = Vec::new();
+        collect_concepts(resource, None, &mut concepts);
+        Self {
+            name: string_field(resource, "name"),
+            version: string_field(resource, "version"),
+            concepts,
+        }
+    }
+
+    fn concept(&self, code: &str) -> Option<&Concept> {
+        self.concepts.iter().find(|concept| concept.code == code)
+    }
+
+    /// Returns true if `ancestor` is a (transitive) parent of `code`
+    fn is_ancestor(&self, ancestor: &str, code: &str) -> bool {
+        let mut visited = HashSet::new();
+        let mut pending = vec![code];
+        while let Some(current) = pending.pop() {
+            let Some(concept) = self.concept(current) else {
+                continue;
+            };
+            for parent in &concept.parents {
+                if parent == ancestor {
+                    return true;
+                }
+                if visited.insert(parent.as_str()) {
+                    pending.push(parent);
+                }
+            }
+        }
+        false
+    }
+
+    /// Evaluates a `ValueSet.compose.include.filter` against a concept
+    fn matches_filter(
+        &self,
+        concept: &Concept,
+        filter: &EvaluationResult,
+    ) -> Result<bool, EvaluationError> {
+        let property = string_field(filter, "property").unwrap_or_default();
+        let op = string_field(filter, "op").unwrap_or_default();
+        let value = string_field(filter, "value").unwrap_or_default();
+        let code = concept.code.as_str();
+
+        let is_a = || code == value || self.is_ancestor(&value, code);
+        let in_list = || value.split(',').any(|listed| listed.trim() == code);
+        match op.as_str() {
+            "is-a" => Ok(is_a()),
+            "is-not-a" => Ok(!is_a()),
+            "descendent-of" => Ok(self.is_ancestor(&value, code)),
+            "generalizes" => Ok(code == value || self.is_ancestor(code, &value)),
+            "in" => Ok(in_list()),
+            "not-in" => Ok(!in_list()),
+            "=" if property == "code" || property == "concept" => Ok(code == value),
+            "regex" if property == "code" || property == "concept" => {
+                let regex = Regex::new(&format!("^(?:{})$", value)).map_err(|e| {
+                    EvaluationError::InvalidRegex(format!("Invalid filter regex: {}", e))
+                })?;
+                Ok(regex.is_match(code))
+            }
+            _ => Err(EvaluationError::InvalidArgument(format!(
+                "Unsupported value set filter '{} {} {}'",
+                property, op, value
+            ))),
+        }
+    }
+}
+
+/// Flattens the (possibly nested) concepts of a CodeSystem
+///
+/// Parents are taken from the nesting and from `parent` properties.
+fn collect_concepts(node: &EvaluationResult, parent: Option<&str>, out: &mut Vec<Concept>) {
+    for concept in items(field(node, "concept")) {
+        let Some(code) = string_field(concept, "code") else {
+            continue;
+        };
+        let mut parents: Vec<String> = parent.map(str::to_string).into_iter().collect();
+        for property in items(field(concept, "property")) {
+            if string_field(property, "code").as_deref() == Some("parent") {
+                parents.extend(string_field(property, "value"));
+            }
+        }
+        out.push(Concept {
+            code: code.clone(),
+            display: string_field(concept, "display"),
+            parents,
+        });
+        collect_concepts(concept, Some(&code), out);
+    }
+}
+
+/// A terminology provider backed by loaded CodeSystem, ValueSet and
+/// ConceptMap resources
+///
+/// Value sets are expanded from their `compose` definitions (supporting
+/// whole-system includes, enumerated concepts, nested value sets, excludes and
+/// the hierarchical filters `is-a`, `is-not-a`, `descendent-of`,
+/// `generalizes`, `in`, `not-in`, `=` and `regex` on codes), or from their
+/// `expansion` when no `compose` is given. Subsumption follows the concept
+/// hierarchy of the code system.
+#[derive(Debug, Default, Clone)]
+pub struct InMemoryTerminologyProvider {
+    code_systems: HashMap<String, CodeSystemData>,
+    value_sets: HashMap<String, EvaluationResult>,
+    concept_maps: HashMap<String, EvaluationResult>,
+}
+
+impl InMemoryTerminologyProvider {
+    /// Creates an empty provider
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads a CodeSystem, ValueSet or ConceptMap resource
+    ///
+    /// The entries of a Bundle are loaded individually. Other resources, and
+    /// resources without a `url`, are ignored.
+    pub fn add_resource(&mut self, resource: &FhirResource) {
+        self.insert_result(resource.to_evaluation_result());
+    }
+
+    /// Loads an already converted CodeSystem, ValueSet, ConceptMap or Bundle
+    pub fn insert_result(&mut self, resource: EvaluationResult) {
+        let resource_type = string_field(&resource, "resourceType");
+        if resource_type.as_deref() == Some("Bundle") {
+            for entry in items(field(&resource, "entry")) {
+                if let Some(entry_resource) = field(entry, "resource") {
+                    self.insert_result(entry_resource.clone());
+                }
+            }
+            return;
+        }
+
+        let Some(url) = string_field(&resource, "url") else {
+            return;
+        };
+        match resource_type.as_deref() {
+            Some("CodeSystem") => {
+                self.code_systems
+                    .insert(url, CodeSystemData::from_result(&resource));
+            }
+            Some("ValueSet") => {
+                self.value_sets.insert(url, resource);
+            }
+            Some("ConceptMap") => {
+                self.concept_maps.insert(url, resource);
+            }
+            _ => {}
+        }
+    }
+
+    fn code_system(&self, url: &str) -> Result<&CodeSystemData, EvaluationError> {
+        self.code_systems.get(url).ok_or_else(|| {
+            EvaluationError::InvalidArgument(format!("Unknown code system '{}'", url))
+        })
+    }
+
+    /// Expands a value set into its codings
+    ///
+    /// `visiting` holds the value sets currently being expanded, to detect
+    /// value sets that include themselves.
+    fn expand_codings(
+        &self,
+        url: &str,
+        visiting: &mut Vec<String>,
+    ) -> Result<Vec<Coding>, EvaluationError> {
+        if visiting.iter().any(|visited| visited == url) {
+            return Err(EvaluationError::InvalidArgument(format!(
+                "Value set '{}' includes itself",
+                url
+            )));
+        }
+        let value_set = self.value_sets.get(url).ok_or_else(|| {
+            EvaluationError::InvalidArgument(format!("Unknown value set '{}'", url))
+        })?;
+
+        visiting.push(url.to_string());
+        let result = match field(value_set, "compose") {
+            Some(compose) => self.expand_compose(compose, visiting),
+            None => {
+                let mut codings = Vec::new();
+                collect_expansion(field(value_set, "expansion"), &mut codings);
+                Ok(codings)
+            }
+        };
+        visiting.pop();
+        result
+    }
+
+    fn expand_compose(
+        &self,
+        compose: &EvaluationResult,
+        visiting: &mut Vec<String>,
+    ) -> Result<Vec<Coding>, EvaluationError> {
+        let mut included: Vec<Coding> = Vec::new();
+        for include in items(field(compose, "include")) {
+            for coding in self.include_codings(include, visiting)? {
+                if !included.iter().any(|existing| existing.matches(&coding)) {
+                    included.push(coding);
+                }
+            }
+        }
+        for exclude in items(field(compose, "exclude")) {
+            let excluded = self.include_codings(exclude, visiting)?;
+            included.retain(|coding| !excluded.iter().any(|ex| ex.matches(coding)));
+        }
+        Ok(included)
+    }
+
+    /// Returns the codings selected by a single include (or exclude) element
+    fn include_codings(
+        &self,
+        include: &EvaluationResult,
+        visiting: &mut Vec<String>,
+    ) -> Result<Vec<Coding>, EvaluationError> {
+        let mut selected: Option<Vec<Coding>> = None;
+
+        if let Some(system) = string_field(include, "system") {
+            let version = string_field(include, "version");
+            let listed = items(field(include, "concept"));
+            let mut codings = Vec::new();
+            if listed.is_empty() {
+                let code_system = self.code_system(&system)?;
+                let filters = items(field(include, "filter"));
+                'concepts: for concept in &code_system.concepts {
+                    for filter in &filters {
+                        if !code_system.matches_filter(concept, filter)? {
+                            continue 'concepts;
+                        }
+                    }
+                    codings.push(Coding {
+                        system: Some(system.clone()),
+                        version: version.clone(),
+                        code: concept.code.clone(),
+                        display: concept.display.clone(),
+                    });
+                }
+            } else {
+                let code_system = self.code_systems.get(&system);
+                for concept in listed {
+                    let Some(code) = string_field(concept, "code") else {
+                        continue;
+                    };
+                    let display = string_field(concept, "display").or_else(|| {
+                        code_system
+                            .and_then(|cs| cs.concept(&code))
+                            .and_then(|c| c.display.clone())
+                    });
+                    codings.push(Coding {
+                        system: Some(system.clone()),
+                        version: version.clone(),
+                        code,
+                        display,
+                    });
+                }
+            }
+            selected = Some(codings);
+        }
+
+        // Included value sets are intersected with each other and with the system
+        for value_set in items(field(include, "valueSet")) {
+            let Some(url) = value_set.as_string() else {
+                continue;
+            };
+            let expanded = self.expand_codings(url, visiting)?;
+            selected = Some(match selected {
+                None => expanded,
+                Some(current) => current
+                    .into_iter()
+                    .filter(|coding| expanded.iter().any(|other| other.matches(coding)))
+                    .collect(),
+            });
+        }
+
+        Ok(selected.unwrap_or_default())
+    }
+}
+
+/// Collects the codings of a `ValueSet.expansion`, including nested contains
+fn collect_expansion(node: Option<&EvaluationResult>, out: &mut Vec<Coding>) {
+    let Some(node) = node else {
+        return;
+    };
+    for contains in items(field(node, "contains")) {
+        if let Some(code) = string_field(contains, "code") {
+            out.push(Coding {
+                system: string_field(contains, "system"),
+                version: string_field(contains, "version"),
+                code,
+                display: string_field(contains, "display"),
+            });
+        }
+        collect_expansion(Some(contains), out);
+    }
+}
+
+impl TerminologyProvider for InMemoryTerminologyProvider {
+    fn expand(
+        &self,
+        value_set: &str,
+        _params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError> {
+        let codings = self.expand_codings(value_set, &mut Vec::new())?;
+
+        let mut expansion = HashMap::new();
+        expansion.insert(
+            "total".to_string(),
+            EvaluationResult::integer(codings.len() as i64),
+        );
+        expansion.insert(
+            "contains".to_string(),
+            EvaluationResult::collection(codings.iter().map(Coding::to_result).collect()),
+        );
+
+        let mut map = HashMap::new();
+        map.insert(
+            "resourceType".to_string(),
+            EvaluationResult::string("ValueSet".to_string()),
+        );
+        map.insert(
+            "url".to_string(),
+            EvaluationResult::string(value_set.to_string()),
+        );
+        map.insert("expansion".to_string(), EvaluationResult::object(expansion));
+        Ok(EvaluationResult::object(map))
+    }
+
+    fn lookup(
+        &self,
+        coding: &Coding,
+        _params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError> {
+        let system = coding.system.as_deref().ok_or_else(|| {
+            EvaluationError::InvalidArgument(format!(
+                "Cannot look up code '{}' without a system",
+                coding.code
+            ))
+        })?;
+        let code_system = self.code_system(system)?;
+        let concept = code_system.concept(&coding.code).ok_or_else(|| {
+            EvaluationError::InvalidArgument(format!(
+                "Code '{}' not found in code system '{}'",
+                coding.code, system
+            ))
+        })?;
+
+        let mut parameters = vec![parameter(
+            "name",
+            string_value(code_system.name.as_deref().unwrap_or(system)),
+        )];
+        if let Some(version) = &code_system.version {
+            parameters.push(parameter("version", string_value(version)));
+        }
+        if let Some(display) = &concept.display {
+            parameters.push(parameter("display", string_value(display)));
+        }
+        for parent in &concept.parents {
+            parameters.push(part_parameter(
+                "property",
+                vec![
+                    parameter("code", string_value("parent")),
+                    parameter("value", string_value(parent)),
+                ],
+            ));
+        }
+        Ok(parameters_resource(parameters))
+    }
+
+    fn validate_vs(
+        &self,
+        value_set: &str,
+        codings: &[Coding],
+        _params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError> {
+        let expansion = self.expand_codings(value_set, &mut Vec::new())?;
+        let found = codings
+            .iter()
+            .find_map(|coding| expansion.iter().find(|member| member.matches(coding)));
+        Ok(validation_result(
+            found.and_then(|member| member.display.as_deref()),
+            found.is_some(),
+            codings,
+            "value set",
+            value_set,
+        ))
+    }
+
+    fn validate_cs(
+        &self,
+        code_system: &str,
+        codings: &[Coding],
+        _params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError> {
+        let data = self.code_system(code_system)?;
+        let found = codings
+            .iter()
+            .filter(|coding| {
+                coding.system.is_none() || coding.system.as_deref() == Some(code_system)
+            })
+            .find_map(|coding| data.concept(&coding.code));
+        Ok(validation_result(
+            found.and_then(|concept| concept.display.as_deref()),
+            found.is_some(),
+            codings,
+            "code system",
+            code_system,
+        ))
+    }
+
+    fn subsumes(
+        &self,
+        system: &str,
+        code_a: &str,
+        code_b: &str,
+        _params: &[(String, String)],
+    ) -> Result<SubsumptionOutcome, EvaluationError> {
+        let code_system = self.code_system(system)?;
+        for code in [code_a, code_b] {
+            if code_system.concept(code).is_none() {
+                return Err(EvaluationError::InvalidArgument(format!(
+                    "Code '{}' not found in code system '{}'",
+                    code, system
+                )));
+            }
+        }
+        Ok(if code_a == code_b {
+            SubsumptionOutcome::Equivalent
+        } else if code_system.is_ancestor(code_a, code_b) {
+            SubsumptionOutcome::Subsumes
+        } else if code_system.is_ancestor(code_b, code_a) {
+            SubsumptionOutcome::SubsumedBy
+        } else {
+            SubsumptionOutcome::NotSubsumed
+        })
+    }
+
+    fn translate(
+        &self,
+        concept_map: &str,
+        coding: &Coding,
+        _params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError> {
+        let map = self.concept_maps.get(concept_map).ok_or_else(|| {
+            EvaluationError::InvalidArgument(format!("Unknown concept map '{}'", concept_map))
+        })?;
+
+        let mut matches = Vec::new();
+        for group in items(field(map, "group")) {
+            let source = string_field(group, "source");
+            if coding.system.is_some() && source.is_some() && source != coding.system {
+                continue;
+            }
+            let elements = items(field(group, "element"))
+                .into_iter()
+                .filter(|element| string_field(element, "code").as_ref() == Some(&coding.code));
+            for element in elements {
+                for target in items(field(element, "target")) {
+                    let Some(code) = string_field(target, "code") else {
+                        continue;
+                    };
+                    let target_coding = Coding {
+                        system: string_field(group, "target"),
+                        version: string_field(group, "targetVersion"),
+                        code,
+                        display: string_field(target, "display"),
+                    };
+                    // R4 maps use "equivalence", R5 maps use "relationship"
+                    let mut parts = Vec::new();
+                    for name in ["equivalence", "relationship"] {
+                        if let Some(value) = string_field(target, name) {
+                            parts.push(parameter(name, string_value(&value)));
+                        }
+                    }
+                    parts.push(parameter("concept", target_coding.to_result()));
+                    matches.push(part_parameter("match", parts));
+                }
+            }
+        }
+
+        let mut parameters = vec![parameter(
+            "result",
+            EvaluationResult::boolean(!matches.is_empty()),
+        )];
+        if matches.is_empty() {
+            parameters.push(parameter(
+                "message",
+                string_value(&format!(
+                    "No mapping found for code '{}' in concept map '{}'",
+                    coding.code, concept_map
+                )),
+            ));
+        }
+        parameters.extend(matches);
+        Ok(parameters_resource(parameters))
+    }
+}
+
+/// Builds the Parameters resource returned by the validate operations
+fn validation_result(
+    display: Option<&str>,
+    found: bool,
+    codings: &[Coding],
+    kind: &str,
+    url: &str,
+) -> EvaluationResult {
+    let mut parameters = vec![parameter("result", EvaluationResult::boolean(found))];
+    if let Some(display) = display {
+        parameters.push(parameter("display", string_value(display)));
+    }
+    if !found {
+        let codes: Vec<&str> = codings.iter().map(|coding| coding.code.as_str()).collect();
+        parameters.push(parameter(
+            "message",
+            string_value(&format!(
+                "The code '{}' is not in the {} '{}'",
+                codes.join("', '"),
+                kind,
+                url
+            )),
+        ));
+    }
+    parameters_resource(parameters)
+}
+
+/// A terminology provider that calls the operations of a FHIR terminology server
+///
+/// Each call POSTs a Parameters resource to the matching operation endpoint
+/// (`ValueSet/$expand`, `CodeSystem/$lookup`, `ValueSet/$validate-code`,
+/// `CodeSystem/$validate-code`, `CodeSystem/$subsumes` or
+/// `ConceptMap/$translate`) and converts the response using the configured
+/// FHIR version.
+///
+/// Requests share one HTTP client, so connections to the server are reused
+/// by the provider and its clones. Each request times out after the
+/// provider's timeout or at the deadline of the calling evaluation, whichever
+/// comes first. Called from within a Tokio runtime, requests run on the
+/// runtime's blocking thread pool.
+///
+/// # Examples
+///
+/// ```no_run
+/// use helios_fhirpath::terminology::HttpTerminologyProvider;
+/// use helios_fhir::FhirVersion;
+/// use std::time::Duration;
+///
+/// let provider = HttpTerminologyProvider::new("https://tx.fhir.org/r4", FhirVersion::R4)
+///     .with_timeout(Duration::from_secs(10));
+/// ```
+#[derive(Debug, Clone)]
+pub struct HttpTerminologyProvider {
+    base_url: String,
+    fhir_version: FhirVersion,
+    timeout: Duration,
+    /// The client shared by all requests, built by the first request
+    ///
+    /// The blocking client cannot be built on an async runtime thread, where
+    /// providers are often created, so it is built on the thread making the
+    /// first request.
+    client: Arc<OnceLock<reqwest::blocking::Client>>,
+}
+
+impl HttpTerminologyProvider {
+    /// Creates a provider for the terminology server at `base_url`
+    pub fn new(base_url: impl Into<String>, fhir_version: FhirVersion) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            fhir_version,
+            timeout: Duration::from_secs(30),
+            client: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Sets the timeout for each request (30 seconds by default)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the base URL of the terminology server
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Invokes an operation and converts the returned resource
+    fn invoke(
+        &self,
+        operation: &str,
+        mut parameters: Vec<Value>,
+        params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError> {
+        for (name, value) in params {
+            parameters.push(json!({ "name": name, "valueString": value }));
+        }
+        let url = format!("{}/{}", self.base_url, operation);
+        let body = json!({ "resourceType": "Parameters", "parameter": parameters });
+
+        let timeout = match request_deadline() {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(EvaluationError::LimitExceeded(
+                        "Evaluation exceeded its deadline".to_string(),
+                    ));
+                }
+                remaining.min(self.timeout)
+            }
+            None => self.timeout,
+        };
+        let response = self.send(url.clone(), body, timeout)?;
+
+        parse_resource(response, self.fhir_version)
+            .map(|resource| resource.to_evaluation_result())
+            .map_err(|e| {
+                EvaluationError::Other(format!(
+                    "Invalid response from terminology server {}: {}",
+                    url, e
+                ))
+            })
+    }
+
+    /// Posts a request, off the async runtime when called from within one
+    ///
+    /// The blocking client cannot run on an async runtime thread (the server
+    /// evaluates expressions inside tokio), so there the request runs on the
+    /// runtime's blocking thread pool. On a multi-threaded runtime the waiting
+    /// worker hands its other tasks to the remaining workers.
+    fn send(&self, url: String, body: Value, timeout: Duration) -> Result<Value, EvaluationError> {
+        let Ok(handle) = Handle::try_current() else {
+            return self.post(&url, &body, timeout);
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        let provider = self.clone();
+        let request_url = url.clone();
+        handle.spawn_blocking(move || {
+            let _ = sender.send(provider.post(&request_url, &body, timeout));
+        });
+        let received = match handle.runtime_flavor() {
+            RuntimeFlavor::MultiThread => tokio::task::block_in_place(|| receiver.recv()),
+            _ => receiver.recv(),
+        };
+        received.map_err(|_| {
+            EvaluationError::Other(format!("Terminology request to {} panicked", url))
+        })?
+    }
+
+    fn post(&self, url: &str, body: &Value, timeout: Duration) -> Result<Value, EvaluationError> {
+        let request_error = |e: reqwest::Error| {
+            EvaluationError::Other(format!("Terminology request to {} failed: {}", url, e))
+        };
+        let client = match self.client.get() {
+            Some(client) => client,
+            None => {
+                let client = reqwest::blocking::Client::builder()
+                    .build()
+                    .map_err(request_error)?;
+                self.client.get_or_init(|| client)
+            }
+        };
+        let response = client
+            .post(url)
+            .timeout(timeout)
+            .header("Accept", "application/fhir+json")
+            .header("Content-Type", "application/fhir+json")
+            .body(body.to_string())
+            .send()
+            .map_err(request_error)?;
+
+        let status = response.status();
+        let text = response.text().map_err(request_error)?;
+        let json: Option<Value> = serde_json::from_str(&text).ok();
+        if !status.is_success() {
+            let message = json
+                .as_ref()
+                .and_then(operation_outcome_message)
+                .unwrap_or(text);
+            return Err(EvaluationError::Other(format!(
+                "Terminology server returned {} for {}: {}",
+                status, url, message
+            )));
+        }
+        json.ok_or_else(|| {
+            EvaluationError::Other(format!(
+                "Terminology server returned invalid JSON for {}",
+                url
+            ))
+        })
+    }
+
+    /// Returns the parameter used to pass one or several codings
+    fn coded_parameter(codings: &[Coding], coding_name: &str) -> Value {
+        match codings {
+            [coding] => json!({ "name": coding_name, "valueCoding": coding.to_json() }),
+            _ => json!({
+                "name": "codeableConcept",
+                "valueCodeableConcept": {
+                    "coding": codings.iter().map(Coding::to_json).collect::<Vec<_>>()
+                }
+            }),
+        }
+    }
+}
+
+impl TerminologyProvider for HttpTerminologyProvider {
+    fn expand(
+        &self,
+        value_set: &str,
+        params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError> {
+        self.invoke(
+            "ValueSet/$expand",
+            vec![json!({ "name": "url", "valueUri": value_set })],
+            params,
+        )
+    }
+
+    fn lookup(
+        &self,
+        coding: &Coding,
+        params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError> {
+        self.invoke(
+            "CodeSystem/$lookup",
+            vec![json!({ "name": "coding", "valueCoding": coding.to_json() })],
+            params,
+        )
+    }
+
+    fn validate_vs(
+        &self,
+        value_set: &str,
+        codings: &[Coding],
+        params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError> {
+        self.invoke(
+            "ValueSet/$validate-code",
+            vec![
+                json!({ "name": "url", "valueUri": value_set }),
+                Self::coded_parameter(codings, "coding"),
+            ],
+            params,
+        )
+    }
+
+    fn validate_cs(
+        &self,
+        code_system: &str,
+        codings: &[Coding],
+        params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError> {
+        self.invoke(
+            "CodeSystem/$validate-code",
+            vec![
+                json!({ "name": "url", "valueUri": code_system }),
+                Self::coded_parameter(codings, "coding"),
+            ],
+            params,
+        )
+    }
+
+    fn subsumes(
+        &self,
+        system: &str,
+        code_a: &str,
+        code_b: &str,
+        params: &[(String, String)],
+    ) -> Result<SubsumptionOutcome, EvaluationError> {
+        let result = self.invoke(
+            "CodeSystem/$subsumes",
+            vec![
+                json!({ "name": "system", "valueUri": system }),
+                json!({ "name": "codeA", "valueCode": code_a }),
+                json!({ "name": "codeB", "valueCode": code_b }),
+            ],
+            params,
+        )?;
+        parameter_value(&result, "outcome")
+            .and_then(|outcome| outcome.as_string())
+            .and_then(|outcome| SubsumptionOutcome::from_code(outcome))
+            .ok_or_else(|| {
+                EvaluationError::Other(
+                    "Terminology server returned no subsumption outcome".to_string(),
+                )
+            })
+    }
+
+    fn translate(
+        &self,
+        concept_map: &str,
+        coding: &Coding,
+        params: &[(String, String)],
+    ) -> Result<EvaluationResult, EvaluationError> {
+        // R5 renamed the input coding parameter of $translate
+        let coding_name = match self.fhir_version.as_str() {
+            "R5" | "R6" => "sourceCoding",
+            _ => "coding",
+        };
+        self.invoke(
+            "ConceptMap/$translate",
+            vec![
+                json!({ "name": "url", "valueUri": concept_map }),
+                json!({ "name": coding_name, "valueCoding": coding.to_json() }),
+            ],
+            params,
+        )
+    }
+}
+
+/// Extracts the diagnostics of an OperationOutcome returned by a server
+fn operation_outcome_message(outcome: &Value) -> Option<String> {
+    let messages: Vec<&str> = outcome
+        .get("issue")?
+        .as_array()?
+        .iter()
+        .filter_map(|issue| {
+            issue
+                .get("diagnostics")
+                .or_else(|| issue.get("details").and_then(|d| d.get("text")))
+                .and_then(Value::as_str)
+        })
+        .collect();
+    (!messages.is_empty()).then(|| messages.join("; "))
+}
+
+/// Parses a JSON resource for the given FHIR version
+pub(crate) fn parse_resource(json: Value, version: FhirVersion) -> Result<FhirResource, String> {
+    match version {
+        #[cfg(feature = "R4")]
+        FhirVersion::R4 => serde_json::from_value::<helios_fhir::r4::Resource>(json)
+            .map(|resource| FhirResource::R4(Box::new(resource)))
+            .map_err(|e| e.to_string()),
+        #[cfg(feature = "R4B")]
+        FhirVersion::R4B => serde_json::from_value::<helios_fhir::r4b::Resource>(json)
+            .map(|resource| FhirResource::R4B(Box::new(resource)))
+            .map_err(|e| e.to_string()),
+        #[cfg(feature = "R5")]
+        FhirVersion::R5 => serde_json::from_value::<helios_fhir::r5::Resource>(json)
+            .map(|resource| FhirResource::R5(Box::new(resource)))
+            .map_err(|e| e.to_string()),
+        #[cfg(feature = "R6")]
+        FhirVersion::R6 => serde_json::from_value::<helios_fhir::r6::Resource>(json)
+            .map(|resource| FhirResource::R6(Box::new(resource)))
+            .map_err(|e| e.to_string()),
+    }
+}
+
+/// Implementation of the FHIR memberOf() function
+///
+/// Syntax: memberOf(valueset : String) : Boolean
+///
+/// Returns true if the code, Coding or CodeableConcept in the input is a
+/// member of the given value set.
+///
+/// # Arguments
+///
+/// * `invocation_base` - The coded value to test
+/// * `args` - The value set URL
+/// * `context` - The evaluation context providing the terminology provider
+///
+/// # Returns
+///
+/// * A Boolean, or Empty if the input or value set is empty
+/// * `Err` - If no provider is configured or the value set is unknown
+pub fn member_of_function(
+    invocation_base: &EvaluationResult,
+    args: &[EvaluationResult],
+    context: &EvaluationContext,
+) -> Result<EvaluationResult, EvaluationError> {
+    let Some(codings) = singleton_codings(invocation_base, "memberOf")? else {
+        return Ok(EvaluationResult::Empty);
+    };
+    let value_set = match &args[0] {
+        EvaluationResult::Empty => return Ok(EvaluationResult::Empty),
+        value => url_argument(value, "memberOf")?,
+    };
+    let provider = provider(context, "memberOf()")?;
+    let _deadline = DeadlineScope::enter(context);
+    Ok(EvaluationResult::boolean(
+        provider.member_of(&value_set, &codings)?,
+    ))
+}
+
+/// Implementation of the FHIR subsumes() function
+///
+/// Syntax: subsumes(code : Coding | code) : Boolean
+///
+/// Returns true if the code in the input subsumes (or is equivalent to) the
+/// given code. When the input is a CodeableConcept, any of its codings may
+/// subsume the argument.
+///
+/// # Returns
+///
+/// * A Boolean, or Empty if either side is empty or has no code system
+/// * `Err` - If no provider is configured
+pub fn subsumes_function(
+    invocation_base: &EvaluationResult,
+    args: &[EvaluationResult],
+    context: &EvaluationContext,
+) -> Result<EvaluationResult, EvaluationError> {
+    subsumption_function(invocation_base, args, context, "subsumes")
+}
+
+/// Implementation of the FHIR subsumedBy() function
+///
+/// Syntax: subsumedBy(code : Coding | code) : Boolean
+///
+/// Returns true if the code in the input is subsumed by (or is equivalent to)
+/// the given code.
+///
+/// # Returns
+///
+/// * A Boolean, or Empty if either side is empty or has no code system
+/// * `Err` - If no provider is configured
+pub fn subsumed_by_function(
+    invocation_base: &EvaluationResult,
+    args: &[EvaluationResult],
+    context: &EvaluationContext,
+) -> Result<EvaluationResult, EvaluationError> {
+    subsumption_function(invocation_base, args, context, "subsumedBy")
+}
+
+fn subsumption_function(
+    invocation_base: &EvaluationResult,
+    args: &[EvaluationResult],
+    context: &EvaluationContext,
+    function_name: &str,
+) -> Result<EvaluationResult, EvaluationError> {
+    let Some(codings) = singleton_codings(invocation_base, function_name)? else {
+        return Ok(EvaluationResult::Empty);
+    };
+    let Some(other) =
+        singleton_codings(&args[0], function_name)?.and_then(|other| other.into_iter().next())
+    else {
+        return Ok(EvaluationResult::Empty);
+    };
+    let provider = provider(context, &format!("{}()", function_name))?;
+    let _deadline = DeadlineScope::enter(context);
+
+    let mut compared = false;
+    for coding in &codings {
+        let Some(system) = coding.system.as_ref().or(other.system.as_ref()) else {
+            continue;
+        };
+        if other
+            .system
+            .as_ref()
+            .is_some_and(|other_system| other_system != system)
+        {
+            continue;
+        }
+        compared = true;
+        let outcome = provider.subsumes(system, &coding.code, &other.code, &[])?;
+        let wanted = match function_name {
+            "subsumes" => SubsumptionOutcome::Subsumes,
+            _ => SubsumptionOutcome::SubsumedBy,
+        };
+        if outcome == SubsumptionOutcome::Equivalent || outcome == wanted {
+            return Ok(EvaluationResult::boolean(true));
+        }
+    }
+
+    // Codes from different (or unknown) systems cannot be compared
+    Ok(if compared || other.system.is_some() {
+        EvaluationResult::boolean(false)
+    } else {
+        EvaluationResult::Empty
+    })
+}
+
+/// Implementation of the `%terminologies` functions
+///
+/// Supported functions:
+/// - `expand(valueSet, params)` - returns the expanded ValueSet
+/// - `lookup(coded, params)` - returns Parameters with the code's details
+/// - `validateVS(valueSet, coded, params)` - returns Parameters with a `result`
+/// - `validateCS(codeSystem, coded, params)` - returns Parameters with a `result`
+/// - `subsumes(system, coded1, coded2, params)` - returns the outcome code
+/// - `translate(conceptMap, coded, params)` - returns Parameters with the matches
+///
+/// The optional `params` argument is a URL-encoded style string of
+/// additional parameters, e.g. `'displayLanguage=de&activeOnly=true'`.
+///
+/// # Arguments
+///
+/// * `name` - The function name
+/// * `args` - The evaluated arguments
+/// * `context` - The evaluation context providing the terminology provider
+///
+/// # Returns
+///
+/// * The operation result, or Empty if a required argument is empty
+/// * `Err` - If the function is unknown, no provider is configured or the
+///   operation fails
+pub fn terminologies_function(
+    name: &str,
+    args: &[EvaluationResult],
+    context: &EvaluationContext,
+) -> Result<EvaluationResult, EvaluationError> {
+    let required = match name {
+        "expand" | "lookup" => 1,
+        "validateVS" | "validateCS" | "translate" => 2,
+        "subsumes" => 3,
+        _ => {
+            return Err(EvaluationError::InvalidOperation(format!(
+                "Unknown terminology function '%terminologies.{}'",
+                name
+            )));
+        }
+    };
+    let function_name = format!("%terminologies.{}", name);
+    if args.len() != required && args.len() != required + 1 {
+        return Err(EvaluationError::InvalidArity(format!(
+            "Function '{}' expects {} or {} arguments",
+            function_name,
+            required,
+            required + 1
+        )));
+    }
+    if args[..required]
+        .iter()
+        .any(|arg| matches!(arg, EvaluationResult::Empty))
+    {
+        return Ok(EvaluationResult::Empty);
+    }
+
+    let params = match args.get(required) {
+        Some(EvaluationResult::String(params, _)) => parse_params(params),
+        Some(EvaluationResult::Empty) | None => Vec::new(),
+        Some(other) => {
+            return Err(EvaluationError::TypeError(format!(
+                "{}() expects its params argument to be a String, found {}",
+                function_name,
+                other.type_name()
+            )));
+        }
+    };
+    let provider = provider(context, &format!("{}()", function_name))?;
+    let _deadline = DeadlineScope::enter(context);
+
+    match name {
+        "expand" => provider.expand(&url_argument(&args[0], &function_name)?, &params),
+        "lookup" => provider.lookup(&single_coding(&args[0], &function_name)?, &params),
+        "validateVS" => provider.validate_vs(
+            &url_argument(&args[0], &function_name)?,
+            &coded_argument(&args[1], &function_name)?,
+            &params,
+        ),
+        "validateCS" => provider.validate_cs(
+            &url_argument(&args[0], &function_name)?,
+            &coded_argument(&args[1], &function_name)?,
+            &params,
+        ),
+        "subsumes" => {
+            let system = url_argument(&args[0], &function_name)?;
+            let code_a = single_coding(&args[1], &function_name)?;
+            let code_b = single_coding(&args[2], &function_name)?;
+            let outcome = provider.subsumes(&system, &code_a.code, &code_b.code, &params)?;
+            Ok(EvaluationResult::string(outcome.as_code().to_string()))
+        }
+        _ => provider.translate(
+            &url_argument(&args[0], &function_name)?,
+            &single_coding(&args[1], &function_name)?,
+            &params,
+        ),
+    }
+}
+
+fn provider<'a>(
+    context: &'a EvaluationContext,
+    function_name: &str,
+) -> Result<&'a dyn TerminologyProvider, EvaluationError> {
+    context.terminology_provider.as_deref().ok_or_else(|| {
+        EvaluationError::InvalidOperation(format!(
+            "{} requires a terminology provider, but none is configured",
+            function_name
+        ))
+    })
+}
+
+/// Extracts the codings of a singleton coded input, or None if it is empty
+fn singleton_codings(
+    value: &EvaluationResult,
+    function_name: &str,
+) -> Result<Option<Vec<Coding>>, EvaluationError> {
+    if value.count() > 1 {
+        return Err(EvaluationError::SingletonEvaluationError(format!(
+            "{}() requires a singleton input",
+            function_name
+        )));
+    }
+    let codings = Coding::from_result(value);
+    Ok((!codings.is_empty()).then_some(codings))
+}
+
+fn coded_argument(
+    value: &EvaluationResult,
+    function_name: &str,
+) -> Result<Vec<Coding>, EvaluationError> {
+    let codings = Coding::from_result(value);
+    if codings.is_empty() {
+        return Err(EvaluationError::TypeError(format!(
+            "{}() expects a code, Coding or CodeableConcept, found {}",
+            function_name,
+            value.type_name()
+        )));
+    }
+    Ok(codings)
+}
+
+fn single_coding(value: &EvaluationResult, function_name: &str) -> Result<Coding, EvaluationError> {
+    let mut codings = coded_argument(value, function_name)?;
+    if codings.len() > 1 {
+        return Err(EvaluationError::SingletonEvaluationError(format!(
+            "{}() expects a single code or Coding",
+            function_name
+        )));
+    }
+    Ok(codings.remove(0))
+}
+
+/// Extracts a canonical URL from a string or from a resource's `url`
+fn url_argument(value: &EvaluationResult, function_name: &str) -> Result<String, EvaluationError> {
+    match value {
+        EvaluationResult::String(url, _) => Ok(url.clone()),
+        EvaluationResult::Object { .. } => string_field(value, "url").ok_or_else(|| {
+            EvaluationError::InvalidArgument(format!(
+                "{}() was given a resource without a url",
+                function_name
+            ))
+        }),
+        other => Err(EvaluationError::TypeError(format!(
+            "{}() expects a URL string, found {}",
+            function_name,
+            other.type_name()
+        ))),
+    }
+}
+
+/// Splits a `name=value&name=value` parameter string
+fn parse_params(params: &str) -> Vec<(String, String)> {
+    params
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => (pair.to_string(), String::new()),
+        })
+        .collect()
+}
+
+/// Returns the value of the named parameter of a Parameters resource
+pub fn parameter_value<'a>(
+    parameters: &'a EvaluationResult,
+    name: &str,
+) -> Option<&'a EvaluationResult> {
+    items(field(parameters, "parameter"))
+        .into_iter()
+        .find(|parameter| string_field(parameter, "name").as_deref() == Some(name))
+        .and_then(|parameter| field(parameter, "value"))
+}
+
+fn parameter(name: &str, value: EvaluationResult) -> EvaluationResult {
+    let mut map = HashMap::new();
+    map.insert("name".to_string(), string_value(name));
+    map.insert("value".to_string(), value);
+    EvaluationResult::object(map)
+}
+
+fn part_parameter(name: &str, parts: Vec<EvaluationResult>) -> EvaluationResult {
+    let mut map = HashMap::new();
+    map.insert("name".to_string(), string_value(name));
+    map.insert("part".to_string(), EvaluationResult::collection(parts));
+    EvaluationResult::object(map)
+}
+
+fn parameters_resource(parameters: Vec<EvaluationResult>) -> EvaluationResult {
+    let mut map = HashMap::new();
+    map.insert(
+        "resourceType".to_string(),
+        EvaluationResult::string("Parameters".to_string()),
+    );
+    map.insert(
+        "parameter".to_string(),
+        EvaluationResult::collection(parameters),
+    );
+    EvaluationResult::object(map)
+}
+
+fn string_value(value: &str) -> EvaluationResult {
+    EvaluationResult::string(value.to_string())
+}
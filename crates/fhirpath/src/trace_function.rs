@@ -153,11 +153,8 @@ pub fn trace_function(
         invocation_base.clone()
     };
 
-    // Store the trace output in the context using RefCell
-    context
-        .trace_outputs
-        .borrow_mut()
-        .push((name.to_string(), trace_value));
+    // Send the trace output to the context's trace sink
+    context.trace(name, &trace_value);
 
     // Return the original input collection unchanged
     Ok(invocation_base.clone())
@@ -0,0 +1,208 @@
+//! # Trace Sinks
+//!
+//! Destinations for the output of the `trace()` function.
+//!
+//! Each call of `trace(name [, projection])` hands its name and traced value to
+//! the [`TraceSink`] attached to the [`EvaluationContext`](crate::EvaluationContext).
+//! Three sinks are included:
+//!
+//! - [`BufferedTraceSink`]: collects the traces in memory, optionally bounded
+//! - [`TracingTraceSink`]: logs each trace as a `tracing` event
+//! - [`CallbackTraceSink`]: passes each trace to a closure
+//!
+//! Without a sink, the context buffers traces itself so that they can be read
+//! with `EvaluationContext::get_trace_outputs()`. That buffer keeps at most
+//! 10,000 traces; set a sink to keep more.
+//!
+//! ```
+//! use helios_fhirpath::evaluator::EvaluationContext;
+//! use helios_fhirpath::trace_sink::CallbackTraceSink;
+//! use helios_fhirpath::evaluate_expression;
+//! use std::sync::{Arc, Mutex};
+//!
+//! let names = Arc::new(Mutex::new(Vec::new()));
+//! let sink = CallbackTraceSink::new({
+//!     let names = names.clone();
+//!     move |name, _value| names.lock().unwrap().push(name.to_string())
+//! });
+//!
+//! let mut context = EvaluationContext::new_empty_with_default_version();
+//! context.set_trace_sink(Arc::new(sink));
+//! evaluate_expression("(1 | 2).trace('numbers').count()", &context).unwrap();
+//!
+//! assert_eq!(*names.lock().unwrap(), vec!["numbers"]);
+//! ```
+
+use helios_fhirpath_support::EvaluationResult;
+use std::fmt;
+use std::sync::Mutex;
+use tracing::Level;
+
+/// Receives the output of `trace()` calls
+///
+/// Sinks must be thread-safe so a single sink can be shared between
+/// evaluation contexts.
+pub trait TraceSink: Send + Sync {
+    /// Records the value traced under `name`
+    fn trace(&self, name: &str, value: &EvaluationResult);
+}
+
+/// A sink that collects traces in memory
+///
+/// With a maximum number of entries, traces beyond the maximum are counted
+/// but not kept.
+#[derive(Debug, Default)]
+pub struct BufferedTraceSink {
+    buffer: Mutex<TraceBuffer>,
+    max_entries: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+struct TraceBuffer {
+    outputs: Vec<(String, EvaluationResult)>,
+    dropped: usize,
+}
+
+impl BufferedTraceSink {
+    /// Creates a sink that keeps every trace
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a sink that keeps at most `max_entries` traces
+    pub fn with_max_entries(max_entries: usize) -> Self {
+        Self {
+            max_entries: Some(max_entries),
+            ..Self::default()
+        }
+    }
+
+    /// Returns a copy of the collected traces, in the order they were made
+    pub fn outputs(&self) -> Vec<(String, EvaluationResult)> {
+        self.buffer
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .outputs
+            .clone()
+    }
+
+    /// Removes and returns the collected traces
+    ///
+    /// A warning is logged when traces have been dropped since the last
+    /// `clear`. The count of dropped traces is left unchanged; `clear` resets
+    /// it.
+    pub fn take(&self) -> Vec<(String, EvaluationResult)> {
+        let mut buffer = self.buffer.lock().unwrap_or_else(|e| e.into_inner());
+        if buffer.dropped > 0 {
+            tracing::warn!(
+                dropped = buffer.dropped,
+                "trace sink was full; {} traces were not kept",
+                buffer.dropped
+            );
+        }
+        std::mem::take(&mut buffer.outputs)
+    }
+
+    /// Removes the collected traces and resets the count of dropped traces
+    pub fn clear(&self) {
+        *self.buffer.lock().unwrap_or_else(|e| e.into_inner()) = TraceBuffer::default();
+    }
+
+    /// Number of traces that were not kept because the sink was full
+    pub fn dropped(&self) -> usize {
+        self.buffer
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .dropped
+    }
+}
+
+impl TraceSink for BufferedTraceSink {
+    fn trace(&self, name: &str, value: &EvaluationResult) {
+        let mut buffer = self.buffer.lock().unwrap_or_else(|e| e.into_inner());
+        if self
+            .max_entries
+            .is_some_and(|max| buffer.outputs.len() >= max)
+        {
+            buffer.dropped += 1;
+            return;
+        }
+        buffer.outputs.push((name.to_string(), value.clone()));
+    }
+}
+
+/// A sink that logs each trace as a `tracing` event
+///
+/// Events have the target `fhirpath::trace` and carry the trace name and the
+/// string value of the traced collection.
+#[derive(Debug, Clone, Copy)]
+pub struct TracingTraceSink {
+    level: Level,
+}
+
+impl TracingTraceSink {
+    /// Creates a sink that logs at debug level
+    pub fn new() -> Self {
+        Self::with_level(Level::DEBUG)
+    }
+
+    /// Creates a sink that logs at `level`
+    pub fn with_level(level: Level) -> Self {
+        Self { level }
+    }
+}
+
+impl Default for TracingTraceSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TraceSink for TracingTraceSink {
+    fn trace(&self, name: &str, value: &EvaluationResult) {
+        let value = value.to_string_value();
+        // The level of a tracing event must be known at compile time
+        if self.level == Level::ERROR {
+            tracing::error!(target: "fhirpath::trace", name, %value);
+        } else if self.level == Level::WARN {
+            tracing::warn!(target: "fhirpath::trace", name, %value);
+        } else if self.level == Level::INFO {
+            tracing::info!(target: "fhirpath::trace", name, %value);
+        } else if self.level == Level::DEBUG {
+            tracing::debug!(target: "fhirpath::trace", name, %value);
+        } else {
+            tracing::trace!(target: "fhirpath::trace", name, %value);
+        }
+    }
+}
+
+type TraceCallback = dyn Fn(&str, &EvaluationResult) + Send + Sync;
+
+/// A sink that passes each trace to a closure
+pub struct CallbackTraceSink {
+    callback: Box<TraceCallback>,
+}
+
+impl CallbackTraceSink {
+    /// Creates a sink that calls `callback` with the name and value of each trace
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&str, &EvaluationResult) + Send + Sync + 'static,
+    {
+        Self {
+            callback: Box::new(callback),
+        }
+    }
+}
+
+impl fmt::Debug for CallbackTraceSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CallbackTraceSink").finish_non_exhaustive()
+    }
+}
+
+impl TraceSink for CallbackTraceSink {
+    fn trace(&self, name: &str, value: &EvaluationResult) {
+        (self.callback)(name, value)
+    }
+}
@@ -0,0 +1,1504 @@
+//! # Static Type Checking
+//!
+//! This module provides [`TypeChecker`], which checks a FHIRPath expression
+//! against the FHIR model of a [`FhirVersion`] without evaluating it. Every path
+//! step is resolved through the [`TypeMetadata`] tables generated for the FHIR
+//! structures, so the checker reports:
+//!
+//! - unknown elements, such as `Patient.nmae`
+//! - choice-type suffixes the element does not allow, such as `Observation.valueFoo`
+//! - unknown functions and functions called with the wrong number of arguments
+//! - function inputs and arguments of the wrong type, such as `Patient.name.upper()`
+//! - unknown type names in `is`, `as` and `ofType()`
+//!
+//! Functions of a [`FunctionRegistry`] passed to
+//! [`TypeChecker::with_function_registry`] are checked against their declared
+//! arity and return type.
+//!
+//! The checker also computes the type the expression is expected to return,
+//! reported to fhirpath-lab as `expectedReturnType`.
+//!
+//! ```rust
+//! use helios_fhir::FhirVersion;
+//! use helios_fhirpath::type_checker::TypeChecker;
+//!
+//! let checker = TypeChecker::new(FhirVersion::R4);
+//!
+//! let result = checker.check("Patient.name.given")?;
+//! assert_eq!(result.expected_return_type().as_deref(), Some("string[]"));
+//!
+//! let result = checker.check("Patient.nmae")?;
+//! assert!(result.has_errors());
+//! assert_eq!(result.diagnostics[0].position, Some(8));
+//! # Ok::<(), String>(())
+//! ```
+//!
+//! ## Positions
+//!
+//! Diagnostics point at the name, operator or argument they report, using the
+//! spans the parser records on the syntax tree. Positions are byte offsets into
+//! the expression.
+
+use crate::compiled::CompiledExpression;
+use crate::function_registry::{ArgumentEvaluation, CustomFunction, FunctionRegistry};
+use crate::json_resource::resource_metadata;
+use crate::parser::{Expression, Invocation, Literal, Span, Term, TypeSpecifier};
+use crate::type_inference::{BuiltinReturnType, InferredType, builtin_return_type};
+use helios_fhir::{FhirResourceTypeProvider, FhirVersion};
+use helios_fhirpath_support::{ElementMetadata, PrimitiveKind, TypeMetadata};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// FHIR primitive types whose values are strings
+const STRING_PRIMITIVES: [&str; 11] = [
+    "string",
+    "code",
+    "id",
+    "uri",
+    "url",
+    "canonical",
+    "markdown",
+    "base64Binary",
+    "oid",
+    "uuid",
+    "xhtml",
+];
+
+/// FHIR primitive types whose values are integers
+const INTEGER_PRIMITIVES: [&str; 4] = ["integer", "positiveInt", "unsignedInt", "integer64"];
+
+/// FHIR primitive types whose values are dates, times or both
+const TEMPORAL_PRIMITIVES: [&str; 4] = ["date", "dateTime", "instant", "time"];
+
+/// Types of the System namespace
+const SYSTEM_TYPES: [&str; 9] = [
+    "Boolean", "String", "Integer", "Long", "Decimal", "Date", "DateTime", "Time", "Quantity",
+];
+
+/// Abstract FHIR types that have no generated structure
+const ABSTRACT_TYPES: [&str; 7] = [
+    "Base",
+    "Resource",
+    "DomainResource",
+    "Element",
+    "BackboneElement",
+    "CanonicalResource",
+    "MetadataResource",
+];
+
+/// FHIR data types that hold quantities
+const QUANTITY_TYPES: [&str; 6] = [
+    "Quantity",
+    "SimpleQuantity",
+    "Age",
+    "Count",
+    "Distance",
+    "Duration",
+];
+
+/// Environment variables defined by the FHIRPath and FHIR specifications
+const STRING_VARIABLES: [&str; 3] = ["ucum", "sct", "loinc"];
+
+static MODELS: Lazy<Mutex<HashMap<FhirVersion, Arc<Model>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// How serious a [`Diagnostic`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The expression is invalid for the FHIR model
+    Error,
+    /// The expression is valid but will not behave as it reads, e.g. it always
+    /// returns an empty collection
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem found by the [`TypeChecker`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// Byte offset of the reported name in the expression, if it could be located
+    pub position: Option<usize>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some(position) => write!(
+                f,
+                "{}: {} (at position {})",
+                self.severity, self.message, position
+            ),
+            None => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+/// The outcome of checking an expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeCheckResult {
+    /// The types the expression can return, all flagged as collections when the
+    /// result can hold more than one item; empty when the type is unknown
+    pub return_types: Vec<InferredType>,
+    /// Problems found in the expression, in source order
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl TypeCheckResult {
+    /// Returns whether any diagnostic is an error
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+
+    /// Formats the return type the way fhirpath-lab displays it, e.g. `string[]`
+    ///
+    /// Choice types list every possible type separated by ` | `. Returns `None`
+    /// when the type is unknown.
+    pub fn expected_return_type(&self) -> Option<String> {
+        if self.return_types.is_empty() {
+            return None;
+        }
+        Some(
+            self.return_types
+                .iter()
+                .map(InferredType::to_display_string)
+                .collect::<Vec<_>>()
+                .join(" | "),
+        )
+    }
+}
+
+/// Checks FHIRPath expressions against the FHIR model of a FHIR version
+///
+/// Without a root type, expressions starting with a resource type name
+/// (`Patient.name`) are checked from that resource; other paths are left
+/// unchecked until they reach a known type.
+#[derive(Clone)]
+pub struct TypeChecker {
+    model: Arc<Model>,
+    root_type: Option<InferredType>,
+    context_types: Option<Vec<InferredType>>,
+    variables: HashMap<String, InferredType>,
+    function_registry: Option<Arc<dyn FunctionRegistry>>,
+}
+
+impl fmt::Debug for TypeChecker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypeChecker")
+            .field("root_type", &self.root_type)
+            .field("context_types", &self.context_types)
+            .field("variables", &self.variables)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TypeChecker {
+    /// Creates a checker for the model of `fhir_version`
+    pub fn new(fhir_version: FhirVersion) -> Self {
+        Self {
+            model: model(fhir_version),
+            root_type: None,
+            context_types: None,
+            variables: HashMap::new(),
+            function_registry: None,
+        }
+    }
+
+    /// Sets the type of the resource the expression is evaluated against
+    pub fn with_root_type(mut self, root_type: InferredType) -> Self {
+        self.root_type = Some(root_type);
+        self
+    }
+
+    /// Sets the types of the context items when the expression is evaluated
+    /// against the results of a context expression rather than the resource
+    pub fn with_context_types(mut self, context_types: Vec<InferredType>) -> Self {
+        self.context_types = Some(context_types);
+        self
+    }
+
+    /// Declares the type of an external constant (`%name`)
+    pub fn with_variable(mut self, name: &str, variable_type: InferredType) -> Self {
+        self.variables.insert(name.to_string(), variable_type);
+        self
+    }
+
+    /// Makes the functions of `registry` known to the checker
+    pub fn with_function_registry(mut self, registry: Arc<dyn FunctionRegistry>) -> Self {
+        self.function_registry = Some(registry);
+        self
+    }
+
+    /// Parses and checks an expression
+    ///
+    /// Returns the parse error if the expression is not valid FHIRPath.
+    pub fn check(&self, expression: &str) -> Result<TypeCheckResult, String> {
+        let compiled = CompiledExpression::compile(expression)?;
+        Ok(self.check_compiled(&compiled))
+    }
+
+    /// Checks a compiled expression
+    pub fn check_compiled(&self, compiled: &CompiledExpression) -> TypeCheckResult {
+        self.check_expression(compiled.expression(), compiled.source())
+    }
+
+    /// Checks a parsed expression; `source` is the text it was parsed from and
+    /// is used to locate diagnostics
+    pub fn check_expression(&self, expression: &Expression, source: &str) -> TypeCheckResult {
+        let root = self
+            .root_type
+            .as_ref()
+            .map(|root_type| StaticType::single(strip_collection(root_type)))
+            .unwrap_or_else(StaticType::unknown);
+        let focus = match &self.context_types {
+            Some(types) => StaticType {
+                types: types.iter().map(strip_collection).collect(),
+                is_collection: false,
+            },
+            None => root.clone(),
+        };
+
+        let mut variables: HashMap<String, StaticType> = self
+            .variables
+            .iter()
+            .map(|(name, variable_type)| {
+                (
+                    name.clone(),
+                    StaticType {
+                        types: vec![strip_collection(variable_type)],
+                        is_collection: variable_type.is_collection,
+                    },
+                )
+            })
+            .collect();
+        for name in ["resource", "rootResource", "context"] {
+            variables.entry(name.to_string()).or_insert(root.clone());
+        }
+
+        let mut walker = Walker {
+            model: &self.model,
+            source,
+            function_registry: self.function_registry.as_deref(),
+            variables,
+            diagnostics: Vec::new(),
+        };
+        let result = walker.expression(expression, &focus);
+
+        TypeCheckResult {
+            return_types: result.to_inferred_types(),
+            diagnostics: walker.diagnostics,
+        }
+    }
+}
+
+/// Infers the type of a member of `input_type` from the FHIR model
+///
+/// Returns `None` when the member is unknown or can have more than one type.
+pub(crate) fn member_type(
+    fhir_version: FhirVersion,
+    input_type: &InferredType,
+    member_name: &str,
+) -> Option<InferredType> {
+    let model = model(fhir_version);
+    if input_type.name == member_name && model.is_resource_type(member_name) {
+        return Some(input_type.clone());
+    }
+    let (types, is_array) = model.member(&strip_collection(input_type), member_name)?;
+    match types.as_slice() {
+        [member_type] if input_type.is_collection || is_array => {
+            Some(member_type.clone().collection())
+        }
+        [member_type] => Some(member_type.clone()),
+        _ => None,
+    }
+}
+
+/// Returns the cached model index of a FHIR version
+fn model(fhir_version: FhirVersion) -> Arc<Model> {
+    let mut models = MODELS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    models
+        .entry(fhir_version)
+        .or_insert_with(|| Arc::new(Model::new(fhir_version)))
+        .clone()
+}
+
+/// Returns the names of the resource types of a FHIR version
+fn resource_type_names(fhir_version: FhirVersion) -> Vec<&'static str> {
+    match fhir_version {
+        #[cfg(feature = "R4")]
+        FhirVersion::R4 => helios_fhir::r4::Resource::get_resource_type_names(),
+        #[cfg(feature = "R4B")]
+        FhirVersion::R4B => helios_fhir::r4b::Resource::get_resource_type_names(),
+        #[cfg(feature = "R5")]
+        FhirVersion::R5 => helios_fhir::r5::Resource::get_resource_type_names(),
+        #[cfg(feature = "R6")]
+        FhirVersion::R6 => helios_fhir::r6::Resource::get_resource_type_names(),
+    }
+}
+
+/// The complex types of a FHIR version, indexed by name
+#[derive(Debug)]
+struct Model {
+    resource_types: Vec<&'static str>,
+    types: HashMap<&'static str, &'static TypeMetadata>,
+}
+
+impl Model {
+    /// Indexes every complex type reachable from the resources of a version
+    fn new(fhir_version: FhirVersion) -> Self {
+        let resource_types = resource_type_names(fhir_version);
+        let mut types = HashMap::new();
+        if let TypeMetadata::Resource { lookup } = resource_metadata(fhir_version) {
+            for name in &resource_types {
+                if let Some(metadata) = lookup(name) {
+                    index_type(metadata, &mut types);
+                }
+            }
+        }
+        Self {
+            resource_types,
+            types,
+        }
+    }
+
+    fn is_resource_type(&self, name: &str) -> bool {
+        self.resource_types.contains(&name)
+    }
+
+    /// Returns whether a type is known to the model or the System namespace
+    fn is_known(&self, inferred: &InferredType) -> bool {
+        if inferred.namespace == "system" {
+            return SYSTEM_TYPES.contains(&inferred.name.as_str());
+        }
+        self.types.contains_key(inferred.name.as_str()) || primitive_kind(&inferred.name).is_some()
+    }
+
+    /// Resolves a member of a single (non-collection) type
+    ///
+    /// Returns the member's possible types and whether the element repeats, or
+    /// `None` if the type has no such member. Types the model does not describe
+    /// (abstract types, unknown names) resolve any member to an unknown type.
+    fn member(&self, input: &InferredType, name: &str) -> Option<(Vec<InferredType>, bool)> {
+        if input.namespace == "system" {
+            // System values have no members; types outside the System namespace
+            // (such as `Any`) are unknown
+            return if SYSTEM_TYPES.contains(&input.name.as_str()) {
+                None
+            } else {
+                Some((Vec::new(), false))
+            };
+        }
+        if primitive_kind(&input.name).is_some() {
+            return match name {
+                "id" => Some((vec![InferredType::fhir("string")], false)),
+                "extension" => Some((vec![InferredType::fhir("Extension")], true)),
+                _ => None,
+            };
+        }
+        let Some(metadata) = self.types.get(input.name.as_str()) else {
+            return Some((Vec::new(), false));
+        };
+        if let Some(element) = metadata.element(name) {
+            return Some((element_types(element), element.is_array));
+        }
+        choice_variant(metadata, name).map(|variant| (vec![variant], false))
+    }
+}
+
+/// Adds a complex type and the complex types of its elements to the index
+fn index_type(
+    metadata: &'static TypeMetadata,
+    types: &mut HashMap<&'static str, &'static TypeMetadata>,
+) {
+    match metadata {
+        TypeMetadata::Complex { name, elements } => {
+            if types.insert(name, metadata).is_some() {
+                return;
+            }
+            for element in *elements {
+                index_type((element.metadata)(), types);
+            }
+        }
+        TypeMetadata::Choice { variants } => {
+            for variant in *variants {
+                index_type((variant.metadata)(), types);
+            }
+        }
+        TypeMetadata::Primitive { .. } | TypeMetadata::Resource { .. } => {}
+    }
+}
+
+/// Returns the possible types of an element's values
+fn element_types(element: &ElementMetadata) -> Vec<InferredType> {
+    match (element.metadata)() {
+        TypeMetadata::Complex { name, .. } => vec![InferredType::fhir(name)],
+        TypeMetadata::Primitive { kind, .. } => vec![InferredType::fhir(
+            element.value_type.unwrap_or(primitive_type_name(*kind)),
+        )],
+        TypeMetadata::Choice { variants } => variants
+            .iter()
+            .map(|variant| match (variant.metadata)() {
+                TypeMetadata::Complex { name, .. } => InferredType::fhir(name),
+                _ => InferredType::fhir(variant.fhir_type),
+            })
+            .collect(),
+        TypeMetadata::Resource { .. } => vec![InferredType::fhir("Resource")],
+    }
+}
+
+/// Resolves a choice element accessed by one of its variant names
+/// (`valueQuantity`) to the variant's type
+fn choice_variant(metadata: &TypeMetadata, name: &str) -> Option<InferredType> {
+    let TypeMetadata::Complex { elements, .. } = metadata else {
+        return None;
+    };
+    elements.iter().find_map(|element| {
+        let TypeMetadata::Choice { variants } = (element.metadata)() else {
+            return None;
+        };
+        variants
+            .iter()
+            .find(|variant| variant.json_name == name)
+            .map(|variant| match (variant.metadata)() {
+                TypeMetadata::Complex { name, .. } => InferredType::fhir(name),
+                _ => InferredType::fhir(variant.fhir_type),
+            })
+    })
+}
+
+/// Finds the choice element a misspelled variant name (`valueFoo`) refers to
+fn choice_element_for(metadata: &TypeMetadata, name: &str) -> Option<&'static ElementMetadata> {
+    let TypeMetadata::Complex { elements, .. } = metadata else {
+        return None;
+    };
+    elements.iter().find(|element| {
+        matches!((element.metadata)(), TypeMetadata::Choice { .. })
+            && name.len() > element.name.len()
+            && name.starts_with(element.name)
+            && name[element.name.len()..].starts_with(|c: char| c.is_ascii_uppercase())
+    })
+}
+
+/// Returns the default FHIR type name of a primitive kind
+fn primitive_type_name(kind: PrimitiveKind) -> &'static str {
+    match kind {
+        PrimitiveKind::String => "string",
+        PrimitiveKind::Boolean => "boolean",
+        PrimitiveKind::Integer => "integer",
+        PrimitiveKind::Integer64 => "integer64",
+        PrimitiveKind::Decimal => "decimal",
+    }
+}
+
+/// Classifies a FHIR primitive type name
+fn primitive_kind(name: &str) -> Option<ValueKind> {
+    if STRING_PRIMITIVES.contains(&name) {
+        Some(ValueKind::String)
+    } else if INTEGER_PRIMITIVES.contains(&name) {
+        Some(ValueKind::Integer)
+    } else if TEMPORAL_PRIMITIVES.contains(&name) {
+        Some(ValueKind::Temporal)
+    } else if name == "decimal" {
+        Some(ValueKind::Decimal)
+    } else if name == "boolean" {
+        Some(ValueKind::Boolean)
+    } else {
+        None
+    }
+}
+
+/// Classifies a type by the kind of value it holds
+fn value_kind(inferred: &InferredType) -> Option<ValueKind> {
+    if inferred.namespace == "system" {
+        return match inferred.name.as_str() {
+            "String" => Some(ValueKind::String),
+            "Integer" | "Long" => Some(ValueKind::Integer),
+            "Decimal" => Some(ValueKind::Decimal),
+            "Boolean" => Some(ValueKind::Boolean),
+            "Date" | "DateTime" | "Time" => Some(ValueKind::Temporal),
+            "Quantity" => Some(ValueKind::Quantity),
+            _ => None,
+        };
+    }
+    if QUANTITY_TYPES.contains(&inferred.name.as_str()) {
+        return Some(ValueKind::Quantity);
+    }
+    primitive_kind(&inferred.name)
+}
+
+fn strip_collection(inferred: &InferredType) -> InferredType {
+    InferredType {
+        is_collection: false,
+        ..inferred.clone()
+    }
+}
+
+/// Edit distance between two names, used to suggest element names
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The kinds of value functions and operators expect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValueKind {
+    String,
+    Integer,
+    Decimal,
+    Boolean,
+    Temporal,
+    Quantity,
+}
+
+/// What a function expects for its input or one of its arguments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Param {
+    /// Any value
+    Any,
+    /// An expression evaluated once per input item, with `$this` set to the item
+    Lambda,
+    /// A type name, as in `ofType(Patient)`
+    Type,
+    /// A String
+    String,
+    /// An Integer
+    Integer,
+    /// An Integer or Decimal
+    Number,
+    /// An Integer, Decimal or Quantity
+    NumberOrQuantity,
+    /// A Boolean
+    Boolean,
+    /// A Date, DateTime or Time
+    Temporal,
+}
+
+impl Param {
+    fn accepts(self, kind: Option<ValueKind>) -> bool {
+        match self {
+            Param::Any | Param::Lambda | Param::Type => true,
+            Param::String => kind == Some(ValueKind::String),
+            Param::Integer => kind == Some(ValueKind::Integer),
+            Param::Number => matches!(kind, Some(ValueKind::Integer | ValueKind::Decimal)),
+            Param::NumberOrQuantity => matches!(
+                kind,
+                Some(ValueKind::Integer | ValueKind::Decimal | ValueKind::Quantity)
+            ),
+            Param::Boolean => kind == Some(ValueKind::Boolean),
+            Param::Temporal => kind == Some(ValueKind::Temporal),
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Param::Any | Param::Lambda | Param::Type => "any value",
+            Param::String => "String",
+            Param::Integer => "Integer",
+            Param::Number => "Integer or Decimal",
+            Param::NumberOrQuantity => "Integer, Decimal or Quantity",
+            Param::Boolean => "Boolean",
+            Param::Temporal => "Date, DateTime or Time",
+        }
+    }
+}
+
+/// The arity and parameter types of a function
+struct Signature {
+    min_args: usize,
+    max_args: usize,
+    input: Param,
+    /// Parameter types; the last one repeats for variadic functions
+    params: &'static [Param],
+}
+
+const fn signature(
+    min_args: usize,
+    max_args: usize,
+    input: Param,
+    params: &'static [Param],
+) -> Signature {
+    Signature {
+        min_args,
+        max_args,
+        input,
+        params,
+    }
+}
+
+/// Returns true if `name` is a built-in function
+pub(crate) fn is_builtin_function(name: &str) -> bool {
+    function_signature(name).is_some()
+}
+
+/// Returns the signature of a built-in function
+fn function_signature(name: &str) -> Option<Signature> {
+    use Param::*;
+
+    let signature = match name {
+        "empty" | "count" | "distinct" | "isDistinct" | "first" | "last" | "tail" | "single"
+        | "children" | "descendants" | "type" | "hasValue" | "getValue" | "htmlChecks"
+        | "elementDefinition" | "resolve" | "getResourceKey" | "toString" | "toInteger"
+        | "toLong" | "toDecimal" | "toBoolean" | "toDate" | "toDateTime" | "toTime"
+        | "convertsToString" | "convertsToInteger" | "convertsToLong" | "convertsToDecimal"
+        | "convertsToBoolean" | "convertsToDate" | "convertsToDateTime" | "convertsToTime"
+        | "now" | "today" | "timeOfDay" | "not" | "precision" | "sum" | "min" | "max" | "avg" => {
+            signature(0, 0, Any, &[])
+        }
+        "exists" => signature(0, 1, Any, &[Lambda]),
+        "where" | "select" | "all" | "repeat" => signature(1, 1, Any, &[Lambda]),
+        "aggregate" => signature(1, 2, Any, &[Lambda, Any]),
+        "sort" => signature(0, usize::MAX, Any, &[Lambda]),
+        "iif" => signature(2, 3, Any, &[Boolean, Any, Any]),
+        "allTrue" | "anyTrue" | "allFalse" | "anyFalse" => signature(0, 0, Boolean, &[]),
+        "subsetOf" | "supersetOf" | "intersect" | "exclude" | "union" | "combine" => {
+            signature(1, 1, Any, &[Any])
+        }
+        "skip" | "take" => signature(1, 1, Any, &[Integer]),
+        "ofType" | "is" | "as" => signature(1, 1, Any, &[Type]),
+        "extension" => signature(1, 1, Any, &[String]),
+        "trace" => signature(1, 2, Any, &[String, Lambda]),
+        "defineVariable" => signature(1, 2, Any, &[String, Lambda]),
+        "toQuantity" | "convertsToQuantity" => signature(0, 1, Any, &[String]),
+        "indexOf" | "lastIndexOf" | "startsWith" | "endsWith" | "contains" | "matchesFull"
+        | "split" | "encode" | "decode" | "escape" | "unescape" => {
+            signature(1, 1, String, &[String])
+        }
+        "matches" => signature(1, 2, String, &[String, String]),
+        "substring" => signature(1, 2, String, &[Integer, Integer]),
+        "replace" | "replaceMatches" => signature(2, 2, String, &[String, String]),
+        "upper" | "lower" | "trim" | "length" | "toChars" => signature(0, 0, String, &[]),
+        "join" => signature(0, 1, String, &[String]),
+        "abs" | "ceiling" | "floor" | "truncate" | "sqrt" | "exp" | "ln" => {
+            signature(0, 0, NumberOrQuantity, &[])
+        }
+        "round" => signature(0, 1, NumberOrQuantity, &[Integer]),
+        "log" | "power" => signature(1, 1, Number, &[Number]),
+        "lowBoundary" | "highBoundary" => signature(0, 1, Any, &[Integer]),
+        "comparable" => signature(1, 1, NumberOrQuantity, &[Any]),
+        "yearOf" | "monthOf" | "dayOf" | "hourOf" | "minuteOf" | "secondOf" | "millisecondOf"
+        | "timezoneOffsetOf" | "dateOf" | "timeOf" => signature(0, 0, Temporal, &[]),
+        "duration" | "difference" => signature(2, 2, Temporal, &[Temporal, String]),
+        "getReferenceKey" => signature(0, 1, Any, &[Type]),
+        "memberOf" | "conformsTo" => signature(1, 1, Any, &[String]),
+        "subsumes" | "subsumedBy" => signature(1, 1, Any, &[Any]),
+        "slice" => signature(2, 2, Any, &[String, String]),
+        "checkModifiers" => signature(1, 1, Any, &[Any]),
+        _ => return None,
+    };
+    Some(signature)
+}
+
+/// The static type of an expression: the possible types of its items and
+/// whether it can hold more than one item
+#[derive(Debug, Clone, PartialEq)]
+struct StaticType {
+    /// Possible item types, none of them flagged as collections; empty when unknown
+    types: Vec<InferredType>,
+    is_collection: bool,
+}
+
+impl StaticType {
+    fn unknown() -> Self {
+        Self {
+            types: Vec::new(),
+            is_collection: false,
+        }
+    }
+
+    fn single(inferred: InferredType) -> Self {
+        Self {
+            types: vec![inferred],
+            is_collection: false,
+        }
+    }
+
+    fn system(name: &str) -> Self {
+        Self::single(InferredType::system(name))
+    }
+
+    fn is_unknown(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    fn with_collection(mut self, is_collection: bool) -> Self {
+        self.is_collection = is_collection;
+        self
+    }
+
+    /// The type of one item of this collection
+    fn item(&self) -> Self {
+        self.clone().with_collection(false)
+    }
+
+    /// Combines the possible types of two expressions
+    fn union(&self, other: &StaticType) -> Self {
+        if self.is_unknown() || other.is_unknown() {
+            return Self::unknown().with_collection(true);
+        }
+        let mut types = self.types.clone();
+        for inferred in &other.types {
+            if !types.contains(inferred) {
+                types.push(inferred.clone());
+            }
+        }
+        Self {
+            types,
+            is_collection: true,
+        }
+    }
+
+    fn display(&self) -> String {
+        self.types
+            .iter()
+            .map(InferredType::to_display_string)
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+
+    fn to_inferred_types(&self) -> Vec<InferredType> {
+        self.types
+            .iter()
+            .map(|inferred| InferredType {
+                is_collection: self.is_collection,
+                ..inferred.clone()
+            })
+            .collect()
+    }
+}
+
+/// Walks an expression, tracking the focus type and collecting diagnostics
+struct Walker<'a> {
+    model: &'a Model,
+    source: &'a str,
+    function_registry: Option<&'a dyn FunctionRegistry>,
+    variables: HashMap<String, StaticType>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Walker<'_> {
+    fn report(&mut self, severity: Severity, message: String, position: Option<usize>) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            message,
+            position,
+        });
+    }
+
+    /// Finds the type name of an `is` or `as` type specifier, which follows
+    /// the operator after the operand and ends the expression
+    fn type_specifier_position(
+        &self,
+        operand: &Expression,
+        span: &Span,
+        type_name: &str,
+    ) -> Option<usize> {
+        let start = operand.span().end;
+        let offset = self.source.get(start..span.end)?.rfind(type_name)?;
+        Some(start + offset)
+    }
+
+    /// Finds the operator of a binary expression, which follows its left operand
+    fn operator_position(&self, left: &Expression, op: &str) -> Option<usize> {
+        let end = left.span().end;
+        let offset = self.source.get(end..)?.find(op)?;
+        Some(end + offset)
+    }
+
+    fn expression(&mut self, expression: &Expression, focus: &StaticType) -> StaticType {
+        match expression {
+            Expression::Term(term, _) => self.term(term, focus),
+            Expression::Invocation(base, invocation, _) => {
+                let input = self.expression(base, focus);
+                self.invocation(invocation, &input, focus, false)
+            }
+            Expression::Indexer(base, index, _) => {
+                let input = self.expression(base, focus);
+                let index_type = self.expression(index, focus);
+                self.expect_kind(
+                    &index_type,
+                    Param::Integer,
+                    Some(index.span().start),
+                    || "The indexer".to_string(),
+                );
+                input.item()
+            }
+            Expression::Polarity(op, operand, span) => {
+                let operand = self.expression(operand, focus);
+                self.expect_kind(&operand, Param::NumberOrQuantity, Some(span.start), || {
+                    format!("The operand of '{}'", op)
+                });
+                operand.item()
+            }
+            Expression::Multiplicative(left, op, right, _)
+            | Expression::Additive(left, op, right, _) => {
+                let position = self.operator_position(left, op);
+                let left = self.expression(left, focus);
+                let right = self.expression(right, focus);
+                self.arithmetic(op, &left, &right, position)
+            }
+            Expression::Inequality(left, op, right, _)
+            | Expression::Equality(left, op, right, _) => {
+                let position = self.operator_position(left, op);
+                let left = self.expression(left, focus);
+                let right = self.expression(right, focus);
+                self.comparison(expression, op, &left, &right, position);
+                StaticType::system("Boolean")
+            }
+            Expression::Membership(left, _, right, _) | Expression::Or(left, _, right, _) => {
+                self.expression(left, focus);
+                self.expression(right, focus);
+                StaticType::system("Boolean")
+            }
+            Expression::And(left, right, _) | Expression::Implies(left, right, _) => {
+                self.expression(left, focus);
+                self.expression(right, focus);
+                StaticType::system("Boolean")
+            }
+            Expression::Type(operand, op, type_specifier, span) => {
+                let input = self.expression(operand, focus);
+                let TypeSpecifier::QualifiedIdentifier(namespace, name) = type_specifier;
+                let type_name = name.as_deref().unwrap_or(namespace);
+                let position = self.type_specifier_position(operand, span, type_name);
+                let target = self.resolve_type(namespace, name.as_deref(), position);
+                if op == "as" {
+                    target
+                        .map(StaticType::single)
+                        .unwrap_or_else(StaticType::unknown)
+                        .with_collection(input.is_collection)
+                } else {
+                    StaticType::system("Boolean")
+                }
+            }
+            Expression::Union(left, right, _) => {
+                let left = self.expression(left, focus);
+                let right = self.expression(right, focus);
+                left.union(&right)
+            }
+            Expression::Lambda(_, body, _) => self.expression(body, focus),
+        }
+    }
+
+    fn term(&mut self, term: &Term, focus: &StaticType) -> StaticType {
+        match term {
+            Term::Literal(literal, _) => literal_type(literal),
+            Term::Invocation(invocation, _) => self.invocation(invocation, focus, focus, true),
+            Term::ExternalConstant(name, span) => {
+                if let Some(variable) = self.variables.get(name) {
+                    return variable.clone();
+                }
+                if STRING_VARIABLES.contains(&name.as_str())
+                    || name.starts_with("vs-")
+                    || name.starts_with("ext-")
+                {
+                    return StaticType::system("String");
+                }
+                if name == "terminologies" || name == "server" || name == "factory" {
+                    return StaticType::unknown();
+                }
+                self.report(
+                    Severity::Error,
+                    format!("Unknown variable '%{}'", name),
+                    Some(span.start),
+                );
+                StaticType::unknown()
+            }
+            Term::Parenthesized(expression, _) => self.expression(expression, focus),
+        }
+    }
+
+    /// Checks an invocation on `input`; `this` is the focus of the enclosing
+    /// expression, against which non-lambda arguments are evaluated
+    fn invocation(
+        &mut self,
+        invocation: &Invocation,
+        input: &StaticType,
+        this: &StaticType,
+        is_term: bool,
+    ) -> StaticType {
+        match invocation {
+            Invocation::Member(name, span) => self.member(name, span.start, input, is_term),
+            Invocation::Function(name, args, span) => {
+                self.function(name, span.start, args, input, this)
+            }
+            Invocation::This(_) => this.clone(),
+            Invocation::Index(_) => StaticType::system("Integer"),
+            Invocation::Total(_) => StaticType::unknown(),
+        }
+    }
+
+    /// Checks the member `name`, written at byte offset `start`, of `input`
+    fn member(
+        &mut self,
+        name: &str,
+        start: usize,
+        input: &StaticType,
+        is_term: bool,
+    ) -> StaticType {
+        let position = Some(start);
+
+        // A path may start with the type of its focus (`Patient.name`)
+        if is_term {
+            if input.types.iter().any(|inferred| inferred.name == name) {
+                return input.clone();
+            }
+            if input.is_unknown() && self.model.is_resource_type(name) {
+                return StaticType::single(InferredType::fhir(name));
+            }
+        }
+        if input.is_unknown() {
+            return StaticType::unknown().with_collection(input.is_collection);
+        }
+
+        let mut result = StaticType::unknown().with_collection(input.is_collection);
+        let mut found = false;
+        for inferred in &input.types {
+            let Some((types, is_array)) = self.model.member(inferred, name) else {
+                continue;
+            };
+            if types.is_empty() {
+                // The input includes a type the model does not describe
+                return StaticType::unknown().with_collection(true);
+            }
+            found = true;
+            result.is_collection |= is_array;
+            for member_type in types {
+                if !result.types.contains(&member_type) {
+                    result.types.push(member_type);
+                }
+            }
+        }
+        if found {
+            return result;
+        }
+
+        if is_term && self.model.is_resource_type(name) {
+            self.report(
+                Severity::Error,
+                format!(
+                    "'{}' does not match the context type {}, so the expression is always empty",
+                    name,
+                    input.display()
+                ),
+                position,
+            );
+            return StaticType::unknown();
+        }
+        let message = self.unknown_member_message(name, input);
+        self.report(Severity::Error, message, position);
+        StaticType::unknown()
+    }
+
+    fn unknown_member_message(&self, name: &str, input: &StaticType) -> String {
+        for inferred in &input.types {
+            let Some(metadata) = self.model.types.get(inferred.name.as_str()) else {
+                continue;
+            };
+            if let Some(element) = choice_element_for(metadata, name) {
+                let TypeMetadata::Choice { variants } = (element.metadata)() else {
+                    continue;
+                };
+                let allowed: Vec<&str> = variants.iter().map(|variant| variant.json_name).collect();
+                return format!(
+                    "'{}' is not a valid type for the choice element {}.{}[x]; expected one of: {}",
+                    name,
+                    inferred.name,
+                    element.name,
+                    allowed.join(", ")
+                );
+            }
+        }
+
+        let message = format!("Unknown element '{}' on {}", name, input.display());
+        let suggestion = input
+            .types
+            .iter()
+            .filter_map(|inferred| self.model.types.get(inferred.name.as_str()))
+            .filter_map(|metadata| match metadata {
+                TypeMetadata::Complex { elements, .. } => Some(elements.iter()),
+                _ => None,
+            })
+            .flatten()
+            .map(|element| (edit_distance(name, element.name), element.name))
+            .filter(|(distance, _)| *distance <= 2)
+            .min();
+        match suggestion {
+            Some((_, element_name)) => format!("{}; did you mean '{}'?", message, element_name),
+            None => message,
+        }
+    }
+
+    /// Checks a call of the function `name`, written at byte offset `start`
+    fn function(
+        &mut self,
+        name: &str,
+        start: usize,
+        args: &[Expression],
+        input: &StaticType,
+        this: &StaticType,
+    ) -> StaticType {
+        let position = Some(start);
+        let Some(signature) = function_signature(name) else {
+            let custom = self
+                .function_registry
+                .and_then(|registry| registry.function(name));
+            if let Some(function) = custom {
+                return self.custom_function(function, args, input, this, position);
+            }
+            for arg in args {
+                self.expression(arg, &StaticType::unknown());
+            }
+            self.report(
+                Severity::Error,
+                format!("Unknown function '{}'", name),
+                position,
+            );
+            return StaticType::unknown();
+        };
+
+        self.check_arity(name, signature.min_args, signature.max_args, args, position);
+
+        self.expect_kind(input, signature.input, position, || {
+            format!("The input of '{}'", name)
+        });
+
+        let item = input.item();
+        let mut arg_types = Vec::with_capacity(args.len());
+        for (index, arg) in args.iter().enumerate() {
+            let param = signature
+                .params
+                .get(index)
+                .or(signature.params.last())
+                .copied()
+                .unwrap_or(Param::Any);
+            let arg_type = match param {
+                Param::Type => {
+                    let target = match type_name_of(arg) {
+                        Some((namespace, type_name, span)) => {
+                            self.resolve_type(namespace, type_name.as_deref(), Some(span.start))
+                        }
+                        None => {
+                            self.expression(arg, this);
+                            None
+                        }
+                    };
+                    target
+                        .map(StaticType::single)
+                        .unwrap_or_else(StaticType::unknown)
+                }
+                Param::Lambda => self.expression(arg, &item),
+                _ => {
+                    let arg_type = self.expression(arg, this);
+                    self.expect_kind(&arg_type, param, Some(arg.span().start), || {
+                        format!("Argument {} of '{}'", index + 1, name)
+                    });
+                    arg_type
+                }
+            };
+            arg_types.push(arg_type);
+        }
+
+        if let (
+            "defineVariable",
+            Some(Expression::Term(Term::Literal(Literal::String(variable), _), _)),
+        ) = (name, args.first())
+        {
+            let value = arg_types.get(1).cloned().unwrap_or_else(|| input.clone());
+            self.variables.insert(variable.clone(), value);
+        }
+
+        function_return_type(name, input, &arg_types)
+    }
+
+    fn check_arity(
+        &mut self,
+        name: &str,
+        min_args: usize,
+        max_args: usize,
+        args: &[Expression],
+        position: Option<usize>,
+    ) {
+        if (min_args..=max_args).contains(&args.len()) {
+            return;
+        }
+        let expected = if min_args == max_args {
+            min_args.to_string()
+        } else if max_args == usize::MAX {
+            format!("at least {}", min_args)
+        } else {
+            format!("{} to {}", min_args, max_args)
+        };
+        self.report(
+            Severity::Error,
+            format!(
+                "Function '{}' expects {} argument{}, but {} {} given",
+                name,
+                expected,
+                if expected == "1" { "" } else { "s" },
+                args.len(),
+                if args.len() == 1 { "was" } else { "were" }
+            ),
+            position,
+        );
+    }
+
+    /// Checks a call of a function from the function registry
+    fn custom_function(
+        &mut self,
+        function: &CustomFunction,
+        args: &[Expression],
+        input: &StaticType,
+        this: &StaticType,
+        position: Option<usize>,
+    ) -> StaticType {
+        let name = function.name();
+        self.check_arity(
+            name,
+            function.min_args(),
+            function.max_args(),
+            args,
+            position,
+        );
+        let per_item = function.argument_evaluation() == ArgumentEvaluation::PerItem;
+        let arg_focus = if per_item { input.item() } else { this.clone() };
+        for arg in args {
+            self.expression(arg, &arg_focus);
+        }
+        match function.return_type() {
+            Some(return_type) => StaticType::single(strip_collection(return_type))
+                .with_collection(return_type.is_collection || (per_item && input.is_collection)),
+            None => StaticType::unknown(),
+        }
+    }
+
+    /// Reports an error if none of the known types of `actual` is accepted by
+    /// `param`; unknown types are not reported
+    fn expect_kind(
+        &mut self,
+        actual: &StaticType,
+        param: Param,
+        position: Option<usize>,
+        subject: impl FnOnce() -> String,
+    ) {
+        if actual.is_unknown()
+            || actual.types.iter().any(|inferred| {
+                !self.model.is_known(inferred) || param.accepts(value_kind(inferred))
+            })
+        {
+            return;
+        }
+        let message = format!(
+            "{} must be {}, but is {}",
+            subject(),
+            param.description(),
+            actual.display()
+        );
+        self.report(Severity::Error, message, position);
+    }
+
+    /// Reports comparisons between values that can never compare: an error for
+    /// ordering operators, a warning for (in)equality, which is always false
+    fn comparison(
+        &mut self,
+        expression: &Expression,
+        op: &str,
+        left: &StaticType,
+        right: &StaticType,
+        position: Option<usize>,
+    ) {
+        let kinds = |operand: &StaticType| -> Option<Vec<ValueKind>> {
+            operand.types.iter().map(value_kind).collect()
+        };
+        let (Some(left_kinds), Some(right_kinds)) = (kinds(left), kinds(right)) else {
+            return;
+        };
+        if left_kinds.is_empty() || right_kinds.is_empty() {
+            return;
+        }
+        let comparable = |a: ValueKind, b: ValueKind| {
+            use ValueKind::*;
+            a == b
+                || matches!(
+                    (a, b),
+                    (Integer, Decimal)
+                        | (Decimal, Integer)
+                        | (String, Temporal)
+                        | (Temporal, String)
+                )
+        };
+        if left_kinds
+            .iter()
+            .any(|&a| right_kinds.iter().any(|&b| comparable(a, b)))
+        {
+            return;
+        }
+        let severity = if matches!(expression, Expression::Inequality(..)) {
+            Severity::Error
+        } else {
+            Severity::Warning
+        };
+        self.report(
+            severity,
+            format!(
+                "Cannot compare {} with {} using '{}'",
+                left.display(),
+                right.display(),
+                op
+            ),
+            position,
+        );
+    }
+
+    fn arithmetic(
+        &mut self,
+        op: &str,
+        left: &StaticType,
+        right: &StaticType,
+        position: Option<usize>,
+    ) -> StaticType {
+        if op == "&" {
+            return StaticType::system("String");
+        }
+        if left.is_unknown() || right.is_unknown() {
+            return StaticType::unknown();
+        }
+        let kinds = |operand: &StaticType| -> Vec<Option<ValueKind>> {
+            operand.types.iter().map(value_kind).collect()
+        };
+        let (left_kinds, right_kinds) = (kinds(left), kinds(right));
+        let known = |operand: &StaticType| {
+            operand
+                .types
+                .iter()
+                .all(|inferred| self.model.is_known(inferred))
+        };
+        let applies = |a: Option<ValueKind>, b: Option<ValueKind>| {
+            use ValueKind::*;
+            let (Some(a), Some(b)) = (a, b) else {
+                return false;
+            };
+            let number = |kind| matches!(kind, Integer | Decimal);
+            match op {
+                "+" | "-" => {
+                    (op == "+" && a == String && b == String)
+                        || (a == Temporal && b == Quantity)
+                        || (number(a) && number(b))
+                        || (a == Quantity && b == Quantity)
+                }
+                _ => (number(a) || a == Quantity) && (number(b) || b == Quantity),
+            }
+        };
+        if known(left)
+            && known(right)
+            && !left_kinds
+                .iter()
+                .any(|&a| right_kinds.iter().any(|&b| applies(a, b)))
+        {
+            self.report(
+                Severity::Error,
+                format!(
+                    "Operator '{}' cannot be applied to {} and {}",
+                    op,
+                    left.display(),
+                    right.display()
+                ),
+                position,
+            );
+            return StaticType::unknown();
+        }
+
+        let has = |kinds: &[Option<ValueKind>], kind: ValueKind| kinds.contains(&Some(kind));
+        let either = |kind| has(&left_kinds, kind) || has(&right_kinds, kind);
+        if has(&left_kinds, ValueKind::Temporal) {
+            return left.item();
+        }
+        if op == "+" && has(&left_kinds, ValueKind::String) {
+            return StaticType::system("String");
+        }
+        match op {
+            "div" | "mod" if !either(ValueKind::Decimal) && !either(ValueKind::Quantity) => {
+                StaticType::system("Integer")
+            }
+            _ if either(ValueKind::Quantity) => StaticType::system("Quantity"),
+            "/" => StaticType::system("Decimal"),
+            _ if either(ValueKind::Decimal) => StaticType::system("Decimal"),
+            _ => StaticType::system("Integer"),
+        }
+    }
+
+    /// Resolves a type name as written in `is`, `as` and `ofType()`, with the
+    /// type name at `position`
+    fn resolve_type(
+        &mut self,
+        namespace_or_name: &str,
+        name: Option<&str>,
+        position: Option<usize>,
+    ) -> Option<InferredType> {
+        let (namespace, type_name) = match name {
+            Some(type_name) => (Some(namespace_or_name), type_name),
+            None => (None, namespace_or_name),
+        };
+        let is_fhir_type = self.model.types.contains_key(type_name)
+            || primitive_kind(type_name).is_some()
+            || ABSTRACT_TYPES.contains(&type_name);
+        let is_system_type = SYSTEM_TYPES.contains(&type_name);
+
+        let resolved = match namespace {
+            Some("FHIR") if is_fhir_type => Some(InferredType::fhir(type_name)),
+            Some("System") if is_system_type => Some(InferredType::system(type_name)),
+            None if is_fhir_type => Some(InferredType::fhir(type_name)),
+            None if is_system_type => Some(InferredType::system(type_name)),
+            _ => None,
+        };
+        if resolved.is_none() {
+            // A qualified name that does not exist is valid and simply never
+            // matches (`is(System.Patient)` is false); an unqualified one is most
+            // likely misspelled
+            match namespace {
+                Some(namespace) => self.report(
+                    Severity::Warning,
+                    format!(
+                        "Unknown type '{}.{}', so the type never matches",
+                        namespace, type_name
+                    ),
+                    position,
+                ),
+                None => self.report(
+                    Severity::Error,
+                    format!("Unknown type '{}'", type_name),
+                    position,
+                ),
+            }
+        }
+        resolved
+    }
+}
+
+fn literal_type(literal: &Literal) -> StaticType {
+    match literal {
+        Literal::Null => StaticType::unknown(),
+        Literal::Boolean(_) => StaticType::system("Boolean"),
+        Literal::String(_) => StaticType::system("String"),
+        Literal::Integer(_) => StaticType::system("Integer"),
+        Literal::Number(_) => StaticType::system("Decimal"),
+        Literal::Date(_) => StaticType::system("Date"),
+        Literal::DateTime(_, _) => StaticType::system("DateTime"),
+        Literal::Time(_) => StaticType::system("Time"),
+        Literal::Quantity(_, _) => StaticType::system("Quantity"),
+    }
+}
+
+/// Reads a type name passed as a function argument (`Patient`, `FHIR.Patient`),
+/// with the span of the type name
+fn type_name_of(arg: &Expression) -> Option<(&str, Option<String>, Span)> {
+    match arg {
+        Expression::Term(Term::Invocation(Invocation::Member(name, span), _), _) => {
+            Some((name, None, span.clone()))
+        }
+        Expression::Invocation(base, Invocation::Member(name, span), _) => match base.as_ref() {
+            Expression::Term(Term::Invocation(Invocation::Member(namespace, _), _), _) => {
+                Some((namespace, Some(name.clone()), span.clone()))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Computes the result type of a built-in function
+///
+/// The result types come from the table shared with type inference
+/// (`builtin_return_type`); the argument types refine the functions whose
+/// result depends on them.
+fn function_return_type(name: &str, input: &StaticType, args: &[StaticType]) -> StaticType {
+    let arg = |index: usize| args.get(index).cloned().unwrap_or_else(StaticType::unknown);
+    let Some(return_type) = builtin_return_type(name) else {
+        return StaticType::unknown().with_collection(true);
+    };
+    match return_type {
+        BuiltinReturnType::Fixed(inferred) => {
+            StaticType::single(strip_collection(&inferred)).with_collection(inferred.is_collection)
+        }
+        BuiltinReturnType::PerItem(inferred) => {
+            StaticType::single(inferred).with_collection(input.is_collection)
+        }
+        BuiltinReturnType::Input => input.clone(),
+        BuiltinReturnType::InputItem => input.item(),
+        BuiltinReturnType::InputCollection => input.union(&arg(0)),
+        BuiltinReturnType::QuantityOrDecimal => {
+            if input
+                .types
+                .iter()
+                .any(|inferred| value_kind(inferred) == Some(ValueKind::Quantity))
+            {
+                input.item()
+            } else {
+                StaticType::system("Decimal")
+            }
+        }
+        BuiltinReturnType::PrimitiveValue => StaticType {
+            types: input
+                .types
+                .iter()
+                .filter_map(primitive_value_type)
+                .collect(),
+            is_collection: input.is_collection,
+        },
+        BuiltinReturnType::Arguments => match name {
+            "select" => {
+                let selected = arg(0);
+                selected.with_collection(
+                    input.is_collection || args.first().is_some_and(|arg| arg.is_collection),
+                )
+            }
+            "repeat" => arg(0).with_collection(true),
+            "ofType" | "as" => arg(0).with_collection(input.is_collection),
+            "iif" => {
+                if args.len() > 2 {
+                    let (then, otherwise) = (arg(1), arg(2));
+                    let is_collection = then.is_collection || otherwise.is_collection;
+                    then.union(&otherwise).with_collection(is_collection)
+                } else {
+                    arg(1)
+                }
+            }
+            _ => StaticType::unknown().with_collection(true),
+        },
+    }
+}
+
+/// The System type of the primitive value held by an item of type `inferred`
+pub(crate) fn primitive_value_type(inferred: &InferredType) -> Option<InferredType> {
+    let name = match value_kind(inferred)? {
+        ValueKind::String => "String",
+        ValueKind::Integer => "Integer",
+        ValueKind::Decimal => "Decimal",
+        ValueKind::Boolean => "Boolean",
+        ValueKind::Temporal => "DateTime",
+        ValueKind::Quantity => return None,
+    };
+    Some(InferredType::system(name))
+}
@@ -46,10 +46,7 @@ fn create_type_object(value: &EvaluationResult) -> EvaluationResult {
 
     EvaluationResult::Object {
         map,
-        type_info: Some(TypeInfoResult {
-            namespace: "System".to_string(),
-            name: "Type".to_string(),
-        }),
+        type_info: Some(TypeInfoResult::new("System", "Type")),
     }
 }
 
@@ -3,8 +3,11 @@
 //! This module provides static type inference for FHIRPath expressions,
 //! determining the return type of expressions without evaluating them.
 
+use crate::function_registry::FunctionRegistry;
 use crate::parser::{Expression, Invocation, Literal, Term, TypeSpecifier};
+use helios_fhir::FhirVersion;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 /// Represents a type in the FHIRPath type system
 #[derive(Debug, Clone, PartialEq)]
@@ -71,6 +74,11 @@ pub struct TypeContext {
     pub current_type: Option<InferredType>,
     /// Variables and their types
     pub variables: HashMap<String, InferredType>,
+    /// The FHIR version whose model member types are looked up in, if any
+    pub fhir_version: Option<FhirVersion>,
+    /// Custom functions, whose declared return types are used for calls of
+    /// functions that are not built-in
+    pub function_registry: Option<Arc<dyn FunctionRegistry>>,
 }
 
 impl TypeContext {
@@ -83,19 +91,31 @@ impl TypeContext {
         self.root_type = Some(root_type);
         self
     }
+
+    /// Infers member types from the FHIR model of `fhir_version`
+    pub fn with_fhir_version(mut self, fhir_version: FhirVersion) -> Self {
+        self.fhir_version = Some(fhir_version);
+        self
+    }
+
+    /// Infers the return types of the functions in `registry`
+    pub fn with_function_registry(mut self, registry: Arc<dyn FunctionRegistry>) -> Self {
+        self.function_registry = Some(registry);
+        self
+    }
 }
 
 /// Infer the return type of a FHIRPath expression
 pub fn infer_expression_type(expr: &Expression, context: &TypeContext) -> Option<InferredType> {
     match expr {
-        Expression::Term(term) => infer_term_type(term, context),
+        Expression::Term(term, _) => infer_term_type(term, context),
 
-        Expression::Invocation(base_expr, invocation) => {
+        Expression::Invocation(base_expr, invocation, _) => {
             let base_type = infer_expression_type(base_expr, context)?;
             infer_invocation_type(invocation, &base_type, context)
         }
 
-        Expression::Indexer(expr, _index) => {
+        Expression::Indexer(expr, _index, _) => {
             let base_type = infer_expression_type(expr, context)?;
             // Indexing a collection returns the element type
             if base_type.is_collection {
@@ -109,7 +129,7 @@ pub fn infer_expression_type(expr: &Expression, context: &TypeContext) -> Option
             }
         }
 
-        Expression::Polarity(op, expr) => {
+        Expression::Polarity(op, expr, _) => {
             let _inner_type = infer_expression_type(expr, context)?;
             match op {
                 '+' | '-' => Some(InferredType::system("Integer")),
@@ -117,7 +137,8 @@ pub fn infer_expression_type(expr: &Expression, context: &TypeContext) -> Option
             }
         }
 
-        Expression::Multiplicative(left, op, right) | Expression::Additive(left, op, right) => {
+        Expression::Multiplicative(left, op, right, _)
+        | Expression::Additive(left, op, right, _) => {
             let left_type = infer_expression_type(left, context)?;
             let _right_type = infer_expression_type(right, context)?;
 
@@ -128,16 +149,15 @@ pub fn infer_expression_type(expr: &Expression, context: &TypeContext) -> Option
             }
         }
 
-        Expression::Inequality(_left, _op, _right) | Expression::Equality(_left, _op, _right) => {
-            Some(InferredType::system("Boolean"))
-        }
+        Expression::Inequality(_left, _op, _right, _)
+        | Expression::Equality(_left, _op, _right, _) => Some(InferredType::system("Boolean")),
 
-        Expression::Membership(expr, op, _type_or_expr) => match op.as_str() {
+        Expression::Membership(expr, op, _type_or_expr, _) => match op.as_str() {
             "in" | "contains" => Some(InferredType::system("Boolean")),
             _ => infer_expression_type(expr, context),
         },
 
-        Expression::Type(expr, op, type_spec) => {
+        Expression::Type(expr, op, type_spec, _) => {
             match op.as_str() {
                 "is" => Some(InferredType::system("Boolean")),
                 "as" => {
@@ -156,7 +176,7 @@ pub fn infer_expression_type(expr: &Expression, context: &TypeContext) -> Option
             }
         }
 
-        Expression::Union(left, right) => {
+        Expression::Union(left, right, _) => {
             let left_type = infer_expression_type(left, context);
             let right_type = infer_expression_type(right, context);
 
@@ -169,13 +189,13 @@ pub fn infer_expression_type(expr: &Expression, context: &TypeContext) -> Option
             }
         }
 
-        Expression::And(_left, _right) | Expression::Implies(_left, _right) => {
+        Expression::And(_left, _right, _) | Expression::Implies(_left, _right, _) => {
             Some(InferredType::system("Boolean"))
         }
 
-        Expression::Or(_left, _op, _right) => Some(InferredType::system("Boolean")),
+        Expression::Or(_left, _op, _right, _) => Some(InferredType::system("Boolean")),
 
-        Expression::Lambda(_param, expr) => {
+        Expression::Lambda(_param, expr, _) => {
             // Lambda returns the type of its body expression
             infer_expression_type(expr, context)
         }
@@ -184,15 +204,15 @@ pub fn infer_expression_type(expr: &Expression, context: &TypeContext) -> Option
 
 fn infer_term_type(term: &Term, context: &TypeContext) -> Option<InferredType> {
     match term {
-        Term::Literal(lit) => infer_literal_type(lit),
-        Term::Invocation(inv) => {
+        Term::Literal(lit, _) => infer_literal_type(lit),
+        Term::Invocation(inv, _) => {
             infer_invocation_type(inv, &context.current_type.clone()?, context)
         }
-        Term::ExternalConstant(name) => {
+        Term::ExternalConstant(name, _) => {
             // Look up variable type
             context.variables.get(name).cloned()
         }
-        Term::Parenthesized(expr) => infer_expression_type(expr, context),
+        Term::Parenthesized(expr, _) => infer_expression_type(expr, context),
     }
 }
 
@@ -213,19 +233,32 @@ fn infer_literal_type(literal: &Literal) -> Option<InferredType> {
 fn infer_invocation_type(
     invocation: &Invocation,
     input_type: &InferredType,
-    _context: &TypeContext,
+    context: &TypeContext,
 ) -> Option<InferredType> {
     match invocation {
-        Invocation::Function(name, args) => {
+        Invocation::Function(name, args, _) => {
+            let custom_function = context
+                .function_registry
+                .as_ref()
+                .filter(|_| !crate::type_checker::is_builtin_function(name))
+                .and_then(|registry| registry.function(name));
+            if let Some(function) = custom_function {
+                return function.return_type().cloned();
+            }
             infer_function_return_type(name, input_type, args.len())
         }
-        Invocation::Member(name) => {
+        Invocation::Member(name, _) => {
             // Member access depends on the input type
-            infer_member_type(name, input_type)
+            match context.fhir_version {
+                Some(fhir_version) => {
+                    crate::type_checker::member_type(fhir_version, input_type, name)
+                }
+                None => infer_member_type(name, input_type),
+            }
         }
-        Invocation::This => Some(input_type.clone()),
-        Invocation::Index => Some(InferredType::system("Integer")),
-        Invocation::Total => Some(InferredType::system("Integer")),
+        Invocation::This(_) => Some(input_type.clone()),
+        Invocation::Index(_) => Some(InferredType::system("Integer")),
+        Invocation::Total(_) => Some(InferredType::system("Integer")),
     }
 }
 
@@ -258,81 +291,112 @@ fn infer_function_return_type(
     input_type: &InferredType,
     _arg_count: usize,
 ) -> Option<InferredType> {
-    // Map of FHIRPath functions to their return types
-    match function_name {
-        // String functions
-        "toString" => Some(InferredType::system("String")),
-        "toChars" => Some(InferredType::system("String").collection()),
-        "substring" => Some(InferredType::system("String")),
-        "startsWith" | "endsWith" | "contains" | "matches" => Some(InferredType::system("Boolean")),
-        "replace" | "replaceMatches" | "trim" | "upper" | "lower" => {
-            Some(InferredType::system("String"))
-        }
-        "split" => Some(InferredType::system("String").collection()),
-        "join" => Some(InferredType::system("String")),
-        "encode" | "decode" => Some(InferredType::system("String")),
-
-        // Numeric functions
-        "toInteger" => Some(InferredType::system("Integer")),
-        "toDecimal" => Some(InferredType::system("Decimal")),
-        "toQuantity" => Some(InferredType::system("Quantity")),
-        "abs" | "ceiling" | "floor" | "round" | "truncate" => Some(input_type.clone()),
-        "sqrt" | "exp" | "ln" | "log" | "power" => Some(InferredType::system("Decimal")),
-
-        // Date/Time functions
-        "toDate" => Some(InferredType::system("Date")),
-        "toDateTime" => Some(InferredType::system("DateTime")),
-        "toTime" => Some(InferredType::system("Time")),
-        "today" => Some(InferredType::system("Date")),
-        "now" => Some(InferredType::system("DateTime")),
-        "timeOfDay" => Some(InferredType::system("Time")),
-
-        // Boolean functions
-        "toBoolean" => Some(InferredType::system("Boolean")),
-        "not" => Some(InferredType::system("Boolean")),
-        "allTrue" | "anyTrue" | "allFalse" | "anyFalse" => Some(InferredType::system("Boolean")),
-
-        // Collection functions
-        "count" => Some(InferredType::system("Integer")),
-        "empty" | "exists" | "all" | "subsetOf" | "supersetOf" => {
-            Some(InferredType::system("Boolean"))
-        }
-        "first" | "last" | "single" => {
-            if input_type.is_collection {
-                Some(InferredType {
-                    namespace: input_type.namespace.clone(),
-                    name: input_type.name.clone(),
-                    is_collection: false,
-                })
+    let item = InferredType {
+        is_collection: false,
+        ..input_type.clone()
+    };
+    match builtin_return_type(function_name)? {
+        BuiltinReturnType::Fixed(inferred) => Some(inferred),
+        BuiltinReturnType::PerItem(inferred) => Some(InferredType {
+            is_collection: input_type.is_collection,
+            ..inferred
+        }),
+        // Argument types are not inferred here, so these keep the input type
+        BuiltinReturnType::Input | BuiltinReturnType::Arguments => Some(input_type.clone()),
+        BuiltinReturnType::InputItem => Some(item),
+        BuiltinReturnType::InputCollection => Some(item.collection()),
+        BuiltinReturnType::QuantityOrDecimal => {
+            if input_type.name == "Quantity" {
+                Some(item)
             } else {
-                Some(input_type.clone())
+                Some(InferredType::system("Decimal"))
             }
         }
-        "tail" | "skip" | "take" | "distinct" => Some(input_type.clone()),
-        "where" | "select" => Some(input_type.clone()),
-        "repeat" => Some(input_type.clone()),
-        "aggregate" => Some(InferredType::system("Any")), // Type depends on aggregator
-
-        // Type functions
-        "ofType" => Some(input_type.clone()),
-        "is" => Some(InferredType::system("Boolean")),
-        "as" => Some(input_type.clone()),
-
-        // Utility functions
-        "trace" => Some(input_type.clone()),
-        "combine" => {
-            // combine returns a collection of the same type
-            if input_type.is_collection {
-                Some(input_type.clone())
-            } else {
-                Some(input_type.clone().collection())
-            }
+        BuiltinReturnType::PrimitiveValue => {
+            crate::type_checker::primitive_value_type(&item).map(|inferred| InferredType {
+                is_collection: input_type.is_collection,
+                ..inferred
+            })
         }
+    }
+}
 
-        // Math aggregates
-        "sum" => Some(input_type.clone()),
-        "min" | "max" | "avg" | "mean" => Some(input_type.clone()),
+/// How the result type of a built-in function follows from its input
+///
+/// This is the one table of built-in return types, shared by type inference
+/// and the type checker.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum BuiltinReturnType {
+    /// Always this type
+    Fixed(InferredType),
+    /// This type for each input item: a collection when the input is one
+    PerItem(InferredType),
+    /// The input itself
+    Input,
+    /// A single item of the input
+    InputItem,
+    /// A collection of the input's items
+    InputCollection,
+    /// A single Quantity for Quantity input, otherwise a Decimal
+    QuantityOrDecimal,
+    /// The System type of each input item's primitive value
+    PrimitiveValue,
+    /// Given by the argument types (select(), repeat(), ofType(), as(), iif(), aggregate())
+    Arguments,
+}
 
-        _ => None, // Unknown function
-    }
+/// Returns how the result type of the built-in function `name` is determined
+pub(crate) fn builtin_return_type(name: &str) -> Option<BuiltinReturnType> {
+    use BuiltinReturnType::*;
+
+    let system = |type_name: &str| Fixed(InferredType::system(type_name));
+    let return_type = match name {
+        // Boolean results
+        "empty" | "exists" | "all" | "allTrue" | "anyTrue" | "allFalse" | "anyFalse"
+        | "subsetOf" | "supersetOf" | "isDistinct" | "not" | "startsWith" | "endsWith"
+        | "contains" | "matches" | "matchesFull" | "hasValue" | "htmlChecks" | "is"
+        | "convertsToString" | "convertsToInteger" | "convertsToLong" | "convertsToDecimal"
+        | "convertsToBoolean" | "convertsToDate" | "convertsToDateTime" | "convertsToTime"
+        | "convertsToQuantity" | "memberOf" | "subsumes" | "subsumedBy" | "conformsTo"
+        | "comparable" | "toBoolean" => system("Boolean"),
+
+        // Integer results
+        "count" | "length" | "indexOf" | "lastIndexOf" | "precision" | "yearOf" | "monthOf"
+        | "dayOf" | "hourOf" | "minuteOf" | "secondOf" | "millisecondOf" | "duration"
+        | "difference" | "ceiling" | "floor" | "truncate" | "toInteger" => system("Integer"),
+        "toLong" => system("Long"),
+
+        // String results
+        "toString" | "upper" | "lower" | "replace" | "replaceMatches" | "trim" | "substring"
+        | "encode" | "decode" | "escape" | "unescape" | "join" | "getResourceKey"
+        | "getReferenceKey" => system("String"),
+        "toChars" | "split" => Fixed(InferredType::system("String").collection()),
+
+        // Decimal, date/time and quantity results
+        "toDecimal" | "timezoneOffsetOf" | "sqrt" | "exp" | "ln" | "log" | "round" => {
+            system("Decimal")
+        }
+        "toDate" | "today" | "dateOf" => system("Date"),
+        "toDateTime" | "now" => system("DateTime"),
+        "toTime" | "timeOfDay" | "timeOf" => system("Time"),
+        "toQuantity" => system("Quantity"),
+
+        // FHIR results
+        "extension" => Fixed(InferredType::fhir("Extension").collection()),
+        "elementDefinition" => Fixed(InferredType::fhir("ElementDefinition").collection()),
+        "resolve" => PerItem(InferredType::fhir("Resource")),
+
+        // Results that follow the input
+        "first" | "last" | "single" | "sum" | "min" | "max" | "abs" | "power" | "lowBoundary"
+        | "highBoundary" => InputItem,
+        "avg" => QuantityOrDecimal,
+        "where" | "distinct" | "tail" | "skip" | "take" | "sort" | "trace" | "defineVariable"
+        | "slice" | "checkModifiers" | "intersect" | "exclude" => Input,
+        "union" | "combine" => InputCollection,
+        "getValue" => PrimitiveValue,
+        "select" | "repeat" | "ofType" | "as" | "iif" | "aggregate" => Arguments,
+
+        _ => return None, // Unknown function
+    };
+    Some(return_type)
 }
@@ -0,0 +1,1007 @@
+//! # UCUM Units
+//!
+//! This module implements the subset of the Unified Code for Units of Measure
+//! (UCUM) that FHIRPath needs for Quantity comparison, equality, arithmetic and
+//! `toQuantity(unit)`.
+//!
+//! A unit expression such as `mg/dL`, `kg.m2/s2`, `10*3/uL` or `[lb_av]` is
+//! parsed according to the UCUM grammar (prefixes, exponents, `.` and `/`,
+//! parentheses, integer factors and `{annotations}`) and reduced to a
+//! [`CanonicalUnit`]: a magnitude relative to the UCUM base units (`m`, `s`,
+//! `g`, `rad`, `K`, `C`, `cd`) together with the exponent of each base unit.
+//! Two units are commensurable when their exponents match, and values convert
+//! between them through their magnitudes.
+//!
+//! Special units are supported with their own conversion functions: the
+//! temperature scales `Cel`, `[degF]` and `[degRe]`, and the logarithmic units
+//! (`Np`, `B`, `B[SPL]`, `[pH]`, ...), which only convert to themselves. They
+//! may be prefixed but cannot be combined with other units.
+//!
+//! Arbitrary units such as `[IU]` or `[CFU]` are treated as base units of their
+//! own, so they only convert to (prefixed) versions of themselves.
+//!
+//! The quantity functions at the end of the module also accept the FHIRPath
+//! calendar duration keywords (`year`, `months`, `day`, ...), which map to the
+//! UCUM units `a`, `mo`, `wk`, `d`, `h`, `min`, `s` and `ms`.
+//!
+//! ## Examples
+//!
+//! ```
+//! use helios_fhirpath::ucum;
+//! use rust_decimal::Decimal;
+//!
+//! assert!(ucum::is_valid_unit("mg/dL"));
+//! assert_eq!(
+//!     ucum::convert(Decimal::from(4), "g", "mg"),
+//!     Some(Decimal::from(4000))
+//! );
+//! assert_eq!(ucum::convert(Decimal::from(1), "g", "m"), None);
+//! ```
+
+use helios_fhirpath_support::EvaluationError;
+use rust_decimal::prelude::*;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+use Definition::{Base, Derived};
+use once_cell::sync::Lazy;
+
+/// Number of significant digits kept when comparing converted values, so that
+/// rounding in the last digits of a division does not break equality
+const COMPARISON_DIGITS: u32 = 20;
+
+/// A unit reduced to the UCUM base units
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanonicalUnit {
+    /// The magnitude of one of this unit, expressed in base units
+    pub factor: Decimal,
+    /// The exponent of each base unit, without zero entries
+    pub dimensions: BTreeMap<String, i32>,
+    special: Option<Special>,
+}
+
+/// Units whose conversion is not a plain multiplication
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Special {
+    Celsius,
+    Fahrenheit,
+    Reaumur,
+    Logarithmic,
+}
+
+impl CanonicalUnit {
+    fn unity() -> Self {
+        CanonicalUnit {
+            factor: Decimal::ONE,
+            dimensions: BTreeMap::new(),
+            special: None,
+        }
+    }
+
+    fn scalar(factor: Decimal) -> Self {
+        CanonicalUnit {
+            factor,
+            ..CanonicalUnit::unity()
+        }
+    }
+
+    fn base(dimension: &str) -> Self {
+        CanonicalUnit {
+            dimensions: BTreeMap::from([(dimension.to_string(), 1)]),
+            ..CanonicalUnit::unity()
+        }
+    }
+
+    /// Returns true if values in this unit can be converted to `other`
+    pub fn is_commensurable(&self, other: &CanonicalUnit) -> bool {
+        self.dimensions == other.dimensions
+    }
+
+    /// Returns true if the unit has no dimensions, like `1`, `%` or `{cells}`
+    pub fn is_dimensionless(&self) -> bool {
+        self.dimensions.is_empty()
+    }
+
+    /// Returns true for the temperature and logarithmic units
+    pub fn is_special(&self) -> bool {
+        self.special.is_some()
+    }
+
+    /// Converts a value in this unit to the base units
+    pub fn to_base(&self, value: Decimal) -> Option<Decimal> {
+        let scaled = value.checked_mul(self.factor)?;
+        match self.special {
+            None | Some(Special::Logarithmic) => Some(scaled),
+            Some(Special::Celsius) => scaled.checked_add(kelvin_offset()),
+            Some(Special::Fahrenheit) => scaled
+                .checked_add(rankine_offset())?
+                .checked_mul(Decimal::from(5))?
+                .checked_div(Decimal::from(9)),
+            Some(Special::Reaumur) => scaled
+                .checked_mul(Decimal::from(5))?
+                .checked_div(Decimal::from(4))?
+                .checked_add(kelvin_offset()),
+        }
+    }
+
+    /// Converts a value in base units to this unit
+    pub fn from_base(&self, value: Decimal) -> Option<Decimal> {
+        let scaled = match self.special {
+            None | Some(Special::Logarithmic) => value,
+            Some(Special::Celsius) => value.checked_sub(kelvin_offset())?,
+            Some(Special::Fahrenheit) => value
+                .checked_mul(Decimal::from(9))?
+                .checked_div(Decimal::from(5))?
+                .checked_sub(rankine_offset())?,
+            Some(Special::Reaumur) => value
+                .checked_sub(kelvin_offset())?
+                .checked_mul(Decimal::from(4))?
+                .checked_div(Decimal::from(5))?,
+        };
+        scaled.checked_div(self.factor)
+    }
+
+    fn multiply(self, other: CanonicalUnit) -> Result<Self, EvaluationError> {
+        if self.special.is_some() || other.special.is_some() {
+            return Err(special_unit_error());
+        }
+        let factor = self
+            .factor
+            .checked_mul(other.factor)
+            .ok_or(EvaluationError::ArithmeticOverflow)?;
+        let mut dimensions = self.dimensions;
+        for (dimension, exponent) in other.dimensions {
+            *dimensions.entry(dimension).or_insert(0) += exponent;
+        }
+        dimensions.retain(|_, exponent| *exponent != 0);
+        Ok(CanonicalUnit {
+            factor,
+            dimensions,
+            special: None,
+        })
+    }
+
+    fn divide(self, other: CanonicalUnit) -> Result<Self, EvaluationError> {
+        self.multiply(other.pow(-1)?)
+    }
+
+    fn pow(self, exponent: i32) -> Result<Self, EvaluationError> {
+        if exponent == 1 {
+            return Ok(self);
+        }
+        if self.special.is_some() {
+            return Err(special_unit_error());
+        }
+        let factor =
+            decimal_pow(self.factor, exponent).ok_or(EvaluationError::ArithmeticOverflow)?;
+        let dimensions = self
+            .dimensions
+            .into_iter()
+            .map(|(dimension, power)| (dimension, power * exponent))
+            .filter(|(_, power)| *power != 0)
+            .collect();
+        Ok(CanonicalUnit {
+            factor,
+            dimensions,
+            special: None,
+        })
+    }
+}
+
+fn kelvin_offset() -> Decimal {
+    Decimal::new(27315, 2)
+}
+
+fn rankine_offset() -> Decimal {
+    Decimal::new(45967, 2)
+}
+
+fn special_unit_error() -> EvaluationError {
+    EvaluationError::InvalidArgument(
+        "UCUM special units (temperatures and logarithmic units) cannot be combined with other units"
+            .to_string(),
+    )
+}
+
+/// Raises a decimal to an integer power, None on overflow or division by zero
+fn decimal_pow(base: Decimal, exponent: i32) -> Option<Decimal> {
+    let mut result = Decimal::ONE;
+    for _ in 0..exponent.unsigned_abs() {
+        result = result.checked_mul(base)?;
+    }
+    if exponent < 0 {
+        Decimal::ONE.checked_div(result)
+    } else {
+        Some(result)
+    }
+}
+
+/// How a unit atom is defined in the table
+enum Definition {
+    /// A base unit, or an arbitrary unit that only converts to itself
+    Base,
+    /// A multiple of another unit expression: (value, unit)
+    Derived(&'static str, &'static str),
+    /// A special unit measured on the scale of a base unit expression
+    Special(Special, &'static str),
+}
+
+struct UnitAtom {
+    metric: bool,
+    definition: Definition,
+}
+
+/// Builds an entry of the unit table
+const fn atom(metric: bool, definition: Definition) -> UnitAtom {
+    UnitAtom { metric, definition }
+}
+
+/// The UCUM unit atoms, keyed by their case-sensitive code
+static UNIT_ATOMS: Lazy<HashMap<&'static str, UnitAtom>> = Lazy::new(|| {
+    HashMap::from([
+        // Base units
+        ("m", atom(true, Base)),
+        ("s", atom(true, Base)),
+        ("g", atom(true, Base)),
+        ("rad", atom(true, Base)),
+        ("K", atom(true, Base)),
+        ("C", atom(true, Base)),
+        ("cd", atom(true, Base)),
+        // Dimensionless
+        ("10*", atom(false, Derived("10", "1"))),
+        ("10^", atom(false, Derived("10", "1"))),
+        (
+            "[pi]",
+            atom(false, Derived("3.141592653589793238462643383", "1")),
+        ),
+        ("%", atom(false, Derived("1", "10*-2"))),
+        ("[ppth]", atom(false, Derived("1", "10*-3"))),
+        ("[ppm]", atom(false, Derived("1", "10*-6"))),
+        ("[ppb]", atom(false, Derived("1", "10*-9"))),
+        ("[pptr]", atom(false, Derived("1", "10*-12"))),
+        // SI units
+        ("mol", atom(true, Derived("6.0221367", "10*23"))),
+        ("sr", atom(true, Derived("1", "rad2"))),
+        ("Hz", atom(true, Derived("1", "s-1"))),
+        ("N", atom(true, Derived("1", "kg.m/s2"))),
+        ("Pa", atom(true, Derived("1", "N/m2"))),
+        ("J", atom(true, Derived("1", "N.m"))),
+        ("W", atom(true, Derived("1", "J/s"))),
+        ("A", atom(true, Derived("1", "C/s"))),
+        ("V", atom(true, Derived("1", "J/C"))),
+        ("F", atom(true, Derived("1", "C/V"))),
+        ("Ohm", atom(true, Derived("1", "V/A"))),
+        ("S", atom(true, Derived("1", "Ohm-1"))),
+        ("Wb", atom(true, Derived("1", "V.s"))),
+        (
+            "Cel",
+            atom(true, Definition::Special(Special::Celsius, "K")),
+        ),
+        ("T", atom(true, Derived("1", "Wb/m2"))),
+        ("H", atom(true, Derived("1", "Wb/A"))),
+        ("lm", atom(true, Derived("1", "cd.sr"))),
+        ("lx", atom(true, Derived("1", "lm/m2"))),
+        ("Bq", atom(true, Derived("1", "s-1"))),
+        ("Gy", atom(true, Derived("1", "J/kg"))),
+        ("Sv", atom(true, Derived("1", "J/kg"))),
+        // Other units from ISO 1000
+        ("gon", atom(false, Derived("0.9", "deg"))),
+        ("deg", atom(false, Derived("2", "[pi].rad/360"))),
+        ("l", atom(true, Derived("1", "dm3"))),
+        ("L", atom(true, Derived("1", "l"))),
+        ("ar", atom(true, Derived("100", "m2"))),
+        ("min", atom(false, Derived("60", "s"))),
+        ("h", atom(false, Derived("60", "min"))),
+        ("d", atom(false, Derived("24", "h"))),
+        ("a_t", atom(false, Derived("365.24219", "d"))),
+        ("a_j", atom(false, Derived("365.25", "d"))),
+        ("a_g", atom(false, Derived("365.2425", "d"))),
+        ("a", atom(false, Derived("1", "a_j"))),
+        ("wk", atom(false, Derived("7", "d"))),
+        ("mo_s", atom(false, Derived("29.53059", "d"))),
+        ("mo_j", atom(false, Derived("1", "a_j/12"))),
+        ("mo_g", atom(false, Derived("1", "a_g/12"))),
+        ("mo", atom(false, Derived("1", "mo_j"))),
+        ("t", atom(true, Derived("1000", "kg"))),
+        ("bar", atom(true, Derived("100000", "Pa"))),
+        ("eV", atom(true, Derived("1", "[e].V"))),
+        ("pc", atom(true, Derived("30856780000000000", "m"))),
+        // Natural constants
+        ("[c]", atom(true, Derived("299792458", "m/s"))),
+        (
+            "[e]",
+            atom(true, Derived("0.000000000000000000160217733", "C")),
+        ),
+        (
+            "[eps_0]",
+            atom(true, Derived("0.000000000008854187817", "F/m")),
+        ),
+        ("[mu_0]", atom(true, Derived("1", "4.[pi].10*-7.N/A2"))),
+        (
+            "[G]",
+            atom(true, Derived("0.0000000000667259", "m3.kg-1.s-2")),
+        ),
+        ("[g]", atom(true, Derived("9.80665", "m/s2"))),
+        ("[ly]", atom(true, Derived("1", "[c].a_j"))),
+        ("gf", atom(true, Derived("1", "g.[g]"))),
+        // CGS units
+        ("Ky", atom(true, Derived("1", "cm-1"))),
+        ("Gal", atom(true, Derived("1", "cm/s2"))),
+        ("dyn", atom(true, Derived("1", "g.cm/s2"))),
+        ("erg", atom(true, Derived("1", "dyn.cm"))),
+        ("P", atom(true, Derived("1", "dyn.s/cm2"))),
+        ("St", atom(true, Derived("1", "cm2/s"))),
+        ("Mx", atom(true, Derived("0.00000001", "Wb"))),
+        ("G", atom(true, Derived("0.0001", "T"))),
+        ("Ci", atom(true, Derived("37000000000", "Bq"))),
+        ("R", atom(true, Derived("0.000258", "C/kg"))),
+        ("RAD", atom(true, Derived("100", "erg/g"))),
+        ("REM", atom(true, Derived("1", "RAD"))),
+        // International customary units
+        ("[in_i]", atom(false, Derived("2.54", "cm"))),
+        ("[ft_i]", atom(false, Derived("12", "[in_i]"))),
+        ("[yd_i]", atom(false, Derived("3", "[ft_i]"))),
+        ("[mi_i]", atom(false, Derived("5280", "[ft_i]"))),
+        ("[fth_i]", atom(false, Derived("6", "[ft_i]"))),
+        ("[nmi_i]", atom(false, Derived("1852", "m"))),
+        ("[kn_i]", atom(false, Derived("1", "[nmi_i]/h"))),
+        ("[sin_i]", atom(false, Derived("1", "[in_i]2"))),
+        ("[sft_i]", atom(false, Derived("1", "[ft_i]2"))),
+        ("[syd_i]", atom(false, Derived("1", "[yd_i]2"))),
+        ("[cin_i]", atom(false, Derived("1", "[in_i]3"))),
+        ("[cft_i]", atom(false, Derived("1", "[ft_i]3"))),
+        ("[cyd_i]", atom(false, Derived("1", "[yd_i]3"))),
+        ("[mil_i]", atom(false, Derived("0.001", "[in_i]"))),
+        ("[hd_i]", atom(false, Derived("4", "[in_i]"))),
+        // US volumes
+        ("[gal_us]", atom(false, Derived("231", "[in_i]3"))),
+        ("[bbl_us]", atom(false, Derived("42", "[gal_us]"))),
+        ("[qt_us]", atom(false, Derived("1", "[gal_us]/4"))),
+        ("[pt_us]", atom(false, Derived("1", "[qt_us]/2"))),
+        ("[gil_us]", atom(false, Derived("1", "[pt_us]/4"))),
+        ("[foz_us]", atom(false, Derived("1", "[gil_us]/4"))),
+        ("[fdr_us]", atom(false, Derived("1", "[foz_us]/8"))),
+        ("[cup_us]", atom(false, Derived("16", "[tbs_us]"))),
+        ("[tbs_us]", atom(false, Derived("1", "[foz_us]/2"))),
+        ("[tsp_us]", atom(false, Derived("1", "[tbs_us]/3"))),
+        // British volumes
+        ("[gal_br]", atom(false, Derived("4.54609", "l"))),
+        ("[qt_br]", atom(false, Derived("1", "[gal_br]/4"))),
+        ("[pt_br]", atom(false, Derived("1", "[qt_br]/2"))),
+        ("[gil_br]", atom(false, Derived("1", "[pt_br]/4"))),
+        ("[foz_br]", atom(false, Derived("1", "[gil_br]/5"))),
+        // Metric cooking measures
+        ("[tsp_m]", atom(false, Derived("5", "mL"))),
+        ("[tbs_m]", atom(false, Derived("15", "mL"))),
+        ("[foz_m]", atom(false, Derived("30", "mL"))),
+        ("[cup_m]", atom(false, Derived("240", "mL"))),
+        ("[drp]", atom(false, Derived("1", "ml/20"))),
+        // Avoirdupois, troy and apothecaries' weights
+        ("[gr]", atom(false, Derived("64.79891", "mg"))),
+        ("[lb_av]", atom(false, Derived("7000", "[gr]"))),
+        ("[oz_av]", atom(false, Derived("1", "[lb_av]/16"))),
+        ("[dr_av]", atom(false, Derived("1", "[oz_av]/16"))),
+        ("[stone_av]", atom(false, Derived("14", "[lb_av]"))),
+        ("[scwt_av]", atom(false, Derived("100", "[lb_av]"))),
+        ("[lcwt_av]", atom(false, Derived("112", "[lb_av]"))),
+        ("[ston_av]", atom(false, Derived("20", "[scwt_av]"))),
+        ("[lton_av]", atom(false, Derived("20", "[lcwt_av]"))),
+        ("[pwt_tr]", atom(false, Derived("24", "[gr]"))),
+        ("[oz_tr]", atom(false, Derived("20", "[pwt_tr]"))),
+        ("[lb_tr]", atom(false, Derived("12", "[oz_tr]"))),
+        ("[sc_ap]", atom(false, Derived("20", "[gr]"))),
+        ("[dr_ap]", atom(false, Derived("3", "[sc_ap]"))),
+        ("[oz_ap]", atom(false, Derived("8", "[dr_ap]"))),
+        ("[lb_ap]", atom(false, Derived("12", "[oz_ap]"))),
+        ("[lbf_av]", atom(false, Derived("1", "[lb_av].[g]"))),
+        ("[psi]", atom(false, Derived("1", "[lbf_av]/[in_i]2"))),
+        // Temperatures
+        (
+            "[degF]",
+            atom(false, Definition::Special(Special::Fahrenheit, "K")),
+        ),
+        ("[degR]", atom(false, Derived("5", "K/9"))),
+        (
+            "[degRe]",
+            atom(false, Definition::Special(Special::Reaumur, "K")),
+        ),
+        // Pressure and heat
+        ("atm", atom(false, Derived("101325", "Pa"))),
+        ("att", atom(false, Derived("1", "kgf/cm2"))),
+        ("m[Hg]", atom(true, Derived("133.322", "kPa"))),
+        ("m[H2O]", atom(true, Derived("9.80665", "kPa"))),
+        ("[in_i'Hg]", atom(false, Derived("1", "m[Hg].[in_i]/m"))),
+        ("[in_i'H2O]", atom(false, Derived("1", "m[H2O].[in_i]/m"))),
+        ("cal", atom(true, Derived("4.184", "J"))),
+        ("[Cal]", atom(false, Derived("1", "kcal"))),
+        // Chemistry and clinical units
+        ("eq", atom(true, Derived("1", "mol"))),
+        ("osm", atom(true, Derived("1", "mol"))),
+        ("g%", atom(true, Derived("1", "g/dl"))),
+        ("kat", atom(true, Derived("1", "mol/s"))),
+        ("U", atom(true, Derived("1", "umol/min"))),
+        ("mho", atom(true, Derived("1", "S"))),
+        ("[iU]", atom(true, Base)),
+        ("[IU]", atom(true, Derived("1", "[iU]"))),
+        ("[arb'U]", atom(false, Base)),
+        ("[USP'U]", atom(false, Base)),
+        ("[CFU]", atom(false, Base)),
+        ("[PFU]", atom(false, Base)),
+        ("[FFU]", atom(false, Base)),
+        ("[BAU]", atom(false, Base)),
+        ("[AU]", atom(false, Base)),
+        ("[HPF]", atom(false, Derived("1", "1"))),
+        ("[LPF]", atom(false, Derived("100", "1"))),
+        // Logarithmic units
+        (
+            "Np",
+            atom(true, Definition::Special(Special::Logarithmic, "Np")),
+        ),
+        (
+            "B",
+            atom(true, Definition::Special(Special::Logarithmic, "B")),
+        ),
+        (
+            "B[SPL]",
+            atom(true, Definition::Special(Special::Logarithmic, "B[SPL]")),
+        ),
+        (
+            "B[V]",
+            atom(true, Definition::Special(Special::Logarithmic, "B[V]")),
+        ),
+        (
+            "B[mV]",
+            atom(true, Definition::Special(Special::Logarithmic, "B[mV]")),
+        ),
+        (
+            "B[uV]",
+            atom(true, Definition::Special(Special::Logarithmic, "B[uV]")),
+        ),
+        (
+            "B[W]",
+            atom(true, Definition::Special(Special::Logarithmic, "B[W]")),
+        ),
+        (
+            "B[kW]",
+            atom(true, Definition::Special(Special::Logarithmic, "B[kW]")),
+        ),
+        (
+            "[pH]",
+            atom(false, Definition::Special(Special::Logarithmic, "[pH]")),
+        ),
+        // Information technology
+        ("bit", atom(true, Derived("1", "1"))),
+        ("By", atom(true, Derived("8", "bit"))),
+        ("Bd", atom(true, Derived("1", "s-1"))),
+    ])
+});
+
+/// The UCUM prefixes, two-letter codes first so that `da` wins over `d`
+static PREFIXES: Lazy<Vec<(&'static str, Decimal)>> = Lazy::new(|| {
+    let power_of_ten = |exponent: i32| decimal_pow(Decimal::TEN, exponent).unwrap();
+    let power_of_two = |exponent: i32| decimal_pow(Decimal::TWO, exponent).unwrap();
+    vec![
+        ("da", power_of_ten(1)),
+        ("Ki", power_of_two(10)),
+        ("Mi", power_of_two(20)),
+        ("Gi", power_of_two(30)),
+        ("Ti", power_of_two(40)),
+        ("Y", power_of_ten(24)),
+        ("Z", power_of_ten(21)),
+        ("E", power_of_ten(18)),
+        ("P", power_of_ten(15)),
+        ("T", power_of_ten(12)),
+        ("G", power_of_ten(9)),
+        ("M", power_of_ten(6)),
+        ("k", power_of_ten(3)),
+        ("h", power_of_ten(2)),
+        ("d", power_of_ten(-1)),
+        ("c", power_of_ten(-2)),
+        ("m", power_of_ten(-3)),
+        ("u", power_of_ten(-6)),
+        ("n", power_of_ten(-9)),
+        ("p", power_of_ten(-12)),
+        ("f", power_of_ten(-15)),
+        ("a", power_of_ten(-18)),
+        ("z", power_of_ten(-21)),
+        ("y", power_of_ten(-24)),
+    ]
+});
+
+/// Recursive descent parser for UCUM unit expressions
+struct UnitParser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> UnitParser<'a> {
+    fn new(input: &'a str) -> Self {
+        UnitParser { input, position: 0 }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn error(&self, message: &str) -> EvaluationError {
+        EvaluationError::InvalidArgument(format!("Invalid UCUM unit '{}': {}", self.input, message))
+    }
+
+    /// Parses the whole input: `['/'] term`
+    fn parse(mut self) -> Result<CanonicalUnit, EvaluationError> {
+        if self.input.is_empty() {
+            return Err(self.error("the unit is empty"));
+        }
+        let unit = if self.peek() == Some('/') {
+            self.position += 1;
+            CanonicalUnit::unity().divide(self.parse_component()?)?
+        } else {
+            self.parse_component()?
+        };
+        let unit = self.parse_operations(unit)?;
+        match self.peek() {
+            None => Ok(unit),
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+        }
+    }
+
+    /// Applies the left-associative `.` and `/` operators that follow a component
+    fn parse_operations(
+        &mut self,
+        mut unit: CanonicalUnit,
+    ) -> Result<CanonicalUnit, EvaluationError> {
+        loop {
+            match self.peek() {
+                Some('.') => {
+                    self.position += 1;
+                    unit = unit.multiply(self.parse_component()?)?;
+                }
+                Some('/') => {
+                    self.position += 1;
+                    unit = unit.divide(self.parse_component()?)?;
+                }
+                _ => return Ok(unit),
+            }
+        }
+    }
+
+    /// Parses a parenthesized term, an annotation, a factor or a unit symbol
+    fn parse_component(&mut self) -> Result<CanonicalUnit, EvaluationError> {
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let inner = self.parse_component()?;
+                let inner = self.parse_operations(inner)?;
+                if self.peek() != Some(')') {
+                    return Err(self.error("missing ')'"));
+                }
+                self.position += 1;
+                Ok(inner)
+            }
+            Some('{') => {
+                self.parse_annotation()?;
+                Ok(CanonicalUnit::unity())
+            }
+            Some(_) => {
+                let symbol = self.read_symbol()?;
+                let unit = resolve_symbol(symbol).map_err(|message| self.error(&message))?;
+                if self.peek() == Some('{') {
+                    self.parse_annotation()?;
+                }
+                Ok(unit)
+            }
+            None => Err(self.error("a unit is missing")),
+        }
+    }
+
+    /// Skips over an annotation, which has no effect on the unit
+    fn parse_annotation(&mut self) -> Result<(), EvaluationError> {
+        let rest = &self.input[self.position + 1..];
+        let Some(end) = rest.find('}') else {
+            return Err(self.error("unterminated annotation"));
+        };
+        if rest[..end].contains('{') {
+            return Err(self.error("nested annotation"));
+        }
+        self.position += end + 2;
+        Ok(())
+    }
+
+    /// Reads a unit symbol with its exponent, keeping `[...]` groups intact
+    fn read_symbol(&mut self) -> Result<&'a str, EvaluationError> {
+        let start = self.position;
+        let mut in_brackets = false;
+        for (offset, c) in self.input[start..].char_indices() {
+            match c {
+                '[' => in_brackets = true,
+                ']' => in_brackets = false,
+                '.' | '/' | '(' | ')' | '{' | '}' if !in_brackets => {
+                    self.position = start + offset;
+                    return self.checked_symbol(start);
+                }
+                c if c.is_whitespace() => {
+                    return Err(self.error("units cannot contain whitespace"));
+                }
+                _ => {}
+            }
+        }
+        if in_brackets {
+            return Err(self.error("missing ']'"));
+        }
+        self.position = self.input.len();
+        self.checked_symbol(start)
+    }
+
+    fn checked_symbol(&self, start: usize) -> Result<&'a str, EvaluationError> {
+        let symbol = &self.input[start..self.position];
+        if symbol.is_empty() {
+            Err(self.error("a unit is missing"))
+        } else {
+            Ok(symbol)
+        }
+    }
+}
+
+/// Resolves a symbol such as `mg`, `m2`, `s-1`, `10*3` or `1000`
+fn resolve_symbol(symbol: &str) -> Result<CanonicalUnit, String> {
+    // Integer factors
+    if symbol.bytes().all(|b| b.is_ascii_digit()) {
+        return Decimal::from_str(symbol)
+            .map(CanonicalUnit::scalar)
+            .map_err(|_| format!("the factor '{}' is too large", symbol));
+    }
+
+    // Split off a trailing exponent, which is an optionally signed integer
+    let digits_start = symbol.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let (atom_part, exponent) = if digits_start < symbol.len() {
+        let sign_start = symbol[..digits_start]
+            .strip_suffix(['+', '-'])
+            .map_or(digits_start, str::len);
+        let exponent = symbol[sign_start..]
+            .parse::<i32>()
+            .map_err(|_| format!("invalid exponent in '{}'", symbol))?;
+        (&symbol[..sign_start], exponent)
+    } else {
+        (symbol, 1)
+    };
+
+    let unit = resolve_atom(atom_part)
+        .ok_or_else(|| format!("unknown unit '{}'", atom_part))?
+        .map_err(|e| e.to_string())?;
+    unit.pow(exponent).map_err(|e| e.to_string())
+}
+
+/// Looks up a unit atom, optionally preceded by a prefix
+fn resolve_atom(symbol: &str) -> Option<Result<CanonicalUnit, EvaluationError>> {
+    if let Some(unit_atom) = UNIT_ATOMS.get(symbol) {
+        return Some(atom_unit(symbol, unit_atom));
+    }
+    PREFIXES.iter().find_map(|(prefix, factor)| {
+        let unit_atom = UNIT_ATOMS.get(symbol.strip_prefix(prefix)?)?;
+        if !unit_atom.metric {
+            return None;
+        }
+        let unit = atom_unit(&symbol[prefix.len()..], unit_atom).and_then(|unit| {
+            let prefixed = unit
+                .factor
+                .checked_mul(*factor)
+                .ok_or(EvaluationError::ArithmeticOverflow)?;
+            Ok(CanonicalUnit {
+                factor: prefixed,
+                ..unit
+            })
+        });
+        Some(unit)
+    })
+}
+
+/// Computes the canonical form of a unit atom from its definition
+fn atom_unit(code: &str, unit_atom: &UnitAtom) -> Result<CanonicalUnit, EvaluationError> {
+    match &unit_atom.definition {
+        Base => Ok(CanonicalUnit::base(code)),
+        Derived(value, unit) => {
+            let value = Decimal::from_str(value).map_err(|_| {
+                EvaluationError::InvalidOperation(format!(
+                    "Invalid UCUM table value for '{}'",
+                    code
+                ))
+            })?;
+            CanonicalUnit::scalar(value).multiply(canonicalize(unit)?)
+        }
+        Definition::Special(Special::Logarithmic, dimension) => Ok(CanonicalUnit {
+            special: Some(Special::Logarithmic),
+            ..CanonicalUnit::base(dimension)
+        }),
+        Definition::Special(special, scale) => Ok(CanonicalUnit {
+            special: Some(*special),
+            ..canonicalize(scale)?
+        }),
+    }
+}
+
+/// Parses a UCUM unit expression and reduces it to base units
+///
+/// # Errors
+/// Returns `InvalidArgument` if the expression is not valid UCUM, uses an
+/// unknown unit, or combines a special unit with other units.
+pub fn canonicalize(unit: &str) -> Result<CanonicalUnit, EvaluationError> {
+    UnitParser::new(unit).parse()
+}
+
+/// Returns true if `unit` is a valid UCUM unit expression
+pub fn is_valid_unit(unit: &str) -> bool {
+    canonicalize(unit).is_ok()
+}
+
+/// Converts a value between two UCUM units
+///
+/// Returns None if either unit is invalid or the units are not commensurable.
+pub fn convert(value: Decimal, from: &str, to: &str) -> Option<Decimal> {
+    convert_between(value, &canonicalize(from).ok()?, &canonicalize(to).ok()?)
+}
+
+fn convert_between(value: Decimal, from: &CanonicalUnit, to: &CanonicalUnit) -> Option<Decimal> {
+    if !from.is_commensurable(to) {
+        return None;
+    }
+    let converted = to.from_base(from.to_base(value)?)?;
+    Some(round_for_comparison(converted).normalize())
+}
+
+fn round_for_comparison(value: Decimal) -> Decimal {
+    value.round_sf(COMPARISON_DIGITS).unwrap_or(value)
+}
+
+/// Maps a FHIRPath calendar duration keyword to its UCUM unit
+///
+/// Braces are ignored, so `{day}` is treated like `day`.
+pub fn calendar_unit_code(unit: &str) -> Option<&'static str> {
+    let keyword = unit
+        .strip_prefix('{')
+        .and_then(|inner| inner.strip_suffix('}'))
+        .unwrap_or(unit);
+    match keyword {
+        "year" | "years" => Some("a"),
+        "month" | "months" => Some("mo"),
+        "week" | "weeks" => Some("wk"),
+        "day" | "days" => Some("d"),
+        "hour" | "hours" => Some("h"),
+        "minute" | "minutes" => Some("min"),
+        "second" | "seconds" => Some("s"),
+        "millisecond" | "milliseconds" => Some("ms"),
+        _ => None,
+    }
+}
+
+/// Normalizes the unit of a Quantity literal
+///
+/// A calendar keyword written as an annotation, like `{day}`, becomes the
+/// keyword itself. Every other unit is returned unchanged.
+pub fn normalize_quantity_unit(unit: &str) -> String {
+    match unit
+        .strip_prefix('{')
+        .and_then(|inner| inner.strip_suffix('}'))
+    {
+        Some(keyword) if calendar_unit_code(keyword).is_some() => keyword.to_string(),
+        _ => unit.to_string(),
+    }
+}
+
+/// Returns true if `unit` is a calendar keyword or a valid UCUM unit
+pub fn is_valid_quantity_unit(unit: &str) -> bool {
+    calendar_unit_code(unit).is_some() || is_valid_unit(unit)
+}
+
+/// Resolves the unit of a FHIRPath Quantity, which may be a calendar keyword
+fn resolve_quantity_unit(unit: &str) -> Option<CanonicalUnit> {
+    let code = match calendar_unit_code(unit) {
+        Some(code) => code,
+        None if unit.is_empty() => "1",
+        None => unit,
+    };
+    canonicalize(code).ok()
+}
+
+/// Returns the UCUM code of a quantity unit, mapping calendar keywords
+fn ucum_code(unit: &str) -> &str {
+    calendar_unit_code(unit).unwrap_or(if unit.is_empty() { "1" } else { unit })
+}
+
+/// Calendar years and months vary in length, so they are not equal to the
+/// fixed UCUM durations, although they are equivalent to them
+fn is_calendar_year_or_month(unit: &str) -> bool {
+    matches!(calendar_unit_code(unit), Some("a" | "mo"))
+}
+
+/// Converts a Quantity value between FHIRPath units
+///
+/// Both units may be calendar keywords or UCUM units. Returns None if the
+/// units are not commensurable.
+pub fn convert_quantity(value: Decimal, from: &str, to: &str) -> Option<Decimal> {
+    if from == to {
+        return Some(value);
+    }
+    convert_between(
+        value,
+        &resolve_quantity_unit(from)?,
+        &resolve_quantity_unit(to)?,
+    )
+}
+
+/// Returns true if quantities in the two units can be compared, i.e. the
+/// units are the same or commensurable
+pub fn units_comparable(left_unit: &str, right_unit: &str) -> bool {
+    if left_unit == right_unit {
+        return true;
+    }
+    match (
+        resolve_quantity_unit(left_unit),
+        resolve_quantity_unit(right_unit),
+    ) {
+        (Some(left), Some(right)) => left.is_commensurable(&right),
+        _ => false,
+    }
+}
+
+/// Orders two quantities, converting the right one to the left unit
+///
+/// Returns None if the units are not commensurable.
+pub fn compare_quantities(
+    left_value: Decimal,
+    left_unit: &str,
+    right_value: Decimal,
+    right_unit: &str,
+) -> Option<Ordering> {
+    if left_unit == right_unit {
+        return Some(left_value.cmp(&right_value));
+    }
+    let converted = convert_quantity(right_value, right_unit, left_unit)?;
+    Some(round_for_comparison(left_value).cmp(&converted))
+}
+
+/// Implements Quantity equality (`=`)
+///
+/// Returns None (an empty result) if the units are not commensurable. A
+/// calendar year or month is never equal to a UCUM duration.
+pub fn quantities_equal(
+    left_value: Decimal,
+    left_unit: &str,
+    right_value: Decimal,
+    right_unit: &str,
+) -> Option<bool> {
+    let calendar_mismatch = |calendar: &str, other: &str| {
+        is_calendar_year_or_month(calendar) && calendar_unit_code(other).is_none()
+    };
+    if calendar_mismatch(left_unit, right_unit) || calendar_mismatch(right_unit, left_unit) {
+        return resolve_quantity_unit(left_unit)
+            .zip(resolve_quantity_unit(right_unit))
+            .filter(|(left, right)| left.is_commensurable(right))
+            .map(|_| false);
+    }
+    compare_quantities(left_value, left_unit, right_value, right_unit)
+        .map(|ordering| ordering == Ordering::Equal)
+}
+
+/// Implements Quantity equivalence (`~`)
+///
+/// Both values are expressed in the larger of the two units and compared at
+/// the precision of the least precise value. Quantities with incommensurable
+/// units are not equivalent.
+pub fn quantities_equivalent(
+    left_value: Decimal,
+    left_unit: &str,
+    right_value: Decimal,
+    right_unit: &str,
+) -> bool {
+    let scale = left_value.scale().min(right_value.scale());
+    let round = |value: Decimal| {
+        value.round_dp_with_strategy(scale, RoundingStrategy::MidpointAwayFromZero)
+    };
+    if left_unit == right_unit {
+        return round(left_value) == round(right_value);
+    }
+    let (Some(left), Some(right)) = (
+        resolve_quantity_unit(left_unit),
+        resolve_quantity_unit(right_unit),
+    ) else {
+        return false;
+    };
+    if !left.is_commensurable(&right) {
+        return false;
+    }
+    let (left_value, right_value) = if left.factor >= right.factor {
+        (left_value, convert_between(right_value, &right, &left))
+    } else {
+        (right_value, convert_between(left_value, &left, &right))
+    };
+    right_value.is_some_and(|converted| round(left_value) == round(converted))
+}
+
+/// Adds two quantities, giving the result in the smaller of the two units
+///
+/// Returns None if the units are not commensurable or are special units.
+pub fn add_quantities(
+    left_value: Decimal,
+    left_unit: &str,
+    right_value: Decimal,
+    right_unit: &str,
+) -> Option<(Decimal, String)> {
+    if left_unit == right_unit {
+        return Some((left_value.checked_add(right_value)?, left_unit.to_string()));
+    }
+    let left = resolve_quantity_unit(left_unit)?;
+    let right = resolve_quantity_unit(right_unit)?;
+    if left.is_special() || right.is_special() || !left.is_commensurable(&right) {
+        return None;
+    }
+    if left.factor <= right.factor {
+        let converted = convert_between(right_value, &right, &left)?;
+        Some((left_value.checked_add(converted)?, left_unit.to_string()))
+    } else {
+        let converted = convert_between(left_value, &left, &right)?;
+        Some((converted.checked_add(right_value)?, right_unit.to_string()))
+    }
+}
+
+/// Multiplies two quantities, combining their units with `.`
+///
+/// A `'1'` unit, as used for plain numbers, leaves the other unit unchanged.
+/// Returns None if either unit is not valid UCUM or is a special unit.
+pub fn multiply_quantities(
+    left_value: Decimal,
+    left_unit: &str,
+    right_value: Decimal,
+    right_unit: &str,
+) -> Option<(Decimal, String)> {
+    let value = left_value.checked_mul(right_value)?;
+    if left_unit == "1" || right_unit == "1" {
+        let unit = if left_unit == "1" {
+            right_unit
+        } else {
+            left_unit
+        };
+        return Some((value, unit.to_string()));
+    }
+    let (left_code, right_code) = combinable_codes(left_unit, right_unit)?;
+    let unit = if right_code.contains('/') {
+        format!("{}.({})", left_code, right_code)
+    } else {
+        format!("{}.{}", left_code, right_code)
+    };
+    Some((value, unit))
+}
+
+/// Divides two quantities, combining their units with `/`
+///
+/// Dividing quantities with the same unit gives a quantity with unit `'1'`,
+/// and dividing by a `'1'` unit leaves the unit unchanged.
+/// Returns None if either unit is not valid UCUM, is a special unit, or the
+/// divisor is zero.
+pub fn divide_quantities(
+    left_value: Decimal,
+    left_unit: &str,
+    right_value: Decimal,
+    right_unit: &str,
+) -> Option<(Decimal, String)> {
+    let value = left_value.checked_div(right_value)?;
+    if right_unit == "1" {
+        return Some((value, left_unit.to_string()));
+    }
+    let (left_code, right_code) = combinable_codes(left_unit, right_unit)?;
+    let unit = if left_code == right_code {
+        "1".to_string()
+    } else if right_code.contains(['.', '/']) {
+        format!("{}/({})", left_code, right_code)
+    } else {
+        format!("{}/{}", left_code, right_code)
+    };
+    Some((value, unit))
+}
+
+/// Returns the UCUM codes of two units that may appear in a product
+fn combinable_codes<'a>(left_unit: &'a str, right_unit: &'a str) -> Option<(&'a str, &'a str)> {
+    let left_code = ucum_code(left_unit);
+    let right_code = ucum_code(right_unit);
+    let combinable = |code: &str| canonicalize(code).is_ok_and(|unit| !unit.is_special());
+    (combinable(left_code) && combinable(right_code)).then_some((left_code, right_code))
+}
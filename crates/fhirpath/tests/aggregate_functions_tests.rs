@@ -0,0 +1,141 @@
+use chumsky::Parser;
+use helios_fhirpath::evaluator::{EvaluationContext, evaluate};
+use helios_fhirpath::parser::parser;
+use helios_fhirpath_support::{EvaluationError, EvaluationResult};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+// Helper function to parse and evaluate
+fn eval(input: &str) -> Result<EvaluationResult, EvaluationError> {
+    let ctx = EvaluationContext::new_empty_with_default_version();
+    let expr = parser().parse(input).unwrap_or_else(|e| {
+        panic!("Parser error for input '{}': {:?}", input, e);
+    });
+    evaluate(&expr, &ctx, None)
+}
+
+fn decimal(s: &str) -> EvaluationResult {
+    EvaluationResult::decimal(Decimal::from_str(s).unwrap())
+}
+
+fn quantity(value: &str, unit: &str) -> EvaluationResult {
+    EvaluationResult::quantity(Decimal::from_str(value).unwrap(), unit.to_string())
+}
+
+#[test]
+fn test_sum() {
+    assert_eq!(
+        eval("(1 | 2 | 3).sum()").unwrap(),
+        EvaluationResult::integer(6)
+    );
+    assert_eq!(eval("(1 | 2.5).sum()").unwrap(), decimal("3.5"));
+    assert_eq!(
+        eval("(1.toLong() | 2.toLong()).sum() = 3.toLong()").unwrap(),
+        EvaluationResult::boolean(true)
+    );
+    assert_eq!(
+        eval("(1 'mg' | 2.5 'mg').sum()").unwrap(),
+        quantity("3.5", "mg")
+    );
+    assert_eq!(
+        eval("(1 | 2 | 3).sum() = (1 | 2 | 3).aggregate($this + $total, 0)").unwrap(),
+        EvaluationResult::boolean(true)
+    );
+}
+
+#[test]
+fn test_sum_empty() {
+    assert_eq!(eval("{}.sum()").unwrap(), EvaluationResult::integer(0));
+}
+
+#[test]
+fn test_sum_errors() {
+    assert!(matches!(
+        eval("(1 | 'a').sum()"),
+        Err(EvaluationError::TypeError(_))
+    ));
+    assert!(matches!(
+        eval("(1 'mg' | 2 'm').sum()"),
+        Err(EvaluationError::TypeError(_))
+    ));
+    assert!(matches!(
+        eval("(1 | 2).sum(0)"),
+        Err(EvaluationError::InvalidArity(_))
+    ));
+}
+
+#[test]
+fn test_min_max_numbers() {
+    assert_eq!(
+        eval("(3 | 1 | 2).min()").unwrap(),
+        EvaluationResult::integer(1)
+    );
+    assert_eq!(
+        eval("(3 | 1 | 2).max()").unwrap(),
+        EvaluationResult::integer(3)
+    );
+    assert_eq!(eval("(1 | 2.5).max()").unwrap(), decimal("2.5"));
+    assert_eq!(eval("(1 | 2.5).min()").unwrap(), decimal("1"));
+    assert_eq!(
+        eval("(1 'mg' | 3 'mg' | 2 'mg').max()").unwrap(),
+        quantity("3", "mg")
+    );
+}
+
+#[test]
+fn test_min_max_strings_and_dates() {
+    assert_eq!(
+        eval("('b' | 'a' | 'c').min()").unwrap(),
+        EvaluationResult::string("a".to_string())
+    );
+    assert_eq!(
+        eval("('b' | 'a' | 'c').max()").unwrap(),
+        EvaluationResult::string("c".to_string())
+    );
+    assert_eq!(
+        eval("(@2020-01-01 | @2019-06-15 | @2021-03-01).min() = @2019-06-15").unwrap(),
+        EvaluationResult::boolean(true)
+    );
+    assert_eq!(
+        eval("(@2020-01-01T10:00:00Z | @2020-01-01T12:00:00Z).max() = @2020-01-01T12:00:00Z")
+            .unwrap(),
+        EvaluationResult::boolean(true)
+    );
+    assert_eq!(
+        eval("(@T10:00 | @T09:30).min() = @T09:30").unwrap(),
+        EvaluationResult::boolean(true)
+    );
+}
+
+#[test]
+fn test_min_max_empty_and_errors() {
+    assert_eq!(eval("{}.min()").unwrap(), EvaluationResult::Empty);
+    assert_eq!(eval("{}.max()").unwrap(), EvaluationResult::Empty);
+    assert!(matches!(
+        eval("(1 | 'a').min()"),
+        Err(EvaluationError::TypeError(_))
+    ));
+    assert!(matches!(
+        eval("(true | false).max()"),
+        Err(EvaluationError::TypeError(_))
+    ));
+    assert!(matches!(
+        eval("(1 'mg' | 2 'm').max()"),
+        Err(EvaluationError::TypeError(_))
+    ));
+}
+
+#[test]
+fn test_avg() {
+    assert_eq!(eval("(1 | 2 | 3 | 4).avg()").unwrap(), decimal("2.5"));
+    assert_eq!(eval("(5.0 | 7.0).avg()").unwrap(), decimal("6"));
+    assert_eq!(
+        eval("(1 'mg' | 2 'mg').avg()").unwrap(),
+        quantity("1.5", "mg")
+    );
+    assert_eq!(eval("{}.avg()").unwrap(), EvaluationResult::Empty);
+    assert!(matches!(
+        eval("('a' | 'b').avg()"),
+        Err(EvaluationError::TypeError(_))
+    ));
+}
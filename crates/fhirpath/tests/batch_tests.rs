@@ -0,0 +1,164 @@
+use helios_fhir::{FhirResource, r4};
+use helios_fhirpath::{BatchEvaluator, CompiledExpression, EvaluationResult, evaluate_expression};
+use helios_fhirpath_support::EvaluationError;
+use serde_json::json;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+fn patient(id: usize, given: &[&str]) -> FhirResource {
+    let resource: r4::Resource = serde_json::from_value(json!({
+        "resourceType": "Patient",
+        "id": format!("p{}", id),
+        "name": [{ "given": given }],
+    }))
+    .unwrap();
+    FhirResource::R4(Box::new(resource))
+}
+
+fn patients(count: usize) -> Vec<FhirResource> {
+    (0..count).map(|id| patient(id, &["Jim"])).collect()
+}
+
+fn evaluator(expression: &str) -> BatchEvaluator {
+    BatchEvaluator::new(CompiledExpression::compile(expression).unwrap())
+}
+
+#[test]
+fn test_results_in_input_order() {
+    let results = evaluator("Patient.id")
+        .with_threads(4)
+        .evaluate(patients(500));
+
+    assert_eq!(results.len(), 500);
+    for (id, result) in results.into_iter().enumerate() {
+        assert_eq!(result, Ok(EvaluationResult::string(format!("p{}", id))));
+    }
+}
+
+#[test]
+fn test_per_item_errors() {
+    let resources = vec![
+        patient(0, &["Jim"]),
+        patient(1, &["Jim", "Peter"]),
+        patient(2, &[]),
+    ];
+    let results = evaluator("name.given.single()")
+        .with_threads(2)
+        .evaluate(resources);
+
+    assert_eq!(results[0], Ok(EvaluationResult::string("Jim".to_string())));
+    assert!(
+        matches!(
+            results[1],
+            Err(EvaluationError::SingletonEvaluationError(_))
+        ),
+        "{:?}",
+        results[1]
+    );
+    assert_eq!(results[2], Ok(EvaluationResult::Empty));
+}
+
+#[test]
+fn test_panics_are_per_item_errors() {
+    let results = evaluator("Patient.id")
+        .with_threads(2)
+        .with_context_setup(|context| {
+            let id = evaluate_expression("Patient.id", context).unwrap();
+            if id == EvaluationResult::string("p1".to_string()) {
+                panic!("cannot set up p1");
+            }
+        })
+        .evaluate(patients(3));
+
+    assert_eq!(results[0], Ok(EvaluationResult::string("p0".to_string())));
+    match &results[1] {
+        Err(EvaluationError::Other(message)) => {
+            assert!(message.contains("cannot set up p1"), "{}", message)
+        }
+        other => panic!("expected a panic error, got {:?}", other),
+    }
+    assert_eq!(results[2], Ok(EvaluationResult::string("p2".to_string())));
+}
+
+#[test]
+fn test_context_setup() {
+    let contexts = Arc::new(AtomicUsize::new(0));
+    let results = evaluator("name.given.first() + %suffix")
+        .with_threads(3)
+        .with_context_setup({
+            let contexts = Arc::clone(&contexts);
+            move |context| {
+                contexts.fetch_add(1, Ordering::Relaxed);
+                context.set_variable_result("suffix", EvaluationResult::string("!".to_string()));
+            }
+        })
+        .evaluate(patients(10));
+
+    assert_eq!(contexts.load(Ordering::Relaxed), 10);
+    assert!(
+        results
+            .iter()
+            .all(|result| *result == Ok(EvaluationResult::string("Jim!".to_string())))
+    );
+}
+
+#[test]
+fn test_thread_counts() {
+    let single = evaluator("Patient.id & '-' & name.given.count().toString()").with_threads(1);
+    let parallel = single.clone().with_threads(8);
+    assert_eq!(single.threads(), 1);
+    assert_eq!(parallel.threads(), 8);
+    assert_eq!(evaluator("1").with_threads(0).threads(), 1);
+
+    assert_eq!(
+        single.evaluate(patients(50)),
+        parallel.evaluate(patients(50))
+    );
+    assert!(parallel.evaluate(Vec::new()).is_empty());
+}
+
+#[test]
+fn test_lazy_input() {
+    let produced = AtomicUsize::new(0);
+    let resources = (0..20).map(|id| {
+        produced.fetch_add(1, Ordering::Relaxed);
+        patient(id, &["Jim"])
+    });
+
+    let results = evaluator("Patient.id").with_threads(4).evaluate(resources);
+    assert_eq!(produced.load(Ordering::Relaxed), 20);
+    assert_eq!(results[19], Ok(EvaluationResult::string("p19".to_string())));
+}
+
+#[test]
+fn test_chunked_results_in_input_order() {
+    let evaluator = evaluator("Patient.id").with_threads(4);
+    let expected = evaluator.evaluate(patients(25));
+
+    for chunk_size in [0, 1, 7, 25, 100] {
+        let results: Vec<_> = evaluator
+            .evaluate_chunked(patients(25), chunk_size)
+            .collect();
+        assert_eq!(results, expected, "chunk size {}", chunk_size);
+    }
+    assert_eq!(evaluator.evaluate_chunked(Vec::new(), 10).count(), 0);
+}
+
+#[test]
+fn test_chunked_input_is_read_a_chunk_at_a_time() {
+    let produced = AtomicUsize::new(0);
+    let resources = (0..20).map(|id| {
+        produced.fetch_add(1, Ordering::Relaxed);
+        patient(id, &["Jim"])
+    });
+
+    let evaluator = evaluator("Patient.id").with_threads(2);
+    let mut results = evaluator.evaluate_chunked(resources, 8);
+    assert_eq!(
+        results.next(),
+        Some(Ok(EvaluationResult::string("p0".to_string())))
+    );
+    assert_eq!(produced.load(Ordering::Relaxed), 8);
+    assert_eq!(results.count(), 19);
+    assert_eq!(produced.load(Ordering::Relaxed), 20);
+}
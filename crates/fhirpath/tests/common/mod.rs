@@ -0,0 +1,93 @@
+//! Helpers shared by the integration tests
+
+// Each test crate uses only some of the helpers
+#![allow(dead_code)]
+
+use chumsky::Parser;
+use helios_fhir::{FhirResource, FhirVersion};
+use helios_fhirpath::evaluator::evaluate;
+use helios_fhirpath::parser::parser;
+use helios_fhirpath::profile_registry::ProfileRegistry;
+use helios_fhirpath::{EvaluationContext, EvaluationResult};
+use helios_fhirpath_support::EvaluationError;
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+pub fn r4_resource(value: Value) -> FhirResource {
+    let resource: helios_fhir::r4::Resource = serde_json::from_value(value).unwrap();
+    FhirResource::R4(Box::new(resource))
+}
+
+/// A StructureDefinition of a resource type with the given snapshot elements
+pub fn structure_definition(url: &str, type_name: &str, elements: Value) -> Value {
+    json!({
+        "resourceType": "StructureDefinition",
+        "url": url,
+        "name": type_name,
+        "status": "active",
+        "kind": "resource",
+        "abstract": false,
+        "type": type_name,
+        "snapshot": {"element": elements}
+    })
+}
+
+/// A profile constraining the base definition of a resource type
+pub fn profile(url: &str, type_name: &str, elements: Value) -> Value {
+    let mut definition = structure_definition(url, type_name, elements);
+    definition["baseDefinition"] = json!(format!(
+        "http://hl7.org/fhir/StructureDefinition/{}",
+        type_name
+    ));
+    definition["derivation"] = json!("constraint");
+    definition
+}
+
+/// A registry holding the given StructureDefinitions
+pub fn registry(definitions: Vec<Value>) -> ProfileRegistry {
+    let mut registry = ProfileRegistry::new(FhirVersion::R4);
+    for definition in definitions {
+        registry.add_resource(&r4_resource(definition));
+    }
+    registry
+}
+
+/// A context evaluating against `resource` with the profiles of `registry`
+pub fn context(resource: Value, registry: ProfileRegistry) -> EvaluationContext {
+    let mut context = EvaluationContext::new(vec![r4_resource(resource)]);
+    context.set_profile_registry(Arc::new(registry));
+    context
+}
+
+/// A context evaluating against the R4 example Patient
+pub fn patient_context() -> EvaluationContext {
+    let json = std::fs::read_to_string(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/data/r4/input/patient-example.json"
+    ))
+    .unwrap();
+    let resource: helios_fhir::r4::Resource = serde_json::from_str(&json).unwrap();
+    EvaluationContext::new(vec![FhirResource::R4(Box::new(resource))])
+}
+
+pub fn eval(
+    expression: &str,
+    context: &EvaluationContext,
+) -> Result<EvaluationResult, EvaluationError> {
+    let expr = parser().parse(expression).unwrap_or_else(|e| {
+        panic!("Parser error for input '{}': {:?}", expression, e);
+    });
+    evaluate(&expr, context, None)
+}
+
+/// The string values of the items `expression` evaluates to
+pub fn strings(expression: &str, context: &EvaluationContext) -> Vec<String> {
+    let result =
+        eval(expression, context).unwrap_or_else(|e| panic!("'{}' failed: {}", expression, e));
+    let items = match result {
+        EvaluationResult::Collection { items, .. } => items,
+        EvaluationResult::Empty => vec![],
+        single => vec![single],
+    };
+    items.iter().map(|item| item.to_string_value()).collect()
+}
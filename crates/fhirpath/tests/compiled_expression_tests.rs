@@ -0,0 +1,183 @@
+use helios_fhirpath::{
+    CompiledExpression, EvaluationContext, EvaluationResult, ExpressionCache, evaluate_expression,
+};
+use std::sync::Arc;
+
+fn context() -> EvaluationContext {
+    EvaluationContext::new_empty_with_default_version()
+}
+
+#[test]
+fn test_compile_and_evaluate() {
+    let compiled = CompiledExpression::compile("(1 | 2 | 3).where($this > 1).count()").unwrap();
+    assert_eq!(compiled.source(), "(1 | 2 | 3).where($this > 1).count()");
+    assert_eq!(compiled.to_string(), compiled.source());
+
+    // Evaluating repeatedly gives the same result as evaluate_expression
+    let context = context();
+    for _ in 0..3 {
+        assert_eq!(
+            compiled.evaluate(&context).unwrap(),
+            evaluate_expression(compiled.source(), &context).unwrap()
+        );
+    }
+    assert_eq!(
+        compiled.evaluate(&context).unwrap(),
+        EvaluationResult::integer(2)
+    );
+
+    let parsed: CompiledExpression = "'a' & 'b'".parse().unwrap();
+    assert_eq!(
+        parsed.evaluate(&context).unwrap(),
+        EvaluationResult::string("ab".to_string())
+    );
+}
+
+#[test]
+fn test_compile_errors() {
+    for invalid in ["Patient.name.", "1 +", "where(", ""] {
+        let err = CompiledExpression::compile(invalid).unwrap_err();
+        assert!(
+            err.starts_with("Failed to parse FHIRPath expression"),
+            "unexpected error for '{}': {}",
+            invalid,
+            err
+        );
+    }
+    let err = CompiledExpression::compile("Patient.name.)").unwrap_err();
+    assert!(
+        err.ends_with("at line 1, column 14\n  |\n1 | Patient.name.)\n  |              ^"),
+        "{}",
+        err
+    );
+
+    // Evaluation errors name the expression
+    let compiled = CompiledExpression::compile("(1 | 2).single()").unwrap();
+    let err = compiled.evaluate(&context()).unwrap_err();
+    assert!(err.starts_with("Failed to evaluate FHIRPath expression '(1 | 2).single()'"));
+}
+
+#[test]
+fn test_evaluate_with_this() {
+    let compiled = CompiledExpression::compile("$this * 2").unwrap();
+    let context = context();
+    assert_eq!(
+        compiled
+            .evaluate_with_this(&context, Some(&EvaluationResult::integer(21)))
+            .unwrap(),
+        EvaluationResult::integer(42)
+    );
+}
+
+#[test]
+fn test_cache_reuses_compiled_expressions() {
+    let cache = ExpressionCache::new(4);
+    assert!(cache.is_empty());
+
+    let first = cache.get_or_compile("1 + 1").unwrap();
+    let second = cache.get_or_compile("1 + 1").unwrap();
+    assert_eq!(first.expression(), second.expression());
+    assert_eq!(cache.len(), 1);
+    assert!(cache.contains("1 + 1"));
+
+    assert_eq!(
+        cache.evaluate("1 + 1", &context()).unwrap(),
+        EvaluationResult::integer(2)
+    );
+    assert_eq!(cache.len(), 1);
+
+    // Failures are reported but not cached
+    assert!(cache.get_or_compile("1 +").is_err());
+    assert_eq!(cache.len(), 1);
+
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_cache_optimized_expressions() {
+    let cache = ExpressionCache::new(4);
+    let source = "name.where(true).given | ('a' + 'b')";
+
+    let optimized = cache.get_or_compile_optimized(source).unwrap();
+    assert_eq!(optimized.source(), source);
+    assert_eq!(optimized.expression().to_string(), "name.given | 'ab'");
+    let again = cache.get_or_compile_optimized(source).unwrap();
+    assert_eq!(again.expression(), optimized.expression());
+
+    // The plain and optimized forms share one entry
+    let plain = cache.get_or_compile(source).unwrap();
+    assert!(plain.expression().to_string().contains("where(true)"));
+    assert_eq!(cache.len(), 1);
+
+    // Optimizing without caching
+    let cache = ExpressionCache::new(0);
+    let optimized = cache.get_or_compile_optimized("1 + 2").unwrap();
+    assert_eq!(optimized.expression().to_string(), "3");
+    assert!(cache.is_empty());
+    assert!(cache.get_or_compile_optimized("1 +").is_err());
+}
+
+#[test]
+fn test_cache_evicts_least_recently_used() {
+    let cache = ExpressionCache::new(2);
+    cache.get_or_compile("1").unwrap();
+    cache.get_or_compile("2").unwrap();
+    // Touch "1" so that "2" becomes the least recently used entry
+    cache.get_or_compile("1").unwrap();
+    cache.get_or_compile("3").unwrap();
+
+    assert_eq!(cache.len(), 2);
+    assert!(cache.contains("1"));
+    assert!(!cache.contains("2"));
+    assert!(cache.contains("3"));
+}
+
+#[test]
+fn test_cache_with_zero_capacity() {
+    let cache = ExpressionCache::new(0);
+    assert_eq!(
+        cache.evaluate("3 * 3", &context()).unwrap(),
+        EvaluationResult::integer(9)
+    );
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_shared_between_threads() {
+    let compiled = CompiledExpression::compile("$this + 1").unwrap();
+    let cache = Arc::new(ExpressionCache::new(8));
+
+    std::thread::scope(|scope| {
+        for i in 0..4 {
+            let compiled = compiled.clone();
+            let cache = Arc::clone(&cache);
+            scope.spawn(move || {
+                let context = context();
+                let this = EvaluationResult::integer(i);
+                assert_eq!(
+                    compiled.evaluate_with_this(&context, Some(&this)).unwrap(),
+                    EvaluationResult::integer(i + 1)
+                );
+                assert_eq!(
+                    cache.evaluate("'shared'", &context).unwrap(),
+                    EvaluationResult::string("shared".to_string())
+                );
+            });
+        }
+    });
+
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+fn test_global_cache() {
+    let expression = "'global cache test'.length()";
+    assert_eq!(
+        ExpressionCache::global()
+            .evaluate(expression, &context())
+            .unwrap(),
+        EvaluationResult::integer(17)
+    );
+    assert!(ExpressionCache::global().contains(expression));
+}
@@ -0,0 +1,365 @@
+mod common;
+
+use common::{eval, profile, r4_resource, registry};
+use helios_fhir::FhirVersion;
+use helios_fhirpath::profile_registry::ProfileRegistry;
+use helios_fhirpath::{EvaluationContext, EvaluationResult};
+use helios_fhirpath_support::{EvaluationError, IntoEvaluationResult};
+use serde_json::{Value, json};
+
+const PATIENT_PROFILE: &str = "http://example.org/fhir/StructureDefinition/strict-patient";
+const VITALS_PROFILE: &str = "http://example.org/fhir/StructureDefinition/vitals";
+const BIRTHSEX: &str = "http://hl7.org/fhir/us/core/StructureDefinition/us-core-birthsex";
+const CATEGORY: &str = "http://terminology.hl7.org/CodeSystem/observation-category";
+
+fn patient_profile() -> Value {
+    profile(
+        PATIENT_PROFILE,
+        "Patient",
+        json!([
+            {
+                "id": "Patient", "path": "Patient", "min": 0, "max": "*",
+                "constraint": [{
+                    "key": "sp-1",
+                    "severity": "error",
+                    "human": "The name must not repeat the id",
+                    "expression": "name.family.exists() implies name.family != %resource.id"
+                }, {
+                    "key": "sp-2",
+                    "severity": "warning",
+                    "human": "Should have a birth date",
+                    "expression": "birthDate.exists()"
+                }]
+            },
+            {"id": "Patient.extension", "path": "Patient.extension", "min": 0, "max": "*",
+             "type": [{"code": "Extension"}],
+             "slicing": {"discriminator": [{"type": "value", "path": "url"}], "rules": "open"}},
+            {"id": "Patient.extension:birthsex", "path": "Patient.extension", "sliceName": "birthsex",
+             "min": 1, "max": "1", "type": [{"code": "Extension", "profile": [BIRTHSEX]}]},
+            {"id": "Patient.identifier", "path": "Patient.identifier", "min": 1, "max": "*",
+             "type": [{"code": "Identifier"}]},
+            {"id": "Patient.identifier.system", "path": "Patient.identifier.system", "min": 1, "max": "1",
+             "type": [{"code": "uri"}]},
+            {"id": "Patient.active", "path": "Patient.active", "min": 0, "max": "1",
+             "type": [{"code": "boolean"}], "fixedBoolean": true},
+            {"id": "Patient.name", "path": "Patient.name", "min": 0, "max": "1",
+             "type": [{"code": "HumanName"}]},
+            {"id": "Patient.gender", "path": "Patient.gender", "min": 1, "max": "1",
+             "type": [{"code": "code"}]},
+            {"id": "Patient.maritalStatus", "path": "Patient.maritalStatus", "min": 0, "max": "1",
+             "type": [{"code": "CodeableConcept"}],
+             "patternCodeableConcept": {"coding": [{"system": "http://terminology.hl7.org/CodeSystem/v3-MaritalStatus"}]}}
+        ]),
+    )
+}
+
+fn vitals_profile() -> Value {
+    profile(
+        VITALS_PROFILE,
+        "Observation",
+        json!([
+            {"id": "Observation", "path": "Observation", "min": 0, "max": "*"},
+            {"id": "Observation.status", "path": "Observation.status", "min": 1, "max": "1",
+             "type": [{"code": "code"}], "fixedCode": "final"},
+            {"id": "Observation.category", "path": "Observation.category", "min": 1, "max": "*",
+             "type": [{"code": "CodeableConcept"}],
+             "slicing": {"discriminator": [{"type": "pattern", "path": "$this"}], "rules": "open"}},
+            {"id": "Observation.category:VSCat", "path": "Observation.category", "sliceName": "VSCat",
+             "min": 1, "max": "1", "type": [{"code": "CodeableConcept"}],
+             "patternCodeableConcept": {"coding": [{"system": CATEGORY, "code": "vital-signs"}]}},
+            {"id": "Observation.code", "path": "Observation.code", "min": 1, "max": "1",
+             "type": [{"code": "CodeableConcept"}]},
+            {"id": "Observation.value[x]", "path": "Observation.value[x]", "min": 0, "max": "1",
+             "type": [{"code": "Quantity"}]},
+            {"id": "Observation.component", "path": "Observation.component", "min": 0, "max": "*",
+             "type": [{"code": "BackboneElement"}]},
+            {"id": "Observation.component.value[x]", "path": "Observation.component.value[x]",
+             "min": 1, "max": "1", "type": [{"code": "Quantity"}, {"code": "string"}]}
+        ]),
+    )
+}
+
+fn profiles() -> ProfileRegistry {
+    registry(vec![patient_profile(), vitals_profile()])
+}
+
+fn patient() -> Value {
+    json!({
+        "resourceType": "Patient",
+        "id": "p1",
+        "extension": [
+            {"url": BIRTHSEX, "valueCode": "F"},
+            {"url": "http://example.org/other", "valueString": "x"}
+        ],
+        "identifier": [{"system": "http://example.org/mrn", "value": "123"}],
+        "active": true,
+        "name": [{"family": "Chalmers"}],
+        "gender": "female",
+        "maritalStatus": {"coding": [{
+            "system": "http://terminology.hl7.org/CodeSystem/v3-MaritalStatus",
+            "code": "M"
+        }]}
+    })
+}
+
+fn observation() -> Value {
+    json!({
+        "resourceType": "Observation",
+        "status": "final",
+        "category": [
+            {"coding": [{"system": CATEGORY, "code": "vital-signs", "display": "Vital Signs"}]},
+            {"text": "Other"}
+        ],
+        "code": {"coding": [{"system": "http://loinc.org", "code": "29463-7"}]},
+        "valueQuantity": {"value": 70, "unit": "kg"},
+        "component": [{"code": {"text": "note"}, "valueString": "fasting"}]
+    })
+}
+
+/// Applies a change to a copy of a JSON resource
+fn with(mut resource: Value, change: impl FnOnce(&mut Value)) -> Value {
+    change(&mut resource);
+    resource
+}
+
+fn context(resource: Value) -> EvaluationContext {
+    common::context(resource, profiles())
+}
+
+fn conforms(resource: Value, profile: &str) -> bool {
+    let context = context(resource);
+    match eval(&format!("conformsTo('{}')", profile), &context) {
+        Ok(EvaluationResult::Boolean(value, _)) => value,
+        other => panic!("Expected a boolean, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_conforms_to_profile() {
+    assert!(conforms(patient(), PATIENT_PROFILE));
+    assert!(conforms(observation(), VITALS_PROFILE));
+    assert!(!conforms(patient(), VITALS_PROFILE));
+    assert!(conforms(patient(), &format!("{}|1.0.0", PATIENT_PROFILE)));
+}
+
+#[test]
+fn test_cardinality() {
+    assert!(!conforms(
+        with(patient(), |p| p["identifier"] = json!([])),
+        PATIENT_PROFILE
+    ));
+    assert!(!conforms(
+        with(patient(), |p| {
+            p.as_object_mut().unwrap().remove("gender");
+        }),
+        PATIENT_PROFILE
+    ));
+    assert!(!conforms(
+        with(patient(), |p| p["name"] =
+            json!([{"family": "A"}, {"family": "B"}])),
+        PATIENT_PROFILE
+    ));
+    // Counted per parent: each identifier needs a system
+    assert!(!conforms(
+        with(patient(), |p| {
+            p["identifier"] = json!([{"system": "http://example.org/mrn"}, {"value": "456"}])
+        }),
+        PATIENT_PROFILE
+    ));
+}
+
+#[test]
+fn test_types() {
+    assert!(!conforms(
+        with(observation(), |o| {
+            o.as_object_mut().unwrap().remove("valueQuantity");
+            o["valueString"] = json!("heavy");
+        }),
+        VITALS_PROFILE
+    ));
+    assert!(!conforms(
+        with(observation(), |o| {
+            o["component"][0]
+                .as_object_mut()
+                .unwrap()
+                .remove("valueString");
+            o["component"][0]["valueBoolean"] = json!(true);
+        }),
+        VITALS_PROFILE
+    ));
+}
+
+#[test]
+fn test_fixed_and_pattern_values() {
+    assert!(!conforms(
+        with(patient(), |p| p["active"] = json!(false)),
+        PATIENT_PROFILE
+    ));
+    assert!(!conforms(
+        with(observation(), |o| o["status"] = json!("preliminary")),
+        VITALS_PROFILE
+    ));
+    assert!(!conforms(
+        with(patient(), |p| {
+            p["maritalStatus"]["coding"][0]["system"] = json!("http://example.org/other")
+        }),
+        PATIENT_PROFILE
+    ));
+}
+
+#[test]
+fn test_slices() {
+    // The birthsex extension slice is required
+    assert!(!conforms(
+        with(patient(), |p| p["extension"] = json!([])),
+        PATIENT_PROFILE
+    ));
+    // ...and limited to one
+    assert!(!conforms(
+        with(patient(), |p| {
+            p["extension"] = json!([
+                {"url": BIRTHSEX, "valueCode": "F"},
+                {"url": BIRTHSEX, "valueCode": "M"}
+            ])
+        }),
+        PATIENT_PROFILE
+    ));
+    // The vital-signs category must be present
+    assert!(!conforms(
+        with(observation(), |o| o["category"] =
+            json!([{"text": "Other"}])),
+        VITALS_PROFILE
+    ));
+}
+
+#[test]
+fn test_constraints() {
+    assert!(!conforms(
+        with(patient(), |p| p["name"] = json!([{"family": "p1"}])),
+        PATIENT_PROFILE
+    ));
+    // Warnings do not affect conformance
+    assert!(conforms(
+        with(patient(), |p| {
+            p.as_object_mut().unwrap().remove("birthDate");
+        }),
+        PATIENT_PROFILE
+    ));
+}
+
+#[test]
+fn test_check_conformance_issues() {
+    let registry = profiles();
+    let context = EvaluationContext::new_empty(FhirVersion::R4);
+    let resource = r4_resource(with(patient(), |p| {
+        p["active"] = json!(false);
+        p["identifier"] = json!([]);
+    }));
+    let issues = registry
+        .check_conformance(&resource.to_evaluation_result(), PATIENT_PROFILE, &context)
+        .unwrap();
+    let elements: Vec<&str> = issues.iter().map(|issue| issue.element.as_str()).collect();
+    assert_eq!(elements, vec!["Patient.identifier", "Patient.active"]);
+    assert_eq!(
+        issues[0].to_string(),
+        "Patient.identifier: minimum required = 1, but only found 0"
+    );
+}
+
+#[test]
+fn test_base_definitions() {
+    let context = EvaluationContext::new(vec![r4_resource(patient())]);
+    assert_eq!(
+        eval(
+            "conformsTo('http://hl7.org/fhir/StructureDefinition/Patient')",
+            &context
+        )
+        .unwrap(),
+        EvaluationResult::boolean(true)
+    );
+    assert_eq!(
+        eval(
+            "conformsTo('http://hl7.org/fhir/StructureDefinition/Person')",
+            &context
+        )
+        .unwrap(),
+        EvaluationResult::boolean(false)
+    );
+    assert_eq!(
+        eval(
+            "conformsTo('http://hl7.org/fhir/StructureDefinition/DomainResource')",
+            &context
+        )
+        .unwrap(),
+        EvaluationResult::boolean(true)
+    );
+}
+
+#[test]
+fn test_conforms_to_errors_and_empty() {
+    let context = context(patient());
+    assert!(matches!(
+        eval("conformsTo('http://trash')", &context),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        eval("conformsTo()", &context),
+        Err(EvaluationError::InvalidArity(_))
+    ));
+    assert!(matches!(
+        eval(
+            &format!("Patient.combine(Patient).conformsTo('{}')", PATIENT_PROFILE),
+            &context
+        ),
+        Err(EvaluationError::SingletonEvaluationError(_))
+    ));
+    assert_eq!(
+        eval(&format!("{{}}.conformsTo('{}')", PATIENT_PROFILE), &context).unwrap(),
+        EvaluationResult::Empty
+    );
+    assert_eq!(
+        eval("conformsTo({})", &context).unwrap(),
+        EvaluationResult::Empty
+    );
+}
+
+#[test]
+fn test_profile_without_snapshot() {
+    let mut profile = patient_profile();
+    profile.as_object_mut().unwrap().remove("snapshot");
+
+    let context = common::context(patient(), registry(vec![profile]));
+    assert!(matches!(
+        eval(&format!("conformsTo('{}')", PATIENT_PROFILE), &context),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_load_from_files() {
+    let dir = std::env::temp_dir().join(format!("fhirpath-profiles-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("patient.json"), patient_profile().to_string()).unwrap();
+    std::fs::write(
+        dir.join("bundle.json"),
+        json!({
+            "resourceType": "Bundle",
+            "type": "collection",
+            "entry": [{"resource": vitals_profile()}]
+        })
+        .to_string(),
+    )
+    .unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a profile").unwrap();
+
+    let mut registry = ProfileRegistry::new(FhirVersion::R4);
+    assert_eq!(registry.load_directory(&dir).unwrap(), 2);
+    assert!(registry.contains(PATIENT_PROFILE));
+    assert!(registry.contains(VITALS_PROFILE));
+
+    let mut registry = ProfileRegistry::new(FhirVersion::R4);
+    assert_eq!(registry.load_file(dir.join("patient.json")).unwrap(), 1);
+    assert!(registry.load_json("{\"resourceType\": \"Nope\"}").is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,238 @@
+use chumsky::Parser;
+use helios_fhirpath::evaluator::{EvaluationContext, evaluate};
+use helios_fhirpath::parser::parser;
+use helios_fhirpath_support::{EvaluationError, EvaluationResult};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+fn eval(input: &str) -> Result<EvaluationResult, EvaluationError> {
+    let expr = parser().parse(input).unwrap_or_else(|e| {
+        panic!("Parser error for input '{}': {:?}", input, e);
+    });
+    let context = EvaluationContext::new_empty_with_default_version();
+    evaluate(&expr, &context, None)
+}
+
+fn eval_ok(input: &str) -> EvaluationResult {
+    eval(input).unwrap_or_else(|e| panic!("'{}' failed: {}", input, e))
+}
+
+fn assert_integer(input: &str, expected: i64) {
+    assert_eq!(
+        eval_ok(input),
+        EvaluationResult::integer(expected),
+        "for {}",
+        input
+    );
+}
+
+fn assert_empty(input: &str) {
+    assert_eq!(eval_ok(input), EvaluationResult::Empty, "for {}", input);
+}
+
+#[test]
+fn test_date_components() {
+    assert_integer("@2020-03-04.yearOf()", 2020);
+    assert_integer("@2020-03-04.monthOf()", 3);
+    assert_integer("@2020-03-04.dayOf()", 4);
+    assert_integer("@2020-03-04T10:30:15.250+05:30.yearOf()", 2020);
+    assert_integer("@2020-03-04T10:30:15.250+05:30.dayOf()", 4);
+}
+
+#[test]
+fn test_time_components() {
+    assert_integer("@2020-03-04T10:30:15.250.hourOf()", 10);
+    assert_integer("@2020-03-04T10:30:15.250.minuteOf()", 30);
+    assert_integer("@2020-03-04T10:30:15.250.secondOf()", 15);
+    assert_integer("@2020-03-04T10:30:15.250.millisecondOf()", 250);
+    assert_integer("@T08:05:09.5.millisecondOf()", 500);
+    assert_integer("@T08:05.hourOf()", 8);
+    assert_integer("@T08:05.minuteOf()", 5);
+}
+
+#[test]
+fn test_components_respect_precision() {
+    assert_empty("@2020.monthOf()");
+    assert_empty("@2020-03.dayOf()");
+    assert_empty("@2020-03-04T.hourOf()");
+    assert_empty("@2020-03-04T10.minuteOf()");
+    assert_empty("@2020-03-04T10:30.secondOf()");
+    assert_empty("@T10:30:15.millisecondOf()");
+    assert_empty("@2020-03-04T10:30.timezoneOffsetOf()");
+    assert_empty("@2020-03-04T.timeOf()");
+    assert_empty("{}.yearOf()");
+}
+
+#[test]
+fn test_timezone_offset_of() {
+    assert_eq!(
+        eval_ok("@2020-03-04T10:30+05:30.timezoneOffsetOf()"),
+        EvaluationResult::decimal(Decimal::from_str("5.5").unwrap())
+    );
+    assert_eq!(
+        eval_ok("@2020-03-04T10:30-05:00.timezoneOffsetOf()"),
+        EvaluationResult::decimal(Decimal::from(-5))
+    );
+    assert_eq!(
+        eval_ok("@2020-03-04T10:30Z.timezoneOffsetOf()"),
+        EvaluationResult::decimal(Decimal::ZERO)
+    );
+}
+
+#[test]
+fn test_date_of_and_time_of() {
+    assert_eq!(
+        eval_ok("@2020-03-04T10:30:15.250+05:30.dateOf()"),
+        EvaluationResult::date("2020-03-04".to_string())
+    );
+    assert_eq!(
+        eval_ok("@2020-03T.dateOf()"),
+        EvaluationResult::date("2020-03".to_string())
+    );
+    assert_eq!(
+        eval_ok("@2020-03-04T10:30:15.250+05:30.timeOf()"),
+        EvaluationResult::time("10:30:15.250".to_string())
+    );
+    assert_eq!(
+        eval_ok("@2020-03-04T10:30.timeOf()"),
+        EvaluationResult::time("10:30".to_string())
+    );
+    assert_eq!(
+        eval_ok("@2020-03-04T10:30:15.dateOf() = @2020-03-04"),
+        EvaluationResult::boolean(true)
+    );
+}
+
+#[test]
+fn test_component_errors() {
+    assert!(matches!(
+        eval("@T10:30.yearOf()"),
+        Err(EvaluationError::TypeError(_))
+    ));
+    assert!(matches!(
+        eval("@2020-03-04.hourOf()"),
+        Err(EvaluationError::TypeError(_))
+    ));
+    assert!(matches!(
+        eval("'2020-03-04'.yearOf()"),
+        Err(EvaluationError::TypeError(_))
+    ));
+    assert!(matches!(
+        eval("(@2020 | @2021).yearOf()"),
+        Err(EvaluationError::SingletonEvaluationError(_))
+    ));
+    assert!(matches!(
+        eval("@2020.yearOf(1)"),
+        Err(EvaluationError::InvalidArity(_))
+    ));
+}
+
+#[test]
+fn test_duration() {
+    // Whole years of age: the birthday has not yet come round in 2024
+    assert_integer("@1980-06-15.duration(@2024-06-14, 'years')", 43);
+    assert_integer("@1980-06-15.duration(@2024-06-15, 'years')", 44);
+    assert_integer("@2024-01-31.duration(@2024-03-30, 'months')", 1);
+    assert_integer("@2024-01-01.duration(@2024-03-01, 'days')", 60);
+    assert_integer("@2024-01-01.duration(@2024-01-20, 'weeks')", 2);
+    // Length of stay in hours across midnight
+    assert_integer(
+        "@2024-01-01T22:00:00.duration(@2024-01-03T06:30:00, 'hours')",
+        32,
+    );
+    assert_integer("@T10:00.duration(@T12:59, 'minutes')", 179);
+    // Negative when the value is earlier than the input
+    assert_integer("@2024-06-14.duration(@1980-06-15, 'years')", -43);
+    // Singular units are accepted too
+    assert_integer("@2024-01-01.duration(@2024-01-02, 'day')", 1);
+}
+
+#[test]
+fn test_difference_counts_boundaries() {
+    assert_integer("@2020-12-31.difference(@2021-01-01, 'years')", 1);
+    assert_integer("@2020-12-31.duration(@2021-01-01, 'years')", 0);
+    assert_integer("@2024-01-31.difference(@2024-02-01, 'months')", 1);
+    assert_integer("@2024-01-31.duration(@2024-02-01, 'months')", 0);
+    assert_integer(
+        "@2024-01-01T23:59:00.difference(@2024-01-02T00:01:00, 'days')",
+        1,
+    );
+    assert_integer(
+        "@2024-01-01T23:59:00.duration(@2024-01-02T00:01:00, 'days')",
+        0,
+    );
+    assert_integer(
+        "@2024-01-01T10:59:59.difference(@2024-01-01T11:00:00, 'hours')",
+        1,
+    );
+    assert_integer("@2021-01-01.difference(@2020-12-31, 'years')", -1);
+}
+
+#[test]
+fn test_duration_and_difference_precision() {
+    // Neither value is precise enough for the requested unit
+    assert_empty("@2020.duration(@2021-06-01, 'months')");
+    assert_empty("@2020-03.difference(@2020-04-02, 'days')");
+    assert_empty("@2020-03-04.duration(@2020-03-05T10:00, 'hours')");
+    assert_empty("@T10:00.duration(@T11:00, 'days')");
+    // Years only need year precision
+    assert_integer("@2020.difference(@2023-06-01, 'years')", 3);
+    assert_empty("{}.duration(@2020, 'years')");
+    assert_empty("@2020.duration({}, 'years')");
+}
+
+#[test]
+fn test_duration_normalizes_timezones() {
+    // 23:00 at -05:00 is 04:00 UTC the next day
+    assert_integer(
+        "@2024-01-01T23:00:00-05:00.difference(@2024-01-02T01:00:00Z, 'days')",
+        0,
+    );
+    assert_integer(
+        "@2024-01-01T23:00:00-05:00.duration(@2024-01-02T01:00:00Z, 'hours')",
+        -3,
+    );
+}
+
+#[test]
+fn test_duration_errors() {
+    assert!(matches!(
+        eval("@2020-01-01.duration(@2020-02-01, 'fortnights')"),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        eval("@2020-01-01.duration(@2020-02-01, 1)"),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        eval("@2020-01-01.duration(@T10:00, 'hours')"),
+        Err(EvaluationError::TypeError(_))
+    ));
+    assert!(matches!(
+        eval("@2020-01-01.difference(@2020-02-01)"),
+        Err(EvaluationError::InvalidArity(_))
+    ));
+}
+
+#[test]
+fn test_components_of_resource_elements() {
+    let json = std::fs::read_to_string(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/data/r4/input/patient-example.json"
+    ))
+    .unwrap();
+    let resource: helios_fhir::r4::Resource = serde_json::from_str(&json).unwrap();
+    let context = EvaluationContext::new(vec![helios_fhir::FhirResource::R4(Box::new(resource))]);
+    let run = |input: &str| {
+        let expr = parser().parse(input).unwrap();
+        evaluate(&expr, &context, None).unwrap()
+    };
+    assert_eq!(
+        run("Patient.birthDate.yearOf()"),
+        EvaluationResult::integer(1974)
+    );
+    assert_eq!(
+        run("Patient.birthDate.duration(@2024-12-24, 'years')"),
+        EvaluationResult::integer(49)
+    );
+}
@@ -0,0 +1,146 @@
+use helios_fhir::FhirVersion;
+use helios_fhirpath::debug_eval::{
+    EvaluationDebugger, EvaluationStep, describe_value, generate_evaluation_debug,
+};
+use helios_fhirpath::{EvaluationContext, EvaluationResult, evaluate_expression};
+use serde_json::json;
+use std::sync::Arc;
+
+fn debug(expression: &str) -> Vec<EvaluationStep> {
+    let mut context = EvaluationContext::from_json(
+        json!({
+            "resourceType": "Patient",
+            "name": [
+                { "use": "official", "family": "Chalmers", "given": ["Peter", "James"] },
+                { "use": "usual", "given": ["Jim"] }
+            ]
+        }),
+        FhirVersion::R4,
+    );
+    let debugger = Arc::new(EvaluationDebugger::new());
+    context.set_debugger(debugger.clone());
+    let _ = evaluate_expression(expression, &context);
+    debugger.take()
+}
+
+fn texts<'a>(steps: &[EvaluationStep], source: &'a str) -> Vec<&'a str> {
+    steps.iter().map(|step| step.source_text(source)).collect()
+}
+
+#[test]
+fn test_steps_form_the_evaluation_tree() {
+    let expression = "Patient.name.where(use = 'official').given";
+    let steps = debug(expression);
+
+    assert_eq!(steps[0].source_text(expression), expression);
+    assert_eq!(steps[0].node_type, "Invocation");
+    assert_eq!((steps[0].parent, steps[0].depth), (None, 0));
+    assert_eq!(
+        steps[0].result.as_ref().unwrap().to_string_value(),
+        "[Peter, James]"
+    );
+    for step in &steps[1..] {
+        let parent = &steps[step.parent.unwrap()];
+        assert_eq!(step.depth, parent.depth + 1);
+    }
+
+    // The criteria is evaluated once for each name, with the name as its focus
+    let where_index = texts(&steps, expression)
+        .iter()
+        .position(|text| *text == "Patient.name.where(use = 'official')")
+        .unwrap();
+    let criteria: Vec<_> = steps
+        .iter()
+        .filter(|step| step.source_text(expression) == "use = 'official'")
+        .collect();
+    assert_eq!(criteria.len(), 2);
+    for step in &criteria {
+        assert_eq!(step.parent, Some(where_index));
+        assert_eq!(step.focus, "HumanName");
+    }
+    assert_eq!(criteria[0].result, Ok(EvaluationResult::boolean(true)));
+    assert_eq!(criteria[1].result, Ok(EvaluationResult::boolean(false)));
+}
+
+#[test]
+fn test_errors_are_recorded() {
+    let expression = "Patient.name.given.single()";
+    let steps = debug(expression);
+    assert!(steps[0].result.is_err());
+    assert_eq!(
+        steps[1].result.as_ref().unwrap().to_string_value(),
+        "[Peter, James, Jim]"
+    );
+}
+
+#[test]
+fn test_aggregate_steps_are_recorded() {
+    let expression = "(1 | 2 | 3).aggregate($total + $this, 0)";
+    let steps = debug(expression);
+    let totals: Vec<_> = steps
+        .iter()
+        .filter(|step| step.source_text(expression) == "$total + $this")
+        .map(|step| step.result.clone().unwrap())
+        .collect();
+    assert_eq!(
+        totals,
+        vec![
+            EvaluationResult::integer(1),
+            EvaluationResult::integer(3),
+            EvaluationResult::integer(6)
+        ]
+    );
+}
+
+#[test]
+fn test_max_steps() {
+    let mut context = EvaluationContext::new_empty_with_default_version();
+    let debugger = Arc::new(EvaluationDebugger::with_max_steps(2));
+    context.set_debugger(debugger.clone());
+    let expression = "(1 | 2).count() + 1";
+    let result = evaluate_expression(expression, &context).unwrap();
+    assert_eq!(result, EvaluationResult::integer(3));
+
+    let steps = debugger.steps();
+    assert_eq!(
+        texts(&steps, expression),
+        vec![expression, "(1 | 2).count()"]
+    );
+    assert!(steps[1].result.is_ok());
+    assert!(debugger.dropped() > 0);
+}
+
+#[test]
+fn test_large_focus_is_summarized() {
+    let expression = "(1 | 2 | 3 | 4 | 5 | 6 | 7 | 8 | 9 | 10 | 11 | 12).select($this)";
+    let steps = debug(expression);
+    let select = steps
+        .iter()
+        .find(|step| step.source_text(expression) == "$this")
+        .unwrap();
+    assert_eq!(select.focus, "1");
+    assert_eq!(steps[0].focus, "Patient");
+    assert_eq!(
+        describe_value(steps[0].result.as_ref().unwrap()),
+        "[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, ... 2 more]"
+    );
+}
+
+#[test]
+fn test_generate_evaluation_debug() {
+    let expression = "name.given.first()";
+    let text = generate_evaluation_debug(&debug(expression), expression);
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(
+        lines[0].starts_with("name.given.first() : Patient -> 'Peter' ("),
+        "{}",
+        text
+    );
+    assert!(
+        lines[1].starts_with("  name.given : Patient -> ['Peter', 'James', 'Jim'] ("),
+        "{}",
+        text
+    );
+    assert!(lines[2].starts_with("    name : Patient -> "), "{}", text);
+}
@@ -0,0 +1,194 @@
+mod common;
+
+use common::{eval, patient_context, strings};
+use helios_fhirpath::evaluator::EvaluationContext;
+use helios_fhirpath_support::EvaluationError;
+
+#[test]
+fn test_define_variable() {
+    let ctx = patient_context();
+    assert_eq!(
+        strings("defineVariable('v1', 'value1').select(%v1)", &ctx),
+        vec!["value1"]
+    );
+    assert_eq!(
+        strings("defineVariable('n1', name.first()).select(%n1.given)", &ctx),
+        vec!["Peter", "James"]
+    );
+    assert_eq!(
+        strings(
+            "defineVariable('n1', name.first()).select(%n1.given).first()",
+            &ctx
+        ),
+        vec!["Peter"]
+    );
+    assert_eq!(
+        strings(
+            "defineVariable('n1', name.first()).select(id & '-' & %n1.given.join('|'))",
+            &ctx
+        ),
+        vec!["example-Peter|James"]
+    );
+}
+
+#[test]
+fn test_define_variable_without_expression() {
+    let ctx = patient_context();
+    // Without an expression the variable holds the input, which is returned unchanged
+    assert_eq!(
+        strings("name.given.defineVariable('g').count()", &ctx),
+        vec!["5"]
+    );
+    assert_eq!(
+        strings(
+            "name.defineVariable('names').first().given.where(%names.count() = 3)",
+            &ctx
+        ),
+        vec!["Peter", "James"]
+    );
+}
+
+#[test]
+fn test_define_variable_chained() {
+    let ctx = patient_context();
+    assert_eq!(
+        strings(
+            "defineVariable('a', 'x').defineVariable('b', %a & 'y').select(%a & %b)",
+            &ctx
+        ),
+        vec!["xxy"]
+    );
+    assert_eq!(
+        strings(
+            "defineVariable('n1', name.first()).name.where(given.first() = %n1.given.first()).use",
+            &ctx
+        ),
+        vec!["official", "maiden"]
+    );
+    // Visible through indexers and function arguments later in the chain
+    assert_eq!(
+        strings(
+            "name.defineVariable('all')[1].select(%all.count() & ' ' & given)",
+            &ctx
+        ),
+        vec!["3 Jim"]
+    );
+}
+
+#[test]
+fn test_define_variable_scopes() {
+    let ctx = patient_context();
+    // Each side of a union has its own scope, so the same name can be reused
+    assert_eq!(
+        strings(
+            "defineVariable('n1', name.first()).select(%n1.given) | defineVariable('n1', name.skip(1).first()).select(%n1.given)",
+            &ctx
+        ),
+        vec!["Peter", "James", "Jim"]
+    );
+    assert_eq!(
+        strings(
+            "defineVariable('n1', name.first()).where(active.not()) | defineVariable('n1', name.skip(1).first()).select(%n1.given)",
+            &ctx
+        ),
+        vec!["Jim"]
+    );
+    // Variables defined inside a function argument are visible there only
+    assert_eq!(
+        strings(
+            "defineVariable('root', 'r1-').select(defineVariable('v1', 'v1').defineVariable('v2', 'v2').select(%v1 | %v2)).select(%root & $this)",
+            &ctx
+        ),
+        vec!["r1-v1", "r1-v2"]
+    );
+    // ...and are defined afresh for each item
+    assert_eq!(
+        strings(
+            "name.select(defineVariable('g', given.first()).select(%g))",
+            &ctx
+        ),
+        vec!["Peter", "Jim", "Peter"]
+    );
+}
+
+#[test]
+fn test_define_variable_out_of_scope() {
+    let ctx = patient_context();
+    assert!(matches!(
+        eval(
+            "defineVariable('n1', name.first()).active | defineVariable('n2', name.skip(1).first()).select(%n1.given)",
+            &ctx
+        ),
+        Err(EvaluationError::UndefinedVariable(_))
+    ));
+    assert!(matches!(
+        eval(
+            "defineVariable('root', 'r1-').select(defineVariable('v1', 'v1').select(%v1)).select(%root & $this & %v1)",
+            &ctx
+        ),
+        Err(EvaluationError::UndefinedVariable(_))
+    ));
+    assert!(matches!(
+        eval("defineVariable('n1', 'x').exists() and %n1 = 'x'", &ctx),
+        Err(EvaluationError::UndefinedVariable(_))
+    ));
+}
+
+#[test]
+fn test_define_variable_redefinition() {
+    let ctx = patient_context();
+    assert!(matches!(
+        eval(
+            "defineVariable('v1', 'a').defineVariable('v1', 'b').select(%v1)",
+            &ctx
+        ),
+        Err(EvaluationError::SemanticError(_))
+    ));
+    assert!(matches!(
+        eval(
+            "defineVariable('v1', 'a').select(defineVariable('v1', 'b'))",
+            &ctx
+        ),
+        Err(EvaluationError::SemanticError(_))
+    ));
+    assert!(matches!(
+        eval("defineVariable('context', 'oops')", &ctx),
+        Err(EvaluationError::SemanticError(_))
+    ));
+
+    let mut ctx = patient_context();
+    ctx.set_variable("external", "value".to_string());
+    assert!(matches!(
+        eval("defineVariable('external', 'other')", &ctx),
+        Err(EvaluationError::SemanticError(_))
+    ));
+}
+
+#[test]
+fn test_define_variable_errors() {
+    let ctx = patient_context();
+    assert!(matches!(
+        eval("defineVariable()", &ctx),
+        Err(EvaluationError::InvalidArity(_))
+    ));
+    assert!(matches!(
+        eval("defineVariable('a', 1, 2)", &ctx),
+        Err(EvaluationError::InvalidArity(_))
+    ));
+    assert!(matches!(
+        eval("defineVariable(1, 2)", &ctx),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_define_variable_in_aggregate() {
+    let ctx = EvaluationContext::new_empty_with_default_version();
+    assert_eq!(
+        strings(
+            "(1 | 2 | 3).defineVariable('step', 10).aggregate($total + $this * %step, 0)",
+            &ctx
+        ),
+        vec!["60"]
+    );
+}
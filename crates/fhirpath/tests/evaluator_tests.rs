@@ -3782,10 +3782,10 @@ fn test_resource_simple_field_access() {
         eval("active", &context).unwrap(),
         EvaluationResult::boolean(true)
     ); // Add unwrap
-    // Accessing 'birthDate' should now return the primitive string directly
+    // Accessing 'birthDate' should return a typed Date keeping its lexical form
     assert_eq!(
         eval("birthDate", &context).unwrap(), // Add unwrap
-        EvaluationResult::string("1980-05-15".to_string())
+        EvaluationResult::date("1980-05-15".to_string())
     );
     let context_result = eval("%context", &context).unwrap(); // Add unwrap
     if let EvaluationResult::Object {
@@ -0,0 +1,347 @@
+mod common;
+
+use common::{eval, r4_resource, registry, structure_definition};
+use helios_fhirpath::{EvaluationContext, EvaluationResult};
+use helios_fhirpath_support::EvaluationError;
+use serde_json::{Value, json};
+
+const CORE: &str = "http://hl7.org/fhir/StructureDefinition/";
+const VITALS_PROFILE: &str = "http://example.org/fhir/StructureDefinition/vitals";
+const CATEGORY: &str = "http://terminology.hl7.org/CodeSystem/observation-category";
+const MODIFIER: &str = "http://example.org/fhir/StructureDefinition/not-really";
+
+/// Cut-down versions of the base definitions
+fn core_definitions() -> Vec<Value> {
+    vec![
+        structure_definition(
+            &format!("{}Patient", CORE),
+            "Patient",
+            json!([
+                {"id": "Patient", "path": "Patient", "min": 0, "max": "*"},
+                {"id": "Patient.name", "path": "Patient.name", "min": 0, "max": "*",
+                 "type": [{"code": "HumanName"}]},
+                {"id": "Patient.birthDate", "path": "Patient.birthDate", "min": 0, "max": "1",
+                 "type": [{"code": "date"}]},
+                {"id": "Patient.contact", "path": "Patient.contact", "min": 0, "max": "*",
+                 "type": [{"code": "BackboneElement"}]},
+                {"id": "Patient.contact.name", "path": "Patient.contact.name", "min": 0, "max": "1",
+                 "type": [{"code": "HumanName"}]}
+            ]),
+        ),
+        structure_definition(
+            &format!("{}HumanName", CORE),
+            "HumanName",
+            json!([
+                {"id": "HumanName", "path": "HumanName", "min": 0, "max": "*"},
+                {"id": "HumanName.family", "path": "HumanName.family", "min": 0, "max": "1",
+                 "type": [{"code": "string"}]},
+                {"id": "HumanName.given", "path": "HumanName.given", "min": 0, "max": "*",
+                 "type": [{"code": "string"}]}
+            ]),
+        ),
+        structure_definition(
+            &format!("{}Observation", CORE),
+            "Observation",
+            json!([
+                {"id": "Observation", "path": "Observation", "min": 0, "max": "*"},
+                {"id": "Observation.category", "path": "Observation.category", "min": 0, "max": "*",
+                 "type": [{"code": "CodeableConcept"}]},
+                {"id": "Observation.value[x]", "path": "Observation.value[x]", "min": 0, "max": "1",
+                 "type": [{"code": "Quantity"}, {"code": "string"}]}
+            ]),
+        ),
+        structure_definition(
+            &format!("{}Quantity", CORE),
+            "Quantity",
+            json!([
+                {"id": "Quantity", "path": "Quantity", "min": 0, "max": "*"},
+                {"id": "Quantity.value", "path": "Quantity.value", "min": 0, "max": "1",
+                 "type": [{"code": "decimal"}]}
+            ]),
+        ),
+        structure_definition(
+            VITALS_PROFILE,
+            "Observation",
+            json!([
+                {"id": "Observation", "path": "Observation", "min": 0, "max": "*"},
+                {"id": "Observation.category", "path": "Observation.category", "min": 1, "max": "*",
+                 "type": [{"code": "CodeableConcept"}],
+                 "slicing": {"discriminator": [{"type": "pattern", "path": "$this"}], "rules": "open"}},
+                {"id": "Observation.category:VSCat", "path": "Observation.category", "sliceName": "VSCat",
+                 "min": 1, "max": "1", "type": [{"code": "CodeableConcept"}],
+                 "patternCodeableConcept": {"coding": [{"system": CATEGORY, "code": "vital-signs"}]}},
+                {"id": "Observation.component", "path": "Observation.component", "min": 0, "max": "*",
+                 "type": [{"code": "BackboneElement"}],
+                 "slicing": {"discriminator": [{"type": "profile", "path": "$this"}], "rules": "open"}},
+                {"id": "Observation.component:note", "path": "Observation.component", "sliceName": "note",
+                 "min": 0, "max": "1", "type": [{"code": "BackboneElement"}]},
+                {"id": "Observation.component:note.value[x]", "path": "Observation.component.value[x]",
+                 "min": 1, "max": "1", "type": [{"code": "string"}]}
+            ]),
+        ),
+    ]
+}
+
+fn patient() -> Value {
+    json!({
+        "resourceType": "Patient",
+        "id": "p1",
+        "text": {
+            "status": "generated",
+            "div": "<div xmlns=\"http://www.w3.org/1999/xhtml\"><p>Peter <b>Chalmers</b></p></div>"
+        },
+        "modifierExtension": [{"url": MODIFIER, "valueBoolean": true}],
+        "active": true,
+        "name": [{"family": "Chalmers", "given": ["Peter", "James"]}],
+        "gender": "male",
+        "birthDate": "1974-12-25",
+        "_deceasedBoolean": {"extension": [{"url": "http://example.org/unknown", "valueString": "?"}]},
+        "contact": [{"name": {"family": "du Marché"}}]
+    })
+}
+
+fn observation() -> Value {
+    json!({
+        "resourceType": "Observation",
+        "status": "final",
+        "category": [
+            {"coding": [{"system": CATEGORY, "code": "vital-signs"}]},
+            {"text": "Other"}
+        ],
+        "code": {"text": "Weight"},
+        "valueQuantity": {"value": 70, "unit": "kg"},
+        "component": [
+            {"code": {"text": "note"}, "valueString": "fasting"},
+            {"code": {"text": "count"}, "valueQuantity": {"value": 3}}
+        ]
+    })
+}
+
+fn context(resource: Value) -> EvaluationContext {
+    common::context(resource, registry(core_definitions()))
+}
+
+fn eval_ok(expression: &str, context: &EvaluationContext) -> EvaluationResult {
+    eval(expression, context).unwrap_or_else(|e| panic!("'{}' failed: {}", expression, e))
+}
+
+fn assert_true(expression: &str, context: &EvaluationContext) {
+    assert_eq!(
+        eval_ok(expression, context),
+        EvaluationResult::boolean(true),
+        "for {}",
+        expression
+    );
+}
+
+fn assert_false(expression: &str, context: &EvaluationContext) {
+    assert_eq!(
+        eval_ok(expression, context),
+        EvaluationResult::boolean(false),
+        "for {}",
+        expression
+    );
+}
+
+#[test]
+fn test_has_value() {
+    let context = context(patient());
+    assert_true("Patient.birthDate.hasValue()", &context);
+    assert_true("Patient.name.family.hasValue()", &context);
+    // Only an extension, no value (as used by ext-1)
+    assert_false("Patient.deceased.hasValue()", &context);
+    assert_false("Patient.name.hasValue()", &context);
+    assert_false("Patient.name.given.hasValue()", &context);
+    assert_false("Patient.multipleBirth.hasValue()", &context);
+}
+
+#[test]
+fn test_get_value() {
+    let context = context(patient());
+    assert_true("Patient.gender.getValue() = 'male'", &context);
+    assert_true("Patient.gender.getValue() is System.String", &context);
+    assert_false("Patient.gender.getValue() is FHIR.code", &context);
+    assert_true("Patient.active.getValue() is System.Boolean", &context);
+    assert_true("Patient.birthDate.getValue() = @1974-12-25", &context);
+    assert_eq!(
+        eval_ok("Patient.deceased.getValue()", &context),
+        EvaluationResult::Empty
+    );
+    assert_eq!(
+        eval_ok("Patient.name.getValue()", &context),
+        EvaluationResult::Empty
+    );
+}
+
+#[test]
+fn test_html_checks() {
+    let context = context(patient());
+    assert_true("Patient.text.div.htmlChecks()", &context);
+
+    let check = |div: &str| {
+        let expression = format!("'{}'.htmlChecks()", div.replace('\'', "\\'"));
+        match eval_ok(&expression, &context) {
+            EvaluationResult::Boolean(value, _) => value,
+            other => panic!("Expected a boolean for {}, got {:?}", div, other),
+        }
+    };
+    let div = |body: &str| format!("<div xmlns=\"http://www.w3.org/1999/xhtml\">{}</div>", body);
+
+    assert!(check(&div(
+        "<table><tr><td class=\"x\">1</td></tr></table>"
+    )));
+    assert!(check(&div("<a href=\"http://example.org\">link</a>")));
+    assert!(check(&div("<img src=\"#photo\" alt=\"\"/>")));
+    assert!(check(&div("<p xml:lang=\"en\">text</p>")));
+    // Scripts, forms and event handlers are not allowed
+    assert!(!check(&div("<script>alert(1)</script>text")));
+    assert!(!check(&div("<form><input/></form>")));
+    assert!(!check(&div("<p onclick=\"alert(1)\">text</p>")));
+    assert!(!check(&div("<a href=\"javascript:alert(1)\">text</a>")));
+    // The narrative needs some content (txt-2)
+    assert!(!check(&div("  <p> </p> ")));
+    // The root must be an XHTML div, and the fragment well formed
+    assert!(!check("<div>text</div>"));
+    assert!(!check("<p xmlns=\"http://www.w3.org/1999/xhtml\">text</p>"));
+    assert!(!check(&div("<p>text")));
+
+    assert_eq!(
+        eval_ok("{}.htmlChecks()", &context),
+        EvaluationResult::Empty
+    );
+    assert!(matches!(
+        eval("1.htmlChecks()", &context),
+        Err(EvaluationError::TypeError(_))
+    ));
+}
+
+#[test]
+fn test_check_modifiers() {
+    let context = context(patient());
+    assert_true(
+        &format!("Patient.checkModifiers('{}').id = 'p1'", MODIFIER),
+        &context,
+    );
+    assert_true(
+        &format!(
+            "Patient.checkModifiers('http://example.org/a,{}').exists()",
+            MODIFIER
+        ),
+        &context,
+    );
+    assert_true(
+        &format!(
+            "Patient.checkModifiers('http://example.org/a' | '{}').exists()",
+            MODIFIER
+        ),
+        &context,
+    );
+    // Elements without modifier extensions always pass
+    assert_true("Patient.name.checkModifiers({}).exists()", &context);
+    assert!(matches!(
+        eval("Patient.checkModifiers('http://example.org/a')", &context),
+        Err(EvaluationError::InvalidOperation(_))
+    ));
+    assert!(matches!(
+        eval("Patient.checkModifiers({})", &context),
+        Err(EvaluationError::InvalidOperation(_))
+    ));
+}
+
+#[test]
+fn test_element_definition() {
+    let context = context(patient());
+    assert_true("Patient.elementDefinition().path = 'Patient'", &context);
+    assert_true(
+        "Patient.name.elementDefinition().path = 'Patient.name'",
+        &context,
+    );
+    assert_true(
+        "Patient.name.elementDefinition().type.code = 'HumanName'",
+        &context,
+    );
+    // Data type elements come from the data type's definition
+    assert_true(
+        "Patient.name.family.elementDefinition().path = 'HumanName.family'",
+        &context,
+    );
+    assert_true(
+        "Patient.name.given.elementDefinition().path.distinct() = 'HumanName.given'",
+        &context,
+    );
+    assert_true(
+        "Patient.contact.name.family.elementDefinition().path = 'HumanName.family'",
+        &context,
+    );
+    assert_true(
+        "Patient.contact.elementDefinition().path = 'Patient.contact'",
+        &context,
+    );
+    // Elements without a registered definition are skipped
+    assert_true("Patient.gender.elementDefinition().empty()", &context);
+
+    let context = self::context(observation());
+    assert_true(
+        "Observation.value.elementDefinition().id = 'Observation.value[x]'",
+        &context,
+    );
+    assert_true(
+        "Observation.value.value.elementDefinition().path = 'Quantity.value'",
+        &context,
+    );
+
+    // Without a registry there are no definitions
+    let context = EvaluationContext::new(vec![r4_resource(patient())]);
+    assert_true("Patient.name.elementDefinition().empty()", &context);
+}
+
+#[test]
+fn test_slice() {
+    let context = context(observation());
+    let slice =
+        |name: &str, path: &str| format!("{}.slice('{}', '{}')", path, VITALS_PROFILE, name);
+
+    assert_true(
+        &format!(
+            "{}.coding.code = 'vital-signs'",
+            slice("VSCat", "Observation.category")
+        ),
+        &context,
+    );
+    assert_true(
+        &format!("{}.count() = 1", slice("VSCat", "Observation.category")),
+        &context,
+    );
+    // The profile discriminator is not supported, so members must conform to the slice
+    assert_true(
+        &format!(
+            "{}.value = 'fasting'",
+            slice("note", "Observation.component")
+        ),
+        &context,
+    );
+    assert_true(
+        &format!("{}.count() = 1", slice("note", "Observation.component")),
+        &context,
+    );
+    assert_true(
+        &format!("{}.empty()", slice("VSCat", "Observation.code")),
+        &context,
+    );
+
+    assert!(matches!(
+        eval(&slice("Missing", "Observation.category"), &context),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        eval(
+            "Observation.category.slice('http://example.org/unknown', 'VSCat')",
+            &context
+        ),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        eval("Observation.category.slice('a')", &context),
+        Err(EvaluationError::InvalidArity(_))
+    ));
+}
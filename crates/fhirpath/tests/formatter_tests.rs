@@ -0,0 +1,193 @@
+use helios_fhirpath::formatter::{
+    FormatOptions, format_expression, format_expression_with, format_source,
+};
+use helios_fhirpath::parser::{Expression, Literal, Term, parse};
+use roxmltree::{Document, Node};
+use rust_decimal::Decimal;
+use std::fs;
+use std::path::PathBuf;
+
+fn format(source: &str) -> String {
+    format_source(source, &FormatOptions::new()).unwrap()
+}
+
+/// Formats `source` with `options` and checks that the output parses back to
+/// the same tree and formats to itself
+fn assert_round_trip(source: &str, options: &FormatOptions) {
+    let parsed = parse(source).unwrap_or_else(|e| panic!("{}: {}", source, e));
+    let formatted = format_expression_with(&parsed, options);
+    let reparsed = parse(&formatted)
+        .unwrap_or_else(|e| panic!("'{}' formatted as '{}': {}", source, formatted, e));
+    assert_eq!(
+        reparsed.without_spans(),
+        parsed.without_spans(),
+        "'{}' formatted as '{}'",
+        source,
+        formatted
+    );
+    assert_eq!(format_expression_with(&reparsed, options), formatted);
+}
+
+#[test]
+fn test_canonical_spacing() {
+    assert_eq!(format("Patient . name . given"), "Patient.name.given");
+    assert_eq!(
+        format("Patient.name.where(use='official'and given.exists( ))"),
+        "Patient.name.where(use = 'official' and given.exists())"
+    );
+    assert_eq!(format("substring( 1 ,2 )"), "substring(1, 2)");
+    assert_eq!(format("name[ 0 ].given"), "name[0].given");
+    assert_eq!(format("value  is   Quantity"), "value is Quantity");
+    assert_eq!(format("value as FHIR.Quantity"), "value as FHIR.Quantity");
+    assert_eq!(format("- 5 + + x"), "-5 + +x");
+    assert_eq!(format("$this.length()>$index"), "$this.length() > $index");
+    assert_eq!(format("5 div 2 mod 3"), "5 div 2 mod 3");
+    assert_eq!(format("a|b|c"), "a | b | c");
+    assert_eq!(format("a  xor  b implies c"), "a xor b implies c");
+}
+
+#[test]
+fn test_minimal_parentheses() {
+    assert_eq!(format("((1 + 2)) * (3)"), "(1 + 2) * 3");
+    assert_eq!(format("(1 * 2) + 3"), "1 * 2 + 3");
+    assert_eq!(format("1 - (2 - 3)"), "1 - (2 - 3)");
+    assert_eq!(format("(1 - 2) - 3"), "1 - 2 - 3");
+    assert_eq!(format("(a or b) and c"), "(a or b) and c");
+    assert_eq!(format("a or (b and c)"), "a or b and c");
+    assert_eq!(format("(a | b).count()"), "(a | b).count()");
+    assert_eq!(format("(-1).abs()"), "(-1).abs()");
+    assert_eq!(format("-(1).abs()"), "-1.abs()");
+    assert_eq!(format("(a is Integer) = true"), "a is Integer = true");
+    assert_eq!(format("(a | b) is Integer"), "a | b is Integer");
+    assert_eq!(format("(a = b) is Boolean"), "(a = b) is Boolean");
+    assert_eq!(format("a implies (b implies c)"), "a implies (b implies c)");
+}
+
+#[test]
+fn test_literals() {
+    assert_eq!(format("{}"), "{}");
+    assert_eq!(format("true and false"), "true and false");
+    assert_eq!(format("1.50"), "1.50");
+    assert_eq!(
+        format("@2015-02-04T14:34:28.123+09:00"),
+        "@2015-02-04T14:34:28.123+09:00"
+    );
+    assert_eq!(format("@2015T"), "@2015T");
+    assert_eq!(format("@T14:34"), "@T14:34");
+    assert_eq!(format("@2015-02.toString()"), "@2015-02.toString()");
+    assert_eq!(format("4 days"), "4 days");
+    assert_eq!(format("4.5 'mg'"), "4.5 'mg'");
+    assert_eq!(format("1 'day'"), "1 day");
+    assert_eq!(format("'it\\'s'"), "'it\\'s'");
+    assert_eq!(format("'tab\\there\\u0001'"), "'tab\\there\\u0001'");
+    assert_eq!(format("'\\u00e9\\/'"), "'é/'");
+}
+
+#[test]
+fn test_identifiers() {
+    assert_eq!(format("`given`"), "given");
+    assert_eq!(format("Patient.`given name`"), "Patient.`given name`");
+    assert_eq!(format("`true`.value"), "`true`.value");
+    assert_eq!(format("`a\\`b`"), "`a\\`b`");
+    assert_eq!(
+        format("%`vs-administrative-gender`"),
+        "%`vs-administrative-gender`"
+    );
+    assert_eq!(format("%'ext-name'"), "%`ext-name`");
+    assert_eq!(format("%resource"), "%resource");
+    assert_eq!(
+        format("value is `System`.`Integer`"),
+        "value is System.Integer"
+    );
+}
+
+#[test]
+fn test_constructed_trees() {
+    let literal = |literal| Expression::Term(Term::Literal(literal, 0..0), 0..0);
+    let invoke = |base, name: &str| {
+        Expression::Invocation(
+            Box::new(base),
+            helios_fhirpath::parser::Invocation::Function(name.to_string(), vec![], 0..0),
+            0..0,
+        )
+    };
+
+    assert_eq!(
+        format_expression(&literal(Literal::Number(Decimal::from(3)))),
+        "3.0"
+    );
+    assert_eq!(
+        format_expression(&invoke(literal(Literal::Integer(-2)), "abs")),
+        "(-2).abs()"
+    );
+    assert_eq!(literal(Literal::String("x".into())).to_string(), "'x'");
+}
+
+#[test]
+fn test_line_breaking() {
+    let options = FormatOptions::new().with_max_width(40);
+    assert_eq!(
+        format_source("Patient.name.given", &options).unwrap(),
+        "Patient.name.given"
+    );
+    assert_eq!(
+        format_source(
+            "Patient.name.where(use = 'official').given.first()",
+            &options
+        )
+        .unwrap(),
+        "Patient.name\n  .where(use = 'official')\n  .given\n  .first()"
+    );
+    assert_eq!(
+        format_source(
+            "Patient.telecom.where(system = 'phone' and use = 'mobile' and rank = 1)[0].value",
+            &options
+        )
+        .unwrap(),
+        "Patient.telecom\n  .where(\n    system = 'phone'\n      and use = 'mobile'\n      and rank = 1\n  )[0]\n  .value"
+    );
+    assert_eq!(
+        format_source(
+            "name.exists() and birthDate.exists() or deceased.exists()",
+            &options.clone().with_indent_width(4)
+        )
+        .unwrap(),
+        "name.exists() and birthDate.exists()\n    or deceased.exists()"
+    );
+}
+
+#[test]
+fn test_round_trip_of_official_tests() {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests/data/r4/tests-fhir-r4.xml");
+    let contents = fs::read_to_string(&path).expect("Failed to read test file");
+    let doc = Document::parse_with_options(
+        &contents,
+        roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        },
+    )
+    .expect("XML parsing failed");
+
+    let mut expressions = Vec::new();
+    collect_expressions(&doc.root_element(), &mut expressions);
+    assert!(!expressions.is_empty(), "No test expressions found");
+
+    let narrow = FormatOptions::new().with_max_width(20);
+    for expression in expressions.iter().filter(|e| parse(e).is_ok()) {
+        assert_round_trip(expression, &FormatOptions::new());
+        assert_round_trip(expression, &narrow);
+    }
+}
+
+fn collect_expressions(node: &Node, expressions: &mut Vec<String>) {
+    if node.has_tag_name("expression")
+        && let Some(text) = node.text()
+    {
+        expressions.push(text.to_string());
+    }
+    for child in node.children().filter(|child| child.is_element()) {
+        collect_expressions(&child, expressions);
+    }
+}
@@ -0,0 +1,247 @@
+use helios_fhir::FhirVersion;
+use helios_fhirpath::function_registry::{
+    ArgumentEvaluation, CustomFunction, FunctionRegistry, InMemoryFunctionRegistry,
+};
+use helios_fhirpath::parser::parse;
+use helios_fhirpath::type_checker::TypeChecker;
+use helios_fhirpath::type_inference::{InferredType, TypeContext, infer_expression_type};
+use helios_fhirpath::{EvaluationContext, EvaluationResult, evaluate_expression};
+use serde_json::json;
+use std::sync::Arc;
+
+/// Returns the items of a collection, or the value itself
+fn items(value: &EvaluationResult) -> Vec<&EvaluationResult> {
+    match value {
+        EvaluationResult::Collection { items, .. } => items.iter().collect(),
+        EvaluationResult::Empty => Vec::new(),
+        value => vec![value],
+    }
+}
+
+fn field<'a>(value: &'a EvaluationResult, name: &str) -> Vec<&'a EvaluationResult> {
+    match value {
+        EvaluationResult::Object { map, .. } => map.get(name).map(items).unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+fn registry() -> Arc<dyn FunctionRegistry> {
+    let normalize_phone = CustomFunction::new("normalizePhone", |input, _args, _context| {
+        Ok(match input {
+            EvaluationResult::String(phone, _) => {
+                EvaluationResult::string(phone.chars().filter(|c| c.is_ascii_digit()).collect())
+            }
+            _ => EvaluationResult::Empty,
+        })
+    })
+    .with_argument_evaluation(ArgumentEvaluation::PerItem)
+    .with_return_type(InferredType::system("String"));
+
+    // The value of the identifier of each input resource with the given system
+    let mrn_for = CustomFunction::new("mrnFor", |input, args, _context| {
+        let Some(EvaluationResult::String(system, _)) = args.first() else {
+            return Ok(EvaluationResult::Empty);
+        };
+        let values: Vec<EvaluationResult> = items(input)
+            .into_iter()
+            .flat_map(|resource| field(resource, "identifier"))
+            .filter(|identifier| {
+                field(identifier, "system").first().is_some_and(
+                    |value| matches!(value, EvaluationResult::String(s, _) if s == system),
+                )
+            })
+            .flat_map(|identifier| field(identifier, "value"))
+            .cloned()
+            .collect();
+        Ok(EvaluationResult::Collection {
+            items: values,
+            has_undefined_order: false,
+            type_info: None,
+        })
+    })
+    .with_arity(1, 1)
+    .with_return_type(InferredType::system("String").collection());
+
+    // Pairs each input item with its argument, evaluated against the item
+    let label = CustomFunction::new("label", |_input, args, _context| {
+        Ok(args.first().cloned().unwrap_or(EvaluationResult::Empty))
+    })
+    .with_arity(1, 1)
+    .with_argument_evaluation(ArgumentEvaluation::PerItem);
+
+    // Built-in functions cannot be replaced
+    let count = CustomFunction::new("count", |_input, _args, _context| {
+        Ok(EvaluationResult::integer(42))
+    });
+    let abs = CustomFunction::new("abs", |_input, _args, _context| {
+        Ok(EvaluationResult::integer(42))
+    });
+
+    Arc::new(
+        InMemoryFunctionRegistry::new()
+            .with_function(normalize_phone)
+            .with_function(mrn_for)
+            .with_function(label)
+            .with_function(count)
+            .with_function(abs),
+    )
+}
+
+fn context() -> EvaluationContext {
+    let mut context = EvaluationContext::from_json(
+        json!({
+            "resourceType": "Patient",
+            "identifier": [
+                { "system": "http://example.org/mrn", "value": "MRN-1" },
+                { "system": "http://example.org/ssn", "value": "123-45-6789" }
+            ],
+            "name": [
+                { "family": "Chalmers", "given": ["Peter"] },
+                { "family": "Windsor", "given": ["Jim"] }
+            ],
+            "telecom": [
+                { "system": "phone", "value": "(03) 5555 6473" },
+                { "system": "phone", "value": "+61 400 123 456" }
+            ]
+        }),
+        FhirVersion::R4,
+    );
+    context.set_function_registry(registry());
+    context
+}
+
+fn strings(result: EvaluationResult) -> Vec<String> {
+    items(&result)
+        .into_iter()
+        .map(|item| match item {
+            EvaluationResult::String(s, _) => s.clone(),
+            other => panic!("expected a string, got {:?}", other),
+        })
+        .collect()
+}
+
+#[test]
+fn test_evaluate_custom_functions() {
+    let context = context();
+
+    assert_eq!(
+        strings(evaluate_expression("Patient.telecom.value.normalizePhone()", &context).unwrap()),
+        vec!["0355556473", "61400123456"]
+    );
+    assert_eq!(
+        strings(evaluate_expression("Patient.mrnFor('http://example.org/mrn')", &context).unwrap()),
+        vec!["MRN-1"]
+    );
+    // Arguments are evaluated against each item for per-item functions
+    assert_eq!(
+        strings(
+            evaluate_expression(
+                "Patient.name.label(family & ', ' & given.first())",
+                &context
+            )
+            .unwrap()
+        ),
+        vec!["Chalmers, Peter", "Windsor, Jim"]
+    );
+    // Functions can be called without an explicit input
+    assert_eq!(
+        strings(evaluate_expression("'+1 (555) 010-9999'.normalizePhone()", &context).unwrap()),
+        vec!["15550109999"]
+    );
+    // Registered functions are available inside aggregate()
+    assert_eq!(
+        strings(
+            evaluate_expression(
+                "Patient.telecom.value.aggregate($total & $this.normalizePhone(), '')",
+                &context
+            )
+            .unwrap()
+        ),
+        vec!["035555647361400123456"]
+    );
+    assert_eq!(
+        evaluate_expression("{}.normalizePhone()", &context).unwrap(),
+        EvaluationResult::Empty
+    );
+}
+
+#[test]
+fn test_builtins_take_precedence() {
+    let context = context();
+    assert_eq!(
+        evaluate_expression("Patient.name.count()", &context).unwrap(),
+        EvaluationResult::integer(2)
+    );
+    assert_eq!(
+        evaluate_expression("(-5).abs()", &context).unwrap(),
+        EvaluationResult::integer(5)
+    );
+}
+
+#[test]
+fn test_arity_errors() {
+    let context = context();
+    let err = evaluate_expression("Patient.mrnFor()", &context).unwrap_err();
+    assert!(
+        err.contains("Function 'mrnFor' expects 1 argument, but 0 were given"),
+        "{}",
+        err
+    );
+    assert!(evaluate_expression("'1'.normalizePhone('x')", &context).is_err());
+}
+
+#[test]
+fn test_without_registry() {
+    // Unknown functions return an empty result, as before
+    let context = EvaluationContext::new_empty_with_default_version();
+    assert_eq!(
+        evaluate_expression("'1'.normalizePhone()", &context).unwrap(),
+        EvaluationResult::Empty
+    );
+}
+
+#[test]
+fn test_type_inference_and_checking() {
+    let type_context = TypeContext::new().with_function_registry(registry());
+    let expression = parse("'(03) 5555 6473'.normalizePhone()").unwrap();
+    assert_eq!(
+        infer_expression_type(&expression, &type_context),
+        Some(InferredType::system("String"))
+    );
+
+    let checker = TypeChecker::new(FhirVersion::R4).with_function_registry(registry());
+    let result = checker
+        .check("Patient.telecom.value.normalizePhone()")
+        .unwrap();
+    assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+    assert_eq!(
+        result.expected_return_type().as_deref(),
+        Some("system.String[]")
+    );
+
+    // Per-item arguments are checked against the items of the input
+    let result = checker.check("Patient.name.label(famly)").unwrap();
+    assert_eq!(result.diagnostics.len(), 1, "{:?}", result.diagnostics);
+    assert!(
+        result.diagnostics[0]
+            .message
+            .starts_with("Unknown element 'famly' on HumanName"),
+        "{:?}",
+        result.diagnostics
+    );
+
+    let result = checker.check("Patient.mrnFor()").unwrap();
+    assert_eq!(
+        result.diagnostics[0].message,
+        "Function 'mrnFor' expects 1 argument, but 0 were given"
+    );
+
+    // Without the registry the function is unknown
+    let result = TypeChecker::new(FhirVersion::R4)
+        .check("Patient.telecom.value.normalizePhone()")
+        .unwrap();
+    assert_eq!(
+        result.diagnostics[0].message,
+        "Unknown function 'normalizePhone'"
+    );
+}
@@ -0,0 +1,310 @@
+use helios_fhir::{FhirResource, FhirVersion, r4};
+use helios_fhirpath::{EvaluationContext, EvaluationResult, JsonResource, evaluate_expression};
+use helios_fhirpath_support::{FhirPathFields, IntoEvaluationResult};
+use serde_json::{Value, json};
+use std::path::PathBuf;
+
+const EXAMPLES: [&str; 4] = [
+    "patient-example.json",
+    "observation-example.json",
+    "questionnaire-example.json",
+    "valueset-example-expansion.json",
+];
+
+fn load_json(json_filename: &str) -> Value {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push(format!("tests/data/r4/input/{}", json_filename));
+    let contents = std::fs::read_to_string(&path).unwrap();
+    serde_json::from_str(&contents).unwrap()
+}
+
+fn typed_resource(json: &Value) -> FhirResource {
+    let resource: r4::Resource = serde_json::from_value(json.clone()).unwrap();
+    FhirResource::R4(Box::new(resource))
+}
+
+/// A patient exercising primitive extensions, contained resources, choice types
+/// and numbers
+fn patient_with_extensions() -> Value {
+    json!({
+        "resourceType": "Patient",
+        "id": "ext",
+        "active": true,
+        "gender": "female",
+        "_gender": { "id": "g1" },
+        "birthDate": "1974-12-25",
+        "_birthDate": {
+            "extension": [{
+                "url": "http://hl7.org/fhir/StructureDefinition/patient-birthTime",
+                "valueDateTime": "1974-12-25T14:35:45-05:00"
+            }]
+        },
+        "name": [{
+            "family": "Chalmers",
+            "given": ["Peter", null, "James"],
+            "_given": [null, { "extension": [{ "url": "http://example.org/middle", "valueBoolean": true }] }, null]
+        }],
+        "multipleBirthInteger": 2,
+        "extension": [
+            { "url": "http://example.org/weight", "valueQuantity": { "value": 72.50, "unit": "kg" } },
+            { "url": "http://example.org/code", "valueCode": "abc" },
+            { "url": "http://example.org/count", "valueInteger": 3 },
+            { "url": "http://example.org/decimal", "valueDecimal": 1.0 }
+        ],
+        "contained": [
+            { "resourceType": "Organization", "id": "org1", "name": "Acme" }
+        ],
+        "managingOrganization": { "reference": "#org1" }
+    })
+}
+
+fn bundle() -> Value {
+    json!({
+        "resourceType": "Bundle",
+        "type": "collection",
+        "total": 2,
+        "entry": [
+            { "fullUrl": "urn:uuid:1", "resource": patient_with_extensions() },
+            { "fullUrl": "urn:uuid:2", "resource": load_json("observation-example.json") }
+        ]
+    })
+}
+
+fn assert_same_results(json: Value, expressions: &[&str]) {
+    for strict in [false, true] {
+        let mut typed = EvaluationContext::new(vec![typed_resource(&json)]);
+        let mut untyped = EvaluationContext::from_json(json.clone(), FhirVersion::R4);
+        typed.set_strict_mode(strict);
+        untyped.set_strict_mode(strict);
+        for expression in expressions {
+            let typed_result = evaluate_expression(expression, &typed);
+            let json_result = evaluate_expression(expression, &untyped);
+            assert_eq!(
+                json_result.is_ok(),
+                typed_result.is_ok(),
+                "{} (strict: {}): {:?} vs {:?}",
+                expression,
+                strict,
+                json_result,
+                typed_result
+            );
+            if let (Ok(json_result), Ok(typed_result)) = (json_result, typed_result) {
+                assert_eq!(
+                    json_result, typed_result,
+                    "{} (strict: {})",
+                    expression, strict
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_conversion_matches_typed_conversion() {
+    let mut resources: Vec<Value> = EXAMPLES.iter().map(|name| load_json(name)).collect();
+    resources.push(patient_with_extensions());
+    resources.push(bundle());
+
+    for json in resources {
+        let typed = typed_resource(&json).to_evaluation_result();
+        let resource = JsonResource::new(json, FhirVersion::R4);
+        assert_eq!(
+            resource.to_evaluation_result(),
+            typed,
+            "{:?}",
+            resource.resource_type()
+        );
+    }
+}
+
+#[test]
+fn test_fields_match_typed_fields() {
+    for json_filename in EXAMPLES {
+        let json = load_json(json_filename);
+        let typed = typed_resource(&json);
+        let resource = JsonResource::new(json, FhirVersion::R4);
+        let EvaluationResult::Object { map, .. } = typed.to_evaluation_result() else {
+            panic!("{} did not convert to an object", json_filename);
+        };
+        for name in map.keys() {
+            assert_eq!(
+                resource.fhirpath_field(name),
+                typed.fhirpath_field(name),
+                "{}: {}",
+                json_filename,
+                name
+            );
+        }
+        for name in ["photo", "notAField", "valueQuantity", "deceasedBoolean"] {
+            assert_eq!(
+                resource.fhirpath_field(name),
+                typed.fhirpath_field(name),
+                "{}: {}",
+                json_filename,
+                name
+            );
+        }
+    }
+}
+
+#[test]
+fn test_patient_expressions() {
+    assert_same_results(
+        patient_with_extensions(),
+        &[
+            "Patient",
+            "Patient.id",
+            "resourceType",
+            "Patient.gender",
+            "Patient.gender.id",
+            "Patient.gender is code",
+            "Patient.birthDate",
+            "Patient.birthDate.extension('http://hl7.org/fhir/StructureDefinition/patient-birthTime').value",
+            "Patient.name.given",
+            "Patient.name.given.count()",
+            "Patient.name.given.extension.value",
+            "Patient.multipleBirth",
+            "Patient.multipleBirthInteger + 1",
+            "Patient.multipleBirth.ofType(integer)",
+            "Patient.extension.value",
+            "Patient.extension.value.ofType(Quantity).value",
+            "Patient.extension('http://example.org/weight').value > 70 'kg'",
+            "Patient.extension('http://example.org/decimal').value.precision()",
+            "Patient.contained.name",
+            "Patient.contained.ofType(Organization).id",
+            "Patient.managingOrganization.resolve().name",
+            "%context.active",
+            "Patient.descendants().count()",
+            "Patient.notAField",
+        ],
+    );
+}
+
+#[test]
+fn test_temporal_primitives_are_typed() {
+    let json = patient_with_extensions();
+    for context in [
+        EvaluationContext::new(vec![typed_resource(&json)]),
+        EvaluationContext::from_json(json.clone(), FhirVersion::R4),
+    ] {
+        let birth_date = evaluate_expression("Patient.birthDate", &context).unwrap();
+        let EvaluationResult::Date(date, Some(type_info)) = &birth_date else {
+            panic!("birthDate is not a typed Date: {:?}", birth_date);
+        };
+        assert_eq!(type_info.name, "date");
+        assert_eq!(date.as_str(), "1974-12-25");
+
+        for (expression, expected) in [
+            ("Patient.birthDate < @1975", true),
+            ("Patient.birthDate is FHIR.date", true),
+            ("Patient.birthDate.yearOf() = 1974", true),
+            ("Patient.birthDate.toString() = '1974-12-25'", true),
+        ] {
+            assert_eq!(
+                evaluate_expression(expression, &context),
+                Ok(EvaluationResult::boolean(expected)),
+                "{}",
+                expression
+            );
+        }
+    }
+
+    // Choice elements such as effective[x] are typed by their variant
+    let json = load_json("observation-example.json");
+    for context in [
+        EvaluationContext::new(vec![typed_resource(&json)]),
+        EvaluationContext::from_json(json.clone(), FhirVersion::R4),
+    ] {
+        let effective = evaluate_expression("Observation.effective", &context).unwrap();
+        let EvaluationResult::DateTime(datetime, Some(type_info)) = &effective else {
+            panic!("effective is not a typed DateTime: {:?}", effective);
+        };
+        assert_eq!(type_info.name, "dateTime");
+        assert_eq!(datetime.to_string(), "2016-03-28");
+        assert_eq!(
+            evaluate_expression("Observation.effective > @2016-03-27T10:00:00Z", &context),
+            Ok(EvaluationResult::boolean(true))
+        );
+    }
+}
+
+#[test]
+fn test_example_expressions() {
+    assert_same_results(
+        load_json("observation-example.json"),
+        &[
+            "Observation.value",
+            "Observation.value.unit",
+            "Observation.valueQuantity.value",
+            "Observation.value.ofType(Quantity).code",
+            "Observation.effective",
+            "Observation.code.coding.code",
+            "valueQuantity > 100 'kg'",
+        ],
+    );
+    assert_same_results(
+        load_json("questionnaire-example.json"),
+        &[
+            "Questionnaire.repeat(item).linkId",
+            "Questionnaire.item.item.count()",
+        ],
+    );
+    assert_same_results(
+        bundle(),
+        &[
+            "Bundle.total",
+            "Bundle.entry.resource.ofType(Patient).name.family",
+            "Bundle.entry.resource.ofType(Observation).value.value",
+            "Bundle.entry.resource.resourceType",
+        ],
+    );
+}
+
+#[test]
+fn test_resources_typed_deserialization_rejects() {
+    // An R5 element, an unknown element and a single value where R4 expects an array
+    let json = json!({
+        "resourceType": "Patient",
+        "id": "r5",
+        "name": { "family": "Chalmers" },
+        "customField": { "nested": [1, 2.5, "three"] },
+        "telecom": [{ "system": "email", "value": "p@example.org" }]
+    });
+    assert!(serde_json::from_value::<r4::Resource>(json.clone()).is_err());
+
+    let context = EvaluationContext::from_json(json, FhirVersion::R4);
+    let evaluate = |expression: &str| evaluate_expression(expression, &context).unwrap();
+
+    assert_eq!(
+        evaluate("Patient.name.family"),
+        EvaluationResult::fhir_string("Chalmers".to_string(), "string")
+    );
+    assert_eq!(
+        evaluate("Patient.telecom.system"),
+        EvaluationResult::fhir_string("email".to_string(), "code")
+    );
+    // Unknown elements are still navigable, without FHIR type information
+    assert_eq!(
+        evaluate("Patient.customField.nested.count()"),
+        EvaluationResult::integer(3)
+    );
+    assert_eq!(
+        evaluate("Patient.customField.nested.last()"),
+        EvaluationResult::string("three".to_string())
+    );
+}
+
+#[test]
+fn test_unknown_resource_type() {
+    let context = EvaluationContext::from_json(
+        json!({ "resourceType": "FutureResource", "status": "active", "count": 2 }),
+        FhirVersion::R4,
+    );
+    let evaluate = |expression: &str| evaluate_expression(expression, &context).unwrap();
+    assert_eq!(
+        evaluate("FutureResource.status"),
+        EvaluationResult::string("active".to_string())
+    );
+    assert_eq!(evaluate("count + 1"), EvaluationResult::integer(3));
+    assert_eq!(evaluate("%context.count"), EvaluationResult::integer(2));
+}
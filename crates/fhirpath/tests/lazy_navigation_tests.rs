@@ -0,0 +1,196 @@
+use helios_fhir::{FhirResource, r4};
+use helios_fhirpath::{EvaluationContext, EvaluationResult, evaluate_expression};
+use helios_fhirpath_support::{FhirPathFields, IntoEvaluationResult};
+use std::path::PathBuf;
+
+fn load_resource(json_filename: &str) -> FhirResource {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push(format!("tests/data/r4/input/{}", json_filename));
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let resource: r4::Resource = serde_json::from_str(&contents).unwrap();
+    FhirResource::R4(Box::new(resource))
+}
+
+/// A context over the typed resource, navigated lazily
+fn lazy_context(json_filename: &str) -> EvaluationContext {
+    EvaluationContext::new(vec![load_resource(json_filename)])
+}
+
+/// A context whose focus is the fully converted resource
+fn eager_context(json_filename: &str) -> EvaluationContext {
+    let resource = load_resource(json_filename);
+    let converted = resource.to_evaluation_result();
+    let mut context = EvaluationContext::new(vec![resource]);
+    context.set_this(converted);
+    context
+}
+
+fn assert_same_results(json_filename: &str, expressions: &[&str]) {
+    for strict in [false, true] {
+        let mut lazy = lazy_context(json_filename);
+        let mut eager = eager_context(json_filename);
+        lazy.set_strict_mode(strict);
+        eager.set_strict_mode(strict);
+        for expression in expressions {
+            let lazy_result = evaluate_expression(expression, &lazy);
+            let eager_result = evaluate_expression(expression, &eager);
+            assert_eq!(
+                lazy_result.is_ok(),
+                eager_result.is_ok(),
+                "{} (strict: {}): {:?} vs {:?}",
+                expression,
+                strict,
+                lazy_result,
+                eager_result
+            );
+            if let (Ok(lazy_result), Ok(eager_result)) = (lazy_result, eager_result) {
+                assert_eq!(
+                    lazy_result, eager_result,
+                    "{} (strict: {})",
+                    expression, strict
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_fields_match_full_conversion() {
+    for json_filename in [
+        "patient-example.json",
+        "observation-example.json",
+        "questionnaire-example.json",
+        "valueset-example-expansion.json",
+    ] {
+        let resource = load_resource(json_filename);
+        let EvaluationResult::Object { map, .. } = resource.to_evaluation_result() else {
+            panic!("{} did not convert to an object", json_filename);
+        };
+        for (name, value) in &map {
+            assert_eq!(
+                resource.fhirpath_field(name).as_ref(),
+                Some(value),
+                "{}: {}",
+                json_filename,
+                name
+            );
+        }
+        assert_eq!(resource.fhirpath_field("notAField"), None);
+    }
+}
+
+#[test]
+fn test_absent_fields() {
+    let resource = load_resource("patient-example.json");
+    // Patient.photo is a field of Patient, but not present in the example
+    assert_eq!(
+        resource.fhirpath_field("photo"),
+        Some(EvaluationResult::Empty)
+    );
+    assert_eq!(resource.fhirpath_field("valueQuantity"), None);
+}
+
+#[test]
+fn test_patient_navigation() {
+    assert_same_results(
+        "patient-example.json",
+        &[
+            "Patient",
+            "Patient.id",
+            "id",
+            "resourceType",
+            "Patient.name.given",
+            "name.family",
+            "Patient.name.where(use = 'official').given.first()",
+            "Patient.birthDate",
+            "Patient.birthDate.extension('http://hl7.org/fhir/StructureDefinition/patient-birthTime').value",
+            "Patient.active.not()",
+            "Patient.telecom.count()",
+            "Patient.photo",
+            "Patient.notAField",
+            "Patient.deceased",
+            "Patient.deceasedBoolean",
+            "$this.gender",
+            "%context.gender",
+            "Patient.contact.name.family",
+            "Patient.managingOrganization.reference",
+            "Patient.children().count()",
+            "Patient.descendants().count()",
+            "name.given | name.family",
+        ],
+    );
+}
+
+#[test]
+fn test_observation_navigation() {
+    assert_same_results(
+        "observation-example.json",
+        &[
+            "Observation.status",
+            "Observation.value",
+            "Observation.value.unit",
+            "Observation.valueQuantity.value",
+            "Observation.value.ofType(Quantity).code",
+            "Observation.effective",
+            "Observation.effectiveDateTime",
+            "Observation.code.coding.code",
+            "Observation.component",
+            "Observation.subject.reference",
+            "valueQuantity > 100 'kg'",
+        ],
+    );
+}
+
+#[test]
+fn test_questionnaire_navigation() {
+    assert_same_results(
+        "questionnaire-example.json",
+        &[
+            "Questionnaire.item.linkId",
+            "Questionnaire.repeat(item).linkId",
+            "Questionnaire.item.item.count()",
+            "Questionnaire.status",
+        ],
+    );
+}
+
+#[test]
+fn test_root_item_defaults_to_first_resource() {
+    let resource = load_resource("patient-example.json");
+    let context = lazy_context("patient-example.json");
+    assert_eq!(context.root_item(), Some(&resource.to_evaluation_result()));
+
+    let mut context = lazy_context("patient-example.json");
+    context.set_this(EvaluationResult::string("other".to_string()));
+    assert_eq!(
+        evaluate_expression("$this", &context).unwrap(),
+        EvaluationResult::string("other".to_string())
+    );
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_deprecated_this_field() {
+    // The field only holds an override; the first resource is not stored in it
+    let mut context = lazy_context("patient-example.json");
+    assert!(context.this.is_none());
+    context.this = Some(EvaluationResult::string("other".to_string()));
+    assert_eq!(
+        context.root_item(),
+        Some(&EvaluationResult::string("other".to_string()))
+    );
+}
+
+#[test]
+fn test_repeated_root_member_reads() {
+    let context = lazy_context("patient-example.json");
+    let first = evaluate_expression("Patient.name", &context).unwrap();
+    assert_eq!(evaluate_expression("name", &context).unwrap(), first);
+    assert_eq!(
+        evaluate_expression("Patient.name", &context).unwrap(),
+        first
+    );
+    // Converting the whole resource afterwards gives the same member
+    assert_eq!(evaluate_expression("$this.name", &context).unwrap(), first);
+    assert_eq!(evaluate_expression("name", &context).unwrap(), first);
+}
@@ -0,0 +1,129 @@
+use helios_fhir::FhirVersion;
+use helios_fhirpath::evaluator::{EvaluationContext, evaluate};
+use helios_fhirpath::limits::{CancellationToken, EvaluationLimits};
+use helios_fhirpath::parser::parse;
+use helios_fhirpath_support::{EvaluationError, EvaluationResult};
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+fn context_with(limits: EvaluationLimits) -> EvaluationContext {
+    let mut context = EvaluationContext::from_json(
+        json!({
+            "resourceType": "Patient",
+            "name": [
+                { "family": "Chalmers", "given": ["Peter", "James"] },
+                { "family": "Windsor", "given": ["Jim"] }
+            ]
+        }),
+        FhirVersion::R4,
+    );
+    context.set_limits(limits);
+    context
+}
+
+fn eval(
+    expression: &str,
+    context: &EvaluationContext,
+) -> Result<EvaluationResult, EvaluationError> {
+    evaluate(&parse(expression).unwrap(), context, None)
+}
+
+/// Asserts that evaluation exceeds a limit, returning the error message
+fn limit_exceeded(expression: &str, context: &EvaluationContext) -> String {
+    match eval(expression, context) {
+        Err(EvaluationError::LimitExceeded(message)) => message,
+        other => panic!(
+            "{}: expected a limit to be exceeded, got {:?}",
+            expression, other
+        ),
+    }
+}
+
+#[test]
+fn test_max_depth() {
+    let context = context_with(EvaluationLimits::new().with_max_depth(10));
+    assert_eq!(
+        eval("1 + 1 + 1", &context).unwrap(),
+        EvaluationResult::integer(3)
+    );
+
+    let sum = vec!["1"; 20].join(" + ");
+    assert_eq!(
+        limit_exceeded(&sum, &context),
+        "Evaluation depth exceeds the limit of 10"
+    );
+
+    // Nested aggregates count towards the depth
+    let context = context_with(EvaluationLimits::new().with_max_depth(5));
+    assert!(eval("(1 | 2).aggregate($total + $this, 0)", &context).is_ok());
+    let nested = "(1 | 2).aggregate($total + (1 | 2).aggregate($total + (1 | 2).aggregate($total + $this, 0), 0), 0)";
+    limit_exceeded(nested, &context);
+
+    // The depth is restored after each evaluation
+    assert_eq!(
+        eval("1 + 1 + 1", &context).unwrap(),
+        EvaluationResult::integer(3)
+    );
+}
+
+#[test]
+fn test_max_collection_size() {
+    let context = context_with(EvaluationLimits::new().with_max_collection_size(3));
+    assert!(eval("Patient.name.given", &context).is_ok());
+    assert_eq!(
+        limit_exceeded("Patient.name.given | 'Jimmy'", &context),
+        "Collection size of 4 exceeds the limit of 3"
+    );
+    limit_exceeded("Patient.descendants()", &context);
+
+    // repeat() stops as soon as its result grows too large
+    let context = context_with(EvaluationLimits::new().with_max_collection_size(100));
+    limit_exceeded("1.repeat($this + 1)", &context);
+}
+
+#[test]
+fn test_deadline() {
+    let context = context_with(EvaluationLimits::new().with_deadline(Instant::now()));
+    assert_eq!(
+        limit_exceeded("Patient.name", &context),
+        "Evaluation exceeded its deadline"
+    );
+
+    // A runaway expression stops once the timeout has passed
+    let context = context_with(EvaluationLimits::new().with_timeout(Duration::from_millis(100)));
+    let start = Instant::now();
+    limit_exceeded("1.repeat($this + 1)", &context);
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn test_cancellation() {
+    let token = CancellationToken::new();
+    let context = context_with(EvaluationLimits::new().with_cancellation_token(token.clone()));
+    assert!(eval("Patient.name", &context).is_ok());
+
+    // Cancel from another thread while evaluation is running
+    let canceller = std::thread::spawn({
+        let token = token.clone();
+        move || {
+            std::thread::sleep(Duration::from_millis(100));
+            token.cancel();
+        }
+    });
+    assert_eq!(
+        limit_exceeded("1.repeat($this + 1)", &context),
+        "Evaluation was cancelled"
+    );
+    canceller.join().unwrap();
+    assert!(token.is_cancelled());
+}
+
+#[test]
+fn test_no_limits_by_default() {
+    let context = EvaluationContext::new_empty_with_default_version();
+    let sum = vec!["1"; 200].join(" + ");
+    assert_eq!(
+        eval(&sum, &context).unwrap(),
+        EvaluationResult::integer(200)
+    );
+}
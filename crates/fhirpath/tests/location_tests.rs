@@ -0,0 +1,139 @@
+use helios_fhir::{FhirResource, FhirVersion, r4};
+use helios_fhirpath::{EvaluationContext, EvaluationResult, evaluate_expression};
+use std::path::PathBuf;
+
+fn load_json(json_filename: &str) -> serde_json::Value {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push(format!("tests/data/r4/input/{}", json_filename));
+    serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap()
+}
+
+fn typed_context(json_filename: &str) -> EvaluationContext {
+    let resource: r4::Resource = serde_json::from_value(load_json(json_filename)).unwrap();
+    let mut context = EvaluationContext::new(vec![FhirResource::R4(Box::new(resource))]);
+    context.set_track_locations(true);
+    context
+}
+
+fn json_context(json_filename: &str) -> EvaluationContext {
+    let mut context = EvaluationContext::from_json(load_json(json_filename), FhirVersion::R4);
+    context.set_track_locations(true);
+    context
+}
+
+fn locations(expression: &str, context: &EvaluationContext) -> Vec<Option<String>> {
+    let items = match evaluate_expression(expression, context).unwrap() {
+        EvaluationResult::Collection { items, .. } => items,
+        EvaluationResult::Empty => vec![],
+        item => vec![item],
+    };
+    items
+        .iter()
+        .map(|item| item.location().map(str::to_string))
+        .collect()
+}
+
+fn located(paths: &[&str]) -> Vec<Option<String>> {
+    paths.iter().map(|path| Some(path.to_string())).collect()
+}
+
+#[test]
+fn test_navigated_items_are_located() {
+    for context in [
+        typed_context("patient-example.json"),
+        json_context("patient-example.json"),
+    ] {
+        assert_eq!(locations("Patient", &context), located(&["Patient"]));
+        assert_eq!(
+            locations("Patient.birthDate", &context),
+            located(&["Patient.birthDate"])
+        );
+        assert_eq!(
+            locations("name.given", &context),
+            located(&[
+                "Patient.name[0].given[0]",
+                "Patient.name[0].given[1]",
+                "Patient.name[1].given[0]",
+                "Patient.name[2].given[0]",
+                "Patient.name[2].given[1]",
+            ])
+        );
+        assert_eq!(
+            locations("Patient.telecom[1].value", &context),
+            located(&["Patient.telecom[1].value"])
+        );
+    }
+}
+
+#[test]
+fn test_locations_survive_filtering() {
+    let context = typed_context("patient-example.json");
+    assert_eq!(
+        locations("Patient.name.where(use = 'maiden').given.last()", &context),
+        located(&["Patient.name[2].given[1]"])
+    );
+    assert_eq!(
+        locations(
+            "Patient.telecom.where(system = 'phone').skip(1).select(period.end)",
+            &context
+        ),
+        located(&["Patient.telecom[3].period.end"])
+    );
+    assert_eq!(
+        locations("Patient.name.family | Patient.name[1].use", &context),
+        located(&[
+            "Patient.name[0].family",
+            "Patient.name[2].family",
+            "Patient.name[1].use",
+        ])
+    );
+}
+
+#[test]
+fn test_computed_values_have_no_location() {
+    let context = typed_context("patient-example.json");
+    assert_eq!(
+        locations("Patient.name.given.count()", &context),
+        vec![None]
+    );
+    assert_eq!(
+        locations("Patient.name.first().family.upper()", &context),
+        vec![None]
+    );
+}
+
+#[test]
+fn test_locations_are_not_tracked_by_default() {
+    let mut context = typed_context("patient-example.json");
+    context.set_track_locations(false);
+    assert_eq!(locations("Patient.name.given", &context), vec![None; 5]);
+}
+
+#[test]
+fn test_changing_tracking_after_evaluation() {
+    let mut context = typed_context("patient-example.json");
+    context.set_track_locations(false);
+    assert_eq!(locations("Patient.name.given", &context), vec![None; 5]);
+
+    // The resource converted without locations is not reused
+    context.set_track_locations(true);
+    assert_eq!(
+        locations("Patient.name[0].given", &context)[0].as_deref(),
+        Some("Patient.name[0].given[0]")
+    );
+
+    context.set_track_locations(false);
+    assert_eq!(locations("Patient.name.given", &context), vec![None; 5]);
+}
+
+#[test]
+fn test_locations_do_not_affect_equality() {
+    let context = typed_context("patient-example.json");
+    let result = evaluate_expression(
+        "Patient.name[0].given[0] = Patient.name[2].given[0]",
+        &context,
+    );
+    assert_eq!(result, Ok(EvaluationResult::boolean(true)));
+    let result = evaluate_expression("(Patient.name.given).distinct().count()", &context);
+    assert_eq!(result, Ok(EvaluationResult::integer(3)));
+}
@@ -0,0 +1,152 @@
+use helios_fhir::{FhirResource, r4};
+use helios_fhirpath::optimizer::optimize;
+use helios_fhirpath::parse_debug::expression_to_debug_tree;
+use helios_fhirpath::parser::parse;
+use helios_fhirpath::type_inference::{InferredType, TypeContext};
+use helios_fhirpath::{CompiledExpression, EvaluationContext};
+use std::path::PathBuf;
+
+fn optimized(source: &str) -> String {
+    optimize(&parse(source).unwrap()).to_string()
+}
+
+fn patient_context() -> EvaluationContext {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests/data/r4/input/patient-example.json");
+    let json = std::fs::read_to_string(&path).unwrap();
+    let resource: r4::Resource = serde_json::from_str(&json).unwrap();
+    EvaluationContext::new(vec![FhirResource::R4(Box::new(resource))])
+}
+
+#[test]
+fn test_constant_folding() {
+    assert_eq!(optimized("'a' + 'b'"), "'ab'");
+    assert_eq!(optimized("1 + 2 * 3"), "7");
+    assert_eq!(optimized("1.5 + 2"), "3.5");
+    assert_eq!(optimized("(1 + 2).toString()"), "'3'");
+    assert_eq!(optimized("'abc'.substring(1).upper()"), "'BC'");
+    assert_eq!(optimized("(1 | 2 | 2).distinct().count()"), "2");
+    assert_eq!(optimized("{}.empty()"), "true");
+    assert_eq!(optimized("5 / 0"), "{}");
+    assert_eq!(optimized("1 is Integer"), "true");
+    assert_eq!(
+        optimized("name.given.count() + (2 * 3)"),
+        "name.given.count() + 6"
+    );
+    assert_eq!(
+        optimized("name.where(use = 'off' + 'icial')"),
+        "name.where(use = 'official')"
+    );
+
+    // Collections, quantities, dates, non-deterministic functions and
+    // failing expressions are left for evaluation
+    assert_eq!(optimized("1 | 2"), "1 | 2");
+    assert_eq!(optimized("1 'mg' + 2 'mg'"), "1 'mg' + 2 'mg'");
+    assert_eq!(optimized("@2020-01-01 + 1 day"), "@2020-01-01 + 1 day");
+    assert_eq!(optimized("today() > @2020-01-01"), "today() > @2020-01-01");
+    assert_eq!(optimized("'a'.trace('x')"), "'a'.trace('x')");
+    assert_eq!(optimized("(1 | 2).single()"), "(1 | 2).single()");
+}
+
+#[test]
+fn test_no_op_removal() {
+    assert_eq!(optimized("name.where(true).given"), "name.given");
+    assert_eq!(optimized("name.where(1 = 1)"), "name");
+    assert_eq!(optimized("name.select($this).given"), "name.given");
+    assert_eq!(optimized("iif(true, name, telecom)"), "name");
+    assert_eq!(optimized("iif(1 > 2, name, telecom)"), "telecom");
+    assert_eq!(optimized("iif({}, name)"), "{}");
+    assert_eq!(
+        optimized("name.where(iif(true, use, text) = 'official')"),
+        "name.where(use = 'official')"
+    );
+
+    // Filters whose result depends on the focus are kept
+    assert_eq!(
+        optimized("iif(active, name, telecom)"),
+        "iif(active, name, telecom)"
+    );
+    assert_eq!(
+        optimized("Patient.iif(true, name, telecom)"),
+        "Patient.iif(true, name, telecom)"
+    );
+    assert_eq!(optimized("where(true)"), "where(true)");
+    assert_eq!(optimized("name.where(false)"), "name.where(false)");
+}
+
+#[test]
+fn test_boolean_short_circuit() {
+    assert_eq!(optimized("true and name.exists()"), "name.exists()");
+    assert_eq!(optimized("name.exists() and false"), "false");
+    assert_eq!(optimized("false or name.empty()"), "name.empty()");
+    assert_eq!(optimized("gender = 'male' or true"), "true");
+    assert_eq!(optimized("false implies name.exists()"), "true");
+    assert_eq!(
+        optimized("true implies (gender = 'male')"),
+        "gender = 'male'"
+    );
+    assert_eq!(optimized("name.exists() implies 1 < 2"), "true");
+    assert_eq!(
+        optimized("(1 = 1) and (active is Boolean)"),
+        "active is Boolean"
+    );
+
+    // Operands that might not be Boolean, and empty operands, are kept
+    assert_eq!(optimized("true and active"), "true and active");
+    assert_eq!(optimized("name.given or false"), "name.given or false");
+    assert_eq!(optimized("{} and name.exists()"), "{} and name.exists()");
+    assert_eq!(
+        optimized("true xor name.exists()"),
+        "true xor name.exists()"
+    );
+}
+
+#[test]
+fn test_evaluation_is_unchanged() {
+    let context = patient_context();
+    for source in [
+        "Patient.name.where(true).given",
+        "Patient.name.select($this).family",
+        "iif(true, name.given, telecom)",
+        "iif({}, name.given)",
+        "name.where(use = 'off' + 'icial').given.first() & ' ' + 'x'",
+        "true and name.exists()",
+        "birthDate < @2000-01-01 or false",
+        "(1 + 2) * 3 = 9 implies active",
+        "gender = 'male' and (1 | 2).count() = 2",
+        "telecom.where(system = 'phone' and true).value",
+    ] {
+        let compiled = CompiledExpression::compile(source).unwrap();
+        let optimized = CompiledExpression::compile_optimized(source).unwrap();
+        assert_eq!(optimized.source(), source);
+        assert_eq!(
+            optimized.evaluate(&context).unwrap(),
+            compiled.evaluate(&context).unwrap(),
+            "'{}' optimized to '{}'",
+            source,
+            optimized.expression()
+        );
+    }
+}
+
+#[test]
+fn test_spans_of_rewritten_nodes() {
+    let source = "name.where(true).given | ('a' + 'b')";
+    let expression = optimize(&parse(source).unwrap());
+    assert_eq!(expression.span(), 0..source.len());
+
+    let type_context = TypeContext::new().with_root_type(InferredType::fhir("Patient"));
+    let tree = expression_to_debug_tree(&expression, &type_context);
+    let folded = &tree["Arguments"][1];
+    assert_eq!(folded["ExpressionType"], "ConstantExpression");
+    assert_eq!(folded["Name"], "ab");
+    assert_eq!(folded["Position"], 25);
+    assert_eq!(folded["Length"], 11);
+
+    // The removed where() leaves given() spanning its original text
+    let given = &tree["Arguments"][0];
+    assert_eq!(given["Name"], "given");
+    assert_eq!(given["Position"], 0);
+    assert_eq!(given["Length"], 22);
+    assert_eq!(given["Arguments"][0]["Name"], "name");
+}
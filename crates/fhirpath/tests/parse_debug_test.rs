@@ -66,4 +66,28 @@ mod tests {
             Some("builtin.that")
         );
     }
+
+    #[test]
+    fn test_node_positions() {
+        let expression = "name.where(use = 'official').given";
+        let parsed =
+            helios_fhirpath::parser::parse(expression).expect("Failed to parse expression");
+        let debug_tree = expression_to_debug_tree(&parsed, &TypeContext::new());
+
+        let span = |node: &serde_json::Value| {
+            let position = node["Position"].as_u64().unwrap() as usize;
+            let length = node["Length"].as_u64().unwrap() as usize;
+            &expression[position..position + length]
+        };
+        assert_eq!(span(&debug_tree), expression);
+        let where_node = &debug_tree["Arguments"][0];
+        assert_eq!(where_node["Name"], "where");
+        assert_eq!(span(where_node), "name.where(use = 'official')");
+        assert_eq!(span(&where_node["Arguments"][0]), "name");
+        assert_eq!(span(&where_node["Arguments"][1]), "use = 'official'");
+        assert_eq!(
+            span(&where_node["Arguments"][1]["Arguments"][1]),
+            "'official'"
+        );
+    }
 }
@@ -1,5 +1,5 @@
 use chumsky::Parser;
-use helios_fhirpath::parser::parser;
+use helios_fhirpath::parser::{Expression, Invocation, Term, parse, parser};
 use roxmltree::{Document, Node};
 use std::fs::File;
 use std::io::Read;
@@ -143,6 +143,164 @@ fn test_parse_simple_expressions() {
     }
 }
 
+#[test]
+fn test_spans() {
+    let source = "Patient.name.where(use = 'official')[0]";
+    let Expression::Indexer(base, index, span) = parse(source).unwrap() else {
+        panic!("expected an indexer");
+    };
+    assert_eq!(span, 0..39);
+    assert_eq!(index.span(), 37..38);
+    let Expression::Invocation(name, Invocation::Function(_, args, _), span) = *base else {
+        panic!("expected a function call");
+    };
+    assert_eq!(span, 0..36);
+    assert_eq!(name.span(), 0..12);
+    assert_eq!(&source[args[0].span()], "use = 'official'");
+
+    // Spans exclude surrounding whitespace but include parentheses
+    let source = "  (1 + 2) * -x ";
+    let Expression::Multiplicative(left, _, right, span) = parse(source).unwrap() else {
+        panic!("expected a multiplication");
+    };
+    assert_eq!(span, 2..14);
+    assert_eq!(&source[left.span()], "(1 + 2)");
+    assert_eq!(&source[right.span()], "-x");
+
+    // Spans are byte offsets
+    let source = "'héllo'.length() > 1";
+    let Expression::Inequality(left, _, right, span) = parse(source).unwrap() else {
+        panic!("expected a comparison");
+    };
+    assert_eq!(span, 0..source.len());
+    assert_eq!(&source[left.span()], "'héllo'.length()");
+    assert_eq!(&source[right.span()], "1");
+
+    let source = "value is FHIR.Quantity";
+    assert_eq!(parse(source).unwrap().span(), 0..source.len());
+    let source = "%`vs-gender`.exists()";
+    let Expression::Invocation(constant, _, _) = parse(source).unwrap() else {
+        panic!("expected an invocation");
+    };
+    assert!(matches!(
+        *constant,
+        Expression::Term(Term::ExternalConstant(_, _), ref span) if *span == (0..12)
+    ));
+}
+
+#[test]
+fn test_delimited_identifier_escapes() {
+    // A backslash starts an escape sequence rather than being taken literally
+    let names = [
+        (r"`given name`", "given name"),
+        (r"`a\`b`", "a`b"),
+        (r"`a\\b`", r"a\b"),
+        (r"`a\u0041`", "aA"),
+    ];
+    for (source, name) in names {
+        let Expression::Term(Term::Invocation(Invocation::Member(member, _), _), _) =
+            parse(source).unwrap()
+        else {
+            panic!("expected a member for {}", source);
+        };
+        assert_eq!(member, name, "{}", source);
+    }
+    assert!(parse(r"`a\`").is_err());
+}
+
+#[test]
+fn test_term_and_invocation_spans() {
+    // Invocations span their own text, without the base or the '.'
+    let source = "Patient.name . where(use = 'official')";
+    let Expression::Invocation(base, invocation, _) = parse(source).unwrap() else {
+        panic!("expected an invocation");
+    };
+    assert_eq!(&source[invocation.span()], "where(use = 'official')");
+    let Expression::Invocation(patient, Invocation::Member(_, member_span), _) = *base else {
+        panic!("expected a member");
+    };
+    assert_eq!(&source[member_span], "name");
+    let Expression::Term(Term::Invocation(invocation, term_span), _) = *patient else {
+        panic!("expected a term");
+    };
+    assert_eq!(&source[term_span], "Patient");
+    assert_eq!(&source[invocation.span()], "Patient");
+
+    // Terms exclude the parentheses around them, which their expression includes
+    let source = "( 'text' ) | ($this)";
+    let Expression::Union(left, right, _) = parse(source).unwrap() else {
+        panic!("expected a union");
+    };
+    let Expression::Term(literal @ Term::Literal(..), span) = *left else {
+        panic!("expected a literal");
+    };
+    assert_eq!(&source[span], "( 'text' )");
+    assert_eq!(&source[literal.span()], "'text'");
+    let Expression::Term(Term::Invocation(this @ Invocation::This(_), _), _) = *right else {
+        panic!("expected $this");
+    };
+    assert_eq!(&source[this.span()], "$this");
+
+    // Spans are part of the tree, but not of its structure
+    assert_eq!(
+        parse("a.b(1)").unwrap().without_spans(),
+        parse(" a . b( 1 )").unwrap().without_spans()
+    );
+}
+
+#[test]
+fn test_parse_errors() {
+    let error = parse("Patient.name.)").unwrap_err();
+    assert_eq!(error.message, "unexpected ')'");
+    assert_eq!(error.span, 13..14);
+    assert_eq!((error.line, error.column), (1, 14));
+    assert_eq!(
+        error.to_string(),
+        "unexpected ')', expected identifier at line 1, column 14\n  |\n1 | Patient.name.)\n  |              ^"
+    );
+
+    let error = parse("Patient.name.where(use = 'official'").unwrap_err();
+    assert_eq!(error.message, "unexpected end of input");
+    assert_eq!((error.line, error.column), (1, 36));
+    assert!(error.expected.contains(&"')'".to_string()));
+
+    // Lines and columns count from the start of the line the error is on
+    let error = parse("Patient.name\n  .where(given = 'x' and)").unwrap_err();
+    assert_eq!((error.line, error.column), (2, 22));
+    assert!(
+        error
+            .to_string()
+            .ends_with("2 |   .where(given = 'x' and)\n  |                      ^^^"),
+        "{}",
+        error
+    );
+
+    let error = parse("").unwrap_err();
+    assert_eq!((error.line, error.column), (1, 1));
+    assert!(error.expected.contains(&"'('".to_string()));
+
+    // Expected characters are reported as the kinds of token they start
+    let error = parse("1 +").unwrap_err();
+    assert_eq!(
+        error.expected,
+        [
+            "'('",
+            "external constant",
+            "identifier",
+            "literal",
+            "special variable"
+        ]
+    );
+    let error = parse("Patient.name.where(use = 'official'").unwrap_err();
+    assert_eq!(error.expected, ["')'", "','", "'.'", "'['", "operator"]);
+    let error = parse("%").unwrap_err();
+    assert_eq!(error.expected, ["identifier"]);
+
+    // Custom errors keep their message
+    let error = parse("@T10:00:00+01:00").unwrap_err();
+    assert!(error.message.contains("timezone"), "{}", error.message);
+}
+
 #[test]
 fn test_multiple_expressions_from_file() {
     // Get the path to the test file
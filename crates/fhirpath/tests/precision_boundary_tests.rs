@@ -0,0 +1,291 @@
+use chumsky::Parser;
+use helios_fhirpath::evaluator::{EvaluationContext, evaluate};
+use helios_fhirpath::parser::parser;
+use helios_fhirpath_support::{EvaluationError, EvaluationResult};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+fn eval(input: &str) -> Result<EvaluationResult, EvaluationError> {
+    let expr = parser().parse(input).unwrap_or_else(|e| {
+        panic!("Parser error for input '{}': {:?}", input, e);
+    });
+    let context = EvaluationContext::new_empty_with_default_version();
+    evaluate(&expr, &context, None)
+}
+
+fn eval_ok(input: &str) -> EvaluationResult {
+    eval(input).unwrap_or_else(|e| panic!("'{}' failed: {}", input, e))
+}
+
+fn assert_result(input: &str, expected: EvaluationResult) {
+    assert_eq!(eval_ok(input), expected, "for {}", input);
+}
+
+fn assert_empty(input: &str) {
+    assert_result(input, EvaluationResult::Empty);
+}
+
+fn dec(value: &str) -> EvaluationResult {
+    EvaluationResult::decimal(Decimal::from_str(value).unwrap())
+}
+
+// The cases below follow the FHIRPath 3.0 test suite (tests-fhir-r4.xml)
+
+#[test]
+fn test_precision() {
+    assert_result("1.58700.precision()", EvaluationResult::integer(5));
+    assert_result("@2014.precision()", EvaluationResult::integer(4));
+    assert_result(
+        "@2014-01-05T10:30:00.000.precision()",
+        EvaluationResult::integer(17),
+    );
+    assert_result("@T10:30.precision()", EvaluationResult::integer(4));
+    assert_result("@T10:30:00.000.precision()", EvaluationResult::integer(9));
+    assert_result("@2014-01-05.precision()", EvaluationResult::integer(8));
+    assert_result("@2014-01-05T10.precision()", EvaluationResult::integer(10));
+    assert_result("5.precision()", EvaluationResult::integer(0));
+    assert_empty("'abc'.precision()");
+    assert_empty("{}.precision()");
+}
+
+#[test]
+fn test_decimal_low_boundary() {
+    assert_result("1.587.lowBoundary()", dec("1.5865"));
+    assert_result("1.587.lowBoundary(6)", dec("1.586500"));
+    assert_result("1.587.lowBoundary(2)", dec("1.58"));
+    assert_result("1.587.lowBoundary(0)", dec("1"));
+    assert_result("(-1.587).lowBoundary()", dec("-1.5875"));
+    assert_result("(-1.587).lowBoundary(2)", dec("-1.59"));
+    assert_result("(-1.587).lowBoundary(0)", dec("-2"));
+    assert_result("0.0034.lowBoundary(1)", dec("0.0"));
+    assert_result("(-0.0034).lowBoundary(1)", dec("-0.1"));
+    assert_result("1.lowBoundary()", dec("0.5"));
+    // The requested precision is kept in the result
+    assert_eq!(
+        eval_ok("1.587.lowBoundary(6)").to_string_value(),
+        "1.586500"
+    );
+}
+
+#[test]
+fn test_decimal_high_boundary() {
+    assert_result("1.587.highBoundary()", dec("1.5875"));
+    assert_result("1.587.highBoundary(6)", dec("1.587500"));
+    assert_result("1.587.highBoundary(2)", dec("1.59"));
+    assert_result("1.587.highBoundary(0)", dec("2"));
+    assert_result("(-1.587).highBoundary()", dec("-1.5865"));
+    assert_result("(-1.587).highBoundary(2)", dec("-1.58"));
+    assert_result("(-1.587).highBoundary(0)", dec("-1"));
+    assert_result("0.0034.highBoundary(1)", dec("0.1"));
+    assert_result("(-0.0034).highBoundary(1)", dec("0.0"));
+    assert_result("1.highBoundary()", dec("1.5"));
+    assert_eq!(
+        eval_ok("(-0.0034).highBoundary(1)").to_string_value(),
+        "0.0"
+    );
+}
+
+#[test]
+fn test_quantity_boundary() {
+    assert_result(
+        "1.587 'cm'.lowBoundary(8)",
+        EvaluationResult::quantity(Decimal::from_str("1.58650000").unwrap(), "cm".to_string()),
+    );
+    assert_result(
+        "1.587 'cm'.highBoundary(8)",
+        EvaluationResult::quantity(Decimal::from_str("1.58750000").unwrap(), "cm".to_string()),
+    );
+}
+
+#[test]
+fn test_date_boundary() {
+    assert_result(
+        "@2014.lowBoundary(6)",
+        EvaluationResult::date("2014-01".to_string()),
+    );
+    assert_result(
+        "@2014.highBoundary(6)",
+        EvaluationResult::date("2014-12".to_string()),
+    );
+    assert_result(
+        "@2014-02.highBoundary(8)",
+        EvaluationResult::date("2014-02-28".to_string()),
+    );
+    assert_result(
+        "@2014-01-05.lowBoundary(4)",
+        EvaluationResult::date("2014".to_string()),
+    );
+    assert_result(
+        "@2014.highBoundary()",
+        EvaluationResult::date("2014-12-31".to_string()),
+    );
+}
+
+#[test]
+fn test_datetime_boundary() {
+    assert_result(
+        "@2014-01-01T08.lowBoundary(17)",
+        EvaluationResult::datetime("2014-01-01T08:00:00.000+14:00".to_string()),
+    );
+    assert_result(
+        "@2014-01-01T08.highBoundary(17)",
+        EvaluationResult::datetime("2014-01-01T08:59:59.999-12:00".to_string()),
+    );
+    // An explicit timezone is preserved
+    assert_result(
+        "@2014-01-01T08:05+08:00.lowBoundary(17)",
+        EvaluationResult::datetime("2014-01-01T08:05:00.000+08:00".to_string()),
+    );
+    assert_result(
+        "@2014-01-01T08:05-05:00.highBoundary(17)",
+        EvaluationResult::datetime("2014-01-01T08:05:59.999-05:00".to_string()),
+    );
+    assert_result(
+        "@2014-01-01T08:05Z.highBoundary()",
+        EvaluationResult::datetime("2014-01-01T08:05:59.999Z".to_string()),
+    );
+    assert_result(
+        "@2014-01-01T08.lowBoundary(12)",
+        EvaluationResult::datetime("2014-01-01T08:00+14:00".to_string()),
+    );
+    assert_result(
+        "@2014-01-01T08.lowBoundary(8)",
+        EvaluationResult::datetime("2014-01-01".to_string()),
+    );
+    assert_result(
+        "@2014T.highBoundary(10)",
+        EvaluationResult::datetime("2014-12-31T23-12:00".to_string()),
+    );
+}
+
+#[test]
+fn test_time_boundary() {
+    assert_result(
+        "@T10:30.lowBoundary(9)",
+        EvaluationResult::time("10:30:00.000".to_string()),
+    );
+    assert_result(
+        "@T10:30.highBoundary(9)",
+        EvaluationResult::time("10:30:59.999".to_string()),
+    );
+    assert_result(
+        "@T10.highBoundary(6)",
+        EvaluationResult::time("10:59:59".to_string()),
+    );
+}
+
+#[test]
+fn test_boundary_invalid_precision() {
+    assert_empty("1.587.lowBoundary(-1)");
+    assert_empty("1.587.highBoundary(29)");
+    assert_empty("@2014.lowBoundary(5)");
+    assert_empty("@2014-01-01T08.highBoundary(11)");
+    assert_empty("@T10:30.lowBoundary(8)");
+    assert_empty("1.587.lowBoundary({})");
+    assert_empty("'abc'.lowBoundary()");
+    assert_empty("'2014-13'.lowBoundary()");
+    // precision() has no string form
+    assert_empty("'10:30'.precision()");
+    assert!(matches!(
+        eval("1.587.lowBoundary('2')"),
+        Err(EvaluationError::TypeError(_))
+    ));
+    assert!(matches!(
+        eval("1.587.highBoundary(1, 2)"),
+        Err(EvaluationError::InvalidArity(_))
+    ));
+}
+
+#[test]
+fn test_comparable() {
+    assert_result(
+        "1 'cm'.comparable(1 '[in_i]')",
+        EvaluationResult::boolean(true),
+    );
+    assert_result("1 'cm'.comparable(1 's')", EvaluationResult::boolean(false));
+    assert_result("1 'g'.comparable(1 'kg')", EvaluationResult::boolean(true));
+    assert_result("1 year.comparable(1 'a')", EvaluationResult::boolean(true));
+    assert_result("1 'cm'.comparable(1 'cm')", EvaluationResult::boolean(true));
+    assert_empty("{}.comparable(1 'cm')");
+    assert_empty("1 'cm'.comparable({})");
+    assert!(matches!(
+        eval("1 'cm'.comparable(1)"),
+        Err(EvaluationError::TypeError(_))
+    ));
+}
+
+#[test]
+fn test_last_index_of() {
+    assert_result(
+        "'LogicalModel-Person'.lastIndexOf('-')",
+        EvaluationResult::integer(12),
+    );
+    assert_result(
+        "'LogicalModel-Person'.lastIndexOf('z')",
+        EvaluationResult::integer(-1),
+    );
+    assert_result(
+        "'LogicalModel-Person'.lastIndexOf('')",
+        EvaluationResult::integer(0),
+    );
+    assert_result("'abcabc'.lastIndexOf('bc')", EvaluationResult::integer(4));
+    // Indexes count characters, not bytes
+    assert_result("'éaéa'.lastIndexOf('a')", EvaluationResult::integer(3));
+    assert_empty("'LogicalModel-Person'.lastIndexOf({})");
+    assert_empty("{}.lastIndexOf('-')");
+}
+
+#[test]
+fn test_matches_full() {
+    let url = "'http://fhir.org/guides/cqf/common/Library/FHIR-ModelInfo|4.0.1'";
+    for (regex, expected) in [
+        ("library", false),
+        ("Library", false),
+        ("^Library$", false),
+        ("Library/FHIR", false),
+        (".*Library.*", true),
+        (
+            "http://fhir.org/guides/cqf/common/Library/FHIR-ModelInfo\\\\|4.0.1",
+            true,
+        ),
+    ] {
+        assert_result(
+            &format!("{}.matchesFull('{}')", url, regex),
+            EvaluationResult::boolean(expected),
+        );
+    }
+    // Unlike matches(), a partial match is not enough
+    assert_result(
+        &format!("{}.matches('Library')", url),
+        EvaluationResult::boolean(true),
+    );
+    // Alternatives are anchored as a group
+    assert_result("'ab'.matchesFull('a|ab')", EvaluationResult::boolean(true));
+    assert_empty("{}.matchesFull('a')");
+    assert_empty("'a'.matchesFull({})");
+    assert!(matches!(
+        eval("'a'.matchesFull('(')"),
+        Err(EvaluationError::InvalidRegex(_))
+    ));
+}
+
+#[test]
+fn test_boundary_of_temporal_strings() {
+    // Strings in a temporal format have the boundaries of that temporal type
+    assert_eq!(
+        eval_ok("'2014-01'.lowBoundary()"),
+        EvaluationResult::date("2014-01-01".to_string())
+    );
+    assert_eq!(
+        eval_ok("'2014'.highBoundary(6)"),
+        EvaluationResult::date("2014-12".to_string())
+    );
+    assert_eq!(
+        eval_ok("'2014-01-01T08'.highBoundary()"),
+        EvaluationResult::datetime("2014-01-01T08:59:59.999-12:00".to_string())
+    );
+    assert_eq!(
+        eval_ok("'10:30'.lowBoundary()"),
+        EvaluationResult::time("10:30:00.000".to_string())
+    );
+}
@@ -159,7 +159,7 @@ fn run_fhir_r4_test(
             // String vs. Code compatibility (since code is stored as String in our implementation)
             (EvaluationResult::String(a, _), EvaluationResult::Date(b, _)) => {
                 // A String can be equal to a Date in certain contexts
-                if a != b {
+                if a != b.as_str() {
                     return Err(format!(
                         "String/Date mismatch {} doesn't match: expected Date {:?}, got String {:?}",
                         i, b, a
@@ -168,7 +168,7 @@ fn run_fhir_r4_test(
             }
             (EvaluationResult::Date(a, _), EvaluationResult::String(b, _)) => {
                 // A Date can be equal to a String in certain contexts
-                if a != b {
+                if a.as_str() != b {
                     return Err(format!(
                         "Date/String mismatch {} doesn't match: expected String {:?}, got Date {:?}",
                         i, b, a
@@ -228,7 +228,7 @@ fn load_test_resource(json_filename: &str) -> Result<EvaluationContext, String>
     // Enhanced context setup for tests: For patient example, we'll add a direct birth date access path
     if json_filename == "patient-example.json" {
         // Clone relevant information before modifying the context
-        let patient_data = if let Some(this) = &context.this {
+        let patient_data = if let Some(this) = context.root_item() {
             if let EvaluationResult::Object { map: obj, .. } = this {
                 if obj.get("resourceType")
                     == Some(&EvaluationResult::String("Patient".to_string(), None))
@@ -282,7 +282,7 @@ fn load_test_resource(json_filename: &str) -> Result<EvaluationContext, String>
     // Enhanced context setup for Observation tests
     else if json_filename == "observation-example.json" {
         // Clone relevant information before modifying the context
-        let observation_data = if let Some(this) = &context.this {
+        let observation_data = if let Some(this) = context.root_item() {
             if let EvaluationResult::Object { map: obj, .. } = this {
                 if obj.get("resourceType")
                     == Some(&EvaluationResult::String("Observation".to_string(), None))
@@ -342,7 +342,7 @@ fn load_test_resource(json_filename: &str) -> Result<EvaluationContext, String>
     // Enhanced context setup for ValueSet tests
     else if json_filename == "valueset-example-expansion.json" {
         // Clone relevant information before modifying the context
-        let valueset_data = if let Some(this) = &context.this {
+        let valueset_data = if let Some(this) = context.root_item() {
             if let EvaluationResult::Object { map: obj, .. } = this {
                 if obj.get("resourceType")
                     == Some(&EvaluationResult::String("ValueSet".to_string(), None))
@@ -367,7 +367,7 @@ fn load_test_resource(json_filename: &str) -> Result<EvaluationContext, String>
     // Enhanced context setup for Questionnaire tests
     else if json_filename == "questionnaire-example.json" {
         // Clone relevant information before modifying the context
-        let questionnaire_data = if let Some(this) = &context.this {
+        let questionnaire_data = if let Some(this) = context.root_item() {
             if let EvaluationResult::Object { map: obj, .. } = this {
                 if obj.get("resourceType")
                     == Some(&EvaluationResult::String("Questionnaire".to_string(), None))
@@ -882,84 +882,75 @@ fn test_r4_test_suite() {
                     underscore_obj.insert("extension".to_string(), extensions);
 
                     // Get the patient object
-                    if let Some(this) = &context.this {
-                        if let EvaluationResult::Object { map: obj, .. } = this {
-                            let mut new_obj = obj.clone();
-
-                            // Make sure birthDate is an Object, not a String
-                            // First check the current birthDate value
-                            let mut birthdate_obj = HashMap::new();
-                            if let Some(EvaluationResult::String(date_str, None)) =
-                                new_obj.get("birthDate")
-                            {
-                                // Convert birthDate String to Object with value property
-                                birthdate_obj.insert(
-                                    "value".to_string(),
-                                    EvaluationResult::String(date_str.clone(), None),
-                                );
-                                new_obj.insert(
-                                    "birthDate".to_string(),
-                                    EvaluationResult::object(birthdate_obj),
-                                );
-                                println!(
-                                    "  DEBUG: Converted birthDate from String to Object for extension access"
-                                );
-                            }
-
-                            // Now add _birthDate with extension
-                            let underscore_birthdate = EvaluationResult::object(underscore_obj);
-                            new_obj.insert("_birthDate".to_string(), underscore_birthdate);
-
-                            // Add debug output
+                    if let Some(EvaluationResult::Object { map: obj, .. }) = context.root_item() {
+                        let mut new_obj = obj.clone();
+
+                        // Make sure birthDate is an Object, not a String
+                        // First check the current birthDate value
+                        let mut birthdate_obj = HashMap::new();
+                        if let Some(EvaluationResult::String(date_str, None)) =
+                            new_obj.get("birthDate")
+                        {
+                            // Convert birthDate String to Object with value property
+                            birthdate_obj.insert(
+                                "value".to_string(),
+                                EvaluationResult::String(date_str.clone(), None),
+                            );
+                            new_obj.insert(
+                                "birthDate".to_string(),
+                                EvaluationResult::object(birthdate_obj),
+                            );
                             println!(
-                                "  DEBUG: Setting up special extension test data for {}",
-                                test.name
+                                "  DEBUG: Converted birthDate from String to Object for extension access"
                             );
+                        }
 
-                            // Update the context this - first clone it for the Patient variable
-                            context.set_variable_result(
-                                "Patient",
-                                EvaluationResult::object(new_obj.clone()),
-                            );
+                        // Now add _birthDate with extension
+                        let underscore_birthdate = EvaluationResult::object(underscore_obj);
+                        new_obj.insert("_birthDate".to_string(), underscore_birthdate);
+
+                        // Add debug output
+                        println!(
+                            "  DEBUG: Setting up special extension test data for {}",
+                            test.name
+                        );
+
+                        // Update the context this - first clone it for the Patient variable
+                        context.set_variable_result(
+                            "Patient",
+                            EvaluationResult::object(new_obj.clone()),
+                        );
 
-                            // Then use it for the this context
-                            context.set_this(EvaluationResult::object(new_obj));
-
-                            // Debug verification
-                            if let Some(this_val) = &context.this {
-                                if let EvaluationResult::Object { map: obj, .. } = this_val {
-                                    if let Some(birthdate_ext) = obj.get("_birthDate") {
-                                        println!("  DEBUG: _birthDate is present in context.this");
-
-                                        // Check for extensions
-                                        if let EvaluationResult::Object { map: bd_obj, .. } =
-                                            birthdate_ext
-                                        {
-                                            if let Some(exts) = bd_obj.get("extension") {
-                                                println!(
-                                                    "  DEBUG: _birthDate.extension is present"
-                                                );
-                                                println!(
-                                                    "  DEBUG: _birthDate.extension = {:?}",
-                                                    exts
-                                                );
-                                            } else {
-                                                println!(
-                                                    "  DEBUG: _birthDate has no extension property"
-                                                );
-                                            }
+                        // Then use it for the this context
+                        context.set_this(EvaluationResult::object(new_obj));
+
+                        // Debug verification
+                        if let Some(this_val) = context.root_item() {
+                            if let EvaluationResult::Object { map: obj, .. } = this_val {
+                                if let Some(birthdate_ext) = obj.get("_birthDate") {
+                                    println!("  DEBUG: _birthDate is present in context.this");
+
+                                    // Check for extensions
+                                    if let EvaluationResult::Object { map: bd_obj, .. } =
+                                        birthdate_ext
+                                    {
+                                        if let Some(exts) = bd_obj.get("extension") {
+                                            println!("  DEBUG: _birthDate.extension is present");
+                                            println!("  DEBUG: _birthDate.extension = {:?}", exts);
+                                        } else {
+                                            println!(
+                                                "  DEBUG: _birthDate has no extension property"
+                                            );
                                         }
-                                    } else {
-                                        println!(
-                                            "  DEBUG: _birthDate is NOT present in context.this"
-                                        );
                                     }
                                 } else {
-                                    println!("  DEBUG: this is not an Object");
+                                    println!("  DEBUG: _birthDate is NOT present in context.this");
                                 }
                             } else {
-                                println!("  DEBUG: this is None");
+                                println!("  DEBUG: this is not an Object");
                             }
+                        } else {
+                            println!("  DEBUG: this is None");
                         }
                     }
                 }
@@ -995,17 +986,19 @@ fn test_r4_test_suite() {
                     "string" => {
                         expected_results.push(EvaluationResult::String(output_value.clone(), None));
                     }
-                    // Support for additional FHIR types that are stored as strings in our implementation
                     "date" => {
-                        // Currently dates are stored as strings in our implementation
-                        expected_results.push(EvaluationResult::Date(output_value.clone(), None));
+                        expected_results
+                            .push(EvaluationResult::Date(output_value.clone().into(), None));
                     }
                     "dateTime" => {
-                        expected_results
-                            .push(EvaluationResult::DateTime(output_value.clone(), None));
+                        expected_results.push(EvaluationResult::DateTime(
+                            output_value.clone().into(),
+                            None,
+                        ));
                     }
                     "time" => {
-                        expected_results.push(EvaluationResult::Time(output_value.clone(), None));
+                        expected_results
+                            .push(EvaluationResult::Time(output_value.clone().into(), None));
                     }
                     "code" => {
                         // FHIR code type is also just a string in our implementation
@@ -1075,29 +1068,6 @@ fn test_r4_test_suite() {
                 continue;
             }
 
-            // Skip specific UCUM quantity tests that we're not implementing yet
-            let quantity_tests_to_ignore = [
-                "testQuantity1",
-                "testQuantity2",
-                "testQuantity4",
-                "testQuantity5",
-                "testQuantity6",
-                "testQuantity7",
-                "testQuantity8",
-                "testQuantity9",
-                "testQuantity10",
-                "testQuantity11",
-            ];
-
-            if quantity_tests_to_ignore.contains(&test.name.as_str()) {
-                println!(
-                    "  SKIP (UCUM not implemented): {} - '{}'",
-                    test.name, test.expression
-                );
-                skipped_tests += 1;
-                continue;
-            }
-
             // Run the test
             let is_predicate_test = test.predicate == "true";
             let test_run_result = run_fhir_r4_test(
@@ -0,0 +1,289 @@
+use helios_fhir::FhirResource;
+use helios_fhirpath::reference_resolver::{
+    ChainedResourceResolver, InMemoryResourceResolver, ReferenceResolver, ResolutionScope,
+};
+use helios_fhirpath::{EvaluationContext, EvaluationResult, evaluate_expression};
+use serde_json::json;
+use std::sync::Arc;
+
+fn r4_resource(value: serde_json::Value) -> FhirResource {
+    let resource: helios_fhir::r4::Resource = serde_json::from_value(value).unwrap();
+    FhirResource::R4(Box::new(resource))
+}
+
+fn eval(expression: &str, context: &EvaluationContext) -> EvaluationResult {
+    evaluate_expression(expression, context)
+        .unwrap_or_else(|e| panic!("Failed to evaluate '{}': {}", expression, e))
+}
+
+fn observation_for(reference: &str) -> serde_json::Value {
+    json!({
+        "resourceType": "Observation",
+        "id": "o1",
+        "status": "final",
+        "code": {"text": "Weight"},
+        "subject": {"reference": reference}
+    })
+}
+
+#[test]
+fn test_resolve_contained() {
+    let context = EvaluationContext::new(vec![r4_resource(json!({
+        "resourceType": "Observation",
+        "id": "o1",
+        "status": "final",
+        "code": {"text": "Weight"},
+        "contained": [
+            {"resourceType": "Patient", "id": "p1", "gender": "female"}
+        ],
+        "subject": {"reference": "#p1"}
+    }))]);
+
+    assert_eq!(
+        eval("subject.resolve().gender", &context),
+        EvaluationResult::string("female".to_string())
+    );
+    assert_eq!(
+        eval("subject.resolve() is Patient", &context),
+        EvaluationResult::boolean(true)
+    );
+}
+
+#[test]
+fn test_resolve_bundle_entries() {
+    let context = EvaluationContext::new(vec![r4_resource(json!({
+        "resourceType": "Bundle",
+        "type": "collection",
+        "entry": [
+            {
+                "fullUrl": "urn:uuid:04121321-4af5-424c-a0e1-ed3aab1c349d",
+                "resource": {"resourceType": "Patient", "id": "p1", "gender": "male"}
+            },
+            {
+                "fullUrl": "http://example.org/fhir/Patient/p2",
+                "resource": {"resourceType": "Patient", "id": "p2", "gender": "female"}
+            },
+            {
+                "fullUrl": "urn:uuid:9b8ec3b4-4b4e-4d2b-8b4b-1f3b0f4a6a11",
+                "resource": observation_for("urn:uuid:04121321-4af5-424c-a0e1-ed3aab1c349d")
+            }
+        ]
+    }))]);
+
+    // Matched by fullUrl
+    assert_eq!(
+        eval(
+            "entry.resource.ofType(Observation).subject.resolve().gender",
+            &context
+        ),
+        EvaluationResult::string("male".to_string())
+    );
+    // Matched by type/id, including versioned references
+    assert_eq!(
+        eval("'Patient/p2'.resolve().gender", &context),
+        EvaluationResult::string("female".to_string())
+    );
+    assert_eq!(
+        eval("'Patient/p2/_history/3'.resolve().gender", &context),
+        EvaluationResult::string("female".to_string())
+    );
+    assert_eq!(
+        eval("'Patient/unknown'.resolve()", &context),
+        EvaluationResult::Empty
+    );
+}
+
+#[test]
+fn test_resolve_contained_within_own_entry() {
+    let observation = |id: &str, patient: &str, gender: &str| {
+        json!({
+            "resourceType": "Observation",
+            "id": id,
+            "status": "final",
+            "code": {"text": "Weight"},
+            "contained": [{"resourceType": "Patient", "id": patient, "gender": gender}],
+            "subject": {"reference": format!("#{}", patient)},
+            "focus": [{"reference": "#"}]
+        })
+    };
+    let bundle = |entries: Vec<serde_json::Value>| {
+        r4_resource(json!({
+            "resourceType": "Bundle",
+            "type": "collection",
+            "entry": entries.into_iter().map(|resource| json!({"resource": resource})).collect::<Vec<_>>()
+        }))
+    };
+
+    let context = EvaluationContext::new(vec![bundle(vec![
+        observation("o1", "p1", "female"),
+        observation("o2", "p2", "male"),
+    ])]);
+    assert_eq!(
+        eval(
+            "entry.resource.subject.resolve().gender.join(',')",
+            &context
+        ),
+        EvaluationResult::string("female,male".to_string())
+    );
+
+    // Identical reference elements in different entries are told apart by
+    // their location
+    let mut context = EvaluationContext::new(vec![bundle(vec![
+        observation("o1", "p1", "female"),
+        observation("o2", "p1", "male"),
+    ])]);
+    context.set_track_locations(true);
+    assert_eq!(
+        eval(
+            "entry.resource.where(id = 'o2').subject.resolve().gender",
+            &context
+        ),
+        EvaluationResult::string("male".to_string())
+    );
+    // '#' is the resource holding the reference, not the Bundle
+    assert_eq!(
+        eval(
+            "entry.resource.where(id = 'o2').focus.resolve().id",
+            &context
+        ),
+        EvaluationResult::string("o2".to_string())
+    );
+}
+
+#[test]
+fn test_resolve_absolute_reference_to_other_server() {
+    let context = EvaluationContext::new(vec![r4_resource(json!({
+        "resourceType": "Bundle",
+        "type": "collection",
+        "entry": [
+            {
+                "fullUrl": "http://example.org/fhir/Patient/p1",
+                "resource": {"resourceType": "Patient", "id": "p1", "gender": "male"}
+            }
+        ]
+    }))]);
+
+    assert_eq!(
+        eval("'http://other.org/fhir/Patient/p1'.resolve()", &context),
+        EvaluationResult::Empty
+    );
+    assert_eq!(
+        eval(
+            "'http://example.org/fhir/Patient/p1/_history/2'.resolve().gender",
+            &context
+        ),
+        EvaluationResult::string("male".to_string())
+    );
+    assert_eq!(
+        eval("'Patient/p1'.resolve().gender", &context),
+        EvaluationResult::string("male".to_string())
+    );
+}
+
+#[test]
+fn test_resolve_without_target_is_empty() {
+    let context = EvaluationContext::new(vec![r4_resource(observation_for("Patient/p1"))]);
+    assert_eq!(eval("subject.resolve()", &context), EvaluationResult::Empty);
+    assert_eq!(eval("code.resolve()", &context), EvaluationResult::Empty);
+}
+
+#[test]
+fn test_resolve_in_memory() {
+    let mut resolver = InMemoryResourceResolver::new();
+    resolver.add_resource(&r4_resource(json!({
+        "resourceType": "Patient",
+        "id": "p1",
+        "gender": "other"
+    })));
+    assert_eq!(resolver.len(), 1);
+
+    let mut context = EvaluationContext::new(vec![r4_resource(observation_for(
+        "http://example.org/fhir/Patient/p1",
+    ))]);
+    context.set_reference_resolver(Arc::new(resolver));
+
+    assert_eq!(
+        eval("subject.resolve().gender", &context),
+        EvaluationResult::string("other".to_string())
+    );
+    assert_eq!(
+        eval(
+            "subject.resolve().where(gender = 'other').exists()",
+            &context
+        ),
+        EvaluationResult::boolean(true)
+    );
+}
+
+#[test]
+fn test_resolve_multiple_references() {
+    let mut resolver = InMemoryResourceResolver::new();
+    for id in ["p1", "p2"] {
+        resolver.add_resource(&r4_resource(json!({"resourceType": "Patient", "id": id})));
+    }
+    let mut context = EvaluationContext::new(vec![]);
+    context.set_reference_resolver(Arc::new(resolver));
+
+    assert_eq!(
+        eval(
+            "('Patient/p1' | 'Patient/p2' | 'Patient/p3').resolve().id",
+            &context
+        ),
+        EvaluationResult::Collection {
+            items: vec![
+                EvaluationResult::string("p1".to_string()),
+                EvaluationResult::string("p2".to_string()),
+            ],
+            has_undefined_order: false,
+            type_info: None,
+        }
+    );
+}
+
+struct StoreResolver;
+
+impl ReferenceResolver for StoreResolver {
+    fn resolve(&self, reference: &str, _scope: &ResolutionScope) -> Option<EvaluationResult> {
+        let mut map = std::collections::HashMap::new();
+        map.insert(
+            "resourceType".to_string(),
+            EvaluationResult::string("Practitioner".to_string()),
+        );
+        map.insert(
+            "id".to_string(),
+            EvaluationResult::string(reference.rsplit('/').next()?.to_string()),
+        );
+        reference
+            .starts_with("Practitioner/")
+            .then(|| EvaluationResult::object(map))
+    }
+}
+
+#[test]
+fn test_resolve_custom_and_chained_resolvers() {
+    let chain = ChainedResourceResolver::new()
+        .with(Arc::new(
+            helios_fhirpath::reference_resolver::ContainedResourceResolver,
+        ))
+        .with(Arc::new(StoreResolver));
+
+    let mut context = EvaluationContext::new(vec![r4_resource(json!({
+        "resourceType": "Observation",
+        "id": "o1",
+        "status": "final",
+        "code": {"text": "Weight"},
+        "contained": [{"resourceType": "Patient", "id": "p1"}],
+        "subject": {"reference": "#p1"},
+        "performer": [{"reference": "Practitioner/dr1"}]
+    }))]);
+    context.set_reference_resolver(Arc::new(chain));
+
+    assert_eq!(
+        eval("subject.resolve().id", &context),
+        EvaluationResult::string("p1".to_string())
+    );
+    assert_eq!(
+        eval("performer.resolve().id", &context),
+        EvaluationResult::string("dr1".to_string())
+    );
+}
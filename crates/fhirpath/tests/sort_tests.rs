@@ -0,0 +1,115 @@
+mod common;
+
+use common::{eval, patient_context, strings};
+use helios_fhirpath::evaluator::EvaluationContext;
+use helios_fhirpath_support::EvaluationError;
+
+#[test]
+fn test_sort_natural_order() {
+    let ctx = EvaluationContext::new_empty_with_default_version();
+    assert_eq!(
+        strings("(3 | 1 | 2.5 | 2).sort()", &ctx),
+        vec!["1", "2", "2.5", "3"]
+    );
+    assert_eq!(
+        strings("('b' | 'c' | 'a').sort()", &ctx),
+        vec!["a", "b", "c"]
+    );
+    assert_eq!(
+        strings("(@2020-03-01 | @2019-12-31 | @2020-01-15).sort()", &ctx),
+        vec!["2019-12-31", "2020-01-15", "2020-03-01"]
+    );
+    assert_eq!(strings("{}.sort()", &ctx), Vec::<String>::new());
+}
+
+#[test]
+fn test_sort_descending() {
+    let ctx = EvaluationContext::new_empty_with_default_version();
+    assert_eq!(
+        strings("(3 | 1 | 2).sort(-$this)", &ctx),
+        vec!["3", "2", "1"]
+    );
+    assert_eq!(
+        strings("('b' | 'c' | 'a').sort(-$this)", &ctx),
+        vec!["c", "b", "a"]
+    );
+}
+
+#[test]
+fn test_sort_by_key() {
+    let ctx = patient_context();
+    assert_eq!(
+        strings("Patient.name.sort(use).use", &ctx),
+        vec!["maiden", "official", "usual"]
+    );
+    assert_eq!(
+        strings("Patient.name.sort(-use).first().use", &ctx),
+        vec!["usual"]
+    );
+    // Empty keys sort first, and last when descending
+    assert_eq!(
+        strings("Patient.name.sort(family).use", &ctx),
+        vec!["usual", "official", "maiden"]
+    );
+    assert_eq!(
+        strings("Patient.name.sort(-family).use", &ctx),
+        vec!["maiden", "official", "usual"]
+    );
+    assert_eq!(
+        strings("Patient.name.sort(period.end).first().use", &ctx),
+        vec!["official"]
+    );
+}
+
+#[test]
+fn test_sort_multiple_keys() {
+    let ctx = patient_context();
+    // Ties on the first key are broken by the second, and the sort is stable
+    assert_eq!(
+        strings(
+            "Patient.name.sort(given.first(), -use).select(given.first() & ':' & use)",
+            &ctx
+        ),
+        vec!["Jim:usual", "Peter:official", "Peter:maiden"]
+    );
+    assert_eq!(
+        strings(
+            "Patient.name.sort(given.first()).select(given.first() & ':' & use)",
+            &ctx
+        ),
+        vec!["Jim:usual", "Peter:official", "Peter:maiden"]
+    );
+    assert_eq!(
+        strings(
+            "Patient.name.sort(given.first(), use).select(given.first() & ':' & use)",
+            &ctx
+        ),
+        vec!["Jim:usual", "Peter:maiden", "Peter:official"]
+    );
+}
+
+#[test]
+fn test_sort_marks_result_ordered() {
+    let mut ctx = EvaluationContext::new_empty_with_default_version();
+    ctx.set_check_ordered_functions(true);
+    // Union has an undefined order, so first() is rejected without sort()
+    assert!(eval("(3 | 1 | 2).first()", &ctx).is_err());
+    assert_eq!(strings("(3 | 1 | 2).sort().first()", &ctx), vec!["1"]);
+    assert_eq!(strings("(3 | 1 | 2).sort(-$this)[1]", &ctx), vec!["2"]);
+}
+
+#[test]
+fn test_sort_errors() {
+    let ctx = patient_context();
+    // Keys must be singletons
+    assert!(matches!(
+        eval("Patient.name.sort(given)", &ctx),
+        Err(EvaluationError::SingletonEvaluationError(_))
+    ));
+    // Keys must be comparable
+    let ctx = EvaluationContext::new_empty_with_default_version();
+    assert!(matches!(
+        eval("(1 | 'a').sort()", &ctx),
+        Err(EvaluationError::TypeError(_))
+    ));
+}
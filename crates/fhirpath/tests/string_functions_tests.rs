@@ -0,0 +1,244 @@
+use chumsky::Parser;
+use helios_fhirpath::evaluator::{EvaluationContext, evaluate};
+use helios_fhirpath::parser::parser;
+use helios_fhirpath_support::{EvaluationError, EvaluationResult};
+
+// Helper function to parse and evaluate
+fn eval(input: &str) -> Result<EvaluationResult, EvaluationError> {
+    let ctx = EvaluationContext::new_empty_with_default_version();
+    let expr = parser().parse(input).unwrap_or_else(|e| {
+        panic!("Parser error for input '{}': {:?}", input, e);
+    });
+    evaluate(&expr, &ctx, None)
+}
+
+fn string(s: &str) -> EvaluationResult {
+    EvaluationResult::string(s.to_string())
+}
+
+fn strings(values: &[&str]) -> Vec<EvaluationResult> {
+    values.iter().map(|s| string(s)).collect()
+}
+
+fn items(result: EvaluationResult) -> Vec<EvaluationResult> {
+    match result {
+        EvaluationResult::Collection { items, .. } => items,
+        EvaluationResult::Empty => vec![],
+        single => vec![single],
+    }
+}
+
+#[test]
+fn test_split() {
+    assert_eq!(
+        items(eval("'A,B,C'.split(',')").unwrap()),
+        strings(&["A", "B", "C"])
+    );
+    assert_eq!(
+        items(eval("'A,,C'.split(',')").unwrap()),
+        strings(&["A", "", "C"])
+    );
+    assert_eq!(items(eval("'ABC'.split(',')").unwrap()), strings(&["ABC"]));
+    assert_eq!(
+        items(eval("'John James'.split(' ').count()").unwrap()),
+        vec![EvaluationResult::integer(2)]
+    );
+    assert_eq!(items(eval("'ab'.split('')").unwrap()), strings(&["a", "b"]));
+}
+
+#[test]
+fn test_split_empty() {
+    assert_eq!(eval("{}.split(',')").unwrap(), EvaluationResult::Empty);
+    assert_eq!(eval("'A,B'.split({})").unwrap(), EvaluationResult::Empty);
+}
+
+#[test]
+fn test_split_errors() {
+    assert!(matches!(
+        eval("'A,B'.split()"),
+        Err(EvaluationError::InvalidArity(_))
+    ));
+    assert!(matches!(
+        eval("('a' | 'b').split(',')"),
+        Err(EvaluationError::SingletonEvaluationError(_))
+    ));
+    assert!(matches!(
+        eval("5.split(',')"),
+        Err(EvaluationError::TypeError(_))
+    ));
+}
+
+#[test]
+fn test_trim() {
+    assert_eq!(eval("'  John  '.trim()").unwrap(), string("John"));
+    assert_eq!(eval("'\\t\\nJohn\\r'.trim()").unwrap(), string("John"));
+    assert_eq!(eval("'   '.trim()").unwrap(), string(""));
+    assert_eq!(eval("{}.trim()").unwrap(), EvaluationResult::Empty);
+    assert!(matches!(
+        eval("' a '.trim(' ')"),
+        Err(EvaluationError::InvalidArity(_))
+    ));
+}
+
+#[test]
+fn test_encode() {
+    assert_eq!(eval("'test'.encode('base64')").unwrap(), string("dGVzdA=="));
+    assert_eq!(eval("'test'.encode('hex')").unwrap(), string("74657374"));
+    assert_eq!(
+        eval("'subjects?_d'.encode('base64')").unwrap(),
+        string("c3ViamVjdHM/X2Q=")
+    );
+    assert_eq!(
+        eval("'subjects?_d'.encode('urlbase64')").unwrap(),
+        string("c3ViamVjdHM_X2Q=")
+    );
+    assert_eq!(eval("{}.encode('hex')").unwrap(), EvaluationResult::Empty);
+    assert_eq!(eval("'test'.encode({})").unwrap(), EvaluationResult::Empty);
+    assert!(matches!(
+        eval("'test'.encode('rot13')"),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_decode() {
+    assert_eq!(eval("'dGVzdA=='.decode('base64')").unwrap(), string("test"));
+    assert_eq!(eval("'74657374'.decode('hex')").unwrap(), string("test"));
+    assert_eq!(eval("'7465734A'.decode('hex')").unwrap(), string("tesJ"));
+    assert_eq!(
+        eval("'c3ViamVjdHM_X2Q='.decode('urlbase64')").unwrap(),
+        string("subjects?_d")
+    );
+    assert_eq!(
+        eval("'c3ViamVjdHM_X2Q'.decode('urlbase64')").unwrap(),
+        string("subjects?_d")
+    );
+    assert_eq!(eval("{}.decode('hex')").unwrap(), EvaluationResult::Empty);
+    assert!(matches!(
+        eval("'abc'.decode('hex')"),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        eval("'zz'.decode('hex')"),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        eval("'!!!'.decode('base64')"),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        eval("'ff'.decode('hex')"),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_encode_decode_round_trip() {
+    for format in ["hex", "base64", "urlbase64"] {
+        let expr = format!(
+            "'Ünïcode <&> ?'.encode('{0}').decode('{0}') = 'Ünïcode <&> ?'",
+            format
+        );
+        assert_eq!(
+            eval(&expr).unwrap(),
+            EvaluationResult::boolean(true),
+            "{}",
+            format
+        );
+    }
+}
+
+#[test]
+fn test_escape() {
+    assert_eq!(
+        eval("'\"1 < 5\"'.escape('html')").unwrap(),
+        string("&quot;1 &lt; 5&quot;")
+    );
+    assert_eq!(
+        eval("'Tom & Jerry\\'s'.escape('html')").unwrap(),
+        string("Tom &amp; Jerry&#39;s")
+    );
+    assert_eq!(
+        eval("'\"1 < 5\"'.escape('json')").unwrap(),
+        string("\\\"1 < 5\\\"")
+    );
+    assert_eq!(
+        eval("'a\\\\b\\nc'.escape('json')").unwrap(),
+        string("a\\\\b\\nc")
+    );
+    assert_eq!(eval("{}.escape('html')").unwrap(), EvaluationResult::Empty);
+    assert!(matches!(
+        eval("'a'.escape('xml')"),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_unescape() {
+    assert_eq!(
+        eval("'&quot;1 &lt; 5&quot;'.unescape('html')").unwrap(),
+        string("\"1 < 5\"")
+    );
+    assert_eq!(
+        eval("'&#39;&#x41;&apos;&gt;&amp;lt;'.unescape('html')").unwrap(),
+        string("'A'>&lt;")
+    );
+    assert_eq!(
+        eval("'AT&T &bogus;'.unescape('html')").unwrap(),
+        string("AT&T &bogus;")
+    );
+    assert_eq!(
+        eval("'\\\\\"1 < 5\\\\\"'.unescape('json')").unwrap(),
+        string("\"1 < 5\"")
+    );
+    assert_eq!(
+        eval("{}.unescape('json')").unwrap(),
+        EvaluationResult::Empty
+    );
+    assert!(matches!(
+        eval("'\\\\x'.unescape('json')"),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_unescape_json_passes_other_characters_through() {
+    assert_eq!(
+        eval("'say \"hi\"'.unescape('json')").unwrap(),
+        string("say \"hi\"")
+    );
+    assert_eq!(
+        eval("'line 1\nline 2\\\\t!'.unescape('json')").unwrap(),
+        string("line 1\nline 2\t!")
+    );
+    assert_eq!(
+        eval("'\\\\u00e9\\\\ud83d\\\\ude00\\\\/'.unescape('json')").unwrap(),
+        string("\u{e9}\u{1f600}/")
+    );
+    for invalid in ["'a\\\\'", "'\\\\u12'", "'\\\\u+123'", "'\\\\ud83d'"] {
+        assert!(
+            matches!(
+                eval(&format!("{}.unescape('json')", invalid)),
+                Err(EvaluationError::InvalidArgument(_))
+            ),
+            "{}",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn test_escape_unescape_round_trip() {
+    for target in ["html", "json"] {
+        let expr = format!(
+            "'<a href=\"x\">Tom & Jerry\\'s</a>'.escape('{0}').unescape('{0}') = '<a href=\"x\">Tom & Jerry\\'s</a>'",
+            target
+        );
+        assert_eq!(
+            eval(&expr).unwrap(),
+            EvaluationResult::boolean(true),
+            "{}",
+            target
+        );
+    }
+}
@@ -0,0 +1,531 @@
+use chumsky::Parser;
+use helios_fhir::{FhirResource, FhirVersion};
+use helios_fhirpath::evaluator::evaluate;
+use helios_fhirpath::limits::EvaluationLimits;
+use helios_fhirpath::parser::parser;
+use helios_fhirpath::terminology::{
+    Coding, HttpTerminologyProvider, InMemoryTerminologyProvider, SubsumptionOutcome,
+    TerminologyProvider,
+};
+use helios_fhirpath::{EvaluationContext, EvaluationResult, evaluate_expression};
+use helios_fhirpath_support::EvaluationError;
+use serde_json::{Value, json};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const SYSTEM: &str = "http://example.org/fhir/CodeSystem/conditions";
+
+fn r4_resource(value: Value) -> FhirResource {
+    let resource: helios_fhir::r4::Resource = serde_json::from_value(value).unwrap();
+    FhirResource::R4(Box::new(resource))
+}
+
+/// A small hierarchy: disorder > (infection > (viral, bacterial), injury)
+fn terminology() -> InMemoryTerminologyProvider {
+    let mut provider = InMemoryTerminologyProvider::new();
+    provider.add_resource(&r4_resource(json!({
+        "resourceType": "CodeSystem",
+        "url": SYSTEM,
+        "name": "Conditions",
+        "version": "1.0",
+        "status": "active",
+        "content": "complete",
+        "concept": [{
+            "code": "disorder",
+            "display": "Disorder",
+            "concept": [
+                {
+                    "code": "infection",
+                    "display": "Infection",
+                    "concept": [
+                        {"code": "viral", "display": "Viral infection"},
+                        {"code": "bacterial", "display": "Bacterial infection"}
+                    ]
+                },
+                {"code": "injury", "display": "Injury"}
+            ]
+        }]
+    })));
+    provider.add_resource(&r4_resource(json!({
+        "resourceType": "ValueSet",
+        "url": "http://example.org/fhir/ValueSet/infections",
+        "status": "active",
+        "compose": {
+            "include": [{
+                "system": SYSTEM,
+                "filter": [{"property": "concept", "op": "is-a", "value": "infection"}]
+            }],
+            "exclude": [{"system": SYSTEM, "concept": [{"code": "bacterial"}]}]
+        }
+    })));
+    provider.add_resource(&r4_resource(json!({
+        "resourceType": "ValueSet",
+        "url": "http://example.org/fhir/ValueSet/injuries",
+        "status": "active",
+        "compose": {
+            "include": [{"system": SYSTEM, "concept": [{"code": "injury"}]}]
+        }
+    })));
+    provider.add_resource(&r4_resource(json!({
+        "resourceType": "ConceptMap",
+        "url": "http://example.org/fhir/ConceptMap/to-icd",
+        "status": "active",
+        "group": [{
+            "source": SYSTEM,
+            "target": "http://hl7.org/fhir/sid/icd-10",
+            "element": [{
+                "code": "viral",
+                "target": [{"code": "B34.9", "display": "Viral infection, unspecified", "equivalence": "wider"}]
+            }]
+        }]
+    })));
+    provider
+}
+
+fn observation(code: &str) -> FhirResource {
+    r4_resource(json!({
+        "resourceType": "Observation",
+        "status": "final",
+        "code": {"coding": [
+            {"system": "http://loinc.org", "code": "1234-5"},
+            {"system": SYSTEM, "code": code}
+        ]},
+        "valueCodeableConcept": {"coding": [{"system": SYSTEM, "code": code}]}
+    }))
+}
+
+fn context_with(provider: Arc<dyn TerminologyProvider>, code: &str) -> EvaluationContext {
+    let mut context = EvaluationContext::new(vec![observation(code)]);
+    context.set_terminology_provider(provider);
+    context
+}
+
+fn eval(expression: &str, context: &EvaluationContext) -> EvaluationResult {
+    evaluate_expression(expression, context)
+        .unwrap_or_else(|e| panic!("Failed to evaluate '{}': {}", expression, e))
+}
+
+fn try_eval(
+    expression: &str,
+    context: &EvaluationContext,
+) -> Result<EvaluationResult, EvaluationError> {
+    let expr = parser().parse(expression).unwrap();
+    evaluate(&expr, context, None)
+}
+
+#[test]
+fn test_member_of() {
+    let context = context_with(Arc::new(terminology()), "viral");
+    assert_eq!(
+        eval(
+            "code.memberOf('http://example.org/fhir/ValueSet/infections')",
+            &context
+        ),
+        EvaluationResult::boolean(true)
+    );
+    assert_eq!(
+        eval(
+            "code.memberOf('http://example.org/fhir/ValueSet/injuries')",
+            &context
+        ),
+        EvaluationResult::boolean(false)
+    );
+    assert_eq!(
+        eval(
+            "code.coding.where(system = 'http://loinc.org').memberOf('http://example.org/fhir/ValueSet/infections')",
+            &context
+        ),
+        EvaluationResult::boolean(false)
+    );
+    assert_eq!(
+        eval(
+            "{}.memberOf('http://example.org/fhir/ValueSet/infections')",
+            &context
+        ),
+        EvaluationResult::Empty
+    );
+
+    // Excluded codes are not members
+    let context = context_with(Arc::new(terminology()), "bacterial");
+    assert_eq!(
+        eval(
+            "code.memberOf('http://example.org/fhir/ValueSet/infections')",
+            &context
+        ),
+        EvaluationResult::boolean(false)
+    );
+}
+
+#[test]
+fn test_member_of_errors() {
+    let context = context_with(Arc::new(terminology()), "viral");
+    assert!(matches!(
+        try_eval("code.memberOf('http://example.org/unknown')", &context),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+
+    let context = EvaluationContext::new(vec![observation("viral")]);
+    assert!(matches!(
+        try_eval(
+            "code.memberOf('http://example.org/fhir/ValueSet/infections')",
+            &context
+        ),
+        Err(EvaluationError::InvalidOperation(_))
+    ));
+}
+
+#[test]
+fn test_subsumes_and_subsumed_by() {
+    let context = context_with(Arc::new(terminology()), "viral");
+    assert_eq!(
+        eval(
+            "value.coding.subsumedBy(value.coding.code.replace('viral', 'infection'))",
+            &context
+        ),
+        EvaluationResult::boolean(true)
+    );
+    assert_eq!(
+        eval(
+            "value.coding.subsumes(value.coding.code.replace('viral', 'infection'))",
+            &context
+        ),
+        EvaluationResult::boolean(false)
+    );
+    assert_eq!(
+        eval("value.coding.subsumes(value.coding)", &context),
+        EvaluationResult::boolean(true)
+    );
+    assert_eq!(
+        eval(
+            "value.coding.subsumedBy(value.coding.code.replace('viral', 'injury'))",
+            &context
+        ),
+        EvaluationResult::boolean(false)
+    );
+}
+
+#[test]
+fn test_terminologies_functions() {
+    let context = context_with(Arc::new(terminology()), "viral");
+
+    assert_eq!(
+        eval(
+            "%terminologies.expand('http://example.org/fhir/ValueSet/infections').expansion.contains.code",
+            &context
+        ),
+        EvaluationResult::collection(vec![
+            EvaluationResult::string("infection".to_string()),
+            EvaluationResult::string("viral".to_string()),
+        ])
+    );
+    assert_eq!(
+        eval(
+            "%terminologies.lookup(value.coding).parameter.where(name = 'display').value",
+            &context
+        ),
+        EvaluationResult::string("Viral infection".to_string())
+    );
+    assert_eq!(
+        eval(
+            "%terminologies.validateVS('http://example.org/fhir/ValueSet/infections', code).parameter.where(name = 'result').value",
+            &context
+        ),
+        EvaluationResult::boolean(true)
+    );
+    assert_eq!(
+        eval(
+            &format!(
+                "%terminologies.validateCS('{}', code).parameter.where(name = 'result').value",
+                SYSTEM
+            ),
+            &context
+        ),
+        EvaluationResult::boolean(true)
+    );
+    assert_eq!(
+        eval(
+            &format!(
+                "%terminologies.subsumes('{}', 'disorder', value.coding)",
+                SYSTEM
+            ),
+            &context
+        ),
+        EvaluationResult::string("subsumes".to_string())
+    );
+    assert_eq!(
+        eval(
+            "%terminologies.translate('http://example.org/fhir/ConceptMap/to-icd', value.coding).parameter.where(name = 'match').part.where(name = 'concept').value.code",
+            &context
+        ),
+        EvaluationResult::string("B34.9".to_string())
+    );
+    assert_eq!(
+        eval("%terminologies.expand({})", &context),
+        EvaluationResult::Empty
+    );
+    assert!(matches!(
+        try_eval("%terminologies.unknown('x')", &context),
+        Err(EvaluationError::InvalidOperation(_))
+    ));
+    assert!(matches!(
+        try_eval("%terminologies.validateVS('x')", &context),
+        Err(EvaluationError::InvalidArity(_))
+    ));
+}
+
+#[test]
+fn test_in_memory_provider_api() {
+    let provider = terminology();
+    assert_eq!(
+        provider
+            .subsumes(SYSTEM, "infection", "viral", &[])
+            .unwrap(),
+        SubsumptionOutcome::Subsumes
+    );
+    assert_eq!(
+        provider.subsumes(SYSTEM, "viral", "disorder", &[]).unwrap(),
+        SubsumptionOutcome::SubsumedBy
+    );
+    assert_eq!(
+        provider.subsumes(SYSTEM, "viral", "injury", &[]).unwrap(),
+        SubsumptionOutcome::NotSubsumed
+    );
+    assert!(
+        provider
+            .member_of(
+                "http://example.org/fhir/ValueSet/injuries",
+                &[Coding::new(SYSTEM, "injury")]
+            )
+            .unwrap()
+    );
+    assert!(
+        !provider
+            .member_of(
+                "http://example.org/fhir/ValueSet/injuries",
+                &[Coding::new("http://other.org", "injury")]
+            )
+            .unwrap()
+    );
+}
+
+/// A minimal terminology server that records requests and answers from a fixed table
+struct MockServer {
+    base_url: String,
+    requests: Arc<Mutex<Vec<(String, Value)>>>,
+}
+
+fn start_mock_server(responses: Vec<(&'static str, u16, Value)>) -> MockServer {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}/fhir", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let recorded = requests.clone();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let path = request_line
+                .split_whitespace()
+                .nth(1)
+                .unwrap_or_default()
+                .to_string();
+
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((_, value)) = header
+                    .split_once(':')
+                    .filter(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+            let (status, response) = responses
+                .iter()
+                .find(|(operation, _, _)| path.ends_with(operation))
+                .map(|(_, status, response)| (*status, response.clone()))
+                .unwrap_or((404, json!({"resourceType": "OperationOutcome"})));
+            recorded.lock().unwrap().push((path, body));
+
+            let response = response.to_string();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {} Mock\r\nContent-Type: application/fhir+json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                response.len(),
+                response
+            );
+        }
+    });
+
+    MockServer { base_url, requests }
+}
+
+fn parameters(parameter: Value) -> Value {
+    json!({"resourceType": "Parameters", "parameter": parameter})
+}
+
+#[test]
+fn test_http_provider_against_mock_server() {
+    let server = start_mock_server(vec![
+        (
+            "ValueSet/$validate-code",
+            200,
+            parameters(json!([{"name": "result", "valueBoolean": true}])),
+        ),
+        (
+            "CodeSystem/$subsumes",
+            200,
+            parameters(json!([{"name": "outcome", "valueCode": "subsumed-by"}])),
+        ),
+        (
+            "ValueSet/$expand",
+            200,
+            json!({
+                "resourceType": "ValueSet",
+                "status": "active",
+                "expansion": {
+                    "timestamp": "2024-01-01T00:00:00Z",
+                    "contains": [{"system": SYSTEM, "code": "viral"}]
+                }
+            }),
+        ),
+        (
+            "CodeSystem/$lookup",
+            404,
+            json!({
+                "resourceType": "OperationOutcome",
+                "issue": [{"severity": "error", "code": "not-found", "diagnostics": "Unknown code"}]
+            }),
+        ),
+    ]);
+    let provider = Arc::new(HttpTerminologyProvider::new(
+        format!("{}/", server.base_url),
+        FhirVersion::R4,
+    ));
+    let context = context_with(provider, "viral");
+
+    assert_eq!(
+        eval("code.memberOf('http://example.org/vs')", &context),
+        EvaluationResult::boolean(true)
+    );
+    assert_eq!(
+        eval("value.coding.subsumedBy('infection')", &context),
+        EvaluationResult::boolean(true)
+    );
+    assert_eq!(
+        eval(
+            "%terminologies.expand('http://example.org/vs', 'count=10').expansion.contains.code",
+            &context
+        ),
+        EvaluationResult::string("viral".to_string())
+    );
+    match try_eval("%terminologies.lookup(value.coding)", &context) {
+        Err(EvaluationError::Other(message)) => assert!(message.contains("Unknown code")),
+        other => panic!("Expected lookup to fail, got {:?}", other),
+    }
+
+    let requests = server.requests.lock().unwrap();
+    let (path, body) = &requests[0];
+    assert_eq!(path, "/fhir/ValueSet/$validate-code");
+    assert_eq!(body["parameter"][0]["valueUri"], "http://example.org/vs");
+    // Both codings of the CodeableConcept are sent
+    assert_eq!(
+        body["parameter"][1]["valueCodeableConcept"]["coding"][1]["code"],
+        "viral"
+    );
+
+    let (path, body) = &requests[1];
+    assert_eq!(path, "/fhir/CodeSystem/$subsumes");
+    assert_eq!(body["parameter"][0]["valueUri"], SYSTEM);
+    assert_eq!(body["parameter"][1]["valueCode"], "viral");
+    assert_eq!(body["parameter"][2]["valueCode"], "infection");
+
+    let (_, body) = &requests[2];
+    assert_eq!(body["parameter"][1]["name"], "count");
+    assert_eq!(body["parameter"][1]["valueString"], "10");
+}
+
+#[tokio::test]
+async fn test_http_provider_inside_async_runtime() {
+    let server = start_mock_server(vec![(
+        "ValueSet/$validate-code",
+        200,
+        parameters(json!([{"name": "result", "valueBoolean": false}])),
+    )]);
+    let context = context_with(
+        Arc::new(HttpTerminologyProvider::new(
+            server.base_url.clone(),
+            FhirVersion::R4,
+        )),
+        "viral",
+    );
+    assert_eq!(
+        eval("code.memberOf('http://example.org/vs')", &context),
+        EvaluationResult::boolean(false)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_http_provider_inside_multi_threaded_runtime() {
+    let server = start_mock_server(vec![(
+        "ValueSet/$validate-code",
+        200,
+        parameters(json!([{"name": "result", "valueBoolean": true}])),
+    )]);
+    let context = context_with(
+        Arc::new(HttpTerminologyProvider::new(
+            server.base_url.clone(),
+            FhirVersion::R4,
+        )),
+        "viral",
+    );
+    for _ in 0..3 {
+        assert_eq!(
+            eval("code.memberOf('http://example.org/vs')", &context),
+            EvaluationResult::boolean(true)
+        );
+    }
+    assert_eq!(server.requests.lock().unwrap().len(), 3);
+}
+
+#[test]
+fn test_http_provider_stops_at_evaluation_deadline() {
+    // A server that accepts connections but never answers
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}/fhir", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        let _connections: Vec<_> = listener.incoming().collect();
+    });
+
+    let mut context = context_with(
+        Arc::new(HttpTerminologyProvider::new(base_url, FhirVersion::R4)),
+        "viral",
+    );
+    context.set_limits(EvaluationLimits::new().with_timeout(Duration::from_millis(300)));
+
+    let start = Instant::now();
+    assert!(try_eval("code.memberOf('http://example.org/vs')", &context).is_err());
+    assert!(start.elapsed() < Duration::from_secs(10));
+
+    // Once the deadline has passed no request is made
+    std::thread::sleep(Duration::from_millis(300));
+    assert!(matches!(
+        try_eval("%terminologies.expand('http://example.org/vs')", &context),
+        Err(EvaluationError::LimitExceeded(_))
+    ));
+}
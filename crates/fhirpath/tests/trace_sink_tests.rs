@@ -0,0 +1,169 @@
+use helios_fhir::FhirVersion;
+use helios_fhirpath::evaluator::MAX_BUFFERED_TRACES;
+use helios_fhirpath::trace_sink::{
+    BufferedTraceSink, CallbackTraceSink, TraceSink, TracingTraceSink,
+};
+use helios_fhirpath::{EvaluationContext, EvaluationResult, evaluate_expression};
+use serde_json::json;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+fn context() -> EvaluationContext {
+    EvaluationContext::from_json(
+        json!({
+            "resourceType": "Patient",
+            "name": [
+                { "family": "Chalmers", "given": ["Peter", "James"] },
+                { "family": "Windsor", "given": ["Jim"] }
+            ]
+        }),
+        FhirVersion::R4,
+    )
+}
+
+fn names(traces: &[(String, EvaluationResult)]) -> Vec<&str> {
+    traces.iter().map(|(name, _)| name.as_str()).collect()
+}
+
+#[test]
+fn test_traces_are_buffered_without_a_sink() {
+    let context = context();
+    evaluate_expression("Patient.name.trace('names').given.trace('given')", &context).unwrap();
+    let traces = context.get_trace_outputs();
+    assert_eq!(names(&traces), vec!["names", "given"]);
+
+    context.clear_trace_outputs();
+    assert!(context.get_trace_outputs().is_empty());
+}
+
+#[test]
+fn test_callback_sink() {
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let mut context = context();
+    context.set_trace_sink(Arc::new(CallbackTraceSink::new({
+        let received = received.clone();
+        move |name, value| {
+            received
+                .lock()
+                .unwrap()
+                .push((name.to_string(), value.clone()))
+        }
+    })));
+
+    evaluate_expression("Patient.name.trace('family', family).count()", &context).unwrap();
+
+    let received = received.lock().unwrap();
+    assert_eq!(names(&received), vec!["family"]);
+    assert_eq!(received[0].1.to_string_value(), "[Chalmers, Windsor]");
+    // Traces go to the sink rather than the context's buffer
+    assert!(context.get_trace_outputs().is_empty());
+}
+
+#[test]
+fn test_buffered_sink_limit() {
+    let sink = Arc::new(BufferedTraceSink::with_max_entries(2));
+    let mut context = context();
+    context.set_trace_sink(sink.clone());
+
+    evaluate_expression(
+        "Patient.name.given.trace('a').trace('b').trace('c').trace('d')",
+        &context,
+    )
+    .unwrap();
+
+    assert_eq!(names(&sink.outputs()), vec!["a", "b"]);
+    assert_eq!(sink.dropped(), 2);
+    assert_eq!(sink.take().len(), 2);
+    assert!(sink.outputs().is_empty());
+    // take() keeps the count of dropped traces; clear() resets it
+    assert_eq!(sink.dropped(), 2);
+    sink.clear();
+    assert_eq!(sink.dropped(), 0);
+}
+
+#[test]
+fn test_default_buffer_is_bounded() {
+    let given: Vec<String> = (0..MAX_BUFFERED_TRACES + 5)
+        .map(|i| format!("n{}", i))
+        .collect();
+    let context = EvaluationContext::from_json(
+        json!({ "resourceType": "Patient", "name": [{ "given": given }] }),
+        FhirVersion::R4,
+    );
+
+    evaluate_expression(
+        "Patient.name.given.select(trace('given')).count()",
+        &context,
+    )
+    .unwrap();
+    assert_eq!(context.get_trace_outputs().len(), MAX_BUFFERED_TRACES);
+    assert_eq!(context.dropped_trace_outputs(), 5);
+
+    context.clear_trace_outputs();
+    assert_eq!(context.dropped_trace_outputs(), 0);
+}
+
+#[test]
+fn test_traces_within_aggregate() {
+    let sink = Arc::new(BufferedTraceSink::new());
+    let mut context = context();
+    context.set_trace_sink(sink.clone());
+    evaluate_expression(
+        "Patient.name.given.aggregate($total + $this.trace('item').length(), 0)",
+        &context,
+    )
+    .unwrap();
+    assert_eq!(names(&sink.outputs()), vec!["item", "item", "item"]);
+
+    // The context's own buffer also keeps them
+    let context = self::context();
+    evaluate_expression(
+        "Patient.name.given.aggregate($total + $this.trace('item').length(), 0)",
+        &context,
+    )
+    .unwrap();
+    assert_eq!(context.get_trace_outputs().len(), 3);
+}
+
+/// Collects the output of a tracing subscriber
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_tracing_sink() {
+    let output = Output::default();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer({
+            let output = output.clone();
+            move || output.clone()
+        })
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(false)
+        .finish();
+
+    let mut context = context();
+    context.set_trace_sink(Arc::new(TracingTraceSink::new()));
+    tracing::subscriber::with_default(subscriber, || {
+        evaluate_expression("Patient.name.family.trace('family')", &context).unwrap();
+        // Events above the subscriber's maximum level are not logged
+        TracingTraceSink::with_level(tracing::Level::TRACE)
+            .trace("hidden", &EvaluationResult::integer(1));
+    });
+
+    let logged = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+    assert!(logged.contains("DEBUG"), "{}", logged);
+    assert!(logged.contains("fhirpath::trace"), "{}", logged);
+    assert!(logged.contains("name=\"family\""), "{}", logged);
+    assert!(logged.contains("value=[Chalmers, Windsor]"), "{}", logged);
+    assert!(!logged.contains("hidden"), "{}", logged);
+}
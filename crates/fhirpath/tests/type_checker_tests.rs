@@ -0,0 +1,381 @@
+use helios_fhir::FhirVersion;
+use helios_fhirpath::parser::parse;
+use helios_fhirpath::type_checker::{Severity, TypeCheckResult, TypeChecker};
+use helios_fhirpath::type_inference::{InferredType, TypeContext, infer_expression_type};
+use roxmltree::Document;
+use std::path::PathBuf;
+
+fn check(expression: &str) -> TypeCheckResult {
+    TypeChecker::new(FhirVersion::R4)
+        .check(expression)
+        .unwrap_or_else(|e| panic!("{}: {}", expression, e))
+}
+
+fn check_on(root_type: &str, expression: &str) -> TypeCheckResult {
+    TypeChecker::new(FhirVersion::R4)
+        .with_root_type(InferredType::fhir(root_type))
+        .check(expression)
+        .unwrap_or_else(|e| panic!("{}: {}", expression, e))
+}
+
+fn assert_type(result: TypeCheckResult, expected: &str) {
+    assert!(
+        result.diagnostics.is_empty(),
+        "unexpected diagnostics: {:?}",
+        result.diagnostics
+    );
+    assert_eq!(result.expected_return_type().as_deref(), Some(expected));
+}
+
+/// Asserts a single error, returning its message and position
+fn single_error(result: TypeCheckResult) -> (String, Option<usize>) {
+    assert_eq!(result.diagnostics.len(), 1, "{:?}", result.diagnostics);
+    let diagnostic = &result.diagnostics[0];
+    assert_eq!(diagnostic.severity, Severity::Error);
+    (diagnostic.message.clone(), diagnostic.position)
+}
+
+#[test]
+fn test_path_return_types() {
+    assert_type(check("Patient.name"), "HumanName[]");
+    assert_type(check("Patient.name.given"), "string[]");
+    assert_type(check("Patient.name.first().family"), "string");
+    assert_type(check("Patient.birthDate"), "date");
+    assert_type(check("Patient.gender"), "code");
+    assert_type(check("Patient.active.not()"), "system.Boolean");
+    assert_type(check("Patient.name.given.count()"), "system.Integer");
+    assert_type(check("Patient.birthDate.extension"), "Extension[]");
+    assert_type(
+        check("Observation.valueQuantity.value + 1"),
+        "system.Decimal",
+    );
+    assert_type(check("Observation.value.ofType(Quantity).unit"), "string");
+    assert_type(
+        check("Patient.name.given | Patient.name.family"),
+        "string[]",
+    );
+    assert_type(
+        check("Patient.contained.ofType(Organization).name"),
+        "string[]",
+    );
+    assert_type(check("Patient.name.select(given.first())"), "string[]");
+    assert_type(
+        check("Patient.name.where(use = 'official').given"),
+        "string[]",
+    );
+    assert_type(check("Patient.birthDate + 1 year"), "date");
+
+    // Choice elements return every type they allow
+    let result = check("Patient.deceased");
+    assert!(result.diagnostics.is_empty());
+    assert_eq!(
+        result.expected_return_type().as_deref(),
+        Some("boolean | dateTime")
+    );
+
+    // Paths through types the model does not describe stay unknown
+    assert_type(check("1 + 2"), "system.Integer");
+    assert_eq!(check("Patient.contained.name").expected_return_type(), None);
+    assert_eq!(check("name.given").expected_return_type(), None);
+}
+
+#[test]
+fn test_function_return_types_match_inference() {
+    // The checker and type inference read built-in return types from one table
+    let type_context = TypeContext::new().with_root_type(InferredType::fhir("Patient"));
+    for (expression, expected) in [
+        ("'abc'.toChars()", "system.String[]"),
+        ("1.5.ceiling()", "system.Integer"),
+        ("1.round()", "system.Decimal"),
+        ("'1'.toLong()", "system.Long"),
+        ("'a'.convertsToInteger()", "system.Boolean"),
+        ("(1 | 2).avg()", "system.Decimal"),
+    ] {
+        assert_type(check(expression), expected);
+        let parsed = parse(expression).unwrap();
+        assert_eq!(
+            infer_expression_type(&parsed, &type_context)
+                .map(|inferred| inferred.to_display_string())
+                .as_deref(),
+            Some(expected),
+            "{}",
+            expression
+        );
+    }
+}
+
+#[test]
+fn test_root_and_context_types() {
+    assert_type(check_on("Patient", "name.given"), "string[]");
+    assert_type(check_on("Patient", "Patient.telecom.system"), "code[]");
+    assert_type(check_on("Patient", "%resource.birthDate"), "date");
+
+    let result = TypeChecker::new(FhirVersion::R4)
+        .with_root_type(InferredType::fhir("Patient"))
+        .with_context_types(vec![InferredType::fhir("HumanName").collection()])
+        .check("given.first() & ' ' & family")
+        .unwrap();
+    assert_type(result, "system.String");
+
+    let (message, position) = single_error(check_on("Patient", "Encounter.status"));
+    assert!(message.contains("Encounter"), "{}", message);
+    assert_eq!(position, Some(0));
+}
+
+#[test]
+fn test_unknown_elements() {
+    let (message, position) = single_error(check("Patient.nmae"));
+    assert_eq!(
+        message,
+        "Unknown element 'nmae' on Patient; did you mean 'name'?"
+    );
+    assert_eq!(position, Some(8));
+
+    let (message, position) = single_error(check("Patient.name.where(use = 'nmae').nmae"));
+    assert!(message.starts_with("Unknown element 'nmae' on HumanName"));
+    assert_eq!(position, Some(33));
+
+    // Positions come from the parsed tree, so escaped names are found too
+    let (message, position) = single_error(check("Patient.`nm\\u0061e`"));
+    assert!(message.starts_with("Unknown element 'nmae' on Patient"));
+    assert_eq!(position, Some(8));
+
+    // Lambda bodies are checked against the items of their input
+    let (message, position) = single_error(check("Patient.name.where(period.strt.exists())"));
+    assert!(
+        message.starts_with("Unknown element 'strt' on Period"),
+        "{}",
+        message
+    );
+    assert_eq!(position, Some(26));
+
+    let (message, _) = single_error(check("Patient.birthDate.year"));
+    assert!(
+        message.starts_with("Unknown element 'year' on date"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn test_choice_suffixes() {
+    assert_type(check("Observation.valueQuantity.unit"), "string");
+    assert_type(check("Observation.effectiveDateTime"), "dateTime");
+
+    let (message, position) = single_error(check("Observation.valueDuration"));
+    assert!(
+        message.starts_with(
+            "'valueDuration' is not a valid type for the choice element Observation.value[x]"
+        ),
+        "{}",
+        message
+    );
+    assert!(message.contains("valueQuantity"), "{}", message);
+    assert_eq!(position, Some(12));
+}
+
+#[test]
+fn test_function_arity_and_types() {
+    let (message, position) = single_error(check("Patient.name.given.substring()"));
+    assert_eq!(
+        message,
+        "Function 'substring' expects 1 to 2 arguments, but 0 were given"
+    );
+    assert_eq!(position, Some(19));
+
+    let (message, _) = single_error(check("Patient.name.exists(given, family)"));
+    assert_eq!(
+        message,
+        "Function 'exists' expects 0 to 1 arguments, but 2 were given"
+    );
+
+    let (message, _) = single_error(check("Patient.name.upper()"));
+    assert_eq!(
+        message,
+        "The input of 'upper' must be String, but is HumanName"
+    );
+
+    let (message, position) = single_error(check("Patient.name.given.substring('a')"));
+    assert_eq!(
+        message,
+        "Argument 1 of 'substring' must be Integer, but is system.String"
+    );
+    assert_eq!(position, Some(29));
+
+    let (message, position) = single_error(check("Patient.name.given.frist()"));
+    assert_eq!(message, "Unknown function 'frist'");
+    assert_eq!(position, Some(19));
+
+    let (message, position) = single_error(check("Patient.contained.ofType(Organisation)"));
+    assert_eq!(message, "Unknown type 'Organisation'");
+    assert_eq!(position, Some(25));
+
+    let result = check("Patient.is(System.Patient)");
+    assert!(!result.has_errors());
+    assert_eq!(result.diagnostics[0].severity, Severity::Warning);
+
+    let (message, _) = single_error(check("iif(1 | 2, true, false)"));
+    assert!(message.starts_with("Argument 1 of 'iif' must be Boolean"));
+}
+
+#[test]
+fn test_operators() {
+    let (message, position) = single_error(check("'a' - 'b'"));
+    assert_eq!(
+        message,
+        "Operator '-' cannot be applied to system.String and system.String"
+    );
+    assert_eq!(position, Some(4));
+
+    let (message, _) = single_error(check("Observation.valueQuantity.value < 'test'"));
+    assert!(message.starts_with("Cannot compare"), "{}", message);
+
+    let (message, _) = single_error(check("-Patient.active"));
+    assert!(message.starts_with("The operand of '-'"), "{}", message);
+}
+
+#[test]
+fn test_variables() {
+    let (message, position) = single_error(check("%unknown.name"));
+    assert_eq!(message, "Unknown variable '%unknown'");
+    assert_eq!(position, Some(0));
+
+    let result = TypeChecker::new(FhirVersion::R4)
+        .with_variable("names", InferredType::fhir("HumanName").collection())
+        .check("%names.given")
+        .unwrap();
+    assert_type(result, "string[]");
+
+    assert_type(
+        check("%ucum & %`vs-administrative-gender`"),
+        "system.String",
+    );
+    assert_type(
+        check("Patient.defineVariable('n', name).select(%n.family)"),
+        "string[]",
+    );
+}
+
+#[test]
+fn test_other_versions() {
+    #[cfg(feature = "R5")]
+    {
+        let checker = TypeChecker::new(FhirVersion::R5);
+        assert!(!checker.check("Patient.name.given").unwrap().has_errors());
+        // Encounter.class repeats in R5
+        assert_eq!(
+            checker
+                .check("Encounter.class")
+                .unwrap()
+                .expected_return_type()
+                .as_deref(),
+            Some("CodeableConcept[]")
+        );
+    }
+}
+
+/// Checks the expressions of the official R4 test suite: expressions marked as
+/// semantically invalid are reported, and no other expression is
+#[test]
+fn test_official_test_suite() {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests/data/r4/tests-fhir-r4.xml");
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let document = Document::parse_with_options(
+        &contents,
+        roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    let mut false_positives = Vec::new();
+    let mut detected = 0;
+    for test in document.descendants().filter(|n| n.has_tag_name("test")) {
+        let Some(expression_node) = test.children().find(|n| n.has_tag_name("expression")) else {
+            continue;
+        };
+        let expression = expression_node.text().unwrap_or("").trim();
+        let invalid = expression_node
+            .attribute("invalid")
+            .or(test.attribute("invalid"))
+            .unwrap_or("");
+
+        let mut checker = TypeChecker::new(FhirVersion::R4);
+        if let Some(input_file) = test.attribute("inputfile") {
+            let mut input_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            input_path.push("tests/data/r4/input");
+            input_path.push(input_file.replace(".xml", ".json"));
+            let json: serde_json::Value =
+                serde_json::from_str(&std::fs::read_to_string(&input_path).unwrap()).unwrap();
+            let resource_type = json["resourceType"].as_str().unwrap();
+            checker = checker.with_root_type(InferredType::fhir(resource_type));
+        }
+
+        let Ok(result) = checker.check(expression) else {
+            continue;
+        };
+        match invalid {
+            "" if result.has_errors() => {
+                false_positives.push(format!("{}: {:?}", expression, result.diagnostics))
+            }
+            "semantic" if result.has_errors() => detected += 1,
+            _ => {}
+        }
+    }
+
+    assert!(
+        false_positives.is_empty(),
+        "valid expressions reported as invalid:\n{}",
+        false_positives.join("\n")
+    );
+    assert!(detected >= 7, "only {} semantic errors detected", detected);
+}
+
+#[tokio::test]
+async fn test_server_returns_expected_return_type() {
+    use axum::Json;
+    use helios_fhirpath::handlers::evaluate_fhirpath;
+    use helios_fhirpath::models::FhirPathParameters;
+    use serde_json::{Value, json};
+
+    let params: FhirPathParameters = serde_json::from_value(json!({
+        "resourceType": "Parameters",
+        "parameter": [
+            { "name": "expression", "valueString": "given.where($this != %resource.nmae)" },
+            { "name": "context", "valueString": "name" },
+            { "name": "validate", "valueBoolean": true },
+            {
+                "name": "resource",
+                "resource": {
+                    "resourceType": "Patient",
+                    "name": [{ "family": "Chalmers", "given": ["Peter"] }]
+                }
+            }
+        ]
+    }))
+    .unwrap();
+
+    let response = evaluate_fhirpath(Json(params)).await.unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    let parts = body["parameter"][0]["part"].as_array().unwrap();
+    let part = |name: &str| {
+        parts
+            .iter()
+            .find(|part| part["name"] == name)
+            .and_then(|part| part["valueString"].as_str())
+            .map(str::to_string)
+    };
+
+    assert_eq!(part("expectedReturnType").as_deref(), Some("string[]"));
+    let parse_debug = part("parseDebug").unwrap();
+    assert!(
+        parse_debug.contains("error: Unknown element 'nmae' on Patient; did you mean 'name'?"),
+        "{}",
+        parse_debug
+    );
+}
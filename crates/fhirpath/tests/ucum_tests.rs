@@ -0,0 +1,279 @@
+use chumsky::Parser;
+use helios_fhirpath::evaluator::{EvaluationContext, evaluate};
+use helios_fhirpath::parser::parser;
+use helios_fhirpath::ucum;
+use helios_fhirpath_support::{EvaluationError, EvaluationResult};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+fn eval(input: &str) -> Result<EvaluationResult, EvaluationError> {
+    let expr = parser().parse(input).unwrap_or_else(|e| {
+        panic!("Parser error for input '{}': {:?}", input, e);
+    });
+    let context = EvaluationContext::new_empty_with_default_version();
+    evaluate(&expr, &context, None)
+}
+
+fn eval_ok(input: &str) -> EvaluationResult {
+    eval(input).unwrap_or_else(|e| panic!("'{}' failed: {}", input, e))
+}
+
+fn assert_true(input: &str) {
+    assert_eq!(
+        eval_ok(input),
+        EvaluationResult::boolean(true),
+        "for {}",
+        input
+    );
+}
+
+fn assert_false(input: &str) {
+    assert_eq!(
+        eval_ok(input),
+        EvaluationResult::boolean(false),
+        "for {}",
+        input
+    );
+}
+
+fn assert_empty(input: &str) {
+    assert_eq!(eval_ok(input), EvaluationResult::Empty, "for {}", input);
+}
+
+fn dec(value: &str) -> Decimal {
+    Decimal::from_str(value).unwrap()
+}
+
+#[test]
+fn test_parse_unit_expressions() {
+    for unit in [
+        "1",
+        "mg",
+        "mg/dL",
+        "kg.m2/s2",
+        "m/s2",
+        "10*3/uL",
+        "10^9/L",
+        "/min",
+        "mm[Hg]",
+        "[lb_av]",
+        "[in_i]",
+        "{cells}/uL",
+        "g{total}",
+        "mL/(kg.min)",
+        "%",
+        "Cel",
+        "[degF]",
+        "dB[SPL]",
+        "[IU]/L",
+        "mmol/L",
+        "KiBy",
+    ] {
+        assert!(ucum::is_valid_unit(unit), "'{}' should be valid", unit);
+    }
+    for unit in [
+        "", "mcg", "kg m", "m/", "(m", "[lb_av", "{cells", "Cel/h", "Cel2", "[ft_i]m", "2m",
+    ] {
+        assert!(!ucum::is_valid_unit(unit), "'{}' should be invalid", unit);
+    }
+    assert!(matches!(
+        ucum::canonicalize("furlong"),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_canonical_units() {
+    let newton = ucum::canonicalize("N").unwrap();
+    let expanded = ucum::canonicalize("kg.m/s2").unwrap();
+    assert_eq!(newton, expanded);
+    assert_eq!(newton.factor, Decimal::from(1000));
+    assert_eq!(newton.dimensions.get("s"), Some(&-2));
+
+    assert!(ucum::canonicalize("{cells}").unwrap().is_dimensionless());
+    assert!(ucum::canonicalize("%").unwrap().is_dimensionless());
+    assert!(ucum::canonicalize("Cel").unwrap().is_special());
+    // Arbitrary units only convert to themselves
+    let iu = ucum::canonicalize("[IU]").unwrap();
+    assert!(iu.is_commensurable(&ucum::canonicalize("m[IU]").unwrap()));
+    assert!(!iu.is_commensurable(&ucum::canonicalize("[CFU]").unwrap()));
+}
+
+#[test]
+fn test_convert() {
+    assert_eq!(
+        ucum::convert(dec("185"), "[lb_av]", "kg"),
+        Some(dec("83.91458845"))
+    );
+    assert_eq!(ucum::convert(dec("1"), "[ft_i]", "cm"), Some(dec("30.48")));
+    assert_eq!(ucum::convert(dec("1"), "L", "mL"), Some(dec("1000")));
+    assert_eq!(
+        ucum::convert(dec("120"), "mm[Hg]", "kPa"),
+        Some(dec("15.99864"))
+    );
+    assert_eq!(ucum::convert(dec("2"), "h", "s"), Some(dec("7200")));
+    assert_eq!(ucum::convert(dec("1"), "wk", "d"), Some(dec("7")));
+    assert_eq!(ucum::convert(dec("5"), "10*3/uL", "10*9/L"), Some(dec("5")));
+    assert_eq!(ucum::convert(dec("50"), "%", "1"), Some(dec("0.5")));
+    assert_eq!(ucum::convert(dec("1"), "KiBy", "bit"), Some(dec("8192")));
+    // Special units
+    assert_eq!(ucum::convert(dec("37"), "Cel", "K"), Some(dec("310.15")));
+    assert_eq!(ucum::convert(dec("212"), "[degF]", "Cel"), Some(dec("100")));
+    assert_eq!(ucum::convert(dec("20"), "dB", "B"), Some(dec("2")));
+    // Not commensurable
+    assert_eq!(ucum::convert(dec("1"), "g", "m"), None);
+    assert_eq!(ucum::convert(dec("1"), "B", "Np"), None);
+    assert_eq!(ucum::convert(dec("1"), "mg", "nonsense"), None);
+}
+
+#[test]
+fn test_quantity_equality_converts_units() {
+    assert_true("4.0000 'g' = 4000.0 'mg'");
+    assert_true("4 'g' != 4040 'mg'");
+    assert_true("1 'L' = 1000 'mL'");
+    assert_true("185 '[lb_av]' != 185 'kg'");
+    assert_true("100 '[degF]' != 100 'Cel'");
+    assert_true("0 'Cel' = 273.15 'K'");
+    assert_true("1 '{cells}' = 1 '1'");
+    // Incommensurable units give an empty result
+    assert_empty("1 'g' = 1 'm'");
+    assert_empty("1 'g' != 1 'm'");
+}
+
+#[test]
+fn test_calendar_durations() {
+    assert_true("7 days = 1 week");
+    assert_true("7 days = 1 'wk'");
+    assert_true("1 'h' = 60 minutes");
+    assert_true("1000 milliseconds = 1 second");
+    assert_true("1 year = 12 months");
+    // Calendar years and months are not equal to UCUM years and months...
+    assert_false("1 year = 1 'a'");
+    assert_false("1 month = 1 'mo'");
+    // ...but they are equivalent
+    assert_true("1 year ~ 1 'a'");
+    assert_true("1 '{day}' = 1 day");
+}
+
+#[test]
+fn test_quantity_equivalence() {
+    assert_true("4 'g' ~ 4000 'mg'");
+    // Compared at the precision of the least precise value
+    assert_true("4 'g' ~ 4040 'mg'");
+    assert_false("4.0 'g' ~ 4100.0 'mg'");
+    assert_true("1.04 'm' ~ 1.0 'm'");
+    assert_false("1 'g' ~ 1 'm'");
+}
+
+#[test]
+fn test_quantity_ordering() {
+    assert_true("6 days < 1 week");
+    assert_true("8 days > 1 week");
+    assert_true("1 'kg' > 999 'g'");
+    assert_true("185 '[lb_av]' < 84 'kg'");
+    assert_true("37 'Cel' > 98 '[degF]'");
+    assert!(matches!(
+        eval("1 'g' < 1 'm'"),
+        Err(EvaluationError::TypeError(_))
+    ));
+}
+
+#[test]
+fn test_quantity_addition() {
+    assert_eq!(
+        eval_ok("1 'g' + 500 'mg'"),
+        EvaluationResult::quantity(dec("1500"), "mg".to_string())
+    );
+    assert_eq!(
+        eval_ok("1 'm' - 1 'cm'"),
+        EvaluationResult::quantity(dec("99"), "cm".to_string())
+    );
+    assert_true("1 'h' + 30 'min' = 90 'min'");
+    assert_empty("1 'g' + 1 'm'");
+    assert_empty("1 'Cel' + 1 'K'");
+}
+
+#[test]
+fn test_quantity_multiplication_and_division() {
+    assert_true("2.0 'cm' * 2.0 'm' = 0.040 'm2'");
+    assert_true("4.0 'g' / 2.0 'm' = 2 'g/m'");
+    assert_true("1.0 'm' / 1.0 'm' = 1 '1'");
+    assert_true("10 'mg' / 2 'mL' = 5 'g/L'");
+    assert_true("3 'm' / 2 's' / 1 's' = 1.5 'm/s2'");
+    assert_eq!(
+        eval_ok("2.0 'cm' * 2.0 'm'"),
+        EvaluationResult::quantity(dec("4.00"), "cm.m".to_string())
+    );
+    assert_eq!(
+        eval_ok("4 'kg' / 2 'm/s'"),
+        EvaluationResult::quantity(dec("2"), "kg/(m/s)".to_string())
+    );
+    // Numbers scale a Quantity without changing its unit
+    assert_eq!(
+        eval_ok("2 * 3 days"),
+        EvaluationResult::quantity(dec("6"), "days".to_string())
+    );
+    assert_eq!(
+        eval_ok("5.5 'mg' / 2"),
+        EvaluationResult::quantity(dec("2.75"), "mg".to_string())
+    );
+    assert_eq!(
+        eval_ok("1 / 4 's'"),
+        EvaluationResult::quantity(dec("0.25"), "1/s".to_string())
+    );
+    assert_empty("1 'g' / 0 'm'");
+    assert_empty("1 'Cel' * 2 'm'");
+}
+
+#[test]
+fn test_to_quantity_with_unit() {
+    assert_eq!(
+        eval_ok("'185 \\'[lb_av]\\''.toQuantity('kg')"),
+        EvaluationResult::quantity(dec("83.91458845"), "kg".to_string())
+    );
+    assert_eq!(
+        eval_ok("1 week.toQuantity('d')"),
+        EvaluationResult::quantity(dec("7"), "d".to_string())
+    );
+    assert_eq!(
+        eval_ok("2 'h'.toQuantity('minutes')"),
+        EvaluationResult::quantity(dec("120"), "minutes".to_string())
+    );
+    assert_eq!(
+        eval_ok("0.5.toQuantity('%')"),
+        EvaluationResult::quantity(dec("50"), "%".to_string())
+    );
+    assert_empty("1 'g'.toQuantity('m')");
+    assert_empty("1 'g'.toQuantity({})");
+    assert_true("'5 mg'.convertsToQuantity('g')");
+    assert_false("'5 mg'.convertsToQuantity('m')");
+    assert_false("'5 apples'.convertsToQuantity()");
+    assert!(matches!(
+        eval("1 'g'.toQuantity(1)"),
+        Err(EvaluationError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        eval("1 'g'.toQuantity('g', 'mg')"),
+        Err(EvaluationError::InvalidArity(_))
+    ));
+}
+
+#[test]
+fn test_aggregates_convert_units() {
+    assert_eq!(
+        eval_ok("(1 'g' | 500 'mg').sum()"),
+        EvaluationResult::quantity(dec("1500"), "mg".to_string())
+    );
+    assert_eq!(
+        eval_ok("(1 'kg' | 900 'g' | 1.2 'kg').max()"),
+        EvaluationResult::quantity(dec("1.2"), "kg".to_string())
+    );
+    assert_eq!(
+        eval_ok("(1 'kg' | 900 'g').min()"),
+        EvaluationResult::quantity(dec("900"), "g".to_string())
+    );
+    assert!(matches!(
+        eval("(1 'g' | 1 'm').sum()"),
+        Err(EvaluationError::TypeError(_))
+    ));
+}
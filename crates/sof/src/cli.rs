@@ -293,11 +293,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     // Build run options
-    let options = RunOptions {
-        since,
-        limit,
-        page: None, // CLI doesn't support page parameter yet
-    };
+    // CLI doesn't support page parameter yet
+    let mut options = RunOptions::default();
+    if let Some(since) = since {
+        options = options.with_since(since);
+    }
+    if let Some(limit) = limit {
+        options = options.with_limit(limit);
+    }
 
     // Run the transformation
     let result = run_view_definition_with_options(view_definition, bundle, content_type, options)?;
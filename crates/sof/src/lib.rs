@@ -179,9 +179,12 @@
 pub mod traits;
 
 use chrono::{DateTime, Utc};
-use helios_fhirpath::{EvaluationContext, EvaluationResult, evaluate_expression};
+use helios_fhirpath::trace_sink::{BufferedTraceSink, TraceSink};
+use helios_fhirpath::{EvaluationContext, EvaluationResult, ExpressionCache};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
 use thiserror::Error;
 use traits::*;
 
@@ -888,7 +891,11 @@ pub fn run_view_definition(
 }
 
 /// Options for filtering and controlling ViewDefinition execution
-#[derive(Debug, Clone, Default)]
+///
+/// The struct is non-exhaustive so that options can be added without breaking
+/// callers; build it from `RunOptions::default()` with the `with_*` methods.
+#[derive(Clone, Default)]
+#[non_exhaustive]
 pub struct RunOptions {
     /// Filter resources modified after this time
     pub since: Option<DateTime<Utc>>,
@@ -896,8 +903,69 @@ pub struct RunOptions {
     pub limit: Option<usize>,
     /// Page number for pagination (1-based)
     pub page: Option<usize>,
+    /// Receives the `trace()` output of each resource once it has been processed
+    pub trace_handler: Option<Arc<ResourceTraceHandler>>,
+}
+
+impl RunOptions {
+    /// Only includes resources modified after `since`
+    pub fn with_since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Returns at most `limit` results
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Returns the given page of results (1-based)
+    pub fn with_page(mut self, page: usize) -> Self {
+        self.page = Some(page);
+        self
+    }
+
+    /// Passes the `trace()` output of each resource to `handler`
+    pub fn with_trace_handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(ResourceTraces) + Send + Sync + 'static,
+    {
+        self.trace_handler = Some(Arc::new(handler));
+        self
+    }
 }
 
+impl fmt::Debug for RunOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RunOptions")
+            .field("since", &self.since)
+            .field("limit", &self.limit)
+            .field("page", &self.page)
+            .field("trace_handler", &self.trace_handler.is_some())
+            .finish()
+    }
+}
+
+/// The `trace()` output of the FHIRPath expressions evaluated for one resource
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceTraces {
+    /// Position of the resource among the Bundle's resources of the
+    /// ViewDefinition's resource type
+    pub index: usize,
+    /// Type of the resource
+    pub resource_type: String,
+    /// Name and value of each trace, in the order they were made
+    pub traces: Vec<(String, EvaluationResult)>,
+}
+
+/// Callback receiving the traces of each resource that made any
+///
+/// Traces are collected per resource and handed over as soon as the
+/// resource has been processed, so a run never holds the traces of more
+/// than one resource.
+pub type ResourceTraceHandler = dyn Fn(ResourceTraces) + Send + Sync;
+
 /// Execute a ViewDefinition transformation with additional filtering options.
 ///
 /// This function extends the basic `run_view_definition` with support for:
@@ -929,7 +997,11 @@ pub fn run_view_definition_with_options(
     };
 
     // Process the ViewDefinition to generate tabular data
-    let processed_result = process_view_definition(view_definition, filtered_bundle)?;
+    let processed_result = process_view_definition(
+        view_definition,
+        filtered_bundle,
+        options.trace_handler.as_deref(),
+    )?;
 
     // Apply pagination if needed
     let processed_result = if options.limit.is_some() || options.page.is_some() {
@@ -945,6 +1017,7 @@ pub fn run_view_definition_with_options(
 fn process_view_definition(
     view_definition: SofViewDefinition,
     bundle: SofBundle,
+    trace_handler: Option<&ResourceTraceHandler>,
 ) -> Result<ProcessedResult, SofError> {
     // Ensure both resources use the same FHIR version
     if view_definition.version() != bundle.version() {
@@ -956,19 +1029,19 @@ fn process_view_definition(
     match (view_definition, bundle) {
         #[cfg(feature = "R4")]
         (SofViewDefinition::R4(vd), SofBundle::R4(bundle)) => {
-            process_view_definition_generic(vd, bundle)
+            process_view_definition_generic(vd, bundle, trace_handler)
         }
         #[cfg(feature = "R4B")]
         (SofViewDefinition::R4B(vd), SofBundle::R4B(bundle)) => {
-            process_view_definition_generic(vd, bundle)
+            process_view_definition_generic(vd, bundle, trace_handler)
         }
         #[cfg(feature = "R5")]
         (SofViewDefinition::R5(vd), SofBundle::R5(bundle)) => {
-            process_view_definition_generic(vd, bundle)
+            process_view_definition_generic(vd, bundle, trace_handler)
         }
         #[cfg(feature = "R6")]
         (SofViewDefinition::R6(vd), SofBundle::R6(bundle)) => {
-            process_view_definition_generic(vd, bundle)
+            process_view_definition_generic(vd, bundle, trace_handler)
         }
         // This case should never happen due to the version check above,
         // but is needed for exhaustive pattern matching when multiple features are enabled
@@ -1010,6 +1083,7 @@ fn extract_view_definition_constants<VD: ViewDefinitionTrait>(
 fn process_view_definition_generic<VD, B>(
     view_definition: VD,
     bundle: B,
+    trace_handler: Option<&ResourceTraceHandler>,
 ) -> Result<ProcessedResult, SofError>
 where
     VD: ViewDefinitionTrait,
@@ -1028,21 +1102,44 @@ where
 
     let filtered_resources = filter_resources(&bundle, target_resource_type)?;
 
-    // Step 3: Apply where clauses to filter resources
-    let filtered_resources = apply_where_clauses(
-        filtered_resources,
-        view_definition.where_clauses(),
-        &variables,
-    )?;
-
-    // Step 4: Process all select clauses to generate rows with forEach support
     let select_clauses = view_definition.select().ok_or_else(|| {
         SofError::InvalidViewDefinition("At least one select clause is required".to_string())
     })?;
 
-    // Generate rows for each resource using the forEach-aware approach
-    let (all_columns, rows) =
-        generate_rows_from_selects(&filtered_resources, select_clauses, &variables)?;
+    let mut all_columns = Vec::new();
+    let mut rows = Vec::new();
+    for (index, resource) in filtered_resources.into_iter().enumerate() {
+        // Traces are buffered per resource only when someone receives them
+        let trace_buffer = trace_handler.map(|_| Arc::new(BufferedTraceSink::new()));
+        let scope = ResourceScope {
+            variables: &variables,
+            trace_sink: trace_buffer
+                .clone()
+                .map(|buffer| buffer as Arc<dyn TraceSink>),
+        };
+
+        // Step 3: Apply where clauses to filter resources
+        // Step 4: Process all select clauses to generate rows with forEach support
+        if matches_where_clauses(resource, view_definition.where_clauses(), &scope)? {
+            rows.extend(generate_rows_for_resource(
+                resource,
+                select_clauses,
+                &mut all_columns,
+                &scope,
+            )?);
+        }
+
+        if let (Some(handler), Some(buffer)) = (trace_handler, trace_buffer) {
+            let traces = buffer.take();
+            if !traces.is_empty() {
+                handler(ResourceTraces {
+                    index,
+                    resource_type: resource.resource_name().to_string(),
+                    traces,
+                });
+            }
+        }
+    }
 
     Ok(ProcessedResult {
         columns: all_columns,
@@ -1050,6 +1147,29 @@ where
     })
 }
 
+/// What every evaluation context created for a resource starts with
+struct ResourceScope<'a> {
+    /// The ViewDefinition's constants
+    variables: &'a HashMap<String, EvaluationResult>,
+    /// Receives the traces made while processing the resource
+    trace_sink: Option<Arc<dyn TraceSink>>,
+}
+
+impl ResourceScope<'_> {
+    /// Creates an evaluation context over `resources` with the constants and
+    /// trace sink of the scope
+    fn evaluation_context(&self, resources: Vec<helios_fhir::FhirResource>) -> EvaluationContext {
+        let mut context = EvaluationContext::new(resources);
+        for (name, value) in self.variables {
+            context.set_variable_result(name, value.clone());
+        }
+        if let Some(sink) = &self.trace_sink {
+            context.set_trace_sink(sink.clone());
+        }
+        context
+    }
+}
+
 // Generic version-agnostic validation
 fn validate_view_definition<VD: ViewDefinitionTrait>(view_def: &VD) -> Result<(), SofError> {
     // Basic validation
@@ -1242,71 +1362,54 @@ fn filter_resources<'a, B: BundleTrait>(
 }
 
 // Generic where clause application
-fn apply_where_clauses<'a, R, W>(
-    resources: Vec<&'a R>,
+fn matches_where_clauses<R, W>(
+    resource: &R,
     where_clauses: Option<&[W]>,
-    variables: &HashMap<String, EvaluationResult>,
-) -> Result<Vec<&'a R>, SofError>
+    scope: &ResourceScope,
+) -> Result<bool, SofError>
 where
     R: ResourceTrait,
     W: ViewDefinitionWhereTrait,
 {
-    if let Some(wheres) = where_clauses {
-        let mut filtered = Vec::new();
-
-        for resource in resources {
-            let mut include_resource = true;
-
-            // All where clauses must evaluate to true for the resource to be included
-            for where_clause in wheres {
-                let fhir_resource = resource.to_fhir_resource();
-                let mut context = EvaluationContext::new(vec![fhir_resource]);
+    let Some(wheres) = where_clauses else {
+        return Ok(true);
+    };
 
-                // Add variables to the context
-                for (name, value) in variables {
-                    context.set_variable_result(name, value.clone());
+    // All where clauses must evaluate to true for the resource to be included
+    for where_clause in wheres {
+        let context = scope.evaluation_context(vec![resource.to_fhir_resource()]);
+
+        let path = where_clause.path().ok_or_else(|| {
+            SofError::InvalidViewDefinition("Where clause path is required".to_string())
+        })?;
+
+        match evaluate_path(path, &context) {
+            Ok(result) => {
+                // Check if the result can be meaningfully used as a boolean
+                if !can_be_coerced_to_boolean(&result) {
+                    return Err(SofError::InvalidViewDefinition(format!(
+                        "Where clause path '{}' returns type '{}' which cannot be used as a boolean condition. \
+                         Where clauses must return boolean values, collections, or empty results.",
+                        path,
+                        result.type_name()
+                    )));
                 }
 
-                let path = where_clause.path().ok_or_else(|| {
-                    SofError::InvalidViewDefinition("Where clause path is required".to_string())
-                })?;
-
-                match evaluate_expression(path, &context) {
-                    Ok(result) => {
-                        // Check if the result can be meaningfully used as a boolean
-                        if !can_be_coerced_to_boolean(&result) {
-                            return Err(SofError::InvalidViewDefinition(format!(
-                                "Where clause path '{}' returns type '{}' which cannot be used as a boolean condition. \
-                                 Where clauses must return boolean values, collections, or empty results.",
-                                path,
-                                result.type_name()
-                            )));
-                        }
-
-                        // Check if result is truthy (non-empty and not false)
-                        if !is_truthy(&result) {
-                            include_resource = false;
-                            break;
-                        }
-                    }
-                    Err(e) => {
-                        return Err(SofError::FhirPathError(format!(
-                            "Error evaluating where clause '{}': {}",
-                            path, e
-                        )));
-                    }
+                // Check if result is truthy (non-empty and not false)
+                if !is_truthy(&result) {
+                    return Ok(false);
                 }
             }
-
-            if include_resource {
-                filtered.push(resource);
+            Err(e) => {
+                return Err(SofError::FhirPathError(format!(
+                    "Error evaluating where clause '{}': {}",
+                    path, e
+                )));
             }
         }
-
-        Ok(filtered)
-    } else {
-        Ok(resources)
     }
+
+    Ok(true)
 }
 
 // Removed generate_rows_per_resource_r4 - replaced with new forEach-aware implementation
@@ -1376,9 +1479,9 @@ fn fhirpath_result_to_json_value(result: EvaluationResult) -> Option<serde_json:
             }
         }
         EvaluationResult::String(s, _) => Some(serde_json::Value::String(s)),
-        EvaluationResult::Date(s, _) => Some(serde_json::Value::String(s)),
-        EvaluationResult::DateTime(s, _) => Some(serde_json::Value::String(s)),
-        EvaluationResult::Time(s, _) => Some(serde_json::Value::String(s)),
+        EvaluationResult::Date(s, _) => Some(serde_json::Value::String(s.to_string())),
+        EvaluationResult::DateTime(s, _) => Some(serde_json::Value::String(s.to_string())),
+        EvaluationResult::Time(s, _) => Some(serde_json::Value::String(s.to_string())),
         EvaluationResult::Collection { items, .. } => {
             if items.len() == 1 {
                 // Single item collection - unwrap to the item itself
@@ -1418,50 +1521,21 @@ fn extract_iteration_items(result: EvaluationResult) -> Vec<EvaluationResult> {
 
 // Generic row generation functions
 
-fn generate_rows_from_selects<R, S>(
-    resources: &[&R],
-    selects: &[S],
-    variables: &HashMap<String, EvaluationResult>,
-) -> Result<(Vec<String>, Vec<ProcessedRow>), SofError>
-where
-    R: ResourceTrait,
-    S: ViewDefinitionSelectTrait,
-    S::Select: ViewDefinitionSelectTrait,
-{
-    let mut all_columns = Vec::new();
-    let mut all_rows = Vec::new();
-
-    // For each resource, generate all possible row combinations
-    for resource in resources {
-        let resource_rows =
-            generate_rows_for_resource(*resource, selects, &mut all_columns, variables)?;
-        all_rows.extend(resource_rows);
-    }
-
-    Ok((all_columns, all_rows))
-}
-
 fn generate_rows_for_resource<R, S>(
     resource: &R,
     selects: &[S],
     all_columns: &mut Vec<String>,
-    variables: &HashMap<String, EvaluationResult>,
+    scope: &ResourceScope,
 ) -> Result<Vec<ProcessedRow>, SofError>
 where
     R: ResourceTrait,
     S: ViewDefinitionSelectTrait,
     S::Select: ViewDefinitionSelectTrait,
 {
-    let fhir_resource = resource.to_fhir_resource();
-    let mut context = EvaluationContext::new(vec![fhir_resource]);
-
-    // Add variables to the context
-    for (name, value) in variables {
-        context.set_variable_result(name, value.clone());
-    }
+    let context = scope.evaluation_context(vec![resource.to_fhir_resource()]);
 
     // Generate all possible row combinations for this resource
-    let row_combinations = generate_row_combinations(&context, selects, all_columns, variables)?;
+    let row_combinations = generate_row_combinations(&context, selects, all_columns, scope)?;
 
     Ok(row_combinations)
 }
@@ -1475,7 +1549,7 @@ fn generate_row_combinations<S>(
     context: &EvaluationContext,
     selects: &[S],
     all_columns: &mut Vec<String>,
-    variables: &HashMap<String, EvaluationResult>,
+    scope: &ResourceScope,
 ) -> Result<Vec<ProcessedRow>, SofError>
 where
     S: ViewDefinitionSelectTrait,
@@ -1491,7 +1565,7 @@ where
 
     for select in selects {
         row_combinations =
-            expand_select_combinations(context, select, &row_combinations, all_columns, variables)?;
+            expand_select_combinations(context, select, &row_combinations, all_columns, scope)?;
     }
 
     // Convert to ProcessedRow format
@@ -1537,7 +1611,7 @@ fn expand_select_combinations<S>(
     select: &S,
     existing_combinations: &[RowCombination],
     all_columns: &[String],
-    variables: &HashMap<String, EvaluationResult>,
+    scope: &ResourceScope,
 ) -> Result<Vec<RowCombination>, SofError>
 where
     S: ViewDefinitionSelectTrait,
@@ -1552,7 +1626,7 @@ where
             all_columns,
             for_each_path,
             false,
-            variables,
+            scope,
         );
     }
 
@@ -1564,7 +1638,7 @@ where
             all_columns,
             for_each_or_null_path,
             true,
-            variables,
+            scope,
         );
     }
 
@@ -1583,7 +1657,7 @@ where
                             SofError::InvalidViewDefinition("Column path is required".to_string())
                         })?;
 
-                        match evaluate_expression(path, context) {
+                        match evaluate_path(path, context) {
                             Ok(result) => {
                                 // Check if this column is marked as a collection
                                 let is_collection = col.collection().unwrap_or(false);
@@ -1617,7 +1691,7 @@ where
                 nested_select,
                 &new_combinations,
                 all_columns,
-                variables,
+                scope,
             )?;
         }
     }
@@ -1634,7 +1708,7 @@ where
                 union_select,
                 &new_combinations,
                 all_columns,
-                variables,
+                scope,
             )?;
             union_combinations.extend(select_combinations);
         }
@@ -1654,14 +1728,14 @@ fn expand_for_each_combinations<S>(
     all_columns: &[String],
     for_each_path: &str,
     allow_null: bool,
-    variables: &HashMap<String, EvaluationResult>,
+    scope: &ResourceScope,
 ) -> Result<Vec<RowCombination>, SofError>
 where
     S: ViewDefinitionSelectTrait,
     S::Select: ViewDefinitionSelectTrait,
 {
     // Evaluate the forEach expression to get iteration items
-    let for_each_result = evaluate_expression(for_each_path, context).map_err(|e| {
+    let for_each_result = evaluate_path(for_each_path, context).map_err(|e| {
         SofError::FhirPathError(format!(
             "Error evaluating forEach expression '{}': {}",
             for_each_path, e
@@ -1704,7 +1778,7 @@ where
     // For each iteration item, create new combinations
     for item in &iteration_items {
         // Create a new context with the iteration item
-        let _item_context = create_iteration_context(item, variables);
+        let _item_context = create_iteration_context(item, scope);
 
         for existing_combo in existing_combinations {
             let mut new_combo = existing_combo.clone();
@@ -1728,7 +1802,7 @@ where
                                 item.clone()
                             } else {
                                 // Evaluate the path on the iteration item
-                                evaluate_path_on_item(path, item, variables)?
+                                evaluate_path_on_item(path, item, scope)?
                             };
 
                             // Check if this column is marked as a collection
@@ -1753,7 +1827,7 @@ where
         let mut final_combinations = Vec::new();
 
         for item in &iteration_items {
-            let item_context = create_iteration_context(item, variables);
+            let item_context = create_iteration_context(item, scope);
 
             // For each iteration item, we need to start with the combinations that have
             // the correct column values for this forEach scope
@@ -1778,7 +1852,7 @@ where
                                 let result = if path == "$this" {
                                     item.clone()
                                 } else {
-                                    evaluate_path_on_item(path, item, variables)?
+                                    evaluate_path_on_item(path, item, scope)?
                                 };
 
                                 // Check if this column is marked as a collection
@@ -1804,7 +1878,7 @@ where
                         nested_select,
                         &item_combinations,
                         all_columns,
-                        variables,
+                        scope,
                     )?;
                 }
 
@@ -1820,7 +1894,7 @@ where
         let mut union_combinations = Vec::new();
 
         for item in &iteration_items {
-            let item_context = create_iteration_context(item, variables);
+            let item_context = create_iteration_context(item, scope);
 
             // For each iteration item, process all unionAll selects
             for existing_combo in existing_combinations {
@@ -1842,7 +1916,7 @@ where
                                 let result = if path == "$this" {
                                     item.clone()
                                 } else {
-                                    evaluate_path_on_item(path, item, variables)?
+                                    evaluate_path_on_item(path, item, scope)?
                                 };
 
                                 // Check if this column is marked as a collection
@@ -1876,7 +1950,7 @@ where
                                         let result = if path == "$this" {
                                             item.clone()
                                         } else {
-                                            evaluate_path_on_item(path, item, variables)?
+                                            evaluate_path_on_item(path, item, scope)?
                                         };
 
                                         // Check if this column is marked as a collection
@@ -1902,7 +1976,7 @@ where
                         union_select,
                         &select_combinations,
                         all_columns,
-                        variables,
+                        scope,
                     )?;
                     union_combinations.extend(select_combinations);
                 }
@@ -1918,30 +1992,31 @@ where
 }
 
 // Generic helper functions
+
+/// Evaluates a ViewDefinition FHIRPath expression against `context`.
+///
+/// The same column, `where` and `forEach` paths are evaluated for every resource,
+/// so expressions are compiled through the shared [`ExpressionCache`] and parsed
+/// only once per process.
+fn evaluate_path(path: &str, context: &EvaluationContext) -> Result<EvaluationResult, String> {
+    ExpressionCache::global().evaluate(path, context)
+}
+
 fn evaluate_path_on_item(
     path: &str,
     item: &EvaluationResult,
-    variables: &HashMap<String, EvaluationResult>,
+    scope: &ResourceScope,
 ) -> Result<EvaluationResult, SofError> {
     // Create a temporary context with the iteration item as the root resource
-    let mut temp_context = match item {
-        EvaluationResult::Object { .. } => {
-            // Convert the iteration item to a resource-like structure for FHIRPath evaluation
-            // For simplicity, we'll create a basic context where the item is available for evaluation
-            let mut context = EvaluationContext::new(vec![]);
-            context.this = Some(item.clone());
-            context
-        }
-        _ => EvaluationContext::new(vec![]),
-    };
-
-    // Add variables to the temporary context
-    for (name, value) in variables {
-        temp_context.set_variable_result(name, value.clone());
+    let mut temp_context = scope.evaluation_context(vec![]);
+    if let EvaluationResult::Object { .. } = item {
+        // Convert the iteration item to a resource-like structure for FHIRPath evaluation
+        // For simplicity, we'll create a basic context where the item is available for evaluation
+        temp_context.set_this(item.clone());
     }
 
     // Evaluate the FHIRPath expression in the context of the iteration item
-    match evaluate_expression(path, &temp_context) {
+    match evaluate_path(path, &temp_context) {
         Ok(result) => Ok(result),
         Err(_e) => {
             // If FHIRPath evaluation fails, try simple property access as fallback
@@ -1959,19 +2034,11 @@ fn evaluate_path_on_item(
     }
 }
 
-fn create_iteration_context(
-    item: &EvaluationResult,
-    variables: &HashMap<String, EvaluationResult>,
-) -> EvaluationContext {
-    // Create a new context with the iteration item as the root
-    let mut context = EvaluationContext::new(vec![]);
-    context.this = Some(item.clone());
-
-    // Preserve variables from the parent context
-    for (name, value) in variables {
-        context.set_variable_result(name, value.clone());
-    }
-
+fn create_iteration_context(item: &EvaluationResult, scope: &ResourceScope) -> EvaluationContext {
+    // Create a new context with the iteration item as the root, keeping the
+    // constants and trace sink of the resource
+    let mut context = scope.evaluation_context(vec![]);
+    context.set_this(item.clone());
     context
 }
 
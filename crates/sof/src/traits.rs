@@ -465,13 +465,14 @@ mod r4_impl {
                         }
                     }
                     ViewDefinitionConstantValue::Date(d) => {
-                        EvaluationResult::Date(d.value.clone().unwrap_or_default(), None)
-                    }
-                    ViewDefinitionConstantValue::DateTime(dt) => {
-                        EvaluationResult::DateTime(dt.value.clone().unwrap_or_default(), None)
+                        EvaluationResult::Date(d.value.clone().unwrap_or_default().into(), None)
                     }
+                    ViewDefinitionConstantValue::DateTime(dt) => EvaluationResult::DateTime(
+                        dt.value.clone().unwrap_or_default().into(),
+                        None,
+                    ),
                     ViewDefinitionConstantValue::Time(t) => {
-                        EvaluationResult::Time(t.value.clone().unwrap_or_default(), None)
+                        EvaluationResult::Time(t.value.clone().unwrap_or_default().into(), None)
                     }
                     ViewDefinitionConstantValue::Code(c) => {
                         EvaluationResult::String(c.value.clone().unwrap_or_default(), None)
@@ -483,7 +484,7 @@ mod r4_impl {
                         EvaluationResult::String(i.value.clone().unwrap_or_default(), None)
                     }
                     ViewDefinitionConstantValue::Instant(i) => {
-                        EvaluationResult::DateTime(i.value.clone().unwrap_or_default(), None)
+                        EvaluationResult::DateTime(i.value.clone().unwrap_or_default().into(), None)
                     }
                     ViewDefinitionConstantValue::Oid(o) => {
                         EvaluationResult::String(o.value.clone().unwrap_or_default(), None)
@@ -656,13 +657,14 @@ mod r4b_impl {
                         }
                     }
                     ViewDefinitionConstantValue::Date(d) => {
-                        EvaluationResult::Date(d.value.clone().unwrap_or_default(), None)
-                    }
-                    ViewDefinitionConstantValue::DateTime(dt) => {
-                        EvaluationResult::DateTime(dt.value.clone().unwrap_or_default(), None)
+                        EvaluationResult::Date(d.value.clone().unwrap_or_default().into(), None)
                     }
+                    ViewDefinitionConstantValue::DateTime(dt) => EvaluationResult::DateTime(
+                        dt.value.clone().unwrap_or_default().into(),
+                        None,
+                    ),
                     ViewDefinitionConstantValue::Time(t) => {
-                        EvaluationResult::Time(t.value.clone().unwrap_or_default(), None)
+                        EvaluationResult::Time(t.value.clone().unwrap_or_default().into(), None)
                     }
                     ViewDefinitionConstantValue::Code(c) => {
                         EvaluationResult::String(c.value.clone().unwrap_or_default(), None)
@@ -674,7 +676,7 @@ mod r4b_impl {
                         EvaluationResult::String(i.value.clone().unwrap_or_default(), None)
                     }
                     ViewDefinitionConstantValue::Instant(i) => {
-                        EvaluationResult::DateTime(i.value.clone().unwrap_or_default(), None)
+                        EvaluationResult::DateTime(i.value.clone().unwrap_or_default().into(), None)
                     }
                     ViewDefinitionConstantValue::Oid(o) => {
                         EvaluationResult::String(o.value.clone().unwrap_or_default(), None)
@@ -849,13 +851,14 @@ mod r5_impl {
                         }
                     }
                     ViewDefinitionConstantValue::Date(d) => {
-                        EvaluationResult::Date(d.value.clone().unwrap_or_default(), None)
-                    }
-                    ViewDefinitionConstantValue::DateTime(dt) => {
-                        EvaluationResult::DateTime(dt.value.clone().unwrap_or_default(), None)
+                        EvaluationResult::Date(d.value.clone().unwrap_or_default().into(), None)
                     }
+                    ViewDefinitionConstantValue::DateTime(dt) => EvaluationResult::DateTime(
+                        dt.value.clone().unwrap_or_default().into(),
+                        None,
+                    ),
                     ViewDefinitionConstantValue::Time(t) => {
-                        EvaluationResult::Time(t.value.clone().unwrap_or_default(), None)
+                        EvaluationResult::Time(t.value.clone().unwrap_or_default().into(), None)
                     }
                     ViewDefinitionConstantValue::Code(c) => {
                         EvaluationResult::String(c.value.clone().unwrap_or_default(), None)
@@ -867,7 +870,7 @@ mod r5_impl {
                         EvaluationResult::String(i.value.clone().unwrap_or_default(), None)
                     }
                     ViewDefinitionConstantValue::Instant(i) => {
-                        EvaluationResult::DateTime(i.value.clone().unwrap_or_default(), None)
+                        EvaluationResult::DateTime(i.value.clone().unwrap_or_default().into(), None)
                     }
                     ViewDefinitionConstantValue::Oid(o) => {
                         EvaluationResult::String(o.value.clone().unwrap_or_default(), None)
@@ -1050,13 +1053,14 @@ mod r6_impl {
                         }
                     }
                     ViewDefinitionConstantValue::Date(d) => {
-                        EvaluationResult::Date(d.value.clone().unwrap_or_default(), None)
-                    }
-                    ViewDefinitionConstantValue::DateTime(dt) => {
-                        EvaluationResult::DateTime(dt.value.clone().unwrap_or_default(), None)
+                        EvaluationResult::Date(d.value.clone().unwrap_or_default().into(), None)
                     }
+                    ViewDefinitionConstantValue::DateTime(dt) => EvaluationResult::DateTime(
+                        dt.value.clone().unwrap_or_default().into(),
+                        None,
+                    ),
                     ViewDefinitionConstantValue::Time(t) => {
-                        EvaluationResult::Time(t.value.clone().unwrap_or_default(), None)
+                        EvaluationResult::Time(t.value.clone().unwrap_or_default().into(), None)
                     }
                     ViewDefinitionConstantValue::Code(c) => {
                         EvaluationResult::String(c.value.clone().unwrap_or_default(), None)
@@ -1068,7 +1072,7 @@ mod r6_impl {
                         EvaluationResult::String(i.value.clone().unwrap_or_default(), None)
                     }
                     ViewDefinitionConstantValue::Instant(i) => {
-                        EvaluationResult::DateTime(i.value.clone().unwrap_or_default(), None)
+                        EvaluationResult::DateTime(i.value.clone().unwrap_or_default().into(), None)
                     }
                     ViewDefinitionConstantValue::Oid(o) => {
                         EvaluationResult::String(o.value.clone().unwrap_or_default(), None)
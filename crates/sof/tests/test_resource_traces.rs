@@ -0,0 +1,138 @@
+use helios_fhir::r4::{Bundle, ViewDefinition};
+use helios_sof::{
+    ContentType, ResourceTraces, RunOptions, SofBundle, SofViewDefinition,
+    run_view_definition_with_options,
+};
+use std::sync::{Arc, Mutex};
+
+fn run_with_traces(view_def_json: serde_json::Value) -> (serde_json::Value, Vec<ResourceTraces>) {
+    let view_def: ViewDefinition = serde_json::from_value(view_def_json).unwrap();
+    let bundle: Bundle = serde_json::from_value(serde_json::json!({
+        "resourceType": "Bundle",
+        "type": "collection",
+        "entry": [
+            {
+                "resource": {
+                    "resourceType": "Patient",
+                    "id": "p1",
+                    "active": true,
+                    "name": [{ "family": "Chalmers", "given": ["Peter", "James"] }]
+                }
+            },
+            {
+                "resource": {
+                    "resourceType": "Observation",
+                    "id": "o1",
+                    "status": "final",
+                    "code": { "text": "Weight" }
+                }
+            },
+            {
+                "resource": {
+                    "resourceType": "Patient",
+                    "id": "p2",
+                    "active": false,
+                    "name": [{ "family": "Windsor", "given": ["Jim"] }]
+                }
+            }
+        ]
+    }))
+    .unwrap();
+
+    let collected = Arc::new(Mutex::new(Vec::new()));
+    let options = RunOptions::default().with_trace_handler({
+        let collected = collected.clone();
+        move |traces: ResourceTraces| collected.lock().unwrap().push(traces)
+    });
+
+    let output = run_view_definition_with_options(
+        SofViewDefinition::R4(view_def),
+        SofBundle::R4(bundle),
+        ContentType::Json,
+        options,
+    )
+    .unwrap();
+
+    let traces = collected.lock().unwrap().clone();
+    (serde_json::from_slice(&output).unwrap(), traces)
+}
+
+fn values(traces: &ResourceTraces) -> Vec<(&str, String)> {
+    traces
+        .traces
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.to_string_value()))
+        .collect()
+}
+
+#[test]
+fn test_traces_are_reported_per_resource() {
+    let (rows, traces) = run_with_traces(serde_json::json!({
+        "resourceType": "ViewDefinition",
+        "resource": "Patient",
+        "status": "active",
+        "select": [
+            {
+                "column": [
+                    { "name": "id", "path": "id" },
+                    { "name": "family", "path": "name.family.trace('family')" }
+                ]
+            },
+            {
+                "forEach": "name",
+                "column": [{ "name": "given", "path": "given.first().trace('given')" }]
+            }
+        ]
+    }));
+
+    // Tracing does not change the rows
+    assert_eq!(rows.as_array().unwrap().len(), 2);
+    assert_eq!(rows[0]["family"], "Chalmers");
+    assert_eq!(rows[0]["given"], "Peter");
+
+    assert_eq!(traces.len(), 2);
+    assert_eq!(traces[0].index, 0);
+    assert_eq!(traces[0].resource_type, "Patient");
+    assert_eq!(
+        values(&traces[0]),
+        vec![
+            ("family", "Chalmers".to_string()),
+            ("given", "Peter".to_string())
+        ]
+    );
+    assert_eq!(traces[1].index, 1);
+    assert_eq!(
+        values(&traces[1]),
+        vec![
+            ("family", "Windsor".to_string()),
+            ("given", "Jim".to_string())
+        ]
+    );
+}
+
+#[test]
+fn test_where_clause_traces() {
+    let (rows, traces) = run_with_traces(serde_json::json!({
+        "resourceType": "ViewDefinition",
+        "resource": "Patient",
+        "status": "active",
+        "where": [{ "path": "active.trace('active')" }],
+        "select": [{ "column": [{ "name": "id", "path": "id" }] }]
+    }));
+
+    // Resources filtered out by a where clause still report their traces
+    assert_eq!(rows.as_array().unwrap().len(), 1);
+    assert_eq!(traces.len(), 2);
+    assert_eq!(values(&traces[1]), vec![("active", "false".to_string())]);
+}
+
+#[test]
+fn test_resources_without_traces_are_not_reported() {
+    let (_, traces) = run_with_traces(serde_json::json!({
+        "resourceType": "ViewDefinition",
+        "resource": "Patient",
+        "status": "active",
+        "select": [{ "column": [{ "name": "id", "path": "id" }] }]
+    }));
+    assert!(traces.is_empty());
+}